@@ -1,44 +1,81 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
-use shared::MetricQuery;
+use criterion::{black_box, criterion_group, Bencher, Criterion, BenchmarkId};
+use futures_util::{StreamExt, TryStreamExt};
+use prost::Message;
+use serde::{de::DeserializeOwned, Serialize};
+use shared::{
+    dataset_from_env, generate_test_data, generate_test_data_iter, BodyEncoding, CapnpEncoding, Compressibility,
+    HttpCompression, MetricPoint, MetricPointV2, MetricQuery, RestPool, TestDataGenerator, TextMode, MAX_HOSTNAME_LEN, MAX_TAGS, MAX_TAG_VALUE_LEN,
+};
+use std::future::Future;
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
+use tokio_util::sync::CancellationToken;
 
 // Include the client modules
-use benchmarks::{rest_client, grpc_client, capnp_client, generate_test_data};
+use benchmarks::cancellation::{self, Cancellable};
+use benchmarks::circuit_breaker::{BreakerConfig, CircuitBreaker};
+use benchmarks::criterion_results;
+use benchmarks::export;
+use benchmarks::orchestrator::{self, GrpcReplicas};
+use benchmarks::resource_usage;
+use benchmarks::results;
+use benchmarks::runtime_flavor::RuntimeFlavor;
+use benchmarks::{
+    rest_client, grpc_client, capnp_client, payload_measurement, protocol_clients,
+    purge_all_services, seed_all_services, submit_in_batches, wire_bytes, benchmark_streaming_query, ComparisonReport,
+    FailureBreakdown, ProtocolClient, ProtocolError,
+};
+
+/// Clear every service, then submit the same points to each of them
+async fn populate_all(clients: &[(String, Box<dyn ProtocolClient>)], metrics: &[MetricPoint]) {
+    let _ = purge_all_services().await;
+    for metric in metrics {
+        for (_, client) in clients {
+            let _ = client.submit_metric(metric.clone()).await;
+        }
+    }
+}
+
+/// `b.iter(routine)`, with the timed iterations' `getrusage` difference
+/// added to `group` and `protocol`'s resource usage, which the run prints at
+/// the end
+fn iter_with_usage<O>(b: &mut Bencher, group: &str, protocol: &str, mut routine: impl FnMut() -> O) {
+    b.iter_custom(|iterations| {
+        let start = Instant::now();
+        resource_usage::measure_batch(group, protocol, iterations, &mut routine);
+        start.elapsed()
+    });
+}
+
+/// Query covering the whole time span of `metrics`
+fn covering_query(metrics: &[MetricPoint]) -> MetricQuery {
+    MetricQuery {
+        start_time: metrics.first().unwrap().timestamp - 100,
+        end_time: metrics.last().unwrap().timestamp + 100,
+        hostname_filter: None,
+        limit: None,
+        offset: None,
+    }
+}
 
 /// Benchmark submit_metric operation across all protocols with single metric
 fn benchmark_submit_single(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
     let test_metric = generate_test_data(1)[0].clone();
+    let clients = protocol_clients();
     
     let mut group = c.benchmark_group("submit_single");
     group.sample_size(100);
     
-    // REST API
-    group.bench_function("REST", |b| {
-        b.iter(|| {
-            rt.block_on(async {
-                rest_client::submit_metric(black_box(test_metric.clone())).await.unwrap()
-            })
-        });
-    });
-    
-    // gRPC
-    group.bench_function("gRPC", |b| {
-        b.iter(|| {
-            rt.block_on(async {
-                grpc_client::submit_metric(black_box(test_metric.clone())).await.unwrap()
-            })
-        });
-    });
-    
-    // Cap'n Proto
-    group.bench_function("CapnProto", |b| {
-        b.iter(|| {
-            rt.block_on(async {
-                capnp_client::submit_metric(black_box(test_metric.clone())).await.unwrap()
-            })
+    for (name, client) in &clients {
+        group.bench_function(name.as_str(), |b| {
+            iter_with_usage(b, "submit_single", name, || {
+                rt.block_on(async {
+                    client.submit_metric(black_box(test_metric.clone())).await.unwrap()
+                })
+            });
         });
-    });
+    }
     
     group.finish();
 }
@@ -46,29 +83,251 @@ fn benchmark_submit_single(c: &mut Criterion) {
 /// Benchmark query_metrics operation across all protocols with single query
 fn benchmark_query_single(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
     
     // Setup: Populate data in all services
     let setup_metrics = generate_test_data(20);
-    rt.block_on(async {
-        for metric in &setup_metrics {
-            // Populate all services with the same data
-            let _ = rest_client::submit_metric(metric.clone()).await;
-            let _ = grpc_client::submit_metric(metric.clone()).await;
-            let _ = capnp_client::submit_metric(metric.clone()).await;
+    rt.block_on(populate_all(&clients, &setup_metrics));
+    let query = covering_query(&setup_metrics);
+    
+    let mut group = c.benchmark_group("query_single");
+    group.sample_size(50);
+    
+    for (name, client) in &clients {
+        group.bench_function(name.as_str(), |b| {
+            iter_with_usage(b, "query_single", name, || {
+                rt.block_on(async {
+                    client.query_metrics(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+/// Benchmark get_statistics operation across all protocols with single query
+fn benchmark_statistics_single(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    
+    // Setup: Use the same data as query benchmark
+    let setup_metrics = generate_test_data(20);
+    rt.block_on(populate_all(&clients, &setup_metrics));
+    let query = covering_query(&setup_metrics);
+    
+    let mut group = c.benchmark_group("statistics_single");
+    group.sample_size(50);
+    
+    for (name, client) in &clients {
+        group.bench_function(name.as_str(), |b| {
+            iter_with_usage(b, "statistics_single", name, || {
+                rt.block_on(async {
+                    client.get_statistics(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+/// Benchmark submit_metric operation with variable payload sizes across all protocols
+fn benchmark_submit_scaling(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("submit_scaling");
+    group.sample_size(30); // Smaller sample for scaling tests
+    
+    // Test different payload sizes
+    for size in [1, 5, 10, 50].iter() {
+        let test_metrics = generate_test_data(*size);
+        
+        for (name, client) in &clients {
+            group.bench_with_input(BenchmarkId::new(name.as_str(), size), size, |b, _| {
+                iter_with_usage(b, "submit_scaling", name, || {
+                    rt.block_on(async {
+                        for metric in &test_metrics {
+                            client.submit_metric(black_box(metric.clone())).await.unwrap();
+                        }
+                    })
+                });
+            });
         }
-    });
+    }
+    
+    group.finish();
+}
+
+/// Benchmark query_metrics operation with variable dataset sizes across all protocols
+fn benchmark_query_scaling(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("query_scaling");
+    group.sample_size(20); // Smaller sample for scaling tests
+    
+    // Test different dataset sizes
+    for dataset_size in [10, 50, 100, 500].iter() {
+        let setup_metrics = generate_test_data(*dataset_size);
+        rt.block_on(populate_all(&clients, &setup_metrics));
+        let query = covering_query(&setup_metrics);
+        
+        for (name, client) in &clients {
+            group.bench_with_input(BenchmarkId::new(name.as_str(), dataset_size), dataset_size, |b, _| {
+                iter_with_usage(b, "query_scaling", name, || {
+                    rt.block_on(async {
+                        client.query_metrics(black_box(query.clone())).await.unwrap()
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Benchmark get_statistics operation with variable dataset sizes across all protocols
+fn benchmark_statistics_scaling(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("statistics_scaling");
+    group.sample_size(20); // Smaller sample for scaling tests
+    
+    // Test different dataset sizes
+    for dataset_size in [10, 50, 100, 500].iter() {
+        let setup_metrics = generate_test_data(*dataset_size);
+        rt.block_on(populate_all(&clients, &setup_metrics));
+        let query = covering_query(&setup_metrics);
+        
+        for (name, client) in &clients {
+            group.bench_with_input(BenchmarkId::new(name.as_str(), dataset_size), dataset_size, |b, _| {
+                iter_with_usage(b, "statistics_scaling", name, || {
+                    rt.block_on(async {
+                        client.get_statistics(black_box(query.clone())).await.unwrap()
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Benchmark per-minute rollups against raw query_metrics over the same dataset,
+/// contrasting a small aggregated response with a large raw response
+fn benchmark_rollup_vs_raw(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("rollup_vs_raw");
+    group.sample_size(20);
+    
+    let setup_metrics = generate_test_data(500);
+    rt.block_on(populate_all(&clients, &setup_metrics));
     
     let query = MetricQuery {
-        start_time: setup_metrics.first().unwrap().timestamp - 100,
-        end_time: setup_metrics.last().unwrap().timestamp + 100,
+        start_time: setup_metrics.iter().map(|m| m.timestamp).min().unwrap() - 100,
+        end_time: setup_metrics.iter().map(|m| m.timestamp).max().unwrap() + 100,
         hostname_filter: None,
+        limit: None,
+        offset: None,
     };
     
-    let mut group = c.benchmark_group("query_single");
-    group.sample_size(50);
+    for (name, client) in &clients {
+        group.bench_function(BenchmarkId::new(name.as_str(), "raw"), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    client.query_metrics(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+        group.bench_function(BenchmarkId::new(name.as_str(), "rollup"), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    client.query_rollups(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+/// Walk every page of a query until a short page signals the end, returning the total fetched
+async fn fetch_all_pages<F, Fut>(query: &MetricQuery, page_size: u32, fetch: F) -> usize
+where
+    F: Fn(MetricQuery) -> Fut,
+    Fut: Future<Output = Result<Vec<MetricPoint>, ProtocolError>>,
+{
+    let mut offset = 0;
+    loop {
+        let page = fetch(MetricQuery {
+            limit: Some(page_size),
+            offset: Some(offset),
+            ..query.clone()
+        })
+        .await
+        .unwrap();
+        
+        offset += page.len() as u32;
+        if page.len() < page_size as usize {
+            return offset as usize;
+        }
+    }
+}
+
+/// Benchmark paged retrieval of a large result set against one giant response
+fn benchmark_query_paged(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("query_paged");
+    group.sample_size(20);
+    
+    let setup_metrics = generate_test_data(500);
+    rt.block_on(populate_all(&clients, &setup_metrics));
+    
+    let query = MetricQuery {
+        start_time: setup_metrics.iter().map(|m| m.timestamp).min().unwrap() - 100,
+        end_time: setup_metrics.iter().map(|m| m.timestamp).max().unwrap() + 100,
+        hostname_filter: None,
+        limit: None,
+        offset: None,
+    };
+    
+    // Page size 0 stands for a single unpaged request
+    for page_size in [0u32, 50, 100, 250].iter() {
+        let label = if *page_size == 0 { "unpaged".to_string() } else { page_size.to_string() };
+        
+        for (name, client) in &clients {
+            group.bench_with_input(BenchmarkId::new(name.as_str(), &label), page_size, |b, &page_size| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        if page_size == 0 {
+                            client.query_metrics(black_box(query.clone())).await.unwrap().len()
+                        } else {
+                            fetch_all_pages(&query, page_size, |page| client.query_metrics(page)).await
+                        }
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Benchmark REST NDJSON streaming against REST's buffered JSON array and
+/// gRPC server streaming over the same result set
+fn benchmark_query_streaming(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("query_streaming");
+    group.sample_size(20);
+    
+    let setup_metrics = generate_test_data(500);
+    rt.block_on(populate_all(&protocol_clients(), &setup_metrics));
+    let query = covering_query(&setup_metrics);
     
-    // REST API
-    group.bench_function("REST", |b| {
+    // REST API, whole body buffered then parsed as one array
+    group.bench_function("REST/json_array", |b| {
         b.iter(|| {
             rt.block_on(async {
                 rest_client::query_metrics(black_box(query.clone())).await.unwrap()
@@ -76,8 +335,17 @@ fn benchmark_query_single(c: &mut Criterion) {
         });
     });
     
-    // gRPC
-    group.bench_function("gRPC", |b| {
+    // REST API, NDJSON parsed chunk by chunk
+    group.bench_function("REST/ndjson", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                rest_client::stream_metrics(black_box(query.clone())).await.unwrap()
+            })
+        });
+    });
+    
+    // gRPC server streaming
+    group.bench_function("gRPC/stream", |b| {
         b.iter(|| {
             rt.block_on(async {
                 grpc_client::query_metrics(black_box(query.clone())).await.unwrap()
@@ -85,8 +353,17 @@ fn benchmark_query_single(c: &mut Criterion) {
         });
     });
     
-    // Cap'n Proto
-    group.bench_function("CapnProto", |b| {
+    // gRPC single response message, the closest match to REST/json_array
+    group.bench_function("gRPC/unary", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                grpc_client::query_metrics_unary(black_box(query.clone())).await.unwrap()
+            })
+        });
+    });
+    
+    // Cap'n Proto single response carrying the whole list
+    group.bench_function("CapnProto/list", |b| {
         b.iter(|| {
             rt.block_on(async {
                 capnp_client::query_metrics(black_box(query.clone())).await.unwrap()
@@ -94,55 +371,73 @@ fn benchmark_query_single(c: &mut Criterion) {
         });
     });
     
+    // Cap'n Proto pushing each point to a client-side MetricSink
+    group.bench_function("CapnProto/callback", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                capnp_client::query_metrics_streaming(black_box(query.clone())).await.unwrap()
+            })
+        });
+    });
+    
     group.finish();
 }
 
-/// Benchmark get_statistics operation across all protocols with single query
-fn benchmark_statistics_single(c: &mut Criterion) {
+/// Streaming consumption: time until the first point of a large result
+/// arrives, and time to drain the whole result without keeping it
+fn benchmark_time_to_first_point(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("time_to_first_point");
+    group.sample_size(20);
     
-    // Setup: Use the same data as query benchmark
-    let setup_metrics = generate_test_data(20);
-    rt.block_on(async {
-        for metric in &setup_metrics {
-            let _ = rest_client::submit_metric(metric.clone()).await;
-            let _ = grpc_client::submit_metric(metric.clone()).await;
-            let _ = capnp_client::submit_metric(metric.clone()).await;
-        }
-    });
+    let setup_metrics = generate_test_data(10000);
+    rt.block_on(populate_all(&protocol_clients(), &setup_metrics));
+    let query = covering_query(&setup_metrics);
     
-    let query = MetricQuery {
-        start_time: setup_metrics.first().unwrap().timestamp - 100,
-        end_time: setup_metrics.last().unwrap().timestamp + 100,
-        hostname_filter: None,
-    };
+    // One drained query each, reported with first point next to completion
+    let (_, rest_metrics) = rt.block_on(benchmark_streaming_query("query", &rest_client::WIRE_BYTES, || {
+        rest_client::query_metrics_stream(query.clone())
+    }));
+    let (_, grpc_metrics) = rt.block_on(benchmark_streaming_query("query", &grpc_client::WIRE_BYTES, || {
+        grpc_client::query_metrics_stream(query.clone())
+    }));
+    if let Some(report) = ComparisonReport::new(&[("REST/ndjson", rest_metrics), ("gRPC/stream", grpc_metrics)]) {
+        print!("{}", report.winners());
+    }
     
-    let mut group = c.benchmark_group("statistics_single");
-    group.sample_size(50);
+    // Dropping each stream after its first point cancels the rest of the response
+    group.bench_function("REST/ndjson/first", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let mut metrics = std::pin::pin!(rest_client::query_metrics_stream(black_box(query.clone())).await.unwrap());
+                metrics.next().await.unwrap().unwrap()
+            })
+        });
+    });
     
-    // REST API
-    group.bench_function("REST", |b| {
+    group.bench_function("gRPC/stream/first", |b| {
         b.iter(|| {
             rt.block_on(async {
-                rest_client::get_statistics(black_box(query.clone())).await.unwrap()
+                let mut metrics = std::pin::pin!(grpc_client::query_metrics_stream(black_box(query.clone())).await.unwrap());
+                metrics.next().await.unwrap().unwrap()
             })
         });
     });
     
-    // gRPC
-    group.bench_function("gRPC", |b| {
+    group.bench_function("REST/ndjson/drain", |b| {
         b.iter(|| {
             rt.block_on(async {
-                grpc_client::get_statistics(black_box(query.clone())).await.unwrap()
+                let metrics = rest_client::query_metrics_stream(black_box(query.clone())).await.unwrap();
+                metrics.try_fold(0, |count, _| async move { Ok(count + 1) }).await.unwrap()
             })
         });
     });
     
-    // Cap'n Proto
-    group.bench_function("CapnProto", |b| {
+    group.bench_function("gRPC/stream/drain", |b| {
         b.iter(|| {
             rt.block_on(async {
-                capnp_client::get_statistics(black_box(query.clone())).await.unwrap()
+                let metrics = grpc_client::query_metrics_stream(black_box(query.clone())).await.unwrap();
+                metrics.try_fold(0, |count, _| async move { Ok(count + 1) }).await.unwrap()
             })
         });
     });
@@ -150,29 +445,24 @@ fn benchmark_statistics_single(c: &mut Criterion) {
     group.finish();
 }
 
-/// Benchmark submit_metric operation with variable payload sizes across all protocols
-fn benchmark_submit_scaling(c: &mut Criterion) {
+/// gRPC ingest: one client stream per batch vs one unary call per point
+fn benchmark_grpc_submit_stream(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
-    let mut group = c.benchmark_group("submit_scaling");
-    group.sample_size(30); // Smaller sample for scaling tests
+    let mut group = c.benchmark_group("grpc_submit_stream");
+    group.sample_size(20);
     
-    // Test different payload sizes
-    for size in [1, 5, 10, 50].iter() {
+    for size in [10, 100, 1000].iter() {
         let test_metrics = generate_test_data(*size);
         
-        // REST API scaling
-        group.bench_with_input(BenchmarkId::new("REST", size), size, |b, _| {
+        group.bench_with_input(BenchmarkId::new("gRPC/stream", size), size, |b, _| {
             b.iter(|| {
                 rt.block_on(async {
-                    for metric in &test_metrics {
-                        rest_client::submit_metric(black_box(metric.clone())).await.unwrap();
-                    }
+                    grpc_client::submit_metric_stream(black_box(test_metrics.clone())).await.unwrap()
                 })
             });
         });
         
-        // gRPC scaling
-        group.bench_with_input(BenchmarkId::new("gRPC", size), size, |b, _| {
+        group.bench_with_input(BenchmarkId::new("gRPC/unary", size), size, |b, _| {
             b.iter(|| {
                 rt.block_on(async {
                     for metric in &test_metrics {
@@ -181,70 +471,45 @@ fn benchmark_submit_scaling(c: &mut Criterion) {
                 })
             });
         });
-        
-        // Cap'n Proto scaling
-        group.bench_with_input(BenchmarkId::new("CapnProto", size), size, |b, _| {
-            b.iter(|| {
-                rt.block_on(async {
-                    for metric in &test_metrics {
-                        capnp_client::submit_metric(black_box(metric.clone())).await.unwrap();
-                    }
-                })
-            });
-        });
     }
     
     group.finish();
 }
 
-/// Benchmark query_metrics operation with variable dataset sizes across all protocols
-fn benchmark_query_scaling(c: &mut Criterion) {
+/// Push delivery: subscribe, submit N points, and wait until all N have been
+/// pushed back over the subscription
+fn benchmark_subscribe_push(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
-    let mut group = c.benchmark_group("query_scaling");
-    group.sample_size(20); // Smaller sample for scaling tests
+    let mut group = c.benchmark_group("subscribe_push");
+    group.sample_size(20);
     
-    // Test different dataset sizes
-    for dataset_size in [10, 50, 100, 500].iter() {
-        let setup_metrics = generate_test_data(*dataset_size);
-        
-        // Setup data for this scale test
-        rt.block_on(async {
-            for metric in &setup_metrics {
-                let _ = rest_client::submit_metric(metric.clone()).await;
-                let _ = grpc_client::submit_metric(metric.clone()).await;
-                let _ = capnp_client::submit_metric(metric.clone()).await;
-            }
-        });
-        
-        let query = MetricQuery {
-            start_time: setup_metrics.first().unwrap().timestamp - 100,
-            end_time: setup_metrics.last().unwrap().timestamp + 100,
-            hostname_filter: None,
-        };
+    for size in [10, 100, 1000].iter() {
+        let test_metrics = generate_test_data(*size);
+        let query = covering_query(&test_metrics);
         
-        // REST API scaling
-        group.bench_with_input(BenchmarkId::new("REST", dataset_size), dataset_size, |b, _| {
+        // REST API, server-sent events
+        group.bench_with_input(BenchmarkId::new("REST/sse", size), size, |b, _| {
             b.iter(|| {
                 rt.block_on(async {
-                    rest_client::query_metrics(black_box(query.clone())).await.unwrap()
+                    rest_client::tail_metrics(black_box(query.clone()), test_metrics.clone()).await.unwrap()
                 })
             });
         });
         
-        // gRPC scaling
-        group.bench_with_input(BenchmarkId::new("gRPC", dataset_size), dataset_size, |b, _| {
+        // gRPC bidirectional stream
+        group.bench_with_input(BenchmarkId::new("gRPC/bidi", size), size, |b, _| {
             b.iter(|| {
                 rt.block_on(async {
-                    grpc_client::query_metrics(black_box(query.clone())).await.unwrap()
+                    grpc_client::tail_metrics(black_box(query.clone()), test_metrics.clone()).await.unwrap()
                 })
             });
         });
         
-        // Cap'n Proto scaling
-        group.bench_with_input(BenchmarkId::new("CapnProto", dataset_size), dataset_size, |b, _| {
+        // Cap'n Proto callback capability
+        group.bench_with_input(BenchmarkId::new("CapnProto/callback", size), size, |b, _| {
             b.iter(|| {
                 rt.block_on(async {
-                    capnp_client::query_metrics(black_box(query.clone())).await.unwrap()
+                    capnp_client::tail_metrics(black_box(query.clone()), test_metrics.clone()).await.unwrap()
                 })
             });
         });
@@ -253,54 +518,78 @@ fn benchmark_query_scaling(c: &mut Criterion) {
     group.finish();
 }
 
-/// Benchmark get_statistics operation with variable dataset sizes across all protocols
-fn benchmark_statistics_scaling(c: &mut Criterion) {
+/// gRPC query result as one unary response vs a server stream, over
+/// variable dataset sizes
+fn benchmark_grpc_query_unary(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
-    let mut group = c.benchmark_group("statistics_scaling");
-    group.sample_size(20); // Smaller sample for scaling tests
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("grpc_query_unary_vs_stream");
+    group.sample_size(20);
     
-    // Test different dataset sizes
-    for dataset_size in [10, 50, 100, 500].iter() {
+    for dataset_size in [10, 100, 500, 1000].iter() {
         let setup_metrics = generate_test_data(*dataset_size);
+        rt.block_on(populate_all(&clients, &setup_metrics));
+        let query = covering_query(&setup_metrics);
         
-        // Setup data for this scale test
-        rt.block_on(async {
-            for metric in &setup_metrics {
-                let _ = rest_client::submit_metric(metric.clone()).await;
-                let _ = grpc_client::submit_metric(metric.clone()).await;
-                let _ = capnp_client::submit_metric(metric.clone()).await;
-            }
+        group.bench_with_input(BenchmarkId::new("gRPC/unary", dataset_size), dataset_size, |b, _| {
+            b.iter(|| {
+                rt.block_on(async {
+                    grpc_client::query_metrics_unary(black_box(query.clone())).await.unwrap()
+                })
+            });
         });
         
-        let query = MetricQuery {
-            start_time: setup_metrics.first().unwrap().timestamp - 100,
-            end_time: setup_metrics.last().unwrap().timestamp + 100,
-            hostname_filter: None,
-        };
-        
-        // REST API scaling
-        group.bench_with_input(BenchmarkId::new("REST", dataset_size), dataset_size, |b, _| {
+        group.bench_with_input(BenchmarkId::new("gRPC/stream", dataset_size), dataset_size, |b, _| {
+            b.iter(|| {
+                rt.block_on(async {
+                    grpc_client::query_metrics(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+/// Timeout handling on every protocol: the same query with the configured
+/// timeout, one that is comfortably met, and one that expires mid-response.
+/// The expiring case measures how quickly the client gets its error back.
+fn benchmark_timeout_behavior(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("timeout_behavior");
+    group.sample_size(20);
+    
+    let setup_metrics = generate_test_data(5000);
+    rt.block_on(populate_all(&clients, &setup_metrics));
+    let query = covering_query(&setup_metrics);
+    
+    for (name, client) in &clients {
+        // Whatever PROTOBENCH_CLIENT_TIMEOUT_MS sets, usually nothing
+        group.bench_function(BenchmarkId::new(name.as_str(), "default"), |b| {
             b.iter(|| {
                 rt.block_on(async {
-                    rest_client::get_statistics(black_box(query.clone())).await.unwrap()
+                    client.query_metrics(black_box(query.clone())).await.unwrap()
                 })
             });
         });
         
-        // gRPC scaling
-        group.bench_with_input(BenchmarkId::new("gRPC", dataset_size), dataset_size, |b, _| {
+        group.bench_function(BenchmarkId::new(name.as_str(), "timeout_met"), |b| {
             b.iter(|| {
                 rt.block_on(async {
-                    grpc_client::get_statistics(black_box(query.clone())).await.unwrap()
+                    client.query_with_timeout(black_box(query.clone()), Duration::from_secs(10)).await.unwrap()
                 })
             });
         });
         
-        // Cap'n Proto scaling
-        group.bench_with_input(BenchmarkId::new("CapnProto", dataset_size), dataset_size, |b, _| {
+        // Measures how quickly each protocol gives up; any failure other than
+        // a timeout means the comparison is off
+        group.bench_function(BenchmarkId::new(name.as_str(), "timeout_expired"), |b| {
             b.iter(|| {
                 rt.block_on(async {
-                    capnp_client::get_statistics(black_box(query.clone())).await.unwrap()
+                    if let Err(e) = client.query_with_timeout(black_box(query.clone()), Duration::from_millis(1)).await {
+                        assert!(e.is_timeout(), "{} failed without timing out: {}", name, e);
+                    }
                 })
             });
         });
@@ -309,13 +598,927 @@ fn benchmark_statistics_scaling(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(
-    benches,
-    benchmark_submit_single,
-    benchmark_query_single,
+/// Chaos run through a `CircuitBreaker` per protocol: each iteration sends a
+/// burst of queries that time out, waits out the open period and then
+/// queries normally. Measures how long a client takes to trip, fail fast and
+/// recover; breaker transitions and failures by category are printed once
+/// the group finishes.
+fn benchmark_circuit_breaker(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("circuit_breaker");
+    group.sample_size(10);
+    
+    let setup_metrics = generate_test_data(5000);
+    rt.block_on(populate_all(&clients, &setup_metrics));
+    let query = covering_query(&setup_metrics);
+    
+    let config = BreakerConfig {
+        failure_threshold: 3,
+        open_for: Duration::from_millis(20),
+        half_open_probes: 1,
+    };
+    let breakers: Vec<(String, CircuitBreaker)> = clients
+        .into_iter()
+        .map(|(name, client)| (name.clone(), CircuitBreaker::new(name, client, config)))
+        .collect();
+    let mut failures = vec![FailureBreakdown::default(); breakers.len()];
+    
+    for ((name, breaker), failures) in breakers.iter().zip(&mut failures) {
+        group.bench_function(BenchmarkId::new(name.as_str(), "trip_and_recover"), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    // Past the threshold these are rejected without a request
+                    for _ in 0..config.failure_threshold * 2 {
+                        if let Err(e) = breaker.query_with_timeout(black_box(query.clone()), Duration::from_millis(1)).await {
+                            assert!(
+                                matches!(e, ProtocolError::Timeout { .. } | ProtocolError::CircuitOpen { .. }),
+                                "{} failed without timing out: {}", name, e
+                            );
+                            failures.record(&e);
+                        }
+                    }
+                    tokio::time::sleep(config.open_for).await;
+                    breaker.query_metrics(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    group.finish();
+    
+    for ((name, breaker), failures) in breakers.iter().zip(&failures) {
+        eprintln!("{} circuit breaker: {}; failures: {}", name, breaker.stats(), failures);
+    }
+}
+
+/// Large queries cancelled part-way through, per protocol. Measures the
+/// server-side work wasted after the client gave up: how long the service's
+/// `requests_in_flight` gauge takes to drop back to zero once the client has
+/// cancelled. Needs each service's Prometheus exporter; protocols whose
+/// `PROTOBENCH_<PROTOCOL>_METRICS_ADDR` isn't set here are skipped.
+fn benchmark_cancellation_waste(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("cancellation_waste");
+    group.sample_size(10);
+    
+    rt.block_on(async {
+        let _ = purge_all_services().await;
+        for (_, client) in &clients {
+            // Batched to stay under the REST body limit
+            let _ = submit_in_batches(client.as_ref(), generate_test_data_iter(20000), 5000).await;
+        }
+    });
+    // Storage holds nothing but these points
+    let query = MetricQuery::all();
+    
+    for (name, client) in &clients {
+        let Ok(exporter) = std::env::var(client.metrics_exporter_env()) else {
+            eprintln!("Skipping {} cancellation_waste: {} is not set", name, client.metrics_exporter_env());
+            continue;
+        };
+        
+        for cancel_after_ms in [1u64, 5] {
+            group.bench_with_input(BenchmarkId::new(name.as_str(), format!("cancel_after_{}ms", cancel_after_ms)), &cancel_after_ms, |b, &cancel_after_ms| {
+                b.iter_custom(|iters| {
+                    rt.block_on(async {
+                        let mut wasted = Duration::ZERO;
+                        for _ in 0..iters {
+                            let token = CancellationToken::new();
+                            let client = Cancellable::new(client.as_ref(), token.clone());
+                            let (result, _) = tokio::join!(client.query_metrics(black_box(query.clone())), async {
+                                tokio::time::sleep(Duration::from_millis(cancel_after_ms)).await;
+                                token.cancel();
+                            });
+                            // A query that beat the cancellation wasted nothing
+                            if let Err(e) = result {
+                                assert!(matches!(e, ProtocolError::Cancelled), "{} failed without being cancelled: {}", name, e);
+                                wasted += cancellation::wait_for_server_idle(&exporter, Duration::from_secs(5)).await.unwrap();
+                            }
+                        }
+                        wasted
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// gRPC query streams for large result sets with and without response
+/// compression; needs `grpc-service` running with
+/// `PROTOBENCH_GRPC_COMPRESSION=gzip,zstd`, otherwise every variant is identity
+fn benchmark_grpc_compression(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("grpc_compression");
+    group.sample_size(20);
+    
+    for dataset_size in [1000, 5000].iter() {
+        let setup_metrics = generate_test_data(*dataset_size);
+        rt.block_on(populate_all(&clients, &setup_metrics));
+        let query = covering_query(&setup_metrics);
+        
+        for encoding in ["identity", "gzip", "zstd"] {
+            let compression = HttpCompression::parse(encoding);
+            group.bench_with_input(BenchmarkId::new(format!("gRPC/{}", encoding), dataset_size), dataset_size, |b, _| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        grpc_client::query_metrics_with_compression(black_box(query.clone()), compression).await.unwrap()
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Best- and worst-case compression: the same 1000 points with 16 tags of 64
+/// bytes each, their values repetitive, natural or random noise (see
+/// `shared::Compressibility`), queried back from every client as configured
+/// plus gRPC with each codec. Prints the bytes each query read off the wire
+/// against the size of the points as uncompressed JSON; REST bytes are only
+/// counted with `PROTOBENCH_REST_WIRE_BYTES` set, and gRPC codecs other than
+/// identity need `PROTOBENCH_GRPC_COMPRESSION=gzip,zstd` on the service.
+fn benchmark_compressibility(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("compressibility");
+    group.sample_size(20);
+    
+    for compressibility in [Compressibility::Repetitive, Compressibility::Natural, Compressibility::Noise] {
+        let test_metrics = TestDataGenerator::new()
+            .tags_per_metric(16)
+            .tag_value_len(64)
+            .compressibility(compressibility)
+            .generate(1000);
+        rt.block_on(seed_all_services(&test_metrics)).unwrap();
+        let query = covering_query(&test_metrics);
+        let json_bytes = BodyEncoding::Json.encode(&test_metrics).map_or(0, |body| body.len());
+        let report = |name: &str, read: u64| {
+            println!(
+                "{} {} query: {} bytes read, {:.2}x the {} bytes of uncompressed JSON",
+                name, compressibility.label(), read, read as f64 / json_bytes as f64, json_bytes
+            );
+        };
+        
+        for (name, client) in &clients {
+            let (_, bytes) = rt.block_on(wire_bytes::measure(client.wire_bytes(), client.query_metrics(query.clone())));
+            report(name, bytes.read);
+            group.bench_with_input(BenchmarkId::new(format!("{}/query", name), compressibility.label()), &query, |b, query| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        client.query_metrics(black_box(query.clone())).await.unwrap()
+                    })
+                });
+            });
+        }
+        
+        for encoding in ["identity", "gzip", "zstd"] {
+            let compression = HttpCompression::parse(encoding);
+            let name = format!("gRPC/{}", encoding);
+            let (_, bytes) = rt.block_on(wire_bytes::measure(
+                &grpc_client::WIRE_BYTES,
+                grpc_client::query_metrics_with_compression(query.clone(), compression),
+            ));
+            report(&name, bytes.read);
+            group.bench_with_input(BenchmarkId::new(name, compressibility.label()), &query, |b, query| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        grpc_client::query_metrics_with_compression(black_box(query.clone()), compression).await.unwrap()
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Payload shape as its own dimension: the same number of points spread over
+/// more hosts, carrying more tags, longer hostnames or multi-byte text,
+/// queried back and submitted as one batch
+fn benchmark_payload_shape(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("payload_shape");
+    group.sample_size(20);
+    
+    let shapes = [
+        TestDataGenerator::new(),
+        TestDataGenerator::new().hosts(1000),
+        TestDataGenerator::new().tags_per_metric(16),
+        TestDataGenerator::new().hostname_len(64),
+        TestDataGenerator::new().hosts(100).tags_per_metric(16).hostname_len(64),
+        TestDataGenerator::new().text(TextMode::Unicode),
+        TestDataGenerator::new().text(TextMode::Long),
+    ];
+    
+    for shape in &shapes {
+        let test_metrics = shape.generate(1000);
+        rt.block_on(async {
+            let _ = purge_all_services().await;
+            for (_, client) in &clients {
+                let _ = client.submit_batch(test_metrics.clone()).await;
+            }
+        });
+        let query = covering_query(&test_metrics);
+        
+        for (name, client) in &clients {
+            // Queried before the submit runs below add more copies of the points
+            group.bench_with_input(BenchmarkId::new(format!("{}/query", name), shape.label()), &query, |b, query| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        client.query_metrics(black_box(query.clone())).await.unwrap()
+                    })
+                });
+            });
+            
+            group.bench_with_input(BenchmarkId::new(format!("{}/submit_batch", name), shape.label()), &test_metrics, |b, metrics| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        client.submit_batch(black_box(metrics.clone())).await.unwrap()
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Worst-case payloads. Adversarial points (see
+/// `TestDataGenerator::adversarial`) are first sent in doubling batches to
+/// report where each protocol's message size limit cuts in, then timed being
+/// rejected; points at every per-point limit are timed being stored and read back.
+fn benchmark_adversarial_payloads(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("adversarial_payloads");
+    group.sample_size(10);
+    
+    let adversarial = TestDataGenerator::new().adversarial();
+    for (name, client) in &clients {
+        for size in [1, 2, 4, 8, 16] {
+            let metrics = adversarial.generate(size);
+            let json_bytes = BodyEncoding::Json.encode(&metrics).map_or(0, |body| body.len());
+            let outcome = match rt.block_on(client.submit_batch(metrics)) {
+                Ok(accepted) => format!("accepted {}", accepted),
+                Err(e) => e.to_string(),
+            };
+            println!("{} adversarial batch of {} ({:.1} MiB as JSON): {}", name, size, json_bytes as f64 / (1024.0 * 1024.0), outcome);
+        }
+    }
+    
+    let rejected = adversarial.generate(1);
+    for (name, client) in &clients {
+        group.bench_function(BenchmarkId::new(name.as_str(), "reject_adversarial"), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    let _ = client.submit_batch(black_box(rejected.clone())).await;
+                })
+            });
+        });
+    }
+    
+    let at_limits = TestDataGenerator::new()
+        .tags_per_metric(MAX_TAGS)
+        .tag_value_len(MAX_TAG_VALUE_LEN)
+        .hostname_len(MAX_HOSTNAME_LEN)
+        .extreme_values(true)
+        .generate(100);
+    rt.block_on(async {
+        let _ = purge_all_services().await;
+        for (_, client) in &clients {
+            let _ = client.submit_batch(at_limits.clone()).await;
+        }
+    });
+    let query = covering_query(&at_limits);
+    
+    for (name, client) in &clients {
+        // Queried before the submit runs below add more copies of the points
+        group.bench_function(BenchmarkId::new(name.as_str(), "query_at_limits"), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    client.query_metrics(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+        
+        group.bench_function(BenchmarkId::new(name.as_str(), "submit_at_limits"), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    client.submit_batch(black_box(at_limits.clone())).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+/// The user's own data from `PROTOBENCH_DATASET` (see `shared::load_dataset`)
+/// instead of generated points: the whole dataset queried back, its
+/// statistics, and up to 1000 of its points submitted as one batch. Skipped
+/// when the variable is unset.
+fn benchmark_dataset(c: &mut Criterion) {
+    let metrics = match dataset_from_env() {
+        Ok(Some(metrics)) if !metrics.is_empty() => metrics,
+        Ok(_) => {
+            eprintln!("Skipping dataset: PROTOBENCH_DATASET is not set or the file is empty");
+            return;
+        }
+        Err(e) => {
+            eprintln!("Skipping dataset: {:#}", e);
+            return;
+        }
+    };
+    
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("dataset");
+    group.sample_size(20);
+    
+    rt.block_on(seed_all_services(&metrics)).unwrap();
+    let query = covering_query(&metrics);
+    let batch: Vec<MetricPoint> = metrics.iter().take(1000).cloned().collect();
+    
+    for (name, client) in &clients {
+        // Read before the submit runs below add more copies of the points
+        group.bench_function(BenchmarkId::new(name.as_str(), format!("query_{}", metrics.len())), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    client.query_metrics(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+        
+        group.bench_function(BenchmarkId::new(name.as_str(), "statistics"), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    client.get_statistics(black_box(query.clone())).await.unwrap()
+                })
+            });
+        });
+        
+        group.bench_function(BenchmarkId::new(name.as_str(), format!("submit_batch_{}", batch.len())), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    client.submit_batch(black_box(batch.clone())).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+/// Batch ingestion: each protocol's batch operation (one REST request, one
+/// gRPC message, pipelined Cap'n Proto calls) plus one gRPC client stream
+/// per batch
+fn benchmark_batch_submit(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("batch_submit");
+    group.sample_size(20);
+    
+    for size in [10, 100, 1000].iter() {
+        let test_metrics = generate_test_data(*size);
+        
+        for (name, client) in &clients {
+            group.bench_with_input(BenchmarkId::new(format!("{}/batch", name), size), size, |b, _| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        client.submit_batch(black_box(test_metrics.clone())).await.unwrap()
+                    })
+                });
+            });
+        }
+        
+        group.bench_with_input(BenchmarkId::new("gRPC/stream", size), size, |b, _| {
+            b.iter(|| {
+                rt.block_on(async {
+                    grpc_client::submit_metric_stream(black_box(test_metrics.clone())).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    group.finish();
+}
+
+/// Concurrent gRPC batch submits against one harness-started `grpc-service`
+/// replica and against `PROTOBENCH_GRPC_REPLICAS` of them behind a balanced
+/// channel. Skipped unless that variable is set.
+fn benchmark_grpc_replicas(c: &mut Criterion) {
+    let Some(count) = orchestrator::grpc_replica_count() else {
+        return;
+    };
+    let rt = Runtime::new().unwrap();
+    let replicas = rt.block_on(GrpcReplicas::start(count)).unwrap();
+    let single = rt.block_on(grpc_client::connect_to(&replicas.targets()[..1])).unwrap();
+    let balanced = rt.block_on(grpc_client::connect_to(replicas.targets())).unwrap();
+    
+    let mut group = c.benchmark_group("grpc_replicas");
+    group.sample_size(20);
+    let test_metrics = generate_test_data(100);
+    
+    for (replica_count, channel) in [(1, &single), (count, &balanced)] {
+        for concurrency in [16, 64].iter() {
+            group.bench_with_input(BenchmarkId::new(format!("gRPC/replicas={}", replica_count), concurrency), concurrency, |b, &concurrency| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let batches = (0..concurrency).map(|_| grpc_client::submit_metrics_with_channel(channel, black_box(test_metrics.clone())));
+                        futures_util::future::try_join_all(batches).await.unwrap()
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Concurrent Cap'n Proto batch submits, each on its own connection in its
+/// own `LocalSet` as `CapnpClient` makes them, against the same submits
+/// spawned as tasks through a `capnp_client::WorkerPool`'s held connections
+fn benchmark_capnp_worker_pool(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("capnp_worker_pool");
+    group.sample_size(20);
+    let test_metrics = generate_test_data(100);
+    
+    for concurrency in [16, 64].iter() {
+        group.bench_with_input(BenchmarkId::new("CapnProto/per-call", concurrency), concurrency, |b, &concurrency| {
+            b.iter(|| {
+                rt.block_on(async {
+                    let batches = (0..concurrency).map(|_| capnp_client::submit_metrics(black_box(test_metrics.clone())));
+                    futures_util::future::try_join_all(batches).await.unwrap()
+                })
+            });
+        });
+    }
+    
+    for workers in [1, 4] {
+        let pool = std::sync::Arc::new(capnp_client::WorkerPool::start(workers).unwrap());
+        for concurrency in [16, 64].iter() {
+            group.bench_with_input(BenchmarkId::new(format!("CapnProto/pool={}", workers), concurrency), concurrency, |b, &concurrency| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let batches = (0..concurrency).map(|_| {
+                            let pool = pool.clone();
+                            let metrics = black_box(test_metrics.clone());
+                            tokio::spawn(async move { pool.submit_metrics(metrics).await })
+                        });
+                        for batch in futures_util::future::join_all(batches).await {
+                            batch.unwrap().unwrap();
+                        }
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Batch submits, one at a time and 16 or 64 at once, from clients running on
+/// each `RuntimeFlavor::from_env()` runtime. Every flavor gets its own REST
+/// client and gRPC channel, so their connections are driven by that runtime;
+/// concurrent REST and gRPC submits are spawned as tasks, so a multi-thread
+/// runtime can spread them over its workers. Cap'n Proto calls are `!Send`
+/// and stay on the thread blocking on the runtime whatever its flavor.
+fn benchmark_runtime_flavor(c: &mut Criterion) {
+    let mut group = c.benchmark_group("runtime_flavor");
+    group.sample_size(20);
+    let test_metrics = generate_test_data(100);
+    
+    for flavor in RuntimeFlavor::from_env() {
+        let rt = flavor.build().unwrap();
+        let rest = rest_client::pooled_client(rest_client::pool_settings()).unwrap();
+        let channel = rt.block_on(grpc_client::connect_to(&grpc_client::targets())).unwrap();
+        
+        for concurrency in [1, 16, 64].iter() {
+            group.bench_with_input(BenchmarkId::new(format!("REST/{}", flavor), concurrency), concurrency, |b, &concurrency| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let batches = (0..concurrency).map(|_| {
+                            let rest = rest.clone();
+                            let metrics = black_box(test_metrics.clone());
+                            tokio::spawn(async move { rest_client::submit_metrics_with_client(&rest, metrics).await })
+                        });
+                        for batch in futures_util::future::join_all(batches).await {
+                            batch.unwrap().unwrap();
+                        }
+                    })
+                });
+            });
+            
+            group.bench_with_input(BenchmarkId::new(format!("gRPC/{}", flavor), concurrency), concurrency, |b, &concurrency| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let batches = (0..concurrency).map(|_| {
+                            let channel = channel.clone();
+                            let metrics = black_box(test_metrics.clone());
+                            tokio::spawn(async move { grpc_client::submit_metrics_with_channel(&channel, metrics).await })
+                        });
+                        for batch in futures_util::future::join_all(batches).await {
+                            batch.unwrap().unwrap();
+                        }
+                    })
+                });
+            });
+            
+            group.bench_with_input(BenchmarkId::new(format!("CapnProto/{}", flavor), concurrency), concurrency, |b, &concurrency| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let batches = (0..concurrency).map(|_| capnp_client::submit_metrics(black_box(test_metrics.clone())));
+                        futures_util::future::try_join_all(batches).await.unwrap()
+                    })
+                });
+            });
+        }
+        
+        // So each flavor submits into services of the same size
+        rt.block_on(async {
+            let _ = purge_all_services().await;
+        });
+    }
+    
+    group.finish();
+}
+
+/// Concurrent REST queries through clients that differ only in how many idle
+/// connections they keep per host, starting from the `[rest_pool]` settings
+fn benchmark_rest_pool_sizes(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let clients = protocol_clients();
+    let mut group = c.benchmark_group("rest_pool_sizes");
+    group.sample_size(20);
+    
+    let setup_metrics = generate_test_data(100);
+    rt.block_on(populate_all(&clients, &setup_metrics));
+    let query = covering_query(&setup_metrics);
+    
+    for max_idle in [0, 1, 4, 16] {
+        let pool = RestPool { max_idle_per_host: Some(max_idle), ..rest_client::pool_settings() };
+        let client = rest_client::pooled_client(pool).unwrap();
+        
+        for concurrency in [1, 16, 64].iter() {
+            group.bench_with_input(BenchmarkId::new(format!("REST/idle={}", max_idle), concurrency), concurrency, |b, &concurrency| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let requests = (0..concurrency).map(|_| rest_client::query_metrics_with_client(&client, black_box(query.clone())));
+                        futures_util::future::try_join_all(requests).await.unwrap()
+                    })
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Statistics plus rollups for one query: REST and gRPC issue the two
+/// requests back to back, Cap'n Proto goes through an `openQuery` handle with
+/// and without promise pipelining
+fn benchmark_promise_pipelining(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("promise_pipelining");
+    group.sample_size(20);
+    
+    let setup_metrics = generate_test_data(500);
+    rt.block_on(populate_all(&protocol_clients(), &setup_metrics));
+    let query = covering_query(&setup_metrics);
+    
+    group.bench_function("REST/sequential", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let statistics = rest_client::get_statistics(black_box(query.clone())).await.unwrap();
+                let rollups = rest_client::query_rollups(black_box(query.clone())).await.unwrap();
+                (statistics, rollups)
+            })
+        });
+    });
+    
+    group.bench_function("gRPC/sequential", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let statistics = grpc_client::get_statistics(black_box(query.clone())).await.unwrap();
+                let rollups = grpc_client::query_rollups(black_box(query.clone())).await.unwrap();
+                (statistics, rollups)
+            })
+        });
+    });
+    
+    // Both Cap'n Proto variants open a fresh connection, so the difference
+    // between them is the two round trips pipelining saves
+    group.bench_function("CapnProto/sequential", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                capnp_client::query_summary(black_box(query.clone()), false).await.unwrap()
+            })
+        });
+    });
+    
+    group.bench_function("CapnProto/pipelined", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                capnp_client::query_summary(black_box(query.clone()), true).await.unwrap()
+            })
+        });
+    });
+    
+    group.finish();
+}
+
+/// Cost of serializing a query response unpacked vs packed, without the
+/// network. Run the other groups with `PROTOBENCH_CAPNP_ENCODING=packed` to
+/// compare the encodings end to end.
+fn benchmark_capnp_packing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("capnp_packing");
+    
+    for size in [10, 100, 1000].iter() {
+        let test_metrics = generate_test_data(*size);
+        
+        for encoding in CapnpEncoding::ALL {
+            let id = BenchmarkId::new(format!("CapnProto/{}", encoding.label()), size);
+            group.bench_with_input(id, size, |b, _| {
+                b.iter(|| {
+                    payload_measurement::measure_capnp_metrics_wire_size(black_box(&test_metrics), encoding).unwrap()
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+/// Encode `metrics` as a REST body and decode it again; returns the body size
+fn body_round_trip<T: Serialize + DeserializeOwned>(encoding: BodyEncoding, metrics: &[T]) -> usize {
+    let body = encoding.encode(&metrics).unwrap();
+    black_box(encoding.decode::<Vec<T>>(&body).unwrap());
+    body.len()
+}
+
+/// Convert `metrics` to one protobuf batch message, encode it and convert it
+/// back; returns the message size
+fn protobuf_round_trip(metrics: &[MetricPoint]) -> usize {
+    let batch = grpc_client::metrics::MetricBatch { metrics: metrics.iter().cloned().map(grpc_client::to_proto_metric).collect() };
+    let bytes = batch.encode_to_vec();
+    let decoded = grpc_client::metrics::MetricBatch::decode(&bytes[..]).unwrap();
+    black_box(decoded.metrics.into_iter().map(grpc_client::from_proto_metric).collect::<Vec<_>>());
+    bytes.len()
+}
+
+fn protobuf_round_trip_v2(metrics: &[MetricPointV2]) -> usize {
+    let batch = grpc_client::metrics::MetricBatchV2 { metrics: metrics.iter().cloned().map(grpc_client::to_proto_metric_v2).collect() };
+    let bytes = batch.encode_to_vec();
+    let decoded = grpc_client::metrics::MetricBatchV2::decode(&bytes[..]).unwrap();
+    black_box(decoded.metrics.into_iter().map(grpc_client::from_proto_metric_v2).collect::<Vec<_>>());
+    bytes.len()
+}
+
+fn capnp_bytes(message: &capnp::message::Builder<capnp::message::HeapAllocator>, encoding: CapnpEncoding) -> Vec<u8> {
+    match encoding {
+        CapnpEncoding::Unpacked => capnp::serialize::write_message_to_words(message),
+        CapnpEncoding::Packed => {
+            let mut bytes = Vec::new();
+            capnp::serialize_packed::write_message(&mut bytes, message).unwrap();
+            bytes
+        }
+    }
+}
+
+fn capnp_message(bytes: &[u8], encoding: CapnpEncoding) -> capnp::message::Reader<capnp::serialize::OwnedSegments> {
+    let options = capnp::message::ReaderOptions::new();
+    match encoding {
+        CapnpEncoding::Unpacked => capnp::serialize::read_message(bytes, options).unwrap(),
+        CapnpEncoding::Packed => capnp::serialize_packed::read_message(bytes, options).unwrap(),
+    }
+}
+
+/// Build a `queryMetrics` response carrying `metrics`, serialize it and read
+/// every point back; returns the message size
+fn capnp_round_trip(metrics: &[MetricPoint], encoding: CapnpEncoding) -> usize {
+    use benchmarks::metrics_capnp::metrics_service::query_metrics_results;
+    
+    let mut message = capnp::message::Builder::new_default();
+    let mut list_builder = message.init_root::<query_metrics_results::Builder>().init_metrics(metrics.len() as u32);
+    for (i, metric) in metrics.iter().enumerate() {
+        capnp_client::write_metric(metric, list_builder.reborrow().get(i as u32));
+    }
+    let bytes = capnp_bytes(&message, encoding);
+    
+    let reader = capnp_message(&bytes, encoding);
+    let list_reader = reader.get_root::<query_metrics_results::Reader>().unwrap().get_metrics().unwrap();
+    black_box(list_reader.iter().map(|metric| capnp_client::read_metric(metric).unwrap()).collect::<Vec<_>>());
+    bytes.len()
+}
+
+fn capnp_round_trip_v2(metrics: &[MetricPointV2], encoding: CapnpEncoding) -> usize {
+    use benchmarks::metrics_capnp::metric_batch_v2;
+    
+    let mut message = capnp::message::Builder::new_default();
+    let mut list_builder = message.init_root::<metric_batch_v2::Builder>().init_metrics(metrics.len() as u32);
+    for (i, metric) in metrics.iter().enumerate() {
+        capnp_client::write_metric_v2(metric, list_builder.reborrow().get(i as u32));
+    }
+    let bytes = capnp_bytes(&message, encoding);
+    
+    let reader = capnp_message(&bytes, encoding);
+    let list_reader = reader.get_root::<metric_batch_v2::Reader>().unwrap().get_metrics().unwrap();
+    black_box(list_reader.iter().map(|metric| capnp_client::read_metric_v2(metric).unwrap()).collect::<Vec<_>>());
+    bytes.len()
+}
+
+/// Flat vs nested points (`MetricPointV2`: per-core CPU, a latency histogram
+/// and a process list) converted, encoded and decoded in each protocol's wire
+/// format, without the network. The flat points are the nested ones
+/// flattened, so the difference is the cost of the repeated and nested
+/// fields. Prints the encoded size of each.
+fn benchmark_nested_payload(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nested_payload");
+    
+    for size in [10, 100, 1000] {
+        let nested = TestDataGenerator::new().generate_v2(size);
+        let flat: Vec<MetricPoint> = nested.iter().map(MetricPointV2::flatten).collect();
+        let (nested, flat) = (&nested, &flat);
+        
+        type RoundTrip<'a> = Box<dyn Fn() -> usize + 'a>;
+        let mut formats: Vec<(String, RoundTrip<'_>, RoundTrip<'_>)> = Vec::new();
+        for encoding in BodyEncoding::ALL {
+            formats.push((
+                format!("REST/{}", encoding.label()),
+                Box::new(move || body_round_trip(encoding, flat)),
+                Box::new(move || body_round_trip(encoding, nested)),
+            ));
+        }
+        formats.push((
+            "gRPC/protobuf".to_string(),
+            Box::new(move || protobuf_round_trip(flat)),
+            Box::new(move || protobuf_round_trip_v2(nested)),
+        ));
+        for encoding in CapnpEncoding::ALL {
+            formats.push((
+                format!("CapnProto/{}", encoding.label()),
+                Box::new(move || capnp_round_trip(flat, encoding)),
+                Box::new(move || capnp_round_trip_v2(nested, encoding)),
+            ));
+        }
+        
+        for (label, flat_round_trip, nested_round_trip) in &formats {
+            println!("{} with {} points: {} bytes flat, {} bytes nested", label, size, flat_round_trip(), nested_round_trip());
+            group.bench_function(BenchmarkId::new(format!("{}/flat", label), size), |b| b.iter(flat_round_trip));
+            group.bench_function(BenchmarkId::new(format!("{}/nested", label), size), |b| b.iter(nested_round_trip));
+        }
+    }
+    
+    group.finish();
+}
+
+/// REST query results as an Arrow IPC stream, a column per field, against
+/// the row-oriented body in `PROTOBENCH_REST_ENCODING` and NDJSON, for large
+/// results of the kind analytical consumers pull. Only `rest-service` offers
+/// Arrow, so only it is seeded. Prints each response's size.
+fn benchmark_columnar_query(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("columnar_query");
+    group.sample_size(10);
+    
+    let rows = format!("REST/{}", rest_client::body_encoding().label());
+    for size in [10_000, 100_000, 1_000_000] {
+        let query = rt.block_on(async {
+            let setup_metrics = generate_test_data(size);
+            let everything = MetricQuery::all();
+            rest_client::delete_metrics(everything).await.unwrap();
+            submit_in_batches(&rest_client::RestClient, setup_metrics.iter().cloned(), 1000).await.unwrap();
+            println!(
+                "{} points: {} {} bytes, REST/arrow {} bytes",
+                size,
+                rows,
+                rest_client::body_encoding().encode(&setup_metrics).unwrap().len(),
+                shared::arrow_ipc::encode(&setup_metrics).unwrap().len()
+            );
+            covering_query(&setup_metrics)
+        });
+        
+        group.bench_with_input(BenchmarkId::new(rows.as_str(), size), &size, |b, _| {
+            b.iter(|| rt.block_on(rest_client::query_metrics(black_box(query.clone()))).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("REST/ndjson", size), &size, |b, _| {
+            b.iter(|| rt.block_on(rest_client::stream_metrics(black_box(query.clone()))).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("REST/arrow", size), &size, |b, _| {
+            b.iter(|| rt.block_on(rest_client::query_metrics_arrow(black_box(query.clone()))).unwrap());
+        });
+    }
+    
+    group.finish();
+}
+
+/// Encode a batch in the experimental delta format and decode it again;
+/// returns the encoded size
+fn delta_batch_round_trip(metrics: &[MetricPoint]) -> usize {
+    let bytes = shared::delta_batch::encode(metrics);
+    black_box(shared::delta_batch::decode(&bytes).unwrap());
+    bytes.len()
+}
+
+/// The experimental delta-encoded batch format (`shared::delta_batch`)
+/// against JSON and protobuf batches of the same points, each encoded and
+/// decoded without the network. Prints each format's size. The points come
+/// in time order from few hosts, as one agent's batches would.
+fn benchmark_delta_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("delta_batch");
+    
+    for size in [10, 100, 1000] {
+        let metrics = &generate_test_data(size);
+        
+        type RoundTrip<'a> = Box<dyn Fn() -> usize + 'a>;
+        let formats: [(&str, RoundTrip<'_>); 3] = [
+            ("REST/json", Box::new(move || body_round_trip(BodyEncoding::Json, metrics))),
+            ("gRPC/protobuf", Box::new(move || protobuf_round_trip(metrics))),
+            ("delta", Box::new(move || delta_batch_round_trip(metrics))),
+        ];
+        
+        for (label, round_trip) in &formats {
+            println!("{} with {} points: {} bytes", label, size, round_trip());
+            group.bench_function(BenchmarkId::new(*label, size), |b| b.iter(round_trip));
+        }
+    }
+    
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_submit_single,
+    benchmark_query_single,
     benchmark_statistics_single,
     benchmark_submit_scaling,
     benchmark_query_scaling,
-    benchmark_statistics_scaling
+    benchmark_statistics_scaling,
+    benchmark_rollup_vs_raw,
+    benchmark_query_paged,
+    benchmark_query_streaming,
+    benchmark_time_to_first_point,
+    benchmark_grpc_submit_stream,
+    benchmark_subscribe_push,
+    benchmark_grpc_query_unary,
+    benchmark_timeout_behavior,
+    benchmark_circuit_breaker,
+    benchmark_cancellation_waste,
+    benchmark_grpc_compression,
+    benchmark_compressibility,
+    benchmark_batch_submit,
+    benchmark_payload_shape,
+    benchmark_adversarial_payloads,
+    benchmark_dataset,
+    benchmark_rest_pool_sizes,
+    benchmark_grpc_replicas,
+    benchmark_capnp_worker_pool,
+    benchmark_runtime_flavor,
+    benchmark_promise_pipelining,
+    benchmark_capnp_packing,
+    benchmark_nested_payload,
+    benchmark_delta_batch,
+    benchmark_columnar_query
 );
-criterion_main!(benches);
\ No newline at end of file
+
+// criterion_main!, plus tracing when PROTOBENCH_OTLP_TRACES_ENDPOINT is set,
+// adding what criterion measured to PROTOBENCH_RESULTS and pushing it to any
+// configured dashboard
+fn main() {
+    // The span exporter runs on this runtime for the whole run; the guard,
+    // declared after it, is dropped (and flushed) before it
+    let runtime = Runtime::new().unwrap();
+    let _tracing_guard = runtime.block_on(async { shared::init_tracing_from_env("benchmarks") }).unwrap();
+    let started = std::time::SystemTime::now();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    let usage = resource_usage::recorded();
+    if !usage.is_empty() {
+        println!("\nResource usage of the harness process (getrusage):");
+        print!("{}", usage);
+    }
+
+    if let Some(path) = results::results_path_from_env() {
+        match criterion_results::export(&path, started) {
+            Ok(added) => println!("Added {} criterion results to {}", added, path.display()),
+            Err(e) => eprintln!("Failed to add criterion results to {}: {:#}", path.display(), e),
+        }
+    }
+    if !export::push_targets_from_env().is_empty() {
+        match criterion_results::collect(&criterion_results::criterion_dir(), started) {
+            Ok(results) => {
+                let run = results::BenchmarkRun { results, ..results::BenchmarkRun::new() };
+                runtime.block_on(export::push_from_env(&run, "criterion"));
+            }
+            Err(e) => eprintln!("Failed to read criterion results: {:#}", e),
+        }
+    }
+}
\ No newline at end of file
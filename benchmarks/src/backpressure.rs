@@ -0,0 +1,254 @@
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use shared::generate_test_data_iter;
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::error::FailureBreakdown;
+use crate::statistics::Summary;
+use crate::{heap_in_use, protocol_clients, purge_all_services, rest_client, ProtocolClient};
+
+// How often in-flight requests and heap use are sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+// How long requests still in flight when sending stops get to finish before
+// they are abandoned
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Share of requests failing above which a protocol is said to shed load
+const ERROR_SHARE: f64 = 0.01;
+
+// Rise in latency from the first quarter to the last above which a protocol
+// is said to queue
+const QUEUEING_GROWTH: f64 = 1.5;
+
+/// Load offered to services slowed by `PROTOBENCH_FAULT_DELAY_MS`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackpressureSettings {
+    /// How long the services hold back every response
+    pub delay: Duration,
+    /// Batch submits per second, sent whether or not earlier ones have been
+    /// answered
+    pub rate: f64,
+    /// How long requests are sent for
+    pub duration: Duration,
+    pub batch_size: usize,
+}
+
+impl BackpressureSettings {
+    /// What the services need set for every response to be held back by
+    /// `delay`
+    pub fn service_env(&self) -> Vec<(String, String)> {
+        vec![
+            ("PROTOBENCH_FAULT_DELAY_RATE".to_string(), "1".to_string()),
+            ("PROTOBENCH_FAULT_DELAY_MS".to_string(), self.delay.as_millis().to_string()),
+        ]
+    }
+}
+
+/// How a protocol's client coped with a server slower than the load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behavior {
+    /// More than 1% of requests failed
+    Errors,
+    /// Few requests failed, but the server throttled with `429`s that the
+    /// client waited out
+    Throttled,
+    /// Latency in the last quarter was over 1.5 times that in the first, so
+    /// requests waited somewhere before the server took them
+    Queueing,
+    /// Every request was taken at once and held for the delay
+    Absorbed,
+}
+
+impl Behavior {
+    pub fn label(self) -> &'static str {
+        match self {
+            Behavior::Errors => "errors",
+            Behavior::Throttled => "throttled",
+            Behavior::Queueing => "queueing",
+            Behavior::Absorbed => "absorbed",
+        }
+    }
+}
+
+/// In-flight requests and heap growth at one moment of a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackpressureSample {
+    pub elapsed: Duration,
+    pub in_flight: usize,
+    /// Heap the process holds beyond what it held when sending started
+    pub heap_growth_bytes: i64,
+}
+
+/// One protocol's run against the slowed services
+#[derive(Debug, Clone)]
+pub struct BackpressureResult {
+    pub protocol: String,
+    pub sent: u64,
+    pub completed: u64,
+    pub failures: FailureBreakdown,
+    /// REST `429` responses received, retried or not
+    pub throttled: u64,
+    /// Requests still unanswered when the drain timeout ran out
+    pub abandoned: u64,
+    pub latency_ms: Option<Summary>,
+    /// Mean latency of requests sent in the first and last quarter of the run
+    pub first_quarter_ms: Option<f64>,
+    pub last_quarter_ms: Option<f64>,
+    pub peak_in_flight: usize,
+    pub peak_heap_growth_bytes: i64,
+    pub samples: Vec<BackpressureSample>,
+}
+
+impl BackpressureResult {
+    /// Last-quarter latency over first-quarter latency
+    pub fn latency_growth(&self) -> Option<f64> {
+        let (first, last) = (self.first_quarter_ms?, self.last_quarter_ms?);
+        (first > 0.0).then_some(last / first)
+    }
+
+    pub fn behavior(&self) -> Behavior {
+        let failed = self.failures.total() + self.abandoned;
+        if failed as f64 > self.sent as f64 * ERROR_SHARE {
+            Behavior::Errors
+        } else if self.throttled > 0 {
+            Behavior::Throttled
+        } else if self.latency_growth().is_some_and(|growth| growth > QUEUEING_GROWTH) {
+            Behavior::Queueing
+        } else {
+            Behavior::Absorbed
+        }
+    }
+}
+
+/// Every protocol's `BackpressureResult`, in `protocol_clients` order
+#[derive(Debug, Clone)]
+pub struct BackpressureReport {
+    pub settings: BackpressureSettings,
+    pub results: Vec<BackpressureResult>,
+}
+
+/// Offer `settings.rate` batch submits a second to each protocol in turn for
+/// `settings.duration`, against services already slowed with
+/// `settings.service_env()`, and record how the client copes: requests
+/// queued in flight, heap growth, errors and throttling. Services are
+/// emptied before each protocol and afterwards.
+pub async fn run(settings: BackpressureSettings) -> anyhow::Result<BackpressureReport> {
+    let mut report = BackpressureReport { settings, results: Vec::new() };
+    for (name, client) in protocol_clients() {
+        purge_all_services().await?;
+        report.results.push(drive(&name, client.as_ref(), &settings).await);
+    }
+    purge_all_services().await?;
+    Ok(report)
+}
+
+async fn drive(protocol: &str, client: &dyn ProtocolClient, settings: &BackpressureSettings) -> BackpressureResult {
+    let mut points = generate_test_data_iter(usize::MAX);
+    let send_interval = Duration::from_secs_f64(1.0 / settings.rate.max(f64::EPSILON));
+    let quarter = settings.duration / 4;
+    let mut pending = FuturesUnordered::new();
+    let mut sends = tokio::time::interval(send_interval);
+    let mut sampling = tokio::time::interval(SAMPLE_INTERVAL);
+    let throttled_before = rest_client::throttled_responses();
+    let heap_before = heap_in_use() as i64;
+
+    let mut result = BackpressureResult {
+        protocol: protocol.to_string(),
+        sent: 0,
+        completed: 0,
+        failures: FailureBreakdown::default(),
+        throttled: 0,
+        abandoned: 0,
+        latency_ms: None,
+        first_quarter_ms: None,
+        last_quarter_ms: None,
+        peak_in_flight: 0,
+        peak_heap_growth_bytes: 0,
+        samples: Vec::new(),
+    };
+    // (sent at, latency) of every answered request
+    let mut latencies: Vec<(Duration, f64)> = Vec::new();
+    let start = Instant::now();
+    let stop_sending = start + settings.duration;
+    let give_up = stop_sending + DRAIN_TIMEOUT;
+
+    loop {
+        let sending = Instant::now() < stop_sending;
+        if !sending && pending.is_empty() {
+            break;
+        }
+        tokio::select! {
+            _ = sends.tick(), if sending => {
+                let batch = points.by_ref().take(settings.batch_size.max(1)).collect();
+                let sent_at = Instant::now();
+                pending.push(async move { (sent_at, client.submit_batch(batch).await) });
+                result.sent += 1;
+                result.peak_in_flight = result.peak_in_flight.max(pending.len());
+            }
+            Some((sent_at, outcome)) = pending.next(), if !pending.is_empty() => match outcome {
+                Ok(_) => {
+                    result.completed += 1;
+                    latencies.push((sent_at - start, sent_at.elapsed().as_secs_f64() * 1000.0));
+                }
+                Err(e) => result.failures.record(&e),
+            },
+            _ = sampling.tick() => {
+                let heap_growth_bytes = heap_in_use() as i64 - heap_before;
+                result.peak_heap_growth_bytes = result.peak_heap_growth_bytes.max(heap_growth_bytes);
+                result.samples.push(BackpressureSample { elapsed: start.elapsed(), in_flight: pending.len(), heap_growth_bytes });
+            }
+            _ = tokio::time::sleep_until(give_up), if !sending => {
+                result.abandoned = pending.len() as u64;
+                break;
+            }
+        }
+    }
+
+    result.throttled = rest_client::throttled_responses() - throttled_before;
+    let all: Vec<f64> = latencies.iter().map(|&(_, ms)| ms).collect();
+    result.latency_ms = Summary::of(&all);
+    result.first_quarter_ms = mean_latency(&latencies, |sent| sent < quarter);
+    result.last_quarter_ms = mean_latency(&latencies, |sent| sent >= settings.duration - quarter);
+    result
+}
+
+/// Mean latency of the requests whose send time `sent` accepts
+fn mean_latency(latencies: &[(Duration, f64)], sent: impl Fn(Duration) -> bool) -> Option<f64> {
+    let selected: Vec<f64> = latencies.iter().filter(|&&(at, _)| sent(at)).map(|&(_, ms)| ms).collect();
+    (!selected.is_empty()).then(|| selected.iter().sum::<f64>() / selected.len() as f64)
+}
+
+impl fmt::Display for BackpressureReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:.0} batch submits/s of {} points for {:?}, every response held back {:?}",
+            self.settings.rate, self.settings.batch_size, self.settings.duration, self.settings.delay
+        )?;
+        writeln!(
+            f,
+            "  {:<24} {:>7} {:>7} {:>10} {:>8} {:>6} {:>9} {:>12} {:>9}  {:<10} Failures",
+            "Protocol", "Sent", "OK", "Mean (ms)", "Growth", "429s", "Abandoned", "Peak heap", "In flight", "Behavior"
+        )?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "  {:<24} {:>7} {:>7} {:>10.1} {:>8} {:>6} {:>9} {:>12} {:>9}  {:<10} {}",
+                result.protocol,
+                result.sent,
+                result.completed,
+                result.latency_ms.map_or(0.0, |latency| latency.mean),
+                result.latency_growth().map_or("-".to_string(), |growth| format!("×{:.2}", growth)),
+                result.throttled,
+                result.abandoned,
+                result.peak_heap_growth_bytes,
+                result.peak_in_flight,
+                result.behavior().label(),
+                result.failures,
+            )?;
+        }
+        Ok(())
+    }
+}
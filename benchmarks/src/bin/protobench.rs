@@ -0,0 +1,690 @@
+use anyhow::Context;
+use benchmarks::backpressure::{self, BackpressureSettings};
+use benchmarks::capacity::{self, CapacitySettings};
+use benchmarks::check::{quick_pass, Thresholds};
+use benchmarks::connection;
+use benchmarks::dictionary::{self, DictionarySettings};
+use benchmarks::diff::RunDiff;
+use benchmarks::distributed::{self, Workload, DEFAULT_AGENT_PORT};
+use benchmarks::export::push_from_env;
+use benchmarks::fleet::{self, FleetSettings};
+use benchmarks::host_metrics::HostSampler;
+use benchmarks::orchestrator::{LocalServices, Service};
+use benchmarks::render::{self, Format};
+use benchmarks::report::PayloadOperation;
+use benchmarks::results::{BenchmarkRun, ServiceRestart};
+use benchmarks::runner::{sampled_pass, RunnerSettings};
+use benchmarks::soak::{self, SoakSettings};
+use benchmarks::statistics::AggregateReport;
+use benchmarks::verify;
+use benchmarks::ComparisonReport;
+use shared::cli_flag;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: protobench <command> [options]
+
+Commands:
+  serve   [--rest] [--grpc] [--capnp]
+          Start the selected services (all three if none is named) at the
+          addresses the clients target, until Ctrl+C
+  bench   [--points <n,...>] [--warmup <n>] [--samples <n>] [--runs <n>] [--serve]
+          [--output <run.json>]
+          Batch submit and query on every protocol at each point count,
+          each warmed up and sampled with outliers set aside (3 and 20 by
+          default); --runs of 2 or more reports means across runs
+  check   [--thresholds <file>] [--output <run.json>]
+          A quick pass held to the limits in the file (thresholds.toml by
+          default), exiting with status 1 if any is exceeded
+  repeat  [--runs <n>] [--points <n>] [--no-restart] [--output <run.json>]
+          The check pass --runs times (10 by default) on services restarted
+          between runs, reported as means with 95% confidence intervals
+  verify  [--points <n>]
+          Run a small seeded workload twice and exit with status 1 if any
+          protocol's wire bytes or responses differ between the runs
+  report  <run.json> [--format markdown|html] [--output <file>]
+          Render a results file as Markdown or HTML
+  compare <baseline.json> <candidate.json> [--fail-over <percent>]
+          Show how each result changed between two runs
+  soak    [--duration <secs>] [--interval <secs>] [--batch <n>] [--serve]
+          Keep every protocol busy without emptying storage and report
+          latency per interval
+  fleet   [--hosts <n>] [--interval <secs>] [--jitter <fraction>] [--duration <secs>]
+          [--seed <n>] [--serve] [--output <run.json>]
+          Simulate hosts each submitting a point every interval, with the
+          same schedule on every protocol
+  connect [--connections <n>] [--serve] [--output <run.json>]
+          Open new connections to every service one at a time and time
+          their setup apart from a request on a warm connection
+  backpressure [--delay-ms <ms>] [--rate <per sec>] [--duration <secs>] [--batch <n>]
+          Start the services with every response held back and see how each
+          client copes with more load than they answer
+  capacity [--p99-ms <ms>] [--batch <n>] [--step <secs>] [--start-rate <points/s>]
+          [--max-rate <points/s>] [--serve]
+          Search for the most points/s each protocol ingests with p99
+          latency within the bound (50 ms by default)
+  dictionary [--points <n,...>] [--samples <n>] [--dict-size <bytes>] [--level <n>]
+          [--output <run.json>]
+          Train a zstd dictionary on generated metrics and compare REST
+          bodies and Cap'n Proto messages compressed with and without it
+  agent   [--listen <addr>]
+          Run workloads a coordinator sends, against the services this
+          machine's clients target (default 0.0.0.0:7878)
+  coordinate --agents <host:port,...> [--points <n>] [--rounds <n>] [--output <run.json>]
+          Have every agent load the services at once and collect their
+          results
+  sysagent [--protocol rest|grpc|capnp] [--interval <secs>] [--count <n>]
+          Submit this machine's CPU, memory and disk figures through one
+          protocol every interval (10s by default), until --count or Ctrl+C
+
+Services are the binaries built next to this one (or in
+PROTOBENCH_SERVICE_BIN_DIR); the usual PROTOBENCH_* variables apply.";
+
+// Points per operation for `bench` unless given
+const DEFAULT_BENCH_POINTS: usize = 100;
+
+// Read by `check` unless --thresholds names another file
+const DEFAULT_THRESHOLDS_PATH: &str = "thresholds.toml";
+
+// Repetitions and points per operation for `repeat` unless given
+const DEFAULT_REPETITIONS: usize = 10;
+const DEFAULT_REPEAT_POINTS: usize = 100;
+
+// Points in `verify`'s workload unless given; a handful is enough to show
+// ordering and encoding differences
+const DEFAULT_VERIFY_POINTS: usize = 20;
+
+// `fleet` size, submission interval, jitter, length and seed unless given
+const DEFAULT_FLEET_HOSTS: usize = 1000;
+const DEFAULT_FLEET_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_FLEET_JITTER: f64 = 0.1;
+const DEFAULT_FLEET_DURATION: Duration = Duration::from_secs(60);
+const DEFAULT_FLEET_SEED: u64 = 42;
+
+// New connections per protocol for `connect` unless given
+const DEFAULT_CONNECTIONS: usize = 50;
+
+// `backpressure` response delay, offered load, length and batch size unless
+// given: 200 batches/s against 200 ms responses keeps about 40 in flight
+const DEFAULT_BACKPRESSURE_DELAY: Duration = Duration::from_millis(200);
+const DEFAULT_BACKPRESSURE_RATE: f64 = 200.0;
+const DEFAULT_BACKPRESSURE_DURATION: Duration = Duration::from_secs(10);
+const DEFAULT_BACKPRESSURE_BATCH: usize = 10;
+
+// Points per operation and rounds per protocol for `coordinate` unless given
+const DEFAULT_DISTRIBUTED_POINTS: usize = 100;
+const DEFAULT_DISTRIBUTED_ROUNDS: usize = 10;
+
+// Seconds between the host samples `sysagent` submits unless given
+const DEFAULT_SYSAGENT_INTERVAL: u64 = 10;
+
+// `soak` length, reporting interval and batch size unless given
+const DEFAULT_SOAK_DURATION: Duration = Duration::from_secs(300);
+const DEFAULT_SOAK_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_SOAK_BATCH: usize = 100;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let _tracing_guard = shared::init_tracing_from_env("protobench")?;
+    match std::env::args().nth(1).as_deref() {
+        Some("serve") => serve().await,
+        Some("bench") => bench().await,
+        Some("check") => check().await,
+        Some("repeat") => repeat().await,
+        Some("verify") => verify().await,
+        Some("report") => report(),
+        Some("compare") => compare(),
+        Some("soak") => soak().await,
+        Some("fleet") => fleet().await,
+        Some("connect") => connect().await,
+        Some("backpressure") => backpressure().await,
+        Some("capacity") => capacity().await,
+        Some("dictionary") => dictionary().await,
+        Some("agent") => agent().await,
+        Some("coordinate") => coordinate().await,
+        Some("sysagent") => sysagent().await,
+        Some("help" | "--help" | "-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => anyhow::bail!("Unknown command {:?}\n\n{}", command, USAGE),
+        None => anyhow::bail!("{}", USAGE),
+    }
+}
+
+/// Arguments after the command up to the first option
+fn positional() -> Vec<String> {
+    std::env::args().skip(2).take_while(|arg| !arg.starts_with("--")).collect()
+}
+
+fn has_flag(flag: &str) -> bool {
+    std::env::args().any(|arg| arg == flag)
+}
+
+fn parsed_flag<T: std::str::FromStr>(flag: &str) -> anyhow::Result<Option<T>> {
+    match cli_flag(flag)? {
+        Some(value) => value.parse().map(Some).map_err(|_| anyhow::anyhow!("Invalid {} {:?}", flag, value)),
+        None => Ok(None),
+    }
+}
+
+/// `--points` as a comma-separated list of point counts
+fn point_counts() -> anyhow::Result<Option<Vec<usize>>> {
+    let Some(list) = cli_flag("--points")? else {
+        return Ok(None);
+    };
+    list.split(',')
+        .map(|points| points.trim().parse().with_context(|| format!("Invalid --points {:?}", list)))
+        .collect::<anyhow::Result<_>>()
+        .map(Some)
+}
+
+/// All three services with `--serve`, for commands that otherwise use
+/// services already running
+async fn services_if_asked() -> anyhow::Result<Option<LocalServices>> {
+    if !has_flag("--serve") {
+        return Ok(None);
+    }
+    println!("Starting rest-service, grpc-service and capnp-service...");
+    Ok(Some(LocalServices::start().await?))
+}
+
+/// `serve [--rest] [--grpc] [--capnp]`
+async fn serve() -> anyhow::Result<()> {
+    let mut selected: Vec<Service> = ["--rest", "--grpc", "--capnp"]
+        .into_iter()
+        .filter(|flag| has_flag(flag))
+        .filter_map(|flag| Service::from_name(&flag[2..]))
+        .collect();
+    if selected.is_empty() {
+        selected = Service::ALL.to_vec();
+    }
+
+    let services = LocalServices::start_only(&selected).await?;
+    for service in &selected {
+        println!("{} listening on {}", service.binary(), service.listen_args()?.join(" "));
+    }
+    println!("Ctrl+C to stop; a service that exits is started again");
+    shared::shutdown_signal().await;
+    print_restarts(&services.take_restarts());
+    drop(services);
+    Ok(())
+}
+
+fn print_restarts<'a>(restarts: impl IntoIterator<Item = &'a ServiceRestart>) {
+    for restart in restarts {
+        println!("⚠️  {}; results around it are suspect", restart);
+    }
+}
+
+/// `bench [--points <n,...>] [--warmup <n>] [--samples <n>] [--runs <n>] [--serve] [--output <run.json>]`
+async fn bench() -> anyhow::Result<()> {
+    let point_counts = point_counts()?.unwrap_or_else(|| vec![DEFAULT_BENCH_POINTS]);
+    let runs = parsed_flag("--runs")?.unwrap_or(1).max(1);
+    let defaults = RunnerSettings::default();
+    let settings = RunnerSettings {
+        warmup: parsed_flag("--warmup")?.unwrap_or(defaults.warmup),
+        samples: parsed_flag("--samples")?.unwrap_or(defaults.samples).max(1),
+        ..defaults
+    };
+    let services = services_if_asked().await?;
+
+    let mut repetitions = Vec::with_capacity(runs);
+    for repetition in 1..=runs {
+        let mut run = BenchmarkRun::new();
+        for &points in &point_counts {
+            println!(
+                "Run {}/{}: {} points per operation, {} warmup and {} measured runs each",
+                repetition, runs, points, settings.warmup, settings.samples
+            );
+            run.results.extend(sampled_pass(points, settings).await?.results);
+        }
+        if let Some(services) = services.as_ref() {
+            run.restarts = services.take_restarts();
+        }
+        repetitions.push(run);
+    }
+    drop(services);
+    print_restarts(repetitions.iter().flat_map(|run| &run.restarts));
+
+    let run = if runs > 1 {
+        let report = AggregateReport::new(&repetitions);
+        println!("\nMean ± 95% confidence interval over {} runs:", runs);
+        print!("{}", report);
+        // A lead within the noise is reported as a tie
+        print_winners(&point_counts, |operation, points| report.comparison(operation, points));
+        report.to_run()
+    } else {
+        let run = repetitions.remove(0);
+        print_winners(&point_counts, |operation, points| run.comparison(operation, points));
+        run
+    };
+
+    if let Some(output) = cli_flag("--output")? {
+        run.write_to(&output)?;
+        println!("\nResults written to {}", output);
+    }
+    push_from_env(&run, "bench").await;
+    Ok(())
+}
+
+fn print_winners(point_counts: &[usize], comparison: impl Fn(&str, usize) -> Option<ComparisonReport>) {
+    for &points in point_counts {
+        for operation in PayloadOperation::ALL {
+            if let Some(comparison) = comparison(operation.label(), points) {
+                println!("\n{} ({} points):", operation.label(), points);
+                print!("{}", comparison.winners());
+            }
+        }
+    }
+}
+
+/// `check [--thresholds <file>] [--output <run.json>]`: a quick pass held to
+/// the file's limits, exiting with status 1 if any protocol exceeds one (or
+/// if the pass itself fails), for use as a nightly performance gate
+async fn check() -> anyhow::Result<()> {
+    let path = cli_flag("--thresholds")?.unwrap_or_else(|| DEFAULT_THRESHOLDS_PATH.to_string());
+    let thresholds = Thresholds::read_from(&path)?;
+
+    println!("ProtoBench check: {} points per operation against {}", thresholds.points, path);
+    let run = quick_pass(thresholds.points).await?;
+    if let Some(output) = cli_flag("--output")? {
+        run.write_to(&output)?;
+        println!("Results written to {}", output);
+    }
+    push_from_env(&run, "check").await;
+    print_winners(&[thresholds.points], |operation, points| run.comparison(operation, points));
+
+    let violations = thresholds.check(&run);
+    println!();
+    if violations.is_empty() {
+        println!("✅ Every protocol within its limits");
+        return Ok(());
+    }
+    for violation in &violations {
+        println!("❌ {}", violation);
+    }
+    println!("{} limit(s) exceeded", violations.len());
+    std::process::exit(1);
+}
+
+/// `repeat [--runs <n>] [--points <n>] [--no-restart] [--output <run.json>]`:
+/// the quick pass `check` runs, repeated `--runs` times, reported as each
+/// measurement's mean with its 95% confidence interval. The harness starts
+/// the services itself and restarts them between repetitions, so each one is
+/// independent; `--no-restart` uses services already running and only empties
+/// them.
+async fn repeat() -> anyhow::Result<()> {
+    let runs = parsed_flag("--runs")?.unwrap_or(DEFAULT_REPETITIONS);
+    if runs < 2 {
+        anyhow::bail!("--runs must be at least 2 to give a confidence interval");
+    }
+    let points = parsed_flag("--points")?.unwrap_or(DEFAULT_REPEAT_POINTS);
+    let restart = !has_flag("--no-restart");
+
+    println!("ProtoBench repeat: {} runs of {} points per operation", runs, points);
+    let mut services = if restart { Some(LocalServices::start().await?) } else { None };
+    let mut repetitions = Vec::with_capacity(runs);
+    for repetition in 1..=runs {
+        if repetition > 1 {
+            if let Some(services) = services.as_mut() {
+                services.restart().await?;
+            }
+        }
+        println!("Run {}/{}", repetition, runs);
+        let mut run = quick_pass(points).await?;
+        if let Some(services) = services.as_ref() {
+            run.restarts = services.take_restarts();
+        }
+        repetitions.push(run);
+    }
+    drop(services);
+
+    let report = AggregateReport::new(&repetitions);
+    println!("\nMean ± 95% confidence interval over {} runs:", runs);
+    print!("{}", report);
+    print_restarts(report.restarts());
+    // A lead within the noise is reported as a tie
+    print_winners(&[points], |operation, points| report.comparison(operation, points));
+
+    let aggregated = report.to_run();
+    if let Some(output) = cli_flag("--output")? {
+        aggregated.write_to(&output)?;
+        println!("Results written to {}", output);
+    }
+    push_from_env(&aggregated, "repeat").await;
+    Ok(())
+}
+
+/// `verify [--points <n>]`: the same small seeded workload run twice against
+/// emptied services, checking every protocol's wire bytes and decoded
+/// responses match exactly between the runs. Anything that differs, such as
+/// map ordering or a timestamp taken at run time, would make size comparisons
+/// between runs noisy, so the command exits with status 1 listing it.
+async fn verify() -> anyhow::Result<()> {
+    let points = parsed_flag("--points")?.unwrap_or(DEFAULT_VERIFY_POINTS);
+
+    println!("ProtoBench verify: {} points, run twice", points);
+    let first = verify::record_run(points).await?;
+    let second = verify::record_run(points).await?;
+
+    let mismatches = verify::compare(&first, &second);
+    if mismatches.is_empty() {
+        println!("✅ {} operations identical across both runs", first.len());
+        return Ok(());
+    }
+    for mismatch in &mismatches {
+        println!("❌ {}", mismatch);
+    }
+    println!("{} difference(s) between runs", mismatches.len());
+    std::process::exit(1);
+}
+
+/// `report <run.json> [--format markdown|html] [--output <file>]`
+fn report() -> anyhow::Result<()> {
+    let [path] = positional().try_into().map_err(|_| anyhow::anyhow!("report takes one results file\n\n{}", USAGE))?;
+    let run = BenchmarkRun::read_from(&path)?;
+    let output = cli_flag("--output")?;
+    let format = match cli_flag("--format")? {
+        Some(name) => Format::from_name(&name).with_context(|| format!("Unknown --format {:?}; use markdown or html", name))?,
+        None => output.as_deref().map_or(Format::Markdown, Format::for_path),
+    };
+
+    let document = render::render(&run, format);
+    match output {
+        Some(output) => {
+            std::fs::write(&output, document).with_context(|| format!("Failed to write {}", output))?;
+            println!("Report written to {}", output);
+        }
+        None => print!("{}", document),
+    }
+    Ok(())
+}
+
+/// `compare <baseline.json> <candidate.json> [--fail-over <percent>]`: exits
+/// with status 1 when `--fail-over` is given and any latency, traffic or
+/// memory figure grew by more than that percentage
+fn compare() -> anyhow::Result<()> {
+    let [baseline, candidate] =
+        positional().try_into().map_err(|_| anyhow::anyhow!("compare takes a baseline and a candidate results file\n\n{}", USAGE))?;
+    let diff = RunDiff::new(&BenchmarkRun::read_from(&baseline)?, &BenchmarkRun::read_from(&candidate)?);
+    println!("{} against {}:", candidate, baseline);
+    print!("{}", diff);
+
+    let Some(percent) = parsed_flag::<f64>("--fail-over")? else {
+        return Ok(());
+    };
+    let regressions = diff.regressions(percent);
+    println!();
+    if regressions.is_empty() {
+        println!("✅ Nothing grew by more than {}%", percent);
+        return Ok(());
+    }
+    for change in &regressions {
+        println!("❌ {} {} {} points", change.protocol, change.operation, change.points);
+    }
+    println!("{} result(s) grew by more than {}%", regressions.len(), percent);
+    std::process::exit(1);
+}
+
+/// `soak [--duration <secs>] [--interval <secs>] [--batch <n>] [--serve]`
+async fn soak() -> anyhow::Result<()> {
+    let settings = SoakSettings {
+        duration: parsed_flag("--duration")?.map_or(DEFAULT_SOAK_DURATION, Duration::from_secs),
+        interval: parsed_flag("--interval")?.map_or(DEFAULT_SOAK_INTERVAL, Duration::from_secs),
+        batch_size: parsed_flag("--batch")?.unwrap_or(DEFAULT_SOAK_BATCH),
+    };
+    if settings.interval.is_zero() {
+        anyhow::bail!("--interval must be at least 1 second");
+    }
+    let services = services_if_asked().await?;
+
+    println!(
+        "ProtoBench soak: {:?} in {:?} intervals, batches of {} points",
+        settings.duration, settings.interval, settings.batch_size
+    );
+    let report = soak::run(settings, |rows| {
+        for row in rows {
+            println!("{}", row);
+        }
+    })
+    .await?;
+    if let Some(services) = services {
+        print_restarts(&services.take_restarts());
+    }
+
+    println!();
+    print!("{}", report);
+    Ok(())
+}
+
+/// `fleet [--hosts <n>] [--interval <secs>] [--jitter <fraction>] [--duration <secs>] [--seed <n>] [--serve] [--output <run.json>]`
+async fn fleet() -> anyhow::Result<()> {
+    let settings = FleetSettings {
+        hosts: parsed_flag("--hosts")?.unwrap_or(DEFAULT_FLEET_HOSTS),
+        interval: match parsed_flag("--interval")? {
+            Some(secs) => Duration::try_from_secs_f64(secs).context("Invalid --interval")?,
+            None => DEFAULT_FLEET_INTERVAL,
+        },
+        jitter: parsed_flag("--jitter")?.unwrap_or(DEFAULT_FLEET_JITTER),
+        duration: parsed_flag("--duration")?.map_or(DEFAULT_FLEET_DURATION, Duration::from_secs),
+        seed: parsed_flag("--seed")?.unwrap_or(DEFAULT_FLEET_SEED),
+    };
+    if settings.interval.is_zero() || settings.hosts == 0 {
+        anyhow::bail!("--hosts and --interval must be above 0");
+    }
+    let services = services_if_asked().await?;
+
+    println!(
+        "ProtoBench fleet: {} hosts every {:?}, {:.1} submissions/s for {:?} per protocol",
+        settings.hosts,
+        settings.interval,
+        settings.offered_rate(),
+        settings.duration
+    );
+    let report = fleet::run(settings).await?;
+    if let Some(services) = services {
+        print_restarts(&services.take_restarts());
+    }
+    print!("{}", report);
+
+    let run = report.to_run();
+    if let Some(output) = cli_flag("--output")? {
+        run.write_to(&output)?;
+        println!("\nResults written to {}", output);
+    }
+    push_from_env(&run, "fleet").await;
+    Ok(())
+}
+
+/// `connect [--connections <n>] [--serve] [--output <run.json>]`
+async fn connect() -> anyhow::Result<()> {
+    let connections = parsed_flag("--connections")?.unwrap_or(DEFAULT_CONNECTIONS).max(1);
+    let services = services_if_asked().await?;
+
+    println!("ProtoBench connect: {} new connections per protocol", connections);
+    let report = connection::run(connections).await?;
+    if let Some(services) = services {
+        print_restarts(&services.take_restarts());
+    }
+    print!("{}", report);
+
+    let run = report.to_run();
+    if let Some(output) = cli_flag("--output")? {
+        run.write_to(&output)?;
+        println!("\nResults written to {}", output);
+    }
+    push_from_env(&run, "connect").await;
+    Ok(())
+}
+
+/// `backpressure [--delay-ms <ms>] [--rate <per sec>] [--duration <secs>] [--batch <n>]`:
+/// always starts its own services, since they need the delay set
+async fn backpressure() -> anyhow::Result<()> {
+    let settings = BackpressureSettings {
+        delay: parsed_flag("--delay-ms")?.map_or(DEFAULT_BACKPRESSURE_DELAY, Duration::from_millis),
+        rate: parsed_flag("--rate")?.unwrap_or(DEFAULT_BACKPRESSURE_RATE),
+        duration: parsed_flag("--duration")?.map_or(DEFAULT_BACKPRESSURE_DURATION, Duration::from_secs),
+        batch_size: parsed_flag("--batch")?.unwrap_or(DEFAULT_BACKPRESSURE_BATCH),
+    };
+    if settings.rate <= 0.0 {
+        anyhow::bail!("--rate must be above 0");
+    }
+
+    println!("Starting rest-service, grpc-service and capnp-service with every response held back {:?}...", settings.delay);
+    let services = LocalServices::start_with_env(&Service::ALL, &settings.service_env()).await?;
+    let report = backpressure::run(settings).await?;
+    print_restarts(&services.take_restarts());
+    drop(services);
+    print!("{}", report);
+    Ok(())
+}
+
+/// `capacity [--p99-ms <ms>] [--batch <n>] [--step <secs>] [--start-rate <points/s>] [--max-rate <points/s>] [--serve]`
+async fn capacity() -> anyhow::Result<()> {
+    let defaults = CapacitySettings::default();
+    let settings = CapacitySettings {
+        p99_bound: parsed_flag("--p99-ms")?.map_or(defaults.p99_bound, Duration::from_millis),
+        batch_size: parsed_flag("--batch")?.unwrap_or(defaults.batch_size).max(1),
+        step: parsed_flag("--step")?.map_or(defaults.step, Duration::from_secs),
+        start_rate: parsed_flag("--start-rate")?.unwrap_or(defaults.start_rate),
+        max_rate: parsed_flag("--max-rate")?.unwrap_or(defaults.max_rate),
+        ..defaults
+    };
+    if settings.step.is_zero() || settings.start_rate <= 0.0 || settings.max_rate < settings.start_rate {
+        anyhow::bail!("--step and --start-rate must be above 0, and --max-rate at least --start-rate");
+    }
+    let services = services_if_asked().await?;
+
+    println!(
+        "ProtoBench capacity: p99 within {:?}, batches of {} points, {:?} per rate",
+        settings.p99_bound, settings.batch_size, settings.step
+    );
+    let report = capacity::run(settings, |protocol, probe| println!("  {:<24} {}", protocol, probe)).await?;
+    if let Some(services) = services {
+        print_restarts(&services.take_restarts());
+    }
+
+    println!();
+    print!("{}", report);
+    Ok(())
+}
+
+/// `dictionary [--points <n,...>] [--samples <n>] [--dict-size <bytes>] [--level <n>] [--output <run.json>]`
+async fn dictionary() -> anyhow::Result<()> {
+    let defaults = DictionarySettings::default();
+    let settings = DictionarySettings {
+        batch_sizes: point_counts()?.unwrap_or(defaults.batch_sizes),
+        training_samples: parsed_flag("--samples")?.unwrap_or(defaults.training_samples),
+        dictionary_bytes: parsed_flag("--dict-size")?.unwrap_or(defaults.dictionary_bytes),
+        level: parsed_flag("--level")?.unwrap_or(defaults.level),
+        ..defaults
+    };
+
+    println!(
+        "ProtoBench dictionary: {} training samples, dictionaries up to {} bytes",
+        settings.training_samples, settings.dictionary_bytes
+    );
+    let report = dictionary::run(&settings)?;
+    print!("{}", report);
+
+    let run = report.to_run();
+    if let Some(output) = cli_flag("--output")? {
+        run.write_to(&output)?;
+        println!("\nResults written to {}", output);
+    }
+    push_from_env(&run, "dictionary").await;
+    Ok(())
+}
+
+/// `agent [--listen <addr>]`
+async fn agent() -> anyhow::Result<()> {
+    let listen = cli_flag("--listen")?.unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_AGENT_PORT));
+    let listener = tokio::net::TcpListener::bind(&listen).await.with_context(|| format!("Failed to listen on {}", listen))?;
+    println!("ProtoBench agent listening on {}", listener.local_addr()?);
+    tokio::select! {
+        result = distributed::serve_agent(listener) => result,
+        _ = shared::shutdown_signal() => Ok(()),
+    }
+}
+
+/// `coordinate --agents <host:port,...> [--points <n>] [--rounds <n>] [--output <run.json>]`
+async fn coordinate() -> anyhow::Result<()> {
+    let agents: Vec<String> = cli_flag("--agents")?
+        .context("coordinate needs --agents host:port,...")?
+        .split(',')
+        .map(|agent| agent.trim().to_string())
+        .filter(|agent| !agent.is_empty())
+        .collect();
+    let workload = Workload {
+        points: parsed_flag("--points")?.unwrap_or(DEFAULT_DISTRIBUTED_POINTS),
+        rounds: parsed_flag("--rounds")?.unwrap_or(DEFAULT_DISTRIBUTED_ROUNDS).max(1),
+    };
+
+    println!(
+        "ProtoBench coordinate: {} agents, {} rounds of {} points per protocol each",
+        agents.len(),
+        workload.rounds,
+        workload.points
+    );
+    let report = distributed::coordinate(&agents, workload).await?;
+    print!("{}", report);
+    // Every round of every agent is one sample, so leads are tested
+    let aggregate = report.aggregate();
+    print_winners(&[workload.points], |operation, points| aggregate.comparison(operation, points));
+
+    let run = report.to_run();
+    if let Some(output) = cli_flag("--output")? {
+        run.write_to(&output)?;
+        println!("\nResults written to {}", output);
+    }
+    push_from_env(&run, "coordinate").await;
+    Ok(())
+}
+
+/// `sysagent [--protocol rest|grpc|capnp] [--interval <secs>] [--count <n>]`:
+/// submit this machine's real CPU, memory and disk figures through one
+/// protocol every `--interval` seconds, like a metrics collector would,
+/// until `--count` samples or Ctrl+C. A failed submit is reported and the
+/// agent carries on.
+async fn sysagent() -> anyhow::Result<()> {
+    let protocol = cli_flag("--protocol")?.unwrap_or_else(|| "rest".to_string());
+    let service = Service::from_name(&protocol).with_context(|| format!("Unknown --protocol {:?}; use rest, grpc or capnp", protocol))?;
+    let interval = parsed_flag("--interval")?.unwrap_or(DEFAULT_SYSAGENT_INTERVAL);
+    if interval == 0 {
+        anyhow::bail!("--interval must be at least 1 second");
+    }
+    let count: Option<u64> = parsed_flag("--count")?;
+
+    let client = service.client();
+    let mut sampler = HostSampler::new();
+    let mut ticks = tokio::time::interval(Duration::from_secs(interval));
+    let shutdown = shared::shutdown_signal();
+    tokio::pin!(shutdown);
+    println!("ProtoBench sysagent: host metrics to {} every {}s; Ctrl+C to stop", service.binary(), interval);
+
+    let (mut submitted, mut failed) = (0u64, 0u64);
+    while count.is_none_or(|count| submitted + failed < count) {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = &mut shutdown => break,
+        }
+        let point = sampler.sample();
+        let summary = format!(
+            "cpu {:.1}%, memory {} bytes, {} disk ops",
+            point.cpu_percent, point.memory_bytes, point.disk_io_ops
+        );
+        match client.submit_metric(point).await {
+            Ok(()) => {
+                submitted += 1;
+                println!("✅ {}", summary);
+            }
+            Err(e) => {
+                failed += 1;
+                println!("❌ {}: {}", summary, e);
+            }
+        }
+    }
+    println!("{} submitted, {} failed", submitted, failed);
+    Ok(())
+}
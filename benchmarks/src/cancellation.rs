@@ -0,0 +1,127 @@
+use shared::{MetricPoint, MetricQuery, MetricRollup, MetricStatistics, MetricsService};
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::connection::ConnectionTimings;
+use crate::report::PayloadOperation;
+use crate::wire_bytes::WireCounter;
+use crate::{ProtocolClient, ProtocolError};
+
+/// Run `operation` until it finishes or `token` is cancelled, whichever comes
+/// first; cancellation fails it with `ProtocolError::Cancelled`. Cancelling drops the operation's future, which is how each server
+/// finds out: reqwest resets the HTTP/2 stream (or closes the HTTP/1
+/// connection), tonic sends RST_STREAM so grpc-service drops the handler, and
+/// dropping the capnp promise sends a `Finish` for the call.
+pub async fn cancellable<T>(token: &CancellationToken, operation: impl Future<Output = Result<T, ProtocolError>>) -> Result<T, ProtocolError> {
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(ProtocolError::Cancelled),
+        result = operation => result,
+    }
+}
+
+/// Any `ProtocolClient` with every operation run through `cancellable`, so
+/// one token cancels whatever the client has in flight
+pub struct Cancellable<'a> {
+    client: &'a dyn ProtocolClient,
+    token: CancellationToken,
+}
+
+impl<'a> Cancellable<'a> {
+    pub fn new(client: &'a dyn ProtocolClient, token: CancellationToken) -> Self {
+        Self { client, token }
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl MetricsService for Cancellable<'_> {
+    type Error = ProtocolError;
+
+    async fn submit_metric(&self, metric: MetricPoint) -> Result<(), ProtocolError> {
+        cancellable(&self.token, self.client.submit_metric(metric)).await
+    }
+
+    async fn query_metrics(&self, query: MetricQuery) -> Result<Vec<MetricPoint>, ProtocolError> {
+        cancellable(&self.token, self.client.query_metrics(query)).await
+    }
+
+    async fn get_statistics(&self, query: MetricQuery) -> Result<MetricStatistics, ProtocolError> {
+        cancellable(&self.token, self.client.get_statistics(query)).await
+    }
+
+    async fn delete_metrics(&self, query: MetricQuery) -> Result<u64, ProtocolError> {
+        cancellable(&self.token, self.client.delete_metrics(query)).await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ProtocolClient for Cancellable<'_> {
+    async fn submit_batch(&self, metrics: Vec<MetricPoint>) -> Result<u64, ProtocolError> {
+        cancellable(&self.token, self.client.submit_batch(metrics)).await
+    }
+
+    async fn query_rollups(&self, query: MetricQuery) -> Result<Vec<MetricRollup>, ProtocolError> {
+        cancellable(&self.token, self.client.query_rollups(query)).await
+    }
+
+    async fn query_with_timeout(&self, query: MetricQuery, timeout: Duration) -> Result<Vec<MetricPoint>, ProtocolError> {
+        cancellable(&self.token, self.client.query_with_timeout(query, timeout)).await
+    }
+
+    fn wire_bytes(&self) -> &'static WireCounter {
+        self.client.wire_bytes()
+    }
+
+    fn payload_bytes(&self, operation: PayloadOperation, metrics: &[MetricPoint]) -> usize {
+        self.client.payload_bytes(operation, metrics)
+    }
+
+    fn metrics_exporter_env(&self) -> &'static str {
+        self.client.metrics_exporter_env()
+    }
+
+    async fn connection_timings(&self) -> Result<ConnectionTimings, ProtocolError> {
+        cancellable(&self.token, self.client.connection_timings()).await
+    }
+}
+
+// Reused so a scrape costs one loopback request, not a new client's setup
+static SCRAPE_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// The `protobench_requests_in_flight` gauge from a service's Prometheus
+/// exporter at `addr`
+pub async fn server_requests_in_flight(addr: &str) -> anyhow::Result<i64> {
+    let body = SCRAPE_CLIENT
+        .get_or_init(reqwest::Client::new)
+        .get(format!("http://{}/metrics", addr))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    body.lines()
+        .find(|line| line.starts_with("protobench_requests_in_flight"))
+        .and_then(|line| line.rsplit(' ').next())
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("No requests_in_flight gauge at {}", addr))
+}
+
+/// Wait until the service behind `exporter_addr` has nothing in flight,
+/// returning how long that took: the server-side work still going on after
+/// the client stopped waiting, to within one scrape
+pub async fn wait_for_server_idle(exporter_addr: &str, timeout: Duration) -> anyhow::Result<Duration> {
+    let start = std::time::Instant::now();
+    while server_requests_in_flight(exporter_addr).await? > 0 {
+        if start.elapsed() > timeout {
+            anyhow::bail!("{} still busy after {:?}", exporter_addr, timeout);
+        }
+        tokio::time::sleep(Duration::from_micros(200)).await;
+    }
+    Ok(start.elapsed())
+}
@@ -0,0 +1,162 @@
+use shared::HttpCompression;
+
+use crate::results::BenchmarkRun;
+
+// The schemas the gRPC and Cap'n Proto clients are generated from, so the
+// matrix lists what the code offers rather than what the README claims
+const PROTO_SCHEMA: &str = include_str!("../../schemas/metrics.proto");
+const CAPNP_SCHEMA: &str = include_str!("../../schemas/metrics.capnp");
+
+/// Column headers of the matrix, one per protocol
+pub const PROTOCOLS: [&str; 3] = ["REST", "gRPC", "Cap'n Proto"];
+
+/// One row of the capability matrix: a feature and how REST, gRPC and Cap'n
+/// Proto offer it, in `PROTOCOLS` order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    pub feature: &'static str,
+    pub support: [String; 3],
+}
+
+impl Capability {
+    fn new(feature: &'static str, rest: impl Into<String>, grpc: impl Into<String>, capnp: impl Into<String>) -> Self {
+        Self { feature, support: [rest.into(), grpc.into(), capnp.into()] }
+    }
+}
+
+/// Methods of a `.proto` service that stream, each with the side that
+/// streams: `client`, `server` or `bidi`
+pub fn grpc_streaming_methods(proto: &str) -> Vec<String> {
+    proto
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("rpc ")?;
+            let (name, rest) = rest.split_once('(')?;
+            let (request, response) = rest.split_once("returns")?;
+            let streams = |message: &str| message.trim_start_matches(|c: char| c == '(' || c.is_whitespace()).starts_with("stream ");
+            let side = match (streams(request), streams(response)) {
+                (true, true) => "bidi",
+                (true, false) => "client",
+                (false, true) => "server",
+                (false, false) => return None,
+            };
+            Some(format!("{} ({})", name.trim(), side))
+        })
+        .collect()
+}
+
+/// Interfaces a Cap'n Proto schema declares
+fn capnp_interfaces(schema: &str) -> Vec<&str> {
+    schema
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("interface ")?.split_whitespace().next())
+        .collect()
+}
+
+/// Methods of a Cap'n Proto schema whose parameters (`params == true`) or
+/// results take one of the schema's own interfaces: callbacks the server
+/// calls into, or capabilities the caller can pipeline further calls on
+fn capnp_methods_passing_capabilities(schema: &str, params: bool) -> Vec<String> {
+    let interfaces = capnp_interfaces(schema);
+    schema
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let (name, signature) = line.split_once(" @")?;
+            let (arguments, results) = signature.split_once("->")?;
+            let side = if params { arguments } else { results };
+            let capability = interfaces.iter().find(|interface| side.contains(&format!(":{}", interface)))?;
+            Some(format!("{} ({})", name.trim(), capability))
+        })
+        .collect()
+}
+
+/// Methods that stream by pushing to a capability the caller passes in
+pub fn capnp_streaming_methods(schema: &str) -> Vec<String> {
+    capnp_methods_passing_capabilities(schema, true)
+}
+
+/// Methods returning a capability that further calls can be pipelined on
+pub fn capnp_pipelined_methods(schema: &str) -> Vec<String> {
+    capnp_methods_passing_capabilities(schema, false)
+}
+
+fn list_or(items: Vec<String>, empty: &str) -> String {
+    if items.is_empty() {
+        empty.to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+/// Authentication measured in `run`, from the settings it recorded
+fn auth_measured(run: &BenchmarkRun) -> [String; 3] {
+    let set = |name: &str| run.settings.contains_key(name);
+    let token = set("PROTOBENCH_AUTH_TOKEN");
+    let mut rest = Vec::new();
+    let mut grpc = Vec::new();
+    if token {
+        rest.push("bearer token");
+        grpc.push("bearer token");
+    }
+    if set("PROTOBENCH_REST_CA_CERT") {
+        rest.push("TLS");
+    }
+    if set("PROTOBENCH_GRPC_CA_CERT") {
+        grpc.push(if set("PROTOBENCH_GRPC_CLIENT_CERT") && set("PROTOBENCH_GRPC_CLIENT_KEY") { "mutual TLS" } else { "TLS" });
+    }
+    let capnp = if token { vec!["token"] } else { Vec::new() };
+    [rest, grpc, capnp].map(|mechanisms| if mechanisms.is_empty() { "none".to_string() } else { mechanisms.join(", ") })
+}
+
+/// Compression measured in `run`, from the settings it recorded
+fn compression_measured(run: &BenchmarkRun) -> [String; 3] {
+    let accepted = |name: &str| run.settings.get(name).map_or_else(|| "identity".to_string(), |list| HttpCompression::parse(list).label());
+    [
+        accepted("PROTOBENCH_REST_ACCEPT_ENCODING"),
+        accepted("PROTOBENCH_GRPC_ACCEPT_ENCODING"),
+        run.settings.get("PROTOBENCH_CAPNP_ENCODING").cloned().unwrap_or_else(|| "unpacked".to_string()),
+    ]
+}
+
+/// What each protocol offers as this harness implements it, and which of
+/// the optional features `run` measured. Streaming and pipelining are read
+/// from the schemas; the rest follows the clients and services.
+pub fn matrix(run: &BenchmarkRun) -> Vec<Capability> {
+    vec![
+        Capability::new(
+            "Streaming",
+            "NDJSON query (/metrics/stream), SSE live tail (/metrics/subscribe)",
+            list_or(grpc_streaming_methods(PROTO_SCHEMA), "none"),
+            list_or(capnp_streaming_methods(CAPNP_SCHEMA), "none"),
+        ),
+        Capability::new(
+            "Promise pipelining",
+            "no",
+            "no",
+            list_or(capnp_pipelined_methods(CAPNP_SCHEMA), "none"),
+        ),
+        Capability::new(
+            "Schema evolution",
+            "by field name; unknown fields are ignored and missing optional ones default",
+            "by field number; unknown fields are skipped and missing ones read as defaults",
+            "by ordinal; fields are only appended, and missing ones read as defaults",
+        ),
+        Capability::new(
+            "Zero-copy reads",
+            "no: bodies decode into owned values",
+            "no: prost decodes into owned messages",
+            "yes: readers borrow the message, though the client copies points into shared types",
+        ),
+        Capability::new(
+            "Browser support",
+            "yes: plain HTTP, but no CORS headers, so same origin only",
+            "no: needs a gRPC-Web proxy",
+            "no: raw TCP or Unix socket",
+        ),
+        Capability::new("Compression", "gzip, br, zstd", "gzip, zstd", "packed encoding"),
+        Capability { feature: "Compression measured", support: compression_measured(run) },
+        Capability::new("Authentication", "bearer token, TLS", "bearer token, TLS, mutual TLS", "token parameter on every call"),
+        Capability { feature: "Authentication measured", support: auth_measured(run) },
+    ]
+}
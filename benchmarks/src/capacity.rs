@@ -0,0 +1,249 @@
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use shared::generate_test_data_iter;
+use std::fmt;
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::error::FailureBreakdown;
+use crate::statistics::percentile;
+use crate::{protocol_clients, purge_all_services, ProtocolClient};
+
+// Share of a probe's requests that may fail, or go unanswered, for it to pass
+const ERROR_SHARE: f64 = 0.01;
+
+// Slowest probe: one batch a second
+const MIN_REQUESTS_PER_SEC: f64 = 1.0;
+
+/// How `run` searches for each protocol's sustainable ingestion rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapacitySettings {
+    /// p99 latency a rate must stay within to count as sustained
+    pub p99_bound: Duration,
+    /// Points per batch submit
+    pub batch_size: usize,
+    /// How long each rate is offered for
+    pub step: Duration,
+    /// Points/s of the first probe
+    pub start_rate: f64,
+    /// Points/s the search stops doubling at
+    pub max_rate: f64,
+    /// The search stops once the highest passing and lowest failing rates are
+    /// within this fraction of each other
+    pub precision: f64,
+}
+
+impl Default for CapacitySettings {
+    fn default() -> Self {
+        Self {
+            p99_bound: Duration::from_millis(50),
+            batch_size: 100,
+            step: Duration::from_secs(10),
+            start_rate: 1_000.0,
+            max_rate: 10_000_000.0,
+            precision: 0.05,
+        }
+    }
+}
+
+impl CapacitySettings {
+    /// Slowest rate probed: one batch a second
+    pub fn min_rate(&self) -> f64 {
+        self.batch_size.max(1) as f64 * MIN_REQUESTS_PER_SEC
+    }
+}
+
+/// Throughput-latency search over rates offered in points/s: doubling from
+/// the start rate until a rate fails (or halving until one passes), then
+/// bisecting between the highest pass and the lowest failure
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateSearch {
+    settings: CapacitySettings,
+    /// Highest rate that passed
+    passed: Option<f64>,
+    /// Lowest rate that failed
+    failed: Option<f64>,
+}
+
+impl RateSearch {
+    pub fn new(settings: CapacitySettings) -> Self {
+        Self { settings, passed: None, failed: None }
+    }
+
+    /// Rate to probe next, or `None` once the search is done
+    pub fn next_rate(&self) -> Option<f64> {
+        let settings = &self.settings;
+        match (self.passed, self.failed) {
+            (None, None) => Some(settings.start_rate.clamp(settings.min_rate(), settings.max_rate)),
+            (Some(passed), None) => (passed < settings.max_rate).then(|| (passed * 2.0).min(settings.max_rate)),
+            (None, Some(failed)) => (failed > settings.min_rate()).then(|| (failed / 2.0).max(settings.min_rate())),
+            (Some(passed), Some(failed)) => (failed > passed * (1.0 + settings.precision)).then(|| (passed + failed) / 2.0),
+        }
+    }
+
+    pub fn record(&mut self, rate: f64, passed: bool) {
+        if passed {
+            self.passed = Some(self.passed.map_or(rate, |best| best.max(rate)));
+        } else {
+            self.failed = Some(self.failed.map_or(rate, |worst| worst.min(rate)));
+        }
+    }
+
+    /// Highest rate that passed, 0 if none did
+    pub fn sustainable_rate(&self) -> f64 {
+        self.passed.unwrap_or(0.0)
+    }
+}
+
+/// One rate offered to one protocol for `CapacitySettings::step`
+#[derive(Debug, Clone)]
+pub struct Probe {
+    /// Points/s offered
+    pub rate: f64,
+    pub sent: u64,
+    pub completed: u64,
+    pub failures: FailureBreakdown,
+    /// Requests still unanswered a step's length after sending stopped
+    pub abandoned: u64,
+    /// From when each batch was due to its response, so time spent queued
+    /// behind a slow protocol counts
+    pub p99_ms: f64,
+    /// Points/s stored over the step and the drain after it
+    pub achieved_rate: f64,
+    pub passed: bool,
+}
+
+/// One protocol's search
+#[derive(Debug, Clone)]
+pub struct CapacityResult {
+    pub protocol: String,
+    /// Highest rate whose p99 stayed within the bound, in points/s
+    pub sustainable_rate: f64,
+    /// In the order they ran
+    pub probes: Vec<Probe>,
+}
+
+impl CapacityResult {
+    /// The probe at `sustainable_rate`
+    pub fn sustained(&self) -> Option<&Probe> {
+        self.probes.iter().find(|probe| probe.passed && probe.rate == self.sustainable_rate)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CapacityReport {
+    pub settings: CapacitySettings,
+    pub results: Vec<CapacityResult>,
+}
+
+/// Find each protocol's maximum sustainable ingestion rate: the most
+/// points/s, offered as batch submits on a fixed schedule whether or not
+/// earlier ones have been answered, at which p99 latency stays within
+/// `settings.p99_bound` and under 1% of requests fail. Services are emptied
+/// before every probe and afterwards.
+pub async fn run(settings: CapacitySettings, mut progress: impl FnMut(&str, &Probe)) -> anyhow::Result<CapacityReport> {
+    let mut report = CapacityReport { settings, results: Vec::new() };
+    for (name, client) in protocol_clients() {
+        let mut search = RateSearch::new(settings);
+        let mut probes = Vec::new();
+        while let Some(rate) = search.next_rate() {
+            purge_all_services().await?;
+            let probe = offer(client.as_ref(), rate, &settings).await;
+            progress(&name, &probe);
+            search.record(rate, probe.passed);
+            probes.push(probe);
+        }
+        report.results.push(CapacityResult { protocol: name, sustainable_rate: search.sustainable_rate(), probes });
+    }
+    purge_all_services().await?;
+    Ok(report)
+}
+
+async fn offer(client: &dyn ProtocolClient, rate: f64, settings: &CapacitySettings) -> Probe {
+    let batch_size = settings.batch_size.max(1);
+    let mut points = generate_test_data_iter(usize::MAX);
+    let mut sends = tokio::time::interval(Duration::from_secs_f64(batch_size as f64 / rate));
+    // Batches due while the harness was busy go out at once, late, rather
+    // than being skipped, so the offered rate holds
+    sends.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    let mut pending = FuturesUnordered::new();
+    let mut latencies_ms = Vec::new();
+    let mut probe = Probe {
+        rate,
+        sent: 0,
+        completed: 0,
+        failures: FailureBreakdown::default(),
+        abandoned: 0,
+        p99_ms: 0.0,
+        achieved_rate: 0.0,
+        passed: false,
+    };
+    let start = Instant::now();
+    let stop_sending = start + settings.step;
+    let give_up = stop_sending + settings.step;
+
+    loop {
+        let sending = Instant::now() < stop_sending;
+        if !sending && pending.is_empty() {
+            break;
+        }
+        tokio::select! {
+            due = sends.tick(), if sending => {
+                let batch: Vec<_> = points.by_ref().take(batch_size).collect();
+                pending.push(async move { (client.submit_batch(batch).await, due.elapsed()) });
+                probe.sent += 1;
+            }
+            Some((outcome, latency)) = pending.next(), if !pending.is_empty() => match outcome {
+                Ok(_) => {
+                    probe.completed += 1;
+                    latencies_ms.push(latency.as_secs_f64() * 1000.0);
+                }
+                Err(e) => probe.failures.record(&e),
+            },
+            _ = tokio::time::sleep_until(give_up), if !sending => {
+                probe.abandoned = pending.len() as u64;
+                break;
+            }
+        }
+    }
+
+    latencies_ms.sort_by(f64::total_cmp);
+    probe.p99_ms = percentile(&latencies_ms, 99.0);
+    probe.achieved_rate = (probe.completed * batch_size as u64) as f64 / start.elapsed().as_secs_f64().max(f64::EPSILON);
+    let failed = probe.failures.total() + probe.abandoned;
+    probe.passed = probe.completed > 0
+        && failed as f64 <= probe.sent as f64 * ERROR_SHARE
+        && probe.p99_ms <= settings.p99_bound.as_secs_f64() * 1000.0;
+    probe
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>12.0} points/s offered, {:>12.0} stored, p99 {:>9.1} ms, {} sent, {} abandoned, failures {}: {}",
+            self.rate,
+            self.achieved_rate,
+            self.p99_ms,
+            self.sent,
+            self.abandoned,
+            self.failures,
+            if self.passed { "sustained" } else { "not sustained" }
+        )
+    }
+}
+
+impl fmt::Display for CapacityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Highest ingestion rate with p99 within {:?}, batches of {} points offered for {:?} each:",
+            self.settings.p99_bound, self.settings.batch_size, self.settings.step
+        )?;
+        writeln!(f, "  {:<24} {:>14} {:>10} {:>7}", "Protocol", "Points/s", "p99 (ms)", "Probes")?;
+        for result in &self.results {
+            let p99 = result.sustained().map_or("-".to_string(), |probe| format!("{:.1}", probe.p99_ms));
+            writeln!(f, "  {:<24} {:>14.0} {:>10} {:>7}", result.protocol, result.sustainable_rate, p99, result.probes.len())?;
+        }
+        Ok(())
+    }
+}
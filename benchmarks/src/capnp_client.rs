@@ -1,22 +1,337 @@
-use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
-use futures_util::io::AsyncReadExt;
-use shared::{MetricPoint as SharedMetricPoint, MetricQuery as SharedMetricQuery, MetricStatistics as SharedMetricStatistics};
+use capnp::capability::Promise;
+use capnp::message::ReaderOptions;
+use capnp_rpc::{pry, rpc_twoparty_capnp, twoparty, RpcSystem};
+use capnp_futures::serialize_packed::{PackedRead, PackedWrite};
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use futures_util::io::{AsyncReadExt, BufReader};
+use shared::{AuthConfig, CapnpEncoding, PayloadCipher, SealedStream, MetricPoint as SharedMetricPoint, MetricQuery as SharedMetricQuery, MetricRollup as SharedMetricRollup, MetricStatistics as SharedMetricStatistics, StorageStats as SharedStorageStats};
 use std::collections::HashMap;
-use tokio::net::TcpStream;
-use crate::metrics_capnp::metrics_service;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::UnixStream;
+use tracing::Instrument;
+use crate::metrics_capnp::{metric_point, metric_point_v2, metric_query, metric_rollup, metric_sink, metric_statistics, metrics_service};
+use crate::connection::{self, ConnectionTimings};
+use crate::report::PayloadOperation;
+use crate::wire_bytes::{CountingStream, WireCounter};
+use crate::ProtocolError;
 
-// Create a new client connection for each request
-// This avoids the Send/Sync issues with static storage
-async fn create_client() -> anyhow::Result<(metrics_service::Client, tokio::task::JoinHandle<()>)> {
-    let stream = TcpStream::connect("127.0.0.1:55556").await?;
+const PROTOCOL: &str = "CapnProto";
+
+/// Bytes exchanged with `capnp-service` over TCP or the Unix socket, each
+/// call's connection setup included
+pub static WIRE_BYTES: WireCounter = WireCounter::new();
+
+static AUTH: OnceLock<Option<AuthConfig>> = OnceLock::new();
+static TARGET: OnceLock<String> = OnceLock::new();
+static ENCODING: OnceLock<CapnpEncoding> = OnceLock::new();
+static UDS_PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+static READER_OPTIONS: OnceLock<ReaderOptions> = OnceLock::new();
+
+/// Token sent in every request's `token` param, from `PROTOBENCH_AUTH_TOKEN`
+fn auth() -> Option<&'static AuthConfig> {
+    AUTH.get_or_init(AuthConfig::from_env).as_ref()
+}
+
+/// Server address, from `PROTOBENCH_CAPNP_TARGET`, then `targets.capnp` in
+/// `protobench.toml` (default `127.0.0.1:55556`)
+pub fn target() -> &'static str {
+    TARGET.get_or_init(|| shared::target_addr("PROTOBENCH_CAPNP_TARGET", shared::FileConfig::get().targets.capnp.as_deref(), "127.0.0.1:55556"))
+}
+
+/// Stream encoding, from `PROTOBENCH_CAPNP_ENCODING`; must match the service's
+pub fn encoding() -> CapnpEncoding {
+    *ENCODING.get_or_init(CapnpEncoding::from_env)
+}
+
+/// Unix domain socket to connect to instead of the TCP target, from
+/// `PROTOBENCH_CAPNP_UDS`; the service must be started with `--uds <path>`
+pub fn uds_path() -> Option<&'static PathBuf> {
+    UDS_PATH.get_or_init(|| std::env::var_os("PROTOBENCH_CAPNP_UDS").map(PathBuf::from)).as_ref()
+}
+
+pub fn uses_uds() -> bool {
+    uds_path().is_some()
+}
+
+/// Limits on the responses the client reads, with the traversal limit from
+/// `PROTOBENCH_CAPNP_MAX_MESSAGE_BYTES` like the service's
+fn reader_options() -> ReaderOptions {
+    *READER_OPTIONS.get_or_init(|| {
+        let mut options = ReaderOptions::new();
+        if let Some(limit) = shared::max_message_bytes("PROTOBENCH_CAPNP_MAX_MESSAGE_BYTES") {
+            options.traversal_limit_in_words(Some(limit / 8));
+        }
+        options
+    })
+}
+
+/// The RPC network over `stream`, sealed frame by frame when
+/// `PROTOBENCH_PAYLOAD_KEY` is set. Wire bytes are counted under the sealing.
+fn rpc_network<S>(stream: S) -> Box<dyn capnp_rpc::VatNetwork<rpc_twoparty_capnp::Side>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    match PayloadCipher::get() {
+        Some(cipher) => vat_network(SealedStream::new(stream, cipher.clone())),
+        None => vat_network(stream),
+    }
+}
+
+fn vat_network<S>(stream: S) -> Box<dyn capnp_rpc::VatNetwork<rpc_twoparty_capnp::Side>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+{
     let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
     
-    let rpc_network = Box::new(twoparty::VatNetwork::new(
-        reader,
-        writer,
-        rpc_twoparty_capnp::Side::Client,
-        Default::default(),
-    ));
+    match encoding() {
+        CapnpEncoding::Unpacked => Box::new(twoparty::VatNetwork::new(
+            reader,
+            writer,
+            rpc_twoparty_capnp::Side::Client,
+            reader_options(),
+        )),
+        // PackedRead pulls a few bytes at a time, so buffer the socket under it
+        CapnpEncoding::Packed => Box::new(twoparty::VatNetwork::new(
+            PackedRead::new(BufReader::new(reader)),
+            PackedWrite::new(writer),
+            rpc_twoparty_capnp::Side::Client,
+            reader_options(),
+        )),
+    }
+}
+
+/// Sort a failed call into its `ProtocolError` category: a lost connection
+/// is `Connect`, anything the service raised is `Server`
+fn call_error(error: capnp::Error) -> ProtocolError {
+    match error.kind {
+        capnp::ErrorKind::Disconnected => ProtocolError::connect(PROTOCOL, error),
+        kind => ProtocolError::Server {
+            protocol: PROTOCOL,
+            code: format!("{:?}", kind).to_lowercase(),
+            message: error.extra,
+        },
+    }
+}
+
+/// Reading a response that arrived is a `Decode` failure
+fn decode_error(error: capnp::Error) -> ProtocolError {
+    ProtocolError::decode(PROTOCOL, error)
+}
+
+/// Await a call's response within `timeout`, reporting expiry as
+/// `ProtocolError::Timeout`. Dropping the promise cancels the call on the server.
+async fn within<T>(timeout: Option<Duration>, response: impl Future<Output = capnp::Result<T>>) -> Result<T, ProtocolError> {
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, response).await {
+            Ok(response) => response.map_err(call_error),
+            Err(_) => Err(ProtocolError::Timeout { protocol: PROTOCOL }),
+        },
+        None => response.await.map_err(call_error),
+    }
+}
+
+/// Await a call's response within `PROTOBENCH_CLIENT_TIMEOUT_MS`
+async fn call<T>(response: impl Future<Output = capnp::Result<T>>) -> Result<T, ProtocolError> {
+    within(crate::client_timeout(), response).await
+}
+
+/// Copy `metric` into a `MetricPoint` builder, the inverse of `read_metric`
+pub fn write_metric(metric: &SharedMetricPoint, mut metric_builder: metric_point::Builder<'_>) {
+    metric_builder.set_timestamp(metric.timestamp);
+    metric_builder.set_hostname((&metric.hostname[..]).into());
+    metric_builder.set_cpu_percent(metric.cpu_percent);
+    metric_builder.set_memory_bytes(metric.memory_bytes);
+    metric_builder.set_disk_io_ops(metric.disk_io_ops);
+    
+    let mut tags_builder = metric_builder.init_tags(metric.tags.len() as u32);
+    for (i, (key, value)) in metric.tags.iter().enumerate() {
+        let mut tag_builder = tags_builder.reborrow().get(i as u32);
+        tag_builder.set_key((&key[..]).into());
+        tag_builder.set_value((&value[..]).into());
+    }
+}
+
+/// Copy `query` into a `MetricQuery` builder. The schema has no optional
+/// integers, so an unset limit or offset is sent as 0, which the service
+/// reads back as unset.
+pub fn write_query(query: &SharedMetricQuery, mut query_builder: metric_query::Builder<'_>) {
+    query_builder.set_start_time(query.start_time);
+    query_builder.set_end_time(query.end_time);
+    
+    if let Some(hostname) = &query.hostname_filter {
+        query_builder.set_hostname_filter((&hostname[..]).into());
+    }
+    query_builder.set_limit(query.limit.unwrap_or(0));
+    query_builder.set_offset(query.offset.unwrap_or(0));
+}
+
+/// Copy `stats` into a `MetricStatistics` builder, the inverse of
+/// `read_statistics`
+pub fn write_statistics(stats: &SharedMetricStatistics, mut stats_builder: metric_statistics::Builder<'_>) {
+    stats_builder.set_count(stats.count);
+    stats_builder.set_avg_cpu_percent(stats.avg_cpu_percent);
+    stats_builder.set_avg_memory_bytes(stats.avg_memory_bytes);
+    stats_builder.set_avg_disk_io_ops(stats.avg_disk_io_ops);
+    stats_builder.set_time_range_seconds(stats.time_range_seconds);
+}
+
+/// Copy `rollup` into a `MetricRollup` builder, the inverse of `read_rollup`
+pub fn write_rollup(rollup: &SharedMetricRollup, mut rollup_builder: metric_rollup::Builder<'_>) {
+    rollup_builder.set_hostname((&rollup.hostname[..]).into());
+    rollup_builder.set_minute_start(rollup.minute_start);
+    rollup_builder.set_count(rollup.count);
+    rollup_builder.set_avg_cpu_percent(rollup.avg_cpu_percent);
+    rollup_builder.set_min_cpu_percent(rollup.min_cpu_percent);
+    rollup_builder.set_max_cpu_percent(rollup.max_cpu_percent);
+    rollup_builder.set_avg_memory_bytes(rollup.avg_memory_bytes);
+    rollup_builder.set_min_memory_bytes(rollup.min_memory_bytes);
+    rollup_builder.set_max_memory_bytes(rollup.max_memory_bytes);
+    rollup_builder.set_avg_disk_io_ops(rollup.avg_disk_io_ops);
+    rollup_builder.set_min_disk_io_ops(rollup.min_disk_io_ops);
+    rollup_builder.set_max_disk_io_ops(rollup.max_disk_io_ops);
+}
+
+pub fn read_metric(metric_reader: metric_point::Reader<'_>) -> capnp::Result<SharedMetricPoint> {
+    let mut tags = HashMap::new();
+    for tag_reader in metric_reader.get_tags()?.iter() {
+        let key = tag_reader.get_key()?.to_str()?.to_string();
+        let value = tag_reader.get_value()?.to_str()?.to_string();
+        tags.insert(key, value);
+    }
+    
+    Ok(SharedMetricPoint {
+        timestamp: metric_reader.get_timestamp(),
+        hostname: metric_reader.get_hostname()?.to_str()?.to_string(),
+        cpu_percent: metric_reader.get_cpu_percent(),
+        memory_bytes: metric_reader.get_memory_bytes(),
+        disk_io_ops: metric_reader.get_disk_io_ops(),
+        tags,
+    })
+}
+
+/// Copy `metric` into a `MetricPointV2` builder, the inverse of `read_metric_v2`
+pub fn write_metric_v2(metric: &shared::MetricPointV2, mut metric_builder: metric_point_v2::Builder<'_>) {
+    metric_builder.set_timestamp(metric.timestamp);
+    metric_builder.set_hostname((&metric.hostname[..]).into());
+    metric_builder.set_memory_bytes(metric.memory_bytes);
+    metric_builder.set_disk_io_ops(metric.disk_io_ops);
+    
+    let mut cores_builder = metric_builder.reborrow().init_per_core_cpu_percent(metric.per_core_cpu_percent.len() as u32);
+    for (i, cpu_percent) in metric.per_core_cpu_percent.iter().enumerate() {
+        cores_builder.set(i as u32, *cpu_percent);
+    }
+    
+    let mut histogram_builder = metric_builder.reborrow().init_latency_histogram(metric.latency_histogram.len() as u32);
+    for (i, bucket) in metric.latency_histogram.iter().enumerate() {
+        let mut bucket_builder = histogram_builder.reborrow().get(i as u32);
+        bucket_builder.set_upper_bound_ms(bucket.upper_bound_ms);
+        bucket_builder.set_count(bucket.count);
+    }
+    
+    let mut processes_builder = metric_builder.reborrow().init_processes(metric.processes.len() as u32);
+    for (i, process) in metric.processes.iter().enumerate() {
+        let mut process_builder = processes_builder.reborrow().get(i as u32);
+        process_builder.set_pid(process.pid);
+        process_builder.set_name((&process.name[..]).into());
+        process_builder.set_cpu_percent(process.cpu_percent);
+        process_builder.set_memory_bytes(process.memory_bytes);
+        let mut args_builder = process_builder.init_args(process.args.len() as u32);
+        for (j, arg) in process.args.iter().enumerate() {
+            args_builder.set(j as u32, (&arg[..]).into());
+        }
+    }
+    
+    let mut tags_builder = metric_builder.init_tags(metric.tags.len() as u32);
+    for (i, (key, value)) in metric.tags.iter().enumerate() {
+        let mut tag_builder = tags_builder.reborrow().get(i as u32);
+        tag_builder.set_key((&key[..]).into());
+        tag_builder.set_value((&value[..]).into());
+    }
+}
+
+pub fn read_metric_v2(metric_reader: metric_point_v2::Reader<'_>) -> capnp::Result<shared::MetricPointV2> {
+    let mut latency_histogram = Vec::new();
+    for bucket_reader in metric_reader.get_latency_histogram()?.iter() {
+        latency_histogram.push(shared::HistogramBucket {
+            upper_bound_ms: bucket_reader.get_upper_bound_ms(),
+            count: bucket_reader.get_count(),
+        });
+    }
+    
+    let mut processes = Vec::new();
+    for process_reader in metric_reader.get_processes()?.iter() {
+        let mut args = Vec::new();
+        for arg in process_reader.get_args()?.iter() {
+            args.push(arg?.to_str()?.to_string());
+        }
+        processes.push(shared::ProcessInfo {
+            pid: process_reader.get_pid(),
+            name: process_reader.get_name()?.to_str()?.to_string(),
+            cpu_percent: process_reader.get_cpu_percent(),
+            memory_bytes: process_reader.get_memory_bytes(),
+            args,
+        });
+    }
+    
+    let mut tags = HashMap::new();
+    for tag_reader in metric_reader.get_tags()?.iter() {
+        let key = tag_reader.get_key()?.to_str()?.to_string();
+        let value = tag_reader.get_value()?.to_str()?.to_string();
+        tags.insert(key, value);
+    }
+    
+    Ok(shared::MetricPointV2 {
+        timestamp: metric_reader.get_timestamp(),
+        hostname: metric_reader.get_hostname()?.to_str()?.to_string(),
+        per_core_cpu_percent: metric_reader.get_per_core_cpu_percent()?.iter().collect(),
+        memory_bytes: metric_reader.get_memory_bytes(),
+        disk_io_ops: metric_reader.get_disk_io_ops(),
+        latency_histogram,
+        processes,
+        tags,
+    })
+}
+
+fn read_statistics(stats_reader: metric_statistics::Reader<'_>) -> SharedMetricStatistics {
+    SharedMetricStatistics {
+        count: stats_reader.get_count(),
+        avg_cpu_percent: stats_reader.get_avg_cpu_percent(),
+        avg_memory_bytes: stats_reader.get_avg_memory_bytes(),
+        avg_disk_io_ops: stats_reader.get_avg_disk_io_ops(),
+        time_range_seconds: stats_reader.get_time_range_seconds(),
+    }
+}
+
+fn read_rollup(rollup_reader: metric_rollup::Reader<'_>) -> capnp::Result<SharedMetricRollup> {
+    Ok(SharedMetricRollup {
+        hostname: rollup_reader.get_hostname()?.to_str()?.to_string(),
+        minute_start: rollup_reader.get_minute_start(),
+        count: rollup_reader.get_count(),
+        avg_cpu_percent: rollup_reader.get_avg_cpu_percent(),
+        min_cpu_percent: rollup_reader.get_min_cpu_percent(),
+        max_cpu_percent: rollup_reader.get_max_cpu_percent(),
+        avg_memory_bytes: rollup_reader.get_avg_memory_bytes(),
+        min_memory_bytes: rollup_reader.get_min_memory_bytes(),
+        max_memory_bytes: rollup_reader.get_max_memory_bytes(),
+        avg_disk_io_ops: rollup_reader.get_avg_disk_io_ops(),
+        min_disk_io_ops: rollup_reader.get_min_disk_io_ops(),
+        max_disk_io_ops: rollup_reader.get_max_disk_io_ops(),
+    })
+}
+
+// Create a new client connection for each request
+// This avoids the Send/Sync issues with static storage
+async fn create_client() -> Result<(metrics_service::Client, tokio::task::JoinHandle<()>), ProtocolError> {
+    let connect_error = |e| ProtocolError::connect(PROTOCOL, e);
+    let rpc_network = match uds_path() {
+        Some(path) => rpc_network(CountingStream::new(UnixStream::connect(path).await.map_err(connect_error)?, &WIRE_BYTES)),
+        None => rpc_network(CountingStream::new(connection::connect_tcp(target()).await.map_err(connect_error)?, &WIRE_BYTES)),
+    };
     
     let mut rpc_system = RpcSystem::new(rpc_network, None);
     let client: metrics_service::Client = rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
@@ -31,86 +346,351 @@ async fn create_client() -> anyhow::Result<(metrics_service::Client, tokio::task
     Ok((client, handle))
 }
 
-pub async fn submit_metric(metric: SharedMetricPoint) -> anyhow::Result<()> {
+pub async fn submit_metric(metric: SharedMetricPoint) -> Result<(), ProtocolError> {
     // Run in LocalSet since Cap'n Proto types are !Send
     tokio::task::LocalSet::new()
         .run_until(async {
             let (client, _handle) = create_client().await?;
-            
-            // Create a request builder
-            let mut request = client.submit_metric_request();
-            let mut metric_builder = request.get().init_metric();
-            
-            // Set basic fields
-            metric_builder.set_timestamp(metric.timestamp);
-            metric_builder.set_hostname((&metric.hostname[..]).into());
-            metric_builder.set_cpu_percent(metric.cpu_percent);
-            metric_builder.set_memory_bytes(metric.memory_bytes);
-            metric_builder.set_disk_io_ops(metric.disk_io_ops);
-            
-            // Set tags
-            let mut tags_builder = metric_builder.init_tags(metric.tags.len() as u32);
-            for (i, (key, value)) in metric.tags.iter().enumerate() {
-                let mut tag_builder = tags_builder.reborrow().get(i as u32);
-                tag_builder.set_key((&key[..]).into());
-                tag_builder.set_value((&value[..]).into());
-            }
-            
-            let _response = request.send().promise.await?;
-            Ok::<(), anyhow::Error>(())
+            submit_metric_with(&client, &metric).await
+        })
+        .await
+}
+
+async fn submit_metric_with(client: &metrics_service::Client, metric: &SharedMetricPoint) -> Result<(), ProtocolError> {
+    // Create a request builder
+    let mut request = client.submit_metric_request();
+    if let Some(auth) = auth() {
+        request.get().set_token((&auth.token[..]).into());
+    }
+    if let Some(traceparent) = shared::current_traceparent() {
+        request.get().set_traceparent((&traceparent[..]).into());
+    }
+    request.get().set_request_id((&shared::next_request_id()[..]).into());
+    write_metric(metric, request.get().init_metric());
+    
+    let _response = call(request.send().promise).await?;
+    Ok(())
+}
+
+/// Submit `metrics` over one connection without waiting between calls.
+/// Cap'n Proto has no batch RPC, so this pipelines one `submitMetric` per
+/// point instead; unlike the REST and gRPC batches it is not all-or-nothing.
+/// Returns the number of points the server reports storing.
+pub async fn submit_metrics(metrics: Vec<SharedMetricPoint>) -> Result<u64, ProtocolError> {
+    // Run in LocalSet since Cap'n Proto types are !Send
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            let (client, _handle) = create_client().await?;
+            submit_metrics_with(&client, &metrics).await
         })
         .await
 }
 
-pub async fn query_metrics(query: SharedMetricQuery) -> anyhow::Result<Vec<SharedMetricPoint>> {
+async fn submit_metrics_with(client: &metrics_service::Client, metrics: &[SharedMetricPoint]) -> Result<u64, ProtocolError> {
+    let mut pending = Vec::with_capacity(metrics.len());
+    for metric in metrics {
+        let mut request = client.submit_metric_request();
+        if let Some(auth) = auth() {
+            request.get().set_token((&auth.token[..]).into());
+        }
+        if let Some(traceparent) = shared::current_traceparent() {
+            request.get().set_traceparent((&traceparent[..]).into());
+        }
+        request.get().set_request_id((&shared::next_request_id()[..]).into());
+        write_metric(metric, request.get().init_metric());
+        
+        pending.push(request.send().promise);
+    }
+    let responses = call(futures_util::future::try_join_all(pending)).await?;
+    
+    let mut stored = 0;
+    for response in &responses {
+        stored += response.get().map_err(decode_error)?.get_stored();
+    }
+    Ok(stored)
+}
+
+pub async fn query_metrics(query: SharedMetricQuery) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    query_metrics_within(query, crate::client_timeout()).await
+}
+
+/// `query_metrics` with its own timeout instead of `PROTOBENCH_CLIENT_TIMEOUT_MS`;
+/// expiry is reported as `ProtocolError::Timeout`
+pub async fn query_metrics_with_timeout(query: SharedMetricQuery, timeout: Duration) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    query_metrics_within(query, Some(timeout)).await
+}
+
+async fn query_metrics_within(query: SharedMetricQuery, timeout: Option<Duration>) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
     // Run in LocalSet since Cap'n Proto types are !Send
     tokio::task::LocalSet::new()
         .run_until(async {
             let (client, _handle) = create_client().await?;
+            query_metrics_with(&client, &query, timeout).await
+        })
+        .await
+}
+
+async fn query_metrics_with(
+    client: &metrics_service::Client,
+    query: &SharedMetricQuery,
+    timeout: Option<Duration>,
+) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    // Create a query request
+    let mut request = client.query_metrics_request();
+    if let Some(auth) = auth() {
+        request.get().set_token((&auth.token[..]).into());
+    }
+    if let Some(traceparent) = shared::current_traceparent() {
+        request.get().set_traceparent((&traceparent[..]).into());
+    }
+    request.get().set_request_id((&shared::next_request_id()[..]).into());
+    write_query(query, request.get().init_query());
+    
+    let response = within(timeout, request.send().promise).await?;
+    let metrics_reader = response.get().and_then(|results| results.get_metrics()).map_err(decode_error)?;
+    
+    metrics_reader
+        .iter()
+        .map(read_metric)
+        .collect::<capnp::Result<Vec<SharedMetricPoint>>>()
+        .map_err(decode_error)
+}
+
+/// Work a pool worker runs against its connection, or against the error
+/// opening it
+type PoolJob = Box<dyn FnOnce(Result<metrics_service::Client, ProtocolError>) -> LocalBoxFuture<'static, ()> + Send>;
+
+/// Threads that each hold one Cap'n Proto connection open on their own
+/// single-threaded runtime and make the calls handed to them over a channel.
+/// Unlike the functions above, which connect per call inside a `LocalSet`,
+/// the pool is `Send + Sync` and its futures are `Send`, so it can be shared
+/// between tasks on a multi-threaded runtime for concurrent load.
+///
+/// Calls go to workers round-robin and run concurrently on the worker's
+/// connection. A worker whose connection has dropped reconnects on its next
+/// call. Dropping the pool closes the channels, and each worker exits once
+/// its queue is empty.
+pub struct WorkerPool {
+    workers: Vec<tokio::sync::mpsc::UnboundedSender<PoolJob>>,
+    next: AtomicUsize,
+}
+
+impl WorkerPool {
+    /// Start `workers` threads (at least one). Each connects on its first call.
+    pub fn start(workers: usize) -> std::io::Result<Self> {
+        let mut senders = Vec::with_capacity(workers.max(1));
+        for id in 0..workers.max(1) {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            std::thread::Builder::new()
+                .name(format!("capnp-client-{}", id))
+                .spawn(move || tokio::task::LocalSet::new().block_on(&runtime, run_pool_worker(rx)))?;
+            senders.push(tx);
+        }
+        Ok(Self { workers: senders, next: AtomicUsize::new(0) })
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub async fn submit_metric(&self, metric: SharedMetricPoint) -> Result<(), ProtocolError> {
+        self.dispatch(move |client| async move { submit_metric_with(&client, &metric).await }.boxed_local()).await
+    }
+
+    pub async fn submit_metrics(&self, metrics: Vec<SharedMetricPoint>) -> Result<u64, ProtocolError> {
+        self.dispatch(move |client| async move { submit_metrics_with(&client, &metrics).await }.boxed_local()).await
+    }
+
+    pub async fn query_metrics(&self, query: SharedMetricQuery) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+        self.dispatch(move |client| async move { query_metrics_with(&client, &query, crate::client_timeout()).await }.boxed_local()).await
+    }
+
+    /// Run `work` on the next worker's connection and wait for its answer.
+    /// The call runs in the caller's span, so its `traceparent` continues the
+    /// caller's trace even though it is sent from another thread.
+    async fn dispatch<T, F>(&self, work: F) -> Result<T, ProtocolError>
+    where
+        T: Send + 'static,
+        F: FnOnce(metrics_service::Client) -> LocalBoxFuture<'static, Result<T, ProtocolError>> + Send + 'static,
+    {
+        let (reply, answer) = tokio::sync::oneshot::channel();
+        let span = tracing::Span::current();
+        let job: PoolJob = Box::new(move |client| {
+            async move {
+                let result = match client {
+                    Ok(client) => work(client).instrument(span).await,
+                    Err(e) => Err(e),
+                };
+                // The caller may have stopped waiting
+                let _ = reply.send(result);
+            }
+            .boxed_local()
+        });
+
+        let worker = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        self.workers[worker].send(job).map_err(|_| pool_stopped())?;
+        answer.await.map_err(|_| pool_stopped())?
+    }
+}
+
+fn pool_stopped() -> ProtocolError {
+    ProtocolError::connect(PROTOCOL, anyhow::anyhow!("Cap'n Proto client worker stopped"))
+}
+
+/// Run the jobs a `WorkerPool` hands this worker, each as its own local task
+/// so they share the connection concurrently, until the channel closes
+async fn run_pool_worker(mut jobs: tokio::sync::mpsc::UnboundedReceiver<PoolJob>) {
+    let mut connection: Option<(metrics_service::Client, tokio::task::JoinHandle<()>)> = None;
+    let mut running = tokio::task::JoinSet::new();
+
+    loop {
+        let job = tokio::select! {
+            received = jobs.recv() => match received {
+                Some(job) => job,
+                None => break,
+            },
+            // Reap finished calls so the set doesn't grow over a long run
+            Some(_) = running.join_next(), if !running.is_empty() => continue,
+        };
+        // The RPC system ends when the connection does
+        let client = match connection.as_ref().filter(|(_, rpc_system)| !rpc_system.is_finished()) {
+            Some((client, _)) => Ok(client.clone()),
+            None => create_client().await.map(|(client, rpc_system)| {
+                connection = Some((client.clone(), rpc_system));
+                client
+            }),
+        };
+        running.spawn_local(job(client));
+    }
+
+    while running.join_next().await.is_some() {}
+}
+
+/// Receives the points the service pushes and hands them to `tail_metrics` or
+/// `query_metrics_streaming`
+struct ChannelSink {
+    tx: tokio::sync::mpsc::UnboundedSender<SharedMetricPoint>,
+}
+
+impl metric_sink::Server for ChannelSink {
+    fn push(
+        &mut self,
+        params: metric_sink::PushParams,
+        _results: metric_sink::PushResults,
+    ) -> Promise<(), capnp::Error> {
+        let shared_metric = pry!(read_metric(pry!(pry!(params.get()).get_metric())));
+        
+        // Only fails once the caller has stopped listening
+        let _ = self.tx.send(shared_metric);
+        Promise::ok(())
+    }
+}
+
+/// Subscribe to `query` with a `MetricSink` callback, submit `metrics` over
+/// the same connection, and wait until every matching point has been pushed
+/// back. Returns the number of points received.
+pub async fn tail_metrics(query: SharedMetricQuery, metrics: Vec<SharedMetricPoint>) -> Result<usize, ProtocolError> {
+    // Run in LocalSet since Cap'n Proto types are !Send
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            let (client, _handle) = create_client().await?;
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
             
-            // Create a query request
-            let mut request = client.query_metrics_request();
+            let mut request = client.subscribe_request();
+            if let Some(auth) = auth() {
+                request.get().set_token((&auth.token[..]).into());
+            }
+            if let Some(traceparent) = shared::current_traceparent() {
+                request.get().set_traceparent((&traceparent[..]).into());
+            }
+            request.get().set_request_id((&shared::next_request_id()[..]).into());
             let mut query_builder = request.get().init_query();
             
             query_builder.set_start_time(query.start_time);
             query_builder.set_end_time(query.end_time);
             
-            if let Some(hostname) = query.hostname_filter {
+            if let Some(hostname) = &query.hostname_filter {
                 query_builder.set_hostname_filter((&hostname[..]).into());
             }
+            request.get().set_sink(capnp_rpc::new_client(ChannelSink { tx }));
             
-            let response = request.send().promise.await?;
-            let metrics_reader = response.get()?.get_metrics()?;
+            // Dropping the returned capability would end the subscription
+            let response = call(request.send().promise).await?;
+            let _subscription = response.get().and_then(|results| results.get_subscription()).map_err(decode_error)?;
             
-            let mut metrics = Vec::new();
-            for metric_reader in metrics_reader.iter() {
-                let tags_reader = metric_reader.get_tags()?;
-                let mut tags = HashMap::new();
-                
-                for tag_reader in tags_reader.iter() {
-                    let key = tag_reader.get_key()?.to_str()?.to_string();
-                    let value = tag_reader.get_value()?.to_str()?.to_string();
-                    tags.insert(key, value);
+            let expected = metrics.iter().filter(|metric| query.matches(metric)).count();
+            
+            for metric in metrics {
+                let mut request = client.submit_metric_request();
+                if let Some(auth) = auth() {
+                    request.get().set_token((&auth.token[..]).into());
                 }
+                if let Some(traceparent) = shared::current_traceparent() {
+                    request.get().set_traceparent((&traceparent[..]).into());
+                }
+                request.get().set_request_id((&shared::next_request_id()[..]).into());
+                write_metric(&metric, request.get().init_metric());
                 
-                let shared_metric = SharedMetricPoint {
-                    timestamp: metric_reader.get_timestamp(),
-                    hostname: metric_reader.get_hostname()?.to_str()?.to_string(),
-                    cpu_percent: metric_reader.get_cpu_percent(),
-                    memory_bytes: metric_reader.get_memory_bytes(),
-                    disk_io_ops: metric_reader.get_disk_io_ops(),
-                    tags,
-                };
-                
-                metrics.push(shared_metric);
+                call(request.send().promise).await?;
+            }
+            
+            // Pushes arrive on the same connection while the submits run and
+            // queue up in the channel
+            let mut received = 0;
+            while received < expected {
+                if rx.recv().await.is_none() {
+                    let closed = anyhow::anyhow!("subscription closed after {} of {} points", received, expected);
+                    return Err(ProtocolError::connect(PROTOCOL, closed));
+                }
+                received += 1;
+            }
+            
+            Ok::<usize, ProtocolError>(received)
+        })
+        .await
+}
+
+/// Run `query` through `queryMetricsStreaming`, collecting the points the
+/// service pushes to a `MetricSink` instead of one list in the response
+pub async fn query_metrics_streaming(query: SharedMetricQuery) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    // Run in LocalSet since Cap'n Proto types are !Send
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            let (client, _handle) = create_client().await?;
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            
+            let mut request = client.query_metrics_streaming_request();
+            if let Some(auth) = auth() {
+                request.get().set_token((&auth.token[..]).into());
+            }
+            if let Some(traceparent) = shared::current_traceparent() {
+                request.get().set_traceparent((&traceparent[..]).into());
             }
+            request.get().set_request_id((&shared::next_request_id()[..]).into());
+            write_query(&query, request.get().init_query());
+            request.get().set_sink(capnp_rpc::new_client(ChannelSink { tx }));
             
-            Ok::<Vec<SharedMetricPoint>, anyhow::Error>(metrics)
+            // The service only returns once every push has been acknowledged,
+            // so all of them are already queued in the channel
+            let response = call(request.send().promise).await?;
+            let count = response.get().map_err(decode_error)?.get_count() as usize;
+            
+            let mut metrics = Vec::with_capacity(count);
+            while let Ok(metric) = rx.try_recv() {
+                metrics.push(metric);
+            }
+            if metrics.len() != count {
+                let mismatch = anyhow::anyhow!("stream reported {} points but pushed {}", count, metrics.len());
+                return Err(ProtocolError::decode(PROTOCOL, mismatch));
+            }
+            
+            Ok::<Vec<SharedMetricPoint>, ProtocolError>(metrics)
         })
         .await
 }
 
-pub async fn get_statistics(query: SharedMetricQuery) -> anyhow::Result<SharedMetricStatistics> {
+pub async fn get_statistics(query: SharedMetricQuery) -> Result<SharedMetricStatistics, ProtocolError> {
     // Run in LocalSet since Cap'n Proto types are !Send
     tokio::task::LocalSet::new()
         .run_until(async {
@@ -118,6 +698,124 @@ pub async fn get_statistics(query: SharedMetricQuery) -> anyhow::Result<SharedMe
             
             // Create a statistics request
             let mut request = client.get_statistics_request();
+            if let Some(auth) = auth() {
+                request.get().set_token((&auth.token[..]).into());
+            }
+            if let Some(traceparent) = shared::current_traceparent() {
+                request.get().set_traceparent((&traceparent[..]).into());
+            }
+            request.get().set_request_id((&shared::next_request_id()[..]).into());
+            write_query(&query, request.get().init_query());
+            
+            let response = call(request.send().promise).await?;
+            let stats_reader = response.get().and_then(|results| results.get_statistics()).map_err(decode_error)?;
+            
+            Ok::<SharedMetricStatistics, ProtocolError>(read_statistics(stats_reader))
+        })
+        .await
+}
+pub async fn query_rollups(query: SharedMetricQuery) -> Result<Vec<SharedMetricRollup>, ProtocolError> {
+    // Run in LocalSet since Cap'n Proto types are !Send
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            let (client, _handle) = create_client().await?;
+            
+            // Create a rollups request
+            let mut request = client.query_rollups_request();
+            if let Some(auth) = auth() {
+                request.get().set_token((&auth.token[..]).into());
+            }
+            if let Some(traceparent) = shared::current_traceparent() {
+                request.get().set_traceparent((&traceparent[..]).into());
+            }
+            request.get().set_request_id((&shared::next_request_id()[..]).into());
+            write_query(&query, request.get().init_query());
+            
+            let response = call(request.send().promise).await?;
+            let rollups_reader = response.get().and_then(|results| results.get_rollups()).map_err(decode_error)?;
+            
+            let rollups = rollups_reader
+                .iter()
+                .map(read_rollup)
+                .collect::<capnp::Result<Vec<SharedMetricRollup>>>()
+                .map_err(decode_error)?;
+            
+            Ok::<Vec<SharedMetricRollup>, ProtocolError>(rollups)
+        })
+        .await
+}
+
+/// Statistics and rollups for `query` through one `openQuery` handle.
+///
+/// With `pipelined` set both calls go out on the handle before `openQuery`
+/// has returned, so the whole exchange is one round trip; otherwise each call
+/// waits for the one before it (three round trips), the way dependent REST or
+/// gRPC requests would.
+pub async fn query_summary(query: SharedMetricQuery, pipelined: bool) -> Result<(SharedMetricStatistics, Vec<SharedMetricRollup>), ProtocolError> {
+    // Run in LocalSet since Cap'n Proto types are !Send
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            let (client, _handle) = create_client().await?;
+            
+            let mut request = client.open_query_request();
+            if let Some(auth) = auth() {
+                request.get().set_token((&auth.token[..]).into());
+            }
+            if let Some(traceparent) = shared::current_traceparent() {
+                request.get().set_traceparent((&traceparent[..]).into());
+            }
+            request.get().set_request_id((&shared::next_request_id()[..]).into());
+            write_query(&query, request.get().init_query());
+            
+            let opened = request.send();
+            let (stats_response, rollups_response) = if pipelined {
+                // Calls on the pipelined handle are queued behind openQuery on
+                // the server, not the client
+                let handle = opened.pipeline.get_handle();
+                let statistics = handle.get_statistics_request().send().promise;
+                let rollups = handle.query_rollups_request().send().promise;
+                let (_, statistics, rollups) = call(async { tokio::try_join!(opened.promise, statistics, rollups) }).await?;
+                (statistics, rollups)
+            } else {
+                let handle = call(opened.promise)
+                    .await?
+                    .get()
+                    .and_then(|results| results.get_handle())
+                    .map_err(decode_error)?;
+                let statistics = call(handle.get_statistics_request().send().promise).await?;
+                let rollups = call(handle.query_rollups_request().send().promise).await?;
+                (statistics, rollups)
+            };
+            
+            let stats_reader = stats_response.get().and_then(|results| results.get_statistics()).map_err(decode_error)?;
+            let shared_stats = read_statistics(stats_reader);
+            
+            let rollups = rollups_response
+                .get()
+                .and_then(|results| results.get_rollups())
+                .and_then(|rollups_reader| rollups_reader.iter().map(read_rollup).collect::<capnp::Result<Vec<SharedMetricRollup>>>())
+                .map_err(decode_error)?;
+            
+            Ok::<(SharedMetricStatistics, Vec<SharedMetricRollup>), ProtocolError>((shared_stats, rollups))
+        })
+        .await
+}
+
+pub async fn delete_metrics(query: SharedMetricQuery) -> Result<u64, ProtocolError> {
+    // Run in LocalSet since Cap'n Proto types are !Send
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            let (client, _handle) = create_client().await?;
+            
+            // Create a delete request
+            let mut request = client.delete_metrics_request();
+            if let Some(auth) = auth() {
+                request.get().set_token((&auth.token[..]).into());
+            }
+            if let Some(traceparent) = shared::current_traceparent() {
+                request.get().set_traceparent((&traceparent[..]).into());
+            }
+            request.get().set_request_id((&shared::next_request_id()[..]).into());
             let mut query_builder = request.get().init_query();
             
             query_builder.set_start_time(query.start_time);
@@ -127,18 +825,165 @@ pub async fn get_statistics(query: SharedMetricQuery) -> anyhow::Result<SharedMe
                 query_builder.set_hostname_filter((&hostname[..]).into());
             }
             
-            let response = request.send().promise.await?;
-            let stats_reader = response.get()?.get_statistics()?;
-            
-            let shared_stats = SharedMetricStatistics {
-                count: stats_reader.get_count(),
-                avg_cpu_percent: stats_reader.get_avg_cpu_percent(),
-                avg_memory_bytes: stats_reader.get_avg_memory_bytes(),
-                avg_disk_io_ops: stats_reader.get_avg_disk_io_ops(),
-                time_range_seconds: stats_reader.get_time_range_seconds(),
-            };
-            
-            Ok::<SharedMetricStatistics, anyhow::Error>(shared_stats)
+            let response = call(request.send().promise).await?;
+            Ok::<u64, ProtocolError>(response.get().map_err(decode_error)?.get_deleted())
+        })
+        .await
+}
+
+pub async fn get_storage_stats() -> Result<SharedStorageStats, ProtocolError> {
+    // Run in LocalSet since Cap'n Proto types are !Send
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            let (client, _handle) = create_client().await?;
+            storage_stats_with(&client).await
+        })
+        .await
+}
+
+async fn storage_stats_with(client: &metrics_service::Client) -> Result<SharedStorageStats, ProtocolError> {
+    let mut request = client.get_storage_stats_request();
+    if let Some(auth) = auth() {
+        request.get().set_token((&auth.token[..]).into());
+    }
+    if let Some(traceparent) = shared::current_traceparent() {
+        request.get().set_traceparent((&traceparent[..]).into());
+    }
+    request.get().set_request_id((&shared::next_request_id()[..]).into());
+    let response = call(request.send().promise).await?;
+    let stats_reader = response.get().and_then(|results| results.get_stats()).map_err(decode_error)?;
+    
+    Ok(SharedStorageStats {
+        point_count: stats_reader.get_point_count(),
+        approx_heap_bytes: stats_reader.get_approx_heap_bytes(),
+        rollup_bucket_count: stats_reader.get_rollup_bucket_count(),
+        rollup_heap_bytes: stats_reader.get_rollup_heap_bytes(),
+    })
+}
+
+/// Time a new connection: the socket connect, then `getStorageStats` twice
+/// over one RPC system. The bootstrap capability is asked for along with the
+/// first call, so both land in the first request. Every other call here
+/// opens a connection like this one, so their latencies include its setup.
+pub async fn connection_timings() -> Result<ConnectionTimings, ProtocolError> {
+    let connect_error = |e| ProtocolError::connect(PROTOCOL, e);
+    let (dns_lookup, socket_connect) = match uds_path() {
+        Some(path) => {
+            let (stream, elapsed) = connection::timed(UnixStream::connect(path)).await;
+            drop(stream.map_err(connect_error)?);
+            (Duration::ZERO, elapsed)
+        }
+        None => {
+            let (addrs, dns_lookup) = connection::lookup(target()).await.map_err(connect_error)?;
+            (dns_lookup, connection::tcp_connect_time(&addrs).await.map_err(connect_error)?)
+        }
+    };
+    
+    // Run in LocalSet since Cap'n Proto types are !Send
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            let (connected, first_request) = connection::timed(async {
+                let (client, handle) = create_client().await?;
+                storage_stats_with(&client).await?;
+                Ok::<_, ProtocolError>((client, handle))
+            })
+            .await;
+            let (client, _handle) = connected?;
+            let (warm, warm_request) = connection::timed(storage_stats_with(&client)).await;
+            warm?;
+            Ok(ConnectionTimings { dns_lookup, socket_connect, first_request, warm_request })
         })
         .await
-}
\ No newline at end of file
+}
+
+/// Bytes `metrics` take as Cap'n Proto: each point's own message for a
+/// submit, which sends one `submitMetric` call per point, or the
+/// `queryMetrics` results for a query. Unpacked messages count their segments
+/// without the segment table; packed ones count the whole packed message,
+/// since the table packs into the same bytes.
+pub fn payload_bytes(operation: PayloadOperation, metrics: &[SharedMetricPoint]) -> usize {
+    match operation {
+        PayloadOperation::Submit => metrics
+            .iter()
+            .map(|metric| {
+                let mut message = capnp::message::Builder::new_default();
+                write_metric(metric, message.init_root::<metric_point::Builder>());
+                message_payload_bytes(&message)
+            })
+            .sum(),
+        PayloadOperation::Query => {
+            let mut message = capnp::message::Builder::new_default();
+            let results = message.init_root::<metrics_service::query_metrics_results::Builder>();
+            let mut list = results.init_metrics(metrics.len() as u32);
+            for (i, metric) in metrics.iter().enumerate() {
+                write_metric(metric, list.reborrow().get(i as u32));
+            }
+            message_payload_bytes(&message)
+        }
+    }
+}
+
+fn message_payload_bytes(message: &capnp::message::Builder<capnp::message::HeapAllocator>) -> usize {
+    match encoding() {
+        CapnpEncoding::Unpacked => message.get_segments_for_output().iter().map(|segment| segment.len()).sum(),
+        CapnpEncoding::Packed => {
+            let mut buffer = Vec::new();
+            capnp::serialize_packed::write_message(&mut buffer, message).map_or(0, |()| buffer.len())
+        }
+    }
+}
+
+/// The Cap'n Proto client as a `shared::MetricsService` and `ProtocolClient`
+pub struct CapnpClient;
+
+#[async_trait::async_trait(?Send)]
+impl shared::MetricsService for CapnpClient {
+    type Error = ProtocolError;
+
+    async fn submit_metric(&self, metric: SharedMetricPoint) -> Result<(), ProtocolError> {
+        submit_metric(metric).await
+    }
+
+    async fn query_metrics(&self, query: SharedMetricQuery) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+        query_metrics(query).await
+    }
+
+    async fn get_statistics(&self, query: SharedMetricQuery) -> Result<SharedMetricStatistics, ProtocolError> {
+        get_statistics(query).await
+    }
+
+    async fn delete_metrics(&self, query: SharedMetricQuery) -> Result<u64, ProtocolError> {
+        delete_metrics(query).await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl crate::ProtocolClient for CapnpClient {
+    async fn submit_batch(&self, metrics: Vec<SharedMetricPoint>) -> Result<u64, ProtocolError> {
+        submit_metrics(metrics).await
+    }
+
+    async fn query_rollups(&self, query: SharedMetricQuery) -> Result<Vec<SharedMetricRollup>, ProtocolError> {
+        query_rollups(query).await
+    }
+
+    async fn query_with_timeout(&self, query: SharedMetricQuery, timeout: Duration) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+        query_metrics_with_timeout(query, timeout).await
+    }
+
+    fn wire_bytes(&self) -> &'static WireCounter {
+        &WIRE_BYTES
+    }
+
+    fn payload_bytes(&self, operation: PayloadOperation, metrics: &[SharedMetricPoint]) -> usize {
+        payload_bytes(operation, metrics)
+    }
+
+    fn metrics_exporter_env(&self) -> &'static str {
+        "PROTOBENCH_CAPNP_METRICS_ADDR"
+    }
+
+    async fn connection_timings(&self) -> Result<ConnectionTimings, ProtocolError> {
+        connection_timings().await
+    }
+}
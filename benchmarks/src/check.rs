@@ -0,0 +1,180 @@
+use anyhow::Context;
+use serde::Deserialize;
+use shared::{generate_test_data, MetricQuery};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::report::PayloadOperation;
+use crate::results::{BenchmarkRun, OperationResult};
+use crate::server_stats::ServerStatsProbe;
+use crate::{benchmark_operation, protocol_clients, purge_all_services};
+
+/// Limits for a gate run, read from a TOML file such as the repository's
+/// `thresholds.toml`. `[limits]` applies to every protocol and operation;
+/// `[operations.<operation>]` and then `[protocols.<label>]` override it field
+/// by field. A protocol section matches the full label (`REST[relay]`) or the
+/// protocol before its variant (`REST`).
+///
+/// ```toml
+/// points = 100
+///
+/// [limits]
+/// max_latency_ms = 250.0
+/// max_memory_bytes = 8_000_000
+///
+/// [operations.query]
+/// max_total_bytes = 40_000
+///
+/// [protocols.REST]
+/// max_latency_ms = 400.0
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    /// Points each operation in the quick pass submits or queries
+    #[serde(default = "default_points")]
+    pub points: usize,
+    #[serde(default)]
+    pub limits: Limits,
+    #[serde(default)]
+    pub operations: BTreeMap<String, Limits>,
+    #[serde(default)]
+    pub protocols: BTreeMap<String, Limits>,
+}
+
+/// Upper bounds on one operation's measurements; unset ones aren't checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    pub max_latency_ms: Option<f64>,
+    /// Request and response bytes on the wire together
+    pub max_total_bytes: Option<u64>,
+    pub max_memory_bytes: Option<u64>,
+}
+
+/// One measurement over its limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub protocol: String,
+    pub operation: String,
+    pub metric: &'static str,
+    pub limit: f64,
+    pub actual: f64,
+}
+
+fn default_points() -> usize {
+    100
+}
+
+impl Limits {
+    /// `self` with every field `other` sets taken from `other`
+    fn overridden_by(self, other: &Limits) -> Limits {
+        Limits {
+            max_latency_ms: other.max_latency_ms.or(self.max_latency_ms),
+            max_total_bytes: other.max_total_bytes.or(self.max_total_bytes),
+            max_memory_bytes: other.max_memory_bytes.or(self.max_memory_bytes),
+        }
+    }
+}
+
+impl Thresholds {
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    pub fn read_from(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_toml(&contents).with_context(|| format!("Invalid thresholds file {}", path.display()))
+    }
+
+    /// The limits that apply to `operation` on the protocol labelled `protocol`
+    pub fn limits_for(&self, protocol: &str, operation: &str) -> Limits {
+        let mut limits = self.limits;
+        if let Some(operation_limits) = self.operations.get(operation) {
+            limits = limits.overridden_by(operation_limits);
+        }
+        let base = protocol.split('[').next().unwrap_or(protocol);
+        // The bare protocol first, so a section for the exact variant wins
+        for key in [base, protocol] {
+            if let Some(protocol_limits) = self.protocols.get(key) {
+                limits = limits.overridden_by(protocol_limits);
+            }
+        }
+        limits
+    }
+
+    /// Every result in `run` that exceeds its limits
+    pub fn check(&self, run: &BenchmarkRun) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for result in &run.results {
+            let limits = self.limits_for(&result.protocol, &result.operation);
+            let measured = [
+                ("latency_ms", limits.max_latency_ms, result.latency_ns as f64 / 1_000_000.0),
+                ("total_bytes", limits.max_total_bytes.map(|limit| limit as f64), (result.request_bytes + result.response_bytes) as f64),
+                ("memory_bytes", limits.max_memory_bytes.map(|limit| limit as f64), result.memory_allocated as f64),
+            ];
+            for (metric, limit, actual) in measured {
+                if let Some(limit) = limit.filter(|&limit| actual > limit) {
+                    violations.push(Violation {
+                        protocol: result.protocol.clone(),
+                        operation: result.operation.clone(),
+                        metric,
+                        limit,
+                        actual,
+                    });
+                }
+            }
+        }
+        violations
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} {:.2} exceeds the limit of {:.2}",
+            self.protocol, self.operation, self.metric, self.actual, self.limit
+        )
+    }
+}
+
+/// The gate's measurements: a batch submit of `points` points and a query
+/// returning them, on every protocol, each against emptied services.
+/// Services are emptied again afterwards. Where a service serves `/stats`,
+/// each result also records the service's own time and allocations.
+pub async fn quick_pass(points: usize) -> anyhow::Result<BenchmarkRun> {
+    let metrics = generate_test_data(points);
+    let query = MetricQuery::all();
+
+    let mut run = BenchmarkRun::new();
+    purge_all_services().await?;
+    for (name, client) in protocol_clients() {
+        let server = ServerStatsProbe::for_client(client.as_ref());
+
+        let before = server.snapshot().await;
+        if let Some(stats) = &before {
+            run.record_server_allocator(stats);
+        }
+        let (stored, submitted) =
+            benchmark_operation("submit_batch", client.wire_bytes(), || client.submit_batch(metrics.clone())).await;
+        stored.with_context(|| format!("{} submit_batch failed", name))?;
+        run.push(
+            OperationResult::new(&name, PayloadOperation::Submit.label(), points, &submitted)
+                .with_server(server.since(before).await),
+        );
+
+        let before = server.snapshot().await;
+        let (returned, queried) =
+            benchmark_operation("query", client.wire_bytes(), || client.query_metrics(query.clone())).await;
+        returned.with_context(|| format!("{} query failed", name))?;
+        run.push(
+            OperationResult::new(&name, PayloadOperation::Query.label(), points, &queried)
+                .with_server(server.since(before).await),
+        );
+    }
+    purge_all_services().await?;
+    Ok(run)
+}
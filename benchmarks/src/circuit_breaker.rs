@@ -0,0 +1,295 @@
+use shared::{MetricPoint, MetricQuery, MetricRollup, MetricStatistics, MetricsService};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::connection::ConnectionTimings;
+use crate::report::PayloadOperation;
+use crate::wire_bytes::WireCounter;
+use crate::{ProtocolClient, ProtocolError};
+
+/// When a `CircuitBreaker` opens and how it recovers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerConfig {
+    /// Consecutive failures that open the breaker
+    pub failure_threshold: u32,
+    /// How long an open breaker rejects calls before letting probes through
+    pub open_for: Duration,
+    /// Successful probes needed to close a half-open breaker; this many may
+    /// be in flight at once
+    pub half_open_probes: u32,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_for: Duration::from_secs(1),
+            half_open_probes: 1,
+        }
+    }
+}
+
+impl BreakerConfig {
+    /// Read `PROTOBENCH_BREAKER_FAILURES`, `PROTOBENCH_BREAKER_OPEN_MS` and
+    /// `PROTOBENCH_BREAKER_PROBES`, keeping the default for anything unset
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok().and_then(|value| value.parse().ok())
+        }
+
+        let defaults = Self::default();
+        Self {
+            failure_threshold: var("PROTOBENCH_BREAKER_FAILURES").unwrap_or(defaults.failure_threshold).max(1),
+            open_for: var("PROTOBENCH_BREAKER_OPEN_MS").map(Duration::from_millis).unwrap_or(defaults.open_for),
+            half_open_probes: var("PROTOBENCH_BREAKER_PROBES").unwrap_or(defaults.half_open_probes).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Calls go through; failures are being counted
+    Closed,
+    /// Calls are rejected without reaching the server
+    Open,
+    /// A limited number of probe calls decide whether to close or reopen
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn label(self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half-open",
+        }
+    }
+}
+
+/// State transitions and rejections since the breaker was created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerStats {
+    pub state: BreakerState,
+    /// Transitions into `Open`, from `Closed` or a failed probe
+    pub opened: u64,
+    pub half_opened: u64,
+    /// Transitions from `HalfOpen` back to `Closed`
+    pub closed: u64,
+    pub rejected: u64,
+}
+
+impl std::fmt::Display for BreakerStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (opened {}, half-opened {}, closed {}, rejected {})",
+            self.state.label(), self.opened, self.half_opened, self.closed, self.rejected
+        )
+    }
+}
+
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { in_flight: u32, successes: u32 },
+}
+
+/// Wraps any `ProtocolClient` so that after `failure_threshold` consecutive
+/// failures calls fail fast with `ProtocolError::CircuitOpen` for `open_for`, then
+/// `half_open_probes` probe calls decide whether it closes again.
+///
+/// Every error from the wrapped client counts as a failure, and so does a
+/// call dropped before it finished.
+pub struct CircuitBreaker {
+    name: String,
+    client: Box<dyn ProtocolClient>,
+    config: BreakerConfig,
+    state: Mutex<State>,
+    opened: AtomicU64,
+    half_opened: AtomicU64,
+    closed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl CircuitBreaker {
+    /// `name` labels `ProtocolError::CircuitOpen`s, usually the client's benchmark label
+    pub fn new(name: impl Into<String>, client: Box<dyn ProtocolClient>, config: BreakerConfig) -> Self {
+        Self {
+            name: name.into(),
+            client,
+            config,
+            state: Mutex::new(State::Closed { failures: 0 }),
+            opened: AtomicU64::new(0),
+            half_opened: AtomicU64::new(0),
+            closed: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> BreakerStats {
+        let state = match *self.lock() {
+            State::Closed { .. } => BreakerState::Closed,
+            State::Open { .. } => BreakerState::Open,
+            State::HalfOpen { .. } => BreakerState::HalfOpen,
+        };
+        BreakerStats {
+            state,
+            opened: self.opened.load(Ordering::Relaxed),
+            half_opened: self.half_opened.load(Ordering::Relaxed),
+            closed: self.closed.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state is only ever replaced whole, so a poisoned lock is still consistent
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Let a call through or reject it; moves an open breaker whose time is
+    /// up to half-open
+    fn admit(&self) -> Result<(), ProtocolError> {
+        let mut state = self.lock();
+        if let State::Open { until } = *state {
+            if Instant::now() < until {
+                drop(state);
+                return Err(self.reject());
+            }
+            *state = State::HalfOpen { in_flight: 0, successes: 0 };
+            self.half_opened.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let State::HalfOpen { in_flight, successes } = &mut *state {
+            if *in_flight + *successes >= self.config.half_open_probes {
+                drop(state);
+                return Err(self.reject());
+            }
+            *in_flight += 1;
+        }
+        Ok(())
+    }
+
+    fn reject(&self) -> ProtocolError {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        ProtocolError::CircuitOpen { client: self.name.clone() }
+    }
+
+    fn record(&self, success: bool) {
+        let mut state = self.lock();
+        match &mut *state {
+            State::Closed { failures } => {
+                if success {
+                    *failures = 0;
+                } else {
+                    *failures += 1;
+                    if *failures >= self.config.failure_threshold {
+                        self.open(&mut state);
+                    }
+                }
+            }
+            State::HalfOpen { in_flight, successes } => {
+                *in_flight -= 1;
+                if !success {
+                    self.open(&mut state);
+                } else {
+                    *successes += 1;
+                    if *successes >= self.config.half_open_probes {
+                        *state = State::Closed { failures: 0 };
+                        self.closed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+            // A call admitted before another one's failure opened the breaker
+            State::Open { .. } => {}
+        }
+    }
+
+    fn open(&self, state: &mut State) {
+        *state = State::Open { until: Instant::now() + self.config.open_for };
+        self.opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    async fn guard<T>(&self, call: impl Future<Output = Result<T, ProtocolError>>) -> Result<T, ProtocolError> {
+        self.admit()?;
+        let mut outcome = Outcome { breaker: self, recorded: false };
+        let result = call.await;
+        outcome.record(result.is_ok());
+        result
+    }
+}
+
+/// Records an admitted call as failed if it is dropped before finishing, so
+/// a cancelled probe doesn't hold its slot forever
+struct Outcome<'a> {
+    breaker: &'a CircuitBreaker,
+    recorded: bool,
+}
+
+impl Outcome<'_> {
+    fn record(&mut self, success: bool) {
+        self.recorded = true;
+        self.breaker.record(success);
+    }
+}
+
+impl Drop for Outcome<'_> {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breaker.record(false);
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl MetricsService for CircuitBreaker {
+    type Error = ProtocolError;
+
+    async fn submit_metric(&self, metric: MetricPoint) -> Result<(), ProtocolError> {
+        self.guard(self.client.submit_metric(metric)).await
+    }
+
+    async fn query_metrics(&self, query: MetricQuery) -> Result<Vec<MetricPoint>, ProtocolError> {
+        self.guard(self.client.query_metrics(query)).await
+    }
+
+    async fn get_statistics(&self, query: MetricQuery) -> Result<MetricStatistics, ProtocolError> {
+        self.guard(self.client.get_statistics(query)).await
+    }
+
+    async fn delete_metrics(&self, query: MetricQuery) -> Result<u64, ProtocolError> {
+        self.guard(self.client.delete_metrics(query)).await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ProtocolClient for CircuitBreaker {
+    async fn submit_batch(&self, metrics: Vec<MetricPoint>) -> Result<u64, ProtocolError> {
+        self.guard(self.client.submit_batch(metrics)).await
+    }
+
+    async fn query_rollups(&self, query: MetricQuery) -> Result<Vec<MetricRollup>, ProtocolError> {
+        self.guard(self.client.query_rollups(query)).await
+    }
+
+    async fn query_with_timeout(&self, query: MetricQuery, timeout: Duration) -> Result<Vec<MetricPoint>, ProtocolError> {
+        self.guard(self.client.query_with_timeout(query, timeout)).await
+    }
+
+    fn wire_bytes(&self) -> &'static WireCounter {
+        self.client.wire_bytes()
+    }
+
+    fn payload_bytes(&self, operation: PayloadOperation, metrics: &[MetricPoint]) -> usize {
+        self.client.payload_bytes(operation, metrics)
+    }
+
+    fn metrics_exporter_env(&self) -> &'static str {
+        self.client.metrics_exporter_env()
+    }
+
+    async fn connection_timings(&self) -> Result<ConnectionTimings, ProtocolError> {
+        self.guard(self.client.connection_timings()).await
+    }
+}
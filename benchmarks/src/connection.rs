@@ -0,0 +1,204 @@
+use shared::DnsConfig;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+use crate::error::FailureBreakdown;
+use crate::results::{BenchmarkRun, OperationResult};
+use crate::statistics::Summary;
+use crate::protocol_clients;
+
+/// Operation name connection setup results are recorded under
+pub const CONNECT_OPERATION: &str = "connect";
+
+/// One new connection, timed from the client's side. Setup isn't timed on
+/// its own, since REST and Cap'n Proto only finish connecting once the first
+/// request goes out; it is what the first request took beyond a warm one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionTimings {
+    /// Looking the target's host up, as the first request had to: zero for
+    /// an IP address or a `PROTOBENCH_RESOLVE` override, and for a pinned
+    /// host once it has been looked up
+    pub dns_lookup: Duration,
+    /// A bare connect to the service's socket, TCP or Unix, on a connection
+    /// of its own
+    pub socket_connect: Duration,
+    /// From nothing to the first response: any lookup, the connect, TLS when
+    /// configured, the HTTP/2 preface and settings or the Cap'n Proto
+    /// bootstrap, then a storage stats request
+    pub first_request: Duration,
+    /// The same request again on the connection now open
+    pub warm_request: Duration,
+}
+
+impl ConnectionTimings {
+    /// What opening the connection added to the first request, the cost a
+    /// short-lived client pays on every invocation
+    pub fn setup(&self) -> Duration {
+        self.first_request.saturating_sub(self.warm_request)
+    }
+
+    /// Setup beyond the lookup and socket connect: TLS, HTTP/2 settings or
+    /// bootstrap
+    pub fn handshake(&self) -> Duration {
+        self.setup().saturating_sub(self.dns_lookup + self.socket_connect)
+    }
+}
+
+/// `future`'s output and how long it took to complete
+pub async fn timed<T>(future: impl Future<Output = T>) -> (T, Duration) {
+    let start = Instant::now();
+    let output = future.await;
+    (output, start.elapsed())
+}
+
+/// The addresses clients connect to for `target` as `DnsConfig` has them
+/// resolved, and how long resolving took
+pub async fn lookup(target: &str) -> std::io::Result<(Vec<SocketAddr>, Duration)> {
+    let (addrs, elapsed) = timed(async {
+        match DnsConfig::get().resolve(target)? {
+            Some(addrs) => Ok(addrs),
+            None => Ok(tokio::net::lookup_host(target).await?.collect()),
+        }
+    })
+    .await;
+    Ok((addrs?, elapsed))
+}
+
+/// TCP connection to `target`, to the addresses `DnsConfig` gives for it
+/// or else looked up as usual
+pub async fn connect_tcp(target: &str) -> std::io::Result<TcpStream> {
+    match DnsConfig::get().resolve(target)? {
+        Some(addrs) => TcpStream::connect(&addrs[..]).await,
+        None => TcpStream::connect(target).await,
+    }
+}
+
+/// How long a bare TCP connect to `addrs`, already resolved, takes; the
+/// connection is closed at once
+pub async fn tcp_connect_time(addrs: &[SocketAddr]) -> std::io::Result<Duration> {
+    let (stream, elapsed) = timed(TcpStream::connect(addrs)).await;
+    drop(stream?);
+    Ok(elapsed)
+}
+
+/// Connection setup on one protocol over every connection opened
+#[derive(Debug, Clone)]
+pub struct ConnectionResult {
+    pub protocol: String,
+    /// Connections that answered both requests
+    pub connections: usize,
+    pub failures: FailureBreakdown,
+    pub dns_lookup_ms: Option<Summary>,
+    pub socket_connect_ms: Option<Summary>,
+    pub setup_ms: Option<Summary>,
+    pub handshake_ms: Option<Summary>,
+    pub warm_request_ms: Option<Summary>,
+}
+
+impl ConnectionResult {
+    pub fn from_timings(protocol: impl Into<String>, timings: &[ConnectionTimings], failures: FailureBreakdown) -> Self {
+        let summary = |duration: fn(&ConnectionTimings) -> Duration| {
+            let samples: Vec<f64> = timings.iter().map(|timing| duration(timing).as_secs_f64() * 1000.0).collect();
+            Summary::of(&samples)
+        };
+        Self {
+            protocol: protocol.into(),
+            connections: timings.len(),
+            failures,
+            dns_lookup_ms: summary(|timing| timing.dns_lookup),
+            socket_connect_ms: summary(|timing| timing.socket_connect),
+            setup_ms: summary(ConnectionTimings::setup),
+            handshake_ms: summary(ConnectionTimings::handshake),
+            warm_request_ms: summary(|timing| timing.warm_request),
+        }
+    }
+}
+
+/// Connection setup on every protocol
+#[derive(Debug, Clone)]
+pub struct ConnectionReport {
+    /// Connections opened per protocol
+    pub connections: usize,
+    pub results: Vec<ConnectionResult>,
+}
+
+/// Open `connections` new connections to every protocol's service one after
+/// another, each the way a freshly started client would, and time their
+/// setup. Nothing is stored, so services needn't be emptied.
+pub async fn run(connections: usize) -> anyhow::Result<ConnectionReport> {
+    let mut results = Vec::new();
+    for (name, client) in protocol_clients() {
+        let mut timings = Vec::with_capacity(connections);
+        let mut failures = FailureBreakdown::default();
+        for _ in 0..connections {
+            match client.connection_timings().await {
+                Ok(timing) => timings.push(timing),
+                Err(e) => failures.record(&e),
+            }
+        }
+        results.push(ConnectionResult::from_timings(name, &timings, failures));
+    }
+    Ok(ConnectionReport { connections, results })
+}
+
+impl ConnectionReport {
+    /// One `connect` result per protocol with the mean setup time and its
+    /// confidence interval as latency; there are no points, and sizes and
+    /// memory are 0
+    pub fn to_run(&self) -> BenchmarkRun {
+        let mut run = BenchmarkRun::new();
+        for result in &self.results {
+            let Some(setup) = result.setup_ms else { continue };
+            let ns = |ms: f64| (ms * 1_000_000.0).max(0.0) as u64;
+            run.push(OperationResult {
+                protocol: result.protocol.clone(),
+                operation: CONNECT_OPERATION.to_string(),
+                points: 0,
+                latency_ns: ns(setup.mean),
+                latency_ci_ns: setup.margin.is_finite().then(|| [ns(setup.lower()), ns(setup.upper())]),
+                time_to_first_byte_ns: None,
+                request_bytes: 0,
+                response_bytes: 0,
+                memory_allocated: 0,
+                cpu_cycles: 0,
+                energy_uj: None,
+                payload_bytes: None,
+                payload_estimated: false,
+                server_time_ns: None,
+                server_allocated_bytes: None,
+            });
+        }
+        run
+    }
+}
+
+impl fmt::Display for ConnectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} new connections per protocol, mean ms:", self.connections)?;
+        writeln!(
+            f,
+            "  {:<24} {:>6} {:>10} {:>10} {:>10} {:>10} {:>10}  Failures",
+            "Protocol", "OK", "DNS", "Socket", "Handshake", "Setup", "Warm req"
+        )?;
+        let mean = |summary: Option<Summary>| summary.map_or(0.0, |summary| summary.mean);
+        for result in &self.results {
+            writeln!(
+                f,
+                "  {:<24} {:>6} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>10.3}  {}",
+                result.protocol,
+                result.connections,
+                mean(result.dns_lookup_ms),
+                mean(result.socket_connect_ms),
+                mean(result.handshake_ms),
+                mean(result.setup_ms),
+                mean(result.warm_request_ms),
+                result.failures,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::results::{BenchmarkRun, OperationResult};
+
+/// What criterion writes to `<benchmark>/new/benchmark.json`
+#[derive(Deserialize)]
+struct CriterionId {
+    group_id: String,
+    function_id: Option<String>,
+    value_str: Option<String>,
+}
+
+/// The part of `<benchmark>/new/estimates.json` kept; times are nanoseconds
+#[derive(Deserialize)]
+struct CriterionEstimates {
+    mean: CriterionEstimate,
+}
+
+#[derive(Deserialize)]
+struct CriterionEstimate {
+    point_estimate: f64,
+    confidence_interval: CriterionInterval,
+}
+
+#[derive(Deserialize)]
+struct CriterionInterval {
+    lower_bound: f64,
+    upper_bound: f64,
+}
+
+/// Where criterion keeps its results, found the way criterion finds it:
+/// `CRITERION_HOME`, then `criterion` under `CARGO_TARGET_DIR`, then under the
+/// workspace's `target`
+pub fn criterion_dir() -> PathBuf {
+    if let Some(home) = std::env::var_os("CRITERION_HOME") {
+        return PathBuf::from(home);
+    }
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../target"))
+        .join("criterion")
+}
+
+/// Every benchmark under `dir` that criterion measured at or after `since`,
+/// as results. Criterion only times, so the mean goes in `latency_ns` with its
+/// confidence interval, and bytes, memory and cycles are left at 0. The group
+/// becomes the operation and the function the protocol; a numeric parameter is
+/// the point count, and any other parameter is added to the operation
+/// (`compressibility/noise`).
+pub fn collect(dir: &Path, since: SystemTime) -> anyhow::Result<Vec<OperationResult>> {
+    let mut benchmark_dirs = Vec::new();
+    find_measured(dir, &mut benchmark_dirs)?;
+    benchmark_dirs.sort();
+
+    let mut results = Vec::new();
+    for new_dir in benchmark_dirs {
+        let estimates_path = new_dir.join("estimates.json");
+        let measured_at = std::fs::metadata(&estimates_path).and_then(|metadata| metadata.modified());
+        if !matches!(measured_at, Ok(measured_at) if measured_at >= since) {
+            continue;
+        }
+        let id: CriterionId = read_json(&new_dir.join("benchmark.json"))?;
+        let estimates: CriterionEstimates = read_json(&estimates_path)?;
+        results.push(to_result(id, estimates.mean));
+    }
+    Ok(results)
+}
+
+/// `collect` from `criterion_dir()`, appended to the run in `path` (or a new
+/// one when the file doesn't exist yet) so criterion and the CLI's own
+/// measurements end up in one dataset. Returns how many results were added.
+pub fn export(path: &Path, since: SystemTime) -> anyhow::Result<usize> {
+    let mut run = BenchmarkRun::open_or_new(path)?;
+    let results = collect(&criterion_dir(), since)?;
+    let added = results.len();
+    run.results.extend(results);
+    run.write_to(path)?;
+    Ok(added)
+}
+
+fn to_result(id: CriterionId, mean: CriterionEstimate) -> OperationResult {
+    let (protocol, parameter) = match id.function_id {
+        Some(function) => (function, id.value_str),
+        None => (id.value_str.unwrap_or_default(), None),
+    };
+    let (operation, points) = match parameter {
+        Some(parameter) => match parameter.parse() {
+            Ok(points) => (id.group_id, points),
+            Err(_) => (format!("{}/{}", id.group_id, parameter), 0),
+        },
+        None => (id.group_id, 0),
+    };
+    OperationResult {
+        latency_ci_ns: Some([
+            mean.confidence_interval.lower_bound as u64,
+            mean.confidence_interval.upper_bound as u64,
+        ]),
+        ..OperationResult::new(protocol, operation, points, &crate::BenchmarkMetrics {
+            latency: std::time::Duration::from_nanos(mean.point_estimate as u64),
+            payload_size: crate::PayloadSizes::new(0, 0),
+            memory_allocated: 0,
+            cpu_cycles: 0,
+            time_to_first_byte: None,
+        })
+    }
+}
+
+// Criterion keeps the latest measurement of each benchmark in a `new`
+// directory next to `base` and `change`; only `new` counts
+fn find_measured(dir: &Path, found: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if path.file_name().is_some_and(|name| name == "new") && path.join("benchmark.json").is_file() {
+            found.push(path);
+        } else {
+            find_measured(&path, found)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+}
@@ -0,0 +1,284 @@
+use shared::{MetricPoint, TestDataGenerator};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::results::{BenchmarkRun, OperationResult};
+
+/// Operation name compression results are recorded under
+pub const COMPRESS_OPERATION: &str = "compress";
+
+/// Seed for the points dictionaries are trained on, apart from the default
+/// 42 the measured points use, so no dictionary is scored on its own samples
+const TRAINING_SEED: u64 = 7;
+
+/// How `run` trains its dictionaries and what it compresses with them
+#[derive(Debug, Clone)]
+pub struct DictionarySettings {
+    /// Points per REST body compressed; Cap'n Proto sends a message per
+    /// point, so for it a batch is that many messages
+    pub batch_sizes: Vec<usize>,
+    /// Payloads each protocol's dictionary is trained on
+    pub training_samples: usize,
+    /// Most bytes a trained dictionary may take
+    pub dictionary_bytes: usize,
+    /// zstd level for both plain and dictionary compression
+    pub level: i32,
+    /// Times each batch is compressed and decompressed, averaged
+    pub rounds: usize,
+}
+
+impl Default for DictionarySettings {
+    fn default() -> Self {
+        Self {
+            batch_sizes: vec![1, 10, 100],
+            training_samples: 1000,
+            dictionary_bytes: 16 * 1024,
+            level: 3,
+            rounds: 20,
+        }
+    }
+}
+
+/// Payload a protocol client puts on the wire for its submissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Payload {
+    /// A batch body in `rest_client::body_encoding()`
+    Rest,
+    /// A `submitMetric` call's params in `capnp_client::encoding()`, one per
+    /// point, without the RPC envelope
+    Capnp,
+}
+
+impl Payload {
+    pub const ALL: [Payload; 2] = [Payload::Rest, Payload::Capnp];
+
+    pub fn label(self) -> String {
+        match self {
+            Payload::Rest => format!("REST/{}", crate::rest_client::body_encoding().label()),
+            Payload::Capnp => format!("Cap'n Proto/{}", crate::capnp_client::encoding().label()),
+        }
+    }
+
+    /// What submitting `metrics` as one batch sends, payload by payload
+    pub fn encode(self, metrics: &[MetricPoint]) -> anyhow::Result<Vec<Vec<u8>>> {
+        match self {
+            Payload::Rest => Ok(vec![crate::rest_client::body_encoding().encode(&metrics)?]),
+            Payload::Capnp => metrics.iter().map(capnp_submit_message).collect(),
+        }
+    }
+}
+
+/// A `submitMetric` call's params carrying `metric`, serialized the way the
+/// client's stream encoding puts it on the wire
+fn capnp_submit_message(metric: &MetricPoint) -> anyhow::Result<Vec<u8>> {
+    use crate::metrics_capnp::metrics_service::submit_metric_params;
+
+    let mut message = capnp::message::Builder::new_default();
+    let params = message.init_root::<submit_metric_params::Builder>();
+    crate::capnp_client::write_metric(metric, params.init_metric());
+    let mut buffer = Vec::new();
+    match crate::capnp_client::encoding() {
+        shared::CapnpEncoding::Unpacked => capnp::serialize::write_message(&mut buffer, &message)?,
+        shared::CapnpEncoding::Packed => capnp::serialize_packed::write_message(&mut buffer, &message)?,
+    }
+    Ok(buffer)
+}
+
+/// A zstd dictionary trained on `samples`, at most `max_bytes` long
+pub fn train(samples: &[Vec<u8>], max_bytes: usize) -> anyhow::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to train a dictionary on {} samples: {}", samples.len(), e))
+}
+
+/// One way of compressing every payload of a batch on its own, as a client
+/// compressing each request would
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Compression {
+    /// Bytes of the batch's payloads once compressed
+    pub bytes: u64,
+    /// Mean time to compress the batch
+    pub compress: Duration,
+    /// Mean time to decompress it again
+    pub decompress: Duration,
+}
+
+/// Compress and decompress each of `payloads` `rounds` times with
+/// `dictionary` (empty for plain zstd), checking every round trip
+pub fn measure(payloads: &[Vec<u8>], dictionary: &[u8], level: i32, rounds: usize) -> anyhow::Result<Compression> {
+    let mut compressor = zstd::bulk::Compressor::with_dictionary(level, dictionary)?;
+    let mut decompressor = zstd::bulk::Decompressor::with_dictionary(dictionary)?;
+    let rounds = rounds.max(1);
+    let mut measured = Compression::default();
+    for _ in 0..rounds {
+        let start = Instant::now();
+        let compressed = payloads.iter().map(|payload| compressor.compress(payload)).collect::<Result<Vec<_>, _>>()?;
+        measured.compress += start.elapsed();
+
+        let start = Instant::now();
+        for (payload, compressed) in payloads.iter().zip(&compressed) {
+            let restored = decompressor.decompress(compressed, payload.len())?;
+            anyhow::ensure!(restored == *payload, "Decompressed payload differs from the original");
+        }
+        measured.decompress += start.elapsed();
+        measured.bytes = compressed.iter().map(|compressed| compressed.len() as u64).sum();
+    }
+    measured.compress /= rounds as u32;
+    measured.decompress /= rounds as u32;
+    Ok(measured)
+}
+
+/// One protocol's batch of one size, raw and compressed both ways
+#[derive(Debug, Clone)]
+pub struct DictionaryResult {
+    pub protocol: String,
+    pub points: usize,
+    /// Payloads the batch is sent as
+    pub payloads: usize,
+    pub raw_bytes: u64,
+    pub plain: Compression,
+    pub dictionary: Compression,
+}
+
+impl DictionaryResult {
+    /// Compressed size as a fraction of the raw size
+    pub fn ratio(&self, compression: &Compression) -> f64 {
+        if self.raw_bytes == 0 {
+            return 1.0;
+        }
+        compression.bytes as f64 / self.raw_bytes as f64
+    }
+}
+
+/// A trained dictionary's size and how long training took
+#[derive(Debug, Clone)]
+pub struct TrainedDictionary {
+    pub protocol: String,
+    pub samples: usize,
+    pub bytes: usize,
+    pub training: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct DictionaryReport {
+    pub level: i32,
+    pub dictionaries: Vec<TrainedDictionary>,
+    pub results: Vec<DictionaryResult>,
+}
+
+/// Train a dictionary per payload on points from their own seed, then
+/// compress batches of the generated metrics the benchmarks submit with
+/// plain zstd and with the dictionary. No service is involved; the payloads
+/// are built the way the clients build them.
+pub fn run(settings: &DictionarySettings) -> anyhow::Result<DictionaryReport> {
+    let training_points = TestDataGenerator::new().seed(TRAINING_SEED).generate(settings.training_samples);
+    let mut dictionaries = Vec::new();
+    let mut results = Vec::new();
+    for payload in Payload::ALL {
+        // A training sample is one payload: a single-point body for REST,
+        // whose bodies all share that shape, and a message for Cap'n Proto
+        let samples = training_points
+            .iter()
+            .map(|point| payload.encode(std::slice::from_ref(point)))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .concat();
+        let start = Instant::now();
+        let dictionary = train(&samples, settings.dictionary_bytes)?;
+        dictionaries.push(TrainedDictionary {
+            protocol: payload.label(),
+            samples: samples.len(),
+            bytes: dictionary.len(),
+            training: start.elapsed(),
+        });
+
+        for &points in &settings.batch_sizes {
+            let payloads = payload.encode(&shared::generate_test_data(points))?;
+            results.push(DictionaryResult {
+                protocol: payload.label(),
+                points,
+                payloads: payloads.len(),
+                raw_bytes: payloads.iter().map(|payload| payload.len() as u64).sum(),
+                plain: measure(&payloads, &[], settings.level, settings.rounds)?,
+                dictionary: measure(&payloads, &dictionary, settings.level, settings.rounds)?,
+            });
+        }
+    }
+    Ok(DictionaryReport { level: settings.level, dictionaries, results })
+}
+
+impl DictionaryReport {
+    /// A `compress` result per protocol, batch size and way of compressing,
+    /// labelled like `REST/json+zstd-dict`: compressed bytes as request
+    /// bytes and compression time as latency. Uncompressed batches are
+    /// recorded under the bare label with no latency.
+    pub fn to_run(&self) -> BenchmarkRun {
+        let mut run = BenchmarkRun::new();
+        for result in &self.results {
+            let rows = [
+                (result.protocol.clone(), result.raw_bytes, Duration::ZERO),
+                (format!("{}+zstd", result.protocol), result.plain.bytes, result.plain.compress),
+                (format!("{}+zstd-dict", result.protocol), result.dictionary.bytes, result.dictionary.compress),
+            ];
+            for (protocol, bytes, compress) in rows {
+                run.push(OperationResult {
+                    protocol,
+                    operation: COMPRESS_OPERATION.to_string(),
+                    points: result.points,
+                    latency_ns: compress.as_nanos() as u64,
+                    latency_ci_ns: None,
+                    time_to_first_byte_ns: None,
+                    request_bytes: bytes,
+                    response_bytes: 0,
+                    memory_allocated: 0,
+                    cpu_cycles: 0,
+                    energy_uj: None,
+                    payload_bytes: None,
+                    payload_estimated: false,
+                    server_time_ns: None,
+                    server_allocated_bytes: None,
+                });
+            }
+        }
+        run
+    }
+}
+
+impl fmt::Display for DictionaryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Dictionaries (zstd level {}):", self.level)?;
+        for dictionary in &self.dictionaries {
+            writeln!(
+                f,
+                "  {:<24} {} bytes from {} samples in {:.1} ms",
+                dictionary.protocol,
+                dictionary.bytes,
+                dictionary.samples,
+                dictionary.training.as_secs_f64() * 1000.0
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "  {:<24} {:>6} {:>10} {:>10} {:>7} {:>10} {:>7} {:>10} {:>10} {:>10} {:>10}",
+            "Protocol", "Points", "Raw", "zstd", "Ratio", "Dict", "Ratio", "zstd µs", "Dict µs", "Unzstd µs", "Undict µs"
+        )?;
+        let micros = |duration: Duration| duration.as_secs_f64() * 1_000_000.0;
+        for result in &self.results {
+            writeln!(
+                f,
+                "  {:<24} {:>6} {:>10} {:>10} {:>7.3} {:>10} {:>7.3} {:>10.1} {:>10.1} {:>10.1} {:>10.1}",
+                result.protocol,
+                result.points,
+                result.raw_bytes,
+                result.plain.bytes,
+                result.ratio(&result.plain),
+                result.dictionary.bytes,
+                result.ratio(&result.dictionary),
+                micros(result.plain.compress),
+                micros(result.dictionary.compress),
+                micros(result.plain.decompress),
+                micros(result.dictionary.decompress),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use crate::results::{BenchmarkRun, OperationResult};
+
+/// One result present in both runs, with the candidate's change over the
+/// baseline as a percentage (positive is worse for all three)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultChange {
+    pub protocol: String,
+    pub operation: String,
+    pub points: usize,
+    pub baseline_latency_ns: u64,
+    pub candidate_latency_ns: u64,
+    pub latency_change: f64,
+    pub total_bytes_change: f64,
+    pub memory_change: f64,
+}
+
+/// Two runs set side by side, matching results by protocol, operation and
+/// point count
+#[derive(Debug, Clone, Default)]
+pub struct RunDiff {
+    pub changes: Vec<ResultChange>,
+    /// `protocol operation points` of results only the baseline has
+    pub removed: Vec<String>,
+    /// `protocol operation points` of results only the candidate has
+    pub added: Vec<String>,
+    /// `process: baseline -> candidate` for every process both runs recorded
+    /// an allocator for and whose allocator differs; changes in memory and
+    /// latency may be the allocator's rather than the protocol's
+    pub allocator_changes: Vec<String>,
+}
+
+fn key(result: &OperationResult) -> (&str, &str, usize) {
+    (result.protocol.as_str(), result.operation.as_str(), result.points)
+}
+
+fn label(result: &OperationResult) -> String {
+    format!("{} {} {}", result.protocol, result.operation, result.points)
+}
+
+/// Relative change from `baseline` to `candidate` in percent; 0 when both
+/// are 0, infinite when only the baseline is
+fn percent_change(baseline: u64, candidate: u64) -> f64 {
+    if baseline == 0 {
+        return if candidate == 0 { 0.0 } else { f64::INFINITY };
+    }
+    (candidate as f64 - baseline as f64) / baseline as f64 * 100.0
+}
+
+impl RunDiff {
+    /// Compare `candidate` against `baseline`. When a run holds the same
+    /// protocol, operation and point count more than once, the first counts.
+    pub fn new(baseline: &BenchmarkRun, candidate: &BenchmarkRun) -> Self {
+        let mut diff = RunDiff::default();
+        for before in &baseline.results {
+            if diff.changes.iter().any(|change| (change.protocol.as_str(), change.operation.as_str(), change.points) == key(before)) {
+                continue;
+            }
+            let Some(after) = candidate.results.iter().find(|after| key(after) == key(before)) else {
+                diff.removed.push(label(before));
+                continue;
+            };
+            diff.changes.push(ResultChange {
+                protocol: before.protocol.clone(),
+                operation: before.operation.clone(),
+                points: before.points,
+                baseline_latency_ns: before.latency_ns,
+                candidate_latency_ns: after.latency_ns,
+                latency_change: percent_change(before.latency_ns, after.latency_ns),
+                total_bytes_change: percent_change(
+                    before.request_bytes + before.response_bytes,
+                    after.request_bytes + after.response_bytes,
+                ),
+                memory_change: percent_change(before.memory_allocated, after.memory_allocated),
+            });
+        }
+        for after in &candidate.results {
+            let label = label(after);
+            if !baseline.results.iter().any(|before| key(before) == key(after)) && !diff.added.contains(&label) {
+                diff.added.push(label);
+            }
+        }
+        for (process, before) in &baseline.allocators {
+            if let Some(after) = candidate.allocators.get(process).filter(|after| *after != before) {
+                diff.allocator_changes.push(format!("{}: {} -> {}", process, before, after));
+            }
+        }
+        diff
+    }
+
+    /// Changes where latency, traffic or memory grew by more than `percent`
+    pub fn regressions(&self, percent: f64) -> Vec<&ResultChange> {
+        self.changes
+            .iter()
+            .filter(|change| {
+                change.latency_change > percent || change.total_bytes_change > percent || change.memory_change > percent
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for RunDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  {:<24} {:<13} {:>7} {:>12} {:>12} {:>10} {:>10} {:>10}",
+            "Protocol", "Operation", "Points", "Before (ms)", "After (ms)", "Latency", "Traffic", "Memory"
+        )?;
+        for change in &self.changes {
+            writeln!(
+                f,
+                "  {:<24} {:<13} {:>7} {:>12.3} {:>12.3} {:>+9.1}% {:>+9.1}% {:>+9.1}%",
+                change.protocol,
+                change.operation,
+                change.points,
+                change.baseline_latency_ns as f64 / 1_000_000.0,
+                change.candidate_latency_ns as f64 / 1_000_000.0,
+                change.latency_change,
+                change.total_bytes_change,
+                change.memory_change,
+            )?;
+        }
+        for removed in &self.removed {
+            writeln!(f, "  only in baseline:  {}", removed)?;
+        }
+        for added in &self.added {
+            writeln!(f, "  only in candidate: {}", added)?;
+        }
+        for change in &self.allocator_changes {
+            writeln!(f, "  allocator changed: {}", change)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use shared::{generate_test_data, MetricQuery};
+use std::fmt;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::report::PayloadOperation;
+use crate::results::{BenchmarkRun, OperationResult};
+use crate::statistics::AggregateReport;
+use crate::{benchmark_operation, protocol_clients};
+
+/// Port `protobench agent` listens on unless given
+pub const DEFAULT_AGENT_PORT: u16 = 7878;
+
+// Time between the coordinator sending the workload and the agents
+// starting it, so every agent is loading the services at once
+const START_DELAY: Duration = Duration::from_secs(2);
+
+// How long the coordinator waits to connect to an agent and hear its hello
+const AGENT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What every agent runs against the services it targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workload {
+    /// Points per batch submit, and per query
+    pub points: usize,
+    /// Submit-and-query rounds on each protocol
+    pub rounds: usize,
+}
+
+/// Coordinator to agent, one JSON object per line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Hello,
+    /// Run `workload` after `start_delay_ms`, tagging points with `agent`
+    Run { agent: String, workload: Workload, start_delay_ms: u64 },
+}
+
+/// Agent to coordinator, one JSON object per line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response {
+    Hello { harness_version: String, protocols: Vec<String> },
+    Finished { run: BenchmarkRun },
+    Failed { message: String },
+}
+
+/// One end of a control connection
+struct Control {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Control {
+    fn new(stream: TcpStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Self { lines: BufReader::new(reader).lines(), writer }
+    }
+
+    async fn send(&mut self, message: &impl Serialize) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// The next message, or `None` once the peer has closed the connection
+    async fn receive<T: for<'de> Deserialize<'de>>(&mut self) -> anyhow::Result<Option<T>> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_str(&line).context("Malformed control message")?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Run workloads for coordinators that connect to `listener`, one
+/// connection at a time, until the listener fails. Results are measured
+/// against the services this process's clients target.
+pub async fn serve_agent(listener: TcpListener) -> anyhow::Result<()> {
+    loop {
+        let (stream, coordinator) = listener.accept().await?;
+        tracing::info!(%coordinator, "coordinator connected");
+        if let Err(e) = handle_coordinator(Control::new(stream)).await {
+            tracing::warn!(%coordinator, error = %e, "control connection failed");
+        }
+    }
+}
+
+async fn handle_coordinator(mut control: Control) -> anyhow::Result<()> {
+    while let Some(request) = control.receive::<Request>().await? {
+        let response = match request {
+            Request::Hello => Response::Hello {
+                harness_version: env!("CARGO_PKG_VERSION").to_string(),
+                protocols: protocol_clients().into_iter().map(|(name, _)| name).collect(),
+            },
+            Request::Run { agent, workload, start_delay_ms } => {
+                tokio::time::sleep(Duration::from_millis(start_delay_ms)).await;
+                match agent_pass(&agent, workload).await {
+                    Ok(run) => Response::Finished { run },
+                    Err(e) => Response::Failed { message: format!("{:#}", e) },
+                }
+            }
+        };
+        control.send(&response).await?;
+    }
+    Ok(())
+}
+
+/// Host name an agent's points are stored under, so agents sharing the
+/// services only ever query and delete their own
+pub fn agent_hostname(agent: &str) -> String {
+    format!("protobench-agent-{}", agent)
+}
+
+/// `workload.rounds` rounds of a batch submit and a query of the same points
+/// on every protocol. Unlike `quick_pass` this never empties the services,
+/// since other agents are using them; it deletes only its own points.
+pub async fn agent_pass(agent: &str, workload: Workload) -> anyhow::Result<BenchmarkRun> {
+    let hostname = agent_hostname(agent);
+    let own_points = MetricQuery {
+        start_time: i64::MIN,
+        end_time: i64::MAX,
+        hostname_filter: Some(hostname.clone()),
+        limit: Some(workload.points as u32),
+        offset: None,
+    };
+
+    let mut run = BenchmarkRun::new();
+    for (name, client) in protocol_clients() {
+        for _ in 0..workload.rounds {
+            let mut metrics = generate_test_data(workload.points);
+            for metric in &mut metrics {
+                metric.hostname = hostname.clone();
+            }
+            let (stored, submitted) =
+                benchmark_operation("submit_batch", client.wire_bytes(), || client.submit_batch(metrics)).await;
+            stored.with_context(|| format!("{} submit_batch failed", name))?;
+            run.push(OperationResult::new(&name, PayloadOperation::Submit.label(), workload.points, &submitted));
+
+            let (returned, queried) =
+                benchmark_operation("query", client.wire_bytes(), || client.query_metrics(own_points.clone())).await;
+            returned.with_context(|| format!("{} query failed", name))?;
+            run.push(OperationResult::new(&name, PayloadOperation::Query.label(), workload.points, &queried));
+        }
+        client
+            .delete_metrics(MetricQuery { limit: None, ..own_points.clone() })
+            .await
+            .with_context(|| format!("{} cleanup failed", name))?;
+    }
+    Ok(run)
+}
+
+/// Each agent's run from one `coordinate`, in the order the agents were given
+#[derive(Debug, Clone)]
+pub struct DistributedReport {
+    pub agents: Vec<(String, BenchmarkRun)>,
+}
+
+/// Connect to every agent in `agents` (`host:port`), check they run the same
+/// harness version, then have them all run `workload` at once and collect
+/// their results. Any agent failing fails the whole run, since the others'
+/// load would then not be what was asked for.
+pub async fn coordinate(agents: &[String], workload: Workload) -> anyhow::Result<DistributedReport> {
+    if agents.is_empty() {
+        bail!("No agents given");
+    }
+
+    let mut controls = Vec::with_capacity(agents.len());
+    for agent in agents {
+        let control = tokio::time::timeout(AGENT_CONNECT_TIMEOUT, hello(agent))
+            .await
+            .with_context(|| format!("Agent {} didn't answer", agent))??;
+        controls.push(control);
+    }
+
+    // Every workload is sent before any agent's delay runs out
+    for (agent, control) in agents.iter().zip(&mut controls) {
+        let run = Request::Run {
+            agent: agent.clone(),
+            workload,
+            start_delay_ms: START_DELAY.as_millis() as u64,
+        };
+        control.send(&run).await.with_context(|| format!("Failed to send the workload to {}", agent))?;
+    }
+
+    let finished = futures_util::future::join_all(
+        agents.iter().zip(controls).map(|(agent, control)| results(agent, control)),
+    )
+    .await;
+    Ok(DistributedReport { agents: finished.into_iter().collect::<anyhow::Result<_>>()? })
+}
+
+async fn results(agent: &str, mut control: Control) -> anyhow::Result<(String, BenchmarkRun)> {
+    match control.receive::<Response>().await? {
+        Some(Response::Finished { run }) => Ok((agent.to_string(), run)),
+        Some(Response::Failed { message }) => bail!("Agent {} failed: {}", agent, message),
+        Some(other) => bail!("Agent {} sent {:?} instead of results", agent, other),
+        None => bail!("Agent {} closed the connection mid-run", agent),
+    }
+}
+
+async fn hello(agent: &str) -> anyhow::Result<Control> {
+    let stream = TcpStream::connect(agent).await.with_context(|| format!("Failed to connect to agent {}", agent))?;
+    let mut control = Control::new(stream);
+    control.send(&Request::Hello).await?;
+    match control.receive::<Response>().await? {
+        Some(Response::Hello { harness_version, protocols }) => {
+            if harness_version != env!("CARGO_PKG_VERSION") {
+                bail!(
+                    "Agent {} runs harness {}, this coordinator {}",
+                    agent,
+                    harness_version,
+                    env!("CARGO_PKG_VERSION")
+                );
+            }
+            tracing::info!(agent, ?protocols, "agent ready");
+            Ok(control)
+        }
+        Some(other) => bail!("Agent {} answered hello with {:?}", agent, other),
+        None => bail!("Agent {} closed the connection", agent),
+    }
+}
+
+impl DistributedReport {
+    /// Every agent's results summarized together, each round of each agent
+    /// counting as one sample
+    pub fn aggregate(&self) -> AggregateReport {
+        let runs: Vec<BenchmarkRun> = self.agents.iter().map(|(_, run)| run.clone()).collect();
+        AggregateReport::new(&runs)
+    }
+
+    /// Every agent's results in one run, with the agents recorded in its
+    /// settings as `PROTOBENCH_AGENTS`
+    pub fn to_run(&self) -> BenchmarkRun {
+        let mut run = BenchmarkRun::new();
+        let agents: Vec<&str> = self.agents.iter().map(|(agent, _)| agent.as_str()).collect();
+        run.settings.insert("PROTOBENCH_AGENTS".to_string(), agents.join(","));
+        for (_, agent_run) in &self.agents {
+            run.results.extend(agent_run.results.iter().cloned());
+        }
+        run
+    }
+}
+
+impl fmt::Display for DistributedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (agent, run) in &self.agents {
+            writeln!(f, "Agent {}:", agent)?;
+            write!(f, "{}", AggregateReport::new(std::slice::from_ref(run)))?;
+        }
+        writeln!(f, "All {} agents:", self.agents.len())?;
+        write!(f, "{}", self.aggregate())
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{bail, Context};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Where Linux exposes RAPL counters through the powercap framework
+pub const POWERCAP_ROOT: &str = "/sys/class/powercap";
+
+/// One CPU package's RAPL zone. AMD CPUs report theirs under the same
+/// `intel-rapl` names.
+#[derive(Debug, Clone)]
+struct Zone {
+    name: String,
+    energy_uj: PathBuf,
+    /// Where `energy_uj` wraps back to 0
+    max_energy_range_uj: u64,
+}
+
+/// The machine's RAPL package zones. Their counters cover everything the
+/// CPUs run, services and harness alike, so measure with nothing else busy.
+#[derive(Debug, Clone)]
+pub struct Rapl {
+    zones: Vec<Zone>,
+}
+
+/// Every zone's counter at one moment
+#[derive(Debug, Clone)]
+pub struct RaplReading {
+    energy_uj: Vec<u64>,
+    at: Instant,
+}
+
+/// Energy drawn between two readings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Energy {
+    pub joules: f64,
+    pub elapsed: Duration,
+}
+
+impl Energy {
+    /// Mean power over the interval
+    pub fn watts(&self) -> f64 {
+        self.joules / self.elapsed.as_secs_f64()
+    }
+
+    /// Joules per 1000 of the `requests` made in the interval
+    pub fn joules_per_1000(&self, requests: usize) -> f64 {
+        self.joules * 1000.0 / requests as f64
+    }
+}
+
+impl Rapl {
+    /// The package zones under `root`, such as `intel-rapl:0`. Their
+    /// subzones (`intel-rapl:0:0` for cores, `intel-rapl:0:1` for uncore)
+    /// are already counted in the package, so they're skipped. Fails when
+    /// there are none or a counter can't be read, which since Linux 5.10
+    /// takes root or a `chmod` of `energy_uj`.
+    pub fn open(root: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let root = root.as_ref();
+        let entries = std::fs::read_dir(root).with_context(|| format!("Failed to read {}", root.display()))?;
+        let mut zones = Vec::new();
+        for entry in entries {
+            let dir = entry?.path();
+            let is_package = dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("intel-rapl:"))
+                .is_some_and(|index| !index.contains(':'));
+            if !is_package {
+                continue;
+            }
+            let zone = Zone {
+                name: read_trimmed(&dir.join("name"))?,
+                energy_uj: dir.join("energy_uj"),
+                max_energy_range_uj: read_trimmed(&dir.join("max_energy_range_uj"))?
+                    .parse()
+                    .with_context(|| format!("Invalid {}", dir.join("max_energy_range_uj").display()))?,
+            };
+            read_counter(&zone.energy_uj)?;
+            zones.push(zone);
+        }
+        if zones.is_empty() {
+            bail!("No RAPL package zones under {}", root.display());
+        }
+        zones.sort_by(|a, b| a.energy_uj.cmp(&b.energy_uj));
+        Ok(Self { zones })
+    }
+
+    /// `open(POWERCAP_ROOT)` when `PROTOBENCH_RAPL` is set, `None` otherwise.
+    /// Setting it on a machine without readable counters is an error rather
+    /// than a run that silently leaves energy out.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        if std::env::var_os("PROTOBENCH_RAPL").is_none() {
+            return Ok(None);
+        }
+        Self::open(POWERCAP_ROOT).map(Some)
+    }
+
+    /// The zones' names, e.g. `package-0`
+    pub fn zones(&self) -> Vec<&str> {
+        self.zones.iter().map(|zone| zone.name.as_str()).collect()
+    }
+
+    pub fn read(&self) -> anyhow::Result<RaplReading> {
+        let energy_uj = self.zones.iter().map(|zone| read_counter(&zone.energy_uj)).collect::<anyhow::Result<_>>()?;
+        Ok(RaplReading { energy_uj, at: Instant::now() })
+    }
+
+    /// Energy drawn from `start` to `end`, summed over the zones. A counter
+    /// lower at `end` wrapped once past its `max_energy_range_uj`, which at
+    /// the ~100W a desktop package draws takes the better part of an hour,
+    /// so an interval can't hide a second wrap.
+    pub fn energy_between(&self, start: &RaplReading, end: &RaplReading) -> Energy {
+        let microjoules: u64 = self
+            .zones
+            .iter()
+            .zip(start.energy_uj.iter().zip(&end.energy_uj))
+            .map(|(zone, (&before, &after))| {
+                if after >= before {
+                    after - before
+                } else {
+                    zone.max_energy_range_uj - before + after
+                }
+            })
+            .sum();
+        Energy {
+            joules: microjoules as f64 / 1_000_000.0,
+            elapsed: end.at.duration_since(start.at),
+        }
+    }
+
+    /// Run `operation` and return the energy the machine drew meanwhile
+    pub async fn measure<T>(&self, operation: impl std::future::Future<Output = T>) -> anyhow::Result<(T, Energy)> {
+        let start = self.read()?;
+        let result = operation.await;
+        let end = self.read()?;
+        Ok((result, self.energy_between(&start, &end)))
+    }
+}
+
+fn read_trimmed(path: &Path) -> anyhow::Result<String> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(contents.trim().to_string())
+}
+
+fn read_counter(path: &Path) -> anyhow::Result<u64> {
+    read_trimmed(path)?.parse().with_context(|| format!("Invalid {}", path.display()))
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+/// Why a client operation failed, in the same categories for every protocol,
+/// so the harness can count failures by kind and callers can `match` on them
+/// instead of parsing messages
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// The service couldn't be reached, the connection broke mid-call, or the
+    /// client couldn't be set up to connect (e.g. an unreadable CA certificate)
+    Connect { protocol: &'static str, source: anyhow::Error },
+    /// The call outlived its client-side timeout, or for gRPC the deadline the
+    /// server enforced
+    Timeout { protocol: &'static str },
+    /// The request couldn't be encoded
+    Serialize { protocol: &'static str, source: anyhow::Error },
+    /// The service answered with an error: `code` is rest-service's error code
+    /// (or the HTTP status when the body has none), the gRPC status code or
+    /// the Cap'n Proto error kind
+    Server { protocol: &'static str, code: String, message: String },
+    /// The response couldn't be decoded, or ended before it was complete
+    Decode { protocol: &'static str, source: anyhow::Error },
+    /// Given up through a `CancellationToken`; see `cancellation`
+    Cancelled,
+    /// Rejected by an open `CircuitBreaker` without contacting the server
+    CircuitOpen { client: String },
+}
+
+impl ProtocolError {
+    pub fn connect(protocol: &'static str, source: impl Into<anyhow::Error>) -> Self {
+        ProtocolError::Connect { protocol, source: source.into() }
+    }
+
+    pub fn serialize(protocol: &'static str, source: impl Into<anyhow::Error>) -> Self {
+        ProtocolError::Serialize { protocol, source: source.into() }
+    }
+
+    pub fn decode(protocol: &'static str, source: impl Into<anyhow::Error>) -> Self {
+        ProtocolError::Decode { protocol, source: source.into() }
+    }
+
+    /// Short name of the failure category, for tallies and result labels
+    pub fn category(&self) -> &'static str {
+        match self {
+            ProtocolError::Connect { .. } => "connect",
+            ProtocolError::Timeout { .. } => "timeout",
+            ProtocolError::Serialize { .. } => "serialize",
+            ProtocolError::Server { .. } => "server",
+            ProtocolError::Decode { .. } => "decode",
+            ProtocolError::Cancelled => "cancelled",
+            ProtocolError::CircuitOpen { .. } => "circuit_open",
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ProtocolError::Timeout { .. })
+    }
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::Connect { protocol, source } => write!(f, "{} connection failed: {:#}", protocol, source),
+            ProtocolError::Timeout { protocol } => write!(f, "{} request timed out", protocol),
+            ProtocolError::Serialize { protocol, source } => write!(f, "{} request couldn't be encoded: {:#}", protocol, source),
+            ProtocolError::Server { protocol, code, message } => write!(f, "{} server error {}: {}", protocol, code, message),
+            ProtocolError::Decode { protocol, source } => write!(f, "{} response couldn't be decoded: {:#}", protocol, source),
+            ProtocolError::Cancelled => write!(f, "request cancelled by the client"),
+            ProtocolError::CircuitOpen { client } => write!(f, "{} circuit breaker is open", client),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Failures counted by `ProtocolError::category`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FailureBreakdown {
+    counts: BTreeMap<&'static str, u64>,
+}
+
+impl FailureBreakdown {
+    pub fn record(&mut self, error: &ProtocolError) {
+        *self.counts.entry(error.category()).or_default() += 1;
+    }
+
+    pub fn count(&self, category: &str) -> u64 {
+        self.counts.get(category).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+impl std::fmt::Display for FailureBreakdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.counts.is_empty() {
+            return write!(f, "no failures");
+        }
+        let counts: Vec<String> = self.counts.iter().map(|(category, count)| format!("{} {}", category, count)).collect();
+        write!(f, "{}", counts.join(", "))
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::Context;
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::report::cost_score;
+use crate::results::{BenchmarkRun, OperationResult};
+
+// A dashboard push shouldn't hold up the end of a run for long
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where `push_from_env` sends a run's results
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushTarget {
+    /// A Prometheus pushgateway's base URL, from `PROTOBENCH_PUSHGATEWAY_URL`.
+    /// Each source replaces its own group under the `protobench` job (or
+    /// `PROTOBENCH_PUSHGATEWAY_JOB`).
+    Pushgateway { url: String, job: String },
+    /// An OTLP/HTTP receiver's base URL, from `PROTOBENCH_OTLP_ENDPOINT`;
+    /// results are posted as JSON to `/v1/metrics`
+    Otlp { endpoint: String },
+}
+
+/// A gauge per `OperationResult` field, labelled with protocol, operation
+/// and point count
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    /// OTLP unit
+    unit: &'static str,
+    value: fn(&OperationResult) -> Option<f64>,
+}
+
+const GAUGES: [Gauge; 8] = [
+    Gauge {
+        name: "protobench_latency_seconds",
+        help: "Time the operation took to complete",
+        unit: "s",
+        value: |result| Some(result.latency_ns as f64 / 1e9),
+    },
+    Gauge {
+        name: "protobench_time_to_first_byte_seconds",
+        help: "Time until a streamed query delivered its first point",
+        unit: "s",
+        value: |result| result.time_to_first_byte_ns.map(|ns| ns as f64 / 1e9),
+    },
+    Gauge {
+        name: "protobench_request_bytes",
+        help: "Bytes the client wrote to the wire",
+        unit: "By",
+        value: |result| Some(result.request_bytes as f64),
+    },
+    Gauge {
+        name: "protobench_response_bytes",
+        help: "Bytes the client read from the wire",
+        unit: "By",
+        value: |result| Some(result.response_bytes as f64),
+    },
+    Gauge {
+        name: "protobench_memory_allocated_bytes",
+        help: "Heap the client allocated during the operation",
+        unit: "By",
+        value: |result| Some(result.memory_allocated as f64),
+    },
+    Gauge {
+        name: "protobench_cpu_cycles",
+        help: "Estimated CPU cycles the operation took",
+        unit: "1",
+        value: |result| Some(result.cpu_cycles as f64),
+    },
+    Gauge {
+        name: "protobench_energy_joules",
+        help: "Energy the machine drew during the operation, from RAPL",
+        unit: "J",
+        value: |result| result.energy_uj.map(|uj| uj as f64 / 1e6),
+    },
+    Gauge {
+        name: "protobench_cost_score",
+        help: "Composite cost of the operation, lower is better",
+        unit: "1",
+        value: |result| Some(cost_score(&result.metrics())),
+    },
+];
+
+/// The targets configured through `PROTOBENCH_PUSHGATEWAY_URL` and
+/// `PROTOBENCH_OTLP_ENDPOINT`; empty when neither is set
+pub fn push_targets_from_env() -> Vec<PushTarget> {
+    let mut targets = Vec::new();
+    if let Ok(url) = std::env::var("PROTOBENCH_PUSHGATEWAY_URL") {
+        let job = std::env::var("PROTOBENCH_PUSHGATEWAY_JOB").unwrap_or_else(|_| "protobench".to_string());
+        targets.push(PushTarget::Pushgateway { url, job });
+    }
+    if let Ok(endpoint) = std::env::var("PROTOBENCH_OTLP_ENDPOINT") {
+        targets.push(PushTarget::Otlp { endpoint });
+    }
+    targets
+}
+
+/// `run`'s results in the Prometheus text exposition format, one gauge
+/// family per measurement plus `protobench_run_started_timestamp_seconds`.
+/// A protocol, operation and point count measured more than once keeps its
+/// last result, since a pushgateway refuses duplicate series.
+pub fn prometheus_text(run: &BenchmarkRun) -> String {
+    let results = latest_results(run);
+    let mut text = String::new();
+    for gauge in &GAUGES {
+        let samples: Vec<(&OperationResult, f64)> =
+            results.iter().filter_map(|result| Some((*result, (gauge.value)(result)?))).collect();
+        if samples.is_empty() {
+            continue;
+        }
+        let _ = writeln!(text, "# HELP {} {}", gauge.name, gauge.help);
+        let _ = writeln!(text, "# TYPE {} gauge", gauge.name);
+        for (result, value) in samples {
+            let _ = writeln!(
+                text,
+                "{}{{protocol=\"{}\",operation=\"{}\",points=\"{}\"}} {}",
+                gauge.name,
+                escape_label(&result.protocol),
+                escape_label(&result.operation),
+                result.points,
+                value
+            );
+        }
+    }
+    let _ = writeln!(text, "# HELP protobench_run_started_timestamp_seconds When the run started");
+    let _ = writeln!(text, "# TYPE protobench_run_started_timestamp_seconds gauge");
+    let _ = writeln!(text, "protobench_run_started_timestamp_seconds {}", run.started_at_ms as f64 / 1000.0);
+    text
+}
+
+/// `run`'s results as an OTLP `ExportMetricsServiceRequest` in its JSON
+/// encoding: the gauges `prometheus_text` writes, each point stamped with the
+/// run's start and carrying `source` as `protobench.source`
+pub fn otlp_json(run: &BenchmarkRun, source: &str) -> Value {
+    let results = latest_results(run);
+    let time_unix_nano = (run.started_at_ms as u128 * 1_000_000).to_string();
+    let metrics: Vec<Value> = GAUGES
+        .iter()
+        .filter_map(|gauge| {
+            let data_points: Vec<Value> = results
+                .iter()
+                .filter_map(|result| {
+                    Some(json!({
+                        "attributes": [
+                            string_attribute("protocol", &result.protocol),
+                            string_attribute("operation", &result.operation),
+                            {"key": "points", "value": {"intValue": result.points.to_string()}},
+                            string_attribute("protobench.source", source),
+                        ],
+                        "timeUnixNano": time_unix_nano,
+                        "asDouble": (gauge.value)(result)?,
+                    }))
+                })
+                .collect();
+            if data_points.is_empty() {
+                return None;
+            }
+            Some(json!({
+                "name": gauge.name,
+                "description": gauge.help,
+                "unit": gauge.unit,
+                "gauge": {"dataPoints": data_points},
+            }))
+        })
+        .collect();
+
+    json!({
+        "resourceMetrics": [{
+            "resource": {"attributes": [string_attribute("service.name", "protobench")]},
+            "scopeMetrics": [{
+                "scope": {"name": "benchmarks", "version": run.harness_version},
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
+
+/// Send `run` to `target`. `source` names what produced it (`cli`, `check`,
+/// `criterion`): its pushgateway group, or its OTLP attribute, so runs of one
+/// kind don't overwrite another's.
+pub async fn push(run: &BenchmarkRun, source: &str, target: &PushTarget) -> anyhow::Result<()> {
+    let client = reqwest::Client::builder().timeout(PUSH_TIMEOUT).build()?;
+    let request = match target {
+        PushTarget::Pushgateway { url, job } => client
+            .put(format!("{}/metrics/job/{}/source/{}", url.trim_end_matches('/'), job, source))
+            .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(prometheus_text(run)),
+        PushTarget::Otlp { endpoint } => {
+            client.post(format!("{}/v1/metrics", endpoint.trim_end_matches('/'))).json(&otlp_json(run, source))
+        }
+    };
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// `push` to every target `push_targets_from_env` finds, reporting each one
+/// on stdout. A failed push is printed and doesn't fail the run, whose
+/// results are already measured.
+pub async fn push_from_env(run: &BenchmarkRun, source: &str) {
+    for target in push_targets_from_env() {
+        let destination = match &target {
+            PushTarget::Pushgateway { url, .. } => format!("pushgateway {}", url),
+            PushTarget::Otlp { endpoint } => format!("OTLP endpoint {}", endpoint),
+        };
+        match push(run, source, &target).await.with_context(|| format!("Failed to push to {}", destination)) {
+            Ok(()) => println!("Pushed {} results to {}", run.results.len(), destination),
+            Err(e) => eprintln!("{:#}", e),
+        }
+    }
+}
+
+// The last result for each protocol, operation and point count, in the order
+// each first appears
+fn latest_results(run: &BenchmarkRun) -> Vec<&OperationResult> {
+    let mut latest: Vec<&OperationResult> = Vec::new();
+    for result in &run.results {
+        let same = |kept: &&OperationResult| {
+            kept.protocol == result.protocol && kept.operation == result.operation && kept.points == result.points
+        };
+        match latest.iter_mut().find(|kept| same(kept)) {
+            Some(kept) => *kept = result,
+            None => latest.push(result),
+        }
+    }
+    latest
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({"key": key, "value": {"stringValue": value}})
+}
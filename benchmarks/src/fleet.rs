@@ -0,0 +1,240 @@
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use shared::{MetricPoint, TestDataGenerator};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+use crate::error::FailureBreakdown;
+use crate::results::{BenchmarkRun, OperationResult};
+use crate::statistics::{percentile, Summary};
+use crate::{protocol_clients, purge_all_services, ProtocolClient, ProtocolError};
+
+// Largest accepted jitter; at 1 or more a host could submit twice at once
+const MAX_JITTER: f64 = 0.9;
+
+/// Operation name fleet results are recorded under
+pub const FLEET_OPERATION: &str = "fleet_submit";
+
+/// A fleet of virtual hosts, each submitting one point every `interval`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FleetSettings {
+    pub hosts: usize,
+    pub interval: Duration,
+    /// How far each wait strays from `interval`, as a fraction of it: 0.1
+    /// waits anywhere from 0.9 to 1.1 intervals
+    pub jitter: f64,
+    /// How long the fleet runs against each protocol
+    pub duration: Duration,
+    /// Seeds the schedule and the points
+    pub seed: u64,
+}
+
+impl FleetSettings {
+    /// Submissions per second the fleet offers
+    pub fn offered_rate(&self) -> f64 {
+        self.hosts as f64 / self.interval.as_secs_f64()
+    }
+}
+
+/// When each submission is due, as its offset from the start and the
+/// host's index, in order. Every host starts at a random point within the
+/// first interval and then waits `interval` ± `jitter` between
+/// submissions. The same settings always give the same schedule, so every
+/// protocol faces identical traffic.
+pub fn schedule(settings: &FleetSettings) -> Vec<(Duration, usize)> {
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    let interval = settings.interval.as_secs_f64();
+    let jitter = settings.jitter.clamp(0.0, MAX_JITTER);
+    let duration = settings.duration.as_secs_f64();
+
+    let mut due = Vec::new();
+    for host in 0..settings.hosts {
+        let mut at = rng.gen_range(0.0..interval);
+        while at < duration {
+            due.push((Duration::from_secs_f64(at), host));
+            at += interval * (1.0 + rng.gen_range(-jitter..=jitter));
+        }
+    }
+    due.sort();
+    due
+}
+
+/// Name virtual host `index` submits under
+pub fn fleet_hostname(index: usize) -> String {
+    format!("fleet-host-{:05}", index)
+}
+
+/// How one protocol kept up with the fleet
+#[derive(Debug, Clone)]
+pub struct FleetResult {
+    pub protocol: String,
+    /// Submissions the schedule called for
+    pub due: usize,
+    pub submitted: u64,
+    pub failures: FailureBreakdown,
+    /// From when each submission was due to its response, so time spent
+    /// waiting behind a slow protocol counts against it
+    pub latency_ms: Option<Summary>,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    /// Most submissions awaiting a response at once
+    pub peak_in_flight: usize,
+    /// From the first submission's due time to the last response
+    pub elapsed: Duration,
+}
+
+impl FleetResult {
+    /// Successful submissions per second
+    pub fn achieved_rate(&self) -> f64 {
+        self.submitted as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Every protocol's `FleetResult`, in `protocol_clients` order
+#[derive(Debug, Clone)]
+pub struct FleetReport {
+    pub settings: FleetSettings,
+    pub results: Vec<FleetResult>,
+}
+
+/// Run the fleet against every protocol in turn, each starting from empty
+/// services. Submissions go out when they're due whether or not earlier ones
+/// have been answered, as real hosts' would, so a protocol that falls
+/// behind builds up requests in flight rather than slowing the fleet down.
+/// Services are emptied again afterwards.
+pub async fn run(settings: FleetSettings) -> anyhow::Result<FleetReport> {
+    let due = schedule(&settings);
+    let mut report = FleetReport { settings, results: Vec::new() };
+    for (name, client) in protocol_clients() {
+        purge_all_services().await?;
+        report.results.push(drive(&name, client.as_ref(), &due, settings.seed).await);
+    }
+    purge_all_services().await?;
+    Ok(report)
+}
+
+async fn drive(protocol: &str, client: &dyn ProtocolClient, due: &[(Duration, usize)], seed: u64) -> FleetResult {
+    let mut points = TestDataGenerator::new().seed(seed).iter(usize::MAX);
+    let mut pending = FuturesUnordered::new();
+    let mut latencies_ms = Vec::with_capacity(due.len());
+    let mut failures = FailureBreakdown::default();
+    let mut peak_in_flight = 0;
+    let mut next = 0;
+    let start = Instant::now();
+
+    loop {
+        let next_due = due.get(next).map(|&(offset, _)| start + offset);
+        if next_due.is_none() && pending.is_empty() {
+            break;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep_until(next_due.unwrap_or(start)), if next_due.is_some() => {
+                let scheduled = start + due[next].0;
+                let point = host_point(&mut points, due[next].1);
+                pending.push(async move {
+                    let result: Result<(), ProtocolError> = client.submit_metric(point).await;
+                    (result, scheduled.elapsed())
+                });
+                peak_in_flight = peak_in_flight.max(pending.len());
+                next += 1;
+            }
+            Some((result, latency)) = pending.next(), if !pending.is_empty() => match result {
+                Ok(()) => latencies_ms.push(latency.as_secs_f64() * 1000.0),
+                Err(e) => failures.record(&e),
+            },
+        }
+    }
+
+    let elapsed = start.elapsed().saturating_sub(due.first().map_or(Duration::ZERO, |&(offset, _)| offset));
+    let latency_ms = Summary::of(&latencies_ms);
+    latencies_ms.sort_by(f64::total_cmp);
+    FleetResult {
+        protocol: protocol.to_string(),
+        due: due.len(),
+        submitted: latencies_ms.len() as u64,
+        failures,
+        latency_ms,
+        p50_ms: percentile(&latencies_ms, 50.0),
+        p99_ms: percentile(&latencies_ms, 99.0),
+        max_ms: latencies_ms.last().copied().unwrap_or(0.0),
+        peak_in_flight,
+        elapsed,
+    }
+}
+
+/// The next generated point, as host `host` reporting now
+fn host_point(points: &mut impl Iterator<Item = MetricPoint>, host: usize) -> MetricPoint {
+    let mut point = points.next().expect("test data never runs out");
+    point.hostname = fleet_hostname(host);
+    point.timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs() as i64);
+    point
+}
+
+impl FleetReport {
+    /// One `fleet_submit` result per protocol over `hosts` points, with the
+    /// mean latency and its confidence interval; sizes and memory are 0
+    pub fn to_run(&self) -> BenchmarkRun {
+        let mut run = BenchmarkRun::new();
+        for result in &self.results {
+            let Some(latency) = result.latency_ms else { continue };
+            let ns = |ms: f64| (ms * 1_000_000.0).max(0.0) as u64;
+            run.push(OperationResult {
+                protocol: result.protocol.clone(),
+                operation: FLEET_OPERATION.to_string(),
+                points: self.settings.hosts,
+                latency_ns: ns(latency.mean),
+                latency_ci_ns: Some([ns(latency.lower()), ns(latency.upper())]),
+                time_to_first_byte_ns: None,
+                request_bytes: 0,
+                response_bytes: 0,
+                memory_allocated: 0,
+                cpu_cycles: 0,
+                energy_uj: None,
+                payload_bytes: None,
+                payload_estimated: false,
+                server_time_ns: None,
+                server_allocated_bytes: None,
+            });
+        }
+        run
+    }
+}
+
+impl fmt::Display for FleetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} hosts every {:?} ± {:.0}%, {:.1} submissions/s offered for {:?}",
+            self.settings.hosts,
+            self.settings.interval,
+            self.settings.jitter.clamp(0.0, MAX_JITTER) * 100.0,
+            self.settings.offered_rate(),
+            self.settings.duration
+        )?;
+        writeln!(
+            f,
+            "  {:<24} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>9}  Failures",
+            "Protocol", "Due", "OK", "Rate (/s)", "Mean (ms)", "p50 (ms)", "p99 (ms)", "Max (ms)", "In flight"
+        )?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "  {:<24} {:>8} {:>8} {:>10.1} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>9}  {}",
+                result.protocol,
+                result.due,
+                result.submitted,
+                result.achieved_rate(),
+                result.latency_ms.map_or(0.0, |latency| latency.mean),
+                result.p50_ms,
+                result.p99_ms,
+                result.max_ms,
+                result.peak_in_flight,
+                result.failures,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
-use shared::{MetricPoint as SharedMetricPoint, MetricQuery as SharedMetricQuery, MetricStatistics as SharedMetricStatistics};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use prost::Message;
+use shared::{AuthConfig, HttpCompression, PayloadCipher, SealedGrpc, MetricPoint as SharedMetricPoint, MetricQuery as SharedMetricQuery, MetricRollup as SharedMetricRollup, MetricStatistics as SharedMetricStatistics, StorageStats as SharedStorageStats};
 use std::sync::OnceLock;
-use tonic::transport::Channel;
+use std::time::{Duration, Instant};
+use tonic::codec::CompressionEncoding;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity, Uri};
+use tonic_health::pb::{health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest};
 
 pub mod metrics {
     tonic::include_proto!("protobench.metrics");
@@ -8,84 +13,568 @@ pub mod metrics {
 
 use metrics::{
     metrics_service_client::MetricsServiceClient,
-    MetricPoint, MetricQuery
+    Empty, MetricBatch, MetricPoint, MetricQuery
 };
 
-static CLIENT: OnceLock<MetricsServiceClient<Channel>> = OnceLock::new();
+// Fully-qualified name `grpc-service` registers with its health service
+const METRICS_SERVICE_NAME: &str = "protobench.metrics.MetricsService";
 
-async fn get_client() -> anyhow::Result<&'static MetricsServiceClient<Channel>> {
+use crate::connection::{self, ConnectionTimings};
+use crate::report::PayloadOperation;
+use crate::wire_bytes::{CountingStream, WireCounter};
+use crate::ProtocolError;
+
+const PROTOCOL: &str = "gRPC";
+
+/// Bytes exchanged with `grpc-service`, counted on the TCP stream under TLS
+pub static WIRE_BYTES: WireCounter = WireCounter::new();
+
+/// Generated client over a channel that seals each message when
+/// `PROTOBENCH_PAYLOAD_KEY` is set, and passes it through otherwise
+type Client = MetricsServiceClient<SealedGrpc<Channel>>;
+
+static CLIENT: OnceLock<Client> = OnceLock::new();
+static AUTH: OnceLock<Option<AuthConfig>> = OnceLock::new();
+static TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+static ACCEPT_ENCODING: OnceLock<HttpCompression> = OnceLock::new();
+static MAX_MESSAGE_BYTES: OnceLock<Option<usize>> = OnceLock::new();
+
+/// Deadline sent with every request, from `PROTOBENCH_GRPC_TIMEOUT_MS`, then
+/// the uniform `PROTOBENCH_CLIENT_TIMEOUT_MS` (unset means none). The server
+/// enforces it and answers `CANCELLED`, or `DEADLINE_EXCEEDED` once a stream
+/// has started; both come back as `ProtocolError::Timeout`.
+pub fn request_timeout() -> Option<Duration> {
+    *TIMEOUT.get_or_init(|| {
+        std::env::var("PROTOBENCH_GRPC_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis)
+            .or_else(crate::client_timeout)
+    })
+}
+
+/// Sort a gRPC status into its `ProtocolError` category. tonic's server
+/// answers `CANCELLED` with "Timeout expired" when the deadline passes before
+/// the handler returns; on the client, a broken connection comes back as
+/// `UNAVAILABLE` and a message prost can't decode as `INTERNAL`.
+fn status_error(status: tonic::Status) -> ProtocolError {
+    match status.code() {
+        tonic::Code::DeadlineExceeded => ProtocolError::Timeout { protocol: PROTOCOL },
+        tonic::Code::Cancelled if status.message() == "Timeout expired" => ProtocolError::Timeout { protocol: PROTOCOL },
+        tonic::Code::Unavailable => ProtocolError::connect(PROTOCOL, status),
+        tonic::Code::Internal if status.message().starts_with("failed to decode") => ProtocolError::decode(PROTOCOL, status),
+        code => ProtocolError::Server {
+            protocol: PROTOCOL,
+            code: format!("{:?}", code),
+            message: status.message().to_string(),
+        },
+    }
+}
+
+/// Wrap a message in a request carrying `authorization: Bearer <token>` when
+/// `PROTOBENCH_AUTH_TOKEN` is set, a `grpc-timeout` deadline when
+/// `PROTOBENCH_GRPC_TIMEOUT_MS` is, and `traceparent` when the caller is
+/// traced. Every request gets a new `x-request-id`.
+fn new_request<T>(message: T) -> Result<tonic::Request<T>, ProtocolError> {
+    let mut request = tonic::Request::new(message);
+    request
+        .metadata_mut()
+        .insert(shared::REQUEST_ID, shared::next_request_id().parse().map_err(|e| ProtocolError::serialize(PROTOCOL, e))?);
+    if let Some(auth) = AUTH.get_or_init(AuthConfig::from_env) {
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {}", auth.token).parse().map_err(|e| ProtocolError::serialize(PROTOCOL, e))?);
+    }
+    if let Some(traceparent) = shared::current_traceparent() {
+        request
+            .metadata_mut()
+            .insert(shared::TRACEPARENT, traceparent.parse().map_err(|e| ProtocolError::serialize(PROTOCOL, e))?);
+    }
+    if let Some(timeout) = request_timeout() {
+        request.set_timeout(timeout);
+    }
+    Ok(request)
+}
+
+/// PEM certificate to trust, from `PROTOBENCH_GRPC_CA_CERT`. Setting it
+/// switches the channel to TLS; point it at the certificate `grpc-service`
+/// generated to trust a self-signed setup.
+pub fn ca_cert_path() -> Option<String> {
+    std::env::var("PROTOBENCH_GRPC_CA_CERT").ok()
+}
+
+/// Client certificate and key presented for mutual TLS, from
+/// `PROTOBENCH_GRPC_CLIENT_CERT` and `PROTOBENCH_GRPC_CLIENT_KEY`
+fn client_identity_paths() -> Option<(String, String)> {
+    let cert_path = std::env::var("PROTOBENCH_GRPC_CLIENT_CERT").ok()?;
+    let key_path = std::env::var("PROTOBENCH_GRPC_CLIENT_KEY").ok()?;
+    Some((cert_path, key_path))
+}
+
+/// Whether the channel uses TLS
+pub fn uses_tls() -> bool {
+    ca_cert_path().is_some()
+}
+
+/// Whether the channel also presents a client certificate
+pub fn uses_mtls() -> bool {
+    uses_tls() && client_identity_paths().is_some()
+}
+
+fn tls_config(ca_cert_path: &str) -> Result<ClientTlsConfig, ProtocolError> {
+    let read = |path: &str| std::fs::read(path).map_err(|e| ProtocolError::connect(PROTOCOL, e));
+    let mut config = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(read(ca_cert_path)?));
+    if let Some((cert_path, key_path)) = client_identity_paths() {
+        config = config.identity(Identity::from_pem(read(&cert_path)?, read(&key_path)?));
+    }
+    Ok(config)
+}
+
+/// Server addresses, from `PROTOBENCH_GRPC_TARGET`, then `targets.grpc` in
+/// `protobench.toml` (default `127.0.0.1:50051`). A comma-separated list
+/// balances requests across replicas.
+pub fn targets() -> Vec<String> {
+    let targets = shared::target_addr(
+        "PROTOBENCH_GRPC_TARGET",
+        shared::FileConfig::get().targets.grpc.as_deref(),
+        "127.0.0.1:50051",
+    );
+    targets
+        .split(',')
+        .map(str::trim)
+        .filter(|target| !target.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn endpoint(target: &str) -> Result<Endpoint, ProtocolError> {
+    // The server name checked against the certificate is the target's host
+    let scheme = if uses_tls() { "https" } else { "http" };
+    let endpoint = Channel::from_shared(format!("{}://{}", scheme, target)).map_err(|e| ProtocolError::connect(PROTOCOL, e))?;
+    match ca_cert_path() {
+        Some(path) => endpoint.tls_config(tls_config(&path)?).map_err(|e| ProtocolError::connect(PROTOCOL, e)),
+        None => Ok(endpoint),
+    }
+}
+
+async fn connect() -> Result<Channel, ProtocolError> {
+    connect_to(&targets()).await
+}
+
+/// Channel to one target, or one balancing requests across several with
+/// tonic's `balance_list`. A balanced channel connects with tonic's own
+/// connector, so its traffic isn't counted in `WIRE_BYTES` and its hosts are
+/// looked up as usual whatever `DnsConfig` says.
+pub async fn connect_to(targets: &[String]) -> Result<Channel, ProtocolError> {
+    match targets {
+        [] => Err(ProtocolError::connect(PROTOCOL, anyhow::anyhow!("no target configured"))),
+        [target] => connect_counted(endpoint(target)?).await,
+        _ => {
+            let endpoints = targets.iter().map(|target| endpoint(target)).collect::<Result<Vec<_>, _>>()?;
+            Ok(Channel::balance_list(endpoints.into_iter()))
+        }
+    }
+}
+
+async fn connect_counted(endpoint: Endpoint) -> Result<Channel, ProtocolError> {
+    // Plain TCP with bytes counted in WIRE_BYTES, to the addresses DnsConfig
+    // gives; tonic layers TLS over it
+    let connector = tower::service_fn(|uri: Uri| async move {
+        let authority = uri
+            .authority()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "gRPC target has no host"))?;
+        let stream = connection::connect_tcp(authority.as_str()).await?;
+        // What tonic's own connector does
+        stream.set_nodelay(true)?;
+        Ok::<_, std::io::Error>(CountingStream::new(stream, &WIRE_BYTES))
+    });
+    
+    endpoint
+        .connect_with_connector(connector)
+        .await
+        .map_err(|e| ProtocolError::connect(PROTOCOL, e))
+}
+
+/// Encodings the client accepts for responses, from
+/// `PROTOBENCH_GRPC_ACCEPT_ENCODING` (e.g. `gzip,zstd`; unset means identity).
+/// `br` is ignored since gRPC has no brotli codec.
+pub fn accept_encoding() -> HttpCompression {
+    *ACCEPT_ENCODING.get_or_init(|| HttpCompression::from_env("PROTOBENCH_GRPC_ACCEPT_ENCODING"))
+}
+
+/// Advertise `compression` in `grpc-accept-encoding` and compress requests
+/// with the first enabled encoding, which the server must accept too
+fn with_compression(client: Client, compression: HttpCompression) -> Client {
+    let encodings: Vec<CompressionEncoding> = [(compression.gzip, CompressionEncoding::Gzip), (compression.zstd, CompressionEncoding::Zstd)]
+        .into_iter()
+        .filter_map(|(enabled, encoding)| enabled.then_some(encoding))
+        .collect();
+    
+    let mut client = client;
+    for encoding in &encodings {
+        client = client.accept_compressed(*encoding);
+    }
+    if let Some(encoding) = encodings.first() {
+        client = client.send_compressed(*encoding);
+    }
+    client
+}
+
+/// Message size limit in both directions, from
+/// `PROTOBENCH_GRPC_MAX_MESSAGE_BYTES` like the service's, so responses past
+/// tonic's 4 MiB default can be read when the service sends them
+fn with_message_limit(client: Client) -> Client {
+    match *MAX_MESSAGE_BYTES.get_or_init(|| shared::max_message_bytes("PROTOBENCH_GRPC_MAX_MESSAGE_BYTES")) {
+        Some(limit) => client.max_decoding_message_size(limit).max_encoding_message_size(limit),
+        None => client,
+    }
+}
+
+fn new_client(channel: Channel) -> Client {
+    MetricsServiceClient::new(SealedGrpc::client(channel, PayloadCipher::get().cloned()))
+}
+
+/// Shared channel with the configured response compression
+async fn client() -> Result<Client, ProtocolError> {
+    Ok(with_compression(get_client().await?.clone(), accept_encoding()))
+}
+
+async fn get_client() -> Result<&'static Client, ProtocolError> {
     if let Some(client) = CLIENT.get() {
         return Ok(client);
     }
     
-    let channel = Channel::from_static("http://127.0.0.1:50051").connect().await?;
-    let client = MetricsServiceClient::new(channel);
+    let client = with_message_limit(new_client(connect().await?));
     
-    CLIENT.set(client).map_err(|_| anyhow::anyhow!("Failed to set client"))?;
-    Ok(CLIENT.get().unwrap())
+    // Another caller may have connected meanwhile; either channel will do
+    Ok(CLIENT.get_or_init(|| client))
 }
 
-pub async fn submit_metric(metric: SharedMetricPoint) -> anyhow::Result<()> {
-    let mut client = get_client().await?.clone();
-    
-    // Convert shared metric to protobuf metric
-    let proto_metric = MetricPoint {
+/// Ask the standard gRPC health service whether `MetricsService` is serving
+pub async fn check_health() -> Result<(), ProtocolError> {
+    check_health_at(&targets()).await
+}
+
+/// `check_health` against every one of `targets`
+pub async fn check_health_at(targets: &[String]) -> Result<(), ProtocolError> {
+    for target in targets {
+        // A fresh channel each time, so a service that isn't up yet is retried
+        // rather than cached as a failed connection
+        let mut client = HealthClient::new(connect_to(std::slice::from_ref(target)).await?);
+        let request = HealthCheckRequest { service: METRICS_SERVICE_NAME.to_string() };
+        let status = client.check(request).await.map_err(status_error)?.into_inner().status();
+        
+        if status != ServingStatus::Serving {
+            return Err(ProtocolError::Server {
+                protocol: PROTOCOL,
+                code: format!("{:?}", status),
+                message: format!("health check for {} says it isn't serving", target),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Poll `check_health` until the service is serving or `timeout` has passed,
+/// then fail with the last check's error
+pub async fn wait_until_serving(timeout: Duration) -> Result<(), ProtocolError> {
+    wait_until_serving_at(&targets(), timeout).await
+}
+
+/// `wait_until_serving` for every one of `targets`
+pub async fn wait_until_serving_at(targets: &[String], timeout: Duration) -> Result<(), ProtocolError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let error = match check_health_at(targets).await {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        if Instant::now() >= deadline {
+            return Err(error);
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+pub fn to_proto_metric(metric: SharedMetricPoint) -> MetricPoint {
+    MetricPoint {
         timestamp: metric.timestamp,
         hostname: metric.hostname,
         cpu_percent: metric.cpu_percent,
         memory_bytes: metric.memory_bytes,
         disk_io_ops: metric.disk_io_ops,
         tags: metric.tags,
-    };
+    }
+}
+
+pub fn from_proto_metric(metric: MetricPoint) -> SharedMetricPoint {
+    SharedMetricPoint {
+        timestamp: metric.timestamp,
+        hostname: metric.hostname,
+        cpu_percent: metric.cpu_percent,
+        memory_bytes: metric.memory_bytes,
+        disk_io_ops: metric.disk_io_ops,
+        tags: metric.tags,
+    }
+}
+
+pub fn to_proto_query(query: SharedMetricQuery) -> MetricQuery {
+    MetricQuery {
+        start_time: query.start_time,
+        end_time: query.end_time,
+        hostname_filter: query.hostname_filter,
+        limit: query.limit,
+        offset: query.offset,
+    }
+}
+
+pub fn to_proto_statistics(stats: SharedMetricStatistics) -> metrics::MetricStatistics {
+    metrics::MetricStatistics {
+        count: stats.count,
+        avg_cpu_percent: stats.avg_cpu_percent,
+        avg_memory_bytes: stats.avg_memory_bytes,
+        avg_disk_io_ops: stats.avg_disk_io_ops,
+        time_range_seconds: stats.time_range_seconds,
+    }
+}
+
+pub fn to_proto_rollup(rollup: SharedMetricRollup) -> metrics::MetricRollup {
+    metrics::MetricRollup {
+        hostname: rollup.hostname,
+        minute_start: rollup.minute_start,
+        count: rollup.count,
+        avg_cpu_percent: rollup.avg_cpu_percent,
+        min_cpu_percent: rollup.min_cpu_percent,
+        max_cpu_percent: rollup.max_cpu_percent,
+        avg_memory_bytes: rollup.avg_memory_bytes,
+        min_memory_bytes: rollup.min_memory_bytes,
+        max_memory_bytes: rollup.max_memory_bytes,
+        avg_disk_io_ops: rollup.avg_disk_io_ops,
+        min_disk_io_ops: rollup.min_disk_io_ops,
+        max_disk_io_ops: rollup.max_disk_io_ops,
+    }
+}
+
+pub fn to_proto_metric_v2(metric: shared::MetricPointV2) -> metrics::MetricPointV2 {
+    metrics::MetricPointV2 {
+        timestamp: metric.timestamp,
+        hostname: metric.hostname,
+        per_core_cpu_percent: metric.per_core_cpu_percent,
+        memory_bytes: metric.memory_bytes,
+        disk_io_ops: metric.disk_io_ops,
+        latency_histogram: metric
+            .latency_histogram
+            .into_iter()
+            .map(|bucket| metrics::HistogramBucket { upper_bound_ms: bucket.upper_bound_ms, count: bucket.count })
+            .collect(),
+        processes: metric
+            .processes
+            .into_iter()
+            .map(|process| metrics::ProcessInfo {
+                pid: process.pid,
+                name: process.name,
+                cpu_percent: process.cpu_percent,
+                memory_bytes: process.memory_bytes,
+                args: process.args,
+            })
+            .collect(),
+        tags: metric.tags,
+    }
+}
+
+pub fn from_proto_metric_v2(metric: metrics::MetricPointV2) -> shared::MetricPointV2 {
+    shared::MetricPointV2 {
+        timestamp: metric.timestamp,
+        hostname: metric.hostname,
+        per_core_cpu_percent: metric.per_core_cpu_percent,
+        memory_bytes: metric.memory_bytes,
+        disk_io_ops: metric.disk_io_ops,
+        latency_histogram: metric
+            .latency_histogram
+            .into_iter()
+            .map(|bucket| shared::HistogramBucket { upper_bound_ms: bucket.upper_bound_ms, count: bucket.count })
+            .collect(),
+        processes: metric
+            .processes
+            .into_iter()
+            .map(|process| shared::ProcessInfo {
+                pid: process.pid,
+                name: process.name,
+                cpu_percent: process.cpu_percent,
+                memory_bytes: process.memory_bytes,
+                args: process.args,
+            })
+            .collect(),
+        tags: metric.tags,
+    }
+}
+
+pub async fn submit_metric(metric: SharedMetricPoint) -> Result<(), ProtocolError> {
+    let mut client = client().await?;
     
-    let request = tonic::Request::new(proto_metric);
-    client.submit_metric(request).await?;
+    let request = new_request(to_proto_metric(metric))?;
+    client.submit_metric(request).await.map_err(status_error)?;
     
     Ok(())
 }
 
-pub async fn query_metrics(query: SharedMetricQuery) -> anyhow::Result<Vec<SharedMetricPoint>> {
-    let mut client = get_client().await?.clone();
+/// Send `metrics` over one client stream; the server stores them as a single
+/// batch and returns how many points were written
+pub async fn submit_metric_stream(metrics: Vec<SharedMetricPoint>) -> Result<u64, ProtocolError> {
+    let mut client = client().await?;
+
+    let proto_metrics = metrics.into_iter().map(to_proto_metric);
+
+    let request = new_request(futures_util::stream::iter(proto_metrics))?;
+    let summary = client.submit_metrics(request).await.map_err(status_error)?.into_inner();
+
+    Ok(summary.accepted)
+}
+
+/// Send `metrics` in one `SubmitMetricBatch` message; the server stores them
+/// all-or-nothing and returns how many points were written
+pub async fn submit_metrics(metrics: Vec<SharedMetricPoint>) -> Result<u64, ProtocolError> {
+    submit_batch_with(client().await?, metrics).await
+}
+
+/// `submit_metrics` over `channel`, such as one from `connect_to`
+pub async fn submit_metrics_with_channel(channel: &Channel, metrics: Vec<SharedMetricPoint>) -> Result<u64, ProtocolError> {
+    submit_batch_with(with_compression(with_message_limit(new_client(channel.clone())), accept_encoding()), metrics).await
+}
+
+async fn submit_batch_with(mut client: Client, metrics: Vec<SharedMetricPoint>) -> Result<u64, ProtocolError> {
+    let proto_metrics = metrics
+        .into_iter()
+        .map(to_proto_metric)
+        .collect();
     
-    // Convert shared query to protobuf query
-    let proto_query = MetricQuery {
-        start_time: query.start_time,
-        end_time: query.end_time,
-        hostname_filter: query.hostname_filter,
-    };
+    let request = new_request(MetricBatch { metrics: proto_metrics })?;
+    let summary = client.submit_metric_batch(request).await.map_err(status_error)?.into_inner();
     
-    let request = tonic::Request::new(proto_query);
-    let mut stream = client.query_metrics(request).await?.into_inner();
-    
-    let mut metrics = Vec::new();
-    while let Some(metric) = stream.message().await? {
-        // Convert protobuf metric back to shared metric
-        let shared_metric = SharedMetricPoint {
-            timestamp: metric.timestamp,
-            hostname: metric.hostname,
-            cpu_percent: metric.cpu_percent,
-            memory_bytes: metric.memory_bytes,
-            disk_io_ops: metric.disk_io_ops,
-            tags: metric.tags,
-        };
-        metrics.push(shared_metric);
-    }
+    Ok(summary.accepted)
+}
+
+pub async fn query_metrics(query: SharedMetricQuery) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    let proto_query = to_proto_query(query);
     
-    Ok(metrics)
+    collect_query(client().await?, new_request(proto_query)?).await
 }
 
-pub async fn get_statistics(query: SharedMetricQuery) -> anyhow::Result<SharedMetricStatistics> {
-    let mut client = get_client().await?.clone();
+/// `query_metrics` with an explicit deadline in place of
+/// `PROTOBENCH_GRPC_TIMEOUT_MS`; expiry is reported as `ProtocolError::Timeout`
+pub async fn query_metrics_with_timeout(query: SharedMetricQuery, timeout: Duration) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    let proto_query = to_proto_query(query);
+    
+    let mut request = new_request(proto_query)?;
+    request.set_timeout(timeout);
+    collect_query(client().await?, request).await
+}
+
+/// `query_metrics` accepting exactly the encodings in `compression`, in place
+/// of `PROTOBENCH_GRPC_ACCEPT_ENCODING`; the server compresses the stream
+/// only if it has one of them enabled
+pub async fn query_metrics_with_compression(query: SharedMetricQuery, compression: HttpCompression) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    let proto_query = to_proto_query(query);
+    
+    let client = with_compression(get_client().await?.clone(), compression);
+    collect_query(client, new_request(proto_query)?).await
+}
+
+/// `query_metrics` yielding each point as its message arrives instead of
+/// collecting them, for time-to-first-point measurements and for consuming
+/// large results in bounded memory. Dropping the stream cancels the call.
+pub async fn query_metrics_stream(query: SharedMetricQuery) -> Result<impl Stream<Item = Result<SharedMetricPoint, ProtocolError>>, ProtocolError> {
+    let proto_query = to_proto_query(query);
+    
+    open_query(client().await?, new_request(proto_query)?).await
+}
+
+/// Send a `QueryMetrics` request and convert each streamed message
+async fn open_query(
+    mut client: Client,
+    request: tonic::Request<MetricQuery>,
+) -> Result<impl Stream<Item = Result<SharedMetricPoint, ProtocolError>>, ProtocolError> {
+    let stream = client.query_metrics(request).await.map_err(status_error)?.into_inner();
+    
+    Ok(stream.map(|message| {
+        let metric = message.map_err(status_error)?;
+        Ok(from_proto_metric(metric))
+    }))
+}
+
+/// Send a `QueryMetrics` request and gather the whole stream
+async fn collect_query(
+    client: Client,
+    request: tonic::Request<MetricQuery>,
+) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    open_query(client, request).await?.try_collect().await
+}
+
+/// Same result as `query_metrics`, fetched with `QueryMetricsUnary` as one
+/// response message instead of a server stream
+pub async fn query_metrics_unary(query: SharedMetricQuery) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+    let mut client = client().await?;
+    
+    let proto_query = to_proto_query(query);
+    
+    let request = new_request(proto_query)?;
+    let response = client.query_metrics_unary(request).await.map_err(status_error)?;
+    
+    Ok(response.into_inner().metrics.into_iter().map(from_proto_metric).collect())
+}
+
+/// Open a `Subscribe` stream for `query`, submit `metrics` with unary calls,
+/// and wait until every matching point has been pushed back. Returns the
+/// number of points received.
+pub async fn tail_metrics(query: SharedMetricQuery, metrics: Vec<SharedMetricPoint>) -> Result<usize, ProtocolError> {
+    let mut client = client().await?;
     
-    // Convert shared query to protobuf query
     let proto_query = MetricQuery {
         start_time: query.start_time,
         end_time: query.end_time,
-        hostname_filter: query.hostname_filter,
+        hostname_filter: query.hostname_filter.clone(),
+        limit: None,
+        offset: None,
     };
     
-    let request = tonic::Request::new(proto_query);
-    let response = client.get_statistics(request).await?;
+    // A single query; the server keeps the subscription open after the
+    // request stream ends
+    let request = new_request(futures_util::stream::iter(vec![proto_query]))?;
+    let mut stream = client.subscribe(request).await.map_err(status_error)?.into_inner();
+    
+    let expected = metrics.iter().filter(|metric| query.matches(metric)).count();
+    
+    let submit = async {
+        for metric in metrics {
+            submit_metric(metric).await?;
+        }
+        Ok::<(), ProtocolError>(())
+    };
+    
+    let receive = async {
+        let mut received = 0;
+        while received < expected {
+            if stream.message().await.map_err(status_error)?.is_none() {
+                let closed = anyhow::anyhow!("subscription closed after {} of {} points", received, expected);
+                return Err(ProtocolError::connect(PROTOCOL, closed));
+            }
+            received += 1;
+        }
+        Ok(received)
+    };
+    
+    let ((), received) = tokio::try_join!(submit, receive)?;
+    Ok(received)
+}
+
+pub async fn get_statistics(query: SharedMetricQuery) -> Result<SharedMetricStatistics, ProtocolError> {
+    let mut client = client().await?;
+    
+    let proto_query = to_proto_query(query);
+    
+    let request = new_request(proto_query)?;
+    let response = client.get_statistics(request).await.map_err(status_error)?;
     let stats = response.into_inner();
     
     // Convert protobuf statistics back to shared statistics
@@ -98,4 +587,154 @@ pub async fn get_statistics(query: SharedMetricQuery) -> anyhow::Result<SharedMe
     };
     
     Ok(shared_stats)
-}
\ No newline at end of file
+}
+pub async fn query_rollups(query: SharedMetricQuery) -> Result<Vec<SharedMetricRollup>, ProtocolError> {
+    let mut client = client().await?;
+    
+    let proto_query = to_proto_query(query);
+    
+    let request = new_request(proto_query)?;
+    let response = client.query_rollups(request).await.map_err(status_error)?;
+    
+    // Convert protobuf rollups back to shared rollups
+    let rollups = response
+        .into_inner()
+        .rollups
+        .into_iter()
+        .map(|rollup| SharedMetricRollup {
+            hostname: rollup.hostname,
+            minute_start: rollup.minute_start,
+            count: rollup.count,
+            avg_cpu_percent: rollup.avg_cpu_percent,
+            min_cpu_percent: rollup.min_cpu_percent,
+            max_cpu_percent: rollup.max_cpu_percent,
+            avg_memory_bytes: rollup.avg_memory_bytes,
+            min_memory_bytes: rollup.min_memory_bytes,
+            max_memory_bytes: rollup.max_memory_bytes,
+            avg_disk_io_ops: rollup.avg_disk_io_ops,
+            min_disk_io_ops: rollup.min_disk_io_ops,
+            max_disk_io_ops: rollup.max_disk_io_ops,
+        })
+        .collect();
+    
+    Ok(rollups)
+}
+
+pub async fn delete_metrics(query: SharedMetricQuery) -> Result<u64, ProtocolError> {
+    let mut client = client().await?;
+    
+    let proto_query = to_proto_query(query);
+    
+    let request = new_request(proto_query)?;
+    let response = client.delete_metrics(request).await.map_err(status_error)?;
+    
+    Ok(response.into_inner().deleted)
+}
+
+pub async fn get_storage_stats() -> Result<SharedStorageStats, ProtocolError> {
+    storage_stats_with(client().await?).await
+}
+
+/// `get_storage_stats` over `channel`, such as one from `connect_to`
+pub async fn get_storage_stats_with_channel(channel: &Channel) -> Result<SharedStorageStats, ProtocolError> {
+    storage_stats_with(with_compression(with_message_limit(new_client(channel.clone())), accept_encoding())).await
+}
+
+async fn storage_stats_with(mut client: Client) -> Result<SharedStorageStats, ProtocolError> {
+    let response = client.get_storage_stats(new_request(Empty {})?).await.map_err(status_error)?;
+    let stats = response.into_inner();
+    
+    Ok(SharedStorageStats {
+        point_count: stats.point_count,
+        approx_heap_bytes: stats.approx_heap_bytes,
+        rollup_bucket_count: stats.rollup_bucket_count,
+        rollup_heap_bytes: stats.rollup_heap_bytes,
+    })
+}
+
+/// Time a new channel: `connect_to` makes the TCP connection, TLS and the
+/// HTTP/2 handshake, then `GetStorageStats` goes over it twice. A channel
+/// balanced across several targets connects on its first request instead,
+/// so setup is counted either way.
+pub async fn connection_timings() -> Result<ConnectionTimings, ProtocolError> {
+    let targets = targets();
+    let target = targets.first().ok_or_else(|| ProtocolError::connect(PROTOCOL, anyhow::anyhow!("no target configured")))?;
+    let connect_error = |e| ProtocolError::connect(PROTOCOL, e);
+    let (addrs, dns_lookup) = connection::lookup(target).await.map_err(connect_error)?;
+    let socket_connect = connection::tcp_connect_time(&addrs).await.map_err(connect_error)?;
+    let (channel, first_request) = connection::timed(async {
+        let channel = connect_to(&targets).await?;
+        get_storage_stats_with_channel(&channel).await?;
+        Ok::<_, ProtocolError>(channel)
+    })
+    .await;
+    let channel = channel?;
+    let (warm, warm_request) = connection::timed(get_storage_stats_with_channel(&channel)).await;
+    warm?;
+    Ok(ConnectionTimings { dns_lookup, socket_connect, first_request, warm_request })
+}
+
+/// Bytes `metrics` take as protobuf: one `MetricBatch` for a submit, or the
+/// `MetricPoint` messages a query streams back, without gRPC's message prefixes
+pub fn payload_bytes(operation: PayloadOperation, metrics: &[SharedMetricPoint]) -> usize {
+    let proto_metrics = metrics.iter().cloned().map(to_proto_metric);
+    match operation {
+        PayloadOperation::Submit => MetricBatch { metrics: proto_metrics.collect() }.encoded_len(),
+        PayloadOperation::Query => proto_metrics.map(|metric| metric.encoded_len()).sum(),
+    }
+}
+
+/// The gRPC client as a `shared::MetricsService` and `ProtocolClient`
+pub struct GrpcClient;
+
+#[async_trait::async_trait(?Send)]
+impl shared::MetricsService for GrpcClient {
+    type Error = ProtocolError;
+
+    async fn submit_metric(&self, metric: SharedMetricPoint) -> Result<(), ProtocolError> {
+        submit_metric(metric).await
+    }
+
+    async fn query_metrics(&self, query: SharedMetricQuery) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+        query_metrics(query).await
+    }
+
+    async fn get_statistics(&self, query: SharedMetricQuery) -> Result<SharedMetricStatistics, ProtocolError> {
+        get_statistics(query).await
+    }
+
+    async fn delete_metrics(&self, query: SharedMetricQuery) -> Result<u64, ProtocolError> {
+        delete_metrics(query).await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl crate::ProtocolClient for GrpcClient {
+    async fn submit_batch(&self, metrics: Vec<SharedMetricPoint>) -> Result<u64, ProtocolError> {
+        submit_metrics(metrics).await
+    }
+
+    async fn query_rollups(&self, query: SharedMetricQuery) -> Result<Vec<SharedMetricRollup>, ProtocolError> {
+        query_rollups(query).await
+    }
+
+    async fn query_with_timeout(&self, query: SharedMetricQuery, timeout: Duration) -> Result<Vec<SharedMetricPoint>, ProtocolError> {
+        query_metrics_with_timeout(query, timeout).await
+    }
+
+    fn wire_bytes(&self) -> &'static WireCounter {
+        &WIRE_BYTES
+    }
+
+    fn payload_bytes(&self, operation: PayloadOperation, metrics: &[SharedMetricPoint]) -> usize {
+        payload_bytes(operation, metrics)
+    }
+
+    fn metrics_exporter_env(&self) -> &'static str {
+        "PROTOBENCH_GRPC_METRICS_ADDR"
+    }
+
+    async fn connection_timings(&self) -> Result<ConnectionTimings, ProtocolError> {
+        connection_timings().await
+    }
+}
@@ -0,0 +1,104 @@
+use shared::MetricPoint;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sysinfo::{Disks, System};
+
+/// Where Linux reports block device I/O counters
+pub const DISKSTATS_PATH: &str = "/proc/diskstats";
+
+/// Reads and writes completed on whole disks in `/proc/diskstats` text.
+/// `is_disk` picks the devices counted, so partitions aren't counted twice.
+pub fn completed_disk_ops(diskstats: &str, is_disk: impl Fn(&str) -> bool) -> u64 {
+    diskstats
+        .lines()
+        .filter_map(|line| {
+            // major minor name reads_completed reads_merged sectors_read
+            // ms_reading writes_completed ...
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 || !is_disk(fields[2]) {
+                return None;
+            }
+            Some(fields[3].parse::<u64>().ok()? + fields[7].parse::<u64>().ok()?)
+        })
+        .sum()
+}
+
+/// Whether `/sys/block` lists `device` as a real disk rather than a
+/// partition, loop device or RAM disk
+fn is_block_disk(device: &str) -> bool {
+    !device.starts_with("loop") && !device.starts_with("ram") && Path::new("/sys/block").join(device).exists()
+}
+
+/// Samples this machine's CPU, memory and disk activity as `MetricPoint`s,
+/// for `agent` to submit like a real metrics collector would
+pub struct HostSampler {
+    system: System,
+    hostname: String,
+    /// Disk operations completed at the last sample, where they can be read
+    last_disk_ops: Option<u64>,
+}
+
+impl HostSampler {
+    /// A sampler with CPU usage and disk counters primed, so the first
+    /// `sample` already covers the time since this call
+    pub fn new() -> Self {
+        let mut system = System::new();
+        system.refresh_cpu();
+        Self {
+            system,
+            hostname: System::host_name().filter(|name| !name.is_empty()).unwrap_or_else(|| "localhost".to_string()),
+            last_disk_ops: Self::disk_ops(),
+        }
+    }
+
+    fn disk_ops() -> Option<u64> {
+        let diskstats = std::fs::read_to_string(DISKSTATS_PATH).ok()?;
+        Some(completed_disk_ops(&diskstats, is_block_disk))
+    }
+
+    /// One point for now: overall CPU use since the last sample, memory in
+    /// use, and disk reads and writes completed since the last sample (0
+    /// where `/proc/diskstats` can't be read). Tags carry the OS, core count,
+    /// 1-minute load average and how full the disks are.
+    pub fn sample(&mut self) -> MetricPoint {
+        self.system.refresh_cpu();
+        self.system.refresh_memory();
+
+        let disk_ops = Self::disk_ops();
+        let disk_io_ops = match (self.last_disk_ops, disk_ops) {
+            (Some(last), Some(now)) => now.saturating_sub(last).min(u32::MAX as u64) as u32,
+            _ => 0,
+        };
+        self.last_disk_ops = disk_ops;
+
+        let mut tags = HashMap::new();
+        tags.insert("source".to_string(), "protobench-agent".to_string());
+        tags.insert("os".to_string(), std::env::consts::OS.to_string());
+        tags.insert("cores".to_string(), self.system.cpus().len().to_string());
+        tags.insert("load_1m".to_string(), format!("{:.2}", System::load_average().one));
+        let disks = Disks::new_with_refreshed_list();
+        let (total, available) = disks
+            .list()
+            .iter()
+            .fold((0u64, 0u64), |(total, available), disk| (total + disk.total_space(), available + disk.available_space()));
+        if total > 0 {
+            tags.insert("disk_used_percent".to_string(), format!("{:.1}", (total - available) as f64 * 100.0 / total as f64));
+        }
+
+        MetricPoint {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs() as i64),
+            hostname: self.hostname.clone(),
+            cpu_percent: self.system.global_cpu_info().cpu_usage(),
+            memory_bytes: self.system.used_memory(),
+            disk_io_ops,
+            tags,
+        }
+    }
+}
+
+impl Default for HostSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,14 +1,11 @@
-use rand::prelude::*;
-use rand::rngs::StdRng;
-use shared::MetricPoint;
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH, Duration, Instant};
-use stats_alloc::{StatsAlloc, INSTRUMENTED_SYSTEM};
-use std::alloc::System;
+use shared::{AuthConfig, BodyEncoding, CapnpEncoding, MetricPoint, MetricQuery, MetricRollup, MetricsService, PayloadCipher, StorageStats};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
 
 // Use instrumented allocator for memory tracking
 #[global_allocator]
-static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+static GLOBAL: shared::CountingAllocator = shared::CountingAllocator::new();
 
 // Generated Cap'n Proto code
 #[allow(clippy::needless_lifetimes)]
@@ -19,22 +16,168 @@ pub mod metrics_capnp {
 pub mod rest_client;
 pub mod grpc_client;
 pub mod capnp_client;
+pub mod backpressure;
+pub mod capabilities;
+pub mod capacity;
+pub mod check;
+pub mod cancellation;
+pub mod circuit_breaker;
+pub mod connection;
+pub mod criterion_results;
+pub mod diff;
+pub mod dictionary;
+pub mod distributed;
+pub mod energy;
+pub mod error;
+pub mod export;
+pub mod fleet;
+pub mod host_metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod orchestrator;
+pub mod render;
+pub mod report;
+pub mod resource_usage;
+pub mod results;
+pub mod runner;
+pub mod runtime_flavor;
+pub mod server_stats;
+pub mod soak;
+pub mod statistics;
+pub mod verify;
+pub mod wire_bytes;
+
+pub use error::{FailureBreakdown, ProtocolError};
+pub use report::ComparisonReport;
+
+static CLIENT_TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+
+/// Per-request timeout every client applies, from `PROTOBENCH_CLIENT_TIMEOUT_MS`
+/// (unset means none): a reqwest timeout for REST, a deadline for gRPC and a
+/// timer around each call's promise for Cap'n Proto
+pub fn client_timeout() -> Option<Duration> {
+    *CLIENT_TIMEOUT.get_or_init(|| {
+        std::env::var("PROTOBENCH_CLIENT_TIMEOUT_MS")
+            .ok()
+            .and_then(|ms| ms.parse().ok())
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis)
+    })
+}
+
+/// A protocol client as the benchmark groups drive it: the
+/// `shared::MetricsService` calls plus the batch and rollup operations every
+/// protocol here offers, so groups loop over `protocol_clients()` instead of
+/// repeating one block per protocol
+#[async_trait::async_trait(?Send)]
+pub trait ProtocolClient: MetricsService<Error = ProtocolError> {
+    /// Store `metrics` in as few calls as the protocol allows, returning how
+    /// many were stored
+    async fn submit_batch(&self, metrics: Vec<MetricPoint>) -> Result<u64, ProtocolError>;
+    async fn query_rollups(&self, query: MetricQuery) -> Result<Vec<MetricRollup>, ProtocolError>;
+    /// `query_metrics` with `timeout` in place of `client_timeout()`
+    async fn query_with_timeout(&self, query: MetricQuery, timeout: Duration) -> Result<Vec<MetricPoint>, ProtocolError>;
+    /// Bytes this client has put on and taken off the wire
+    fn wire_bytes(&self) -> &'static wire_bytes::WireCounter;
+    /// Bytes `metrics` take in this client's own serialization as the points
+    /// `operation` carries, uncompressed and without any framing
+    fn payload_bytes(&self, operation: report::PayloadOperation, metrics: &[MetricPoint]) -> usize;
+    /// Variable naming the service's Prometheus exporter address; the harness
+    /// reads it too, to scrape a service started with the same environment
+    fn metrics_exporter_env(&self) -> &'static str;
+    /// Open a new connection of this client's own, as a freshly started
+    /// client would, and time its setup against a request once it is warm
+    async fn connection_timings(&self) -> Result<connection::ConnectionTimings, ProtocolError>;
+}
+
+/// Every protocol client, labelled with the name used for its benchmark IDs.
+/// Labels carry the active variant (e.g. `REST[msgpack,tls,gzip,relay,auth]`,
+/// `gRPC[mtls]` or `CapnProto[packed]`) so re-encoded, encrypted, compressed or authenticated runs
+/// are recorded as separate results.
+pub fn protocol_clients() -> Vec<(String, Box<dyn ProtocolClient>)> {
+    let auth = AuthConfig::from_env().is_some();
+    
+    let encoding = rest_client::accept_encoding();
+    let mut rest_variant = Vec::new();
+    let body_encoding = rest_client::body_encoding();
+    if body_encoding != BodyEncoding::Json {
+        rest_variant.push(body_encoding.label().to_string());
+    }
+    if rest_client::uses_tls() {
+        rest_variant.push("tls".to_string());
+    }
+    if encoding.is_enabled() {
+        rest_variant.push(encoding.label());
+    }
+    if rest_client::counts_wire_bytes() {
+        rest_variant.push("relay".to_string());
+    }
+    
+    let mut common_variant: Vec<String> = if auth { vec!["auth".to_string()] } else { Vec::new() };
+    if PayloadCipher::get().is_some() {
+        common_variant.push("sealed".to_string());
+    }
+    rest_variant.extend(common_variant.iter().cloned());
+    
+    let mut grpc_variant = Vec::new();
+    if grpc_client::uses_mtls() {
+        grpc_variant.push("mtls".to_string());
+    } else if grpc_client::uses_tls() {
+        grpc_variant.push("tls".to_string());
+    }
+    let grpc_encoding = grpc_client::accept_encoding();
+    if grpc_encoding.is_enabled() {
+        grpc_variant.push(grpc_encoding.label());
+    }
+    grpc_variant.extend(common_variant.iter().cloned());
+    
+    let mut capnp_variant = Vec::new();
+    if capnp_client::uses_uds() {
+        capnp_variant.push("uds".to_string());
+    }
+    let capnp_encoding = capnp_client::encoding();
+    if capnp_encoding != CapnpEncoding::default() {
+        capnp_variant.push(capnp_encoding.label().to_string());
+    }
+    capnp_variant.extend(common_variant.iter().cloned());
+    
+    vec![
+        (variant_label("REST", &rest_variant), Box::new(rest_client::RestClient)),
+        (variant_label("gRPC", &grpc_variant), Box::new(grpc_client::GrpcClient)),
+        (variant_label("CapnProto", &capnp_variant), Box::new(capnp_client::CapnpClient)),
+    ]
+}
+
+fn variant_label(protocol: &str, variant: &[String]) -> String {
+    if variant.is_empty() {
+        protocol.to_string()
+    } else {
+        format!("{}[{}]", protocol, variant.join(","))
+    }
+}
 
 /// Comprehensive performance metrics for benchmarking
 #[derive(Debug, Clone)]
 pub struct BenchmarkMetrics {
     pub latency: Duration,           // Time taken for operation
-    pub payload_size: PayloadSizes,  // Bytes sent/received
+    pub payload_size: PayloadSizes,  // Bytes sent/received on the socket
     pub memory_allocated: usize,     // Heap allocations during operation
     pub cpu_cycles: u64,             // CPU cycles (approximated via timing)
+    /// Time until the first point of a streamed response arrived, the
+    /// earliest the caller can act on it; `None` for operations that return
+    /// their whole result at once
+    pub time_to_first_byte: Option<Duration>,
 }
 
 /// Payload size breakdown for request and response
 #[derive(Debug, Clone)]
 pub struct PayloadSizes {
-    pub request_bytes: usize,     // Serialized request size
-    pub response_bytes: usize,    // Serialized response size
+    pub request_bytes: usize,     // Bytes sent for the request
+    pub response_bytes: usize,    // Bytes received for the response
     pub total_bytes: usize,       // Total network traffic
+    /// Worked out by a size estimator such as
+    /// `payload_measurement::measure_capnp_metric_size` rather than counted
+    pub estimated: bool,
 }
 
 impl PayloadSizes {
@@ -43,21 +186,56 @@ impl PayloadSizes {
             request_bytes,
             response_bytes,
             total_bytes: request_bytes + response_bytes,
+            estimated: false,
         }
     }
+    
+    /// Sizes from an estimator, which reports flag as estimates
+    pub fn estimated(request_bytes: usize, response_bytes: usize) -> Self {
+        Self { estimated: true, ..Self::new(request_bytes, response_bytes) }
+    }
+    
+    /// Bytes actually written (request) and read (response) on the socket
+    pub fn from_wire(bytes: wire_bytes::WireBytes) -> Self {
+        Self::new(bytes.written as usize, bytes.read as usize)
+    }
 }
 
-/// Measure memory allocations during a closure execution
+/// Measure the bytes a closure allocates on the calling thread. Other
+/// threads allocating meanwhile, such as runtime workers, aren't counted.
 pub fn measure_memory<T, F>(f: F) -> (T, usize)
 where
     F: FnOnce() -> T,
 {
-    let start_stats = GLOBAL.stats();
+    let start = shared::thread_allocated_bytes();
     let result = f();
-    let end_stats = GLOBAL.stats();
     
-    let bytes_allocated = end_stats.bytes_allocated - start_stats.bytes_allocated;
-    (result, bytes_allocated)
+    (result, shared::thread_allocated_bytes().wrapping_sub(start))
+}
+
+/// `measure_memory` for a future: the bytes allocated while it is being
+/// polled, from its first poll until it completes, on whichever threads
+/// those polls run. Other tasks running in between aren't counted, and
+/// neither are tasks it spawns, such as a connection's driver. It is awaited
+/// in place rather than blocked on, so it works on either runtime flavor.
+pub async fn measure_memory_async<T>(future: impl std::future::Future<Output = T>) -> (T, usize) {
+    let mut future = std::pin::pin!(future);
+    let mut allocated = 0usize;
+    let result = std::future::poll_fn(|cx| {
+        let start = shared::thread_allocated_bytes();
+        let poll = std::future::Future::poll(future.as_mut(), cx);
+        allocated = allocated.wrapping_add(shared::thread_allocated_bytes().wrapping_sub(start));
+        poll
+    })
+    .await;
+
+    (result, allocated)
+}
+
+/// Heap bytes the process holds right now, across every thread
+pub fn heap_in_use() -> usize {
+    let stats = GLOBAL.stats();
+    (stats.bytes_allocated as isize - stats.bytes_deallocated as isize + stats.bytes_reallocated).max(0) as usize
 }
 
 /// Estimate CPU cycles based on high-resolution timing
@@ -68,9 +246,20 @@ pub fn estimate_cpu_cycles(duration: Duration) -> u64 {
     (duration.as_nanos() as u64 * APPROXIMATE_CPU_HZ) / 1_000_000_000
 }
 
-/// Measure payload sizes for different serialization formats
+/// Sizes of a value as each protocol serializes it, each encoded with the
+/// protocol's own message types rather than assumed to match JSON
 pub trait PayloadMeasurement {
+    /// Bytes as JSON, REST's default body encoding
     fn measure_payload_size(&self) -> usize;
+
+    /// Bytes as the protobuf message gRPC carries it in, without gRPC's
+    /// 5-byte message prefix
+    fn measure_grpc_payload_size(&self) -> usize;
+
+    /// Bytes as the Cap'n Proto message carrying it, serialized the way
+    /// `encoding` puts it on the wire, segment table included but not the
+    /// RPC envelope
+    fn measure_capnp_payload_size(&self, encoding: CapnpEncoding) -> usize;
 }
 
 impl PayloadMeasurement for shared::MetricPoint {
@@ -78,24 +267,72 @@ impl PayloadMeasurement for shared::MetricPoint {
         // JSON size (what REST uses)
         serde_json::to_vec(self).map(|v| v.len()).unwrap_or(0)
     }
+
+    fn measure_grpc_payload_size(&self) -> usize {
+        payload_measurement::measure_grpc_metric_size(self)
+    }
+
+    fn measure_capnp_payload_size(&self, encoding: CapnpEncoding) -> usize {
+        payload_measurement::measure_capnp_metric_wire_size(self, encoding).unwrap_or(0)
+    }
 }
 
 impl PayloadMeasurement for shared::MetricQuery {
     fn measure_payload_size(&self) -> usize {
         serde_json::to_vec(self).map(|v| v.len()).unwrap_or(0)
     }
+
+    fn measure_grpc_payload_size(&self) -> usize {
+        payload_measurement::measure_grpc_query_size(self)
+    }
+
+    fn measure_capnp_payload_size(&self, encoding: CapnpEncoding) -> usize {
+        payload_measurement::measure_capnp_query_wire_size(self, encoding).unwrap_or(0)
+    }
 }
 
+/// A query's results: `MetricPointList` for gRPC, as `QueryMetricsUnary`
+/// returns them, and the `queryMetrics` results for Cap'n Proto
 impl PayloadMeasurement for Vec<shared::MetricPoint> {
     fn measure_payload_size(&self) -> usize {
         serde_json::to_vec(self).map(|v| v.len()).unwrap_or(0)
     }
+
+    fn measure_grpc_payload_size(&self) -> usize {
+        payload_measurement::measure_grpc_metrics_size(self)
+    }
+
+    fn measure_capnp_payload_size(&self, encoding: CapnpEncoding) -> usize {
+        payload_measurement::measure_capnp_metrics_wire_size(self, encoding).unwrap_or(0)
+    }
+}
+
+impl PayloadMeasurement for Vec<shared::MetricRollup> {
+    fn measure_payload_size(&self) -> usize {
+        serde_json::to_vec(self).map(|v| v.len()).unwrap_or(0)
+    }
+
+    fn measure_grpc_payload_size(&self) -> usize {
+        payload_measurement::measure_grpc_rollups_size(self)
+    }
+
+    fn measure_capnp_payload_size(&self, encoding: CapnpEncoding) -> usize {
+        payload_measurement::measure_capnp_rollups_wire_size(self, encoding).unwrap_or(0)
+    }
 }
 
 impl PayloadMeasurement for shared::MetricStatistics {
     fn measure_payload_size(&self) -> usize {
         serde_json::to_vec(self).map(|v| v.len()).unwrap_or(0)
     }
+
+    fn measure_grpc_payload_size(&self) -> usize {
+        payload_measurement::measure_grpc_statistics_size(self)
+    }
+
+    fn measure_capnp_payload_size(&self, encoding: CapnpEncoding) -> usize {
+        payload_measurement::measure_capnp_statistics_wire_size(self, encoding).unwrap_or(0)
+    }
 }
 
 /// Helper functions for measuring protocol-specific payload sizes
@@ -104,28 +341,36 @@ pub mod payload_measurement {
 
     /// Measure gRPC protobuf payload size
     pub fn measure_grpc_metric_size(metric: &shared::MetricPoint) -> usize {
-        let proto_metric = crate::grpc_client::metrics::MetricPoint {
-            timestamp: metric.timestamp,
-            hostname: metric.hostname.clone(),
-            cpu_percent: metric.cpu_percent,
-            memory_bytes: metric.memory_bytes,
-            disk_io_ops: metric.disk_io_ops,
-            tags: metric.tags.clone(),
-        };
-        proto_metric.encoded_len()
+        crate::grpc_client::to_proto_metric(metric.clone()).encoded_len()
     }
 
     /// Measure gRPC protobuf query size
     pub fn measure_grpc_query_size(query: &shared::MetricQuery) -> usize {
-        let proto_query = crate::grpc_client::metrics::MetricQuery {
-            start_time: query.start_time,
-            end_time: query.end_time,
-            hostname_filter: query.hostname_filter.clone(),
-        };
-        proto_query.encoded_len()
+        crate::grpc_client::to_proto_query(query.clone()).encoded_len()
+    }
+
+    /// gRPC protobuf size of `metrics` as one `MetricPointList`
+    pub fn measure_grpc_metrics_size(metrics: &[shared::MetricPoint]) -> usize {
+        let metrics = metrics.iter().cloned().map(crate::grpc_client::to_proto_metric).collect();
+        crate::grpc_client::metrics::MetricPointList { metrics }.encoded_len()
+    }
+
+    /// gRPC protobuf size of `rollups` as one `MetricRollupList`
+    pub fn measure_grpc_rollups_size(rollups: &[shared::MetricRollup]) -> usize {
+        let rollups = rollups.iter().cloned().map(crate::grpc_client::to_proto_rollup).collect();
+        crate::grpc_client::metrics::MetricRollupList { rollups }.encoded_len()
+    }
+
+    /// gRPC protobuf size of `stats`
+    pub fn measure_grpc_statistics_size(stats: &shared::MetricStatistics) -> usize {
+        crate::grpc_client::to_proto_statistics(stats.clone()).encoded_len()
     }
 
-    /// Measure Cap'n Proto payload size (estimated based on schema)
+    /// Legacy estimate of a Cap'n Proto metric's size from its field lengths,
+    /// which results recorded before wire counting used. It leaves out
+    /// padding and list and pointer words, so it undercounts; kept so those
+    /// results can be set against `measure_capnp_metric_wire_size`, as
+    /// `compare_capnp_metric_sizes` does.
     pub fn measure_capnp_metric_size(metric: &shared::MetricPoint) -> usize {
         // Cap'n Proto has fixed overhead + variable string lengths
         // Fixed: 8+4+8+4 = 24 bytes for primitives
@@ -137,6 +382,122 @@ pub mod payload_measurement {
         24 + hostname_len + tags_len + 32 // 32 bytes Cap'n Proto overhead
     }
 
+    /// Exact size of a Cap'n Proto `queryMetrics` response carrying `metrics`,
+    /// serialized the way `encoding` puts it on the wire (excluding the RPC
+    /// envelope)
+    pub fn measure_capnp_metrics_wire_size(
+        metrics: &[shared::MetricPoint],
+        encoding: shared::CapnpEncoding,
+    ) -> capnp::Result<usize> {
+        use crate::metrics_capnp::metrics_service::query_metrics_results;
+        
+        let mut message = capnp::message::Builder::new_default();
+        let results = message.init_root::<query_metrics_results::Builder>();
+        let mut list_builder = results.init_metrics(metrics.len() as u32);
+        for (i, metric) in metrics.iter().enumerate() {
+            crate::capnp_client::write_metric(metric, list_builder.reborrow().get(i as u32));
+        }
+        
+        serialized_size(&message, encoding)
+    }
+
+    /// Exact size of a Cap'n Proto `queryRollups` response carrying
+    /// `rollups`, serialized the way `encoding` puts it on the wire
+    pub fn measure_capnp_rollups_wire_size(
+        rollups: &[shared::MetricRollup],
+        encoding: shared::CapnpEncoding,
+    ) -> capnp::Result<usize> {
+        use crate::metrics_capnp::metrics_service::query_rollups_results;
+
+        let mut message = capnp::message::Builder::new_default();
+        let results = message.init_root::<query_rollups_results::Builder>();
+        let mut list_builder = results.init_rollups(rollups.len() as u32);
+        for (i, rollup) in rollups.iter().enumerate() {
+            crate::capnp_client::write_rollup(rollup, list_builder.reborrow().get(i as u32));
+        }
+
+        serialized_size(&message, encoding)
+    }
+
+    /// Exact size of a Cap'n Proto `getStatistics` response carrying
+    /// `stats`, serialized the way `encoding` puts it on the wire
+    pub fn measure_capnp_statistics_wire_size(
+        stats: &shared::MetricStatistics,
+        encoding: shared::CapnpEncoding,
+    ) -> capnp::Result<usize> {
+        use crate::metrics_capnp::metrics_service::get_statistics_results;
+
+        let mut message = capnp::message::Builder::new_default();
+        let results = message.init_root::<get_statistics_results::Builder>();
+        crate::capnp_client::write_statistics(stats, results.init_statistics());
+        serialized_size(&message, encoding)
+    }
+
+    /// Exact size of `query` serialized alone as a Cap'n Proto message the
+    /// way `encoding` puts it on the wire, segment table included
+    pub fn measure_capnp_query_wire_size(
+        query: &shared::MetricQuery,
+        encoding: shared::CapnpEncoding,
+    ) -> capnp::Result<usize> {
+        let mut message = capnp::message::Builder::new_default();
+        crate::capnp_client::write_query(query, message.init_root::<crate::metrics_capnp::metric_query::Builder>());
+        serialized_size(&message, encoding)
+    }
+
+    /// Exact size of `metric` serialized alone as a Cap'n Proto message the
+    /// way `encoding` puts it on the wire, segment table included
+    pub fn measure_capnp_metric_wire_size(
+        metric: &shared::MetricPoint,
+        encoding: shared::CapnpEncoding,
+    ) -> capnp::Result<usize> {
+        let mut message = capnp::message::Builder::new_default();
+        crate::capnp_client::write_metric(metric, message.init_root::<crate::metrics_capnp::metric_point::Builder>());
+        serialized_size(&message, encoding)
+    }
+    
+    fn serialized_size(
+        message: &capnp::message::Builder<capnp::message::HeapAllocator>,
+        encoding: shared::CapnpEncoding,
+    ) -> capnp::Result<usize> {
+        Ok(match encoding {
+            shared::CapnpEncoding::Unpacked => capnp::serialize::compute_serialized_size_in_words(message) * 8,
+            shared::CapnpEncoding::Packed => {
+                let mut buffer = Vec::new();
+                capnp::serialize_packed::write_message(&mut buffer, message)?;
+                buffer.len()
+            }
+        })
+    }
+    
+    /// Legacy estimates and exact sizes of the same metrics, added up
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SizeComparison {
+        pub estimated_bytes: usize,
+        pub actual_bytes: usize,
+    }
+    
+    impl SizeComparison {
+        /// What to multiply an estimate by to get the size on the wire, for
+        /// reinterpreting results recorded with the estimator
+        pub fn correction(&self) -> f64 {
+            self.actual_bytes as f64 / self.estimated_bytes.max(1) as f64
+        }
+    }
+    
+    /// `measure_capnp_metric_size` against `measure_capnp_metric_wire_size`
+    /// over every metric in `metrics`
+    pub fn compare_capnp_metric_sizes(
+        metrics: &[shared::MetricPoint],
+        encoding: shared::CapnpEncoding,
+    ) -> capnp::Result<SizeComparison> {
+        let mut sizes = SizeComparison { estimated_bytes: 0, actual_bytes: 0 };
+        for metric in metrics {
+            sizes.estimated_bytes += measure_capnp_metric_size(metric);
+            sizes.actual_bytes += measure_capnp_metric_wire_size(metric, encoding)?;
+        }
+        Ok(sizes)
+    }
+    
     /// Measure Cap'n Proto query size
     pub fn measure_capnp_query_size(query: &shared::MetricQuery) -> usize {
         let hostname_len = query.hostname_filter.as_ref().map(|s| s.len()).unwrap_or(0);
@@ -144,76 +505,131 @@ pub mod payload_measurement {
     }
 }
 
-/// Comprehensive benchmark wrapper that measures all metrics
+/// Comprehensive benchmark wrapper that measures all metrics. Payload sizes
+/// are the bytes `wire` saw on the socket while `f` ran, so other traffic at
+/// the same time is counted too. Memory is only what `f`'s future allocated
+/// while being polled (see `measure_memory_async`). `f` runs inside a span
+/// named after the operation, which the clients propagate when tracing is
+/// on, so the service's spans nest under it.
 pub async fn benchmark_operation<T, F, Fut>(
-    _operation_name: &str,
-    request_payload_size: usize,
+    operation_name: &str,
+    wire: &wire_bytes::WireCounter,
     f: F,
 ) -> (T, BenchmarkMetrics)
 where
     F: FnOnce() -> Fut,
     Fut: std::future::Future<Output = T>,
-    T: PayloadMeasurement,
 {
     let start_time = Instant::now();
+    let wire_before = wire.snapshot();
     
-    let (result, memory_allocated) = measure_memory(|| {
-        tokio::runtime::Handle::current().block_on(f())
-    });
+    // Awaited rather than `block_on`, which panics inside the caller's runtime
+    let (result, memory_allocated) =
+        measure_memory_async(f().instrument(tracing::info_span!("benchmark_operation", operation = operation_name))).await;
     
     let latency = start_time.elapsed();
     let cpu_cycles = estimate_cpu_cycles(latency);
     
-    let response_payload_size = result.measure_payload_size();
-    let payload_size = PayloadSizes::new(request_payload_size, response_payload_size);
+    let payload_size = PayloadSizes::from_wire(wire.snapshot() - wire_before);
     
     let metrics = BenchmarkMetrics {
         latency,
         payload_size,
         memory_allocated,
         cpu_cycles,
+        time_to_first_byte: None,
     };
     
     (result, metrics)
 }
 
-pub fn generate_test_data(count: usize) -> Vec<MetricPoint> {
-    let mut rng = StdRng::seed_from_u64(42); // Deterministic for consistent benchmarks
-    let mut metrics = Vec::with_capacity(count);
-    
-    let hostnames = [
-        "web-01", "web-02", "db-primary", "db-replica", "cache-01", 
-        "api-gateway", "worker-01", "worker-02", "monitoring", "load-balancer"
-    ];
-    
-    let environments = ["prod", "staging", "dev"];
-    let regions = ["us-east", "us-west", "eu-central", "ap-southeast"];
-    let services = ["frontend", "backend", "database", "cache", "queue"];
+/// `benchmark_operation` for a query whose points arrive as a stream, such as
+/// `rest_client::query_metrics_stream` or `grpc_client::query_metrics_stream`.
+/// The stream is drained without keeping the points; latency runs until the
+/// last one and `time_to_first_byte` until the first. Returns how many points
+/// arrived.
+pub async fn benchmark_streaming_query<S, F, Fut>(
+    operation_name: &str,
+    wire: &wire_bytes::WireCounter,
+    f: F,
+) -> (Result<usize, ProtocolError>, BenchmarkMetrics)
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<S, ProtocolError>>,
+    S: futures_util::Stream<Item = Result<MetricPoint, ProtocolError>>,
+{
+    use futures_util::StreamExt;
     
-    let base_timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
+    let start_time = Instant::now();
+    let mut first_point = None;
+    let (result, mut metrics) = benchmark_operation(operation_name, wire, || async {
+        let mut points = std::pin::pin!(f().await?);
+        let mut count = 0;
+        while let Some(point) = points.next().await {
+            point?;
+            if count == 0 {
+                first_point = Some(start_time.elapsed());
+            }
+            count += 1;
+        }
+        Ok(count)
+    })
+    .await;
     
-    for i in 0..count {
-        let mut tags = HashMap::new();
-        tags.insert("env".to_string(), environments.choose(&mut rng).unwrap().to_string());
-        tags.insert("region".to_string(), regions.choose(&mut rng).unwrap().to_string());
-        tags.insert("service".to_string(), services.choose(&mut rng).unwrap().to_string());
-        tags.insert("version".to_string(), format!("v{}.{}.{}", 
-            rng.gen_range(1..3), rng.gen_range(0..10), rng.gen_range(0..5)));
-        
-        let metric = MetricPoint {
-            timestamp: base_timestamp - rng.gen_range(0..3600) + (i as i64), // Spread over last hour
-            hostname: hostnames.choose(&mut rng).unwrap().to_string(),
-            cpu_percent: rng.gen_range(5.0..95.0), // Realistic CPU usage
-            memory_bytes: rng.gen_range(1_000_000_000..16_000_000_000), // 1GB to 16GB
-            disk_io_ops: rng.gen_range(100..10_000), // Reasonable I/O operations
-            tags,
-        };
-        
-        metrics.push(metric);
+    metrics.time_to_first_byte = first_point;
+    (result, metrics)
+}
+
+/// Submit `points` in batches of `batch_size`, pulling each batch from the
+/// iterator only when it is sent, so a run can drive millions of points from
+/// `generate_test_data_iter` without holding them in memory. Returns how many
+/// points the service stored.
+pub async fn submit_in_batches(
+    client: &dyn ProtocolClient,
+    mut points: impl Iterator<Item = MetricPoint>,
+    batch_size: usize,
+) -> Result<u64, ProtocolError> {
+    let mut stored = 0;
+    loop {
+        let batch: Vec<MetricPoint> = points.by_ref().take(batch_size.max(1)).collect();
+        if batch.is_empty() {
+            return Ok(stored);
+        }
+        stored += client.submit_batch(batch).await?;
     }
+}
+
+/// Delete every stored point on all three services so a benchmark phase
+/// starts from empty storage instead of whatever earlier phases left behind
+pub async fn purge_all_services() -> Result<(), ProtocolError> {
+    let everything = MetricQuery::all();
     
-    metrics
-}
\ No newline at end of file
+    for (_, client) in protocol_clients() {
+        client.delete_metrics(everything.clone()).await?;
+    }
+    Ok(())
+}
+
+// Points per batch when seeding; a few hundred bytes each keeps a batch far
+// below the default REST body limit
+const SEED_BATCH_SIZE: usize = 1000;
+
+/// Empty every service, then store `metrics` on each of them, e.g. a dataset
+/// read by `shared::load_dataset`
+pub async fn seed_all_services(metrics: &[MetricPoint]) -> Result<(), ProtocolError> {
+    purge_all_services().await?;
+    for (_, client) in protocol_clients() {
+        submit_in_batches(client.as_ref(), metrics.iter().cloned(), SEED_BATCH_SIZE).await?;
+    }
+    Ok(())
+}
+
+/// Fetch the server-side storage footprint from every service, so results can
+/// compare memory held per protocol for the same dataset
+pub async fn collect_storage_stats() -> Vec<(&'static str, Result<StorageStats, ProtocolError>)> {
+    vec![
+        ("REST", rest_client::get_storage_stats().await),
+        ("gRPC", grpc_client::get_storage_stats().await),
+        ("Cap'n Proto", capnp_client::get_storage_stats().await),
+    ]
+}
@@ -1,13 +1,62 @@
-use benchmarks::{generate_test_data, rest_client, grpc_client, capnp_client};
-use shared::MetricQuery;
+use benchmarks::energy::Rapl;
+use benchmarks::export::push_from_env;
+use benchmarks::report::{measure_framing, FramingReport, PayloadOperation};
+use benchmarks::results::{results_path_from_env, BenchmarkRun, OperationResult};
+use benchmarks::{
+    benchmark_operation, benchmark_streaming_query, capnp_client, collect_storage_stats, grpc_client,
+    payload_measurement, protocol_clients, purge_all_services, rest_client, seed_all_services, BenchmarkMetrics,
+    ComparisonReport, ProtocolError,
+};
+use shared::{dataset_from_env, generate_test_data, CapnpEncoding, MetricQuery};
+use std::time::Duration;
+
+// How long to wait for grpc-service to report SERVING, e.g. when it was
+// started alongside the harness
+const GRPC_READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Points the responsiveness comparison queries back
+const RESPONSIVENESS_POINTS: usize = 1000;
+
+// Requests each protocol makes while its energy is sampled, and how long
+// the idle draw they're compared against is sampled for
+const ENERGY_REQUESTS: usize = 1000;
+const IDLE_SAMPLE: Duration = Duration::from_secs(1);
+
+// Point counts the framing overhead table covers
+const FRAMING_POINT_COUNTS: [usize; 4] = [1, 10, 100, 1000];
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let _tracing_guard = shared::init_tracing_from_env("benchmarks")?;
+    // Every command lives in protobench; this binary is only the smoke pass
+    if let Some(command) = std::env::args().nth(1) {
+        anyhow::bail!("benchmarks takes no command; run `protobench {}` instead", command.trim_start_matches("--"));
+    }
+    
     println!("ProtoBench - Protocol Performance Comparison");
     println!("===========================================");
     
     // Run basic functionality tests
     test_protocols().await?;
+    let mut run = BenchmarkRun::new();
+    compare_protocols(&mut run).await;
+    compare_query_responsiveness(&mut run).await?;
+    if let Some(rapl) = Rapl::from_env()? {
+        compare_energy(&rapl, &mut run).await?;
+    }
+    report_framing_overhead().await?;
+    push_from_env(&run, "cli").await;
+    if let Some(path) = results_path_from_env() {
+        let mut stored = BenchmarkRun::open_or_new(&path)?;
+        stored.results.extend(run.results);
+        stored.write_to(&path)?;
+        println!("\nResults added to {}", path.display());
+    }
+    
+    if let Some(metrics) = dataset_from_env()? {
+        seed_all_services(&metrics).await?;
+        println!("\nSeeded every service with {} points from PROTOBENCH_DATASET", metrics.len());
+    }
     
     println!("\nProtocols working correctly!");
     println!("Run 'cargo bench' to execute performance benchmarks.");
@@ -25,6 +74,10 @@ async fn test_protocols() -> anyhow::Result<()> {
     }
     
     println!("Testing gRPC...");  
+    match grpc_client::wait_until_serving(GRPC_READY_TIMEOUT).await {
+        Ok(()) => println!("✅ gRPC health check: serving"),
+        Err(e) => println!("❌ gRPC health check failed: {}", e),
+    }
     match grpc_client::submit_metric(test_metric.clone()).await {
         Ok(()) => println!("✅ gRPC metric submitted successfully!"),
         Err(e) => println!("❌ gRPC failed: {}", e),
@@ -41,6 +94,8 @@ async fn test_protocols() -> anyhow::Result<()> {
         start_time: test_metric.timestamp - 3600,
         end_time: test_metric.timestamp + 3600,
         hostname_filter: Some(test_metric.hostname.clone()),
+        limit: None,
+        offset: None,
     };
     
     println!("\nTesting query operations...");
@@ -55,6 +110,184 @@ async fn test_protocols() -> anyhow::Result<()> {
         Err(e) => println!("❌ Cap'n Proto statistics failed: {}", e),
     }
     
+    match rest_client::negotiated_encoding().await {
+        Ok(encoding) => println!(
+            "✅ REST content encoding: {} (advertised: {})",
+            encoding, rest_client::accept_encoding().label()
+        ),
+        Err(e) => println!("❌ REST encoding negotiation failed: {}", e),
+    }
+    
+    let throttled = rest_client::throttled_responses();
+    if throttled > 0 {
+        println!("⚠️  REST responses throttled with 429: {}", throttled);
+    }
+    
+    // Sizes come from encoding locally, so they don't depend on which
+    // encoding the service was started with
+    let response_metrics = generate_test_data(100);
+    for encoding in CapnpEncoding::ALL {
+        match payload_measurement::measure_capnp_metrics_wire_size(&response_metrics, encoding) {
+            Ok(bytes) => println!(
+                "✅ Cap'n Proto {} query response: {} bytes for {} points{}",
+                encoding.label(), bytes, response_metrics.len(),
+                if encoding == capnp_client::encoding() { " (in use)" } else { "" }
+            ),
+            Err(e) => println!("❌ Cap'n Proto {} size measurement failed: {}", encoding.label(), e),
+        }
+        // What results recorded with the old estimator need scaling by
+        match payload_measurement::compare_capnp_metric_sizes(&response_metrics, encoding) {
+            Ok(sizes) => println!(
+                "   legacy estimate: {} bytes against {} serialized one point at a time (×{:.2} to correct)",
+                sizes.estimated_bytes, sizes.actual_bytes, sizes.correction()
+            ),
+            Err(e) => println!("❌ Cap'n Proto {} size comparison failed: {}", encoding.label(), e),
+        }
+    }
+    
+    println!("\nServer-side storage footprint...");
+    for (protocol, stats) in collect_storage_stats().await {
+        match stats {
+            Ok(stats) => println!(
+                "✅ {}: {} points, ~{} bytes heap, {} rollup buckets (~{} bytes)",
+                protocol, stats.point_count, stats.approx_heap_bytes,
+                stats.rollup_bucket_count, stats.rollup_heap_bytes
+            ),
+            Err(e) => println!("❌ {} storage stats failed: {}", protocol, e),
+        }
+    }
+    
     Ok(())
 }
 
+/// One submit_metric per protocol, ranked the way the benchmark groups rank
+/// their results
+async fn compare_protocols(run: &mut BenchmarkRun) {
+    let test_metric = generate_test_data(1)[0].clone();
+    let mut results = Vec::new();
+    for (name, client) in protocol_clients() {
+        let (result, metrics) =
+            benchmark_operation("submit_metric", client.wire_bytes(), || client.submit_metric(test_metric.clone())).await;
+        match result {
+            Ok(()) => results.push((name, metrics)),
+            Err(e) => println!("❌ {} left out of the comparison: {}", name, e),
+        }
+    }
+    
+    if let Some(report) = ComparisonReport::new(&results) {
+        println!("\n🔍 Efficiency Analysis (one submit_metric each):");
+        print!("{}", report.winners());
+    }
+    for (name, metrics) in &results {
+        run.push(OperationResult::new(name, "submit_metric", 1, metrics));
+    }
+}
+
+/// One large query per protocol and response style, so streamed responses'
+/// time to first point shows next to every style's completion time
+async fn compare_query_responsiveness(run: &mut BenchmarkRun) -> anyhow::Result<()> {
+    seed_all_services(&generate_test_data(RESPONSIVENESS_POINTS)).await?;
+    let query = MetricQuery::all();
+    
+    let mut results = Vec::new();
+    let mut record = |name: &str, result: Result<usize, ProtocolError>, metrics: BenchmarkMetrics| match result {
+        Ok(_) => results.push((name.to_string(), metrics)),
+        Err(e) => println!("❌ {} left out of the comparison: {}", name, e),
+    };
+    
+    let (result, metrics) =
+        benchmark_operation("query", &rest_client::WIRE_BYTES, || rest_client::query_metrics(query.clone())).await;
+    record("REST/json_array", result.map(|points| points.len()), metrics);
+    let (result, metrics) =
+        benchmark_streaming_query("query", &rest_client::WIRE_BYTES, || rest_client::query_metrics_stream(query.clone())).await;
+    record("REST/ndjson", result, metrics);
+    let (result, metrics) =
+        benchmark_streaming_query("query", &grpc_client::WIRE_BYTES, || grpc_client::query_metrics_stream(query.clone())).await;
+    record("gRPC/stream", result, metrics);
+    let (result, metrics) =
+        benchmark_operation("query", &capnp_client::WIRE_BYTES, || capnp_client::query_metrics(query.clone())).await;
+    record("CapnProto/list", result.map(|points| points.len()), metrics);
+    
+    if let Some(report) = ComparisonReport::new(&results) {
+        println!("\n🔍 Query Responsiveness ({} points each; first point only for streams):", RESPONSIVENESS_POINTS);
+        print!("{}", report.winners());
+    }
+    for (name, metrics) in &results {
+        run.push(OperationResult::new(name, "query", RESPONSIVENESS_POINTS, metrics));
+    }
+    Ok(())
+}
+
+/// `ENERGY_REQUESTS` sequential submit_metric calls per protocol with the
+/// machine's RAPL counters sampled around them, as joules per 1000 requests.
+/// The counters see the service and the harness alike, so the idle draw is
+/// measured first and what the requests added over it shown alongside.
+async fn compare_energy(rapl: &Rapl, run: &mut BenchmarkRun) -> anyhow::Result<()> {
+    let metrics = generate_test_data(ENERGY_REQUESTS);
+    purge_all_services().await?;
+    let ((), idle) = rapl.measure(tokio::time::sleep(IDLE_SAMPLE)).await?;
+    
+    println!(
+        "\n⚡ Energy ({} submit_metric calls each, RAPL {}, idle {:.1} W):",
+        ENERGY_REQUESTS,
+        rapl.zones().join("+"),
+        idle.watts()
+    );
+    for (name, client) in protocol_clients() {
+        let submit_all = benchmark_operation("submit_metric", client.wire_bytes(), || async {
+            for metric in &metrics {
+                client.submit_metric(metric.clone()).await?;
+            }
+            Ok::<_, ProtocolError>(())
+        });
+        let ((result, measured), energy) = rapl.measure(submit_all).await?;
+        if let Err(e) = result {
+            println!("❌ {} left out of the energy comparison: {}", name, e);
+            continue;
+        }
+        let above_idle = (energy.joules - idle.watts() * energy.elapsed.as_secs_f64()).max(0.0);
+        println!(
+            "  {:<24} {:>8.3} J per 1000 requests ({:.3} J above idle, {:.1} W over {:.2}s)",
+            name,
+            energy.joules_per_1000(ENERGY_REQUESTS),
+            above_idle * 1000.0 / ENERGY_REQUESTS as f64,
+            energy.watts(),
+            energy.elapsed.as_secs_f64()
+        );
+        run.push(OperationResult {
+            energy_uj: Some((energy.joules * 1_000_000.0).round() as u64),
+            ..OperationResult::new(&name, "submit_metric", ENERGY_REQUESTS, &measured)
+        });
+    }
+    purge_all_services().await?;
+    Ok(())
+}
+
+/// Wire bytes minus serialized points for every protocol, operation and point
+/// count, each measured on freshly emptied services
+async fn report_framing_overhead() -> anyhow::Result<()> {
+    let clients = protocol_clients();
+    let mut framing = FramingReport::new();
+    for count in FRAMING_POINT_COUNTS {
+        let metrics = generate_test_data(count);
+        purge_all_services().await?;
+        for (name, client) in &clients {
+            for operation in PayloadOperation::ALL {
+                match measure_framing(name, client.as_ref(), operation, &metrics).await {
+                    // REST bytes aren't counted without its relay
+                    Ok(row) if row.wire_total() == 0 => {}
+                    Ok(row) => framing.push(row),
+                    Err(e) => println!("❌ {} {} framing measurement failed: {}", name, operation.label(), e),
+                }
+            }
+        }
+    }
+    purge_all_services().await?;
+    
+    println!("\n📐 Framing overhead (wire bytes minus serialized points):");
+    print!("{}", framing);
+    if !rest_client::counts_wire_bytes() {
+        println!("  (REST left out: set PROTOBENCH_REST_WIRE_BYTES to count its bytes)");
+    }
+    Ok(())
+}
@@ -0,0 +1,686 @@
+//! In-process stand-ins for the three services, so client code can be tested
+//! for conversion, error mapping and retries without spawning the real
+//! binaries. Each mock listens on a free loopback port, keeps points in an
+//! `InMemoryStorage` and answers with the next queued failure instead
+//! whenever one is queued.
+//!
+//! The clients read their targets once per process, so point
+//! `PROTOBENCH_*_TARGET` at `addr()` before a client is first used and keep
+//! the mock alive for the rest of the process.
+
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use capnp::capability::Promise;
+use capnp_rpc::{pry, rpc_twoparty_capnp, twoparty, RpcSystem};
+use futures_util::io::{AsyncReadExt, BufReader};
+use shared::{BodyEncoding, CapnpEncoding, InMemoryStorage, MetricPoint, MetricQuery, MetricsStorage, PayloadCipher, SealedGrpc};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+use crate::grpc_client::metrics as proto;
+use crate::metrics_capnp::{metric_query, metrics_service};
+use crate::{capnp_client, grpc_client};
+
+/// Storage, queued failures and a call count, shared by a mock and its server
+struct MockState<F> {
+    storage: InMemoryStorage,
+    failures: Mutex<VecDeque<F>>,
+    calls: AtomicU64,
+}
+
+impl<F> MockState<F> {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            storage: InMemoryStorage::new(),
+            failures: Mutex::new(VecDeque::new()),
+            calls: AtomicU64::new(0),
+        })
+    }
+
+    fn push_failure(&self, failure: F) {
+        self.failures.lock().unwrap().push_back(failure);
+    }
+
+    /// Count a call and take the failure queued for it, if any
+    fn next_failure(&self) -> Option<F> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.failures.lock().unwrap().pop_front()
+    }
+}
+
+/// A canned HTTP response for `MockRest::respond_next`
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn new(status: StatusCode) -> Self {
+        Self { status, headers: HeaderMap::new(), body: Vec::new() }
+    }
+
+    /// `rest-service`'s JSON error body with `code` and `message`
+    pub fn error(status: StatusCode, code: &str, message: &str) -> Self {
+        let body = serde_json::json!({ "code": code, "message": message });
+        Self::new(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+    }
+
+    pub fn header(mut self, name: HeaderName, value: &str) -> Self {
+        self.headers.insert(name, HeaderValue::from_str(value).expect("invalid mock header value"));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+impl IntoResponse for MockResponse {
+    fn into_response(self) -> Response {
+        (self.status, self.headers, self.body).into_response()
+    }
+}
+
+/// `rest-service`'s routes over HTTP/2 prior knowledge, as `rest_client`
+/// speaks it, with bodies in whichever `BodyEncoding` each request names
+pub struct MockRest {
+    addr: SocketAddr,
+    state: Arc<MockState<MockResponse>>,
+    server: JoinHandle<()>,
+}
+
+impl MockRest {
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = MockState::new();
+
+        let app = Router::new()
+            .route("/metrics", post(rest_submit_metric).get(rest_query_metrics).delete(rest_delete_metrics))
+            .route("/metrics/batch", post(rest_submit_metrics))
+            .route("/statistics", get(rest_get_statistics))
+            .route("/rollups", get(rest_query_rollups))
+            .route("/admin/storage", get(rest_get_storage_stats))
+            .layer(middleware::from_fn_with_state(state.clone(), rest_scripted))
+            .with_state(state.clone());
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Ok(Self { addr, state, server })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Answer the next request with `response` instead of routing it
+    pub fn respond_next(&self, response: MockResponse) {
+        self.state.push_failure(response);
+    }
+
+    /// Requests received so far, answered from a queued response or not
+    pub fn calls(&self) -> u64 {
+        self.state.calls.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MockRest {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn rest_scripted(State(state): State<Arc<MockState<MockResponse>>>, request: Request, next: Next) -> Response {
+    match state.next_failure() {
+        Some(response) => response.into_response(),
+        None => next.run(request).await,
+    }
+}
+
+/// Encoding named by `name` (`Content-Type` or `Accept`), JSON when absent
+fn rest_encoding(headers: &HeaderMap, name: HeaderName) -> BodyEncoding {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| BodyEncoding::from_media_type(value.split(';').next().unwrap_or_default().trim()))
+        .unwrap_or_default()
+}
+
+fn rest_encoded<T: serde::Serialize>(status: StatusCode, encoding: BodyEncoding, value: &T) -> Response {
+    match encoding.encode(value) {
+        Ok(body) => (status, [(header::CONTENT_TYPE, encoding.content_type())], body).into_response(),
+        Err(e) => rest_error(StatusCode::INTERNAL_SERVER_ERROR, "encode_failed", e).into_response(),
+    }
+}
+
+fn rest_error(status: StatusCode, code: &str, error: impl std::fmt::Display) -> MockResponse {
+    MockResponse::error(status, code, &error.to_string())
+}
+
+fn rest_body<T: serde::de::DeserializeOwned>(headers: &HeaderMap, body: &[u8]) -> Result<T, MockResponse> {
+    rest_encoding(headers, header::CONTENT_TYPE)
+        .decode(body)
+        .map_err(|e| rest_error(StatusCode::BAD_REQUEST, "invalid_body", e))
+}
+
+async fn rest_submit_metric(
+    State(state): State<Arc<MockState<MockResponse>>>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<StatusCode, MockResponse> {
+    let metric: MetricPoint = rest_body(&headers, &body)?;
+    state
+        .storage
+        .store_metric(metric)
+        .await
+        .map_err(|e| rest_error(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e))?;
+    Ok(StatusCode::CREATED)
+}
+
+async fn rest_submit_metrics(
+    State(state): State<Arc<MockState<MockResponse>>>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Response, MockResponse> {
+    let metrics: Vec<MetricPoint> = rest_body(&headers, &body)?;
+    let accepted = state
+        .storage
+        .store_metrics(metrics)
+        .await
+        .map_err(|e| rest_error(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e))?;
+    let summary = serde_json::json!({ "accepted": accepted });
+    Ok(rest_encoded(StatusCode::CREATED, rest_encoding(&headers, header::ACCEPT), &summary))
+}
+
+async fn rest_query_metrics(
+    State(state): State<Arc<MockState<MockResponse>>>,
+    headers: HeaderMap,
+    Query(query): Query<MetricQuery>,
+) -> Result<Response, MockResponse> {
+    let metrics = state
+        .storage
+        .query_metrics(&query)
+        .await
+        .map_err(|e| rest_error(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e))?;
+    if headers.get(header::ACCEPT).is_some_and(|accept| accept == shared::arrow_ipc::ARROW_STREAM) {
+        let body = shared::arrow_ipc::encode(&metrics).map_err(|e| rest_error(StatusCode::INTERNAL_SERVER_ERROR, "encode_failed", e))?;
+        return Ok((StatusCode::OK, [(header::CONTENT_TYPE, shared::arrow_ipc::ARROW_STREAM)], body).into_response());
+    }
+    Ok(rest_encoded(StatusCode::OK, rest_encoding(&headers, header::ACCEPT), &metrics))
+}
+
+async fn rest_get_statistics(
+    State(state): State<Arc<MockState<MockResponse>>>,
+    Query(query): Query<MetricQuery>,
+) -> Result<Response, MockResponse> {
+    let stats = state
+        .storage
+        .calculate_statistics(&query)
+        .await
+        .map_err(|e| rest_error(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e))?;
+    Ok(Json(stats).into_response())
+}
+
+async fn rest_query_rollups(
+    State(state): State<Arc<MockState<MockResponse>>>,
+    Query(query): Query<MetricQuery>,
+) -> Result<Response, MockResponse> {
+    let rollups = state
+        .storage
+        .query_rollups(&query)
+        .await
+        .map_err(|e| rest_error(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e))?;
+    Ok(Json(rollups).into_response())
+}
+
+async fn rest_delete_metrics(
+    State(state): State<Arc<MockState<MockResponse>>>,
+    Query(query): Query<MetricQuery>,
+) -> Result<Response, MockResponse> {
+    let deleted = state
+        .storage
+        .delete_metrics(&query)
+        .await
+        .map_err(|e| rest_error(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e))?;
+    Ok(Json(serde_json::json!({ "deleted": deleted })).into_response())
+}
+
+async fn rest_get_storage_stats(State(state): State<Arc<MockState<MockResponse>>>) -> Result<Response, MockResponse> {
+    let stats = state
+        .storage
+        .storage_stats()
+        .await
+        .map_err(|e| rest_error(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e))?;
+    Ok(Json(stats).into_response())
+}
+
+/// `MetricsService` over plaintext HTTP/2. `Subscribe` answers
+/// `UNIMPLEMENTED`; everything else works like `grpc-service` without
+/// validation, deadlines or compression.
+pub struct MockGrpc {
+    addr: SocketAddr,
+    state: Arc<MockState<tonic::Status>>,
+    server: JoinHandle<()>,
+}
+
+impl MockGrpc {
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = MockState::new();
+
+        let incoming = futures_util::stream::unfold(listener, |listener| async move {
+            let accepted = listener.accept().await.map(|(stream, _)| stream);
+            Some((accepted, listener))
+        });
+        // Seals like grpc-service when PROTOBENCH_PAYLOAD_KEY is set, to match the client
+        let service = SealedGrpc::server(
+            proto::metrics_service_server::MetricsServiceServer::new(GrpcService { state: state.clone() }),
+            PayloadCipher::get().cloned(),
+        );
+        let server = tokio::spawn(async move {
+            let _ = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await;
+        });
+
+        Ok(Self { addr, state, server })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Fail the next call with `status`
+    pub fn fail_next(&self, status: tonic::Status) {
+        self.state.push_failure(status);
+    }
+
+    /// Calls received so far, failed or not
+    pub fn calls(&self) -> u64 {
+        self.state.calls.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MockGrpc {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+struct GrpcService {
+    state: Arc<MockState<tonic::Status>>,
+}
+
+impl GrpcService {
+    fn begin(&self) -> Result<(), tonic::Status> {
+        match self.state.next_failure() {
+            Some(status) => Err(status),
+            None => Ok(()),
+        }
+    }
+}
+
+fn from_proto_query(query: proto::MetricQuery) -> MetricQuery {
+    MetricQuery {
+        start_time: query.start_time,
+        end_time: query.end_time,
+        hostname_filter: query.hostname_filter,
+        limit: query.limit,
+        offset: query.offset,
+    }
+}
+
+fn storage_status(error: anyhow::Error) -> tonic::Status {
+    tonic::Status::internal(error.to_string())
+}
+
+type GrpcResult<T> = Result<tonic::Response<T>, tonic::Status>;
+
+#[tonic::async_trait]
+impl proto::metrics_service_server::MetricsService for GrpcService {
+    async fn submit_metric(&self, request: tonic::Request<proto::MetricPoint>) -> GrpcResult<proto::Empty> {
+        self.begin()?;
+        let metric = grpc_client::from_proto_metric(request.into_inner());
+        self.state.storage.store_metric(metric).await.map_err(storage_status)?;
+        Ok(tonic::Response::new(proto::Empty {}))
+    }
+
+    async fn submit_metrics(&self, request: tonic::Request<tonic::Streaming<proto::MetricPoint>>) -> GrpcResult<proto::SubmitSummary> {
+        self.begin()?;
+        let mut stream = request.into_inner();
+        let mut batch = Vec::new();
+        while let Some(metric) = stream.message().await? {
+            batch.push(grpc_client::from_proto_metric(metric));
+        }
+        let accepted = self.state.storage.store_metrics(batch).await.map_err(storage_status)?;
+        Ok(tonic::Response::new(proto::SubmitSummary { accepted: accepted as u64 }))
+    }
+
+    async fn submit_metric_batch(&self, request: tonic::Request<proto::MetricBatch>) -> GrpcResult<proto::SubmitSummary> {
+        self.begin()?;
+        let batch = request.into_inner().metrics.into_iter().map(grpc_client::from_proto_metric).collect();
+        let accepted = self.state.storage.store_metrics(batch).await.map_err(storage_status)?;
+        Ok(tonic::Response::new(proto::SubmitSummary { accepted: accepted as u64 }))
+    }
+
+    type QueryMetricsStream = futures_util::stream::Iter<std::vec::IntoIter<Result<proto::MetricPoint, tonic::Status>>>;
+
+    async fn query_metrics(&self, request: tonic::Request<proto::MetricQuery>) -> GrpcResult<Self::QueryMetricsStream> {
+        self.begin()?;
+        let query = from_proto_query(request.into_inner());
+        let metrics = self.state.storage.query_metrics(&query).await.map_err(storage_status)?;
+        let messages: Vec<_> = metrics.into_iter().map(|metric| Ok(grpc_client::to_proto_metric(metric))).collect();
+        Ok(tonic::Response::new(futures_util::stream::iter(messages)))
+    }
+
+    async fn query_metrics_unary(&self, request: tonic::Request<proto::MetricQuery>) -> GrpcResult<proto::MetricPointList> {
+        self.begin()?;
+        let query = from_proto_query(request.into_inner());
+        let metrics = self.state.storage.query_metrics(&query).await.map_err(storage_status)?;
+        let metrics = metrics.into_iter().map(grpc_client::to_proto_metric).collect();
+        Ok(tonic::Response::new(proto::MetricPointList { metrics }))
+    }
+
+    type SubscribeStream = futures_util::stream::Empty<Result<proto::MetricPoint, tonic::Status>>;
+
+    async fn subscribe(&self, _request: tonic::Request<tonic::Streaming<proto::MetricQuery>>) -> GrpcResult<Self::SubscribeStream> {
+        self.begin()?;
+        Err(tonic::Status::unimplemented("the mock has no live feed"))
+    }
+
+    async fn get_statistics(&self, request: tonic::Request<proto::MetricQuery>) -> GrpcResult<proto::MetricStatistics> {
+        self.begin()?;
+        let query = from_proto_query(request.into_inner());
+        let stats = self.state.storage.calculate_statistics(&query).await.map_err(storage_status)?;
+        Ok(tonic::Response::new(proto::MetricStatistics {
+            count: stats.count,
+            avg_cpu_percent: stats.avg_cpu_percent,
+            avg_memory_bytes: stats.avg_memory_bytes,
+            avg_disk_io_ops: stats.avg_disk_io_ops,
+            time_range_seconds: stats.time_range_seconds,
+        }))
+    }
+
+    async fn query_rollups(&self, request: tonic::Request<proto::MetricQuery>) -> GrpcResult<proto::MetricRollupList> {
+        self.begin()?;
+        let query = from_proto_query(request.into_inner());
+        let rollups = self.state.storage.query_rollups(&query).await.map_err(storage_status)?;
+        let rollups = rollups
+            .into_iter()
+            .map(|rollup| proto::MetricRollup {
+                hostname: rollup.hostname,
+                minute_start: rollup.minute_start,
+                count: rollup.count,
+                avg_cpu_percent: rollup.avg_cpu_percent,
+                min_cpu_percent: rollup.min_cpu_percent,
+                max_cpu_percent: rollup.max_cpu_percent,
+                avg_memory_bytes: rollup.avg_memory_bytes,
+                min_memory_bytes: rollup.min_memory_bytes,
+                max_memory_bytes: rollup.max_memory_bytes,
+                avg_disk_io_ops: rollup.avg_disk_io_ops,
+                min_disk_io_ops: rollup.min_disk_io_ops,
+                max_disk_io_ops: rollup.max_disk_io_ops,
+            })
+            .collect();
+        Ok(tonic::Response::new(proto::MetricRollupList { rollups }))
+    }
+
+    async fn delete_metrics(&self, request: tonic::Request<proto::MetricQuery>) -> GrpcResult<proto::DeleteSummary> {
+        self.begin()?;
+        let query = from_proto_query(request.into_inner());
+        let deleted = self.state.storage.delete_metrics(&query).await.map_err(storage_status)?;
+        Ok(tonic::Response::new(proto::DeleteSummary { deleted }))
+    }
+
+    async fn get_storage_stats(&self, _request: tonic::Request<proto::Empty>) -> GrpcResult<proto::StorageStats> {
+        self.begin()?;
+        let stats = self.state.storage.storage_stats().await.map_err(storage_status)?;
+        Ok(tonic::Response::new(proto::StorageStats {
+            point_count: stats.point_count,
+            approx_heap_bytes: stats.approx_heap_bytes,
+            rollup_bucket_count: stats.rollup_bucket_count,
+            rollup_heap_bytes: stats.rollup_heap_bytes,
+        }))
+    }
+}
+
+/// `MetricsService` over loopback TCP in `capnp_client::encoding()`. Only
+/// the plain calls are served; `subscribe`, `queryMetricsStreaming` and
+/// `openQuery` answer `unimplemented`. Cap'n Proto RPC is `!Send`, so the
+/// mock runs on its own thread with its own runtime.
+pub struct MockCapnp {
+    addr: SocketAddr,
+    state: Arc<MockState<capnp::Error>>,
+    // Dropping it stops the server thread
+    _shutdown: tokio::sync::oneshot::Sender<()>,
+}
+
+impl MockCapnp {
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let state = MockState::new();
+        let (shutdown, stopped) = tokio::sync::oneshot::channel::<()>();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+
+        let server_state = state.clone();
+        std::thread::spawn(move || {
+            tokio::task::LocalSet::new().block_on(&runtime, async move {
+                let Ok(listener) = TcpListener::from_std(listener) else {
+                    return;
+                };
+                let accept = async {
+                    while let Ok((stream, _)) = listener.accept().await {
+                        let _ = stream.set_nodelay(true);
+                        tokio::task::spawn_local(serve_capnp(stream, server_state.clone()));
+                    }
+                };
+                tokio::select! {
+                    _ = stopped => {}
+                    _ = accept => {}
+                }
+            });
+        });
+
+        Ok(Self { addr, state, _shutdown: shutdown })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Fail the next call with `error`, e.g. `capnp::Error::failed(..)`
+    pub fn fail_next(&self, error: capnp::Error) {
+        self.state.push_failure(error);
+    }
+
+    /// Calls received so far, failed or not
+    pub fn calls(&self) -> u64 {
+        self.state.calls.load(Ordering::Relaxed)
+    }
+}
+
+async fn serve_capnp(stream: tokio::net::TcpStream, state: Arc<MockState<capnp::Error>>) {
+    let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+    let options = capnp::message::ReaderOptions::new();
+    let network: Box<dyn capnp_rpc::VatNetwork<rpc_twoparty_capnp::Side>> = match capnp_client::encoding() {
+        CapnpEncoding::Unpacked => Box::new(twoparty::VatNetwork::new(reader, writer, rpc_twoparty_capnp::Side::Server, options)),
+        CapnpEncoding::Packed => Box::new(twoparty::VatNetwork::new(
+            capnp_futures::serialize_packed::PackedRead::new(BufReader::new(reader)),
+            capnp_futures::serialize_packed::PackedWrite::new(writer),
+            rpc_twoparty_capnp::Side::Server,
+            options,
+        )),
+    };
+
+    let service: metrics_service::Client = capnp_rpc::new_client(CapnpService { state });
+    let _ = RpcSystem::new(network, Some(service.client)).await;
+}
+
+struct CapnpService {
+    state: Arc<MockState<capnp::Error>>,
+}
+
+impl CapnpService {
+    fn begin(&self) -> capnp::Result<()> {
+        match self.state.next_failure() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The inverse of `capnp_client::write_query`, reading a 0 limit or offset as unset
+fn read_query(query_reader: metric_query::Reader<'_>) -> capnp::Result<MetricQuery> {
+    let hostname_filter = if query_reader.has_hostname_filter() {
+        Some(query_reader.get_hostname_filter()?.to_str()?.to_string())
+    } else {
+        None
+    };
+    Ok(MetricQuery {
+        start_time: query_reader.get_start_time(),
+        end_time: query_reader.get_end_time(),
+        hostname_filter,
+        limit: Some(query_reader.get_limit()).filter(|&limit| limit > 0),
+        offset: Some(query_reader.get_offset()).filter(|&offset| offset > 0),
+    })
+}
+
+fn storage_failed(error: anyhow::Error) -> capnp::Error {
+    capnp::Error::failed(error.to_string())
+}
+
+impl metrics_service::Server for CapnpService {
+    fn submit_metric(
+        &mut self,
+        params: metrics_service::SubmitMetricParams,
+        mut results: metrics_service::SubmitMetricResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.begin());
+        let metric = pry!(capnp_client::read_metric(pry!(pry!(params.get()).get_metric())));
+        let state = self.state.clone();
+        Promise::from_future(async move {
+            let stored = state.storage.store_metrics(vec![metric]).await.map_err(storage_failed)?;
+            results.get().set_stored(stored as u64);
+            Ok(())
+        })
+    }
+
+    fn query_metrics(
+        &mut self,
+        params: metrics_service::QueryMetricsParams,
+        mut results: metrics_service::QueryMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.begin());
+        let query = pry!(read_query(pry!(pry!(params.get()).get_query())));
+        let state = self.state.clone();
+        Promise::from_future(async move {
+            let metrics = state.storage.query_metrics(&query).await.map_err(storage_failed)?;
+            let mut list_builder = results.get().init_metrics(metrics.len() as u32);
+            for (i, metric) in metrics.iter().enumerate() {
+                capnp_client::write_metric(metric, list_builder.reborrow().get(i as u32));
+            }
+            Ok(())
+        })
+    }
+
+    fn get_statistics(
+        &mut self,
+        params: metrics_service::GetStatisticsParams,
+        mut results: metrics_service::GetStatisticsResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.begin());
+        let query = pry!(read_query(pry!(pry!(params.get()).get_query())));
+        let state = self.state.clone();
+        Promise::from_future(async move {
+            let stats = state.storage.calculate_statistics(&query).await.map_err(storage_failed)?;
+            let mut stats_builder = results.get().init_statistics();
+            stats_builder.set_count(stats.count);
+            stats_builder.set_avg_cpu_percent(stats.avg_cpu_percent);
+            stats_builder.set_avg_memory_bytes(stats.avg_memory_bytes);
+            stats_builder.set_avg_disk_io_ops(stats.avg_disk_io_ops);
+            stats_builder.set_time_range_seconds(stats.time_range_seconds);
+            Ok(())
+        })
+    }
+
+    fn query_rollups(
+        &mut self,
+        params: metrics_service::QueryRollupsParams,
+        mut results: metrics_service::QueryRollupsResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.begin());
+        let query = pry!(read_query(pry!(pry!(params.get()).get_query())));
+        let state = self.state.clone();
+        Promise::from_future(async move {
+            let rollups = state.storage.query_rollups(&query).await.map_err(storage_failed)?;
+            let mut list_builder = results.get().init_rollups(rollups.len() as u32);
+            for (i, rollup) in rollups.iter().enumerate() {
+                let mut rollup_builder = list_builder.reborrow().get(i as u32);
+                rollup_builder.set_hostname((&rollup.hostname[..]).into());
+                rollup_builder.set_minute_start(rollup.minute_start);
+                rollup_builder.set_count(rollup.count);
+                rollup_builder.set_avg_cpu_percent(rollup.avg_cpu_percent);
+                rollup_builder.set_min_cpu_percent(rollup.min_cpu_percent);
+                rollup_builder.set_max_cpu_percent(rollup.max_cpu_percent);
+                rollup_builder.set_avg_memory_bytes(rollup.avg_memory_bytes);
+                rollup_builder.set_min_memory_bytes(rollup.min_memory_bytes);
+                rollup_builder.set_max_memory_bytes(rollup.max_memory_bytes);
+                rollup_builder.set_avg_disk_io_ops(rollup.avg_disk_io_ops);
+                rollup_builder.set_min_disk_io_ops(rollup.min_disk_io_ops);
+                rollup_builder.set_max_disk_io_ops(rollup.max_disk_io_ops);
+            }
+            Ok(())
+        })
+    }
+
+    fn delete_metrics(
+        &mut self,
+        params: metrics_service::DeleteMetricsParams,
+        mut results: metrics_service::DeleteMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.begin());
+        let query = pry!(read_query(pry!(pry!(params.get()).get_query())));
+        let state = self.state.clone();
+        Promise::from_future(async move {
+            let deleted = state.storage.delete_metrics(&query).await.map_err(storage_failed)?;
+            results.get().set_deleted(deleted);
+            Ok(())
+        })
+    }
+
+    fn get_storage_stats(
+        &mut self,
+        _params: metrics_service::GetStorageStatsParams,
+        mut results: metrics_service::GetStorageStatsResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.begin());
+        let state = self.state.clone();
+        Promise::from_future(async move {
+            let stats = state.storage.storage_stats().await.map_err(storage_failed)?;
+            let mut stats_builder = results.get().init_stats();
+            stats_builder.set_point_count(stats.point_count);
+            stats_builder.set_approx_heap_bytes(stats.approx_heap_bytes);
+            stats_builder.set_rollup_bucket_count(stats.rollup_bucket_count);
+            stats_builder.set_rollup_heap_bytes(stats.rollup_heap_bytes);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,334 @@
+use anyhow::Context;
+use shared::MetricQuery;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::results::{now_ms, ServiceRestart};
+use crate::{capnp_client, grpc_client, rest_client, ProtocolClient};
+
+// How long replicas get to report SERVING after being spawned
+const REPLICA_READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of `grpc-service` replicas the harness starts for the
+/// `grpc_replicas` group, from `PROTOBENCH_GRPC_REPLICAS` (unset skips it)
+pub fn grpc_replica_count() -> Option<usize> {
+    std::env::var("PROTOBENCH_GRPC_REPLICAS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .filter(|&count| count > 0)
+}
+
+/// Path of a service binary in `PROTOBENCH_SERVICE_BIN_DIR`, or else in the
+/// cargo target directory the harness itself was built into
+fn service_binary(name: &str) -> anyhow::Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("PROTOBENCH_SERVICE_BIN_DIR") {
+        return Ok(PathBuf::from(dir).join(name));
+    }
+
+    // Benches run from target/<profile>/deps, binaries from target/<profile>
+    let exe = std::env::current_exe()?;
+    exe.ancestors()
+        .skip(1)
+        .take(2)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .with_context(|| format!("{} not found next to {}; build it or set PROTOBENCH_SERVICE_BIN_DIR", name, exe.display()))
+}
+
+/// A loopback address nothing is listening on right now
+pub fn free_local_addr() -> anyhow::Result<String> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.to_string())
+}
+
+/// `grpc-service` processes started by the harness, each on its own loopback
+/// port with its own in-memory storage. They are killed when this is dropped.
+pub struct GrpcReplicas {
+    children: Vec<Child>,
+    targets: Vec<String>,
+}
+
+impl GrpcReplicas {
+    /// Spawn `count` replicas and wait until every one reports SERVING
+    pub async fn start(count: usize) -> anyhow::Result<Self> {
+        let binary = service_binary("grpc-service")?;
+        let mut replicas = GrpcReplicas { children: Vec::new(), targets: Vec::new() };
+
+        for _ in 0..count {
+            let addr = free_local_addr()?;
+            let child = Command::new(&binary)
+                .arg("--addr")
+                .arg(&addr)
+                // One metrics exporter port and one WAL can't be shared
+                .env_remove("PROTOBENCH_GRPC_METRICS_ADDR")
+                .env_remove("PROTOBENCH_WAL_PATH")
+                .stdout(Stdio::null())
+                .spawn()
+                .with_context(|| format!("Failed to start {}", binary.display()))?;
+            replicas.children.push(child);
+            replicas.targets.push(addr);
+        }
+
+        grpc_client::wait_until_serving_at(&replicas.targets, REPLICA_READY_TIMEOUT).await?;
+        Ok(replicas)
+    }
+
+    /// `host:port` of every replica, for `grpc_client::connect_to`
+    pub fn targets(&self) -> &[String] {
+        &self.targets
+    }
+}
+
+impl Drop for GrpcReplicas {
+    fn drop(&mut self) {
+        for child in &mut self.children {
+            // Storage is in memory, so there is nothing to drain
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// How long restarted services get to answer a request on every protocol
+const SERVICES_READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One of the services the harness can start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Rest,
+    Grpc,
+    Capnp,
+}
+
+impl Service {
+    pub const ALL: [Service; 3] = [Service::Rest, Service::Grpc, Service::Capnp];
+
+    /// `rest`, `grpc` or `capnp`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rest" => Some(Service::Rest),
+            "grpc" => Some(Service::Grpc),
+            "capnp" => Some(Service::Capnp),
+            _ => None,
+        }
+    }
+
+    pub fn binary(self) -> &'static str {
+        match self {
+            Service::Rest => "rest-service",
+            Service::Grpc => "grpc-service",
+            Service::Capnp => "capnp-service",
+        }
+    }
+
+    /// Where the service's client connects, which is where it is started
+    pub fn listen_args(self) -> anyhow::Result<[String; 2]> {
+        Ok(match self {
+            Service::Rest => ["--addr".to_string(), rest_client::target().to_string()],
+            Service::Grpc => {
+                let target = grpc_client::targets().into_iter().next().context("No gRPC target configured")?;
+                ["--addr".to_string(), target]
+            }
+            Service::Capnp => match capnp_client::uds_path() {
+                Some(path) => ["--uds".to_string(), path.display().to_string()],
+                None => ["--addr".to_string(), capnp_client::target().to_string()],
+            },
+        })
+    }
+
+    /// The plain client for the service's protocol
+    pub fn client(self) -> Box<dyn ProtocolClient> {
+        match self {
+            Service::Rest => Box::new(rest_client::RestClient),
+            Service::Grpc => Box::new(grpc_client::GrpcClient),
+            Service::Capnp => Box::new(capnp_client::CapnpClient),
+        }
+    }
+}
+
+/// Services started by the harness at the addresses the clients target,
+/// with the harness's environment, so they can be restarted between
+/// repetitions. While they run a supervisor thread watches for any that
+/// exits and starts it again, recording a `ServiceRestart` for
+/// `take_restarts`. They are killed when this is dropped.
+pub struct LocalServices {
+    services: Vec<Service>,
+    /// Variables set on the services on top of the harness's environment
+    env: Vec<(String, String)>,
+    children: Arc<Mutex<Vec<(Service, Child)>>>,
+    restarts: Arc<Mutex<Vec<ServiceRestart>>>,
+    supervisor: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+}
+
+// How often the supervisor checks whether a service has exited
+const SUPERVISOR_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Restarts of one service before the supervisor leaves it down, so a service
+// that can't start doesn't spin
+const MAX_RESTARTS_PER_SERVICE: usize = 5;
+
+fn spawn_service(service: Service, env: &[(String, String)]) -> anyhow::Result<Child> {
+    let binary = service_binary(service.binary())?;
+    Command::new(&binary)
+        .args(service.listen_args()?)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to start {}", binary.display()))
+}
+
+/// Restart every child that has exited until `stop` is set
+fn supervise(
+    children: &Mutex<Vec<(Service, Child)>>,
+    env: &[(String, String)],
+    restarts: &Mutex<Vec<ServiceRestart>>,
+    stop: &AtomicBool,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(SUPERVISOR_POLL_INTERVAL);
+        let mut children = children.lock().unwrap();
+        let mut index = 0;
+        while index < children.len() {
+            let (service, child) = &mut children[index];
+            let service = *service;
+            let status = match child.try_wait() {
+                Ok(Some(status)) => status,
+                Ok(None) | Err(_) => {
+                    index += 1;
+                    continue;
+                }
+            };
+
+            let mut restarts = restarts.lock().unwrap();
+            let previous = restarts.iter().filter(|restart| restart.service == service.binary()).count();
+            restarts.push(ServiceRestart {
+                service: service.binary().to_string(),
+                exit_status: status.to_string(),
+                at_ms: now_ms(),
+            });
+            drop(restarts);
+            if previous >= MAX_RESTARTS_PER_SERVICE {
+                tracing::error!(service = service.binary(), %status, "service exited again; leaving it down");
+                children.remove(index);
+                continue;
+            }
+            tracing::warn!(service = service.binary(), %status, "service exited mid-run; restarting it");
+            match spawn_service(service, env) {
+                Ok(restarted) => {
+                    children[index].1 = restarted;
+                    index += 1;
+                }
+                Err(e) => {
+                    tracing::error!(service = service.binary(), error = %e, "failed to restart service");
+                    children.remove(index);
+                }
+            }
+        }
+    }
+}
+
+impl LocalServices {
+    /// Start all three services and wait until every protocol answers.
+    /// Fails if something is already listening on a target address.
+    pub async fn start() -> anyhow::Result<Self> {
+        Self::start_only(&Service::ALL).await
+    }
+
+    /// `start` for just `services`
+    pub async fn start_only(services: &[Service]) -> anyhow::Result<Self> {
+        Self::start_with_env(services, &[]).await
+    }
+
+    /// `start_only` with `env` set on the services as well, e.g.
+    /// `PROTOBENCH_FAULT_*` to slow them down; restarts keep it
+    pub async fn start_with_env(services: &[Service], env: &[(String, String)]) -> anyhow::Result<Self> {
+        let mut started = LocalServices {
+            services: services.to_vec(),
+            env: env.to_vec(),
+            children: Arc::default(),
+            restarts: Arc::default(),
+            supervisor: None,
+        };
+        for &service in services {
+            let child = spawn_service(service, env)?;
+            started.children.lock().unwrap().push((service, child));
+        }
+        started.wait_until_ready().await?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (children, restarts, stopping) = (started.children.clone(), started.restarts.clone(), stop.clone());
+        let env = started.env.clone();
+        let thread = std::thread::Builder::new()
+            .name("protobench-supervisor".to_string())
+            .spawn(move || supervise(&children, &env, &restarts, &stopping))?;
+        started.supervisor = Some((stop, thread));
+        Ok(started)
+    }
+
+    /// Stop the services and start them again with empty storage, so the
+    /// next repetition shares no warmed caches, allocations or connections
+    /// with the last. Restarts not yet taken are kept.
+    pub async fn restart(&mut self) -> anyhow::Result<()> {
+        self.stop();
+        let restarts = self.take_restarts();
+        *self = Self::start_with_env(&self.services, &self.env).await?;
+        self.restarts.lock().unwrap().extend(restarts);
+        Ok(())
+    }
+
+    /// Restarts the supervisor made since the last call, oldest first
+    pub fn take_restarts(&self) -> Vec<ServiceRestart> {
+        std::mem::take(&mut *self.restarts.lock().unwrap())
+    }
+
+    // Purging goes through the clients' own connections, so it also
+    // replaces any the restart broke
+    async fn wait_until_ready(&mut self) -> anyhow::Result<()> {
+        let everything = MetricQuery::all();
+        let deadline = std::time::Instant::now() + SERVICES_READY_TIMEOUT;
+        loop {
+            for (_, child) in self.children.lock().unwrap().iter_mut() {
+                if let Some(status) = child.try_wait()? {
+                    anyhow::bail!("A service exited during startup ({}); is its address already in use?", status);
+                }
+            }
+            let mut purged = Ok(());
+            for service in &self.services {
+                if let Err(e) = service.client().delete_metrics(everything.clone()).await {
+                    purged = Err(e);
+                    break;
+                }
+            }
+            match purged {
+                Ok(()) => return Ok(()),
+                Err(e) if std::time::Instant::now() >= deadline => {
+                    return Err(e).context("Services didn't become ready");
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        // The supervisor goes first so it doesn't restart what is killed here
+        if let Some((stop, thread)) = self.supervisor.take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = thread.join();
+        }
+        for (_, child) in self.children.lock().unwrap().iter_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        self.children.lock().unwrap().clear();
+    }
+}
+
+impl Drop for LocalServices {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
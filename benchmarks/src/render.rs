@@ -0,0 +1,241 @@
+use std::fmt::Write;
+
+use crate::capabilities::{self, PROTOCOLS};
+use crate::results::{BenchmarkRun, OperationResult};
+
+/// Document format `protobench report` writes a run in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl Format {
+    /// `markdown` (or `md`) and `html`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "markdown" | "md" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+
+    /// HTML for `.html` and `.htm` paths, Markdown for anything else
+    pub fn for_path(path: &str) -> Self {
+        if path.ends_with(".html") || path.ends_with(".htm") {
+            Format::Html
+        } else {
+            Format::Markdown
+        }
+    }
+}
+
+/// `run` as a document in `format`
+pub fn render(run: &BenchmarkRun, format: Format) -> String {
+    match format {
+        Format::Markdown => markdown(run),
+        Format::Html => html(run),
+    }
+}
+
+// Header and cells of the per-protocol table, shared by both formats
+const SUMMARY_COLUMNS: [&str; 4] = ["Protocol", "Operations", "Mean latency (ms)", "Total traffic (bytes)"];
+const RESULT_COLUMNS: [&str; 7] = ["Protocol", "Operation", "Points", "Latency (ms)", "Request (bytes)", "Response (bytes)", "Memory (bytes)"];
+
+fn summary_rows(run: &BenchmarkRun) -> Vec<[String; 4]> {
+    run.summaries()
+        .into_iter()
+        .map(|summary| {
+            [
+                summary.protocol,
+                summary.operations.to_string(),
+                format!("{:.3}", summary.mean_latency_ns as f64 / 1_000_000.0),
+                summary.total_bytes.to_string(),
+            ]
+        })
+        .collect()
+}
+
+fn result_row(result: &OperationResult) -> [String; 7] {
+    let estimated = if result.payload_estimated { "*" } else { "" };
+    let latency = match result.latency_ci_ns {
+        Some([lower, upper]) => format!(
+            "{:.3} ({:.3}–{:.3})",
+            result.latency_ns as f64 / 1_000_000.0,
+            lower as f64 / 1_000_000.0,
+            upper as f64 / 1_000_000.0
+        ),
+        None => format!("{:.3}", result.latency_ns as f64 / 1_000_000.0),
+    };
+    [
+        result.protocol.clone(),
+        result.operation.clone(),
+        result.points.to_string(),
+        latency,
+        format!("{}{}", result.request_bytes, estimated),
+        format!("{}{}", result.response_bytes, estimated),
+        result.memory_allocated.to_string(),
+    ]
+}
+
+const SERVER_COLUMNS: [&str; 7] = ["Protocol", "Operation", "Points", "Client (ms)", "Server (ms)", "Server share", "Server memory (bytes)"];
+
+/// Results the service reported its own side of, split into the time and
+/// memory spent in the service against what the client saw
+fn server_rows(run: &BenchmarkRun) -> Vec<[String; 7]> {
+    run.results
+        .iter()
+        .filter_map(|result| {
+            let server_ns = result.server_time_ns?;
+            Some([
+                result.protocol.clone(),
+                result.operation.clone(),
+                result.points.to_string(),
+                format!("{:.3}", result.latency_ns as f64 / 1_000_000.0),
+                format!("{:.3}", server_ns as f64 / 1_000_000.0),
+                result.server_share().map_or("-".to_string(), |share| format!("{:.0}%", share * 100.0)),
+                result.server_allocated_bytes.unwrap_or(0).to_string(),
+            ])
+        })
+        .collect()
+}
+
+const CAPABILITY_COLUMNS: [&str; 4] = ["Feature", PROTOCOLS[0], PROTOCOLS[1], PROTOCOLS[2]];
+
+/// The capability matrix, with what `run` measured
+fn capability_rows(run: &BenchmarkRun) -> Vec<[String; 4]> {
+    capabilities::matrix(run)
+        .into_iter()
+        .map(|capability| {
+            let [rest, grpc, capnp] = capability.support;
+            [capability.feature.to_string(), rest, grpc, capnp]
+        })
+        .collect()
+}
+
+/// Settings recorded with the run, as `NAME=value` lines
+fn settings(run: &BenchmarkRun) -> Vec<String> {
+    run.settings.iter().map(|(name, value)| format!("{}={}", name, value)).collect()
+}
+
+/// Allocators recorded with the run, as `process allocator`
+fn allocators(run: &BenchmarkRun) -> Vec<String> {
+    run.allocators.iter().map(|(process, allocator)| format!("{} {}", process, allocator)).collect()
+}
+
+/// GitHub-flavoured Markdown: the run's provenance, a table per protocol, a
+/// table of every result, the server's share of any results the services
+/// reported on, the capability matrix and any service restarts
+pub fn markdown(run: &BenchmarkRun) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# ProtoBench results\n");
+    let _ = writeln!(out, "Started at {} ms since the Unix epoch by harness {}.\n", run.started_at_ms, run.harness_version);
+    let settings = settings(run);
+    if !settings.is_empty() {
+        let _ = writeln!(out, "Settings: {}\n", settings.iter().map(|setting| format!("`{}`", setting)).collect::<Vec<_>>().join(", "));
+    }
+    let allocators = allocators(run);
+    if !allocators.is_empty() {
+        let _ = writeln!(out, "Allocators: {}\n", allocators.join(", "));
+    }
+
+    let _ = writeln!(out, "## Protocols\n");
+    markdown_table(&mut out, &SUMMARY_COLUMNS, summary_rows(run));
+    let _ = writeln!(out, "\n## Results\n");
+    markdown_table(&mut out, &RESULT_COLUMNS, run.results.iter().map(result_row).collect());
+    if run.results.iter().any(|result| result.payload_estimated) {
+        let _ = writeln!(out, "\n\\* estimated rather than counted on the socket");
+    }
+    let server = server_rows(run);
+    if !server.is_empty() {
+        let _ = writeln!(out, "\n## Client and server\n");
+        markdown_table(&mut out, &SERVER_COLUMNS, server);
+    }
+    let _ = writeln!(out, "\n## Capabilities\n");
+    markdown_table(&mut out, &CAPABILITY_COLUMNS, capability_rows(run));
+    if !run.restarts.is_empty() {
+        let _ = writeln!(out, "\n## Restarts\n\nResults taken around these don't compare fairly.\n");
+        for restart in &run.restarts {
+            let _ = writeln!(out, "- {}", restart);
+        }
+    }
+    out
+}
+
+fn markdown_table<const N: usize>(out: &mut String, columns: &[&str; N], rows: Vec<[String; N]>) {
+    let escape = |cell: &str| cell.replace('|', "\\|");
+    let _ = writeln!(out, "| {} |", columns.join(" | "));
+    let _ = writeln!(out, "|{}", " --- |".repeat(N));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|cell| escape(cell)).collect();
+        let _ = writeln!(out, "| {} |", cells.join(" | "));
+    }
+}
+
+/// A standalone HTML page with the same content as `markdown`
+pub fn html(run: &BenchmarkRun) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>ProtoBench results</title>\n");
+    out.push_str("<style>body{font-family:sans-serif}table{border-collapse:collapse}th,td{border:1px solid #ccc;padding:4px 8px}td{text-align:right}td:first-child,td:nth-child(2),.matrix td{text-align:left}</style>\n");
+    out.push_str("</head>\n<body>\n<h1>ProtoBench results</h1>\n");
+    let _ = writeln!(
+        out,
+        "<p>Started at {} ms since the Unix epoch by harness {}.</p>",
+        run.started_at_ms,
+        escape_html(&run.harness_version)
+    );
+    let settings = settings(run);
+    if !settings.is_empty() {
+        let settings: Vec<String> = settings.iter().map(|setting| format!("<code>{}</code>", escape_html(setting))).collect();
+        let _ = writeln!(out, "<p>Settings: {}</p>", settings.join(", "));
+    }
+    let allocators = allocators(run);
+    if !allocators.is_empty() {
+        let _ = writeln!(out, "<p>Allocators: {}</p>", escape_html(&allocators.join(", ")));
+    }
+
+    out.push_str("<h2>Protocols</h2>\n");
+    html_table(&mut out, &SUMMARY_COLUMNS, summary_rows(run));
+    out.push_str("<h2>Results</h2>\n");
+    html_table(&mut out, &RESULT_COLUMNS, run.results.iter().map(result_row).collect());
+    if run.results.iter().any(|result| result.payload_estimated) {
+        out.push_str("<p>* estimated rather than counted on the socket</p>\n");
+    }
+    let server = server_rows(run);
+    if !server.is_empty() {
+        out.push_str("<h2>Client and server</h2>\n");
+        html_table(&mut out, &SERVER_COLUMNS, server);
+    }
+    out.push_str("<h2>Capabilities</h2>\n<div class=\"matrix\">\n");
+    html_table(&mut out, &CAPABILITY_COLUMNS, capability_rows(run));
+    out.push_str("</div>\n");
+    if !run.restarts.is_empty() {
+        out.push_str("<h2>Restarts</h2>\n<p>Results taken around these don't compare fairly.</p>\n<ul>\n");
+        for restart in &run.restarts {
+            let _ = writeln!(out, "<li>{}</li>", escape_html(&restart.to_string()));
+        }
+        out.push_str("</ul>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn html_table<const N: usize>(out: &mut String, columns: &[&str; N], rows: Vec<[String; N]>) {
+    out.push_str("<table>\n<tr>");
+    for column in columns {
+        let _ = write!(out, "<th>{}</th>", escape_html(column));
+    }
+    out.push_str("</tr>\n");
+    for row in rows {
+        out.push_str("<tr>");
+        for cell in &row {
+            let _ = write!(out, "<td>{}</td>", escape_html(cell));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
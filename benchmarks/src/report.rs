@@ -0,0 +1,428 @@
+use shared::{MetricPoint, MetricQuery};
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::statistics::{self, Summary};
+use crate::wire_bytes::{self, WireBytes};
+use crate::{BenchmarkMetrics, ProtocolClient, ProtocolError};
+
+/// Composite cost of one measurement, lower is better: latency in
+/// milliseconds weighted 0.4, and heap allocated in KiB, wire traffic in KiB
+/// and estimated CPU cycles in millions weighted 0.2 each
+pub fn cost_score(metrics: &BenchmarkMetrics) -> f64 {
+    let latency_ms = metrics.latency.as_nanos() as f64 / 1_000_000.0;
+    let memory_kb = metrics.memory_allocated as f64 / 1024.0;
+    let traffic_kb = metrics.payload_size.total_bytes as f64 / 1024.0;
+    let cpu_millions = metrics.cpu_cycles as f64 / 1_000_000.0;
+
+    latency_ms * 0.4 + memory_kb * 0.2 + traffic_kb * 0.2 + cpu_millions * 0.2
+}
+
+/// One protocol's measurement and its `cost_score`
+#[derive(Debug, Clone)]
+pub struct RankedProtocol {
+    pub protocol: String,
+    pub metrics: BenchmarkMetrics,
+    pub cost_score: f64,
+    /// How the figures varied, when `metrics` holds means over repeated runs
+    pub spread: Option<Spread>,
+}
+
+/// Summaries of the repeated runs behind a `RankedProtocol`, which decide
+/// whether its lead on a metric is significant
+#[derive(Debug, Clone, Copy)]
+pub struct Spread {
+    pub latency_ms: Summary,
+    pub memory_bytes: Summary,
+    pub total_bytes: Summary,
+}
+
+/// A metric `ComparisonReport::verdict` picks a winner on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Latency,
+    Memory,
+    Traffic,
+}
+
+impl Metric {
+    fn summary(self, spread: &Spread) -> &Summary {
+        match self {
+            Metric::Latency => &spread.latency_ms,
+            Metric::Memory => &spread.memory_bytes,
+            Metric::Traffic => &spread.total_bytes,
+        }
+    }
+}
+
+/// Who leads on a metric
+#[derive(Debug, Clone)]
+pub enum Verdict<'a> {
+    /// Ahead of every other protocol by a significant margin
+    Winner(&'a RankedProtocol),
+    /// The leader and every protocol not significantly behind it, leader first
+    Tie(Vec<&'a RankedProtocol>),
+    /// The leader of single runs, whose spread is unknown, so the margin
+    /// couldn't be tested
+    Untested(&'a RankedProtocol),
+}
+
+impl<'a> Verdict<'a> {
+    /// The protocol with the best figure, tied or not
+    pub fn leader(&self) -> &'a RankedProtocol {
+        match self {
+            Verdict::Winner(leader) | Verdict::Untested(leader) => leader,
+            Verdict::Tie(tied) => tied[0],
+        }
+    }
+}
+
+impl fmt::Display for RankedProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  {} Results:", self.protocol)?;
+        writeln!(f, "    ⏱️  Latency:        {:?}", self.metrics.latency)?;
+        if let Some(time_to_first_byte) = self.metrics.time_to_first_byte {
+            writeln!(f, "    🥇 First Point:    {:?}", time_to_first_byte)?;
+        }
+        let estimated = if self.metrics.payload_size.estimated { " (estimated)" } else { "" };
+        writeln!(f, "    📦 Request Size:   {} bytes{}", self.metrics.payload_size.request_bytes, estimated)?;
+        writeln!(f, "    📥 Response Size:  {} bytes{}", self.metrics.payload_size.response_bytes, estimated)?;
+        writeln!(f, "    📊 Total Traffic:  {} bytes{}", self.metrics.payload_size.total_bytes, estimated)?;
+        writeln!(f, "    🧠 Memory Used:    {} bytes", self.metrics.memory_allocated)?;
+        writeln!(f, "    ⚡ CPU Cycles:     {} (estimated)", self.metrics.cpu_cycles)?;
+        writeln!(f, "    💰 Cost Score:     {:.2} (lower is better)", self.cost_score)
+    }
+}
+
+/// The same operation measured once per protocol, ranked by `cost_score`
+/// with the winner on each metric. Criterion groups, the CLI and long runs
+/// build one from whatever `BenchmarkMetrics` they collected, so they all
+/// report winners the same way.
+#[derive(Debug, Clone)]
+pub struct ComparisonReport {
+    ranking: Vec<RankedProtocol>,
+}
+
+impl ComparisonReport {
+    /// Rank `results`, or `None` when there are none. Protocols with equal
+    /// cost keep the order they were given in, and a tie on any single
+    /// metric goes to the one ranked higher.
+    pub fn new<S: AsRef<str>>(results: &[(S, BenchmarkMetrics)]) -> Option<Self> {
+        Self::rank(results.iter().map(|(protocol, metrics)| (protocol.as_ref(), metrics.clone(), None)).collect())
+    }
+
+    /// `new` for means over repeated runs, whose spread lets `verdict` tell
+    /// a real lead from noise
+    pub fn with_spread<S: AsRef<str>>(results: &[(S, BenchmarkMetrics, Spread)]) -> Option<Self> {
+        Self::rank(
+            results
+                .iter()
+                .map(|(protocol, metrics, spread)| (protocol.as_ref(), metrics.clone(), Some(*spread)))
+                .collect(),
+        )
+    }
+
+    fn rank(results: Vec<(&str, BenchmarkMetrics, Option<Spread>)>) -> Option<Self> {
+        if results.is_empty() {
+            return None;
+        }
+        let mut ranking: Vec<RankedProtocol> = results
+            .into_iter()
+            .map(|(protocol, metrics, spread)| RankedProtocol {
+                protocol: protocol.to_string(),
+                cost_score: cost_score(&metrics),
+                metrics,
+                spread,
+            })
+            .collect();
+        ranking.sort_by(|a, b| a.cost_score.partial_cmp(&b.cost_score).unwrap_or(Ordering::Equal));
+        Some(Self { ranking })
+    }
+
+    /// Every protocol, lowest cost first
+    pub fn ranking(&self) -> &[RankedProtocol] {
+        &self.ranking
+    }
+
+    pub fn best_overall(&self) -> &RankedProtocol {
+        &self.ranking[0]
+    }
+
+    /// Lowest latency, i.e. first to complete
+    pub fn fastest(&self) -> &RankedProtocol {
+        self.best_by(|entry| entry.metrics.latency)
+    }
+
+    /// Quickest to deliver its first point, among the protocols that
+    /// streamed; `None` when none did. A streaming protocol can win here while
+    /// finishing last.
+    pub fn most_responsive(&self) -> Option<&RankedProtocol> {
+        self.ranking
+            .iter()
+            .filter_map(|entry| Some((entry.metrics.time_to_first_byte?, entry)))
+            .min_by_key(|(time_to_first_byte, _)| *time_to_first_byte)
+            .map(|(_, entry)| entry)
+    }
+
+    pub fn least_memory(&self) -> &RankedProtocol {
+        self.best_by(|entry| entry.metrics.memory_allocated)
+    }
+
+    pub fn least_traffic(&self) -> &RankedProtocol {
+        self.best_by(|entry| entry.metrics.payload_size.total_bytes)
+    }
+
+    /// The leader on `metric`, and whether its lead is significant: a
+    /// Welch's t test at the 5% level against every other protocol. Single
+    /// runs can't be tested, so their leader is `Untested`.
+    pub fn verdict(&self, metric: Metric) -> Verdict<'_> {
+        let leader = match metric {
+            Metric::Latency => self.fastest(),
+            Metric::Memory => self.least_memory(),
+            Metric::Traffic => self.least_traffic(),
+        };
+        let mut tied = vec![leader];
+        for entry in self.ranking.iter().filter(|entry| !std::ptr::eq(*entry, leader)) {
+            let (Some(leader_spread), Some(spread)) = (&leader.spread, &entry.spread) else {
+                return Verdict::Untested(leader);
+            };
+            match statistics::differs_significantly(metric.summary(leader_spread), metric.summary(spread)) {
+                Some(true) => {}
+                Some(false) => tied.push(entry),
+                None => return Verdict::Untested(leader),
+            }
+        }
+        if tied.len() == 1 {
+            Verdict::Winner(leader)
+        } else {
+            Verdict::Tie(tied)
+        }
+    }
+
+    /// The entry for `protocol`, if it was measured
+    pub fn get(&self, protocol: &str) -> Option<&RankedProtocol> {
+        self.ranking.iter().find(|entry| entry.protocol == protocol)
+    }
+
+    fn best_by<K: Ord>(&self, key: impl Fn(&RankedProtocol) -> K) -> &RankedProtocol {
+        // min_by_key keeps the first of equal keys, i.e. the higher ranked
+        self.ranking.iter().min_by_key(|entry| key(entry)).expect("a report has at least one protocol")
+    }
+
+    /// The winners alone, without each protocol's figures
+    pub fn winners(&self) -> Winners<'_> {
+        Winners(self)
+    }
+}
+
+/// Displays each protocol's figures in ranking order, then the winners
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.ranking {
+            writeln!(f, "{}", entry)?;
+        }
+        write!(f, "{}", self.winners())
+    }
+}
+
+/// `ComparisonReport::winners`
+pub struct Winners<'a>(&'a ComparisonReport);
+
+impl fmt::Display for Winners<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let report = self.0;
+        let best_overall = report.best_overall();
+        let fastest = report.verdict(Metric::Latency);
+        let least_memory = report.verdict(Metric::Memory);
+        let least_traffic = report.verdict(Metric::Traffic);
+
+        writeln!(f, "🏆 Winners:")?;
+        write_verdict(f, "Fastest:      ", &fastest, |entry| match &entry.spread {
+            Some(spread) => format!("{:.3}ms", spread.latency_ms),
+            None => format!("{:?}", entry.metrics.latency),
+        })?;
+        if let Some(most_responsive) = report.most_responsive() {
+            let time_to_first_byte = most_responsive.metrics.time_to_first_byte.unwrap_or_default();
+            writeln!(f, "  First Point:   {} ({:?})", most_responsive.protocol, time_to_first_byte)?;
+        }
+        write_verdict(f, "Least Memory: ", &least_memory, |entry| match &entry.spread {
+            Some(spread) => format!("{:.0} bytes", spread.memory_bytes),
+            None => format!("{} bytes", entry.metrics.memory_allocated),
+        })?;
+        write_verdict(f, "Least Traffic:", &least_traffic, |entry| {
+            let estimated = if entry.metrics.payload_size.estimated { ", estimated" } else { "" };
+            match &entry.spread {
+                Some(spread) => format!("{:.0} bytes{}", spread.total_bytes, estimated),
+                None => format!("{} bytes{}", entry.metrics.payload_size.total_bytes, estimated),
+            }
+        })?;
+        writeln!(f, "  Best Overall:  {} (cost: {:.2})", best_overall.protocol, best_overall.cost_score)?;
+        writeln!(f, "  Ranking:       {}", report.ranking.iter().map(|entry| entry.protocol.as_str()).collect::<Vec<_>>().join(" < "))?;
+        if matches!(fastest, Verdict::Untested(_)) && report.ranking.len() > 1 {
+            writeln!(f, "  (one run each, so no lead was tested for significance)")?;
+        }
+        Ok(())
+    }
+}
+
+// `  <label> <protocol> (<figure>)`, or every tied protocol with its figure
+fn write_verdict(
+    f: &mut fmt::Formatter<'_>,
+    label: &str,
+    verdict: &Verdict<'_>,
+    figure: impl Fn(&RankedProtocol) -> String,
+) -> fmt::Result {
+    match verdict {
+        Verdict::Winner(entry) | Verdict::Untested(entry) => writeln!(f, "  {} {} ({})", label, entry.protocol, figure(entry)),
+        Verdict::Tie(tied) => {
+            let tied: Vec<String> = tied.iter().map(|entry| format!("{} ({})", entry.protocol, figure(entry))).collect();
+            writeln!(f, "  {} tie: {}", label, tied.join(", "))
+        }
+    }
+}
+
+/// Operations whose payload is a list of points, which is what framing
+/// overhead is measured against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadOperation {
+    /// `ProtocolClient::submit_batch`; the points are the request
+    Submit,
+    /// `MetricsService::query_metrics`; the points are the response
+    Query,
+}
+
+impl PayloadOperation {
+    pub const ALL: [PayloadOperation; 2] = [PayloadOperation::Submit, PayloadOperation::Query];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PayloadOperation::Submit => "submit_batch",
+            PayloadOperation::Query => "query",
+        }
+    }
+}
+
+/// One operation's wire bytes split into the serialized points and the
+/// framing around them: HTTP headers, HTTP/2 and gRPC frames, Cap'n Proto
+/// segment tables and RPC envelopes, the query or acknowledgement, and TLS
+/// records when enabled
+#[derive(Debug, Clone)]
+pub struct FramingOverhead {
+    pub protocol: String,
+    pub operation: PayloadOperation,
+    pub points: usize,
+    /// Bytes written and read on the socket
+    pub wire: WireBytes,
+    /// What `ProtocolClient::payload_bytes` gives the points
+    pub payload_bytes: usize,
+}
+
+impl FramingOverhead {
+    pub fn wire_total(&self) -> u64 {
+        self.wire.written + self.wire.read
+    }
+
+    /// Wire bytes minus payload bytes. Negative when compression shrank the
+    /// points below their uncompressed serialization by more than the
+    /// framing added.
+    pub fn overhead_bytes(&self) -> i64 {
+        self.wire_total() as i64 - self.payload_bytes as i64
+    }
+
+    /// Overhead as a share of the wire bytes, 0.0 when nothing was sent
+    pub fn overhead_ratio(&self) -> f64 {
+        match self.wire_total() {
+            0 => 0.0,
+            total => self.overhead_bytes() as f64 / total as f64,
+        }
+    }
+}
+
+/// `FramingOverhead` rows for every protocol, operation and point count a run
+/// measured, displayed as one table
+#[derive(Debug, Clone, Default)]
+pub struct FramingReport {
+    rows: Vec<FramingOverhead>,
+}
+
+impl FramingReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, row: FramingOverhead) {
+        self.rows.push(row);
+    }
+
+    pub fn rows(&self) -> &[FramingOverhead] {
+        &self.rows
+    }
+
+    /// The row for `protocol` running `operation` over `points` points
+    pub fn get(&self, protocol: &str, operation: PayloadOperation, points: usize) -> Option<&FramingOverhead> {
+        self.rows
+            .iter()
+            .find(|row| row.protocol == protocol && row.operation == operation && row.points == points)
+    }
+}
+
+impl fmt::Display for FramingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  {:<24} {:<13} {:>7} {:>12} {:>12} {:>12} {:>9}",
+            "Protocol", "Operation", "Points", "Wire", "Payload", "Overhead", "Overhead%"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "  {:<24} {:<13} {:>7} {:>12} {:>12} {:>12} {:>8.1}%",
+                row.protocol,
+                row.operation.label(),
+                row.points,
+                row.wire_total(),
+                row.payload_bytes,
+                row.overhead_bytes(),
+                row.overhead_ratio() * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `operation` over `metrics` on `client` and split the bytes it put on
+/// the wire into payload and framing. A query asks for the time range
+/// `metrics` cover, so the service should hold exactly those points, as after
+/// a `Submit` on an emptied service; its payload is whatever came back.
+pub async fn measure_framing(
+    protocol: &str,
+    client: &dyn ProtocolClient,
+    operation: PayloadOperation,
+    metrics: &[MetricPoint],
+) -> Result<FramingOverhead, ProtocolError> {
+    let (payload_bytes, wire) = match operation {
+        PayloadOperation::Submit => {
+            let (result, wire) = wire_bytes::measure(client.wire_bytes(), client.submit_batch(metrics.to_vec())).await;
+            result?;
+            (client.payload_bytes(operation, metrics), wire)
+        }
+        PayloadOperation::Query => {
+            let query = MetricQuery {
+                start_time: metrics.iter().map(|metric| metric.timestamp).min().unwrap_or(0),
+                end_time: metrics.iter().map(|metric| metric.timestamp).max().unwrap_or(0),
+                hostname_filter: None,
+                limit: None,
+                offset: None,
+            };
+            let (result, wire) = wire_bytes::measure(client.wire_bytes(), client.query_metrics(query)).await;
+            (client.payload_bytes(operation, &result?), wire)
+        }
+    };
+    Ok(FramingOverhead {
+        protocol: protocol.to_string(),
+        operation,
+        points: metrics.len(),
+        wire,
+        payload_bytes,
+    })
+}
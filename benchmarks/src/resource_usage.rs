@@ -0,0 +1,189 @@
+use std::fmt;
+use std::ops::{AddAssign, Sub};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// What `getrusage` reports for this process: CPU time, context switches and
+/// peak resident set size. Only the harness is counted, not the services.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub user_cpu: Duration,
+    pub system_cpu: Duration,
+    /// Times the process gave up the CPU to wait, e.g. on a socket
+    pub voluntary_context_switches: u64,
+    /// Times the scheduler took the CPU away
+    pub involuntary_context_switches: u64,
+    /// Peak resident set size so far; in a difference, how much it rose
+    pub max_rss_bytes: u64,
+}
+
+impl ResourceUsage {
+    /// The process's usage so far, or `None` where `getrusage` isn't
+    /// available
+    #[cfg(unix)]
+    pub fn now() -> Option<Self> {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+        // SAFETY: getrusage only writes the struct it's given, and fills it
+        // in whenever it returns 0
+        let usage = unsafe {
+            if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+                return None;
+            }
+            usage.assume_init()
+        };
+        let cpu = |time: libc::timeval| Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64);
+        // Linux and the BSDs count ru_maxrss in KiB, Apple's kernels in bytes
+        let rss_unit = if cfg!(target_vendor = "apple") { 1 } else { 1024 };
+        Some(Self {
+            user_cpu: cpu(usage.ru_utime),
+            system_cpu: cpu(usage.ru_stime),
+            voluntary_context_switches: usage.ru_nvcsw as u64,
+            involuntary_context_switches: usage.ru_nivcsw as u64,
+            max_rss_bytes: usage.ru_maxrss as u64 * rss_unit,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn now() -> Option<Self> {
+        None
+    }
+}
+
+impl Sub for ResourceUsage {
+    type Output = ResourceUsage;
+
+    fn sub(self, earlier: ResourceUsage) -> ResourceUsage {
+        ResourceUsage {
+            user_cpu: self.user_cpu.saturating_sub(earlier.user_cpu),
+            system_cpu: self.system_cpu.saturating_sub(earlier.system_cpu),
+            voluntary_context_switches: self.voluntary_context_switches.saturating_sub(earlier.voluntary_context_switches),
+            involuntary_context_switches: self
+                .involuntary_context_switches
+                .saturating_sub(earlier.involuntary_context_switches),
+            max_rss_bytes: self.max_rss_bytes.saturating_sub(earlier.max_rss_bytes),
+        }
+    }
+}
+
+impl AddAssign for ResourceUsage {
+    fn add_assign(&mut self, other: ResourceUsage) {
+        self.user_cpu += other.user_cpu;
+        self.system_cpu += other.system_cpu;
+        self.voluntary_context_switches += other.voluntary_context_switches;
+        self.involuntary_context_switches += other.involuntary_context_switches;
+        self.max_rss_bytes += other.max_rss_bytes;
+    }
+}
+
+/// Usage added up over the iterations a benchmark group ran for one protocol
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupUsage {
+    pub group: String,
+    pub protocol: String,
+    pub iterations: u64,
+    pub usage: ResourceUsage,
+}
+
+impl GroupUsage {
+    /// CPU time, user and system, per iteration
+    pub fn cpu_per_iteration(&self) -> (Duration, Duration) {
+        let iterations = self.iterations.max(1) as f64;
+        (self.usage.user_cpu.div_f64(iterations), self.usage.system_cpu.div_f64(iterations))
+    }
+
+    /// Voluntary and involuntary context switches per 1000 iterations
+    pub fn context_switches_per_1000(&self) -> (f64, f64) {
+        let per_1000 = |switches: u64| switches as f64 * 1000.0 / self.iterations.max(1) as f64;
+        (
+            per_1000(self.usage.voluntary_context_switches),
+            per_1000(self.usage.involuntary_context_switches),
+        )
+    }
+}
+
+/// `GroupUsage` for every group and protocol measured, in the order each
+/// first appears
+#[derive(Debug, Clone, Default)]
+pub struct UsageReport {
+    rows: Vec<GroupUsage>,
+}
+
+impl UsageReport {
+    pub const fn new() -> Self {
+        Self { rows: Vec::new() }
+    }
+
+    /// Add `usage` over `iterations` more iterations to `group` and
+    /// `protocol`'s totals
+    pub fn add(&mut self, group: &str, protocol: &str, iterations: u64, usage: ResourceUsage) {
+        match self.rows.iter_mut().find(|row| row.group == group && row.protocol == protocol) {
+            Some(row) => {
+                row.iterations += iterations;
+                row.usage += usage;
+            }
+            None => self.rows.push(GroupUsage {
+                group: group.to_string(),
+                protocol: protocol.to_string(),
+                iterations,
+                usage,
+            }),
+        }
+    }
+
+    pub fn rows(&self) -> &[GroupUsage] {
+        &self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+impl fmt::Display for UsageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  {:<24} {:<24} {:>10} {:>12} {:>12} {:>12} {:>12} {:>13}",
+            "Group", "Protocol", "Iterations", "User µs/it", "System µs/it", "Vol cs/1k", "Invol cs/1k", "Peak RSS +KiB"
+        )?;
+        for row in &self.rows {
+            let (user, system) = row.cpu_per_iteration();
+            let (voluntary, involuntary) = row.context_switches_per_1000();
+            writeln!(
+                f,
+                "  {:<24} {:<24} {:>10} {:>12.1} {:>12.1} {:>12.1} {:>12.1} {:>13}",
+                row.group,
+                row.protocol,
+                row.iterations,
+                user.as_secs_f64() * 1e6,
+                system.as_secs_f64() * 1e6,
+                voluntary,
+                involuntary,
+                row.usage.max_rss_bytes / 1024
+            )?;
+        }
+        Ok(())
+    }
+}
+
+static RECORDED: Mutex<UsageReport> = Mutex::new(UsageReport::new());
+
+/// Run `routine` `iterations` times and add the `getrusage` difference to
+/// `group` and `protocol`'s totals, for `recorded` to report. Two syscalls
+/// per call, so it's meant for a whole batch of iterations, like the one
+/// criterion's `iter_custom` hands over. Records nothing where `getrusage`
+/// isn't available.
+pub fn measure_batch<O>(group: &str, protocol: &str, iterations: u64, mut routine: impl FnMut() -> O) {
+    let before = ResourceUsage::now();
+    for _ in 0..iterations {
+        std::hint::black_box(routine());
+    }
+    if let (Some(before), Some(after)) = (before, ResourceUsage::now()) {
+        RECORDED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).add(group, protocol, iterations, after - before);
+    }
+}
+
+/// Everything `measure_batch` has recorded so far
+pub fn recorded() -> UsageReport {
+    RECORDED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
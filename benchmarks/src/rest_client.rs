@@ -1,67 +1,654 @@
-use reqwest::Client;
-use shared::{MetricPoint, MetricQuery, MetricStatistics};
+use futures_util::{Stream, TryStreamExt};
+use reqwest::{header, Certificate, Client, ClientBuilder, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use shared::{AuthConfig, BodyEncoding, DnsConfig, HttpCompression, MetricPoint, MetricQuery, MetricRollup, MetricStatistics, PayloadCipher, RestPool, StorageStats};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::connection::{self, ConnectionTimings};
+use crate::report::PayloadOperation;
+use crate::wire_bytes::{self, WireCounter};
+use crate::ProtocolError;
+
+const PROTOCOL: &str = "REST";
+
+/// Bytes exchanged with `rest-service`; only counted with `PROTOBENCH_REST_WIRE_BYTES`
+pub static WIRE_BYTES: WireCounter = WireCounter::new();
 
 static CLIENT: OnceLock<Client> = OnceLock::new();
+static THROTTLED: AtomicU64 = AtomicU64::new(0);
+static TARGET: OnceLock<String> = OnceLock::new();
+static BODY_ENCODING: OnceLock<BodyEncoding> = OnceLock::new();
+static RELAY: OnceLock<Option<SocketAddr>> = OnceLock::new();
+
+#[derive(Deserialize)]
+struct DeleteSummary {
+    deleted: u64,
+}
+
+/// Encodings the client advertises in `Accept-Encoding`, from
+/// `PROTOBENCH_REST_ACCEPT_ENCODING` (e.g. `gzip,br,zstd`; unset means identity)
+pub fn accept_encoding() -> HttpCompression {
+    HttpCompression::from_env("PROTOBENCH_REST_ACCEPT_ENCODING")
+}
+
+/// Body format for submitted and queried metrics, from `PROTOBENCH_REST_ENCODING`
+pub fn body_encoding() -> BodyEncoding {
+    *BODY_ENCODING.get_or_init(BodyEncoding::from_env)
+}
+
+/// PEM certificate to trust for HTTPS, from `PROTOBENCH_REST_CA_CERT`. Setting
+/// it switches the client to `https://`; point it at the certificate
+/// `rest-service` generated to trust a self-signed setup.
+pub fn ca_cert_path() -> Option<String> {
+    std::env::var("PROTOBENCH_REST_CA_CERT").ok()
+}
+
+/// Whether requests go over TLS
+pub fn uses_tls() -> bool {
+    ca_cert_path().is_some()
+}
+
+/// Server address, from `PROTOBENCH_REST_TARGET`, then `targets.rest` in
+/// `protobench.toml` (default `127.0.0.1:3000`)
+pub fn target() -> &'static str {
+    TARGET.get_or_init(|| shared::target_addr("PROTOBENCH_REST_TARGET", shared::FileConfig::get().targets.rest.as_deref(), "127.0.0.1:3000"))
+}
+
+/// Whether traffic is counted in `WIRE_BYTES`, from `PROTOBENCH_REST_WIRE_BYTES`.
+/// reqwest doesn't let its connections be wrapped, so counting routes them
+/// through a loopback relay, which adds a hop to every request.
+pub fn counts_wire_bytes() -> bool {
+    std::env::var_os("PROTOBENCH_REST_WIRE_BYTES").is_some()
+}
+
+/// Local relay address standing in for `target()` while bytes are counted
+fn relay() -> Result<Option<SocketAddr>, ProtocolError> {
+    if let Some(relay) = RELAY.get() {
+        return Ok(*relay);
+    }
+    if !counts_wire_bytes() {
+        return Ok(*RELAY.get_or_init(|| None));
+    }
 
-fn get_client() -> &'static Client {
-    CLIENT.get_or_init(|| {
-        Client::builder()
-            .http2_prior_knowledge() // Use HTTP/2 for fair comparison with gRPC
-            .build()
-            .expect("Failed to create HTTP/2 client")
+    // The relay connects on the client's behalf, so it resolves as the client would
+    let upstream = match DnsConfig::get().resolve(target()) {
+        Ok(Some(addrs)) if !addrs.is_empty() => addrs[0].to_string(),
+        _ => target().to_string(),
+    };
+    let relay = wire_bytes::spawn_relay(upstream, &WIRE_BYTES).map_err(|e| ProtocolError::connect(PROTOCOL, e))?;
+
+    // Another caller may have started one meanwhile; either relay counts into `WIRE_BYTES`
+    Ok(*RELAY.get_or_init(|| Some(relay)))
+}
+
+fn endpoint(path: &str) -> Result<String, ProtocolError> {
+    let scheme = if uses_tls() { "https" } else { "http" };
+    Ok(match relay()? {
+        Some(relay) => format!("{}://{}{}", scheme, relay, path),
+        None => format!("{}://{}{}", scheme, target(), path),
     })
 }
 
-pub async fn submit_metric(metric: MetricPoint) -> anyhow::Result<()> {
-    let client = get_client();
-    let response = client
-        .post("http://127.0.0.1:3000/metrics")
-        .json(&metric)
-        .send()
-        .await?;
+fn client_builder() -> Result<ClientBuilder, ProtocolError> {
+    let mut builder = Client::builder()
+        .http2_prior_knowledge(); // Use HTTP/2 for fair comparison with gRPC
     
-    if !response.status().is_success() {
-        anyhow::bail!("REST submit failed: {}", response.status());
+    // Sent on every request when PROTOBENCH_AUTH_TOKEN is set
+    if let Some(auth) = AuthConfig::from_env() {
+        let mut headers = header::HeaderMap::new();
+        let value = format!("Bearer {}", auth.token).parse().map_err(|e| ProtocolError::serialize(PROTOCOL, e))?;
+        headers.insert(header::AUTHORIZATION, value);
+        builder = builder.default_headers(headers);
     }
     
-    Ok(())
+    // Only the lookup is replaced; URLs keep the hostname for TLS and Host
+    if let Some((host, _)) = shared::split_host_port(target()).filter(|(host, _)| host.parse::<IpAddr>().is_err()) {
+        if let Some(addrs) = DnsConfig::get().resolve(target()).map_err(|e| ProtocolError::connect(PROTOCOL, e))? {
+            builder = builder.resolve_to_addrs(host, &addrs);
+        }
+    }
+    
+    if let Some(path) = ca_cert_path() {
+        let pem = std::fs::read(&path).map_err(|e| ProtocolError::connect(PROTOCOL, e))?;
+        let certificate = Certificate::from_pem(&pem).map_err(|e| ProtocolError::connect(PROTOCOL, e))?;
+        builder = builder
+            .use_rustls_tls()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(certificate);
+    }
+    
+    Ok(builder)
+}
+
+/// Connection pool settings, from `[rest_pool]` in `protobench.toml`
+pub fn pool_settings() -> RestPool {
+    shared::FileConfig::get().rest_pool
+}
+
+fn with_pool(mut builder: ClientBuilder, pool: RestPool) -> ClientBuilder {
+    if let Some(max_idle) = pool.max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(idle_timeout) = pool.idle_timeout_ms {
+        builder = builder.pool_idle_timeout(Duration::from_millis(idle_timeout));
+    }
+    if let Some(adaptive) = pool.http2_adaptive_window {
+        builder = builder.http2_adaptive_window(adaptive);
+    }
+    if let Some(nodelay) = pool.tcp_nodelay {
+        builder = builder.tcp_nodelay(nodelay);
+    }
+    builder
+}
+
+/// A client configured like the shared one except for its pool settings,
+/// for comparing pool configurations side by side
+pub fn pooled_client(pool: RestPool) -> Result<Client, ProtocolError> {
+    let encoding = accept_encoding();
+    // reqwest sets Accept-Encoding and decompresses transparently for each enabled codec
+    let mut builder = with_pool(client_builder()?, pool);
+    // Covers connecting through reading the whole body; a request's own
+    // timeout replaces it
+    if let Some(timeout) = crate::client_timeout() {
+        builder = builder.timeout(timeout);
+    }
+    builder
+        .gzip(encoding.gzip)
+        .brotli(encoding.br)
+        .zstd(encoding.zstd)
+        .build()
+        .map_err(|e| ProtocolError::connect(PROTOCOL, e))
 }
 
-pub async fn query_metrics(query: MetricQuery) -> anyhow::Result<Vec<MetricPoint>> {
-    let client = get_client();
-    let mut url = "http://127.0.0.1:3000/metrics".to_string();
-    url.push_str(&format!("?start_time={}&end_time={}", query.start_time, query.end_time));
+fn get_client() -> Result<&'static Client, ProtocolError> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    let client = pooled_client(pool_settings())?;
+
+    // Another caller may have built one meanwhile; either client will do
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// How many times a `429` is retried, from `PROTOBENCH_REST_MAX_RETRIES`
+/// (default 3). Set it to 0 to surface throttling as request failures.
+fn max_retries() -> u32 {
+    std::env::var("PROTOBENCH_REST_MAX_RETRIES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3)
+}
+
+/// Number of `429 Too Many Requests` responses received so far, retried or not
+pub fn throttled_responses() -> u64 {
+    THROTTLED.load(Ordering::Relaxed)
+}
+
+/// Sort a reqwest failure into its `ProtocolError` category
+fn request_error(error: reqwest::Error) -> ProtocolError {
+    if error.is_timeout() {
+        ProtocolError::Timeout { protocol: PROTOCOL }
+    } else if error.is_decode() {
+        ProtocolError::decode(PROTOCOL, error)
+    } else if error.is_builder() {
+        ProtocolError::serialize(PROTOCOL, error)
+    } else {
+        ProtocolError::connect(PROTOCOL, error)
+    }
+}
+
+/// Send a request, waiting out the server's `Retry-After` (in seconds,
+/// defaulting to 1) and trying again whenever it answers `429`. Carries a
+/// new `x-request-id`, kept across retries, and `traceparent` when the caller
+/// is traced.
+async fn send(request: RequestBuilder) -> Result<Response, ProtocolError> {
+    let request = request.header(shared::REQUEST_ID, shared::next_request_id());
+    let request = match shared::current_traceparent() {
+        Some(traceparent) => request.header(shared::TRACEPARENT, traceparent),
+        None => request,
+    };
+    let cipher = PayloadCipher::get();
+    let request = match cipher {
+        Some(cipher) => seal_request(request, cipher)?,
+        None => request,
+    };
+    let mut retries = 0;
+    loop {
+        let attempt = request
+            .try_clone()
+            .ok_or_else(|| ProtocolError::serialize(PROTOCOL, anyhow::anyhow!("request body can't be retried")))?;
+        let response = attempt.send().await.map_err(request_error)?;
+        
+        let throttled = response.status() == StatusCode::TOO_MANY_REQUESTS;
+        if throttled {
+            THROTTLED.fetch_add(1, Ordering::Relaxed);
+        }
+        if !throttled || retries >= max_retries() {
+            return match cipher {
+                Some(cipher) => open_response(response, cipher).await,
+                None => Ok(response),
+            };
+        }
+        
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1);
+        tokio::time::sleep(Duration::from_secs(retry_after)).await;
+        retries += 1;
+    }
+}
+
+/// Seal the request's body, if it has one, and name the cipher in
+/// `x-payload-cipher`, which `rest-service` requires of every request once it
+/// has a payload key
+fn seal_request(request: RequestBuilder, cipher: &PayloadCipher) -> Result<RequestBuilder, ProtocolError> {
+    let (client, request) = request.build_split();
+    let mut request = request.map_err(|e| ProtocolError::serialize(PROTOCOL, e))?;
+    if let Some(sealed) = request.body().and_then(|body| body.as_bytes()).map(|body| cipher.seal(body)) {
+        *request.body_mut() = Some(sealed.into());
+    }
+    request
+        .headers_mut()
+        .insert(shared::PAYLOAD_CIPHER, header::HeaderValue::from_static(PayloadCipher::NAME));
+    Ok(RequestBuilder::from_parts(client, request))
+}
+
+/// `response` with its body opened, when the service sealed it. Streamed
+/// responses and errors from layers outside the sealing come back as sent.
+async fn open_response(response: Response, cipher: &PayloadCipher) -> Result<Response, ProtocolError> {
+    if !response.headers().contains_key(shared::PAYLOAD_CIPHER) {
+        return Ok(response);
+    }
+    let mut opened = http::Response::builder().status(response.status()).version(response.version());
+    for (name, value) in response.headers() {
+        if *name != header::CONTENT_LENGTH && name.as_str() != shared::PAYLOAD_CIPHER {
+            opened = opened.header(name, value);
+        }
+    }
+    let sealed = response.bytes().await.map_err(request_error)?;
+    let body = cipher.open(&sealed).map_err(|e| ProtocolError::decode(PROTOCOL, e))?;
+    let opened = opened.body(body).map_err(|e| ProtocolError::decode(PROTOCOL, e))?;
+    Ok(Response::from(opened))
+}
+
+/// `rest-service`'s JSON error body
+#[derive(Deserialize)]
+struct ErrorBody {
+    code: String,
+    message: String,
+    #[serde(default)]
+    details: Option<serde_json::Value>,
+}
+
+/// Pass successful responses through; turn anything else into
+/// `ProtocolError::Server`, coded with the error body's `code`, or with the
+/// HTTP status for responses that never reached a handler (such as an
+/// unknown route)
+async fn error_for_status(response: Response) -> Result<Response, ProtocolError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
     
-    if let Some(hostname) = query.hostname_filter {
-        url.push_str(&format!("&hostname_filter={}", hostname));
+    let (code, message) = match serde_json::from_str::<ErrorBody>(&body) {
+        Ok(ErrorBody { code, message, details: Some(details) }) => (code, format!("{} ({})", message, details)),
+        Ok(ErrorBody { code, message, details: None }) => (code, message),
+        Err(_) => (status.as_str().to_string(), if body.is_empty() { status.to_string() } else { body }),
+    };
+    Err(ProtocolError::Server { protocol: PROTOCOL, code, message })
+}
+
+/// Query-string pairs for `query`'s time range and hostname filter
+fn range_params(query: &MetricQuery) -> Vec<(&'static str, String)> {
+    let mut params = vec![
+        ("start_time", query.start_time.to_string()),
+        ("end_time", query.end_time.to_string()),
+    ];
+    if let Some(hostname) = &query.hostname_filter {
+        params.push(("hostname_filter", hostname.clone()));
+    }
+    params
+}
+
+/// Query-string pairs for every field of `query` that is set. Pass them to
+/// `RequestBuilder::query`, which percent-encodes them, so hostnames with
+/// spaces, `+`, `&` or non-ASCII characters reach the server unchanged.
+pub fn query_params(query: &MetricQuery) -> Vec<(&'static str, String)> {
+    let mut params = range_params(query);
+    if let Some(limit) = query.limit {
+        params.push(("limit", limit.to_string()));
     }
+    if let Some(offset) = query.offset {
+        params.push(("offset", offset.to_string()));
+    }
+    params
+}
+
+/// Ask the server which content encoding it picks for our `Accept-Encoding`.
+/// Uses a separate non-decompressing client, since reqwest strips the
+/// `Content-Encoding` header from responses it decompresses.
+pub async fn negotiated_encoding() -> Result<String, ProtocolError> {
+    let probe = client_builder()?
+        .no_gzip()
+        .no_brotli()
+        .no_zstd()
+        .build()
+        .map_err(|e| ProtocolError::connect(PROTOCOL, e))?;
+    
+    let mut request = probe.get(endpoint("/admin/storage")?).header(header::ACCEPT_ENCODING, accept_encoding().label());
+    if PayloadCipher::get().is_some() {
+        request = request.header(shared::PAYLOAD_CIPHER, PayloadCipher::NAME);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(request_error)?;
+    
+    let encoding = response
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("identity");
+    Ok(encoding.to_string())
+}
+
+pub async fn submit_metric(metric: MetricPoint) -> Result<(), ProtocolError> {
+    let client = get_client()?;
+    let encoding = body_encoding();
+    let request = client
+        .post(endpoint("/metrics")?)
+        .header(header::CONTENT_TYPE, encoding.content_type())
+        .body(encoding.encode(&metric).map_err(|e| ProtocolError::serialize(PROTOCOL, e))?);
+    let response = send(request).await?;
     
-    let response = client.get(&url).send().await?;
+    error_for_status(response).await?;
     
-    if !response.status().is_success() {
-        anyhow::bail!("REST query failed: {}", response.status());
+    Ok(())
+}
+
+/// Body of a `201` from `POST /metrics/batch`
+#[derive(Deserialize)]
+struct BatchSummary {
+    accepted: u64,
+}
+
+/// Store `metrics` with one `POST /metrics/batch`; the server stores them
+/// all-or-nothing and returns how many points were written
+pub async fn submit_metrics(metrics: Vec<MetricPoint>) -> Result<u64, ProtocolError> {
+    submit_metrics_with_client(get_client()?, metrics).await
+}
+
+/// `submit_metrics` sent through `client`, such as one from `pooled_client`
+pub async fn submit_metrics_with_client(client: &Client, metrics: Vec<MetricPoint>) -> Result<u64, ProtocolError> {
+    let encoding = body_encoding();
+    let request = client
+        .post(endpoint("/metrics/batch")?)
+        .header(header::CONTENT_TYPE, encoding.content_type())
+        .header(header::ACCEPT, encoding.content_type())
+        .body(encoding.encode(&metrics).map_err(|e| ProtocolError::serialize(PROTOCOL, e))?);
+    let response = send(request).await?;
+    
+    let response = error_for_status(response).await?;
+    
+    let summary: BatchSummary = encoding
+        .decode(&response.bytes().await.map_err(request_error)?)
+        .map_err(|e| ProtocolError::decode(PROTOCOL, e))?;
+    Ok(summary.accepted)
+}
+
+pub async fn query_metrics(query: MetricQuery) -> Result<Vec<MetricPoint>, ProtocolError> {
+    query_metrics_within(get_client()?, query, None).await
+}
+
+/// `query_metrics` sent through `client`, such as one from `pooled_client`
+pub async fn query_metrics_with_client(client: &Client, query: MetricQuery) -> Result<Vec<MetricPoint>, ProtocolError> {
+    query_metrics_within(client, query, None).await
+}
+
+/// `query_metrics` with its own timeout instead of `PROTOBENCH_CLIENT_TIMEOUT_MS`;
+/// expiry is reported as `ProtocolError::Timeout`
+pub async fn query_metrics_with_timeout(query: MetricQuery, timeout: Duration) -> Result<Vec<MetricPoint>, ProtocolError> {
+    query_metrics_within(get_client()?, query, Some(timeout)).await
+}
+
+async fn query_metrics_within(client: &Client, query: MetricQuery, timeout: Option<Duration>) -> Result<Vec<MetricPoint>, ProtocolError> {
+    let encoding = body_encoding();
+    let mut request = client
+        .get(endpoint("/metrics")?)
+        .query(&query_params(&query))
+        .header(header::ACCEPT, encoding.content_type());
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
     }
+    let response = send(request).await?;
+    
+    let response = error_for_status(response).await?;
     
-    let metrics: Vec<MetricPoint> = response.json().await?;
+    let metrics: Vec<MetricPoint> = encoding
+        .decode(&response.bytes().await.map_err(request_error)?)
+        .map_err(|e| ProtocolError::decode(PROTOCOL, e))?;
     Ok(metrics)
 }
 
-pub async fn get_statistics(query: MetricQuery) -> anyhow::Result<MetricStatistics> {
-    let client = get_client();
-    let mut url = "http://127.0.0.1:3000/statistics".to_string();
-    url.push_str(&format!("?start_time={}&end_time={}", query.start_time, query.end_time));
+/// `query_metrics` answered as an Arrow IPC stream, a column per field,
+/// instead of rows in `body_encoding()`
+pub async fn query_metrics_arrow(query: MetricQuery) -> Result<Vec<MetricPoint>, ProtocolError> {
+    let request = get_client()?
+        .get(endpoint("/metrics")?)
+        .query(&query_params(&query))
+        .header(header::ACCEPT, shared::arrow_ipc::ARROW_STREAM);
+    let response = error_for_status(send(request).await?).await?;
+    shared::arrow_ipc::decode(&response.bytes().await.map_err(request_error)?).map_err(|e| ProtocolError::decode(PROTOCOL, e))
+}
+
+/// Query via `GET /metrics/stream`, parsing each NDJSON line as soon as its
+/// chunk arrives instead of buffering the whole body first
+pub async fn stream_metrics(query: MetricQuery) -> Result<Vec<MetricPoint>, ProtocolError> {
+    query_metrics_stream(query).await?.try_collect().await
+}
+
+/// `stream_metrics` yielding each point as its line arrives instead of
+/// collecting them, for time-to-first-point measurements and for consuming
+/// large results in bounded memory. Dropping the stream cancels the request.
+pub async fn query_metrics_stream(query: MetricQuery) -> Result<impl Stream<Item = Result<MetricPoint, ProtocolError>>, ProtocolError> {
+    let client = get_client()?;
+    let response = send(client.get(endpoint("/metrics/stream")?).query(&query_params(&query))).await?;
     
-    if let Some(hostname) = query.hostname_filter {
-        url.push_str(&format!("&hostname_filter={}", hostname));
-    }
+    let response = error_for_status(response).await?;
+    
+    let metrics = futures_util::stream::try_unfold((response, Vec::new()), |(mut response, mut pending)| async move {
+        loop {
+            // Chunk boundaries don't line up with records; keep any partial line for the next chunk
+            if let Some(newline) = pending.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                let metric: MetricPoint = serde_json::from_slice(&line[..newline]).map_err(|e| ProtocolError::decode(PROTOCOL, e))?;
+                return Ok(Some((metric, (response, pending))));
+            }
+            
+            match response.chunk().await.map_err(request_error)? {
+                Some(chunk) => pending.extend_from_slice(&chunk),
+                None if pending.iter().all(u8::is_ascii_whitespace) => return Ok(None),
+                None => return Err(ProtocolError::decode(PROTOCOL, anyhow::anyhow!("stream ended mid-record"))),
+            }
+        }
+    });
+    Ok(metrics)
+}
+
+/// Open a `/metrics/subscribe` event stream for `query`, submit `metrics`,
+/// and wait until every matching point has been pushed back. Returns the
+/// number of points received.
+pub async fn tail_metrics(query: MetricQuery, metrics: Vec<MetricPoint>) -> Result<usize, ProtocolError> {
+    let client = get_client()?;
+    let request = client
+        .get(endpoint("/metrics/subscribe")?)
+        .query(&range_params(&query))
+        .header(header::ACCEPT, "text/event-stream");
+    let response = send(request).await?;
+    let mut response = error_for_status(response).await?;
     
-    let response = client.get(&url).send().await?;
+    let expected = metrics.iter().filter(|metric| query.matches(metric)).count();
     
-    if !response.status().is_success() {
-        anyhow::bail!("REST statistics failed: {}", response.status());
-    }
+    // The server holds the subscription from before the response headers
+    // were sent, so points submitted from here on are all delivered
+    let submit = async {
+        for metric in metrics {
+            submit_metric(metric).await?;
+        }
+        Ok::<(), ProtocolError>(())
+    };
+    
+    let receive = async {
+        let mut received = 0;
+        let mut pending: Vec<u8> = Vec::new();
+        while received < expected {
+            let Some(chunk) = response.chunk().await.map_err(request_error)? else {
+                let closed = anyhow::anyhow!("subscription closed after {} of {} points", received, expected);
+                return Err(ProtocolError::connect(PROTOCOL, closed));
+            };
+            pending.extend_from_slice(&chunk);
+            
+            // Each event carries one `data:` line; keep-alive comments and
+            // blank separators are skipped
+            while let Some(newline) = pending.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                if let Some(data) = line[..newline].strip_prefix(b"data:") {
+                    let _: MetricPoint = serde_json::from_slice(data.trim_ascii()).map_err(|e| ProtocolError::decode(PROTOCOL, e))?;
+                    received += 1;
+                }
+            }
+        }
+        Ok(received)
+    };
+    
+    let ((), received) = tokio::try_join!(submit, receive)?;
+    Ok(received)
+}
+
+pub async fn get_statistics(query: MetricQuery) -> Result<MetricStatistics, ProtocolError> {
+    let client = get_client()?;
+    let response = send(client.get(endpoint("/statistics")?).query(&query_params(&query))).await?;
+    
+    let response = error_for_status(response).await?;
+    
+    let stats: MetricStatistics = response.json().await.map_err(request_error)?;
+    Ok(stats)
+}
+pub async fn query_rollups(query: MetricQuery) -> Result<Vec<MetricRollup>, ProtocolError> {
+    let client = get_client()?;
+    let response = send(client.get(endpoint("/rollups")?).query(&query_params(&query))).await?;
+    
+    let response = error_for_status(response).await?;
+    
+    let rollups: Vec<MetricRollup> = response.json().await.map_err(request_error)?;
+    Ok(rollups)
+}
+
+pub async fn delete_metrics(query: MetricQuery) -> Result<u64, ProtocolError> {
+    let client = get_client()?;
+    let response = send(client.delete(endpoint("/metrics")?).query(&range_params(&query))).await?;
+    
+    let response = error_for_status(response).await?;
+    
+    let summary: DeleteSummary = response.json().await.map_err(request_error)?;
+    Ok(summary.deleted)
+}
+
+pub async fn get_storage_stats() -> Result<StorageStats, ProtocolError> {
+    get_storage_stats_with_client(get_client()?).await
+}
+
+/// `get_storage_stats` sent through `client`, such as one from `pooled_client`
+pub async fn get_storage_stats_with_client(client: &Client) -> Result<StorageStats, ProtocolError> {
+    let response = send(client.get(endpoint("/admin/storage")?)).await?;
+    
+    let response = error_for_status(response).await?;
     
-    let stats: MetricStatistics = response.json().await?;
+    let stats: StorageStats = response.json().await.map_err(request_error)?;
     Ok(stats)
-}
\ No newline at end of file
+}
+
+/// Time a new connection: a fresh client configured like the shared one
+/// sends `get_storage_stats` twice. reqwest connects lazily, so the first
+/// request carries the TCP connect, TLS and HTTP/2 handshake. Through the
+/// wire byte relay, the relay's own hop to the service is included.
+pub async fn connection_timings() -> Result<ConnectionTimings, ProtocolError> {
+    let connect_error = |e| ProtocolError::connect(PROTOCOL, e);
+    let (addrs, dns_lookup) = connection::lookup(target()).await.map_err(connect_error)?;
+    let socket_connect = connection::tcp_connect_time(&addrs).await.map_err(connect_error)?;
+    let client = pooled_client(pool_settings())?;
+    let (first, first_request) = connection::timed(get_storage_stats_with_client(&client)).await;
+    first?;
+    let (warm, warm_request) = connection::timed(get_storage_stats_with_client(&client)).await;
+    warm?;
+    Ok(ConnectionTimings { dns_lookup, socket_connect, first_request, warm_request })
+}
+
+/// Bytes `metrics` take as a batch submit or query response body in the
+/// active `body_encoding`, before any content encoding
+pub fn payload_bytes(_operation: PayloadOperation, metrics: &[MetricPoint]) -> usize {
+    body_encoding().encode(&metrics).map_or(0, |body| body.len())
+}
+
+/// The REST client as a `shared::MetricsService` and `ProtocolClient`
+pub struct RestClient;
+
+#[async_trait::async_trait(?Send)]
+impl shared::MetricsService for RestClient {
+    type Error = ProtocolError;
+
+    async fn submit_metric(&self, metric: MetricPoint) -> Result<(), ProtocolError> {
+        submit_metric(metric).await
+    }
+
+    async fn query_metrics(&self, query: MetricQuery) -> Result<Vec<MetricPoint>, ProtocolError> {
+        query_metrics(query).await
+    }
+
+    async fn get_statistics(&self, query: MetricQuery) -> Result<MetricStatistics, ProtocolError> {
+        get_statistics(query).await
+    }
+
+    async fn delete_metrics(&self, query: MetricQuery) -> Result<u64, ProtocolError> {
+        delete_metrics(query).await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl crate::ProtocolClient for RestClient {
+    async fn submit_batch(&self, metrics: Vec<MetricPoint>) -> Result<u64, ProtocolError> {
+        submit_metrics(metrics).await
+    }
+
+    async fn query_rollups(&self, query: MetricQuery) -> Result<Vec<MetricRollup>, ProtocolError> {
+        query_rollups(query).await
+    }
+
+    async fn query_with_timeout(&self, query: MetricQuery, timeout: Duration) -> Result<Vec<MetricPoint>, ProtocolError> {
+        query_metrics_with_timeout(query, timeout).await
+    }
+
+    fn wire_bytes(&self) -> &'static WireCounter {
+        &WIRE_BYTES
+    }
+
+    fn payload_bytes(&self, operation: PayloadOperation, metrics: &[MetricPoint]) -> usize {
+        payload_bytes(operation, metrics)
+    }
+
+    fn metrics_exporter_env(&self) -> &'static str {
+        "PROTOBENCH_REST_METRICS_ADDR"
+    }
+
+    async fn connection_timings(&self) -> Result<ConnectionTimings, ProtocolError> {
+        connection_timings().await
+    }
+}
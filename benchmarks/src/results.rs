@@ -0,0 +1,302 @@
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use shared::{OperationStats, ServerStats};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::report::{cost_score, ComparisonReport};
+use crate::{BenchmarkMetrics, PayloadSizes};
+
+/// Version of the `BenchmarkRun` layout. Adding a field with a default keeps
+/// the version; renaming, removing or changing the meaning of one bumps it,
+/// and `BenchmarkRun::from_json` refuses runs newer than it understands.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// File named by `PROTOBENCH_RESULTS`, which the CLI and criterion runs both
+/// add their results to when set
+pub fn results_path_from_env() -> Option<PathBuf> {
+    std::env::var_os("PROTOBENCH_RESULTS").map(PathBuf::from)
+}
+
+/// Milliseconds since the Unix epoch
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Key of the harness's own allocator in `BenchmarkRun::allocators`
+pub const HARNESS: &str = "harness";
+
+// Settings recorded as set but never with their value
+const REDACTED_SETTINGS: [&str; 2] = ["PROTOBENCH_AUTH_TOKEN", "PROTOBENCH_PAYLOAD_KEY"];
+
+/// Everything one harness run measured, in the form exports, comparisons and
+/// reports read back. Durations are whole nanoseconds and sizes whole bytes,
+/// so a run round-trips through JSON unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkRun {
+    pub schema_version: u32,
+    /// Milliseconds since the Unix epoch when the run started
+    pub started_at_ms: u64,
+    /// Version of the `benchmarks` crate that produced the run
+    pub harness_version: String,
+    /// `PROTOBENCH_*` variables set for the run, which decide the protocol
+    /// variants measured. `PROTOBENCH_AUTH_TOKEN` and `PROTOBENCH_PAYLOAD_KEY` are
+    /// recorded as `<redacted>`.
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+    /// Allocator each process was built with, from `shared::allocator_name`:
+    /// the harness's under `harness`, and a service's under its name once
+    /// its `/stats` has been read. It can outweigh the protocol in memory
+    /// and latency figures, so runs built differently don't compare fairly.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub allocators: BTreeMap<String, String>,
+    #[serde(default)]
+    pub results: Vec<OperationResult>,
+    /// Services that exited while the run was measuring and were started
+    /// again by the harness. Results taken around a restart include
+    /// failed requests and cold starts, so they don't compare fairly.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restarts: Vec<ServiceRestart>,
+}
+
+/// A service process the harness found exited and started again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceRestart {
+    /// Binary name, like `capnp-service`
+    pub service: String,
+    /// How the process ended, e.g. `exit status: 101` for a panic
+    pub exit_status: String,
+    /// Milliseconds since the Unix epoch when the exit was noticed
+    pub at_ms: u64,
+}
+
+/// One measured operation: a protocol label from `protocol_clients` (or a
+/// group's own, like `REST/ndjson`), what it did and over how many points
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OperationResult {
+    pub protocol: String,
+    pub operation: String,
+    pub points: usize,
+    pub latency_ns: u64,
+    /// Bounds of the 95% confidence interval on `latency_ns`, for results
+    /// estimated from many samples such as criterion's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ci_ns: Option<[u64; 2]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_to_first_byte_ns: Option<u64>,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub memory_allocated: u64,
+    pub cpu_cycles: u64,
+    /// Energy the whole machine drew during the operation, in microjoules,
+    /// when RAPL was sampled (`PROTOBENCH_RAPL`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub energy_uj: Option<u64>,
+    /// Serialized points, when the framing overhead was worked out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_bytes: Option<u64>,
+    /// Whether `request_bytes` and `response_bytes` came from a size
+    /// estimator rather than the socket
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub payload_estimated: bool,
+    /// Time the service spent handling the operation's requests, from its
+    /// `/stats` endpoint, when the service had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_time_ns: Option<u64>,
+    /// Bytes the service allocated handling the operation's requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_allocated_bytes: Option<u64>,
+}
+
+/// Totals for one protocol across a run's results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolSummary {
+    pub protocol: String,
+    pub operations: usize,
+    pub mean_latency_ns: u64,
+    pub total_bytes: u64,
+    pub mean_cost_score: f64,
+}
+
+impl BenchmarkRun {
+    /// An empty run stamped with the current time and settings
+    pub fn new() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            started_at_ms: now_ms(),
+            harness_version: env!("CARGO_PKG_VERSION").to_string(),
+            settings: settings_from_env(),
+            allocators: [(HARNESS.to_string(), shared::allocator_name().to_string())].into_iter().collect(),
+            results: Vec::new(),
+            restarts: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, result: OperationResult) {
+        self.results.push(result);
+    }
+
+    /// Record the allocator of the service `stats` were read from, unless
+    /// it is too old to report one
+    pub fn record_server_allocator(&mut self, stats: &ServerStats) {
+        if !stats.allocator.is_empty() {
+            self.allocators.insert(stats.service.clone(), stats.allocator.clone());
+        }
+    }
+
+    /// One summary per protocol, in the order each first appears
+    pub fn summaries(&self) -> Vec<ProtocolSummary> {
+        let mut protocols: Vec<&str> = Vec::new();
+        for result in &self.results {
+            if !protocols.contains(&result.protocol.as_str()) {
+                protocols.push(&result.protocol);
+            }
+        }
+        protocols
+            .into_iter()
+            .map(|protocol| {
+                let results: Vec<&OperationResult> = self.results.iter().filter(|result| result.protocol == protocol).collect();
+                let count = results.len();
+                ProtocolSummary {
+                    protocol: protocol.to_string(),
+                    operations: count,
+                    mean_latency_ns: results.iter().map(|result| result.latency_ns).sum::<u64>() / count as u64,
+                    total_bytes: results.iter().map(|result| result.request_bytes + result.response_bytes).sum(),
+                    mean_cost_score: results.iter().map(|result| cost_score(&result.metrics())).sum::<f64>() / count as f64,
+                }
+            })
+            .collect()
+    }
+
+    /// The results for `operation` over `points` points ranked as a
+    /// `ComparisonReport`, or `None` when the run has none
+    pub fn comparison(&self, operation: &str, points: usize) -> Option<ComparisonReport> {
+        let results: Vec<(&str, BenchmarkMetrics)> = self
+            .results
+            .iter()
+            .filter(|result| result.operation == operation && result.points == points)
+            .map(|result| (result.protocol.as_str(), result.metrics()))
+            .collect();
+        ComparisonReport::new(&results)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parse a run, refusing one written with a newer `SCHEMA_VERSION`
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        struct Version {
+            schema_version: u32,
+        }
+
+        let version: Version = serde_json::from_str(json).context("not a benchmark run")?;
+        if version.schema_version > SCHEMA_VERSION {
+            bail!(
+                "benchmark run has schema version {}, this build reads up to {}",
+                version.schema_version, SCHEMA_VERSION
+            );
+        }
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_json()?).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn read_from(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_json(&json).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The run in `path` to add results to, or a new one if there's no file
+    pub fn open_or_new(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            Self::read_from(path)
+        } else {
+            Ok(Self::new())
+        }
+    }
+}
+
+impl fmt::Display for ServiceRestart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} exited ({}) at {} ms and was restarted", self.service, self.exit_status, self.at_ms)
+    }
+}
+
+impl Default for BenchmarkRun {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OperationResult {
+    pub fn new(protocol: impl Into<String>, operation: impl Into<String>, points: usize, metrics: &BenchmarkMetrics) -> Self {
+        Self {
+            protocol: protocol.into(),
+            operation: operation.into(),
+            points,
+            latency_ns: metrics.latency.as_nanos() as u64,
+            latency_ci_ns: None,
+            time_to_first_byte_ns: metrics.time_to_first_byte.map(|time| time.as_nanos() as u64),
+            request_bytes: metrics.payload_size.request_bytes as u64,
+            response_bytes: metrics.payload_size.response_bytes as u64,
+            memory_allocated: metrics.memory_allocated as u64,
+            cpu_cycles: metrics.cpu_cycles,
+            energy_uj: None,
+            payload_bytes: None,
+            payload_estimated: metrics.payload_size.estimated,
+            server_time_ns: None,
+            server_allocated_bytes: None,
+        }
+    }
+
+    /// This result with the service's side of it, when it was read
+    pub fn with_server(self, server: Option<OperationStats>) -> Self {
+        Self {
+            server_time_ns: server.map(|stats| stats.total_ns),
+            server_allocated_bytes: server.map(|stats| stats.allocated_bytes),
+            ..self
+        }
+    }
+
+    /// Share of the client's latency spent inside the service; the rest went
+    /// on serialization, the client library and the network
+    pub fn server_share(&self) -> Option<f64> {
+        let server = self.server_time_ns?;
+        (self.latency_ns > 0).then(|| server as f64 / self.latency_ns as f64)
+    }
+
+    /// Back to the `BenchmarkMetrics` it was recorded from
+    pub fn metrics(&self) -> BenchmarkMetrics {
+        BenchmarkMetrics {
+            latency: Duration::from_nanos(self.latency_ns),
+            payload_size: if self.payload_estimated {
+                PayloadSizes::estimated(self.request_bytes as usize, self.response_bytes as usize)
+            } else {
+                PayloadSizes::new(self.request_bytes as usize, self.response_bytes as usize)
+            },
+            memory_allocated: self.memory_allocated as usize,
+            cpu_cycles: self.cpu_cycles,
+            time_to_first_byte: self.time_to_first_byte_ns.map(Duration::from_nanos),
+        }
+    }
+}
+
+fn settings_from_env() -> BTreeMap<String, String> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.to_string_lossy().into_owned())))
+        .filter(|(name, _)| name.starts_with("PROTOBENCH_"))
+        .map(|(name, value)| {
+            let value = if REDACTED_SETTINGS.contains(&name.as_str()) { "<redacted>".to_string() } else { value };
+            (name, value)
+        })
+        .collect()
+}
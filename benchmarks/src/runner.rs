@@ -0,0 +1,226 @@
+use anyhow::Context;
+use shared::{generate_test_data, MetricQuery, OperationStats};
+use std::future::Future;
+use std::time::Duration;
+
+use crate::report::PayloadOperation;
+use crate::results::{BenchmarkRun, OperationResult};
+use crate::server_stats::ServerStatsProbe;
+use crate::statistics::Summary;
+use crate::wire_bytes::WireCounter;
+use crate::{benchmark_operation, protocol_clients, purge_all_services, BenchmarkMetrics, PayloadSizes};
+
+/// How often `sample` runs an operation, and which runs it keeps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunnerSettings {
+    /// Runs made and thrown away first, so connections, caches and the
+    /// allocator are warm before anything is measured
+    pub warmup: usize,
+    /// Runs measured
+    pub samples: usize,
+    /// Runs slower or faster than the middle half of the latencies by more
+    /// than this many interquartile ranges are set aside as outliers
+    /// (Tukey's fences; 1.5 is the usual choice)
+    pub outlier_fence: f64,
+}
+
+impl Default for RunnerSettings {
+    fn default() -> Self {
+        Self { warmup: 3, samples: 20, outlier_fence: 1.5 }
+    }
+}
+
+/// Every measured run of one operation, split into those kept and the
+/// outliers set aside
+#[derive(Debug, Clone)]
+pub struct Sampled {
+    pub kept: Vec<BenchmarkMetrics>,
+    pub outliers: Vec<BenchmarkMetrics>,
+    /// The request IDs each outlier was sent with, as `request_id_range`
+    /// gives them, so services' request logs can say where the time went.
+    /// Filled in by `sample`; empty when built `from_samples`.
+    pub outlier_requests: Vec<String>,
+}
+
+/// Linearly interpolated quantile of sorted `values`, which can't be empty
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+/// Whether each of `samples` lies within Tukey's fences on latency
+fn within_fences(samples: &[BenchmarkMetrics], fence: f64) -> Vec<bool> {
+    if samples.len() < 4 {
+        return vec![true; samples.len()];
+    }
+    let mut latencies: Vec<f64> = samples.iter().map(|sample| sample.latency.as_secs_f64()).collect();
+    latencies.sort_by(f64::total_cmp);
+    let (q1, q3) = (quantile(&latencies, 0.25), quantile(&latencies, 0.75));
+    let (low, high) = (q1 - fence * (q3 - q1), q3 + fence * (q3 - q1));
+    samples.iter().map(|sample| (low..=high).contains(&sample.latency.as_secs_f64())).collect()
+}
+
+impl Sampled {
+    /// Split `samples` by Tukey's fences on latency, `fence` interquartile
+    /// ranges beyond the first and third quartiles. Fewer than four samples
+    /// have no meaningful quartiles, so all are kept.
+    pub fn from_samples(samples: Vec<BenchmarkMetrics>, fence: f64) -> Self {
+        let within = within_fences(&samples, fence);
+        let (mut kept, mut outliers) = (Vec::new(), Vec::new());
+        for (sample, within) in samples.into_iter().zip(within) {
+            if within {
+                kept.push(sample);
+            } else {
+                outliers.push(sample);
+            }
+        }
+        Self { kept, outliers, outlier_requests: Vec::new() }
+    }
+
+    /// Latency of the kept runs, in milliseconds
+    pub fn latency_ms(&self) -> Option<Summary> {
+        let latencies: Vec<f64> = self.kept.iter().map(|sample| sample.latency.as_secs_f64() * 1000.0).collect();
+        Summary::of(&latencies)
+    }
+
+    /// Every field averaged over the kept runs; time to first byte over
+    /// those that had one
+    pub fn mean(&self) -> Option<BenchmarkMetrics> {
+        let n = self.kept.len();
+        if n == 0 {
+            return None;
+        }
+        let mean = |value: &dyn Fn(&BenchmarkMetrics) -> f64| self.kept.iter().map(value).sum::<f64>() / n as f64;
+        let first_bytes: Vec<Duration> = self.kept.iter().filter_map(|sample| sample.time_to_first_byte).collect();
+
+        let request_bytes = mean(&|sample| sample.payload_size.request_bytes as f64).round() as usize;
+        let response_bytes = mean(&|sample| sample.payload_size.response_bytes as f64).round() as usize;
+        let payload_size = if self.kept.iter().any(|sample| sample.payload_size.estimated) {
+            PayloadSizes::estimated(request_bytes, response_bytes)
+        } else {
+            PayloadSizes::new(request_bytes, response_bytes)
+        };
+        Some(BenchmarkMetrics {
+            latency: Duration::from_secs_f64(mean(&|sample| sample.latency.as_secs_f64())),
+            payload_size,
+            memory_allocated: mean(&|sample| sample.memory_allocated as f64).round() as usize,
+            cpu_cycles: mean(&|sample| sample.cpu_cycles as f64).round() as u64,
+            time_to_first_byte: (!first_bytes.is_empty())
+                .then(|| first_bytes.iter().sum::<Duration>() / first_bytes.len() as u32),
+        })
+    }
+
+    /// The kept runs' means as a result, with the latency's 95% confidence
+    /// interval in `latency_ci_ns`
+    pub fn to_result(&self, protocol: &str, operation: &str, points: usize) -> Option<OperationResult> {
+        let result = OperationResult::new(protocol, operation, points, &self.mean()?);
+        let latency = self.latency_ms()?;
+        let nanos = |ms: f64| (ms.max(0.0) * 1_000_000.0).round() as u64;
+        Some(OperationResult {
+            latency_ci_ns: latency.margin.is_finite().then(|| [nanos(latency.lower()), nanos(latency.upper())]),
+            ..result
+        })
+    }
+}
+
+/// Run `f` `settings.warmup` times unmeasured, then `settings.samples` times
+/// through `benchmark_operation`, and set aside the outliers. Any failed run
+/// fails the whole sample, since the rest no longer measure the same thing.
+pub async fn sample<T, E, F, Fut>(
+    operation_name: &str,
+    wire: &WireCounter,
+    settings: RunnerSettings,
+    mut f: F,
+) -> Result<Sampled, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    for _ in 0..settings.warmup {
+        f().await?;
+    }
+    let mut samples = Vec::with_capacity(settings.samples);
+    let mut requests = Vec::with_capacity(settings.samples);
+    for _ in 0..settings.samples.max(1) {
+        let first = shared::next_request_sequence();
+        let (result, metrics) = benchmark_operation(operation_name, wire, &mut f).await;
+        result?;
+        samples.push(metrics);
+        requests.push(shared::request_id_range(first, shared::next_request_sequence()));
+    }
+    let within = within_fences(&samples, settings.outlier_fence);
+    let outlier_requests = requests.into_iter().zip(&within).filter(|(_, within)| !**within).map(|(ids, _)| ids).collect();
+    Ok(Sampled { outlier_requests, ..Sampled::from_samples(samples, settings.outlier_fence) })
+}
+
+/// `quick_pass` with every operation sampled: a batch submit of `points`
+/// points and a query returning `points` of them, on every protocol, each
+/// against emptied services. Repeated submits keep adding points, so the
+/// query is limited to `points`. Services are emptied again afterwards.
+/// Server figures, where a service serves `/stats`, are averaged over every
+/// run, warmup included.
+pub async fn sampled_pass(points: usize, settings: RunnerSettings) -> anyhow::Result<BenchmarkRun> {
+    let metrics = generate_test_data(points);
+    let query = MetricQuery {
+        start_time: i64::MIN,
+        end_time: i64::MAX,
+        hostname_filter: None,
+        limit: Some(points as u32),
+        offset: None,
+    };
+
+    let mut run = BenchmarkRun::new();
+    for (name, client) in protocol_clients() {
+        purge_all_services().await?;
+        let server = ServerStatsProbe::for_client(client.as_ref());
+
+        let before = server.snapshot().await;
+        if let Some(stats) = &before {
+            run.record_server_allocator(stats);
+        }
+        let submitted = sample("submit_batch", client.wire_bytes(), settings, || client.submit_batch(metrics.clone()))
+            .await
+            .with_context(|| format!("{} submit_batch failed", name))?;
+        let submit_server = server.since(before).await;
+        push_sampled(&mut run, &name, PayloadOperation::Submit, points, &submitted, submit_server, settings);
+
+        let before = server.snapshot().await;
+        let queried = sample("query", client.wire_bytes(), settings, || client.query_metrics(query.clone()))
+            .await
+            .with_context(|| format!("{} query failed", name))?;
+        let query_server = server.since(before).await;
+        push_sampled(&mut run, &name, PayloadOperation::Query, points, &queried, query_server, settings);
+    }
+    purge_all_services().await?;
+    Ok(run)
+}
+
+fn push_sampled(
+    run: &mut BenchmarkRun,
+    protocol: &str,
+    operation: PayloadOperation,
+    points: usize,
+    sampled: &Sampled,
+    server: Option<OperationStats>,
+    settings: RunnerSettings,
+) {
+    if !sampled.outliers.is_empty() {
+        tracing::info!(protocol, operation = operation.label(), outliers = sampled.outliers.len(), "outliers set aside");
+    }
+    if shared::request_log_enabled() {
+        for (outlier, requests) in sampled.outliers.iter().zip(&sampled.outlier_requests) {
+            eprintln!("{} {} outlier: {:?}, requests {}", protocol, operation.label(), outlier.latency, requests);
+        }
+    }
+    // The server saw the warmup runs and outliers too, and can't tell them apart
+    let runs = (settings.warmup + settings.samples.max(1)) as u64;
+    let per_run = server.map(|stats| OperationStats {
+        requests: stats.requests / runs,
+        total_ns: stats.total_ns / runs,
+        allocated_bytes: stats.allocated_bytes / runs,
+    });
+    if let Some(result) = sampled.to_result(protocol, operation.label(), points) {
+        run.push(result.with_server(per_run));
+    }
+}
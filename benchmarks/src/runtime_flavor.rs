@@ -0,0 +1,75 @@
+use std::fmt;
+use tokio::runtime::{Builder, Runtime};
+
+/// How the runtime the clients run on is configured, which the
+/// `runtime_flavor` benchmark group varies: a single-threaded runtime like a
+/// sidecar's, or a work-stealing one with a given number of worker threads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeFlavor {
+    CurrentThread,
+    MultiThread { workers: usize },
+}
+
+impl RuntimeFlavor {
+    /// Parse `current-thread`, `multi-thread` (one worker per core) or
+    /// `multi-thread:<workers>`
+    pub fn parse(flavor: &str) -> Option<Self> {
+        match flavor.trim() {
+            "current-thread" => Some(RuntimeFlavor::CurrentThread),
+            "multi-thread" => Some(RuntimeFlavor::MultiThread { workers: cores() }),
+            other => other
+                .strip_prefix("multi-thread:")
+                .and_then(|workers| workers.parse().ok())
+                .filter(|&workers| workers > 0)
+                .map(|workers| RuntimeFlavor::MultiThread { workers }),
+        }
+    }
+
+    /// Flavors to compare, from a comma-separated `PROTOBENCH_CLIENT_RUNTIMES`
+    /// such as `current-thread,multi-thread:2`. Names that don't parse are
+    /// skipped. By default: current-thread, then multi-thread with 2 workers
+    /// and with one per core.
+    pub fn from_env() -> Vec<Self> {
+        let flavors: Vec<Self> = match std::env::var("PROTOBENCH_CLIENT_RUNTIMES") {
+            Ok(list) => list.split(',').filter_map(Self::parse).collect(),
+            Err(_) => vec![
+                RuntimeFlavor::CurrentThread,
+                RuntimeFlavor::MultiThread { workers: 2 },
+                RuntimeFlavor::MultiThread { workers: cores() },
+            ],
+        };
+        let mut unique = Vec::with_capacity(flavors.len());
+        for flavor in flavors {
+            if !unique.contains(&flavor) {
+                unique.push(flavor);
+            }
+        }
+        unique
+    }
+
+    /// A runtime of this flavor with IO and time enabled
+    pub fn build(&self) -> std::io::Result<Runtime> {
+        match self {
+            RuntimeFlavor::CurrentThread => Builder::new_current_thread().enable_all().build(),
+            RuntimeFlavor::MultiThread { workers } => Builder::new_multi_thread().worker_threads(*workers).enable_all().build(),
+        }
+    }
+
+    /// `current-thread` or `multi-thread:<workers>`, as `parse` reads it
+    pub fn label(&self) -> String {
+        match self {
+            RuntimeFlavor::CurrentThread => "current-thread".to_string(),
+            RuntimeFlavor::MultiThread { workers } => format!("multi-thread:{}", workers),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label())
+    }
+}
+
+fn cores() -> usize {
+    std::thread::available_parallelism().map_or(1, |cores| cores.get())
+}
@@ -0,0 +1,56 @@
+use shared::{OperationStats, ServerStats};
+use std::sync::OnceLock;
+
+use crate::ProtocolClient;
+
+// Reused so a scrape costs one loopback request, not a new client's setup
+static STATS_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// The per-method `ServerStats` a service serves at `GET /stats` on its
+/// exporter at `addr`
+pub async fn fetch_server_stats(addr: &str) -> anyhow::Result<ServerStats> {
+    let stats = STATS_CLIENT
+        .get_or_init(reqwest::Client::new)
+        .get(format!("http://{}/stats", addr))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(stats)
+}
+
+/// Reads what the service behind a client spent handling an operation, by
+/// taking its stats before and after. The service is found through the same
+/// `PROTOBENCH_<PROTOCOL>_METRICS_ADDR` variable it reads; without it every
+/// reading is `None`.
+pub struct ServerStatsProbe {
+    addr: Option<String>,
+}
+
+impl ServerStatsProbe {
+    pub fn for_client(client: &dyn ProtocolClient) -> Self {
+        Self { addr: std::env::var(client.metrics_exporter_env()).ok() }
+    }
+
+    /// The service's stats now, or `None` when it has no exporter or it
+    /// couldn't be read
+    pub async fn snapshot(&self) -> Option<ServerStats> {
+        let addr = self.addr.as_deref()?;
+        match fetch_server_stats(addr).await {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                tracing::warn!(addr, error = %e, "server stats unavailable");
+                None
+            }
+        }
+    }
+
+    /// Every request the service handled since `before` was taken, all
+    /// methods together. Only accurate while nothing else is using the
+    /// service.
+    pub async fn since(&self, before: Option<ServerStats>) -> Option<OperationStats> {
+        let before = before?;
+        Some(self.snapshot().await?.since(&before).total())
+    }
+}
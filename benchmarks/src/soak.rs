@@ -0,0 +1,167 @@
+use shared::{generate_test_data_iter, MetricPoint, MetricQuery};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::error::FailureBreakdown;
+use crate::statistics::Summary;
+use crate::{protocol_clients, purge_all_services, ProtocolError};
+
+/// How long `run` keeps the services busy and how it reports
+#[derive(Debug, Clone, Copy)]
+pub struct SoakSettings {
+    pub duration: Duration,
+    /// Length of each reported interval
+    pub interval: Duration,
+    /// Points per submitted batch, and the limit on each query
+    pub batch_size: usize,
+}
+
+/// What one protocol did over one interval of a soak
+#[derive(Debug, Clone)]
+pub struct SoakInterval {
+    pub protocol: String,
+    /// 0 for the first interval
+    pub index: usize,
+    /// Points the service reported storing
+    pub points_stored: u64,
+    pub submit_latency_ms: Option<Summary>,
+    pub query_latency_ms: Option<Summary>,
+    pub failures: FailureBreakdown,
+}
+
+/// Every interval of a soak, in order, one row per protocol each
+#[derive(Debug, Clone, Default)]
+pub struct SoakReport {
+    pub intervals: Vec<SoakInterval>,
+}
+
+/// Samples for one protocol while an interval runs
+#[derive(Default)]
+struct Tally {
+    points_stored: u64,
+    submit_ms: Vec<f64>,
+    query_ms: Vec<f64>,
+    failures: FailureBreakdown,
+}
+
+impl Tally {
+    /// The value and milliseconds since `started` of a call that succeeded;
+    /// a failure is only counted
+    fn timed<T>(&mut self, started: Instant, result: Result<T, ProtocolError>) -> Option<(T, f64)> {
+        match result {
+            Ok(value) => Some((value, started.elapsed().as_secs_f64() * 1000.0)),
+            Err(e) => {
+                self.failures.record(&e);
+                None
+            }
+        }
+    }
+}
+
+/// Submit batches and query a batch's worth of points back on every protocol
+/// in turn until `settings.duration` has passed, never emptying the services
+/// in between, so growing storage, leaks and slowdowns show up as drift
+/// between intervals. `on_interval` sees each interval's rows as they complete.
+/// Services are emptied before the soak starts and after it ends.
+pub async fn run(settings: SoakSettings, mut on_interval: impl FnMut(&[SoakInterval])) -> anyhow::Result<SoakReport> {
+    let clients = protocol_clients();
+    let mut points = generate_test_data_iter(usize::MAX);
+    let query = MetricQuery {
+        start_time: i64::MIN,
+        end_time: i64::MAX,
+        hostname_filter: None,
+        limit: Some(settings.batch_size as u32),
+        offset: None,
+    };
+
+    purge_all_services().await?;
+    let started = Instant::now();
+    let mut report = SoakReport::default();
+    let mut index = 0;
+    while started.elapsed() < settings.duration {
+        let interval_end = Instant::now() + settings.interval;
+        let mut tallies: Vec<Tally> = clients.iter().map(|_| Tally::default()).collect();
+        while Instant::now() < interval_end {
+            for ((_, client), tally) in clients.iter().zip(&mut tallies) {
+                let batch: Vec<MetricPoint> = points.by_ref().take(settings.batch_size.max(1)).collect();
+                let submitted = Instant::now();
+                if let Some((stored, ms)) = tally.timed(submitted, client.submit_batch(batch).await) {
+                    tally.points_stored += stored;
+                    tally.submit_ms.push(ms);
+                }
+                let queried = Instant::now();
+                if let Some((_, ms)) = tally.timed(queried, client.query_metrics(query.clone()).await) {
+                    tally.query_ms.push(ms);
+                }
+            }
+        }
+
+        let rows: Vec<SoakInterval> = clients
+            .iter()
+            .zip(tallies)
+            .map(|((name, _), tally)| SoakInterval {
+                protocol: name.clone(),
+                index,
+                points_stored: tally.points_stored,
+                submit_latency_ms: Summary::of(&tally.submit_ms),
+                query_latency_ms: Summary::of(&tally.query_ms),
+                failures: tally.failures,
+            })
+            .collect();
+        on_interval(&rows);
+        report.intervals.extend(rows);
+        index += 1;
+    }
+    purge_all_services().await?;
+    Ok(report)
+}
+
+impl SoakReport {
+    /// Mean submit latency in the last interval over that in the first, for
+    /// `protocol`; above 1 means it slowed down as the soak went on
+    pub fn submit_drift(&self, protocol: &str) -> Option<f64> {
+        let mut means = self
+            .intervals
+            .iter()
+            .filter(|interval| interval.protocol == protocol)
+            .filter_map(|interval| interval.submit_latency_ms.map(|summary| summary.mean));
+        let first = means.next()?;
+        let last = means.last()?;
+        (first > 0.0).then_some(last / first)
+    }
+}
+
+impl fmt::Display for SoakInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let latency = |summary: &Option<Summary>| summary.map_or("-".to_string(), |summary| format!("{:.3}", summary.mean));
+        write!(
+            f,
+            "  {:>4} {:<24} {:>10} {:>12} {:>12}  {}",
+            self.index,
+            self.protocol,
+            self.points_stored,
+            latency(&self.submit_latency_ms),
+            latency(&self.query_latency_ms),
+            self.failures
+        )
+    }
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  {:>4} {:<24} {:>10} {:>12} {:>12}  Failures", "#", "Protocol", "Stored", "Submit (ms)", "Query (ms)")?;
+        let mut protocols: Vec<&str> = Vec::new();
+        for interval in &self.intervals {
+            writeln!(f, "{}", interval)?;
+            if !protocols.contains(&interval.protocol.as_str()) {
+                protocols.push(&interval.protocol);
+            }
+        }
+        for protocol in protocols {
+            if let Some(drift) = self.submit_drift(protocol) {
+                writeln!(f, "  {} submit latency, last interval over first: ×{:.2}", protocol, drift)?;
+            }
+        }
+        Ok(())
+    }
+}
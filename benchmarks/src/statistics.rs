@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::report::{ComparisonReport, Spread};
+use crate::results::{BenchmarkRun, OperationResult, ServiceRestart};
+use crate::BenchmarkMetrics;
+
+// Two-sided 95% critical values of Student's t for 1 to 30 degrees of freedom
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160, 2.145, 2.131, 2.120,
+    2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+
+/// Two-sided 95% critical value of Student's t with `degrees_of_freedom`.
+/// Past 30 it takes the value at the nearest tabulated count below (40, 60,
+/// 120), which widens intervals slightly rather than narrowing them.
+pub fn t_critical_95(degrees_of_freedom: usize) -> f64 {
+    match degrees_of_freedom {
+        0 => f64::INFINITY,
+        1..=30 => T_95[degrees_of_freedom - 1],
+        31..=40 => 2.042,
+        41..=60 => 2.021,
+        61..=120 => 2.000,
+        _ => 1.980,
+    }
+}
+
+/// Nearest-rank percentile of sorted `samples`, 0 when there are none
+pub fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Mean of repeated measurements with its 95% confidence interval, from the
+/// sample standard deviation and Student's t
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub samples: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Half the interval's width; infinite for a single sample
+    pub margin: f64,
+}
+
+impl Summary {
+    /// `None` for no samples
+    pub fn of(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let n = samples.len();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let std_dev = if n > 1 {
+            (samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        let margin = t_critical_95(n - 1) * std_dev / (n as f64).sqrt();
+        Some(Self { samples: n, mean, std_dev, margin: if n > 1 { margin } else { f64::INFINITY } })
+    }
+
+    pub fn lower(&self) -> f64 {
+        self.mean - self.margin
+    }
+
+    pub fn upper(&self) -> f64 {
+        self.mean + self.margin
+    }
+}
+
+/// Whether `a` and `b` have different means at the 5% level, by Welch's t
+/// test, which doesn't assume equal variances. `None` when either has fewer
+/// than two samples, so its spread is unknown.
+pub fn differs_significantly(a: &Summary, b: &Summary) -> Option<bool> {
+    if a.samples < 2 || b.samples < 2 {
+        return None;
+    }
+    let a_variance = a.std_dev.powi(2) / a.samples as f64;
+    let b_variance = b.std_dev.powi(2) / b.samples as f64;
+    let standard_error = (a_variance + b_variance).sqrt();
+    if standard_error == 0.0 {
+        // No spread at all: any difference is real
+        return Some(a.mean != b.mean);
+    }
+    let t = (a.mean - b.mean).abs() / standard_error;
+    // Welch–Satterthwaite, rounded down so the test stays conservative
+    let degrees_of_freedom = (a_variance + b_variance).powi(2)
+        / (a_variance.powi(2) / (a.samples - 1) as f64 + b_variance.powi(2) / (b.samples - 1) as f64);
+    Some(t > t_critical_95(degrees_of_freedom.floor() as usize))
+}
+
+/// `mean ± margin`, to the precision the formatter asks for
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(2);
+        write!(f, "{:.*} ± {:.*}", precision, self.mean, precision, self.margin)
+    }
+}
+
+/// One protocol's operation across every repetition of a run
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+    pub protocol: String,
+    pub operation: String,
+    pub points: usize,
+    pub latency_ms: Summary,
+    pub request_bytes: Summary,
+    pub response_bytes: Summary,
+    /// Request and response bytes together
+    pub total_bytes: Summary,
+    pub memory_bytes: Summary,
+    /// Whether any run's sizes were estimated rather than counted
+    pub payload_estimated: bool,
+}
+
+impl Aggregate {
+    /// The means as a result, with the latency interval in `latency_ci_ns`
+    pub fn to_result(&self) -> OperationResult {
+        let nanos = |ms: f64| (ms.max(0.0) * 1_000_000.0).round() as u64;
+        OperationResult {
+            protocol: self.protocol.clone(),
+            operation: self.operation.clone(),
+            points: self.points,
+            latency_ns: nanos(self.latency_ms.mean),
+            latency_ci_ns: self.latency_ms.margin.is_finite().then(|| [nanos(self.latency_ms.lower()), nanos(self.latency_ms.upper())]),
+            time_to_first_byte_ns: None,
+            request_bytes: self.request_bytes.mean.round() as u64,
+            response_bytes: self.response_bytes.mean.round() as u64,
+            memory_allocated: self.memory_bytes.mean.round() as u64,
+            cpu_cycles: 0,
+            energy_uj: None,
+            payload_bytes: None,
+            payload_estimated: self.payload_estimated,
+            server_time_ns: None,
+            server_allocated_bytes: None,
+        }
+    }
+}
+
+/// `Aggregate`s for every protocol, operation and point count measured in
+/// `runs`, in the order each first appears
+#[derive(Debug, Clone, Default)]
+pub struct AggregateReport {
+    rows: Vec<Aggregate>,
+    restarts: Vec<ServiceRestart>,
+    allocators: BTreeMap<String, String>,
+}
+
+impl AggregateReport {
+    /// Summarize independent repetitions of the same measurements. A result
+    /// missing from some runs is summarized over the runs that have it.
+    pub fn new(runs: &[BenchmarkRun]) -> Self {
+        let mut keys: Vec<(&str, &str, usize)> = Vec::new();
+        for result in runs.iter().flat_map(|run| &run.results) {
+            let key = (result.protocol.as_str(), result.operation.as_str(), result.points);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        let rows = keys
+            .into_iter()
+            .filter_map(|(protocol, operation, points)| {
+                let results: Vec<&OperationResult> = runs
+                    .iter()
+                    .flat_map(|run| &run.results)
+                    .filter(|result| result.protocol == protocol && result.operation == operation && result.points == points)
+                    .collect();
+                let summarize = |value: fn(&OperationResult) -> f64| {
+                    Summary::of(&results.iter().map(|result| value(result)).collect::<Vec<_>>())
+                };
+                Some(Aggregate {
+                    protocol: protocol.to_string(),
+                    operation: operation.to_string(),
+                    points,
+                    latency_ms: summarize(|result| result.latency_ns as f64 / 1_000_000.0)?,
+                    request_bytes: summarize(|result| result.request_bytes as f64)?,
+                    response_bytes: summarize(|result| result.response_bytes as f64)?,
+                    total_bytes: summarize(|result| (result.request_bytes + result.response_bytes) as f64)?,
+                    memory_bytes: summarize(|result| result.memory_allocated as f64)?,
+                    payload_estimated: results.iter().any(|result| result.payload_estimated),
+                })
+            })
+            .collect();
+        let restarts = runs.iter().flat_map(|run| run.restarts.iter().cloned()).collect();
+        let allocators = runs.iter().flat_map(|run| run.allocators.clone()).collect();
+        Self { rows, restarts, allocators }
+    }
+
+    pub fn rows(&self) -> &[Aggregate] {
+        &self.rows
+    }
+
+    /// Every run's service restarts, which `to_run` keeps
+    pub fn restarts(&self) -> &[ServiceRestart] {
+        &self.restarts
+    }
+
+    pub fn get(&self, protocol: &str, operation: &str, points: usize) -> Option<&Aggregate> {
+        self.rows
+            .iter()
+            .find(|row| row.protocol == protocol && row.operation == operation && row.points == points)
+    }
+
+    /// The rows for `operation` over `points` points ranked as a
+    /// `ComparisonReport` whose verdicts are tested for significance, or
+    /// `None` when there are none
+    pub fn comparison(&self, operation: &str, points: usize) -> Option<ComparisonReport> {
+        let results: Vec<(&str, BenchmarkMetrics, Spread)> = self
+            .rows
+            .iter()
+            .filter(|row| row.operation == operation && row.points == points)
+            .map(|row| {
+                let spread = Spread {
+                    latency_ms: row.latency_ms,
+                    memory_bytes: row.memory_bytes,
+                    total_bytes: row.total_bytes,
+                };
+                (row.protocol.as_str(), row.to_result().metrics(), spread)
+            })
+            .collect();
+        ComparisonReport::with_spread(&results)
+    }
+
+    /// The means as a run, for `BenchmarkRun::write_to` and the exporters
+    pub fn to_run(&self) -> BenchmarkRun {
+        let mut run = BenchmarkRun {
+            results: self.rows.iter().map(Aggregate::to_result).collect(),
+            restarts: self.restarts.clone(),
+            ..BenchmarkRun::new()
+        };
+        run.allocators.extend(self.allocators.clone());
+        run
+    }
+}
+
+impl fmt::Display for AggregateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  {:<24} {:<13} {:>7} {:>5} {:>22} {:>24} {:>24}",
+            "Protocol", "Operation", "Points", "Runs", "Latency (ms)", "Traffic (bytes)", "Memory (bytes)"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "  {:<24} {:<13} {:>7} {:>5} {:>22} {:>24} {:>24}",
+                row.protocol,
+                row.operation,
+                row.points,
+                row.latency_ms.samples,
+                format!("{:.3}", row.latency_ms),
+                format!("{:.0}{}", row.total_bytes, if row.payload_estimated { "*" } else { "" }),
+                format!("{:.0}", row.memory_bytes),
+            )?;
+        }
+        if self.rows.iter().any(|row| row.payload_estimated) {
+            writeln!(f, "  * traffic estimated rather than counted on the socket")?;
+        }
+        Ok(())
+    }
+}
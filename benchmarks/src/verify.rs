@@ -0,0 +1,146 @@
+use anyhow::Context;
+use serde::Serialize;
+use shared::{MetricQuery, TestDataGenerator};
+use std::fmt;
+
+use crate::{benchmark_operation, protocol_clients, purge_all_services, BenchmarkMetrics};
+
+// The workload's points end at this fixed time rather than now, so both runs
+// submit byte-identical points
+const VERIFY_BASE_TIMESTAMP: i64 = 1_700_000_000;
+const VERIFY_SEED: u64 = 42;
+
+/// What one operation of a `record_run` pass put on the wire and got back
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub protocol: String,
+    pub operation: &'static str,
+    pub request_bytes: usize,
+    pub response_bytes: usize,
+    /// The decoded response as JSON with object keys sorted, so tags compare
+    /// regardless of map order while the order points come back in still counts
+    pub response: String,
+}
+
+/// One way the second pass differed from the first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub protocol: String,
+    pub operation: String,
+    /// `request_bytes`, `response_bytes`, `response` or `missing`
+    pub field: &'static str,
+    pub first: String,
+    pub second: String,
+}
+
+impl Observation {
+    pub fn new(protocol: &str, operation: &'static str, metrics: &BenchmarkMetrics, response: &impl Serialize) -> Self {
+        Self {
+            protocol: protocol.to_string(),
+            operation,
+            request_bytes: metrics.payload_size.request_bytes,
+            response_bytes: metrics.payload_size.response_bytes,
+            response: canonical_json(response),
+        }
+    }
+}
+
+/// `value` as JSON, going through `serde_json::Value` so every map is written
+/// in key order rather than `HashMap` iteration order
+pub fn canonical_json(value: &impl Serialize) -> String {
+    serde_json::to_value(value).map(|value| value.to_string()).unwrap_or_else(|e| format!("<unserializable: {}>", e))
+}
+
+/// Every difference between two passes, matching observations by protocol
+/// and operation. An observation only one pass has is reported as `missing`.
+pub fn compare(first: &[Observation], second: &[Observation]) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    for a in first {
+        let Some(b) = second.iter().find(|b| b.protocol == a.protocol && b.operation == a.operation) else {
+            mismatches.push(Mismatch::new(a, "missing", "recorded", "absent"));
+            continue;
+        };
+        if a.request_bytes != b.request_bytes {
+            mismatches.push(Mismatch::new(a, "request_bytes", a.request_bytes, b.request_bytes));
+        }
+        if a.response_bytes != b.response_bytes {
+            mismatches.push(Mismatch::new(a, "response_bytes", a.response_bytes, b.response_bytes));
+        }
+        if a.response != b.response {
+            mismatches.push(Mismatch::new(a, "response", &a.response, &b.response));
+        }
+    }
+    for b in second {
+        if !first.iter().any(|a| a.protocol == b.protocol && a.operation == b.operation) {
+            mismatches.push(Mismatch::new(b, "missing", "absent", "recorded"));
+        }
+    }
+    mismatches
+}
+
+impl Mismatch {
+    fn new(observation: &Observation, field: &'static str, first: impl fmt::Display, second: impl fmt::Display) -> Self {
+        Self {
+            protocol: observation.protocol.clone(),
+            operation: observation.operation.to_string(),
+            field,
+            first: first.to_string(),
+            second: second.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {} differs", self.protocol, self.operation, self.field)?;
+        if self.field == "response" {
+            // Responses can run to kilobytes; show where they part
+            let at = self.first.bytes().zip(self.second.bytes()).take_while(|(a, b)| a == b).count();
+            let excerpt = |text: &str| text.get(at.saturating_sub(20)..).unwrap_or("").chars().take(60).collect::<String>();
+            write!(f, " from byte {}: {:?} then {:?}", at, excerpt(&self.first), excerpt(&self.second))
+        } else {
+            write!(f, ": {} then {}", self.first, self.second)
+        }
+    }
+}
+
+/// One pass of the fixed workload `verify` runs twice: against emptied
+/// services, a batch submit of `points` seeded points with a pinned
+/// timestamp, then a query, statistics and rollups over them, on every
+/// protocol. Services are emptied again afterwards.
+pub async fn record_run(points: usize) -> anyhow::Result<Vec<Observation>> {
+    let metrics = TestDataGenerator::new().seed(VERIFY_SEED).base_timestamp(VERIFY_BASE_TIMESTAMP).generate(points);
+    let query = MetricQuery {
+        start_time: VERIFY_BASE_TIMESTAMP - 3600,
+        end_time: VERIFY_BASE_TIMESTAMP,
+        hostname_filter: None,
+        limit: None,
+        offset: None,
+    };
+
+    let mut observations = Vec::new();
+    purge_all_services().await?;
+    for (name, client) in protocol_clients() {
+        let (stored, submitted) =
+            benchmark_operation("submit_batch", client.wire_bytes(), || client.submit_batch(metrics.clone())).await;
+        let stored = stored.with_context(|| format!("{} submit_batch failed", name))?;
+        observations.push(Observation::new(&name, "submit_batch", &submitted, &stored));
+
+        let (returned, queried) =
+            benchmark_operation("query", client.wire_bytes(), || client.query_metrics(query.clone())).await;
+        let returned = returned.with_context(|| format!("{} query failed", name))?;
+        observations.push(Observation::new(&name, "query", &queried, &returned));
+
+        let (statistics, measured) =
+            benchmark_operation("statistics", client.wire_bytes(), || client.get_statistics(query.clone())).await;
+        let statistics = statistics.with_context(|| format!("{} statistics failed", name))?;
+        observations.push(Observation::new(&name, "statistics", &measured, &statistics));
+
+        let (rollups, rolled) =
+            benchmark_operation("rollups", client.wire_bytes(), || client.query_rollups(query.clone())).await;
+        let rollups = rollups.with_context(|| format!("{} rollups failed", name))?;
+        observations.push(Observation::new(&name, "rollups", &rolled, &rollups));
+    }
+    purge_all_services().await?;
+    Ok(observations)
+}
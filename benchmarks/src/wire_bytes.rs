@@ -0,0 +1,161 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Running totals of the bytes one client has written to and read from its
+/// sockets, TLS and protocol framing included
+pub struct WireCounter {
+    written: AtomicU64,
+    read: AtomicU64,
+}
+
+impl WireCounter {
+    pub const fn new() -> Self {
+        Self {
+            written: AtomicU64::new(0),
+            read: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> WireBytes {
+        WireBytes {
+            written: self.written.load(Ordering::Relaxed),
+            read: self.read.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for WireCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A `WireCounter` reading, or the difference between two
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WireBytes {
+    pub written: u64,
+    pub read: u64,
+}
+
+impl std::ops::Sub for WireBytes {
+    type Output = WireBytes;
+
+    fn sub(self, earlier: WireBytes) -> WireBytes {
+        WireBytes {
+            written: self.written - earlier.written,
+            read: self.read - earlier.read,
+        }
+    }
+}
+
+/// Run `operation` and return what it put on and took off the wire through
+/// `counter`. Only meaningful while nothing else uses the same client.
+pub async fn measure<T>(counter: &WireCounter, operation: impl std::future::Future<Output = T>) -> (T, WireBytes) {
+    let before = counter.snapshot();
+    let result = operation.await;
+    (result, counter.snapshot() - before)
+}
+
+/// A socket that adds every byte it writes and reads to a `WireCounter`
+pub struct CountingStream<S> {
+    inner: S,
+    counter: &'static WireCounter,
+}
+
+impl<S> CountingStream<S> {
+    pub fn new(inner: S, counter: &'static WireCounter) -> Self {
+        Self { inner, counter }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            self.counter.read.fetch_add((buf.filled().len() - filled) as u64, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            self.counter.written.fetch_add(written as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_write_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(written)) = result {
+            self.counter.written.fetch_add(written as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Listen on a loopback port and forward every connection to `target`,
+/// counting the forwarded bytes in `counter` as if the client had written
+/// and read them itself. For clients whose sockets can't be wrapped directly.
+///
+/// The relay runs on its own thread and runtime, so it outlives the
+/// per-benchmark runtimes; returns the address to connect to instead of
+/// `target`.
+pub fn spawn_relay(target: String, counter: &'static WireCounter) -> io::Result<std::net::SocketAddr> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    std::thread::Builder::new()
+        .name("wire-bytes-relay".to_string())
+        .spawn(move || {
+            runtime.block_on(async move {
+                let listener = match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => listener,
+                    Err(e) => return eprintln!("Wire byte relay failed to start: {}", e),
+                };
+                loop {
+                    let (mut client, _) = match listener.accept().await {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            eprintln!("Wire byte relay accept failed: {}", e);
+                            continue;
+                        }
+                    };
+                    let target = target.clone();
+                    tokio::spawn(async move {
+                        let upstream = match tokio::net::TcpStream::connect(&target).await {
+                            Ok(upstream) => upstream,
+                            Err(e) => return eprintln!("Wire byte relay couldn't reach {}: {}", target, e),
+                        };
+                        let _ = client.set_nodelay(true);
+                        let _ = upstream.set_nodelay(true);
+                        let mut upstream = CountingStream::new(upstream, counter);
+                        // Ends when either side closes; there is nothing to report
+                        let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                    });
+                }
+            })
+        })?;
+
+    Ok(addr)
+}
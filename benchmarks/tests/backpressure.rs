@@ -0,0 +1,59 @@
+use benchmarks::backpressure::{BackpressureResult, BackpressureSettings, Behavior};
+use benchmarks::{FailureBreakdown, ProtocolError};
+use std::time::Duration;
+
+fn result(sent: u64, first_quarter_ms: f64, last_quarter_ms: f64) -> BackpressureResult {
+    BackpressureResult {
+        protocol: "gRPC".to_string(),
+        sent,
+        completed: sent,
+        failures: FailureBreakdown::default(),
+        throttled: 0,
+        abandoned: 0,
+        latency_ms: None,
+        first_quarter_ms: Some(first_quarter_ms),
+        last_quarter_ms: Some(last_quarter_ms),
+        peak_in_flight: 40,
+        peak_heap_growth_bytes: 0,
+        samples: Vec::new(),
+    }
+}
+
+#[test]
+fn services_are_slowed_on_every_response() {
+    let settings = BackpressureSettings {
+        delay: Duration::from_millis(250),
+        rate: 100.0,
+        duration: Duration::from_secs(5),
+        batch_size: 10,
+    };
+    let env = settings.service_env();
+
+    assert!(env.contains(&("PROTOBENCH_FAULT_DELAY_RATE".to_string(), "1".to_string())));
+    assert!(env.contains(&("PROTOBENCH_FAULT_DELAY_MS".to_string(), "250".to_string())));
+}
+
+#[test]
+fn steady_latency_is_absorbed_and_growing_latency_is_queueing() {
+    assert_eq!(result(1000, 200.0, 210.0).behavior(), Behavior::Absorbed);
+    let queued = result(1000, 200.0, 900.0);
+    assert_eq!(queued.latency_growth(), Some(4.5));
+    assert_eq!(queued.behavior(), Behavior::Queueing);
+}
+
+#[test]
+fn failures_outrank_throttling_and_queueing() {
+    let mut throttled = result(1000, 200.0, 900.0);
+    throttled.throttled = 3;
+    assert_eq!(throttled.behavior(), Behavior::Throttled);
+
+    // 1% failing is tolerated, more is shedding load
+    let mut shedding = throttled.clone();
+    for _ in 0..10 {
+        shedding.failures.record(&ProtocolError::Timeout { protocol: "gRPC" });
+    }
+    assert_eq!(shedding.behavior(), Behavior::Throttled);
+    shedding.abandoned = 1;
+    assert_eq!(shedding.behavior(), Behavior::Errors);
+    assert_eq!(Behavior::Errors.label(), "errors");
+}
@@ -0,0 +1,88 @@
+//! `benchmark_operation` and `measure_memory_async` on both runtime flavors,
+//! with operations that suspend across await points, and with another
+//! thread allocating meanwhile. Runs without any service.
+
+use benchmarks::wire_bytes::WireCounter;
+use benchmarks::{benchmark_operation, measure_memory, measure_memory_async};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const ALLOCATION: usize = 1 << 20;
+
+static UNUSED_WIRE: WireCounter = WireCounter::new();
+
+/// Yields, sleeps, then allocates `ALLOCATION` bytes, so both the time and
+/// the memory come after the first suspension
+async fn suspending_operation() -> usize {
+    tokio::task::yield_now().await;
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let buffer = std::hint::black_box(vec![1u8; ALLOCATION]);
+    buffer.len()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn measures_across_await_points_on_the_multi_threaded_runtime() {
+    let (result, metrics) = benchmark_operation("suspending", &UNUSED_WIRE, suspending_operation).await;
+
+    assert_eq!(result, ALLOCATION);
+    assert!(metrics.latency >= Duration::from_millis(5), "{:?}", metrics.latency);
+    assert!(metrics.memory_allocated >= ALLOCATION, "{}", metrics.memory_allocated);
+    assert_eq!(metrics.payload_size.total_bytes, 0);
+}
+
+#[tokio::test]
+async fn measures_across_await_points_on_the_current_thread_runtime() {
+    let (result, metrics) = benchmark_operation("suspending", &UNUSED_WIRE, suspending_operation).await;
+
+    assert_eq!(result, ALLOCATION);
+    assert!(metrics.latency >= Duration::from_millis(5), "{:?}", metrics.latency);
+    assert!(metrics.memory_allocated >= ALLOCATION, "{}", metrics.memory_allocated);
+}
+
+// A spawned task runs on a runtime worker, where blocking on the runtime
+// would panic
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn runs_inside_spawned_tasks() {
+    let measured = tokio::spawn(async { benchmark_operation("spawned", &UNUSED_WIRE, suspending_operation).await })
+        .await
+        .unwrap();
+
+    assert_eq!(measured.0, ALLOCATION);
+    assert!(measured.1.memory_allocated >= ALLOCATION);
+}
+
+#[tokio::test]
+async fn memory_is_counted_until_the_future_completes() {
+    let (_, allocated) = measure_memory_async(suspending_operation()).await;
+    assert!(allocated >= ALLOCATION, "{}", allocated);
+}
+
+/// Allocate `ALLOCATION` bytes at a time on another thread until the
+/// returned flag is set
+fn allocate_elsewhere() -> (Arc<AtomicBool>, std::thread::JoinHandle<()>) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let thread = std::thread::spawn(move || {
+        while !stopped.load(Ordering::Relaxed) {
+            std::hint::black_box(vec![1u8; ALLOCATION]);
+        }
+    });
+    (stop, thread)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn allocations_on_other_threads_are_not_counted() {
+    let (stop, thread) = allocate_elsewhere();
+
+    let (_, allocated) = measure_memory_async(suspending_operation()).await;
+    let (_, allocated_sync) = measure_memory(|| {
+        std::thread::sleep(Duration::from_millis(5));
+        std::hint::black_box(vec![1u8; ALLOCATION]).len()
+    });
+
+    stop.store(true, Ordering::Relaxed);
+    thread.join().unwrap();
+    assert!((ALLOCATION..2 * ALLOCATION).contains(&allocated), "{}", allocated);
+    assert!((ALLOCATION..2 * ALLOCATION).contains(&allocated_sync), "{}", allocated_sync);
+}
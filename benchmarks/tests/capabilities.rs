@@ -0,0 +1,45 @@
+//! The capability matrix and the schema scanning behind it
+
+use benchmarks::capabilities::{self, capnp_pipelined_methods, capnp_streaming_methods, grpc_streaming_methods};
+use benchmarks::results::BenchmarkRun;
+
+#[test]
+fn grpc_streaming_sides_are_read_from_the_service() {
+    let proto = "service Metrics {
+  rpc Submit(MetricPoint) returns (Empty);
+  rpc Upload(stream MetricPoint) returns (Summary);
+  rpc Query(MetricQuery) returns (stream MetricPoint);
+  rpc Tail(stream MetricQuery) returns (stream MetricPoint);
+}";
+
+    assert_eq!(grpc_streaming_methods(proto), ["Upload (client)", "Query (server)", "Tail (bidi)"]);
+}
+
+#[test]
+fn capnp_capabilities_in_params_stream_and_in_results_pipeline() {
+    let schema = "interface Sink {
+  push @0 (value :UInt64) -> ();
+}
+interface Handle {}
+interface Service {
+  get @0 (token :Text) -> (value :UInt64);
+  watch @1 (sink :Sink) -> (count :UInt64);
+  open @2 () -> (handle :Handle);
+}";
+
+    assert_eq!(capnp_streaming_methods(schema), ["watch (Sink)"]);
+    assert_eq!(capnp_pipelined_methods(schema), ["open (Handle)"]);
+}
+
+#[test]
+fn measured_rows_default_to_plain_unauthenticated_requests() {
+    let mut run = BenchmarkRun::new();
+    run.settings.clear();
+
+    let matrix = capabilities::matrix(&run);
+    let row = |feature: &str| matrix.iter().find(|capability| capability.feature == feature).unwrap().support.clone();
+
+    assert_eq!(row("Compression measured"), ["identity", "identity", "unpacked"]);
+    assert_eq!(row("Authentication measured"), ["none", "none", "none"]);
+    assert!(row("Promise pipelining")[2].contains("openQuery (QueryHandle)"));
+}
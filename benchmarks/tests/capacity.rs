@@ -0,0 +1,87 @@
+//! The sustainable-rate search, on its own and against the in-process mock
+//! services from `benchmarks::mock`
+
+use benchmarks::capacity::{self, CapacitySettings, RateSearch};
+use benchmarks::mock::{MockCapnp, MockGrpc, MockRest};
+use benchmarks::protocol_clients;
+use std::time::Duration;
+
+/// Run `search` against a protocol that sustains anything up to `limit`
+/// points/s, returning every rate probed
+fn probe_until_done(search: &mut RateSearch, limit: f64) -> Vec<f64> {
+    let mut rates = Vec::new();
+    while let Some(rate) = search.next_rate() {
+        search.record(rate, rate <= limit);
+        rates.push(rate);
+        assert!(rates.len() < 100, "search never finished: {:?}", rates);
+    }
+    rates
+}
+
+#[test]
+fn the_search_doubles_then_bisects_to_within_the_precision() {
+    let settings = CapacitySettings { start_rate: 1_000.0, precision: 0.05, ..CapacitySettings::default() };
+    let mut search = RateSearch::new(settings);
+
+    let rates = probe_until_done(&mut search, 10_000.0);
+
+    assert_eq!(&rates[..5], [1_000.0, 2_000.0, 4_000.0, 8_000.0, 16_000.0]);
+    let found = search.sustainable_rate();
+    assert!(found <= 10_000.0 && found >= 10_000.0 / 1.05, "found {}", found);
+}
+
+#[test]
+fn a_failing_start_rate_is_halved_until_one_passes() {
+    let settings = CapacitySettings { start_rate: 1_000.0, batch_size: 10, ..CapacitySettings::default() };
+    let mut search = RateSearch::new(settings);
+
+    let rates = probe_until_done(&mut search, 300.0);
+
+    assert_eq!(&rates[..3], [1_000.0, 500.0, 250.0]);
+    assert!(search.sustainable_rate() >= 250.0 && search.sustainable_rate() <= 300.0);
+}
+
+#[test]
+fn the_search_stops_at_the_bounds() {
+    let settings = CapacitySettings { start_rate: 1_000.0, max_rate: 3_000.0, ..CapacitySettings::default() };
+    let mut search = RateSearch::new(settings);
+    assert_eq!(probe_until_done(&mut search, f64::INFINITY), [1_000.0, 2_000.0, 3_000.0]);
+    assert_eq!(search.sustainable_rate(), 3_000.0);
+
+    // Nothing passes, down to one batch a second
+    let settings = CapacitySettings { start_rate: 1_000.0, batch_size: 100, ..CapacitySettings::default() };
+    let mut search = RateSearch::new(settings);
+    assert_eq!(probe_until_done(&mut search, 0.0), [1_000.0, 500.0, 250.0, 125.0, 100.0]);
+    assert_eq!(search.sustainable_rate(), 0.0);
+}
+
+#[tokio::test]
+async fn every_protocol_is_searched_against_mocks() {
+    let rest = MockRest::start().await.unwrap();
+    let grpc = MockGrpc::start().await.unwrap();
+    let capnp = MockCapnp::start().await.unwrap();
+    std::env::set_var("PROTOBENCH_REST_TARGET", rest.addr().to_string());
+    std::env::set_var("PROTOBENCH_GRPC_TARGET", grpc.addr().to_string());
+    std::env::set_var("PROTOBENCH_CAPNP_TARGET", capnp.addr().to_string());
+    std::env::remove_var("PROTOBENCH_CAPNP_UDS");
+
+    // A bound no mock can miss, so each search climbs to the maximum
+    let settings = CapacitySettings {
+        p99_bound: Duration::from_secs(10),
+        batch_size: 10,
+        step: Duration::from_millis(200),
+        start_rate: 100.0,
+        max_rate: 400.0,
+        precision: 0.05,
+    };
+    let report = capacity::run(settings, |_, _| {}).await.unwrap();
+
+    assert_eq!(report.results.len(), protocol_clients().len());
+    for result in &report.results {
+        assert_eq!(result.sustainable_rate, 400.0, "{:?}", result);
+        assert_eq!(result.probes.len(), 3, "{:?}", result);
+        let sustained = result.sustained().unwrap();
+        assert!(sustained.completed > 0 && sustained.failures.total() == 0, "{:?}", sustained);
+    }
+    assert!(report.to_string().contains("Points/s"));
+}
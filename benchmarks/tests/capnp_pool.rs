@@ -0,0 +1,49 @@
+//! `capnp_client::WorkerPool` against `benchmarks::mock::MockCapnp`, its
+//! calls spawned as tasks on a multi-threaded runtime. The client reads its
+//! target once per process, so there is one test.
+
+use benchmarks::capnp_client::WorkerPool;
+use benchmarks::mock::MockCapnp;
+use benchmarks::ProtocolError;
+use shared::{generate_test_data, MetricQuery};
+use std::sync::Arc;
+
+const BATCHES: usize = 32;
+const BATCH_SIZE: usize = 10;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn pooled_calls_run_concurrently_from_spawned_tasks() {
+    let mock = MockCapnp::start().await.unwrap();
+    std::env::set_var("PROTOBENCH_CAPNP_TARGET", mock.addr().to_string());
+    std::env::remove_var("PROTOBENCH_CAPNP_UDS");
+    let pool = Arc::new(WorkerPool::start(3).unwrap());
+    assert_eq!(pool.workers(), 3);
+
+    let points = generate_test_data(BATCHES * BATCH_SIZE);
+    let submits: Vec<_> = points
+        .chunks(BATCH_SIZE)
+        .map(|batch| {
+            let pool = pool.clone();
+            let batch = batch.to_vec();
+            tokio::spawn(async move { pool.submit_metrics(batch).await })
+        })
+        .collect();
+    for submit in submits {
+        assert_eq!(submit.await.unwrap().unwrap(), BATCH_SIZE as u64);
+    }
+    pool.submit_metric(points[0].clone()).await.unwrap();
+
+    let stored = pool.query_metrics(MetricQuery::all()).await.unwrap();
+    assert_eq!(stored.len(), points.len() + 1);
+    for point in &points {
+        assert!(stored.contains(point), "{:?} missing", point);
+    }
+
+    // Errors come back from the worker thread in their usual category
+    mock.fail_next(capnp::Error::failed("end before start".to_string()));
+    match pool.query_metrics(MetricQuery::all()).await {
+        Err(ProtocolError::Server { code, .. }) => assert_eq!(code, "failed"),
+        other => panic!("expected a server error, got {:?}", other),
+    }
+    assert_eq!(pool.query_metrics(MetricQuery::all()).await.unwrap().len(), points.len() + 1);
+}
@@ -0,0 +1,80 @@
+//! The legacy Cap'n Proto size estimator against exact serialized sizes,
+//! over generated corpora of different shapes. Runs without any service.
+
+use benchmarks::payload_measurement::{compare_capnp_metric_sizes, measure_capnp_metric_size, measure_capnp_metric_wire_size};
+use shared::{CapnpEncoding, MetricPoint, TestDataGenerator, TextMode};
+use std::collections::HashMap;
+
+const POINTS: usize = 50;
+
+/// Shapes small enough to serialize into the builder's first segment
+fn corpora() -> Vec<TestDataGenerator> {
+    vec![
+        TestDataGenerator::new(),
+        TestDataGenerator::new().tags_per_metric(0),
+        TestDataGenerator::new().tags_per_metric(16).tag_value_len(64),
+        TestDataGenerator::new().hostname_len(200),
+        TestDataGenerator::new().text(TextMode::Unicode),
+        TestDataGenerator::new().extreme_values(true),
+    ]
+}
+
+#[test]
+fn a_point_is_sized_word_by_word() {
+    let metric = MetricPoint {
+        timestamp: 1_700_000_000,
+        hostname: "host-1".to_string(),
+        cpu_percent: 50.0,
+        memory_bytes: 1024,
+        disk_io_ops: 7,
+        tags: HashMap::from([("env".to_string(), "prod".to_string())]),
+    };
+
+    // Segment table, root pointer, 3 data words and 2 pointers, the hostname
+    // and its NUL in a word, the tag list's tag word, the tag's 2 pointers,
+    // and its key and value in a word each
+    assert_eq!(measure_capnp_metric_wire_size(&metric, CapnpEncoding::Unpacked).unwrap(), 8 + 8 + 24 + 16 + 8 + 8 + 16 + 8 + 8);
+    assert_eq!(measure_capnp_metric_size(&metric), 24 + 6 + (3 + 4 + 8) + 32);
+}
+
+#[test]
+fn the_estimate_undercounts_by_at_most_padding_and_pointers() {
+    for generator in corpora() {
+        for metric in generator.generate(POINTS) {
+            let estimated = measure_capnp_metric_size(&metric);
+            let actual = measure_capnp_metric_wire_size(&metric, CapnpEncoding::Unpacked).unwrap();
+            // The segment table, root pointer and tag word come to 8 bytes over
+            // the estimate's fixed overhead, each text gains up to 8 bytes of
+            // NUL and padding, and each tag has 16 bytes of pointers against
+            // the estimate's 8
+            let most = 8 + 8 + metric.tags.len() * (8 + 8 + 8);
+            assert!(estimated <= actual, "{}: estimated {} > actual {}", generator.label(), estimated, actual);
+            assert!(actual - estimated <= most, "{}: {} bytes off, at most {} expected", generator.label(), actual - estimated, most);
+        }
+    }
+}
+
+#[test]
+fn oversized_points_are_still_undercounted() {
+    let metric = &TestDataGenerator::new().adversarial().generate(1)[0];
+
+    assert!(measure_capnp_metric_size(metric) < measure_capnp_metric_wire_size(metric, CapnpEncoding::Unpacked).unwrap());
+}
+
+#[test]
+fn corrections_scale_estimates_to_the_wire() {
+    for generator in corpora() {
+        let metrics = generator.generate(POINTS);
+        for encoding in CapnpEncoding::ALL {
+            let sizes = compare_capnp_metric_sizes(&metrics, encoding).unwrap();
+            let actual: usize = metrics.iter().map(|metric| measure_capnp_metric_wire_size(metric, encoding).unwrap()).sum();
+            let estimated: usize = metrics.iter().map(measure_capnp_metric_size).sum();
+
+            assert_eq!((sizes.estimated_bytes, sizes.actual_bytes), (estimated, actual), "{}", generator.label());
+            let corrected = sizes.estimated_bytes as f64 * sizes.correction();
+            assert!((corrected - actual as f64).abs() < 1e-6, "{}", generator.label());
+        }
+        let unpacked = compare_capnp_metric_sizes(&metrics, CapnpEncoding::Unpacked).unwrap();
+        assert!(unpacked.correction() >= 1.0, "{}", generator.label());
+    }
+}
@@ -0,0 +1,96 @@
+use benchmarks::check::{Limits, Thresholds};
+use benchmarks::results::{BenchmarkRun, OperationResult};
+use benchmarks::{BenchmarkMetrics, PayloadSizes};
+use std::time::Duration;
+
+const THRESHOLDS: &str = r#"
+points = 50
+
+[limits]
+max_latency_ms = 100.0
+max_memory_bytes = 1_000_000
+
+[operations.query]
+max_total_bytes = 20_000
+
+[protocols.REST]
+max_latency_ms = 200.0
+
+[protocols."REST[relay]"]
+max_total_bytes = 30_000
+"#;
+
+fn result(protocol: &str, operation: &str, latency_ms: u64, total_bytes: usize, memory: usize) -> OperationResult {
+    let metrics = BenchmarkMetrics {
+        latency: Duration::from_millis(latency_ms),
+        payload_size: PayloadSizes::new(100, total_bytes - 100),
+        memory_allocated: memory,
+        cpu_cycles: 0,
+        time_to_first_byte: None,
+    };
+    OperationResult::new(protocol, operation, 50, &metrics)
+}
+
+#[test]
+fn the_repository_thresholds_parse() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../thresholds.toml");
+    let thresholds = Thresholds::read_from(path).unwrap();
+
+    assert!(thresholds.points > 0);
+    assert!(thresholds.limits.max_latency_ms.is_some());
+}
+
+#[test]
+fn points_default_when_left_out() {
+    assert_eq!(Thresholds::from_toml("").unwrap().points, 100);
+}
+
+#[test]
+fn unknown_settings_are_rejected() {
+    let error = format!("{:#}", Thresholds::from_toml("[limits]\nmax_latency = 5.0\n").unwrap_err());
+    assert!(error.contains("max_latency"), "{}", error);
+}
+
+#[test]
+fn sections_override_field_by_field() {
+    let thresholds = Thresholds::from_toml(THRESHOLDS).unwrap();
+
+    assert_eq!(thresholds.points, 50);
+    assert_eq!(
+        thresholds.limits_for("gRPC", "submit_batch"),
+        Limits { max_latency_ms: Some(100.0), max_total_bytes: None, max_memory_bytes: Some(1_000_000) }
+    );
+    assert_eq!(thresholds.limits_for("gRPC[tls]", "query").max_total_bytes, Some(20_000));
+    assert_eq!(thresholds.limits_for("REST", "query").max_latency_ms, Some(200.0));
+    assert_eq!(thresholds.limits_for("REST[gzip]", "query").max_latency_ms, Some(200.0));
+
+    // The exact label beats both the bare protocol and the operation
+    let relay = thresholds.limits_for("REST[relay]", "query");
+    assert_eq!(relay.max_total_bytes, Some(30_000));
+    assert_eq!(relay.max_latency_ms, Some(200.0));
+}
+
+#[test]
+fn only_exceeded_limits_are_reported() {
+    let thresholds = Thresholds::from_toml(THRESHOLDS).unwrap();
+    let mut run = BenchmarkRun::new();
+    run.push(result("gRPC", "query", 40, 12_000, 500_000));
+    run.push(result("REST", "query", 150, 25_000, 500_000));
+    run.push(result("CapnProto", "submit_batch", 120, 90_000, 2_000_000));
+
+    let violations = thresholds.check(&run);
+    let found: Vec<(&str, &str)> = violations.iter().map(|violation| (violation.protocol.as_str(), violation.metric)).collect();
+    assert_eq!(found, [("REST", "total_bytes"), ("CapnProto", "latency_ms"), ("CapnProto", "memory_bytes")]);
+
+    let message = violations[1].to_string();
+    assert_eq!(message, "CapnProto submit_batch: latency_ms 120.00 exceeds the limit of 100.00");
+}
+
+#[test]
+fn a_limit_is_inclusive() {
+    let thresholds = Thresholds::from_toml(THRESHOLDS).unwrap();
+    let mut run = BenchmarkRun::new();
+    run.push(result("gRPC", "query", 100, 20_000, 1_000_000));
+
+    assert!(thresholds.check(&run).is_empty());
+}
@@ -0,0 +1,66 @@
+//! Connection setup timings, and new connections opened to the in-process
+//! mock services from `benchmarks::mock`
+
+use benchmarks::connection::{self, ConnectionResult, ConnectionTimings, CONNECT_OPERATION};
+use benchmarks::FailureBreakdown;
+use benchmarks::mock::{MockCapnp, MockGrpc, MockRest};
+use benchmarks::protocol_clients;
+use std::time::Duration;
+
+fn timings(socket_us: u64, first_us: u64, warm_us: u64) -> ConnectionTimings {
+    ConnectionTimings {
+        dns_lookup: Duration::ZERO,
+        socket_connect: Duration::from_micros(socket_us),
+        first_request: Duration::from_micros(first_us),
+        warm_request: Duration::from_micros(warm_us),
+    }
+}
+
+#[test]
+fn setup_is_what_the_first_request_took_beyond_a_warm_one() {
+    let timing = timings(100, 1_500, 300);
+
+    assert_eq!(timing.setup(), Duration::from_micros(1_200));
+    assert_eq!(timing.handshake(), Duration::from_micros(1_100));
+    // A first request that happened to beat the warm one set up for free
+    assert_eq!(timings(100, 250, 300).setup(), Duration::ZERO);
+    assert_eq!(timings(100, 250, 300).handshake(), Duration::ZERO);
+    // A lookup the first request made is part of setup but not the handshake
+    let looked_up = ConnectionTimings { dns_lookup: Duration::from_micros(400), ..timing };
+    assert_eq!(looked_up.setup(), Duration::from_micros(1_200));
+    assert_eq!(looked_up.handshake(), Duration::from_micros(700));
+}
+
+#[test]
+fn results_summarise_every_connection() {
+    let result = ConnectionResult::from_timings("gRPC", &[timings(100, 1_500, 300), timings(200, 2_500, 500)], FailureBreakdown::default());
+
+    assert_eq!(result.connections, 2);
+    assert!((result.setup_ms.unwrap().mean - 1.6).abs() < 1e-9);
+    assert!((result.socket_connect_ms.unwrap().mean - 0.15).abs() < 1e-9);
+    assert!((result.warm_request_ms.unwrap().mean - 0.4).abs() < 1e-9);
+    assert!(ConnectionResult::from_timings("gRPC", &[], FailureBreakdown::default()).setup_ms.is_none());
+}
+
+#[tokio::test]
+async fn new_connections_to_mocks() {
+    let rest = MockRest::start().await.unwrap();
+    let grpc = MockGrpc::start().await.unwrap();
+    let capnp = MockCapnp::start().await.unwrap();
+    std::env::set_var("PROTOBENCH_REST_TARGET", rest.addr().to_string());
+    std::env::set_var("PROTOBENCH_GRPC_TARGET", grpc.addr().to_string());
+    std::env::set_var("PROTOBENCH_CAPNP_TARGET", capnp.addr().to_string());
+    std::env::remove_var("PROTOBENCH_CAPNP_UDS");
+
+    let report = connection::run(5).await.unwrap();
+
+    assert_eq!(report.results.len(), protocol_clients().len());
+    for result in &report.results {
+        assert_eq!(result.connections, 5, "{:?}", result);
+        assert_eq!(result.failures.total(), 0, "{:?}", result);
+        assert!(result.socket_connect_ms.unwrap().mean > 0.0, "{:?}", result);
+    }
+    let run = report.to_run();
+    assert_eq!(run.results.len(), report.results.len());
+    assert!(run.results.iter().all(|result| result.operation == CONNECT_OPERATION && result.points == 0));
+}
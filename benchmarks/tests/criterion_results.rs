@@ -0,0 +1,126 @@
+use benchmarks::criterion_results::{collect, export};
+use benchmarks::results::BenchmarkRun;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// A criterion output directory under the system temp dir, removed when dropped
+struct CriterionDir(PathBuf);
+
+impl CriterionDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("protobench-criterion-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+
+    /// Write a measurement the way criterion lays it out, with a stale `base`
+    /// copy beside it
+    fn measure(&self, dir: &str, group: &str, function: Option<&str>, value: Option<&str>, mean_ns: f64) {
+        let id = serde_json::json!({
+            "group_id": group,
+            "function_id": function,
+            "value_str": value,
+            "throughput": null,
+            "full_id": dir,
+            "directory_name": dir,
+            "title": dir,
+        });
+        let estimate = |point: f64| {
+            serde_json::json!({
+                "confidence_interval": {"confidence_level": 0.95, "lower_bound": point * 0.9, "upper_bound": point * 1.1},
+                "point_estimate": point,
+                "standard_error": point * 0.05,
+            })
+        };
+        let estimates = serde_json::json!({
+            "mean": estimate(mean_ns),
+            "median": estimate(mean_ns),
+            "median_abs_dev": estimate(1.0),
+            "slope": null,
+            "std_dev": estimate(1.0),
+        });
+        for (subdir, mean) in [("base", mean_ns * 2.0), ("new", mean_ns)] {
+            let path = self.0.join(dir).join(subdir);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("benchmark.json"), id.to_string()).unwrap();
+            let estimates = if subdir == "new" { estimates.clone() } else { serde_json::json!({"mean": estimate(mean)}) };
+            std::fs::write(path.join("estimates.json"), estimates.to_string()).unwrap();
+        }
+    }
+}
+
+impl Drop for CriterionDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn long_ago() -> SystemTime {
+    SystemTime::now() - Duration::from_secs(3600)
+}
+
+#[test]
+fn measurements_become_results() {
+    let dir = CriterionDir::new("ids");
+    dir.measure("submit_scaling/gRPC/100", "submit_scaling", Some("gRPC"), Some("100"), 250_000.0);
+    dir.measure("compressibility/REST_query/noise", "compressibility", Some("REST/query"), Some("noise"), 1_500_000.5);
+    dir.measure("query_single/CapnProto", "query_single", Some("CapnProto"), None, 90_000.0);
+
+    let mut results = collect(&dir.0, long_ago()).unwrap();
+    results.sort_by(|a, b| a.operation.cmp(&b.operation));
+
+    let found: Vec<(&str, &str, usize)> =
+        results.iter().map(|result| (result.protocol.as_str(), result.operation.as_str(), result.points)).collect();
+    assert_eq!(
+        found,
+        [
+            ("REST/query", "compressibility/noise", 0),
+            ("CapnProto", "query_single", 0),
+            ("gRPC", "submit_scaling", 100),
+        ]
+    );
+    // `new` holds the latest measurement; `base` is ignored
+    assert_eq!(results[2].latency_ns, 250_000);
+    assert_eq!(results[2].latency_ci_ns, Some([225_000, 275_000]));
+    assert_eq!(results[2].request_bytes + results[2].response_bytes, 0);
+}
+
+#[test]
+fn only_measurements_since_the_run_started_are_collected() {
+    let dir = CriterionDir::new("since");
+    dir.measure("query_single/REST", "query_single", Some("REST"), None, 1_000.0);
+
+    assert_eq!(collect(&dir.0, long_ago()).unwrap().len(), 1);
+    assert!(collect(&dir.0, SystemTime::now() + Duration::from_secs(3600)).unwrap().is_empty());
+}
+
+#[test]
+fn a_missing_directory_has_no_results() {
+    assert!(collect(Path::new("/nonexistent/criterion"), long_ago()).unwrap().is_empty());
+}
+
+#[test]
+fn export_appends_to_an_existing_run() {
+    let dir = CriterionDir::new("export");
+    std::fs::create_dir_all(&dir.0).unwrap();
+    let path = dir.0.join("run.json");
+    let mut run = BenchmarkRun::new();
+    run.push(benchmarks::results::OperationResult::new("gRPC", "query", 1000, &benchmarks::BenchmarkMetrics {
+        latency: Duration::from_millis(3),
+        payload_size: benchmarks::PayloadSizes::new(100, 100_000),
+        memory_allocated: 0,
+        cpu_cycles: 0,
+        time_to_first_byte: None,
+    }));
+    run.write_to(&path).unwrap();
+
+    std::env::set_var("CRITERION_HOME", dir.0.join("criterion"));
+    dir.measure("criterion/query_single/gRPC", "query_single", Some("gRPC"), None, 80_000.0);
+    let added = export(&path, long_ago()).unwrap();
+
+    assert_eq!(added, 1);
+    let stored = BenchmarkRun::read_from(&path).unwrap();
+    assert_eq!(stored.started_at_ms, run.started_at_ms);
+    assert_eq!(stored.results.len(), 2);
+    assert_eq!(stored.results[1].operation, "query_single");
+}
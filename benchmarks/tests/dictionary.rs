@@ -0,0 +1,61 @@
+//! zstd dictionary compression of the payloads the clients submit
+
+use benchmarks::dictionary::{self, DictionarySettings, Payload, COMPRESS_OPERATION};
+use shared::generate_test_data;
+
+fn settings() -> DictionarySettings {
+    DictionarySettings { batch_sizes: vec![1, 50], training_samples: 500, rounds: 2, ..DictionarySettings::default() }
+}
+
+#[test]
+fn rest_batches_are_one_body_and_capnp_batches_a_message_per_point() {
+    let metrics = generate_test_data(5);
+
+    assert_eq!(Payload::Rest.encode(&metrics).unwrap().len(), 1);
+    assert_eq!(Payload::Capnp.encode(&metrics).unwrap().len(), 5);
+}
+
+#[test]
+fn compressed_payloads_round_trip_with_and_without_a_dictionary() {
+    let samples: Vec<Vec<u8>> = generate_test_data(500).iter().flat_map(|point| Payload::Capnp.encode(std::slice::from_ref(point)).unwrap()).collect();
+    let dictionary = dictionary::train(&samples, 4096).unwrap();
+    assert!(!dictionary.is_empty() && dictionary.len() <= 4096);
+
+    // `measure` checks every decompressed payload against its original
+    let payloads = Payload::Capnp.encode(&generate_test_data(20)).unwrap();
+    let raw: u64 = payloads.iter().map(|payload| payload.len() as u64).sum();
+    let plain = dictionary::measure(&payloads, &[], 3, 1).unwrap();
+    let with_dictionary = dictionary::measure(&payloads, &dictionary, 3, 1).unwrap();
+    assert!(plain.bytes > 0 && with_dictionary.bytes > 0);
+    assert!(with_dictionary.bytes < raw);
+}
+
+#[test]
+fn a_dictionary_shrinks_single_point_payloads_beyond_plain_zstd() {
+    let report = dictionary::run(&settings()).unwrap();
+
+    assert_eq!(report.dictionaries.len(), Payload::ALL.len());
+    for result in report.results.iter().filter(|result| result.points == 1) {
+        assert!(
+            result.dictionary.bytes < result.plain.bytes,
+            "{}: {} bytes with the dictionary, {} without",
+            result.protocol,
+            result.dictionary.bytes,
+            result.plain.bytes
+        );
+    }
+}
+
+#[test]
+fn the_run_records_each_batch_raw_and_compressed_both_ways() {
+    let report = dictionary::run(&settings()).unwrap();
+    let run = report.to_run();
+
+    assert_eq!(run.results.len(), report.results.len() * 3);
+    assert!(run.results.iter().all(|result| result.operation == COMPRESS_OPERATION));
+    let rest = Payload::Rest.label();
+    let result = |protocol: String| run.results.iter().find(|result| result.protocol == protocol && result.points == 50).unwrap();
+    assert_eq!(result(rest.clone()).latency_ns, 0);
+    assert!(result(format!("{}+zstd", rest)).request_bytes < result(rest.clone()).request_bytes);
+    assert!(result(format!("{}+zstd-dict", rest)).request_bytes > 0);
+}
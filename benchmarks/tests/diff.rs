@@ -0,0 +1,90 @@
+use benchmarks::diff::RunDiff;
+use benchmarks::results::{BenchmarkRun, OperationResult};
+use benchmarks::{BenchmarkMetrics, PayloadSizes};
+use std::time::Duration;
+
+fn metrics(latency_us: u64, request_bytes: usize, response_bytes: usize) -> BenchmarkMetrics {
+    BenchmarkMetrics {
+        latency: Duration::from_micros(latency_us),
+        payload_size: PayloadSizes::new(request_bytes, response_bytes),
+        memory_allocated: 4_096,
+        cpu_cycles: latency_us * 3_000,
+        time_to_first_byte: None,
+    }
+}
+
+fn run(results: &[(&str, &str, BenchmarkMetrics)]) -> BenchmarkRun {
+    let mut run = BenchmarkRun::new();
+    for (protocol, operation, metrics) in results {
+        run.push(OperationResult::new(*protocol, *operation, 100, metrics));
+    }
+    run
+}
+
+#[test]
+fn changes_are_percentages_of_the_baseline() {
+    let baseline = run(&[("REST", "query", metrics(1_000, 100, 900)), ("gRPC", "query", metrics(800, 100, 400))]);
+    let candidate = run(&[("gRPC", "query", metrics(400, 100, 400)), ("REST", "query", metrics(1_500, 100, 1_900))]);
+    let diff = RunDiff::new(&baseline, &candidate);
+
+    assert_eq!(diff.changes.len(), 2);
+    let rest = &diff.changes[0];
+    assert_eq!((rest.protocol.as_str(), rest.points), ("REST", 100));
+    assert_eq!((rest.baseline_latency_ns, rest.candidate_latency_ns), (1_000_000, 1_500_000));
+    assert_eq!(rest.latency_change, 50.0);
+    assert_eq!(rest.total_bytes_change, 100.0);
+    assert_eq!(rest.memory_change, 0.0);
+    assert_eq!(diff.changes[1].latency_change, -50.0);
+    assert!(diff.removed.is_empty() && diff.added.is_empty());
+}
+
+#[test]
+fn only_growth_past_the_limit_is_a_regression() {
+    let baseline = run(&[("REST", "query", metrics(1_000, 100, 900)), ("gRPC", "query", metrics(800, 100, 400))]);
+    let candidate = run(&[("REST", "query", metrics(1_050, 100, 900)), ("gRPC", "query", metrics(400, 100, 600))]);
+    let diff = RunDiff::new(&baseline, &candidate);
+
+    // REST is 5% slower; gRPC is faster but sends 40% more bytes
+    let regressions: Vec<&str> = diff.regressions(10.0).iter().map(|change| change.protocol.as_str()).collect();
+    assert_eq!(regressions, ["gRPC"]);
+    assert_eq!(diff.regressions(4.0).len(), 2);
+    assert!(diff.regressions(50.0).is_empty());
+}
+
+#[test]
+fn unmatched_results_are_listed_apart() {
+    let baseline = run(&[("REST", "query", metrics(1_000, 100, 900)), ("REST", "submit_batch", metrics(900, 900, 10))]);
+    let candidate = run(&[("REST", "query", metrics(1_000, 100, 900)), ("CapnProto", "query", metrics(500, 80, 700))]);
+    let diff = RunDiff::new(&baseline, &candidate);
+
+    assert_eq!(diff.changes.len(), 1);
+    assert_eq!(diff.removed, ["REST submit_batch 100"]);
+    assert_eq!(diff.added, ["CapnProto query 100"]);
+    let table = diff.to_string();
+    assert!(table.contains("only in baseline:  REST submit_batch 100"), "{}", table);
+    assert!(table.contains("only in candidate: CapnProto query 100"), "{}", table);
+}
+
+#[test]
+fn growth_from_zero_is_infinite() {
+    let baseline = run(&[("REST", "query", BenchmarkMetrics { memory_allocated: 0, ..metrics(1_000, 0, 0) })]);
+    let candidate = run(&[("REST", "query", BenchmarkMetrics { memory_allocated: 0, ..metrics(1_000, 0, 10) })]);
+    let change = &RunDiff::new(&baseline, &candidate).changes[0];
+
+    assert_eq!(change.memory_change, 0.0);
+    assert_eq!(change.total_bytes_change, f64::INFINITY);
+}
+
+#[test]
+fn allocator_changes_are_listed() {
+    let baseline = run(&[("REST", "query", metrics(1_000, 100, 900))]);
+    let mut candidate = baseline.clone();
+    candidate.allocators.insert("harness".to_string(), "jemalloc".to_string());
+    candidate.allocators.insert("rest".to_string(), "mimalloc".to_string());
+
+    // Only processes both runs recorded are compared
+    let diff = RunDiff::new(&baseline, &candidate);
+    assert_eq!(diff.allocator_changes, [format!("harness: {} -> jemalloc", shared::allocator_name())]);
+    assert!(diff.to_string().contains("allocator changed: harness"));
+    assert!(RunDiff::new(&baseline, &baseline).allocator_changes.is_empty());
+}
@@ -0,0 +1,52 @@
+//! A coordinator driving two agents in this process, each loading the
+//! in-process mock services from `benchmarks::mock`. The clients read their
+//! targets once per process, so everything runs in one test.
+
+use benchmarks::distributed::{self, Workload};
+use benchmarks::mock::{MockCapnp, MockGrpc, MockRest};
+use benchmarks::protocol_clients;
+use shared::MetricQuery;
+use tokio::net::TcpListener;
+use tokio::task::LocalSet;
+
+#[tokio::test]
+async fn coordinator_collects_every_agents_results() {
+    let rest = MockRest::start().await.unwrap();
+    let grpc = MockGrpc::start().await.unwrap();
+    let capnp = MockCapnp::start().await.unwrap();
+    std::env::set_var("PROTOBENCH_REST_TARGET", rest.addr().to_string());
+    std::env::set_var("PROTOBENCH_GRPC_TARGET", grpc.addr().to_string());
+    std::env::set_var("PROTOBENCH_CAPNP_TARGET", capnp.addr().to_string());
+    std::env::remove_var("PROTOBENCH_CAPNP_UDS");
+
+    // Agents' futures hold the clients, which aren't `Send`
+    let local = LocalSet::new();
+    let mut agents = Vec::new();
+    for _ in 0..2 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        agents.push(listener.local_addr().unwrap().to_string());
+        local.spawn_local(distributed::serve_agent(listener));
+    }
+    let workload = Workload { points: 5, rounds: 2 };
+    let report = local.run_until(distributed::coordinate(&agents, workload)).await.unwrap();
+
+    let protocols = protocol_clients().len();
+    assert_eq!(report.agents.len(), 2);
+    for ((agent, run), expected) in report.agents.iter().zip(&agents) {
+        assert_eq!(agent, expected);
+        // A submit and a query per round per protocol
+        assert_eq!(run.results.len(), protocols * 2 * 2);
+        assert!(run.results.iter().all(|result| result.points == 5));
+    }
+    let run = report.to_run();
+    assert_eq!(run.results.len(), protocols * 2 * 2 * 2);
+    assert_eq!(run.settings["PROTOBENCH_AGENTS"], agents.join(","));
+    // Four samples per protocol and operation, two from each agent
+    assert!(report.aggregate().rows().iter().all(|row| row.latency_ms.samples == 4));
+
+    // Each agent deleted its own points when it finished
+    let everything = MetricQuery::all();
+    for (_, client) in protocol_clients() {
+        assert_eq!(local.run_until(client.query_metrics(everything.clone())).await.unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,83 @@
+//! Every client operation against all three services, which the test starts
+//! itself on free loopback ports and stops at the end. The services have no
+//! library targets to serve from in-process, so they run as the binaries
+//! `cargo build --workspace` leaves in the target directory (or those in
+//! `PROTOBENCH_SERVICE_BIN_DIR`), with this test's environment.
+//!
+//! The clients read their targets once per process, so everything runs in a
+//! single test, pointed at the new ports before any client is used.
+
+use benchmarks::orchestrator::{free_local_addr, LocalServices};
+use benchmarks::{capnp_client, grpc_client, protocol_clients, rest_client};
+use futures_util::TryStreamExt;
+use shared::{generate_test_data, MetricPoint, MetricQuery};
+use std::time::Duration;
+
+const POINTS: usize = 20;
+
+/// `returned` holds exactly `expected`, in any order
+fn assert_same_points(returned: &[MetricPoint], expected: &[MetricPoint], context: &str) {
+    assert_eq!(returned.len(), expected.len(), "{}", context);
+    for point in expected {
+        assert!(returned.contains(point), "{}: {:?} missing", context, point);
+    }
+}
+
+#[tokio::test]
+async fn every_client_operation_succeeds_against_local_services() {
+    for variable in ["PROTOBENCH_REST_TARGET", "PROTOBENCH_GRPC_TARGET", "PROTOBENCH_CAPNP_TARGET"] {
+        std::env::set_var(variable, free_local_addr().unwrap());
+    }
+    std::env::remove_var("PROTOBENCH_CAPNP_UDS");
+    let services = LocalServices::start().await.unwrap();
+    let points = generate_test_data(POINTS);
+
+    for (name, client) in protocol_clients() {
+        client.submit_metric(points[0].clone()).await.unwrap();
+        assert_eq!(client.submit_batch(points[1..].to_vec()).await.unwrap(), (POINTS - 1) as u64, "{}", name);
+
+        assert_same_points(&client.query_metrics(MetricQuery::all()).await.unwrap(), &points, &name);
+        let returned = client.query_with_timeout(MetricQuery::all(), Duration::from_secs(5)).await.unwrap();
+        assert_same_points(&returned, &points, &name);
+        assert_eq!(client.get_statistics(MetricQuery::all()).await.unwrap().count, POINTS as u64, "{}", name);
+        let rollups = client.query_rollups(MetricQuery::all()).await.unwrap();
+        assert_eq!(rollups.iter().map(|rollup| rollup.count).sum::<u64>(), POINTS as u64, "{}", name);
+
+        assert_eq!(client.delete_metrics(MetricQuery::all()).await.unwrap(), POINTS as u64, "{}", name);
+        assert_eq!(client.query_metrics(MetricQuery::all()).await.unwrap(), vec![], "{}", name);
+    }
+
+    // What each protocol offers beyond `ProtocolClient`, on emptied services
+    rest_client::negotiated_encoding().await.unwrap();
+    assert_eq!(rest_client::submit_metrics(points.clone()).await.unwrap(), POINTS as u64);
+    assert_same_points(&rest_client::stream_metrics(MetricQuery::all()).await.unwrap(), &points, "REST stream");
+    let streamed: Vec<MetricPoint> = rest_client::query_metrics_stream(MetricQuery::all()).await.unwrap().try_collect().await.unwrap();
+    assert_same_points(&streamed, &points, "REST stream");
+    assert_eq!(rest_client::get_storage_stats().await.unwrap().point_count, POINTS as u64);
+    rest_client::delete_metrics(MetricQuery::all()).await.unwrap();
+    assert_eq!(rest_client::tail_metrics(MetricQuery::all(), points.clone()).await.unwrap(), POINTS);
+
+    grpc_client::check_health().await.unwrap();
+    assert_eq!(grpc_client::submit_metric_stream(points.clone()).await.unwrap(), POINTS as u64);
+    assert_same_points(&grpc_client::query_metrics_unary(MetricQuery::all()).await.unwrap(), &points, "gRPC unary");
+    let streamed: Vec<MetricPoint> = grpc_client::query_metrics_stream(MetricQuery::all()).await.unwrap().try_collect().await.unwrap();
+    assert_same_points(&streamed, &points, "gRPC stream");
+    assert_eq!(grpc_client::get_storage_stats().await.unwrap().point_count, POINTS as u64);
+    grpc_client::delete_metrics(MetricQuery::all()).await.unwrap();
+    assert_eq!(grpc_client::submit_metrics(points.clone()).await.unwrap(), POINTS as u64);
+    grpc_client::delete_metrics(MetricQuery::all()).await.unwrap();
+    assert_eq!(grpc_client::tail_metrics(MetricQuery::all(), points.clone()).await.unwrap(), POINTS);
+
+    assert_eq!(capnp_client::submit_metrics(points.clone()).await.unwrap(), POINTS as u64);
+    assert_same_points(&capnp_client::query_metrics_streaming(MetricQuery::all()).await.unwrap(), &points, "Cap'n Proto callback");
+    for pipelined in [false, true] {
+        let (statistics, rollups) = capnp_client::query_summary(MetricQuery::all(), pipelined).await.unwrap();
+        assert_eq!(statistics.count, POINTS as u64);
+        assert_eq!(rollups.iter().map(|rollup| rollup.count).sum::<u64>(), POINTS as u64);
+    }
+    assert_eq!(capnp_client::get_storage_stats().await.unwrap().point_count, POINTS as u64);
+    capnp_client::delete_metrics(MetricQuery::all()).await.unwrap();
+    assert_eq!(capnp_client::tail_metrics(MetricQuery::all(), points.clone()).await.unwrap(), POINTS);
+
+    drop(services);
+}
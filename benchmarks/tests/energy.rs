@@ -0,0 +1,84 @@
+use benchmarks::energy::{Energy, Rapl};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A fake powercap tree under the system temp dir, removed when dropped
+struct Powercap(PathBuf);
+
+impl Powercap {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("protobench-powercap-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn zone(&self, dir: &str, name: &str, energy_uj: u64, max_energy_range_uj: u64) {
+        let zone = self.0.join(dir);
+        std::fs::create_dir_all(&zone).unwrap();
+        std::fs::write(zone.join("name"), format!("{}\n", name)).unwrap();
+        std::fs::write(zone.join("max_energy_range_uj"), format!("{}\n", max_energy_range_uj)).unwrap();
+        self.set(dir, energy_uj);
+    }
+
+    fn set(&self, dir: &str, energy_uj: u64) {
+        std::fs::write(self.0.join(dir).join("energy_uj"), format!("{}\n", energy_uj)).unwrap();
+    }
+}
+
+impl Drop for Powercap {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn packages_are_summed_and_subzones_skipped() {
+    let powercap = Powercap::new("packages");
+    powercap.zone("intel-rapl:0", "package-0", 1_000_000, 10_000_000);
+    powercap.zone("intel-rapl:1", "package-1", 5_000_000, 10_000_000);
+    powercap.zone("intel-rapl:0:0", "core", 0, 10_000_000);
+    std::fs::create_dir_all(powercap.0.join("dtpm")).unwrap();
+
+    let rapl = Rapl::open(&powercap.0).unwrap();
+    assert_eq!(rapl.zones(), ["package-0", "package-1"]);
+
+    let start = rapl.read().unwrap();
+    powercap.set("intel-rapl:0", 3_500_000);
+    powercap.set("intel-rapl:1", 5_500_000);
+    powercap.set("intel-rapl:0:0", 9_000_000);
+    let end = rapl.read().unwrap();
+
+    assert_eq!(rapl.energy_between(&start, &end).joules, 3.0);
+}
+
+#[test]
+fn a_wrapped_counter_counts_past_its_range() {
+    let powercap = Powercap::new("wrap");
+    powercap.zone("intel-rapl:0", "package-0", 9_500_000, 10_000_000);
+
+    let rapl = Rapl::open(&powercap.0).unwrap();
+    let start = rapl.read().unwrap();
+    powercap.set("intel-rapl:0", 250_000);
+    let end = rapl.read().unwrap();
+
+    assert_eq!(rapl.energy_between(&start, &end).joules, 0.75);
+}
+
+#[test]
+fn opening_without_packages_fails() {
+    let powercap = Powercap::new("empty");
+    powercap.zone("intel-rapl:0:0", "core", 0, 10_000_000);
+
+    let error = Rapl::open(&powercap.0).unwrap_err();
+    assert!(error.to_string().contains("No RAPL package zones"), "{}", error);
+    assert!(Rapl::open(powercap.0.join("missing")).is_err());
+}
+
+#[test]
+fn energy_is_scaled_per_1000_requests() {
+    let energy = Energy { joules: 6.0, elapsed: Duration::from_millis(500) };
+
+    assert_eq!(energy.watts(), 12.0);
+    assert_eq!(energy.joules_per_1000(4_000), 1.5);
+}
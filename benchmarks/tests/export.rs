@@ -0,0 +1,128 @@
+use benchmarks::export::{otlp_json, prometheus_text, push, PushTarget};
+use benchmarks::results::{BenchmarkRun, OperationResult};
+use benchmarks::{BenchmarkMetrics, PayloadSizes};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn result(protocol: &str, operation: &str, points: usize, latency_ms: u64) -> OperationResult {
+    OperationResult::new(protocol, operation, points, &BenchmarkMetrics {
+        latency: Duration::from_millis(latency_ms),
+        payload_size: PayloadSizes::new(100, 2_000),
+        memory_allocated: 4_096,
+        cpu_cycles: 1_000,
+        time_to_first_byte: None,
+    })
+}
+
+fn run() -> BenchmarkRun {
+    let mut run = BenchmarkRun::new();
+    run.started_at_ms = 1_700_000_000_000;
+    run.push(result("REST", "query", 100, 12));
+    run.push(result("gRPC", "query", 100, 4));
+    run
+}
+
+#[test]
+fn prometheus_text_has_a_sample_per_result() {
+    let text = prometheus_text(&run());
+
+    assert!(text.contains("# TYPE protobench_latency_seconds gauge"), "{}", text);
+    assert!(text.contains("protobench_latency_seconds{protocol=\"REST\",operation=\"query\",points=\"100\"} 0.012\n"), "{}", text);
+    assert!(text.contains("protobench_response_bytes{protocol=\"gRPC\",operation=\"query\",points=\"100\"} 2000\n"), "{}", text);
+    assert!(text.contains("protobench_run_started_timestamp_seconds 1700000000\n"), "{}", text);
+    // Nothing streamed, so there's no first point family
+    assert!(!text.contains("time_to_first_byte"), "{}", text);
+}
+
+#[test]
+fn repeated_measurements_keep_the_last() {
+    let mut run = run();
+    run.push(result("REST", "query", 100, 20));
+    run.push(result("REST/ndjson", "query", 100, 9));
+
+    let text = prometheus_text(&run);
+    let latencies: Vec<&str> = text.lines().filter(|line| line.starts_with("protobench_latency_seconds{")).collect();
+    assert_eq!(latencies.len(), 3, "{}", text);
+    assert!(latencies[0].ends_with(" 0.02"), "{}", text);
+}
+
+#[test]
+fn label_values_are_escaped() {
+    let mut run = BenchmarkRun::new();
+    run.push(result("REST \"relay\"", "compressibility\\noise", 1, 1));
+
+    let text = prometheus_text(&run);
+    assert!(text.contains("protocol=\"REST \\\"relay\\\"\",operation=\"compressibility\\\\noise\""), "{}", text);
+}
+
+#[test]
+fn otlp_json_holds_a_gauge_per_measurement() {
+    let request = otlp_json(&run(), "cli");
+    let metrics = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+
+    let latency = metrics.as_array().unwrap().iter().find(|metric| metric["name"] == "protobench_latency_seconds").unwrap();
+    assert_eq!(latency["unit"], "s");
+    let points = latency["gauge"]["dataPoints"].as_array().unwrap();
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[1]["asDouble"], 0.004);
+    assert_eq!(points[1]["timeUnixNano"], "1700000000000000000");
+    assert!(points[1]["attributes"].as_array().unwrap().iter().any(|attribute| {
+        attribute["key"] == "protobench.source" && attribute["value"]["stringValue"] == "cli"
+    }));
+}
+
+/// Accept one request on a loopback port, answer 200 and hand back what
+/// was sent
+async fn capture_one_request() -> (String, tokio::task::JoinHandle<String>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 8192];
+        loop {
+            let read = socket.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length: ").map(str::to_string))
+                    .and_then(|length| length.parse::<usize>().ok())
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    break;
+                }
+            }
+            if read == 0 {
+                break;
+            }
+        }
+        socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    (url, handle)
+}
+
+#[tokio::test]
+async fn pushgateway_groups_by_source() {
+    let (url, request) = capture_one_request().await;
+
+    push(&run(), "check", &PushTarget::Pushgateway { url: format!("{}/", url), job: "nightly".to_string() }).await.unwrap();
+
+    let request = request.await.unwrap();
+    assert!(request.starts_with("PUT /metrics/job/nightly/source/check HTTP/1.1"), "{}", request);
+    assert!(request.contains("protobench_cost_score{protocol=\"gRPC\""), "{}", request);
+}
+
+#[tokio::test]
+async fn otlp_posts_to_the_metrics_path() {
+    let (endpoint, request) = capture_one_request().await;
+
+    push(&run(), "criterion", &PushTarget::Otlp { endpoint }).await.unwrap();
+
+    let request = request.await.unwrap();
+    assert!(request.starts_with("POST /v1/metrics HTTP/1.1"), "{}", request);
+    assert!(request.to_ascii_lowercase().contains("content-type: application/json"), "{}", request);
+    assert!(request.contains("\"stringValue\":\"criterion\""), "{}", request);
+}
@@ -0,0 +1,75 @@
+//! The fleet schedule, and a small fleet driven against the in-process
+//! mock services from `benchmarks::mock`
+
+use benchmarks::fleet::{self, schedule, FleetSettings, FLEET_OPERATION};
+use benchmarks::mock::{MockCapnp, MockGrpc, MockRest};
+use benchmarks::protocol_clients;
+use std::time::Duration;
+
+fn settings(hosts: usize, jitter: f64) -> FleetSettings {
+    FleetSettings {
+        hosts,
+        interval: Duration::from_secs(10),
+        jitter,
+        duration: Duration::from_secs(60),
+        seed: 7,
+    }
+}
+
+#[test]
+fn schedules_are_repeatable_and_ordered() {
+    let due = schedule(&settings(100, 0.1));
+
+    assert_eq!(due, schedule(&settings(100, 0.1)));
+    assert_ne!(due, schedule(&FleetSettings { seed: 8, ..settings(100, 0.1) }));
+    assert!(due.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    assert!(due.iter().all(|&(at, host)| at < Duration::from_secs(60) && host < 100));
+    assert_eq!(settings(100, 0.1).offered_rate(), 10.0);
+}
+
+#[test]
+fn every_host_submits_once_per_interval_within_the_jitter() {
+    let due = schedule(&settings(50, 0.2));
+
+    for host in 0..50 {
+        let times: Vec<f64> = due.iter().filter(|&&(_, h)| h == host).map(|(at, _)| at.as_secs_f64()).collect();
+        assert!(times[0] < 10.0, "host {} starts at {}", host, times[0]);
+        for pair in times.windows(2) {
+            let wait = pair[1] - pair[0];
+            assert!((8.0..=12.0).contains(&wait), "host {} waited {}", host, wait);
+        }
+    }
+    // Without jitter every host submits exactly 6 times in 60 seconds
+    assert_eq!(schedule(&settings(50, 0.0)).len(), 300);
+}
+
+#[tokio::test]
+async fn a_small_fleet_against_mocks() {
+    let rest = MockRest::start().await.unwrap();
+    let grpc = MockGrpc::start().await.unwrap();
+    let capnp = MockCapnp::start().await.unwrap();
+    std::env::set_var("PROTOBENCH_REST_TARGET", rest.addr().to_string());
+    std::env::set_var("PROTOBENCH_GRPC_TARGET", grpc.addr().to_string());
+    std::env::set_var("PROTOBENCH_CAPNP_TARGET", capnp.addr().to_string());
+    std::env::remove_var("PROTOBENCH_CAPNP_UDS");
+
+    let settings = FleetSettings {
+        hosts: 20,
+        interval: Duration::from_millis(100),
+        jitter: 0.0,
+        duration: Duration::from_millis(500),
+        seed: 1,
+    };
+    let report = fleet::run(settings).await.unwrap();
+
+    assert_eq!(report.results.len(), protocol_clients().len());
+    for result in &report.results {
+        assert_eq!(result.due, 100, "{:?}", result);
+        assert_eq!(result.submitted, 100, "{:?}", result);
+        assert!(result.p50_ms <= result.p99_ms && result.p99_ms <= result.max_ms, "{:?}", result);
+        assert!(result.peak_in_flight >= 1);
+    }
+    let run = report.to_run();
+    assert_eq!(run.results.len(), report.results.len());
+    assert!(run.results.iter().all(|result| result.operation == FLEET_OPERATION && result.points == 20));
+}
@@ -0,0 +1,30 @@
+use benchmarks::host_metrics::{completed_disk_ops, HostSampler};
+
+const DISKSTATS: &str = "\
+   7       0 loop0 52 0 2116 10 0 0 0 0 0 24 10 0 0 0 0 0 0
+ 259       0 nvme0n1 120543 4367 9483214 30112 98231 61234 7251392 81234 0 91200 111346 0 0 0 0 0 0
+ 259       1 nvme0n1p1 812 0 42110 301 2 0 2 0 0 144 301 0 0 0 0 0 0
+   8       0 sda 1000 10 8000 500 2000 20 16000 900 0 1200 1400
+";
+
+#[test]
+fn disk_ops_count_reads_and_writes_on_chosen_devices() {
+    let ops = completed_disk_ops(DISKSTATS, |device| device == "nvme0n1" || device == "sda");
+
+    assert_eq!(ops, 120_543 + 98_231 + 1_000 + 2_000);
+    assert_eq!(completed_disk_ops(DISKSTATS, |_| false), 0);
+    // Short or malformed lines are skipped
+    assert_eq!(completed_disk_ops("8 0 sda 12\n8 0 sdb x 0 0 0 y\n", |_| true), 0);
+}
+
+#[test]
+fn samples_are_valid_points() {
+    let mut sampler = HostSampler::new();
+    for _ in 0..2 {
+        let point = sampler.sample();
+        point.validate().unwrap();
+        assert!((0.0..=100.0).contains(&point.cpu_percent), "{:?}", point);
+        assert!(point.memory_bytes > 0, "{:?}", point);
+        assert_eq!(point.tags["source"], "protobench-agent");
+    }
+}
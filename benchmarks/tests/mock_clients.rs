@@ -0,0 +1,137 @@
+//! Each client against its in-process mock from `benchmarks::mock`: points
+//! survive the client's conversions, service errors come back in the right
+//! `ProtocolError` category, and REST waits out `429`s. The clients read
+//! their targets once per process, so each protocol gets one test.
+
+use benchmarks::mock::{MockCapnp, MockGrpc, MockResponse, MockRest};
+use benchmarks::{capnp_client, grpc_client, rest_client, ProtocolError};
+use reqwest::{header, StatusCode};
+use shared::{generate_test_data, MetricPoint, MetricQuery};
+
+const POINTS: usize = 10;
+
+/// `returned` holds exactly `expected`, in any order
+fn assert_same_points(returned: &[MetricPoint], expected: &[MetricPoint]) {
+    assert_eq!(returned.len(), expected.len());
+    for point in expected {
+        assert!(returned.contains(point), "{:?} missing", point);
+    }
+}
+
+/// `code` and `message` of a `ProtocolError::Server`, panicking on anything else
+fn server_error<T: std::fmt::Debug>(result: Result<T, ProtocolError>) -> (String, String) {
+    match result {
+        Err(ProtocolError::Server { code, message, .. }) => (code, message),
+        other => panic!("expected a server error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn rest_client_against_mock() {
+    let mock = MockRest::start().await.unwrap();
+    std::env::set_var("PROTOBENCH_REST_TARGET", mock.addr().to_string());
+    let points = generate_test_data(POINTS);
+
+    rest_client::submit_metric(points[0].clone()).await.unwrap();
+    assert_eq!(rest_client::submit_metrics(points[1..].to_vec()).await.unwrap(), (POINTS - 1) as u64);
+    assert_same_points(&rest_client::query_metrics(MetricQuery::all()).await.unwrap(), &points);
+    assert_same_points(&rest_client::query_metrics_arrow(MetricQuery::all()).await.unwrap(), &points);
+    assert_eq!(rest_client::get_statistics(MetricQuery::all()).await.unwrap().count, POINTS as u64);
+    let rollups = rest_client::query_rollups(MetricQuery::all()).await.unwrap();
+    assert_eq!(rollups.iter().map(|rollup| rollup.count).sum::<u64>(), POINTS as u64);
+    assert_eq!(rest_client::get_storage_stats().await.unwrap().point_count, POINTS as u64);
+
+    // An error body's code and message, or the bare status without one
+    mock.respond_next(MockResponse::error(StatusCode::BAD_REQUEST, "invalid_query", "end before start"));
+    let (code, message) = server_error(rest_client::query_metrics(MetricQuery::all()).await);
+    assert_eq!((code.as_str(), message.as_str()), ("invalid_query", "end before start"));
+    mock.respond_next(MockResponse::new(StatusCode::SERVICE_UNAVAILABLE));
+    assert_eq!(server_error(rest_client::query_metrics(MetricQuery::all()).await).0, "503");
+
+    mock.respond_next(MockResponse::new(StatusCode::OK).header(header::CONTENT_TYPE, "application/json").body("[{"));
+    assert!(matches!(rest_client::query_metrics(MetricQuery::all()).await, Err(ProtocolError::Decode { .. })));
+
+    // One 429 is waited out and the request sent again
+    let (calls, throttled) = (mock.calls(), rest_client::throttled_responses());
+    mock.respond_next(MockResponse::new(StatusCode::TOO_MANY_REQUESTS).header(header::RETRY_AFTER, "0"));
+    assert_same_points(&rest_client::query_metrics(MetricQuery::all()).await.unwrap(), &points);
+    assert_eq!(mock.calls() - calls, 2);
+    assert_eq!(rest_client::throttled_responses() - throttled, 1);
+
+    // Past the default 3 retries the last 429 is the error
+    let calls = mock.calls();
+    for _ in 0..4 {
+        mock.respond_next(MockResponse::new(StatusCode::TOO_MANY_REQUESTS).header(header::RETRY_AFTER, "0"));
+    }
+    assert_eq!(server_error(rest_client::query_metrics(MetricQuery::all()).await).0, "429");
+    assert_eq!(mock.calls() - calls, 4);
+
+    assert_eq!(rest_client::delete_metrics(MetricQuery::all()).await.unwrap(), POINTS as u64);
+    assert_eq!(rest_client::query_metrics(MetricQuery::all()).await.unwrap(), vec![]);
+}
+
+#[tokio::test]
+async fn grpc_client_against_mock() {
+    let mock = MockGrpc::start().await.unwrap();
+    std::env::set_var("PROTOBENCH_GRPC_TARGET", mock.addr().to_string());
+    let points = generate_test_data(POINTS);
+
+    grpc_client::submit_metric(points[0].clone()).await.unwrap();
+    assert_eq!(grpc_client::submit_metric_stream(points[1..5].to_vec()).await.unwrap(), 4);
+    assert_eq!(grpc_client::submit_metrics(points[5..].to_vec()).await.unwrap(), (POINTS - 5) as u64);
+    assert_same_points(&grpc_client::query_metrics(MetricQuery::all()).await.unwrap(), &points);
+    assert_same_points(&grpc_client::query_metrics_unary(MetricQuery::all()).await.unwrap(), &points);
+    assert_eq!(grpc_client::get_statistics(MetricQuery::all()).await.unwrap().count, POINTS as u64);
+    let rollups = grpc_client::query_rollups(MetricQuery::all()).await.unwrap();
+    assert_eq!(rollups.iter().map(|rollup| rollup.count).sum::<u64>(), POINTS as u64);
+    assert_eq!(grpc_client::get_storage_stats().await.unwrap().point_count, POINTS as u64);
+
+    mock.fail_next(tonic::Status::invalid_argument("end before start"));
+    let (code, message) = server_error(grpc_client::query_metrics(MetricQuery::all()).await);
+    assert_eq!((code.as_str(), message.as_str()), ("InvalidArgument", "end before start"));
+
+    // Deadlines the server enforced, before and after the stream started
+    mock.fail_next(tonic::Status::cancelled("Timeout expired"));
+    assert!(grpc_client::query_metrics(MetricQuery::all()).await.unwrap_err().is_timeout());
+    mock.fail_next(tonic::Status::deadline_exceeded("Deadline exceeded while streaming metrics"));
+    assert!(grpc_client::query_metrics(MetricQuery::all()).await.unwrap_err().is_timeout());
+    // Any other cancellation is the server's
+    mock.fail_next(tonic::Status::cancelled("shutting down"));
+    assert_eq!(server_error(grpc_client::query_metrics(MetricQuery::all()).await).0, "Cancelled");
+
+    mock.fail_next(tonic::Status::unavailable("connection reset"));
+    assert!(matches!(grpc_client::submit_metric(points[0].clone()).await, Err(ProtocolError::Connect { .. })));
+
+    assert_eq!(grpc_client::delete_metrics(MetricQuery::all()).await.unwrap(), POINTS as u64);
+    assert_eq!(grpc_client::query_metrics(MetricQuery::all()).await.unwrap(), vec![]);
+}
+
+#[tokio::test]
+async fn capnp_client_against_mock() {
+    let mock = MockCapnp::start().await.unwrap();
+    std::env::set_var("PROTOBENCH_CAPNP_TARGET", mock.addr().to_string());
+    std::env::remove_var("PROTOBENCH_CAPNP_UDS");
+    let points = generate_test_data(POINTS);
+
+    capnp_client::submit_metric(points[0].clone()).await.unwrap();
+    assert_eq!(capnp_client::submit_metrics(points[1..].to_vec()).await.unwrap(), (POINTS - 1) as u64);
+    assert_same_points(&capnp_client::query_metrics(MetricQuery::all()).await.unwrap(), &points);
+    assert_eq!(capnp_client::get_statistics(MetricQuery::all()).await.unwrap().count, POINTS as u64);
+    let rollups = capnp_client::query_rollups(MetricQuery::all()).await.unwrap();
+    assert_eq!(rollups.iter().map(|rollup| rollup.count).sum::<u64>(), POINTS as u64);
+    assert_eq!(capnp_client::get_storage_stats().await.unwrap().point_count, POINTS as u64);
+
+    // The error kind is the code; the reason arrives with a remote prefix
+    mock.fail_next(capnp::Error::failed("end before start".to_string()));
+    let (code, message) = server_error(capnp_client::query_metrics(MetricQuery::all()).await);
+    assert_eq!(code, "failed");
+    assert!(message.ends_with("end before start"), "{}", message);
+    mock.fail_next(capnp::Error::overloaded("too many calls".to_string()));
+    assert_eq!(server_error(capnp_client::get_statistics(MetricQuery::all()).await).0, "overloaded");
+
+    mock.fail_next(capnp::Error::disconnected("connection reset".to_string()));
+    assert!(matches!(capnp_client::query_metrics(MetricQuery::all()).await, Err(ProtocolError::Connect { .. })));
+
+    assert_eq!(capnp_client::delete_metrics(MetricQuery::all()).await.unwrap(), POINTS as u64);
+    assert_eq!(capnp_client::query_metrics(MetricQuery::all()).await.unwrap(), vec![]);
+}
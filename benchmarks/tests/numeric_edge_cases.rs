@@ -0,0 +1,79 @@
+//! NaN and infinite `cpu_percent`, `u64::MAX` memory and negative timestamps
+//! through every protocol. These need all three services running at the
+//! targets `protocol_clients` uses, so they're ignored by default:
+//! `cargo test -p benchmarks --test numeric_edge_cases -- --ignored --test-threads 1`
+
+use benchmarks::{protocol_clients, ProtocolError};
+use shared::{MetricPoint, MetricQuery};
+use std::collections::HashMap;
+
+fn metric() -> MetricPoint {
+    MetricPoint {
+        timestamp: 1_700_000_000,
+        hostname: "edge-case".to_string(),
+        cpu_percent: 42.5,
+        memory_bytes: 8_589_934_592,
+        disk_io_ops: 150,
+        tags: HashMap::new(),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs rest-service, grpc-service and capnp-service running"]
+async fn invalid_numbers_are_rejected_by_validation_everywhere() {
+    let invalid = [
+        ("cpu_percent", MetricPoint { cpu_percent: f32::NAN, ..metric() }),
+        ("cpu_percent", MetricPoint { cpu_percent: f32::INFINITY, ..metric() }),
+        ("cpu_percent", MetricPoint { cpu_percent: f32::NEG_INFINITY, ..metric() }),
+        ("timestamp", MetricPoint { timestamp: -1, ..metric() }),
+        ("timestamp", MetricPoint { timestamp: i64::MIN, ..metric() }),
+    ];
+
+    for (name, client) in protocol_clients() {
+        client.delete_metrics(MetricQuery::all()).await.unwrap();
+        for (field, point) in &invalid {
+            // The same validation error on every protocol, not a decode failure
+            match client.submit_metric(point.clone()).await {
+                Err(ProtocolError::Server { message, .. }) => assert!(message.contains(field), "{}: {}", name, message),
+                other => panic!("{} accepted or mangled {:?}: {:?}", name, point, other),
+            }
+        }
+        assert_eq!(client.query_metrics(MetricQuery::all()).await.unwrap(), vec![], "{} stored an invalid point", name);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs rest-service, grpc-service and capnp-service running"]
+async fn u64_max_memory_comes_back_exactly() {
+    let point = MetricPoint { memory_bytes: u64::MAX, ..metric() };
+
+    for (name, client) in protocol_clients() {
+        client.delete_metrics(MetricQuery::all()).await.unwrap();
+        client.submit_metric(point.clone()).await.unwrap();
+
+        assert_eq!(client.query_metrics(MetricQuery::all()).await.unwrap(), vec![point.clone()], "{}", name);
+        let statistics = client.get_statistics(MetricQuery::all()).await.unwrap();
+        assert_eq!(statistics.avg_memory_bytes, u64::MAX, "{}", name);
+
+        client.delete_metrics(MetricQuery::all()).await.unwrap();
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs rest-service, grpc-service and capnp-service running"]
+async fn negative_query_bounds_are_accepted() {
+    let query = MetricQuery {
+        end_time: -1,
+        ..MetricQuery::all()
+    };
+
+    for (name, client) in protocol_clients() {
+        client.delete_metrics(MetricQuery::all()).await.unwrap();
+        client.submit_metric(metric()).await.unwrap();
+
+        assert_eq!(client.query_metrics(query.clone()).await.unwrap(), vec![], "{}", name);
+        assert_eq!(client.get_statistics(query.clone()).await.unwrap().count, 0, "{}", name);
+
+        client.delete_metrics(MetricQuery::all()).await.unwrap();
+    }
+}
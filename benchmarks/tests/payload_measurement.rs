@@ -0,0 +1,89 @@
+//! `PayloadMeasurement`'s per-protocol sizes against the protocols' own
+//! encoders. Runs without any service.
+
+use benchmarks::payload_measurement::{measure_capnp_metric_wire_size, measure_capnp_metrics_wire_size};
+use benchmarks::PayloadMeasurement;
+use shared::{generate_test_data, CapnpEncoding, MetricQuery, MetricRollup, MetricStatistics};
+
+fn statistics() -> MetricStatistics {
+    MetricStatistics {
+        count: 1_000,
+        avg_cpu_percent: 42.5,
+        avg_memory_bytes: 8_589_934_592,
+        avg_disk_io_ops: 120.0,
+        time_range_seconds: 3_600,
+    }
+}
+
+fn rollup(minute: i64) -> MetricRollup {
+    MetricRollup {
+        hostname: "server-001".to_string(),
+        minute_start: 1_700_000_000 + minute * 60,
+        count: 12,
+        avg_cpu_percent: 40.0,
+        min_cpu_percent: 10.0,
+        max_cpu_percent: 90.0,
+        avg_memory_bytes: 4_096,
+        min_memory_bytes: 1_024,
+        max_memory_bytes: 8_192,
+        avg_disk_io_ops: 3.5,
+        min_disk_io_ops: 1,
+        max_disk_io_ops: 9,
+    }
+}
+
+#[test]
+fn binary_protocols_are_not_sized_as_json() {
+    let metrics = generate_test_data(100);
+
+    assert!(metrics.measure_grpc_payload_size() < metrics.measure_payload_size());
+    // Unpacked Cap'n Proto pads every text to a word, so it can come out
+    // either side of JSON; packing always removes zero bytes
+    let unpacked = metrics.measure_capnp_payload_size(CapnpEncoding::Unpacked);
+    assert!(unpacked > 0);
+    assert!(metrics.measure_capnp_payload_size(CapnpEncoding::Packed) < unpacked);
+}
+
+#[test]
+fn point_sizes_match_the_existing_measurements() {
+    let metrics = generate_test_data(10);
+
+    for encoding in CapnpEncoding::ALL {
+        assert_eq!(metrics.measure_capnp_payload_size(encoding), measure_capnp_metrics_wire_size(&metrics, encoding).unwrap());
+        assert_eq!(metrics[0].measure_capnp_payload_size(encoding), measure_capnp_metric_wire_size(&metrics[0], encoding).unwrap());
+    }
+    // A list adds a tag and a length prefix to each point's own message
+    let points: usize = metrics.iter().map(|metric| metric.measure_grpc_payload_size()).sum();
+    assert!(metrics.measure_grpc_payload_size() > points);
+}
+
+#[test]
+fn statistics_are_sized_field_by_field() {
+    let stats = statistics();
+
+    // A tag byte per field; count and time_range_seconds take two varint
+    // bytes, avg_memory_bytes (2^33) five, and each float four
+    assert_eq!(stats.measure_grpc_payload_size(), 3 + 5 + 6 + 5 + 3);
+    // Segment table, root pointer, the results' pointer, then four data words
+    assert_eq!(stats.measure_capnp_payload_size(CapnpEncoding::Unpacked), 8 + 8 + 8 + 32);
+    assert!(stats.measure_capnp_payload_size(CapnpEncoding::Packed) < 56);
+}
+
+#[test]
+fn rollups_and_queries_are_sized() {
+    let rollups: Vec<MetricRollup> = (0..20).map(rollup).collect();
+    assert!(rollups.measure_grpc_payload_size() > 0);
+    assert!(rollups.measure_capnp_payload_size(CapnpEncoding::Unpacked) > rollups.measure_capnp_payload_size(CapnpEncoding::Packed));
+
+    let query = MetricQuery {
+        start_time: 0,
+        end_time: 1_700_000_000,
+        hostname_filter: Some("server-001".to_string()),
+        limit: Some(100),
+        offset: None,
+    };
+    assert!(query.measure_grpc_payload_size() < query.measure_payload_size());
+    // Three data words and the hostname pointer, with the segment table and
+    // root pointer
+    assert_eq!(query.measure_capnp_payload_size(CapnpEncoding::Unpacked), 8 + 8 + 24 + 8 + 16);
+}
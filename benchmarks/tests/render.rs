@@ -0,0 +1,94 @@
+use benchmarks::render::{self, Format};
+use benchmarks::results::{BenchmarkRun, OperationResult};
+use benchmarks::{BenchmarkMetrics, PayloadSizes};
+use shared::OperationStats;
+use std::time::Duration;
+
+fn metrics(latency_us: u64, request_bytes: usize, response_bytes: usize) -> BenchmarkMetrics {
+    BenchmarkMetrics {
+        latency: Duration::from_micros(latency_us),
+        payload_size: PayloadSizes::new(request_bytes, response_bytes),
+        memory_allocated: 4_096,
+        cpu_cycles: latency_us * 3_000,
+        time_to_first_byte: None,
+    }
+}
+
+fn sample_run() -> BenchmarkRun {
+    let mut run = BenchmarkRun::new();
+    run.settings.clear();
+    run.settings.insert("PROTOBENCH_REST_FORMAT".to_string(), "<json>".to_string());
+    run.push(OperationResult::new("REST", "query", 100, &metrics(900, 120, 20_000)));
+    let mut grpc = OperationResult::new("gRPC", "query", 100, &metrics(700, 110, 11_000));
+    grpc.latency_ci_ns = Some([650_000, 750_000]);
+    run.push(grpc);
+    run
+}
+
+#[test]
+fn formats_are_chosen_by_name_or_extension() {
+    assert_eq!(Format::from_name("md"), Some(Format::Markdown));
+    assert_eq!(Format::from_name("html"), Some(Format::Html));
+    assert_eq!(Format::from_name("pdf"), None);
+    assert_eq!(Format::for_path("report.html"), Format::Html);
+    assert_eq!(Format::for_path("report.md"), Format::Markdown);
+    assert_eq!(Format::for_path("report"), Format::Markdown);
+}
+
+#[test]
+fn markdown_has_a_row_per_result() {
+    let document = render::render(&sample_run(), Format::Markdown);
+
+    assert!(document.contains("| REST | query | 100 | 0.900 | 120 | 20000 | 4096 |"), "{}", document);
+    assert!(document.contains("| gRPC | query | 100 | 0.700 (0.650–0.750) |"), "{}", document);
+    assert!(document.contains("`PROTOBENCH_REST_FORMAT=<json>`"), "{}", document);
+    assert!(!document.contains("estimated"), "{}", document);
+}
+
+#[test]
+fn html_is_escaped_and_standalone() {
+    let document = render::render(&sample_run(), Format::Html);
+
+    assert!(document.starts_with("<!DOCTYPE html>"), "{}", document);
+    assert!(document.contains("<td>REST</td><td>query</td><td>100</td><td>0.900</td>"), "{}", document);
+    assert!(document.contains("<code>PROTOBENCH_REST_FORMAT=&lt;json&gt;</code>"), "{}", document);
+    assert!(document.trim_end().ends_with("</html>"), "{}", document);
+}
+
+#[test]
+fn estimated_sizes_are_marked() {
+    let mut run = sample_run();
+    let estimated = BenchmarkMetrics { payload_size: PayloadSizes::estimated(80, 0), ..metrics(500, 0, 0) };
+    run.push(OperationResult::new("CapnProto", "submit_metric", 1, &estimated));
+    let document = render::markdown(&run);
+
+    assert!(document.contains("| CapnProto | submit_metric | 1 | 0.500 | 80* | 0* |"), "{}", document);
+    assert!(document.contains("estimated rather than counted on the socket"), "{}", document);
+}
+
+#[test]
+fn server_figures_get_their_own_table() {
+    let plain = render::markdown(&sample_run());
+    assert!(!plain.contains("Client and server"), "{}", plain);
+
+    let mut run = sample_run();
+    run.results[0] = run.results[0].clone().with_server(Some(OperationStats { requests: 1, total_ns: 450_000, allocated_bytes: 8_192 }));
+    let document = render::markdown(&run);
+
+    assert!(document.contains("## Client and server"), "{}", document);
+    assert!(document.contains("| REST | query | 100 | 0.900 | 0.450 | 50% | 8192 |"), "{}", document);
+    assert!(!document.contains("| gRPC | query | 100 | 0.700 | "), "{}", document);
+}
+
+#[test]
+fn capabilities_follow_the_schemas_and_settings() {
+    let document = render::markdown(&sample_run());
+    assert!(document.contains("## Capabilities"), "{}", document);
+    assert!(document.contains("Subscribe (bidi)"), "{}", document);
+    assert!(document.contains("| Authentication measured | none | none | none |"), "{}", document);
+
+    let mut run = sample_run();
+    run.settings.insert("PROTOBENCH_AUTH_TOKEN".to_string(), "secret".to_string());
+    let document = render::markdown(&run);
+    assert!(document.contains("| Authentication measured | bearer token | bearer token | token |"), "{}", document);
+}
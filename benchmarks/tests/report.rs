@@ -0,0 +1,175 @@
+use benchmarks::report::{cost_score, FramingOverhead, FramingReport, Metric, PayloadOperation, Verdict};
+use benchmarks::wire_bytes::WireBytes;
+use benchmarks::{BenchmarkMetrics, ComparisonReport, PayloadSizes};
+use std::time::Duration;
+
+fn metrics(latency_ms: u64, memory: usize, request_bytes: usize, response_bytes: usize) -> BenchmarkMetrics {
+    let latency = Duration::from_millis(latency_ms);
+    BenchmarkMetrics {
+        latency,
+        payload_size: PayloadSizes::new(request_bytes, response_bytes),
+        memory_allocated: memory,
+        cpu_cycles: benchmarks::estimate_cpu_cycles(latency),
+        time_to_first_byte: None,
+    }
+}
+
+fn streamed(latency_ms: u64, first_point_ms: u64) -> BenchmarkMetrics {
+    BenchmarkMetrics {
+        time_to_first_byte: Some(Duration::from_millis(first_point_ms)),
+        ..metrics(latency_ms, 1_000, 100, 10_000)
+    }
+}
+
+#[test]
+fn no_results_make_no_report() {
+    let results: Vec<(String, BenchmarkMetrics)> = Vec::new();
+    assert!(ComparisonReport::new(&results).is_none());
+}
+
+#[test]
+fn cost_score_weighs_each_metric() {
+    // 10 ms, 2 KiB allocated, 1 KiB on the wire and 30 million cycles
+    let score = cost_score(&metrics(10, 2048, 512, 512));
+    assert!((score - (10.0 * 0.4 + 2.0 * 0.2 + 1.0 * 0.2 + 30.0 * 0.2)).abs() < 1e-9, "{}", score);
+}
+
+#[test]
+fn each_metric_has_its_own_winner() {
+    let report = ComparisonReport::new(&[
+        ("REST", metrics(5, 40_000, 2_000, 2_000)),
+        ("gRPC", metrics(3, 8_000, 600, 400)),
+        ("CapnProto", metrics(2, 16_000, 900, 900)),
+    ])
+    .unwrap();
+
+    assert_eq!(report.fastest().protocol, "CapnProto");
+    assert_eq!(report.least_memory().protocol, "gRPC");
+    assert_eq!(report.least_traffic().protocol, "gRPC");
+    assert_eq!(report.best_overall().protocol, "gRPC");
+
+    let ranking: Vec<&str> = report.ranking().iter().map(|entry| entry.protocol.as_str()).collect();
+    assert_eq!(ranking, ["gRPC", "CapnProto", "REST"]);
+    assert!(report.ranking().windows(2).all(|pair| pair[0].cost_score <= pair[1].cost_score));
+    assert_eq!(report.get("REST").unwrap().metrics.payload_size.total_bytes, 4_000);
+    assert!(report.get("SOAP").is_none());
+}
+
+#[test]
+fn ties_keep_the_given_order() {
+    let report = ComparisonReport::new(&[("first", metrics(4, 1_000, 100, 100)), ("second", metrics(4, 1_000, 100, 100))]).unwrap();
+
+    assert_eq!(report.best_overall().protocol, "first");
+    assert_eq!(report.fastest().protocol, "first");
+    assert_eq!(report.least_traffic().protocol, "first");
+}
+
+#[test]
+fn display_lists_every_protocol_then_the_winners() {
+    let report = ComparisonReport::new(&[("REST", metrics(5, 4_000, 300, 200)), ("gRPC", metrics(1, 1_000, 100, 50))]).unwrap();
+
+    let text = report.to_string();
+    let winners = text.find("Winners").unwrap();
+    assert!(text.find("REST Results").unwrap() < winners, "{}", text);
+    assert!(text.find("gRPC Results").unwrap() < text.find("REST Results").unwrap(), "{}", text);
+    assert!(text.contains("Total Traffic:  500 bytes"), "{}", text);
+    assert!(text.contains("Best Overall:  gRPC"), "{}", text);
+    assert!(text.contains("Ranking:       gRPC < REST"), "{}", text);
+
+    assert!(!report.winners().to_string().contains("Results"));
+}
+
+#[test]
+fn first_point_and_completion_are_reported_separately() {
+    let report = ComparisonReport::new(&[
+        ("REST/json_array", metrics(20, 1_000, 100, 10_000)),
+        ("REST/ndjson", streamed(30, 2)),
+        ("gRPC/stream", streamed(25, 4)),
+    ])
+    .unwrap();
+
+    assert_eq!(report.fastest().protocol, "REST/json_array");
+    assert_eq!(report.most_responsive().unwrap().protocol, "REST/ndjson");
+
+    let text = report.to_string();
+    assert!(text.contains("First Point:   REST/ndjson (2ms)"), "{}", text);
+    assert_eq!(text.matches("🥇 First Point:").count(), 2, "{}", text);
+}
+
+#[test]
+fn no_streams_means_no_first_point_winner() {
+    let report = ComparisonReport::new(&[("REST", metrics(5, 1_000, 100, 100))]).unwrap();
+
+    assert!(report.most_responsive().is_none());
+    assert!(!report.to_string().contains("First Point"));
+}
+
+#[test]
+fn estimated_sizes_are_marked() {
+    let estimated = BenchmarkMetrics { payload_size: PayloadSizes::estimated(60, 20), ..metrics(5, 1_000, 0, 0) };
+    let report = ComparisonReport::new(&[("CapnProto", estimated), ("gRPC", metrics(6, 1_000, 70, 20))]).unwrap();
+    let text = report.to_string();
+
+    assert!(text.contains("Total Traffic:  80 bytes (estimated)"), "{}", text);
+    assert!(text.contains("Total Traffic:  90 bytes\n"), "{}", text);
+    assert!(text.contains("Least Traffic: CapnProto (80 bytes, estimated)"), "{}", text);
+}
+
+fn framing(protocol: &str, operation: PayloadOperation, points: usize, written: u64, read: u64, payload_bytes: usize) -> FramingOverhead {
+    FramingOverhead {
+        protocol: protocol.to_string(),
+        operation,
+        points,
+        wire: WireBytes { written, read },
+        payload_bytes,
+    }
+}
+
+#[test]
+fn framing_overhead_is_wire_minus_payload() {
+    let row = framing("gRPC", PayloadOperation::Query, 100, 120, 11_000, 10_480);
+
+    assert_eq!(row.wire_total(), 11_120);
+    assert_eq!(row.overhead_bytes(), 640);
+    assert!((row.overhead_ratio() - 640.0 / 11_120.0).abs() < 1e-12);
+}
+
+#[test]
+fn compression_can_make_overhead_negative() {
+    let row = framing("REST[gzip]", PayloadOperation::Query, 1000, 150, 40_000, 199_000);
+    assert!(row.overhead_bytes() < 0);
+
+    let nothing_sent = framing("REST", PayloadOperation::Submit, 1, 0, 0, 0);
+    assert_eq!(nothing_sent.overhead_ratio(), 0.0);
+}
+
+#[test]
+fn framing_report_finds_and_lists_rows() {
+    let mut report = FramingReport::new();
+    for operation in PayloadOperation::ALL {
+        report.push(framing("CapnProto", operation, 10, 900, 100, 880));
+        report.push(framing("CapnProto", operation, 100, 9_000, 100, 8_800));
+    }
+
+    assert_eq!(report.rows().len(), 4);
+    assert_eq!(report.get("CapnProto", PayloadOperation::Query, 100).unwrap().overhead_bytes(), 300);
+    assert!(report.get("CapnProto", PayloadOperation::Query, 1000).is_none());
+
+    let text = report.to_string();
+    assert_eq!(text.lines().count(), 5, "{}", text);
+    assert!(text.lines().nth(1).unwrap().contains("submit_batch"), "{}", text);
+    assert!(text.contains("Overhead%"), "{}", text);
+}
+
+#[test]
+fn single_runs_are_untested() {
+    let report = ComparisonReport::new(&[("REST", metrics(5, 4_000, 300, 200)), ("gRPC", metrics(1, 1_000, 100, 50))]).unwrap();
+
+    assert!(matches!(report.verdict(Metric::Latency), Verdict::Untested(entry) if entry.protocol == "gRPC"));
+    assert_eq!(report.verdict(Metric::Memory).leader().protocol, "gRPC");
+    assert!(report.winners().to_string().contains("one run each"));
+
+    let alone = ComparisonReport::new(&[("REST", metrics(5, 4_000, 300, 200))]).unwrap();
+    assert!(matches!(alone.verdict(Metric::Traffic), Verdict::Winner(_)));
+    assert!(!alone.winners().to_string().contains("one run each"));
+}
@@ -0,0 +1,47 @@
+use benchmarks::resource_usage::{measure_batch, recorded, ResourceUsage, UsageReport};
+use std::time::Duration;
+
+fn usage(user_ms: u64, system_ms: u64, voluntary: u64, involuntary: u64, max_rss_bytes: u64) -> ResourceUsage {
+    ResourceUsage {
+        user_cpu: Duration::from_millis(user_ms),
+        system_cpu: Duration::from_millis(system_ms),
+        voluntary_context_switches: voluntary,
+        involuntary_context_switches: involuntary,
+        max_rss_bytes,
+    }
+}
+
+#[test]
+fn differences_and_totals_add_up_per_group_and_protocol() {
+    let mut report = UsageReport::new();
+    report.add("submit_single", "REST", 100, usage(20, 10, 100, 2, 4096) - usage(10, 5, 50, 1, 2048));
+    report.add("submit_single", "gRPC", 100, usage(3, 1, 40, 0, 0));
+    report.add("submit_single", "REST", 300, usage(30, 15, 150, 3, 0));
+
+    let rest = &report.rows()[0];
+    assert_eq!(report.rows().len(), 2);
+    assert_eq!((rest.protocol.as_str(), rest.iterations), ("REST", 400));
+    assert_eq!(rest.usage, usage(40, 20, 200, 4, 2048));
+    assert_eq!(rest.cpu_per_iteration(), (Duration::from_micros(100), Duration::from_micros(50)));
+    assert_eq!(rest.context_switches_per_1000(), (500.0, 10.0));
+
+    // A peak can't fall, but a later reading never makes a difference negative
+    assert_eq!(usage(1, 1, 1, 1, 1024) - usage(2, 2, 2, 2, 4096), ResourceUsage::default());
+
+    let table = report.to_string();
+    assert!(table.lines().nth(1).unwrap().contains("REST"), "{}", table);
+    assert!(table.contains("100.0"), "{}", table);
+}
+
+#[cfg(unix)]
+#[test]
+fn batches_are_measured_with_getrusage() {
+    let before = ResourceUsage::now().unwrap();
+    assert!(before.max_rss_bytes > 0);
+
+    measure_batch("busy", "test", 3, || (0..2_000_000u64).fold(0u64, |sum, n| sum.wrapping_add(n * n)));
+
+    let busy = recorded().rows().iter().find(|row| row.group == "busy").cloned().unwrap();
+    assert_eq!(busy.iterations, 3);
+    assert!(ResourceUsage::now().unwrap().user_cpu >= before.user_cpu + busy.usage.user_cpu);
+}
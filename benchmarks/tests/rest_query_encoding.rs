@@ -0,0 +1,67 @@
+use benchmarks::rest_client;
+use shared::{generate_unusual_hostname_data, MetricQuery, UNUSUAL_HOSTNAMES};
+
+fn host_query(hostname: &str) -> MetricQuery {
+    MetricQuery {
+        start_time: 0,
+        end_time: 1_000,
+        hostname_filter: Some(hostname.to_string()),
+        limit: None,
+        offset: None,
+    }
+}
+
+/// Build the request `rest_client` would send for `query` and decode its
+/// query string the way `rest-service`'s `Query` extractor does
+fn decoded_params(query: &MetricQuery) -> Vec<(String, String)> {
+    let request = reqwest::Client::new()
+        .get("http://127.0.0.1:3000/metrics")
+        .query(&rest_client::query_params(query))
+        .build()
+        .unwrap();
+    request
+        .url()
+        .query_pairs()
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect()
+}
+
+#[test]
+fn unusual_hostnames_round_trip() {
+    for hostname in UNUSUAL_HOSTNAMES {
+        let params = decoded_params(&host_query(hostname));
+        assert!(
+            params.contains(&("hostname_filter".to_string(), hostname.to_string())),
+            "{:?} came back as {:?}",
+            hostname,
+            params
+        );
+    }
+}
+
+#[test]
+fn generated_hostnames_do_not_add_parameters() {
+    for metric in generate_unusual_hostname_data(UNUSUAL_HOSTNAMES.len()) {
+        let query = MetricQuery {
+            start_time: metric.timestamp - 60,
+            end_time: metric.timestamp + 60,
+            hostname_filter: Some(metric.hostname.clone()),
+            limit: Some(10),
+            offset: Some(5),
+        };
+        let names: Vec<String> = decoded_params(&query).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["start_time", "end_time", "hostname_filter", "limit", "offset"], "for {:?}", metric.hostname);
+    }
+}
+
+#[test]
+fn unset_fields_are_left_out() {
+    let query = MetricQuery {
+        hostname_filter: None,
+        ..host_query("unused")
+    };
+    assert_eq!(
+        decoded_params(&query),
+        [("start_time".to_string(), "0".to_string()), ("end_time".to_string(), "1000".to_string())]
+    );
+}
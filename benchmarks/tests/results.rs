@@ -0,0 +1,156 @@
+use benchmarks::results::{BenchmarkRun, OperationResult, ServiceRestart, SCHEMA_VERSION};
+use benchmarks::{BenchmarkMetrics, PayloadSizes};
+use shared::OperationStats;
+use std::time::Duration;
+
+fn metrics(latency_us: u64, request_bytes: usize, response_bytes: usize) -> BenchmarkMetrics {
+    BenchmarkMetrics {
+        latency: Duration::from_micros(latency_us),
+        payload_size: PayloadSizes::new(request_bytes, response_bytes),
+        memory_allocated: 4_096,
+        cpu_cycles: latency_us * 3_000,
+        time_to_first_byte: None,
+    }
+}
+
+fn sample_run() -> BenchmarkRun {
+    let mut run = BenchmarkRun::new();
+    run.push(OperationResult::new("REST", "query", 100, &metrics(900, 120, 20_000)));
+    run.push(OperationResult::new("gRPC", "query", 100, &metrics(700, 110, 11_000)));
+    run.push(OperationResult::new("REST", "submit_batch", 100, &metrics(1_100, 20_000, 90)));
+    let mut streamed = OperationResult::new("gRPC/stream", "query", 100, &BenchmarkMetrics {
+        time_to_first_byte: Some(Duration::from_micros(80)),
+        ..metrics(750, 110, 11_100)
+    });
+    streamed.payload_bytes = Some(10_480);
+    run.push(streamed);
+    run
+}
+
+#[test]
+fn runs_round_trip_through_json() {
+    let run = sample_run();
+    let json = run.to_json().unwrap();
+
+    assert_eq!(BenchmarkRun::from_json(&json).unwrap(), run);
+    assert!(json.contains(&format!("\"schema_version\": {}", SCHEMA_VERSION)), "{}", json);
+    // Optional fields are left out rather than written as null
+    assert!(!json.contains("null"), "{}", json);
+}
+
+#[test]
+fn metrics_survive_the_conversion() {
+    let original = BenchmarkMetrics {
+        time_to_first_byte: Some(Duration::from_nanos(12_345)),
+        ..metrics(321, 10, 20)
+    };
+    let restored = OperationResult::new("CapnProto", "query", 1, &original).metrics();
+
+    assert_eq!(restored.latency, original.latency);
+    assert_eq!(restored.time_to_first_byte, original.time_to_first_byte);
+    assert_eq!(restored.payload_size.total_bytes, 30);
+    assert_eq!(restored.memory_allocated, original.memory_allocated);
+    assert_eq!(restored.cpu_cycles, original.cpu_cycles);
+}
+
+#[test]
+fn estimated_sizes_stay_flagged() {
+    let estimated = BenchmarkMetrics { payload_size: PayloadSizes::estimated(80, 0), ..metrics(500, 0, 0) };
+    let mut run = BenchmarkRun::new();
+    run.push(OperationResult::new("CapnProto", "submit_metric", 1, &estimated));
+    run.push(OperationResult::new("gRPC", "submit_metric", 1, &metrics(400, 60, 0)));
+
+    let json = run.to_json().unwrap();
+    assert_eq!(json.matches("payload_estimated").count(), 1, "{}", json);
+    let restored = BenchmarkRun::from_json(&json).unwrap();
+    assert!(restored.results[0].metrics().payload_size.estimated);
+    assert!(!restored.results[1].metrics().payload_size.estimated);
+}
+
+#[test]
+fn restarts_are_kept_and_left_out_when_none() {
+    let mut run = sample_run();
+    assert!(!run.to_json().unwrap().contains("restarts"));
+
+    run.restarts.push(ServiceRestart {
+        service: "capnp-service".to_string(),
+        exit_status: "exit status: 101".to_string(),
+        at_ms: 1_700_000_000_000,
+    });
+    assert_eq!(BenchmarkRun::from_json(&run.to_json().unwrap()).unwrap(), run);
+    assert_eq!(
+        run.restarts[0].to_string(),
+        "capnp-service exited (exit status: 101) at 1700000000000 ms and was restarted"
+    );
+}
+
+#[test]
+fn server_figures_are_kept_and_left_out_when_unread() {
+    let result = OperationResult::new("gRPC", "query", 100, &metrics(800, 110, 11_000));
+    assert_eq!(result.clone().with_server(None), result);
+    assert_eq!(result.server_share(), None);
+
+    let server = OperationStats { requests: 1, total_ns: 200_000, allocated_bytes: 65_536 };
+    let attributed = result.with_server(Some(server));
+    assert_eq!(attributed.server_time_ns, Some(200_000));
+    assert_eq!(attributed.server_allocated_bytes, Some(65_536));
+    assert_eq!(attributed.server_share(), Some(0.25));
+
+    let mut run = BenchmarkRun::new();
+    run.push(attributed);
+    assert_eq!(BenchmarkRun::from_json(&run.to_json().unwrap()).unwrap(), run);
+    assert!(!sample_run().to_json().unwrap().contains("server_time_ns"));
+}
+
+#[test]
+fn newer_schema_versions_are_refused() {
+    let mut value: serde_json::Value = serde_json::from_str(&sample_run().to_json().unwrap()).unwrap();
+    value["schema_version"] = (SCHEMA_VERSION + 1).into();
+
+    let error = BenchmarkRun::from_json(&value.to_string()).unwrap_err().to_string();
+    assert!(error.contains("schema version"), "{}", error);
+}
+
+#[test]
+fn fields_added_later_and_unknown_fields_are_tolerated() {
+    let json = format!(
+        r#"{{"schema_version": {}, "started_at_ms": 1, "harness_version": "0.1.0", "from_the_future": true}}"#,
+        SCHEMA_VERSION
+    );
+    let run = BenchmarkRun::from_json(&json).unwrap();
+
+    assert!(run.results.is_empty() && run.settings.is_empty());
+}
+
+#[test]
+fn summaries_follow_first_appearance() {
+    let summaries = sample_run().summaries();
+
+    let protocols: Vec<&str> = summaries.iter().map(|summary| summary.protocol.as_str()).collect();
+    assert_eq!(protocols, ["REST", "gRPC", "gRPC/stream"]);
+    assert_eq!(summaries[0].operations, 2);
+    assert_eq!(summaries[0].mean_latency_ns, 1_000_000);
+    assert_eq!(summaries[0].total_bytes, 120 + 20_000 + 20_000 + 90);
+}
+
+#[test]
+fn comparisons_come_from_matching_results() {
+    let run = sample_run();
+
+    let report = run.comparison("query", 100).unwrap();
+    assert_eq!(report.ranking().len(), 3);
+    assert_eq!(report.fastest().protocol, "gRPC");
+    assert_eq!(report.most_responsive().unwrap().protocol, "gRPC/stream");
+    assert!(run.comparison("query", 1000).is_none());
+}
+
+#[test]
+fn runs_are_written_and_read_back() {
+    let path = std::env::temp_dir().join(format!("protobench-results-{}.json", std::process::id()));
+    let run = sample_run();
+    run.write_to(&path).unwrap();
+
+    let read = BenchmarkRun::read_from(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(read.unwrap(), run);
+}
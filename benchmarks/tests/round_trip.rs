@@ -0,0 +1,109 @@
+//! The hand-written converters between `shared` types and the generated
+//! protobuf and Cap'n Proto types, checked by encoding arbitrary values to
+//! bytes and decoding them back. Runs without any service.
+
+use benchmarks::capnp_client::{read_metric, read_metric_v2, write_metric, write_metric_v2, write_query};
+use benchmarks::grpc_client::{from_proto_metric, from_proto_metric_v2, metrics, to_proto_metric, to_proto_metric_v2, to_proto_query};
+use benchmarks::metrics_capnp::{metric_point, metric_point_v2, metric_query};
+use capnp::message::{Builder, ReaderOptions};
+use proptest::prelude::*;
+use prost::Message;
+use shared::strategies::{any_f32, metric_point_with, metric_query, same_metric};
+use shared::{CapnpEncoding, MetricPoint, MetricPointV2, TestDataGenerator};
+
+/// Serialize `message` the way `encoding` puts it on the wire
+fn to_bytes(message: &Builder<capnp::message::HeapAllocator>, encoding: CapnpEncoding) -> Vec<u8> {
+    match encoding {
+        CapnpEncoding::Unpacked => capnp::serialize::write_message_to_words(message),
+        CapnpEncoding::Packed => {
+            let mut buffer = Vec::new();
+            capnp::serialize_packed::write_message(&mut buffer, message).unwrap();
+            buffer
+        }
+    }
+}
+
+fn from_bytes(bytes: &[u8], encoding: CapnpEncoding) -> capnp::message::Reader<capnp::serialize::OwnedSegments> {
+    match encoding {
+        CapnpEncoding::Unpacked => capnp::serialize::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap(),
+        CapnpEncoding::Packed => capnp::serialize_packed::read_message(&mut &bytes[..], ReaderOptions::new()).unwrap(),
+    }
+}
+
+proptest! {
+    #[test]
+    fn points_round_trip_through_protobuf(metric in metric_point_with(any_f32())) {
+        let bytes = to_proto_metric(metric.clone()).encode_to_vec();
+        let decoded = from_proto_metric(metrics::MetricPoint::decode(&bytes[..]).unwrap());
+        // Proto3 leaves out scalars equal to their default, and -0.0 == 0.0, so
+        // the sign of a zero is lost on the wire
+        let expected = MetricPoint { cpu_percent: if metric.cpu_percent == 0.0 { 0.0 } else { metric.cpu_percent }, ..metric };
+        prop_assert!(same_metric(&decoded, &expected), "{:?}", decoded);
+    }
+
+    #[test]
+    fn queries_round_trip_through_protobuf(query in metric_query()) {
+        let bytes = to_proto_query(query.clone()).encode_to_vec();
+        let decoded = metrics::MetricQuery::decode(&bytes[..]).unwrap();
+        // Proto3 `optional` keeps presence, so `Some(0)` and `Some("")` survive
+        prop_assert_eq!(decoded.start_time, query.start_time);
+        prop_assert_eq!(decoded.end_time, query.end_time);
+        prop_assert_eq!(decoded.hostname_filter, query.hostname_filter);
+        prop_assert_eq!(decoded.limit, query.limit);
+        prop_assert_eq!(decoded.offset, query.offset);
+    }
+
+    #[test]
+    fn points_round_trip_through_capnp(metric in metric_point_with(any_f32())) {
+        let mut message = Builder::new_default();
+        write_metric(&metric, message.init_root::<metric_point::Builder>());
+        for encoding in [CapnpEncoding::Unpacked, CapnpEncoding::Packed] {
+            let reader = from_bytes(&to_bytes(&message, encoding), encoding);
+            let decoded: MetricPoint = read_metric(reader.get_root::<metric_point::Reader>().unwrap()).unwrap();
+            prop_assert!(same_metric(&decoded, &metric), "{:?} gave {:?}", encoding, decoded);
+        }
+    }
+
+    #[test]
+    fn queries_round_trip_through_capnp(query in metric_query()) {
+        let mut message = Builder::new_default();
+        write_query(&query, message.init_root::<metric_query::Builder>());
+        for encoding in [CapnpEncoding::Unpacked, CapnpEncoding::Packed] {
+            let reader = from_bytes(&to_bytes(&message, encoding), encoding);
+            let decoded = reader.get_root::<metric_query::Reader>().unwrap();
+            prop_assert_eq!(decoded.get_start_time(), query.start_time);
+            prop_assert_eq!(decoded.get_end_time(), query.end_time);
+            let hostname_filter = if decoded.has_hostname_filter() {
+                Some(decoded.get_hostname_filter().unwrap().to_str().unwrap().to_string())
+            } else {
+                None
+            };
+            prop_assert_eq!(&hostname_filter, &query.hostname_filter);
+            // No optional scalars in Cap'n Proto: 0 and unset both read back as
+            // unset, as in capnp-service
+            prop_assert_eq!(Some(decoded.get_limit()).filter(|&limit| limit > 0), query.limit.filter(|&limit| limit > 0));
+            prop_assert_eq!(Some(decoded.get_offset()).filter(|&offset| offset > 0), query.offset.filter(|&offset| offset > 0));
+        }
+    }
+}
+
+#[test]
+fn v2_points_round_trip_through_protobuf() {
+    for metric in TestDataGenerator::new().generate_v2(50) {
+        let bytes = to_proto_metric_v2(metric.clone()).encode_to_vec();
+        assert_eq!(from_proto_metric_v2(metrics::MetricPointV2::decode(&bytes[..]).unwrap()), metric);
+    }
+}
+
+#[test]
+fn v2_points_round_trip_through_capnp() {
+    for metric in TestDataGenerator::new().generate_v2(50) {
+        let mut message = Builder::new_default();
+        write_metric_v2(&metric, message.init_root::<metric_point_v2::Builder>());
+        for encoding in [CapnpEncoding::Unpacked, CapnpEncoding::Packed] {
+            let reader = from_bytes(&to_bytes(&message, encoding), encoding);
+            let decoded: MetricPointV2 = read_metric_v2(reader.get_root::<metric_point_v2::Reader>().unwrap()).unwrap();
+            assert_eq!(decoded, metric, "{:?}", encoding);
+        }
+    }
+}
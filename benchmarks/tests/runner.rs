@@ -0,0 +1,95 @@
+//! The sampling runner on synthetic operations. Runs without any service.
+
+use benchmarks::runner::{sample, RunnerSettings, Sampled};
+use benchmarks::wire_bytes::WireCounter;
+use benchmarks::{BenchmarkMetrics, PayloadSizes};
+use std::cell::Cell;
+use std::time::Duration;
+
+static UNUSED_WIRE: WireCounter = WireCounter::new();
+
+fn metrics(latency_us: u64, response_bytes: usize) -> BenchmarkMetrics {
+    BenchmarkMetrics {
+        latency: Duration::from_micros(latency_us),
+        payload_size: PayloadSizes::new(100, response_bytes),
+        memory_allocated: 1_000,
+        cpu_cycles: latency_us * 3_000,
+        time_to_first_byte: None,
+    }
+}
+
+#[test]
+fn outliers_beyond_the_fences_are_set_aside() {
+    let mut samples: Vec<BenchmarkMetrics> = [100, 102, 98, 101, 99, 100, 103, 97].iter().map(|&us| metrics(us, 2_000)).collect();
+    samples.push(metrics(5_000, 2_000));
+    samples.push(metrics(1, 2_000));
+
+    let sampled = Sampled::from_samples(samples, 1.5);
+
+    assert_eq!(sampled.kept.len(), 8);
+    let mut outliers: Vec<Duration> = sampled.outliers.iter().map(|outlier| outlier.latency).collect();
+    outliers.sort();
+    assert_eq!(outliers, [Duration::from_micros(1), Duration::from_micros(5_000)]);
+}
+
+#[test]
+fn a_handful_of_samples_are_all_kept() {
+    let samples = vec![metrics(100, 0), metrics(100, 0), metrics(10_000, 0)];
+
+    let sampled = Sampled::from_samples(samples, 1.5);
+
+    assert_eq!(sampled.kept.len(), 3);
+    assert!(sampled.outliers.is_empty());
+}
+
+#[test]
+fn kept_runs_are_averaged_into_a_result() {
+    let sampled = Sampled::from_samples(vec![metrics(100, 1_000), metrics(200, 3_000), metrics(300, 2_000)], 1.5);
+
+    let mean = sampled.mean().unwrap();
+    assert_eq!(mean.latency, Duration::from_micros(200));
+    assert_eq!(mean.payload_size.response_bytes, 2_000);
+    assert_eq!(mean.memory_allocated, 1_000);
+
+    let result = sampled.to_result("gRPC", "query", 100).unwrap();
+    assert_eq!(result.latency_ns, 200_000);
+    let [lower, upper] = result.latency_ci_ns.unwrap();
+    assert!(lower < 200_000 && upper > 200_000, "{:?}", result.latency_ci_ns);
+
+    assert!(Sampled::from_samples(Vec::new(), 1.5).to_result("gRPC", "query", 100).is_none());
+}
+
+#[tokio::test]
+async fn warmup_runs_are_made_but_not_measured() {
+    let calls = Cell::new(0);
+    let settings = RunnerSettings { warmup: 3, samples: 5, ..RunnerSettings::default() };
+
+    let sampled = sample("counted", &UNUSED_WIRE, settings, || async {
+        calls.set(calls.get() + 1);
+        Ok::<_, std::io::Error>(())
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(calls.get(), 8);
+    assert_eq!(sampled.kept.len() + sampled.outliers.len(), 5);
+}
+
+#[tokio::test]
+async fn a_failed_run_fails_the_sample() {
+    let calls = Cell::new(0);
+    let settings = RunnerSettings { warmup: 1, samples: 10, ..RunnerSettings::default() };
+
+    let result = sample("failing", &UNUSED_WIRE, settings, || async {
+        calls.set(calls.get() + 1);
+        if calls.get() == 4 {
+            Err("server went away")
+        } else {
+            Ok(())
+        }
+    })
+    .await;
+
+    assert_eq!(result.unwrap_err(), "server went away");
+    assert_eq!(calls.get(), 4);
+}
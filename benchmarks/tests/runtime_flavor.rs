@@ -0,0 +1,27 @@
+use benchmarks::runtime_flavor::RuntimeFlavor;
+
+#[test]
+fn flavors_parse_from_their_labels() {
+    assert_eq!(RuntimeFlavor::parse("current-thread"), Some(RuntimeFlavor::CurrentThread));
+    assert_eq!(RuntimeFlavor::parse(" multi-thread:4 "), Some(RuntimeFlavor::MultiThread { workers: 4 }));
+    assert!(matches!(RuntimeFlavor::parse("multi-thread"), Some(RuntimeFlavor::MultiThread { workers }) if workers >= 1));
+
+    for flavor in [RuntimeFlavor::CurrentThread, RuntimeFlavor::MultiThread { workers: 3 }] {
+        assert_eq!(RuntimeFlavor::parse(&flavor.label()), Some(flavor));
+    }
+    assert_eq!(RuntimeFlavor::MultiThread { workers: 3 }.to_string(), "multi-thread:3");
+
+    for invalid in ["", "multi-thread:0", "multi-thread:many", "single"] {
+        assert_eq!(RuntimeFlavor::parse(invalid), None, "{:?}", invalid);
+    }
+}
+
+#[test]
+fn each_flavor_builds_a_runtime_with_its_worker_count() {
+    let rt = RuntimeFlavor::CurrentThread.build().unwrap();
+    assert_eq!(rt.block_on(async { tokio::runtime::Handle::current().runtime_flavor() }), tokio::runtime::RuntimeFlavor::CurrentThread);
+
+    let rt = RuntimeFlavor::MultiThread { workers: 2 }.build().unwrap();
+    assert_eq!(rt.handle().runtime_flavor(), tokio::runtime::RuntimeFlavor::MultiThread);
+    assert_eq!(rt.metrics().num_workers(), 2);
+}
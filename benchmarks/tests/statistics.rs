@@ -0,0 +1,159 @@
+use benchmarks::results::{BenchmarkRun, OperationResult, ServiceRestart};
+use benchmarks::report::{Metric, Verdict};
+use benchmarks::statistics::{differs_significantly, t_critical_95, AggregateReport, Summary};
+use benchmarks::{BenchmarkMetrics, PayloadSizes};
+use std::time::Duration;
+
+fn close(actual: f64, expected: f64) -> bool {
+    (actual - expected).abs() < 1e-3
+}
+
+fn result(protocol: &str, latency_us: u64, response_bytes: usize) -> OperationResult {
+    OperationResult::new(protocol, "query", 100, &BenchmarkMetrics {
+        latency: Duration::from_micros(latency_us),
+        payload_size: PayloadSizes::new(100, response_bytes),
+        memory_allocated: 10_000,
+        cpu_cycles: 0,
+        time_to_first_byte: None,
+    })
+}
+
+fn run(results: Vec<OperationResult>) -> BenchmarkRun {
+    BenchmarkRun { results, ..BenchmarkRun::new() }
+}
+
+#[test]
+fn summary_has_a_t_interval() {
+    let summary = Summary::of(&[1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+
+    assert_eq!(summary.samples, 5);
+    assert!(close(summary.mean, 3.0));
+    assert!(close(summary.std_dev, 1.5811), "{:?}", summary);
+    // t(4) = 2.776
+    assert!(close(summary.margin, 2.776 * 1.5811 / 5f64.sqrt()), "{:?}", summary);
+    assert!(close(summary.lower() + summary.upper(), 6.0));
+    assert_eq!(format!("{:.1}", summary), "3.0 ± 2.0");
+}
+
+#[test]
+fn one_sample_has_no_interval() {
+    assert!(Summary::of(&[]).is_none());
+    let summary = Summary::of(&[7.0]).unwrap();
+    assert_eq!(summary.mean, 7.0);
+    assert!(summary.margin.is_infinite());
+}
+
+#[test]
+fn identical_samples_have_no_spread() {
+    let summary = Summary::of(&[4.0; 6]).unwrap();
+    assert_eq!(summary.margin, 0.0);
+}
+
+#[test]
+fn t_values_shrink_towards_the_normal() {
+    assert!(close(t_critical_95(1), 12.706));
+    assert!(close(t_critical_95(30), 2.042));
+    assert!((1..200).all(|df| t_critical_95(df + 1) <= t_critical_95(df)));
+    assert!(t_critical_95(1_000) > 1.96);
+}
+
+#[test]
+fn repetitions_aggregate_per_protocol() {
+    let runs = [
+        run(vec![result("REST", 1_000, 5_000), result("gRPC", 400, 3_000)]),
+        run(vec![result("REST", 1_200, 5_000), result("gRPC", 500, 3_000)]),
+        run(vec![result("REST", 1_100, 5_000)]),
+    ];
+    let report = AggregateReport::new(&runs);
+
+    assert_eq!(report.rows().len(), 2);
+    let rest = report.get("REST", "query", 100).unwrap();
+    assert_eq!(rest.latency_ms.samples, 3);
+    assert!(close(rest.latency_ms.mean, 1.1));
+    assert_eq!(rest.total_bytes.margin, 0.0);
+    assert_eq!(report.get("gRPC", "query", 100).unwrap().latency_ms.samples, 2);
+    assert!(report.get("REST", "query", 1_000).is_none());
+
+    let text = report.to_string();
+    assert_eq!(text.lines().count(), 3, "{}", text);
+    assert!(text.contains("1.100 ±"), "{}", text);
+}
+
+#[test]
+fn the_aggregate_run_carries_the_interval() {
+    let runs = [
+        run(vec![result("REST", 1_000, 5_000)]),
+        run(vec![result("REST", 1_100, 6_000)]),
+        run(vec![result("REST", 1_200, 7_000)]),
+    ];
+    let aggregated = AggregateReport::new(&runs).to_run();
+
+    let rest = &aggregated.results[0];
+    assert_eq!(rest.latency_ns, 1_100_000);
+    assert_eq!(rest.response_bytes, 6_000);
+    assert_eq!(rest.request_bytes, 100);
+    // 1.1 ms ± t(2) * 0.1 / sqrt(3)
+    let [lower, upper] = rest.latency_ci_ns.unwrap();
+    assert!(lower.abs_diff(851_560) < 10 && upper.abs_diff(1_348_440) < 10, "{:?}", rest.latency_ci_ns);
+}
+
+#[test]
+fn the_aggregate_run_keeps_every_restart() {
+    let restart = |at_ms| ServiceRestart {
+        service: "capnp-service".to_string(),
+        exit_status: "signal: 6 (SIGABRT)".to_string(),
+        at_ms,
+    };
+    let mut runs = [run(vec![result("REST", 1_000, 5_000)]), run(vec![result("REST", 1_100, 6_000)])];
+    runs[0].restarts.push(restart(1));
+    runs[1].restarts.push(restart(2));
+    let report = AggregateReport::new(&runs);
+
+    assert_eq!(report.restarts(), [restart(1), restart(2)]);
+    assert_eq!(report.to_run().restarts, [restart(1), restart(2)]);
+}
+
+#[test]
+fn welch_separates_distinct_means_from_noise() {
+    let fast = Summary::of(&[1.0, 1.1, 0.9, 1.05, 0.95]).unwrap();
+    let slow = Summary::of(&[2.0, 2.1, 1.9, 2.05, 1.95]).unwrap();
+    let noisy = Summary::of(&[0.5, 1.8, 1.2, 0.7, 1.6]).unwrap();
+
+    assert_eq!(differs_significantly(&fast, &slow), Some(true));
+    assert_eq!(differs_significantly(&fast, &noisy), Some(false));
+    assert_eq!(differs_significantly(&fast, &Summary::of(&[1.0]).unwrap()), None);
+    // Without any spread only equal means are alike
+    let constant = Summary::of(&[3.0; 4]).unwrap();
+    assert_eq!(differs_significantly(&constant, &constant), Some(false));
+    assert_eq!(differs_significantly(&constant, &Summary::of(&[4.0; 4]).unwrap()), Some(true));
+}
+
+#[test]
+fn aggregated_comparisons_test_their_winners() {
+    let runs: Vec<BenchmarkRun> = [(1_000, 400, 3_000), (1_300, 410, 3_000), (900, 390, 3_000), (1_200, 405, 3_000)]
+        .into_iter()
+        .map(|(rest_us, grpc_us, capnp_us)| {
+            run(vec![result("REST", rest_us, 5_000), result("gRPC", grpc_us, 5_000), result("CapnProto", capnp_us, 9_000)])
+        })
+        .collect();
+    let comparison = AggregateReport::new(&runs).comparison("query", 100).unwrap();
+
+    match comparison.verdict(Metric::Latency) {
+        Verdict::Winner(entry) => assert_eq!(entry.protocol, "gRPC"),
+        other => panic!("{:?}", other),
+    }
+    match comparison.verdict(Metric::Traffic) {
+        Verdict::Tie(tied) => {
+            let tied: Vec<&str> = tied.iter().map(|entry| entry.protocol.as_str()).collect();
+            assert_eq!(tied, ["gRPC", "REST"]);
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let text = comparison.winners().to_string();
+    assert!(text.contains("Fastest:       gRPC (0.401 ± "), "{}", text);
+    assert!(text.contains("Least Traffic: tie: gRPC (5100 ± 0 bytes), REST (5100 ± 0 bytes)"), "{}", text);
+    assert!(!text.contains("one run each"), "{}", text);
+    assert!(comparison.get("REST").unwrap().spread.is_some());
+    assert!(AggregateReport::new(&runs).comparison("query", 1).is_none());
+}
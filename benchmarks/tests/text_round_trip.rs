@@ -0,0 +1,57 @@
+//! Unicode and long text through every protocol and back. These need all
+//! three services running at the targets `protocol_clients` uses, so they're
+//! ignored by default:
+//! `cargo test -p benchmarks --test text_round_trip -- --ignored --test-threads 1`
+
+use benchmarks::{protocol_clients, ProtocolClient};
+use shared::{MetricPoint, MetricQuery, TestDataGenerator, TextMode};
+
+fn sorted(mut metrics: Vec<MetricPoint>) -> Vec<MetricPoint> {
+    metrics.sort_by(|a, b| {
+        (a.timestamp, &a.hostname, a.cpu_percent.to_bits()).cmp(&(b.timestamp, &b.hostname, b.cpu_percent.to_bits()))
+    });
+    metrics
+}
+
+/// Store `metrics` on an emptied service and check that a full query and a
+/// query per hostname give back exactly the same points
+async fn assert_round_trip(name: &str, client: &dyn ProtocolClient, metrics: &[MetricPoint]) {
+    client.delete_metrics(MetricQuery::all()).await.unwrap();
+    client.submit_batch(metrics.to_vec()).await.unwrap();
+
+    let returned = client.query_metrics(MetricQuery::all()).await.unwrap();
+    assert_eq!(sorted(returned), sorted(metrics.to_vec()), "{} changed the points", name);
+
+    let mut hostnames: Vec<&String> = metrics.iter().map(|metric| &metric.hostname).collect();
+    hostnames.sort();
+    hostnames.dedup();
+    for hostname in hostnames {
+        let query = MetricQuery {
+            hostname_filter: Some(hostname.clone()),
+            ..MetricQuery::all()
+        };
+        let returned = client.query_metrics(query).await.unwrap();
+        let expected: Vec<MetricPoint> = metrics.iter().filter(|metric| &metric.hostname == hostname).cloned().collect();
+        assert_eq!(sorted(returned), sorted(expected), "{} filtering on {:?}", name, hostname);
+    }
+
+    client.delete_metrics(MetricQuery::all()).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs rest-service, grpc-service and capnp-service running"]
+async fn unicode_text_round_trips() {
+    let metrics = TestDataGenerator::new().text(TextMode::Unicode).tags_per_metric(8).generate(200);
+    for (name, client) in protocol_clients() {
+        assert_round_trip(&name, client.as_ref(), &metrics).await;
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs rest-service, grpc-service and capnp-service running"]
+async fn long_text_round_trips() {
+    let metrics = TestDataGenerator::new().text(TextMode::Long).tags_per_metric(16).generate(100);
+    for (name, client) in protocol_clients() {
+        assert_round_trip(&name, client.as_ref(), &metrics).await;
+    }
+}
@@ -0,0 +1,67 @@
+use benchmarks::verify::{canonical_json, compare, Observation};
+use shared::TestDataGenerator;
+use std::collections::HashMap;
+
+fn observation(protocol: &str, operation: &'static str, request_bytes: usize, response: &str) -> Observation {
+    Observation { protocol: protocol.to_string(), operation, request_bytes, response_bytes: 100, response: response.to_string() }
+}
+
+#[test]
+fn tags_compare_regardless_of_map_order() {
+    let mut point = TestDataGenerator::new().seed(1).base_timestamp(1_700_000_000).generate(1).remove(0);
+    let forwards = canonical_json(&point);
+    // A map built in the other order, with its own hasher
+    let mut tags: Vec<_> = point.tags.drain().collect();
+    tags.reverse();
+    point.tags = tags.into_iter().collect::<HashMap<_, _>>();
+
+    assert_eq!(canonical_json(&point), forwards);
+}
+
+#[test]
+fn point_order_still_counts() {
+    let points = TestDataGenerator::new().base_timestamp(1_700_000_000).generate(3);
+    let mut reversed = points.clone();
+    reversed.reverse();
+
+    assert_ne!(canonical_json(&points), canonical_json(&reversed));
+}
+
+#[test]
+fn identical_runs_have_no_mismatches() {
+    let run = vec![observation("REST", "query", 200, "[1,2]"), observation("gRPC", "query", 150, "[1,2]")];
+
+    assert!(compare(&run, &run.clone()).is_empty());
+}
+
+#[test]
+fn differences_are_reported_per_protocol_and_field() {
+    let first = vec![
+        observation("REST", "query", 200, "[1,2]"),
+        observation("gRPC", "query", 150, "[1,2]"),
+        observation("CapnProto", "query", 120, "[1,2]"),
+    ];
+    let second = vec![
+        observation("REST", "query", 201, "[1,2]"),
+        observation("gRPC", "query", 150, "[2,1]"),
+        observation("CapnProto", "statistics", 120, "{}"),
+    ];
+
+    let found: Vec<_> = compare(&first, &second)
+        .iter()
+        .map(|mismatch| (mismatch.protocol.clone(), mismatch.operation.clone(), mismatch.field))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("REST".to_string(), "query".to_string(), "request_bytes"),
+            ("gRPC".to_string(), "query".to_string(), "response"),
+            ("CapnProto".to_string(), "query".to_string(), "missing"),
+            ("CapnProto".to_string(), "statistics".to_string(), "missing"),
+        ]
+    );
+
+    let mismatches = compare(&first, &second);
+    assert_eq!(mismatches[0].to_string(), "REST query: request_bytes differs: 200 then 201");
+    assert_eq!(mismatches[1].to_string(), "gRPC query: response differs from byte 1: \"[1,2]\" then \"[2,1]\"");
+}
@@ -0,0 +1,112 @@
+use capnp::capability::Promise;
+use shared::{Fault, FaultInjection};
+use std::rc::Rc;
+use tokio::sync::Notify;
+
+use crate::metrics_capnp::metrics_service;
+
+/// Wraps a `metrics_service::Server`, failing, delaying or dropping calls as
+/// `FaultInjection` picks. A dropped call never resolves and wakes `dropped`,
+/// which `serve_connection` waits on to close the connection, the nearest
+/// Cap'n Proto has to a dropped request. Calls on a returned QueryHandle go
+/// straight to it and never fail.
+pub struct FaultyService<S> {
+    pub inner: S,
+    pub faults: FaultInjection,
+    pub dropped: Rc<Notify>,
+}
+
+impl<S> FaultyService<S> {
+    fn inject(&mut self, call: impl FnOnce(&mut S) -> Promise<(), capnp::Error>) -> Promise<(), capnp::Error> {
+        match self.faults.pick() {
+            None => call(&mut self.inner),
+            Some(Fault::Error) => Promise::err(capnp::Error::overloaded(shared::INJECTED_FAULT.to_string())),
+            Some(Fault::Delay(delay)) => {
+                let promise = call(&mut self.inner);
+                Promise::from_future(async move {
+                    let result = promise.await;
+                    tokio::time::sleep(delay).await;
+                    result
+                })
+            }
+            Some(Fault::Drop) => {
+                self.dropped.notify_one();
+                Promise::from_future(std::future::pending())
+            }
+        }
+    }
+}
+
+impl<S: metrics_service::Server> metrics_service::Server for FaultyService<S> {
+    fn submit_metric(
+        &mut self,
+        params: metrics_service::SubmitMetricParams,
+        results: metrics_service::SubmitMetricResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.submit_metric(params, results))
+    }
+
+    fn query_metrics(
+        &mut self,
+        params: metrics_service::QueryMetricsParams,
+        results: metrics_service::QueryMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.query_metrics(params, results))
+    }
+
+    fn get_statistics(
+        &mut self,
+        params: metrics_service::GetStatisticsParams,
+        results: metrics_service::GetStatisticsResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.get_statistics(params, results))
+    }
+
+    fn query_rollups(
+        &mut self,
+        params: metrics_service::QueryRollupsParams,
+        results: metrics_service::QueryRollupsResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.query_rollups(params, results))
+    }
+
+    fn delete_metrics(
+        &mut self,
+        params: metrics_service::DeleteMetricsParams,
+        results: metrics_service::DeleteMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.delete_metrics(params, results))
+    }
+
+    fn get_storage_stats(
+        &mut self,
+        params: metrics_service::GetStorageStatsParams,
+        results: metrics_service::GetStorageStatsResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.get_storage_stats(params, results))
+    }
+
+    fn subscribe(
+        &mut self,
+        params: metrics_service::SubscribeParams,
+        results: metrics_service::SubscribeResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.subscribe(params, results))
+    }
+
+    fn query_metrics_streaming(
+        &mut self,
+        params: metrics_service::QueryMetricsStreamingParams,
+        results: metrics_service::QueryMetricsStreamingResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.query_metrics_streaming(params, results))
+    }
+
+    fn open_query(
+        &mut self,
+        params: metrics_service::OpenQueryParams,
+        results: metrics_service::OpenQueryResults,
+    ) -> Promise<(), capnp::Error> {
+        self.inject(|inner| inner.open_query(params, results))
+    }
+}
@@ -1,23 +1,135 @@
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use capnp::capability::Promise;
+use capnp::message::ReaderOptions;
 use capnp_rpc::{pry, rpc_twoparty_capnp, twoparty, RpcSystem};
-use shared::{InMemoryStorage, MetricPoint as SharedMetricPoint, MetricQuery as SharedMetricQuery};
-use std::collections::HashMap;
-use futures_util::io::AsyncReadExt;
+use shared::{AuthConfig, CapnpEncoding, FaultInjection, InMemoryStorage, MetricPoint as SharedMetricPoint, MetricQuery as SharedMetricQuery, MetricsStorage, PayloadCipher, SealedStream, ServiceMetrics};
+use std::collections::{HashMap, VecDeque};
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::rc::Rc;
+use capnp_futures::serialize_packed::{PackedRead, PackedWrite};
+use futures_util::io::{AsyncReadExt, BufReader};
 
 pub mod metrics_capnp {
     include!(concat!(env!("OUT_DIR"), "/metrics_capnp.rs"));
 }
 
-use metrics_capnp::metrics_service;
+use metrics_capnp::{metric_sink, metrics_service, query_handle, subscription};
+
+mod faults;
+mod telemetry;
+
+// Instrumented so the stats endpoint can report what each request allocated
+#[global_allocator]
+static GLOBAL: shared::CountingAllocator = shared::CountingAllocator::new();
+
+// Pushes to one sink are delivered in order, so queryMetricsStreaming keeps
+// several outstanding instead of paying a round trip per point
+const STREAMING_PUSH_WINDOW: usize = 64;
 
 struct MetricsServiceImpl {
     storage: Arc<InMemoryStorage>,
+    auth: Option<AuthConfig>,
 }
 
 impl MetricsServiceImpl {
-    fn new(storage: Arc<InMemoryStorage>) -> Self {
-        Self { storage }
+    fn new(storage: Arc<InMemoryStorage>, auth: Option<AuthConfig>) -> Self {
+        Self { storage, auth }
+    }
+
+    /// Check the request's token param when auth is enabled
+    fn authorize(&self, token: capnp::Result<capnp::text::Reader>) -> Result<(), capnp::Error> {
+        let Some(auth) = &self.auth else {
+            return Ok(());
+        };
+
+        if auth.verify(token?.to_str()?) {
+            Ok(())
+        } else {
+            Err(capnp::Error::failed("Missing or invalid credentials".to_string()))
+        }
+    }
+}
+
+/// Capability returned by `subscribe`; the push task stops when the
+/// subscriber drops it
+struct SubscriptionImpl {
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl subscription::Server for SubscriptionImpl {}
+
+impl Drop for SubscriptionImpl {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Capability returned by `openQuery`, bound to one query
+struct QueryHandleImpl {
+    storage: Arc<InMemoryStorage>,
+    query: SharedMetricQuery,
+}
+
+impl query_handle::Server for QueryHandleImpl {
+    fn get_statistics(
+        &mut self,
+        _params: query_handle::GetStatisticsParams,
+        mut results: query_handle::GetStatisticsResults,
+    ) -> Promise<(), capnp::Error> {
+        let storage = self.storage.clone();
+        let query = self.query.clone();
+        Promise::from_future(async move {
+            let stats = storage
+                .calculate_statistics(&query)
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to calculate statistics".to_string()))?;
+
+            let mut stats_builder = results.get().init_statistics();
+            stats_builder.set_count(stats.count);
+            stats_builder.set_avg_cpu_percent(stats.avg_cpu_percent);
+            stats_builder.set_avg_memory_bytes(stats.avg_memory_bytes);
+            stats_builder.set_avg_disk_io_ops(stats.avg_disk_io_ops);
+            stats_builder.set_time_range_seconds(stats.time_range_seconds);
+
+            Ok(())
+        })
+    }
+
+    fn query_rollups(
+        &mut self,
+        _params: query_handle::QueryRollupsParams,
+        mut results: query_handle::QueryRollupsResults,
+    ) -> Promise<(), capnp::Error> {
+        let storage = self.storage.clone();
+        let query = self.query.clone();
+        Promise::from_future(async move {
+            let rollups = storage
+                .query_rollups(&query)
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to query rollups".to_string()))?;
+
+            let mut rollups_builder = results.get().init_rollups(rollups.len() as u32);
+            
+            for (i, rollup) in rollups.iter().enumerate() {
+                let mut rollup_builder = rollups_builder.reborrow().get(i as u32);
+                rollup_builder.set_hostname((&rollup.hostname[..]).into());
+                rollup_builder.set_minute_start(rollup.minute_start);
+                rollup_builder.set_count(rollup.count);
+                rollup_builder.set_avg_cpu_percent(rollup.avg_cpu_percent);
+                rollup_builder.set_min_cpu_percent(rollup.min_cpu_percent);
+                rollup_builder.set_max_cpu_percent(rollup.max_cpu_percent);
+                rollup_builder.set_avg_memory_bytes(rollup.avg_memory_bytes);
+                rollup_builder.set_min_memory_bytes(rollup.min_memory_bytes);
+                rollup_builder.set_max_memory_bytes(rollup.max_memory_bytes);
+                rollup_builder.set_avg_disk_io_ops(rollup.avg_disk_io_ops);
+                rollup_builder.set_min_disk_io_ops(rollup.min_disk_io_ops);
+                rollup_builder.set_max_disk_io_ops(rollup.max_disk_io_ops);
+            }
+
+            Ok(())
+        })
     }
 }
 
@@ -25,8 +137,10 @@ impl metrics_service::Server for MetricsServiceImpl {
     fn submit_metric(
         &mut self,
         params: metrics_service::SubmitMetricParams,
-        mut _results: metrics_service::SubmitMetricResults,
+        mut results: metrics_service::SubmitMetricResults,
     ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
         let metric_reader = pry!(pry!(params.get()).get_metric());
         
         // Convert Cap'n Proto MetricPoint to shared MetricPoint  
@@ -47,11 +161,20 @@ impl metrics_service::Server for MetricsServiceImpl {
             disk_io_ops: metric_reader.get_disk_io_ops(),
             tags,
         };
-
-        match self.storage.store_metric(shared_metric) {
-            Ok(_) => Promise::ok(()),
-            Err(_) => Promise::err(capnp::Error::failed("Failed to store metric".to_string())),
+        if let Err(e) = shared_metric.validate() {
+            return Promise::err(capnp::Error::failed(format!("Invalid metric: {}", e)));
         }
+
+        let storage = self.storage.clone();
+        Promise::from_future(async move {
+            let stored = storage
+                .store_metrics(vec![shared_metric])
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to store metric".to_string()))?;
+
+            results.get().set_stored(stored as u64);
+            Ok(())
+        })
     }
 
     fn query_metrics(
@@ -59,6 +182,8 @@ impl metrics_service::Server for MetricsServiceImpl {
         params: metrics_service::QueryMetricsParams,
         mut results: metrics_service::QueryMetricsResults,
     ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
         let query_reader = pry!(pry!(params.get()).get_query());
         
         let hostname_filter = if query_reader.has_hostname_filter() {
@@ -71,32 +196,41 @@ impl metrics_service::Server for MetricsServiceImpl {
             start_time: query_reader.get_start_time(),
             end_time: query_reader.get_end_time(),
             hostname_filter,
+            // Cap'n Proto has no optional scalars; 0 means "not set" for both
+            limit: Some(query_reader.get_limit()).filter(|&limit| limit > 0),
+            offset: Some(query_reader.get_offset()).filter(|&offset| offset > 0),
         };
+        if let Err(e) = shared_query.validate() {
+            return Promise::err(capnp::Error::failed(format!("Invalid query: {}", e)));
+        }
 
-        let metrics = match self.storage.query_metrics(&shared_query) {
-            Ok(metrics) => metrics,
-            Err(_) => return Promise::err(capnp::Error::failed("Failed to query metrics".to_string())),
-        };
+        let storage = self.storage.clone();
+        Promise::from_future(async move {
+            let metrics = storage
+                .query_metrics(&shared_query)
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to query metrics".to_string()))?;
 
-        let mut results_builder = results.get().init_metrics(metrics.len() as u32);
-        
-        for (i, metric) in metrics.iter().enumerate() {
-            let mut metric_builder = results_builder.reborrow().get(i as u32);
-            metric_builder.set_timestamp(metric.timestamp);
-            metric_builder.set_hostname((&metric.hostname[..]).into());
-            metric_builder.set_cpu_percent(metric.cpu_percent);
-            metric_builder.set_memory_bytes(metric.memory_bytes);
-            metric_builder.set_disk_io_ops(metric.disk_io_ops);
+            let mut results_builder = results.get().init_metrics(metrics.len() as u32);
             
-            let mut tags_builder = metric_builder.init_tags(metric.tags.len() as u32);
-            for (j, (key, value)) in metric.tags.iter().enumerate() {
-                let mut tag_builder = tags_builder.reborrow().get(j as u32);
-                tag_builder.set_key((&key[..]).into());
-                tag_builder.set_value((&value[..]).into());
+            for (i, metric) in metrics.iter().enumerate() {
+                let mut metric_builder = results_builder.reborrow().get(i as u32);
+                metric_builder.set_timestamp(metric.timestamp);
+                metric_builder.set_hostname((&metric.hostname[..]).into());
+                metric_builder.set_cpu_percent(metric.cpu_percent);
+                metric_builder.set_memory_bytes(metric.memory_bytes);
+                metric_builder.set_disk_io_ops(metric.disk_io_ops);
+                
+                let mut tags_builder = metric_builder.init_tags(metric.tags.len() as u32);
+                for (j, (key, value)) in metric.tags.iter().enumerate() {
+                    let mut tag_builder = tags_builder.reborrow().get(j as u32);
+                    tag_builder.set_key((&key[..]).into());
+                    tag_builder.set_value((&value[..]).into());
+                }
             }
-        }
 
-        Promise::ok(())
+            Ok(())
+        })
     }
 
     fn get_statistics(
@@ -104,6 +238,8 @@ impl metrics_service::Server for MetricsServiceImpl {
         params: metrics_service::GetStatisticsParams,
         mut results: metrics_service::GetStatisticsResults,
     ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
         let query_reader = pry!(pry!(params.get()).get_query());
         
         let hostname_filter = if query_reader.has_hostname_filter() {
@@ -116,60 +252,632 @@ impl metrics_service::Server for MetricsServiceImpl {
             start_time: query_reader.get_start_time(),
             end_time: query_reader.get_end_time(),
             hostname_filter,
+            // Cap'n Proto has no optional scalars; 0 means "not set" for both
+            limit: Some(query_reader.get_limit()).filter(|&limit| limit > 0),
+            offset: Some(query_reader.get_offset()).filter(|&offset| offset > 0),
         };
+        if let Err(e) = shared_query.validate() {
+            return Promise::err(capnp::Error::failed(format!("Invalid query: {}", e)));
+        }
 
-        let stats = match self.storage.calculate_statistics(&shared_query) {
-            Ok(stats) => stats,
-            Err(_) => return Promise::err(capnp::Error::failed("Failed to calculate statistics".to_string())),
+        let storage = self.storage.clone();
+        Promise::from_future(async move {
+            let stats = storage
+                .calculate_statistics(&shared_query)
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to calculate statistics".to_string()))?;
+
+            let mut stats_builder = results.get().init_statistics();
+            stats_builder.set_count(stats.count);
+            stats_builder.set_avg_cpu_percent(stats.avg_cpu_percent);
+            stats_builder.set_avg_memory_bytes(stats.avg_memory_bytes);
+            stats_builder.set_avg_disk_io_ops(stats.avg_disk_io_ops);
+            stats_builder.set_time_range_seconds(stats.time_range_seconds);
+
+            Ok(())
+        })
+    }
+
+    fn query_rollups(
+        &mut self,
+        params: metrics_service::QueryRollupsParams,
+        mut results: metrics_service::QueryRollupsResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
+        let query_reader = pry!(pry!(params.get()).get_query());
+        
+        let hostname_filter = if query_reader.has_hostname_filter() {
+            Some(pry!(pry!(query_reader.get_hostname_filter()).to_str()).to_string())
+        } else {
+            None
+        };
+        
+        let shared_query = SharedMetricQuery {
+            start_time: query_reader.get_start_time(),
+            end_time: query_reader.get_end_time(),
+            hostname_filter,
+            // Cap'n Proto has no optional scalars; 0 means "not set" for both
+            limit: Some(query_reader.get_limit()).filter(|&limit| limit > 0),
+            offset: Some(query_reader.get_offset()).filter(|&offset| offset > 0),
         };
+        if let Err(e) = shared_query.validate() {
+            return Promise::err(capnp::Error::failed(format!("Invalid query: {}", e)));
+        }
+
+        let storage = self.storage.clone();
+        Promise::from_future(async move {
+            let rollups = storage
+                .query_rollups(&shared_query)
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to query rollups".to_string()))?;
+
+            let mut rollups_builder = results.get().init_rollups(rollups.len() as u32);
+            
+            for (i, rollup) in rollups.iter().enumerate() {
+                let mut rollup_builder = rollups_builder.reborrow().get(i as u32);
+                rollup_builder.set_hostname((&rollup.hostname[..]).into());
+                rollup_builder.set_minute_start(rollup.minute_start);
+                rollup_builder.set_count(rollup.count);
+                rollup_builder.set_avg_cpu_percent(rollup.avg_cpu_percent);
+                rollup_builder.set_min_cpu_percent(rollup.min_cpu_percent);
+                rollup_builder.set_max_cpu_percent(rollup.max_cpu_percent);
+                rollup_builder.set_avg_memory_bytes(rollup.avg_memory_bytes);
+                rollup_builder.set_min_memory_bytes(rollup.min_memory_bytes);
+                rollup_builder.set_max_memory_bytes(rollup.max_memory_bytes);
+                rollup_builder.set_avg_disk_io_ops(rollup.avg_disk_io_ops);
+                rollup_builder.set_min_disk_io_ops(rollup.min_disk_io_ops);
+                rollup_builder.set_max_disk_io_ops(rollup.max_disk_io_ops);
+            }
 
-        let mut stats_builder = results.get().init_statistics();
-        stats_builder.set_count(stats.count);
-        stats_builder.set_avg_cpu_percent(stats.avg_cpu_percent);
-        stats_builder.set_avg_memory_bytes(stats.avg_memory_bytes);
-        stats_builder.set_avg_disk_io_ops(stats.avg_disk_io_ops);
-        stats_builder.set_time_range_seconds(stats.time_range_seconds);
+            Ok(())
+        })
+    }
 
+    fn delete_metrics(
+        &mut self,
+        params: metrics_service::DeleteMetricsParams,
+        mut results: metrics_service::DeleteMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
+        let query_reader = pry!(pry!(params.get()).get_query());
+        
+        let hostname_filter = if query_reader.has_hostname_filter() {
+            Some(pry!(pry!(query_reader.get_hostname_filter()).to_str()).to_string())
+        } else {
+            None
+        };
+        
+        let shared_query = SharedMetricQuery {
+            start_time: query_reader.get_start_time(),
+            end_time: query_reader.get_end_time(),
+            hostname_filter,
+            limit: None,
+            offset: None,
+        };
+        if let Err(e) = shared_query.validate() {
+            return Promise::err(capnp::Error::failed(format!("Invalid query: {}", e)));
+        }
+
+        let storage = self.storage.clone();
+        Promise::from_future(async move {
+            let deleted = storage
+                .delete_metrics(&shared_query)
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to delete metrics".to_string()))?;
+
+            results.get().set_deleted(deleted);
+            Ok(())
+        })
+    }
+
+    fn get_storage_stats(
+        &mut self,
+        params: metrics_service::GetStorageStatsParams,
+        mut results: metrics_service::GetStorageStatsResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
+        let storage = self.storage.clone();
+        Promise::from_future(async move {
+            let stats = storage
+                .storage_stats()
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to read storage stats".to_string()))?;
+
+            let mut stats_builder = results.get().init_stats();
+            stats_builder.set_point_count(stats.point_count);
+            stats_builder.set_approx_heap_bytes(stats.approx_heap_bytes);
+            stats_builder.set_rollup_bucket_count(stats.rollup_bucket_count);
+            stats_builder.set_rollup_heap_bytes(stats.rollup_heap_bytes);
+
+            Ok(())
+        })
+    }
+
+    fn subscribe(
+        &mut self,
+        params: metrics_service::SubscribeParams,
+        mut results: metrics_service::SubscribeResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
+        let query_reader = pry!(pry!(params.get()).get_query());
+
+        let hostname_filter = if query_reader.has_hostname_filter() {
+            Some(pry!(pry!(query_reader.get_hostname_filter()).to_str()).to_string())
+        } else {
+            None
+        };
+
+        let shared_query = SharedMetricQuery {
+            start_time: query_reader.get_start_time(),
+            end_time: query_reader.get_end_time(),
+            hostname_filter,
+            limit: None,
+            offset: None,
+        };
+        if let Err(e) = shared_query.validate() {
+            return Promise::err(capnp::Error::failed(format!("Invalid query: {}", e)));
+        }
+        let sink: metric_sink::Client = pry!(pry!(params.get()).get_sink());
+
+        // Subscribe before replying so nothing stored after the client sees the
+        // result is missed
+        let mut live = self.storage.subscribe();
+        let task = tokio::task::spawn_local(async move {
+            while let Some(metric) = live.recv().await {
+                if !shared_query.matches(&metric) {
+                    continue;
+                }
+
+                let mut request = sink.push_request();
+                let mut metric_builder = request.get().init_metric();
+                metric_builder.set_timestamp(metric.timestamp);
+                metric_builder.set_hostname((&metric.hostname[..]).into());
+                metric_builder.set_cpu_percent(metric.cpu_percent);
+                metric_builder.set_memory_bytes(metric.memory_bytes);
+                metric_builder.set_disk_io_ops(metric.disk_io_ops);
+
+                let mut tags_builder = metric_builder.init_tags(metric.tags.len() as u32);
+                for (i, (key, value)) in metric.tags.iter().enumerate() {
+                    let mut tag_builder = tags_builder.reborrow().get(i as u32);
+                    tag_builder.set_key((&key[..]).into());
+                    tag_builder.set_value((&value[..]).into());
+                }
+
+                // One push in flight at a time keeps delivery ordered; an error
+                // means the subscriber has gone away
+                if request.send().promise.await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        results.get().set_subscription(capnp_rpc::new_client(SubscriptionImpl { task }));
+        Promise::ok(())
+    }
+
+    fn query_metrics_streaming(
+        &mut self,
+        params: metrics_service::QueryMetricsStreamingParams,
+        mut results: metrics_service::QueryMetricsStreamingResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
+        let query_reader = pry!(pry!(params.get()).get_query());
+
+        let hostname_filter = if query_reader.has_hostname_filter() {
+            Some(pry!(pry!(query_reader.get_hostname_filter()).to_str()).to_string())
+        } else {
+            None
+        };
+
+        let shared_query = SharedMetricQuery {
+            start_time: query_reader.get_start_time(),
+            end_time: query_reader.get_end_time(),
+            hostname_filter,
+            limit: Some(query_reader.get_limit()).filter(|&limit| limit > 0),
+            offset: Some(query_reader.get_offset()).filter(|&offset| offset > 0),
+        };
+        if let Err(e) = shared_query.validate() {
+            return Promise::err(capnp::Error::failed(format!("Invalid query: {}", e)));
+        }
+        let sink: metric_sink::Client = pry!(pry!(params.get()).get_sink());
+
+        let storage = self.storage.clone();
+        Promise::from_future(async move {
+            let metrics = storage
+                .query_metrics(&shared_query)
+                .await
+                .map_err(|_| capnp::Error::failed("Failed to query metrics".to_string()))?;
+
+            let mut in_flight = VecDeque::with_capacity(STREAMING_PUSH_WINDOW);
+            for metric in &metrics {
+                let mut request = sink.push_request();
+                let mut metric_builder = request.get().init_metric();
+                metric_builder.set_timestamp(metric.timestamp);
+                metric_builder.set_hostname((&metric.hostname[..]).into());
+                metric_builder.set_cpu_percent(metric.cpu_percent);
+                metric_builder.set_memory_bytes(metric.memory_bytes);
+                metric_builder.set_disk_io_ops(metric.disk_io_ops);
+
+                let mut tags_builder = metric_builder.init_tags(metric.tags.len() as u32);
+                for (i, (key, value)) in metric.tags.iter().enumerate() {
+                    let mut tag_builder = tags_builder.reborrow().get(i as u32);
+                    tag_builder.set_key((&key[..]).into());
+                    tag_builder.set_value((&value[..]).into());
+                }
+
+                in_flight.push_back(request.send().promise);
+                if in_flight.len() == STREAMING_PUSH_WINDOW {
+                    if let Some(push) = in_flight.pop_front() {
+                        push.await?;
+                    }
+                }
+            }
+            for push in in_flight {
+                push.await?;
+            }
+
+            results.get().set_count(metrics.len() as u64);
+            Ok(())
+        })
+    }
+
+    fn open_query(
+        &mut self,
+        params: metrics_service::OpenQueryParams,
+        mut results: metrics_service::OpenQueryResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.authorize(pry!(params.get()).get_token()));
+
+        let query_reader = pry!(pry!(params.get()).get_query());
+
+        let hostname_filter = if query_reader.has_hostname_filter() {
+            Some(pry!(pry!(query_reader.get_hostname_filter()).to_str()).to_string())
+        } else {
+            None
+        };
+
+        let query = SharedMetricQuery {
+            start_time: query_reader.get_start_time(),
+            end_time: query_reader.get_end_time(),
+            hostname_filter,
+            limit: Some(query_reader.get_limit()).filter(|&limit| limit > 0),
+            offset: Some(query_reader.get_offset()).filter(|&offset| offset > 0),
+        };
+        if let Err(e) = query.validate() {
+            return Promise::err(capnp::Error::failed(format!("Invalid query: {}", e)));
+        }
+
+        let handle = QueryHandleImpl { storage: self.storage.clone(), query };
+        results.get().set_handle(capnp_rpc::new_client(handle));
         Promise::ok(())
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "127.0.0.1:55556";
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    println!("Cap'n Proto service listening on {}", addr);
-
-    let storage = Arc::new(InMemoryStorage::new());
-
-    // Use LocalSet for concurrent connections since RpcSystem is !Send
-    tokio::task::LocalSet::new()
-        .run_until(async move {
-            loop {
-                let (stream, client_addr) = listener.accept().await?;
-                println!("Cap'n Proto client connected from {}", client_addr);
-                
-                let storage_clone = storage.clone();
-                
-                // Use spawn_local since RpcSystem doesn't implement Send
-                tokio::task::spawn_local(async move {
-                    let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
-                    let rpc_network = Box::new(twoparty::VatNetwork::new(
-                        reader,
-                        writer,
-                        rpc_twoparty_capnp::Side::Server,
-                        Default::default(),
-                    ));
-
-                    let service_impl = MetricsServiceImpl::new(storage_clone);
-                    let metrics_service: metrics_service::Client = capnp_rpc::new_client(service_impl);
-                    let rpc_system = RpcSystem::new(rpc_network, Some(metrics_service.clone().client));
-
-                    if let Err(e) = rpc_system.await {
-                        eprintln!("RPC system error: {}", e);
-                    }
-                });
+    let tracing_guard = shared::init_tracing_from_env("capnp-service")?;
+    let listener = match shared::uds_path()? {
+        Some(path) => {
+            // A socket left behind by a previous run would make bind fail
+            let stale = std::fs::symlink_metadata(&path).map(|meta| meta.file_type().is_socket());
+            if let Ok(true) = stale {
+                std::fs::remove_file(&path)?;
+            }
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            println!("Cap'n Proto service listening on unix:{}", path.display());
+            Listener::Unix(listener, path)
+        }
+        None => {
+            let addr = shared::bind_addr("PROTOBENCH_CAPNP_ADDR", "127.0.0.1:55556")?;
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            println!("Cap'n Proto service listening on {}", addr);
+            Listener::Tcp(listener)
+        }
+    };
+
+    let storage = Arc::new(InMemoryStorage::from_env().await?);
+    storage.spawn_eviction_task();
+    let auth = AuthConfig::from_env();
+    if auth.is_some() {
+        println!("Cap'n Proto authentication: required");
+    }
+    let telemetry = ServiceMetrics::spawn_exporter_from_env("capnp", "PROTOBENCH_CAPNP_METRICS_ADDR")?;
+    let encoding = CapnpEncoding::from_env();
+    if encoding != CapnpEncoding::default() {
+        println!("Cap'n Proto encoding: {}", encoding.label());
+    }
+
+    // The traversal limit also caps the size of each incoming message
+    let mut reader_options = ReaderOptions::new();
+    if let Some(limit) = shared::max_message_bytes("PROTOBENCH_CAPNP_MAX_MESSAGE_BYTES") {
+        println!("Cap'n Proto message size limit: {} bytes", limit);
+        reader_options.traversal_limit_in_words(Some(limit / 8));
+    }
+
+    let faults = FaultInjection::from_env();
+    if let Some(faults) = &faults {
+        println!("Cap'n Proto fault injection: {}", faults.label());
+    }
+    if shared::request_log_enabled() {
+        println!("Cap'n Proto request log: stderr");
+    }
+    let cipher = PayloadCipher::from_env()?;
+    if cipher.is_some() {
+        println!("Cap'n Proto payload encryption: {}", PayloadCipher::NAME);
+    }
+
+    let workers = worker_count();
+    println!("Cap'n Proto workers: {}", workers);
+    let context = ConnectionContext {
+        storage: storage.clone(),
+        auth,
+        telemetry,
+        traced: tracing_guard.is_some() || shared::request_log_enabled(),
+        faults,
+        encoding,
+        reader_options,
+        cipher,
+    };
+
+    let mut senders = Vec::with_capacity(workers);
+    let mut live = Vec::with_capacity(workers);
+    let mut threads = Vec::with_capacity(workers);
+    for id in 0..workers {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let context = context.clone();
+        let worker_connections = connections.clone();
+        let thread = std::thread::Builder::new()
+            .name(format!("capnp-worker-{}", id))
+            .spawn(move || run_worker(rx, worker_connections, context))?;
+        senders.push(tx);
+        live.push(connections);
+        threads.push(thread);
+    }
+
+    let shutdown = shared::shutdown_signal();
+    tokio::pin!(shutdown);
+
+    // Each connection goes to the worker with the fewest live ones, ties taken
+    // in turn. Pooled clients (`capnp_client::WorkerPool`) hold a connection
+    // for the whole run, so plain round-robin could stack them on one worker
+    // while connection-per-call traffic left another idle.
+    let mut next = 0;
+    loop {
+        let (stream, client_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = &mut shutdown => break,
+        };
+        println!("Cap'n Proto client connected from {}", client_addr);
+
+        let worker = (0..workers)
+            .map(|offset| (next + offset) % workers)
+            .min_by_key(|&worker| live[worker].load(Ordering::Relaxed))
+            .unwrap_or(0);
+        next = (worker + 1) % workers;
+        live[worker].fetch_add(1, Ordering::Relaxed);
+        senders[worker].send(stream)?;
+    }
+
+    // Closing the channels tells each worker to drain its connections
+    if let Listener::Unix(_, path) = &listener {
+        let _ = std::fs::remove_file(path);
+    }
+    drop(listener);
+    drop(senders);
+    storage.close_subscriptions();
+    for thread in threads {
+        tokio::task::spawn_blocking(move || thread.join())
+            .await?
+            .map_err(|_| "Cap'n Proto worker panicked")??;
+    }
+
+    println!("Cap'n Proto service stopped");
+    Ok(())
+}
+
+/// TCP by default, or a Unix domain socket with `--uds <path>`
+enum Listener {
+    Tcp(tokio::net::TcpListener),
+    Unix(tokio::net::UnixListener, PathBuf),
+}
+
+/// A connection detached from the accepting runtime, so the worker it is
+/// handed to can register it with its own
+#[derive(Debug)]
+enum Accepted {
+    Tcp(std::net::TcpStream),
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl Listener {
+    /// Next connection and a description of the peer for logging
+    async fn accept(&self) -> std::io::Result<(Accepted, String)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                Ok((Accepted::Tcp(stream.into_std()?), addr.to_string()))
             }
+            // Unix peers are unnamed, so report the socket they came in on
+            Listener::Unix(listener, path) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((Accepted::Unix(stream.into_std()?), format!("unix:{}", path.display())))
+            }
+        }
+    }
+}
+
+/// `PROTOBENCH_CAPNP_WORKERS`, defaulting to one worker per core like the
+/// multi-threaded runtimes behind rest-service and grpc-service
+fn worker_count() -> usize {
+    std::env::var("PROTOBENCH_CAPNP_WORKERS")
+        .ok()
+        .and_then(|workers| workers.parse().ok())
+        .or_else(|| std::thread::available_parallelism().ok().map(|cores| cores.get()))
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Everything a worker needs to serve a connection
+#[derive(Clone)]
+struct ConnectionContext {
+    storage: Arc<InMemoryStorage>,
+    auth: Option<AuthConfig>,
+    telemetry: Option<Arc<ServiceMetrics>>,
+    /// Whether calls go through `TracedService`, for spans continuing the
+    /// client's trace or the request log
+    traced: bool,
+    faults: Option<FaultInjection>,
+    encoding: CapnpEncoding,
+    reader_options: ReaderOptions,
+    /// Seals the connection's messages when `PROTOBENCH_PAYLOAD_KEY` is set
+    cipher: Option<PayloadCipher>,
+}
+
+/// Serve the connections the accept loop hands this worker until the channel
+/// closes, then give them the grace period to finish. RpcSystem is !Send, so
+/// each worker runs its connections on its own single-threaded runtime.
+fn run_worker(
+    mut streams: tokio::sync::mpsc::UnboundedReceiver<Accepted>,
+    live: Arc<AtomicUsize>,
+    context: ConnectionContext,
+) -> std::io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    tokio::task::LocalSet::new().block_on(&runtime, async move {
+        let mut connections = tokio::task::JoinSet::new();
+
+        loop {
+            let stream = tokio::select! {
+                received = streams.recv() => match received {
+                    Some(stream) => stream,
+                    None => break,
+                },
+                // Reap finished connections so the set doesn't grow over a long run
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            };
+            // Counted by the accept loop when dispatched; released when the
+            // connection's task ends, or here if it never starts
+            let counted = LiveConnection(live.clone());
+            let registered = match stream {
+                Accepted::Tcp(stream) => tokio::net::TcpStream::from_std(stream).map(|stream| {
+                    let context = context.clone();
+                    connections.spawn_local(async move {
+                        let _counted = counted;
+                        serve_connection(stream, context).await
+                    })
+                }),
+                Accepted::Unix(stream) => tokio::net::UnixStream::from_std(stream).map(|stream| {
+                    let context = context.clone();
+                    connections.spawn_local(async move {
+                        let _counted = counted;
+                        serve_connection(stream, context).await
+                    })
+                }),
+            };
+            if let Err(e) = registered {
+                eprintln!("Failed to register Cap'n Proto connection: {}", e);
+            }
+        }
+
+        // Clients hold connections open between calls, so there is no idle
+        // point to wait for; give them the grace period to disconnect, then drop them
+        let grace = shared::shutdown_grace_period();
+        let drained = tokio::time::timeout(grace, async {
+            while connections.join_next().await.is_some() {}
         })
-        .await
+        .await;
+        if drained.is_err() {
+            println!("Dropping {} Cap'n Proto connections after {:?}", connections.len(), grace);
+            connections.shutdown().await;
+        }
+
+        Ok(())
+    })
+}
+
+fn rpc_network<S>(stream: S, context: &ConnectionContext) -> Box<dyn capnp_rpc::VatNetwork<rpc_twoparty_capnp::Side>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + 'static,
+{
+    let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+    match context.encoding {
+        CapnpEncoding::Unpacked => Box::new(twoparty::VatNetwork::new(
+            reader,
+            writer,
+            rpc_twoparty_capnp::Side::Server,
+            context.reader_options,
+        )),
+        // PackedRead pulls a few bytes at a time, so buffer the socket under it
+        CapnpEncoding::Packed => Box::new(twoparty::VatNetwork::new(
+            PackedRead::new(BufReader::new(reader)),
+            PackedWrite::new(writer),
+            rpc_twoparty_capnp::Side::Server,
+            context.reader_options,
+        )),
+    }
+}
+
+/// One of a worker's live connections, for the accept loop's least-connections
+/// dispatch; the count drops when this does
+struct LiveConnection(Arc<AtomicUsize>);
+
+impl Drop for LiveConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+async fn serve_connection<S>(stream: S, context: ConnectionContext)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + 'static,
+{
+    let rpc_network = match &context.cipher {
+        Some(cipher) => rpc_network(SealedStream::new(stream, cipher.clone()), &context),
+        None => rpc_network(stream, &context),
+    };
+
+    let service_impl = MetricsServiceImpl::new(context.storage, context.auth);
+    // Innermost, so telemetry and traces record injected faults like real ones
+    let dropped = Rc::new(tokio::sync::Notify::new());
+    let metrics_service = match context.faults {
+        Some(faults) => metrics_client(
+            faults::FaultyService { inner: service_impl, faults, dropped: dropped.clone() },
+            context.telemetry,
+            context.traced,
+        ),
+        None => metrics_client(service_impl, context.telemetry, context.traced),
+    };
+    let rpc_system = RpcSystem::new(rpc_network, Some(metrics_service.clone().client));
+
+    tokio::select! {
+        result = rpc_system => {
+            if let Err(e) = result {
+                eprintln!("RPC system error: {}", e);
+            }
+        }
+        // Dropping the RPC system closes the socket under every call on it
+        _ = dropped.notified() => {}
+    }
+}
+
+/// `inner` behind whichever of the telemetry and tracing wrappers are enabled
+fn metrics_client<S: metrics_service::Server + 'static>(
+    inner: S,
+    telemetry: Option<Arc<ServiceMetrics>>,
+    traced: bool,
+) -> metrics_service::Client {
+    match (telemetry, traced) {
+        (Some(metrics), true) => capnp_rpc::new_client(telemetry::InstrumentedService {
+            inner: telemetry::TracedService { inner },
+            metrics,
+        }),
+        (Some(metrics), false) => capnp_rpc::new_client(telemetry::InstrumentedService { inner, metrics }),
+        (None, true) => capnp_rpc::new_client(telemetry::TracedService { inner }),
+        (None, false) => capnp_rpc::new_client(inner),
+    }
 }
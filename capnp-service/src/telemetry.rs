@@ -0,0 +1,286 @@
+use capnp::capability::Promise;
+use shared::ServiceMetrics;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::Instrument;
+
+use crate::metrics_capnp::metrics_service;
+
+/// Wraps a `metrics_service::Server`, recording each call in `ServiceMetrics`.
+/// Cap'n Proto has no middleware hook, so every method delegates and times
+/// the promise the inner server returns; rejected tokens show as `failed`.
+pub struct InstrumentedService<S> {
+    pub inner: S,
+    pub metrics: Arc<ServiceMetrics>,
+}
+
+impl<S> InstrumentedService<S> {
+    fn instrument(&self, method: &str, promise: Promise<(), capnp::Error>) -> Promise<(), capnp::Error> {
+        let mut timer = self.metrics.start(method);
+        Promise::from_future(async move {
+            let result = timer.track(promise).await;
+            match &result {
+                Ok(()) => timer.finish("ok"),
+                Err(e) => timer.finish(&format!("{:?}", e.kind).to_lowercase()),
+            }
+            result
+        })
+    }
+}
+
+impl<S: metrics_service::Server> metrics_service::Server for InstrumentedService<S> {
+    fn submit_metric(
+        &mut self,
+        params: metrics_service::SubmitMetricParams,
+        results: metrics_service::SubmitMetricResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.submit_metric(params, results);
+        self.instrument("submitMetric", promise)
+    }
+
+    fn query_metrics(
+        &mut self,
+        params: metrics_service::QueryMetricsParams,
+        results: metrics_service::QueryMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.query_metrics(params, results);
+        self.instrument("queryMetrics", promise)
+    }
+
+    fn get_statistics(
+        &mut self,
+        params: metrics_service::GetStatisticsParams,
+        results: metrics_service::GetStatisticsResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.get_statistics(params, results);
+        self.instrument("getStatistics", promise)
+    }
+
+    fn query_rollups(
+        &mut self,
+        params: metrics_service::QueryRollupsParams,
+        results: metrics_service::QueryRollupsResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.query_rollups(params, results);
+        self.instrument("queryRollups", promise)
+    }
+
+    fn delete_metrics(
+        &mut self,
+        params: metrics_service::DeleteMetricsParams,
+        results: metrics_service::DeleteMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.delete_metrics(params, results);
+        self.instrument("deleteMetrics", promise)
+    }
+
+    fn get_storage_stats(
+        &mut self,
+        params: metrics_service::GetStorageStatsParams,
+        results: metrics_service::GetStorageStatsResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.get_storage_stats(params, results);
+        self.instrument("getStorageStats", promise)
+    }
+
+    fn subscribe(
+        &mut self,
+        params: metrics_service::SubscribeParams,
+        results: metrics_service::SubscribeResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.subscribe(params, results);
+        self.instrument("subscribe", promise)
+    }
+
+    fn query_metrics_streaming(
+        &mut self,
+        params: metrics_service::QueryMetricsStreamingParams,
+        results: metrics_service::QueryMetricsStreamingResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.query_metrics_streaming(params, results);
+        self.instrument("queryMetricsStreaming", promise)
+    }
+
+    // Calls on the returned QueryHandle go straight to it and aren't counted
+    fn open_query(
+        &mut self,
+        params: metrics_service::OpenQueryParams,
+        results: metrics_service::OpenQueryResults,
+    ) -> Promise<(), capnp::Error> {
+        let promise = self.inner.open_query(params, results);
+        self.instrument("openQuery", promise)
+    }
+}
+
+/// Wraps a `metrics_service::Server`, running each call inside a span that
+/// continues the client's `traceparent` param and is tagged with its
+/// `requestId`, then logging the call if the request log is on. Like
+/// `InstrumentedService` it delegates every method, and the span covers the
+/// promise until it resolves.
+pub struct TracedService<S> {
+    pub inner: S,
+}
+
+/// A call `TracedService` has started
+struct TracedCall {
+    method: &'static str,
+    request_id: Option<String>,
+    span: tracing::Span,
+    started: Instant,
+}
+
+type TextParam<'a> = capnp::Result<capnp::text::Reader<'a>>;
+
+fn text(param: TextParam<'_>) -> Option<&str> {
+    param.ok().and_then(|param| param.to_str().ok())
+}
+
+impl TracedCall {
+    fn start(method: &'static str, traceparent: TextParam<'_>, request_id: TextParam<'_>) -> Self {
+        let request_id = text(request_id).and_then(shared::valid_request_id).map(str::to_string);
+        let span = tracing::info_span!("capnp.request", method, request_id = request_id.as_deref().unwrap_or_default());
+        shared::set_remote_parent(&span, text(traceparent));
+        Self { method, request_id, span, started: Instant::now() }
+    }
+
+    fn finish(self, promise: Promise<(), capnp::Error>) -> Promise<(), capnp::Error> {
+        Promise::from_future(async move {
+            let result = promise.instrument(self.span).await;
+            let status = match &result {
+                Ok(()) => "ok".to_string(),
+                Err(e) => format!("{:?}", e.kind).to_lowercase(),
+            };
+            shared::log_request("capnp", self.method, self.request_id.as_deref(), &status, self.started.elapsed());
+            result
+        })
+    }
+}
+
+impl<S: metrics_service::Server> metrics_service::Server for TracedService<S> {
+    fn submit_metric(
+        &mut self,
+        params: metrics_service::SubmitMetricParams,
+        results: metrics_service::SubmitMetricResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "submitMetric",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.submit_metric(params, results));
+        call.finish(promise)
+    }
+
+    fn query_metrics(
+        &mut self,
+        params: metrics_service::QueryMetricsParams,
+        results: metrics_service::QueryMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "queryMetrics",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.query_metrics(params, results));
+        call.finish(promise)
+    }
+
+    fn get_statistics(
+        &mut self,
+        params: metrics_service::GetStatisticsParams,
+        results: metrics_service::GetStatisticsResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "getStatistics",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.get_statistics(params, results));
+        call.finish(promise)
+    }
+
+    fn query_rollups(
+        &mut self,
+        params: metrics_service::QueryRollupsParams,
+        results: metrics_service::QueryRollupsResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "queryRollups",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.query_rollups(params, results));
+        call.finish(promise)
+    }
+
+    fn delete_metrics(
+        &mut self,
+        params: metrics_service::DeleteMetricsParams,
+        results: metrics_service::DeleteMetricsResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "deleteMetrics",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.delete_metrics(params, results));
+        call.finish(promise)
+    }
+
+    fn get_storage_stats(
+        &mut self,
+        params: metrics_service::GetStorageStatsParams,
+        results: metrics_service::GetStorageStatsResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "getStorageStats",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.get_storage_stats(params, results));
+        call.finish(promise)
+    }
+
+    fn subscribe(
+        &mut self,
+        params: metrics_service::SubscribeParams,
+        results: metrics_service::SubscribeResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "subscribe",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.subscribe(params, results));
+        call.finish(promise)
+    }
+
+    fn query_metrics_streaming(
+        &mut self,
+        params: metrics_service::QueryMetricsStreamingParams,
+        results: metrics_service::QueryMetricsStreamingResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "queryMetricsStreaming",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.query_metrics_streaming(params, results));
+        call.finish(promise)
+    }
+
+    // Calls on the returned QueryHandle aren't traced
+    fn open_query(
+        &mut self,
+        params: metrics_service::OpenQueryParams,
+        results: metrics_service::OpenQueryResults,
+    ) -> Promise<(), capnp::Error> {
+        let call = TracedCall::start(
+            "openQuery",
+            params.get().and_then(|params| params.get_traceparent()),
+            params.get().and_then(|params| params.get_request_id()),
+        );
+        let promise = call.span.in_scope(|| self.inner.open_query(params, results));
+        call.finish(promise)
+    }
+}
@@ -0,0 +1,25 @@
+//! Cap'n Proto messages read as a `MetricPoint`, in both stream encodings,
+//! and converted to `shared::MetricPoint` the way capnp-service reads each
+//! submitted point, then validated as it is before storing
+
+#![no_main]
+
+use benchmarks::capnp_client::read_metric;
+use benchmarks::metrics_capnp::metric_point;
+use capnp::message::ReaderOptions;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let messages = [
+        capnp::serialize::read_message(&mut &data[..], ReaderOptions::new()),
+        capnp::serialize_packed::read_message(&mut &data[..], ReaderOptions::new()),
+    ];
+    for message in messages.into_iter().flatten() {
+        let Ok(reader) = message.get_root::<metric_point::Reader>() else {
+            continue;
+        };
+        if let Ok(metric) = read_metric(reader) {
+            let _ = metric.validate();
+        }
+    }
+});
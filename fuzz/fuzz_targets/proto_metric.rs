@@ -0,0 +1,20 @@
+//! Protobuf `MetricPoint` and `MetricBatch` messages decoded and converted to
+//! `shared::MetricPoint`, the field-for-field copy grpc-service makes of each
+//! point it's sent, then validated as it is before storing
+
+#![no_main]
+
+use benchmarks::grpc_client::{from_proto_metric, metrics};
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(metric) = metrics::MetricPoint::decode(data) {
+        let _ = from_proto_metric(metric).validate();
+    }
+    if let Ok(batch) = metrics::MetricBatch::decode(data) {
+        for metric in batch.metrics {
+            let _ = from_proto_metric(metric).validate();
+        }
+    }
+});
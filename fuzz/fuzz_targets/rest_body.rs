@@ -0,0 +1,21 @@
+//! Request bodies the way rest-service's `Negotiated` extractor decodes them
+//! for `POST /metrics` and `POST /metrics/batch`, in every `BodyEncoding`,
+//! then validated as the handlers do before storing
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::{BodyEncoding, MetricPoint};
+
+fuzz_target!(|data: &[u8]| {
+    for encoding in BodyEncoding::ALL {
+        if let Ok(metric) = encoding.decode::<MetricPoint>(data) {
+            let _ = metric.validate();
+        }
+        if let Ok(metrics) = encoding.decode::<Vec<MetricPoint>>(data) {
+            for metric in &metrics {
+                let _ = metric.validate();
+            }
+        }
+    }
+});
@@ -1,7 +1,12 @@
+use std::path::PathBuf;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Descriptor set served by the reflection service
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
     tonic_build::configure()
         .build_server(true)
         .build_client(true)
+        .file_descriptor_set_path(out_dir.join("metrics_descriptor.bin"))
         .compile(&["../schemas/metrics.proto"], &["../schemas"])?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+use tokio::time::Instant;
+use tonic::Request;
+
+/// Server-side cap on every request, from `PROTOBENCH_GRPC_SERVER_TIMEOUT_MS`.
+/// Tonic applies the shorter of this and the client's `grpc-timeout` to each
+/// handler, answering `CANCELLED` when it expires.
+pub fn server_timeout_from_env() -> Option<Duration> {
+    std::env::var("PROTOBENCH_GRPC_SERVER_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&ms| ms > 0)
+        .map(Duration::from_millis)
+}
+
+/// When the client's `grpc-timeout` runs out, counted from now. Tonic only
+/// bounds the handler itself, so streaming handlers use this to stop the
+/// task that keeps sending after the response headers went out.
+pub fn from_request<T>(request: &Request<T>) -> Option<Instant> {
+    let value = request.metadata().get("grpc-timeout")?.to_str().ok()?;
+    parse_timeout(value).map(|timeout| Instant::now() + timeout)
+}
+
+/// `grpc-timeout` is at most 8 digits followed by a unit:
+/// H, M, S, m (milli), u (micro) or n (nano)
+fn parse_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
@@ -0,0 +1,70 @@
+use shared::{Fault, FaultInjection};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::{http, Service};
+use tonic::Status;
+use tower::BoxError;
+
+/// Fails, delays or drops MetricsService calls as `FaultInjection` picks.
+/// Health and reflection calls pass untouched, so probes still see the
+/// service as up while its calls fail.
+#[derive(Clone)]
+pub struct FaultLayer {
+    pub faults: FaultInjection,
+}
+
+impl<S> tower::Layer<S> for FaultLayer {
+    type Service = FaultService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FaultService { inner, faults: self.faults }
+    }
+}
+
+#[derive(Clone)]
+pub struct FaultService<S> {
+    inner: S,
+    faults: FaultInjection,
+}
+
+impl<S, ReqBody> Service<http::Request<ReqBody>> for FaultService<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let fault = if request.uri().path().starts_with("/protobench.metrics.MetricsService/") {
+            self.faults.pick()
+        } else {
+            None
+        };
+
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            match fault {
+                None => inner.call(request).await.map_err(Into::into),
+                Some(Fault::Error) => Ok(Status::unavailable(shared::INJECTED_FAULT).to_http()),
+                Some(Fault::Delay(delay)) => {
+                    let response = inner.call(request).await.map_err(Into::into);
+                    tokio::time::sleep(delay).await;
+                    response
+                }
+                // An error that isn't a Status makes hyper reset the stream
+                Some(Fault::Drop) => Err(std::io::Error::new(std::io::ErrorKind::ConnectionAborted, shared::INJECTED_FAULT).into()),
+            }
+        })
+    }
+}
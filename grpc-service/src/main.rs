@@ -1,14 +1,31 @@
 use std::sync::Arc;
-use tonic::{transport::Server, Request, Response, Status};
-use shared::{InMemoryStorage, MetricPoint as SharedMetricPoint, MetricQuery as SharedMetricQuery};
+use tonic::{
+    codec::CompressionEncoding,
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::Server,
+    Request, Response, Status,
+};
+use shared::{AuthConfig, FaultInjection, HttpCompression, InMemoryStorage, MetricPoint as SharedMetricPoint, MetricQuery as SharedMetricQuery, MetricsStorage, PayloadCipher, SealedGrpc, ServiceMetrics};
+
+mod deadline;
+mod faults;
+mod telemetry;
+mod tls;
+
+// Instrumented so the stats endpoint can report what each request allocated
+#[global_allocator]
+static GLOBAL: shared::CountingAllocator = shared::CountingAllocator::new();
 
 pub mod metrics {
     tonic::include_proto!("protobench.metrics");
+
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("metrics_descriptor");
 }
 
 use metrics::{
     metrics_service_server::{MetricsService, MetricsServiceServer},
-    Empty, MetricPoint, MetricQuery, MetricStatistics,
+    DeleteSummary, Empty, MetricBatch, MetricPoint, MetricPointList, MetricQuery, MetricRollup, MetricRollupList, MetricStatistics,
+    StorageStats, SubmitSummary,
 };
 
 pub struct MetricsServiceImpl {
@@ -38,13 +55,76 @@ impl MetricsService for MetricsServiceImpl {
             disk_io_ops: metric.disk_io_ops,
             tags: metric.tags,
         };
+        shared_metric
+            .validate()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
 
-        match self.storage.store_metric(shared_metric) {
+        match self.storage.store_metric(shared_metric).await {
             Ok(_) => Ok(Response::new(Empty {})),
             Err(_) => Err(Status::internal("Failed to store metric")),
         }
     }
 
+    /// Client-streamed batch. Points are buffered until the stream ends and
+    /// stored all-or-nothing, so one invalid point rejects the whole batch.
+    async fn submit_metrics(
+        &self,
+        request: Request<tonic::Streaming<MetricPoint>>,
+    ) -> Result<Response<SubmitSummary>, Status> {
+        let mut stream = request.into_inner();
+        let mut batch = Vec::new();
+
+        while let Some(metric) = stream.message().await? {
+            let shared_metric = SharedMetricPoint {
+                timestamp: metric.timestamp,
+                hostname: metric.hostname,
+                cpu_percent: metric.cpu_percent,
+                memory_bytes: metric.memory_bytes,
+                disk_io_ops: metric.disk_io_ops,
+                tags: metric.tags,
+            };
+            shared_metric
+                .validate()
+                .map_err(|e| Status::invalid_argument(format!("metric {}: {}", batch.len(), e)))?;
+            batch.push(shared_metric);
+        }
+
+        match self.storage.store_metrics(batch).await {
+            Ok(accepted) => Ok(Response::new(SubmitSummary { accepted: accepted as u64 })),
+            Err(_) => Err(Status::internal("Failed to store metrics")),
+        }
+    }
+
+    /// Whole batch in one message, stored all-or-nothing like the REST batch
+    /// endpoint
+    async fn submit_metric_batch(
+        &self,
+        request: Request<MetricBatch>,
+    ) -> Result<Response<SubmitSummary>, Status> {
+        let batch = request.into_inner();
+        let mut shared_metrics = Vec::with_capacity(batch.metrics.len());
+
+        for (index, metric) in batch.metrics.into_iter().enumerate() {
+            let shared_metric = SharedMetricPoint {
+                timestamp: metric.timestamp,
+                hostname: metric.hostname,
+                cpu_percent: metric.cpu_percent,
+                memory_bytes: metric.memory_bytes,
+                disk_io_ops: metric.disk_io_ops,
+                tags: metric.tags,
+            };
+            shared_metric
+                .validate()
+                .map_err(|e| Status::invalid_argument(format!("metric {}: {}", index, e)))?;
+            shared_metrics.push(shared_metric);
+        }
+
+        match self.storage.store_metrics(shared_metrics).await {
+            Ok(accepted) => Ok(Response::new(SubmitSummary { accepted: accepted as u64 })),
+            Err(_) => Err(Status::internal("Failed to store metrics")),
+        }
+    }
+
     type QueryMetricsStream = 
         tokio_stream::wrappers::ReceiverStream<Result<MetricPoint, Status>>;
 
@@ -52,6 +132,7 @@ impl MetricsService for MetricsServiceImpl {
         &self,
         request: Request<MetricQuery>,
     ) -> Result<Response<Self::QueryMetricsStream>, Status> {
+        let deadline = deadline::from_request(&request);
         let query = request.into_inner();
         
         // Convert protobuf query to shared query
@@ -59,27 +140,158 @@ impl MetricsService for MetricsServiceImpl {
             start_time: query.start_time,
             end_time: query.end_time,
             hostname_filter: query.hostname_filter,
+            limit: query.limit,
+            offset: query.offset,
         };
+        shared_query.validate().map_err(|e| Status::invalid_argument(e.to_string()))?;
 
-        let metrics = self.storage.query_metrics(&shared_query)
+        let metrics = self.storage.query_metrics(&shared_query).await
             .map_err(|_| Status::internal("Failed to query metrics"))?;
 
         let (tx, rx) = tokio::sync::mpsc::channel(128);
         
         tokio::spawn(async move {
-            for metric in metrics {
-                // Convert shared MetricPoint to protobuf MetricPoint
-                let proto_metric = MetricPoint {
-                    timestamp: metric.timestamp,
-                    hostname: metric.hostname,
-                    cpu_percent: metric.cpu_percent,
-                    memory_bytes: metric.memory_bytes,
-                    disk_io_ops: metric.disk_io_ops,
-                    tags: metric.tags,
-                };
-                
-                if tx.send(Ok(proto_metric)).await.is_err() {
-                    break;
+            let send_all = async {
+                for metric in metrics {
+                    // Convert shared MetricPoint to protobuf MetricPoint
+                    let proto_metric = MetricPoint {
+                        timestamp: metric.timestamp,
+                        hostname: metric.hostname,
+                        cpu_percent: metric.cpu_percent,
+                        memory_bytes: metric.memory_bytes,
+                        disk_io_ops: metric.disk_io_ops,
+                        tags: metric.tags,
+                    };
+                    
+                    if tx.send(Ok(proto_metric)).await.is_err() {
+                        break;
+                    }
+                }
+            };
+
+            // End with an error rather than a silently truncated result
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, send_all).await.is_err() {
+                        let _ = tx.send(Err(Status::deadline_exceeded("Deadline exceeded while streaming metrics"))).await;
+                    }
+                }
+                None => send_all.await,
+            }
+        });
+
+        Ok(Response::new(tokio_stream::wrappers::ReceiverStream::new(rx)))
+    }
+
+    async fn query_metrics_unary(
+        &self,
+        request: Request<MetricQuery>,
+    ) -> Result<Response<MetricPointList>, Status> {
+        let query = request.into_inner();
+        
+        // Convert protobuf query to shared query
+        let shared_query = SharedMetricQuery {
+            start_time: query.start_time,
+            end_time: query.end_time,
+            hostname_filter: query.hostname_filter,
+            limit: query.limit,
+            offset: query.offset,
+        };
+        shared_query.validate().map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let metrics = self.storage.query_metrics(&shared_query).await
+            .map_err(|_| Status::internal("Failed to query metrics"))?;
+
+        // Convert shared MetricPoints to protobuf MetricPoints
+        let metrics = metrics
+            .into_iter()
+            .map(|metric| MetricPoint {
+                timestamp: metric.timestamp,
+                hostname: metric.hostname,
+                cpu_percent: metric.cpu_percent,
+                memory_bytes: metric.memory_bytes,
+                disk_io_ops: metric.disk_io_ops,
+                tags: metric.tags,
+            })
+            .collect();
+
+        Ok(Response::new(MetricPointList { metrics }))
+    }
+
+    type SubscribeStream =
+        tokio_stream::wrappers::ReceiverStream<Result<MetricPoint, Status>>;
+
+    /// Live tail. Nothing is delivered until the first query arrives; each
+    /// later query replaces the filter, and the stream stays open after the
+    /// client stops sending queries.
+    async fn subscribe(
+        &self,
+        request: Request<tonic::Streaming<MetricQuery>>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let deadline = deadline::from_request(&request);
+        let mut queries = request.into_inner();
+        // Subscribe before returning so nothing stored after the response
+        // headers reach the client is missed
+        let mut subscription = self.storage.subscribe();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(128);
+
+        tokio::spawn(async move {
+            let mut filter: Option<SharedMetricQuery> = None;
+            let mut queries_open = true;
+            // A deadline bounds the whole subscription
+            let expiry = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::pin!(expiry);
+
+            loop {
+                tokio::select! {
+                    query = queries.message(), if queries_open => match query {
+                        Ok(Some(query)) => {
+                            let query = SharedMetricQuery {
+                                start_time: query.start_time,
+                                end_time: query.end_time,
+                                hostname_filter: query.hostname_filter,
+                                limit: None,
+                                offset: None,
+                            };
+                            if let Err(e) = query.validate() {
+                                let _ = tx.send(Err(Status::invalid_argument(e.to_string()))).await;
+                                break;
+                            }
+                            filter = Some(query);
+                        }
+                        Ok(None) => queries_open = false,
+                        Err(_) => break,
+                    },
+                    metric = subscription.recv(), if filter.is_some() => {
+                        let Some(metric) = metric else { break };
+                        if !filter.as_ref().is_some_and(|filter| filter.matches(&metric)) {
+                            continue;
+                        }
+
+                        let proto_metric = MetricPoint {
+                            timestamp: metric.timestamp,
+                            hostname: metric.hostname,
+                            cpu_percent: metric.cpu_percent,
+                            memory_bytes: metric.memory_bytes,
+                            disk_io_ops: metric.disk_io_ops,
+                            tags: metric.tags,
+                        };
+
+                        if tx.send(Ok(proto_metric)).await.is_err() {
+                            break;
+                        }
+                    },
+                    _ = &mut expiry => {
+                        let _ = tx.send(Err(Status::deadline_exceeded("Subscription deadline reached"))).await;
+                        break;
+                    },
+                    _ = tx.closed() => break,
                 }
             }
         });
@@ -98,9 +310,12 @@ impl MetricsService for MetricsServiceImpl {
             start_time: query.start_time,
             end_time: query.end_time,
             hostname_filter: query.hostname_filter,
+            limit: query.limit,
+            offset: query.offset,
         };
+        shared_query.validate().map_err(|e| Status::invalid_argument(e.to_string()))?;
 
-        let stats = self.storage.calculate_statistics(&shared_query)
+        let stats = self.storage.calculate_statistics(&shared_query).await
             .map_err(|_| Status::internal("Failed to calculate statistics"))?;
 
         // Convert shared statistics to protobuf statistics
@@ -114,20 +329,206 @@ impl MetricsService for MetricsServiceImpl {
 
         Ok(Response::new(proto_stats))
     }
+
+    async fn query_rollups(
+        &self,
+        request: Request<MetricQuery>,
+    ) -> Result<Response<MetricRollupList>, Status> {
+        let query = request.into_inner();
+        
+        // Convert protobuf query to shared query
+        let shared_query = SharedMetricQuery {
+            start_time: query.start_time,
+            end_time: query.end_time,
+            hostname_filter: query.hostname_filter,
+            limit: query.limit,
+            offset: query.offset,
+        };
+        shared_query.validate().map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let rollups = self.storage.query_rollups(&shared_query).await
+            .map_err(|_| Status::internal("Failed to query rollups"))?;
+
+        // Convert shared rollups to protobuf rollups
+        let rollups = rollups
+            .into_iter()
+            .map(|rollup| MetricRollup {
+                hostname: rollup.hostname,
+                minute_start: rollup.minute_start,
+                count: rollup.count,
+                avg_cpu_percent: rollup.avg_cpu_percent,
+                min_cpu_percent: rollup.min_cpu_percent,
+                max_cpu_percent: rollup.max_cpu_percent,
+                avg_memory_bytes: rollup.avg_memory_bytes,
+                min_memory_bytes: rollup.min_memory_bytes,
+                max_memory_bytes: rollup.max_memory_bytes,
+                avg_disk_io_ops: rollup.avg_disk_io_ops,
+                min_disk_io_ops: rollup.min_disk_io_ops,
+                max_disk_io_ops: rollup.max_disk_io_ops,
+            })
+            .collect();
+
+        Ok(Response::new(MetricRollupList { rollups }))
+    }
+
+    async fn delete_metrics(
+        &self,
+        request: Request<MetricQuery>,
+    ) -> Result<Response<DeleteSummary>, Status> {
+        let query = request.into_inner();
+        
+        // Convert protobuf query to shared query
+        let shared_query = SharedMetricQuery {
+            start_time: query.start_time,
+            end_time: query.end_time,
+            hostname_filter: query.hostname_filter,
+            limit: query.limit,
+            offset: query.offset,
+        };
+        shared_query.validate().map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let deleted = self.storage.delete_metrics(&shared_query).await
+            .map_err(|_| Status::internal("Failed to delete metrics"))?;
+
+        Ok(Response::new(DeleteSummary { deleted }))
+    }
+
+    async fn get_storage_stats(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<StorageStats>, Status> {
+        let stats = self.storage.storage_stats().await
+            .map_err(|_| Status::internal("Failed to read storage stats"))?;
+
+        Ok(Response::new(StorageStats {
+            point_count: stats.point_count,
+            approx_heap_bytes: stats.approx_heap_bytes,
+            rollup_bucket_count: stats.rollup_bucket_count,
+            rollup_heap_bytes: stats.rollup_heap_bytes,
+        }))
+    }
+}
+
+/// Requires `authorization: Bearer <token>` metadata on every call
+#[derive(Clone)]
+struct AuthInterceptor {
+    auth: AuthConfig,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let authorized = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| self.auth.verify_bearer(value));
+
+        if authorized {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("Missing or invalid credentials"))
+        }
+    }
+}
+
+/// The tonic codecs for the enabled encodings; gRPC has no brotli, so `br` is ignored
+fn compression_encodings(compression: HttpCompression) -> Vec<CompressionEncoding> {
+    [(compression.gzip, CompressionEncoding::Gzip), (compression.zstd, CompressionEncoding::Zstd)]
+        .into_iter()
+        .filter_map(|(enabled, encoding)| enabled.then_some(encoding))
+        .collect()
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let storage = Arc::new(InMemoryStorage::new());
-    let service = MetricsServiceImpl::new(storage);
+    let tracing_guard = shared::init_tracing_from_env("grpc-service")?;
+    let storage = Arc::new(InMemoryStorage::from_env().await?);
+    storage.spawn_eviction_task();
+    let service = MetricsServiceImpl::new(storage.clone());
 
-    let addr = "127.0.0.1:50051".parse()?;
+    let addr = shared::bind_addr("PROTOBENCH_GRPC_ADDR", "127.0.0.1:50051")?;
     println!("gRPC service listening on {}", addr);
 
-    Server::builder()
-        .add_service(MetricsServiceServer::new(service))
-        .serve(addr)
-        .await?;
+    let telemetry = ServiceMetrics::spawn_exporter_from_env("grpc", "PROTOBENCH_GRPC_METRICS_ADDR")?
+        .map(|metrics| telemetry::TelemetryLayer { metrics });
+    let mut builder = Server::builder();
+    if let Some(timeout) = deadline::server_timeout_from_env() {
+        println!("gRPC server timeout: {:?}", timeout);
+        builder = builder.timeout(timeout);
+    }
+    if let Some(tls) = tls::TlsConfig::from_env() {
+        println!("gRPC transport: {}", tls.label());
+        builder = builder.tls_config(tls.server_config()?)?;
+    }
+    if shared::request_log_enabled() {
+        println!("gRPC request log: stderr");
+    }
+    let cipher = PayloadCipher::from_env()?;
+    if cipher.is_some() {
+        println!("gRPC payload encryption: {}", PayloadCipher::NAME);
+    }
+    let trace = (tracing_guard.is_some() || shared::request_log_enabled()).then_some(telemetry::TraceLayer);
+    // Innermost, so telemetry and traces record injected faults like real ones
+    let faults = FaultInjection::from_env().map(|faults| {
+        println!("gRPC fault injection: {}", faults.label());
+        faults::FaultLayer { faults }
+    });
+    let mut server = builder
+        .layer(tower::util::option_layer(trace))
+        .layer(tower::util::option_layer(telemetry))
+        .layer(tower::util::option_layer(faults));
+
+    // Health and reflection sit beside MetricsService rather than behind its
+    // interceptor, so probes and grpcurl work without credentials
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter.set_serving::<MetricsServiceServer<MetricsServiceImpl>>().await;
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(metrics::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build()?;
+    let router = server.add_service(health_service).add_service(reflection_service);
+
+    let shutdown = async move {
+        shared::shutdown_signal().await;
+        // Report NOT_SERVING while draining so health-checking clients stop
+        // routing here
+        health_reporter.set_not_serving::<MetricsServiceServer<MetricsServiceImpl>>().await;
+        // Open subscriptions never finish on their own, so end them before draining
+        storage.close_subscriptions();
+    };
+
+    // Off by default so the baseline measures raw protobuf; responses are only
+    // compressed for clients that list the encoding in grpc-accept-encoding
+    let compression = HttpCompression::from_env("PROTOBENCH_GRPC_COMPRESSION");
+    let mut metrics_server = MetricsServiceServer::new(service);
+    if let Some(limit) = shared::max_message_bytes("PROTOBENCH_GRPC_MAX_MESSAGE_BYTES") {
+        println!("gRPC message size limit: {} bytes", limit);
+        metrics_server = metrics_server.max_decoding_message_size(limit).max_encoding_message_size(limit);
+    }
+    if compression.is_enabled() {
+        println!("gRPC compression: {}", compression.label());
+        for encoding in compression_encodings(compression) {
+            metrics_server = metrics_server.send_compressed(encoding).accept_compressed(encoding);
+        }
+    }
+
+    // No interceptor at all when auth is off, so the baseline pays nothing for it
+    match AuthConfig::from_env() {
+        Some(auth) => {
+            println!("gRPC authentication: required");
+            router
+                .add_service(SealedGrpc::server(InterceptedService::new(metrics_server, AuthInterceptor { auth }), cipher))
+                .serve_with_shutdown(addr, shutdown)
+                .await?;
+        }
+        None => {
+            router
+                .add_service(SealedGrpc::server(metrics_server, cipher))
+                .serve_with_shutdown(addr, shutdown)
+                .await?;
+        }
+    }
 
+    println!("gRPC service stopped");
     Ok(())
 }
@@ -0,0 +1,134 @@
+use shared::ServiceMetrics;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::codegen::{http, Service};
+use tracing::Instrument;
+
+/// Records every RPC in `ServiceMetrics`. A tower layer rather than a tonic
+/// interceptor, since interceptors only see the request and can't time the
+/// call or read its status; wrapping the whole server also counts calls the
+/// auth interceptor rejects.
+#[derive(Clone)]
+pub struct TelemetryLayer {
+    pub metrics: Arc<ServiceMetrics>,
+}
+
+impl<S> tower::Layer<S> for TelemetryLayer {
+    type Service = TelemetryService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TelemetryService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TelemetryService<S> {
+    inner: S,
+    metrics: Arc<ServiceMetrics>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for TelemetryService<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        // Paths look like /protobench.metrics.MetricsService/SubmitMetric
+        let method = request.uri().path().rsplit('/').next().unwrap_or_default().to_string();
+        let mut timer = self.metrics.start(method);
+
+        // The clone that was driven to readiness must be the one called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let result = timer.track(inner.call(request)).await;
+            match &result {
+                // Errors returned before any message are "trailers-only" responses with
+                // grpc-status in the headers; successful calls send it later in trailers
+                Ok(response) => timer.finish(
+                    response
+                        .headers()
+                        .get("grpc-status")
+                        .and_then(|status| status.to_str().ok())
+                        .unwrap_or("0"),
+                ),
+                Err(_) => timer.finish("transport_error"),
+            }
+            result
+        })
+    }
+}
+
+/// Runs every RPC inside a span continuing the `traceparent` the client sent
+/// in its metadata and tagged with its `x-request-id`, then logs the RPC if
+/// the request log is on. Like `TelemetryLayer` it wraps the whole server, so
+/// the span and the logged time cover decoding, auth and encoding as well as
+/// the handler.
+#[derive(Clone)]
+pub struct TraceLayer;
+
+impl<S> tower::Layer<S> for TraceLayer {
+    type Service = TraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct TraceService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for TraceService<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let method = request.uri().path().rsplit('/').next().unwrap_or_default().to_string();
+        let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok());
+        let request_id = header(shared::REQUEST_ID).and_then(shared::valid_request_id).map(str::to_string);
+        let span = tracing::info_span!("grpc.request", method = %method, request_id = request_id.as_deref().unwrap_or_default());
+        shared::set_remote_parent(&span, header(shared::TRACEPARENT));
+
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let started = Instant::now();
+        let call = span.in_scope(|| inner.call(request)).instrument(span);
+        Box::pin(async move {
+            let result = call.await;
+            let status = match &result {
+                Ok(response) => response.headers().get("grpc-status").and_then(|status| status.to_str().ok()).unwrap_or("0"),
+                Err(_) => "transport_error",
+            };
+            shared::log_request("grpc", &method, request_id.as_deref(), status, started.elapsed());
+            result
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+/// PEM certificate and key for TLS, from `PROTOBENCH_GRPC_TLS_CERT` and
+/// `PROTOBENCH_GRPC_TLS_KEY`; TLS is enabled only when both are set. Setting
+/// `PROTOBENCH_GRPC_TLS_CLIENT_CA` as well turns on mutual TLS: clients must
+/// present a certificate signed by that CA.
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    pub client_ca_path: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Option<Self> {
+        let cert_path = std::env::var("PROTOBENCH_GRPC_TLS_CERT").ok()?;
+        let key_path = std::env::var("PROTOBENCH_GRPC_TLS_KEY").ok()?;
+
+        Some(Self {
+            cert_path: PathBuf::from(cert_path),
+            key_path: PathBuf::from(key_path),
+            client_ca_path: std::env::var("PROTOBENCH_GRPC_TLS_CLIENT_CA").ok().map(PathBuf::from),
+        })
+    }
+
+    pub fn label(&self) -> &'static str {
+        if self.client_ca_path.is_some() {
+            "mutual TLS"
+        } else {
+            "TLS"
+        }
+    }
+
+    /// Load the certificate and key, plus the client CA for mutual TLS. When
+    /// neither server file exists yet a self-signed certificate is generated
+    /// there first, as `rest-service` does. Client certificates are never
+    /// generated; mutual TLS needs a CA the clients' certificates chain to.
+    pub fn server_config(&self) -> anyhow::Result<ServerTlsConfig> {
+        if !self.cert_path.exists() && !self.key_path.exists() {
+            generate_self_signed(&self.cert_path, &self.key_path)?;
+            println!("Generated self-signed certificate at {}", self.cert_path.display());
+        }
+
+        let identity = Identity::from_pem(std::fs::read(&self.cert_path)?, std::fs::read(&self.key_path)?);
+        let mut config = ServerTlsConfig::new().identity(identity);
+        if let Some(path) = &self.client_ca_path {
+            config = config.client_ca_root(Certificate::from_pem(std::fs::read(path)?));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Write a self-signed certificate valid for `localhost` and `127.0.0.1`,
+/// which `grpc_client` can trust via `PROTOBENCH_GRPC_CA_CERT`
+fn generate_self_signed(cert_path: &Path, key_path: &Path) -> anyhow::Result<()> {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string(), "127.0.0.1".to_string()])?;
+
+    std::fs::write(cert_path, cert.pem())?;
+    std::fs::write(key_path, key_pair.serialize_pem())?;
+    Ok(())
+}
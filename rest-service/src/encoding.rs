@@ -0,0 +1,134 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+use shared::{arrow_ipc, BodyEncoding, MetricPoint};
+
+use crate::error::AppError;
+
+/// Pick the response encoding from `Accept`, honouring the client's order but
+/// ignoring q-values. No header, or a wildcard, means JSON.
+pub fn from_accept(headers: &HeaderMap) -> Result<BodyEncoding, AppError> {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|value| value.to_str().ok()) else {
+        return Ok(BodyEncoding::Json);
+    };
+
+    for media_type in accept.split(',').map(|part| part.split(';').next().unwrap_or("").trim()) {
+        if media_type == "*/*" || media_type == "application/*" {
+            return Ok(BodyEncoding::Json);
+        }
+        if let Some(encoding) = BodyEncoding::from_media_type(media_type) {
+            return Ok(encoding);
+        }
+    }
+
+    Err(AppError::new(StatusCode::NOT_ACCEPTABLE, "not_acceptable", format!("Cannot produce any of: {}", accept))
+        .with_details(json!({ "supported": supported() })))
+}
+
+/// Whether `Accept` asks for an Arrow IPC stream before any `BodyEncoding`
+/// or wildcard. Only `GET /metrics` offers one; every other route answers
+/// per `from_accept`.
+pub fn accepts_arrow(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    accept
+        .split(',')
+        .map(|part| part.split(';').next().unwrap_or("").trim())
+        .find(|media_type| {
+            *media_type == arrow_ipc::ARROW_STREAM
+                || *media_type == "*/*"
+                || *media_type == "application/*"
+                || BodyEncoding::from_media_type(media_type).is_some()
+        })
+        == Some(arrow_ipc::ARROW_STREAM)
+}
+
+/// Request body encoding from `Content-Type`; a missing header means JSON
+fn from_content_type(headers: &HeaderMap) -> Result<BodyEncoding, AppError> {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()) else {
+        return Ok(BodyEncoding::Json);
+    };
+
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    BodyEncoding::from_media_type(media_type).ok_or_else(|| {
+        AppError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            format!("Unsupported content type: {}", content_type),
+        )
+        .with_details(json!({ "supported": supported() }))
+    })
+}
+
+fn supported() -> Vec<&'static str> {
+    BodyEncoding::ALL.iter().map(|encoding| encoding.content_type()).collect()
+}
+
+/// Request body decoded according to its `Content-Type`
+pub struct Negotiated<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Negotiated<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let encoding = from_content_type(request.headers())?;
+        let bytes = Bytes::from_request(request, state)
+            .await
+            .map_err(|rejection| {
+                let code = match rejection.status() {
+                    StatusCode::PAYLOAD_TOO_LARGE => "body_too_large",
+                    _ => "invalid_body",
+                };
+                AppError::new(rejection.status(), code, rejection.body_text())
+            })?;
+
+        encoding.decode(&bytes).map(Negotiated).map_err(|e| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_body",
+                format!("Failed to decode {} body: {}", encoding.content_type(), e),
+            )
+        })
+    }
+}
+
+/// Query results as an Arrow IPC stream, for clients that asked for one
+pub struct Columnar(pub Vec<MetricPoint>);
+
+impl IntoResponse for Columnar {
+    fn into_response(self) -> Response {
+        match arrow_ipc::encode(&self.0) {
+            Ok(body) => ([(header::CONTENT_TYPE, HeaderValue::from_static(arrow_ipc::ARROW_STREAM))], body).into_response(),
+            Err(e) => AppError::internal("Failed to encode response", e).into_response(),
+        }
+    }
+}
+
+/// Response body serialized in the encoding picked by `from_accept`
+pub struct Encoded<T>(pub BodyEncoding, pub T);
+
+impl<T: Serialize> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        let Encoded(encoding, value) = self;
+        match encoding.encode(&value) {
+            Ok(body) => (
+                [(header::CONTENT_TYPE, HeaderValue::from_static(encoding.content_type()))],
+                body,
+            )
+                .into_response(),
+            Err(e) => AppError::internal("Failed to encode response", e).into_response(),
+        }
+    }
+}
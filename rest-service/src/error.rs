@@ -0,0 +1,68 @@
+use axum::{
+    extract::rejection::QueryRejection,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use serde_json::json;
+use std::fmt::Display;
+use utoipa::ToSchema;
+
+/// Body of every non-2xx response, published as the `Error` schema
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = Error)]
+pub struct ErrorBody {
+    /// Stable, machine-readable identifier such as `invalid_metric`
+    code: &'static str,
+    /// Human-readable description
+    message: String,
+    /// Structured context for the error, e.g. which batch entry was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
+}
+
+/// Error returned by handlers and middleware, rendered as an `ErrorBody`
+#[derive(Debug)]
+pub struct AppError {
+    status: StatusCode,
+    body: ErrorBody,
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: ErrorBody {
+                code,
+                message: message.into(),
+                details: None,
+            },
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.body.details = Some(details);
+        self
+    }
+
+    /// A storage failure; the cause goes in `details` so it reaches the client
+    pub fn internal(message: impl Into<String>, cause: impl Display) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", message)
+            .with_details(json!({ "cause": cause.to_string() }))
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+// Extractor rejections otherwise answer in plain text; handlers take
+// `Result<Query<_>, QueryRejection>` and convert with `?`. Bodies go through
+// `encoding::Negotiated`, which reports its own errors.
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), "invalid_query", rejection.body_text())
+    }
+}
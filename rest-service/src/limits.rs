@@ -0,0 +1,89 @@
+use axum::{
+    error_handling::HandleErrorLayer,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError, Router,
+};
+use serde_json::json;
+use std::time::Duration;
+use tower::{load_shed::error::Overloaded, ServiceBuilder};
+
+use crate::error::AppError;
+
+// Requests waiting for the rate limiter's worker; past this the buffer itself
+// applies backpressure, but the load shedder behind it answers immediately
+const RATE_LIMIT_BUFFER: usize = 1024;
+
+/// Request throttling, from `PROTOBENCH_REST_RATE_LIMIT` (requests per second)
+/// and `PROTOBENCH_REST_CONCURRENCY_LIMIT` (requests in flight). Requests over
+/// either limit are shed with `429 Too Many Requests` rather than queued.
+pub struct LimitConfig {
+    pub rate_per_second: Option<u64>,
+    pub max_concurrent: Option<usize>,
+}
+
+impl LimitConfig {
+    /// Returns `None` when neither limit is set
+    pub fn from_env() -> Option<Self> {
+        let rate_per_second = std::env::var("PROTOBENCH_REST_RATE_LIMIT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&rate| rate > 0);
+        let max_concurrent = std::env::var("PROTOBENCH_REST_CONCURRENCY_LIMIT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&max| max > 0);
+
+        if rate_per_second.is_none() && max_concurrent.is_none() {
+            return None;
+        }
+        Some(Self { rate_per_second, max_concurrent })
+    }
+
+    pub fn label(&self) -> String {
+        let rate = self.rate_per_second.map_or("unlimited".to_string(), |rate| format!("{}/s", rate));
+        let concurrent = self.max_concurrent.map_or("unlimited".to_string(), |max| max.to_string());
+        format!("rate {}, concurrency {}", rate, concurrent)
+    }
+
+    /// Wrap the whole router in the configured limits. `Router::layer` would
+    /// give every route its own limiter, so the limited router is mounted as
+    /// the fallback of an empty one to share a single budget across routes.
+    pub fn apply(&self, app: Router) -> Router {
+        let mut app = app;
+
+        if let Some(rate) = self.rate_per_second {
+            // RateLimit isn't Clone, so it sits behind a Buffer that every
+            // request clone shares
+            let limited = ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(shed))
+                .buffer(RATE_LIMIT_BUFFER)
+                .load_shed()
+                .rate_limit(rate, Duration::from_secs(1))
+                .service(app);
+            app = Router::new().fallback_service(limited);
+        }
+
+        if let Some(max) = self.max_concurrent {
+            let limited = ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(shed))
+                .load_shed()
+                .concurrency_limit(max)
+                .service(app);
+            app = Router::new().fallback_service(limited);
+        }
+
+        app
+    }
+}
+
+/// Both limits reset within a second, which is what `Retry-After` advertises
+async fn shed(error: BoxError) -> Response {
+    if error.is::<Overloaded>() {
+        let error = AppError::new(StatusCode::TOO_MANY_REQUESTS, "rate_limited", "Request limit exceeded, retry later")
+            .with_details(json!({ "retry_after_seconds": 1 }));
+        ([(header::RETRY_AFTER, "1")], error).into_response()
+    } else {
+        AppError::internal("Request limiter failed", error).into_response()
+    }
+}
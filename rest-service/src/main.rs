@@ -1,19 +1,147 @@
 use axum::{
-    extract::Query,
-    http::StatusCode,
-    response::Json,
+    body::{Body, Bytes},
+    extract::{rejection::QueryRejection, DefaultBodyLimit, Query, Request},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
     routing::{get, post},
     Router,
 };
-use serde::Deserialize;
-use shared::{InMemoryStorage, MetricPoint, MetricQuery, MetricStatistics};
+use serde::{Deserialize, Serialize};
+use shared::{AuthConfig, BodyEncoding, Fault, FaultInjection, HttpCompression, InMemoryStorage, MetricPoint, MetricQuery, MetricRollup, MetricStatistics, MetricsStorage, PayloadCipher, ServiceMetrics, StorageStats};
+use std::collections::HashSet;
+use std::convert::Infallible;
 use std::sync::Arc;
+use std::time::Instant;
+use tower_http::compression::CompressionLayer;
+use tracing::Instrument;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    IntoParams, Modify, OpenApi, ToSchema,
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+use encoding::{Columnar, Encoded, Negotiated};
+use error::{AppError, ErrorBody};
+
+mod encoding;
+mod error;
+mod limits;
+mod sealed;
+mod tls;
 
-#[derive(Debug, Deserialize)]
+// Instrumented so the stats endpoint can report what each request allocated
+#[global_allocator]
+static GLOBAL: shared::CountingAllocator = shared::CountingAllocator::new();
+
+/// axum's request body limit when `PROTOBENCH_REST_MAX_BODY_BYTES` is unset
+const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct QueryParams {
+    /// Unix seconds, inclusive; milliseconds are rejected
     start_time: i64,
+    /// Unix seconds, inclusive
     end_time: i64,
     hostname_filter: Option<String>,
+    /// Maximum number of metrics to return
+    limit: Option<u32>,
+    /// Number of matching metrics to skip
+    offset: Option<u32>,
+}
+
+impl QueryParams {
+    /// The query the parameters describe, once its bounds are checked
+    fn into_query(self) -> Result<MetricQuery, AppError> {
+        let query = MetricQuery {
+            start_time: self.start_time,
+            end_time: self.end_time,
+            hostname_filter: self.hostname_filter,
+            limit: self.limit,
+            offset: self.offset,
+        };
+        query
+            .validate()
+            .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, "invalid_query", e.to_string()))?;
+        Ok(query)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct BatchSummary {
+    accepted: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DeleteSummary {
+    deleted: u64,
+}
+
+/// Spec served at `/openapi.json`, generated from the handler annotations below
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "ProtoBench Metrics API", description = "REST API for metrics collection and querying"),
+    paths(
+        submit_metric,
+        submit_metrics,
+        query_metrics,
+        stream_metrics,
+        subscribe_metrics,
+        get_statistics,
+        query_rollups,
+        delete_metrics,
+        get_storage_stats,
+    ),
+    components(schemas(
+        MetricPoint,
+        MetricStatistics,
+        MetricRollup,
+        StorageStats,
+        BatchSummary,
+        DeleteSummary,
+        ErrorBody,
+    )),
+    modifiers(&SecuritySchemes, &BodyEncodings),
+    security((), ("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+struct ApiDoc;
+
+// Credentials are only enforced when the service runs with PROTOBENCH_AUTH_TOKEN
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearerAuth",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "apiKeyAuth",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+    }
+}
+
+// utoipa takes a single request body content type, so the JSON entry is copied
+// to every other encoding `Negotiated` accepts
+struct BodyEncodings;
+
+impl Modify for BodyEncodings {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let operations = openapi.paths.paths.values_mut().flat_map(|item| item.operations.values_mut());
+        for body in operations.filter_map(|operation| operation.request_body.as_mut()) {
+            if let Some(json) = body.content.get(BodyEncoding::Json.content_type()).cloned() {
+                for encoding in BodyEncoding::ALL {
+                    body.content.entry(encoding.content_type().to_string()).or_insert_with(|| json.clone());
+                }
+            }
+        }
+    }
 }
 
 // Application dependency container - equivalent to Spring's @Autowired beans.
@@ -25,59 +153,486 @@ struct AppState {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let storage = Arc::new(InMemoryStorage::new());
-    let app_state = Arc::new(AppState { storage });
+    let tracing_guard = shared::init_tracing_from_env("rest-service")?;
+    let storage = Arc::new(InMemoryStorage::from_env().await?);
+    storage.spawn_eviction_task();
+    let app_state = Arc::new(AppState { storage: storage.clone() });
 
     let app = Router::new()
-        .route("/metrics", post(submit_metric).get(query_metrics))
+        .route("/metrics", post(submit_metric).get(query_metrics).delete(delete_metrics))
+        .route("/metrics/batch", post(submit_metrics))
+        .route("/metrics/stream", get(stream_metrics))
+        .route("/metrics/subscribe", get(subscribe_metrics))
         .route("/statistics", get(get_statistics))
+        .route("/rollups", get(query_rollups))
+        .route("/admin/storage", get(get_storage_stats))
         .with_state(app_state);
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
-    println!("REST service listening on http://127.0.0.1:3000");
+    // Innermost, so every other layer sees an injected fault as the handler's own
+    let app = match FaultInjection::from_env() {
+        Some(faults) => {
+            println!("REST fault injection: {}", faults.label());
+            app.layer(middleware::from_fn(move |request: Request, next: Next| inject_fault(faults, request, next)))
+        }
+        None => app,
+    };
+
+    // Batches larger than axum's 2 MiB default are rejected with 413 unless raised
+    let app = match shared::max_message_bytes("PROTOBENCH_REST_MAX_BODY_BYTES") {
+        Some(limit) => {
+            println!("REST request body limit: {} bytes", limit);
+            app.layer(DefaultBodyLimit::max(limit))
+        }
+        None => app,
+    };
+
+    // Inside compression, which then only sees ciphertext, and outside the
+    // body limit, which applies to the opened body
+    let app = match PayloadCipher::from_env()? {
+        Some(cipher) => {
+            println!("REST payload encryption: {}", PayloadCipher::NAME);
+            let body_limit = shared::max_message_bytes("PROTOBENCH_REST_MAX_BODY_BYTES").unwrap_or(DEFAULT_BODY_LIMIT);
+            app.layer(middleware::from_fn(move |request: Request, next: Next| {
+                let cipher = cipher.clone();
+                async move { sealed::seal_payloads(&cipher, body_limit, request, next).await }
+            }))
+        }
+        None => app,
+    };
+
+    // Off by default so the baseline measures raw serialization; the layer negotiates
+    // against each request's Accept-Encoding among the enabled algorithms
+    let compression = HttpCompression::from_env("PROTOBENCH_REST_COMPRESSION");
+    let app = if compression.is_enabled() {
+        println!("REST response compression: {}", compression.label());
+        app.layer(
+            CompressionLayer::new()
+                .gzip(compression.gzip)
+                .br(compression.br)
+                .zstd(compression.zstd)
+                .no_deflate(),
+        )
+    } else {
+        app
+    };
+
+    // Throttling sits inside auth so unauthenticated requests don't spend the budget
+    let app = match limits::LimitConfig::from_env() {
+        Some(limits) => {
+            println!("REST request limits: {}", limits.label());
+            limits.apply(app)
+        }
+        None => app,
+    };
+
+    let app = match AuthConfig::from_env() {
+        Some(auth) => {
+            println!("REST authentication: required");
+            app.layer(middleware::from_fn(move |request: Request, next: Next| {
+                let auth = auth.clone();
+                async move { require_auth(&auth, request, next).await }
+            }))
+        }
+        None => app,
+    };
+
+    // Outermost, so requests rejected by auth or the limits are counted too
+    let app = match ServiceMetrics::spawn_exporter_from_env("rest", "PROTOBENCH_REST_METRICS_ADDR")? {
+        Some(telemetry) => {
+            // Label by the routes in the spec; any other path shares one label
+            // so stray URLs can't grow the metric cardinality
+            let routes: Arc<HashSet<String>> = Arc::new(ApiDoc::openapi().paths.paths.into_keys().collect());
+            app.layer(middleware::from_fn(move |request: Request, next: Next| {
+                let telemetry = telemetry.clone();
+                let routes = routes.clone();
+                async move { record_request(&telemetry, &routes, request, next).await }
+            }))
+        }
+        None => app,
+    };
+
+    if shared::request_log_enabled() {
+        println!("REST request log: stderr");
+    }
+    // Outside the metrics layer so its span covers everything the service does
+    let app = if tracing_guard.is_some() || shared::request_log_enabled() {
+        app.layer(middleware::from_fn(trace_request))
+    } else {
+        app
+    };
+
+    // Added after the auth and limit layers so the docs stay reachable without credentials
+    let app = app.merge(SwaggerUi::new("/swagger-ui").url("/openapi.json", ApiDoc::openapi()));
+
+    let addr = shared::bind_addr("PROTOBENCH_REST_ADDR", "127.0.0.1:3000")?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+
+    // Open subscriptions never finish on their own, so end them before draining
+    let shutdown = async move {
+        shared::shutdown_signal().await;
+        storage.close_subscriptions();
+    };
+    
+    match tls::TlsConfig::from_env() {
+        Some(tls_config) => {
+            let acceptor = tls_config.acceptor()?;
+            println!("REST service listening on https://{}", addr);
+            tls::serve(listener, app, acceptor, shutdown).await?;
+        }
+        None => {
+            println!("REST service listening on http://{}", addr);
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await?;
+        }
+    }
     
-    axum::serve(listener, app).await?;
+    println!("REST service stopped");
     Ok(())
 }
 
+async fn record_request(
+    telemetry: &Arc<ServiceMetrics>,
+    routes: &HashSet<String>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let route = if routes.contains(path) { path } else { "unmatched" };
+    let mut timer = telemetry.start(format!("{} {}", request.method(), route));
+
+    let response = timer.track(next.run(request)).await;
+    timer.finish(response.status().as_str());
+    response
+}
+
+/// Run the request inside a span continuing the client's `traceparent` and
+/// tagged with its `x-request-id`, then log it if the request log is on
+async fn trace_request(request: Request, next: Next) -> Response {
+    // Owned copies, so nothing borrowed from `request` is held across the await
+    // and the future stays `Send`
+    let (request_id, traceparent) = {
+        let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok());
+        (
+            header(shared::REQUEST_ID).and_then(shared::valid_request_id).map(str::to_string),
+            header(shared::TRACEPARENT).map(str::to_string),
+        )
+    };
+    let span = tracing::info_span!(
+        "rest.request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        request_id = request_id.as_deref().unwrap_or_default(),
+    );
+    shared::set_remote_parent(&span, traceparent.as_deref());
+
+    let method = format!("{} {}", request.method(), request.uri().path());
+    let started = Instant::now();
+    let response = next.run(request).instrument(span).await;
+    shared::log_request("rest", &method, request_id.as_deref(), response.status().as_str(), started.elapsed());
+    response
+}
+
+/// Fail, delay or drop the request as `faults` picks
+async fn inject_fault(faults: FaultInjection, request: Request, next: Next) -> Result<Response, AppError> {
+    match faults.pick() {
+        None => Ok(next.run(request).await),
+        Some(Fault::Error) => Err(AppError::new(StatusCode::SERVICE_UNAVAILABLE, "injected_fault", shared::INJECTED_FAULT)),
+        Some(Fault::Delay(delay)) => {
+            let response = next.run(request).await;
+            tokio::time::sleep(delay).await;
+            Ok(response)
+        }
+        // A body that fails makes hyper abort the connection mid-response
+        Some(Fault::Drop) => {
+            let failure = std::io::Error::new(std::io::ErrorKind::ConnectionAborted, shared::INJECTED_FAULT);
+            Ok(Body::from_stream(tokio_stream::once(Err::<Bytes, _>(failure))).into_response())
+        }
+    }
+}
+
+/// Accept either `Authorization: Bearer <token>` or `X-API-Key: <token>`
+async fn require_auth(auth: &AuthConfig, request: Request, next: Next) -> Result<Response, AppError> {
+    let headers = request.headers();
+    let bearer_ok = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| auth.verify_bearer(value));
+    let api_key_ok = headers
+        .get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| auth.verify(value));
+
+    if bearer_ok || api_key_ok {
+        Ok(next.run(request).await)
+    } else {
+        Err(AppError::new(StatusCode::UNAUTHORIZED, "unauthorized", "Missing or invalid credentials"))
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/metrics",
+    request_body(description = "Encoded per `Content-Type`", content = MetricPoint),
+    responses(
+        (status = 201, description = "Metric submitted successfully"),
+        (status = 400, description = "Invalid metric data", body = ErrorBody),
+        (status = 415, description = "Unsupported `Content-Type`", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
 async fn submit_metric(
     axum::extract::State(state): axum::extract::State<Arc<AppState>>,
-    Json(metric): Json<MetricPoint>,
-) -> Result<StatusCode, StatusCode> {
-    match state.storage.store_metric(metric) {
+    Negotiated(metric): Negotiated<MetricPoint>,
+) -> Result<StatusCode, AppError> {
+    metric
+        .validate()
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, "invalid_metric", e.to_string()))?;
+
+    match state.storage.store_metric(metric).await {
         Ok(_) => Ok(StatusCode::CREATED),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Err(AppError::internal("Failed to store metric", e)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/metrics/batch",
+    request_body(description = "Encoded per `Content-Type`", content = Vec<MetricPoint>),
+    responses(
+        (status = 201, description = "Batch stored, encoded per `Accept`", body = BatchSummary, content_type = ["application/json", "application/msgpack", "application/cbor"]),
+        (status = 400, description = "A metric in the batch is invalid; nothing was stored", body = ErrorBody),
+        (status = 406, description = "No supported encoding in `Accept`", body = ErrorBody),
+        (status = 415, description = "Unsupported `Content-Type`", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
+async fn submit_metrics(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    headers: HeaderMap,
+    Negotiated(metrics): Negotiated<Vec<MetricPoint>>,
+) -> Result<(StatusCode, Encoded<BatchSummary>), AppError> {
+    // Checked before storing so an unacceptable request has no side effects
+    let encoding = encoding::from_accept(&headers)?;
+    // A batch is all-or-nothing: one invalid point rejects the whole request
+    for (index, metric) in metrics.iter().enumerate() {
+        metric.validate().map_err(|e| {
+            AppError::new(StatusCode::BAD_REQUEST, "invalid_metric", format!("metric {}: {}", index, e))
+                .with_details(serde_json::json!({ "index": index }))
+        })?;
+    }
+
+    match state.storage.store_metrics(metrics).await {
+        Ok(accepted) => Ok((StatusCode::CREATED, Encoded(encoding, BatchSummary { accepted }))),
+        Err(e) => Err(AppError::internal("Failed to store metrics", e)),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    params(QueryParams),
+    responses(
+        (status = 200, description = "Metrics retrieved successfully, encoded per `Accept`, or as an Arrow IPC stream with one column per field", body = Vec<MetricPoint>, content_type = ["application/json", "application/msgpack", "application/cbor", "application/vnd.apache.arrow.stream"]),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 406, description = "No supported encoding in `Accept`", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
 async fn query_metrics(
     axum::extract::State(state): axum::extract::State<Arc<AppState>>,
-    Query(params): Query<QueryParams>,
-) -> Result<Json<Vec<MetricPoint>>, StatusCode> {
-    let query = MetricQuery {
-        start_time: params.start_time,
-        end_time: params.end_time,
-        hostname_filter: params.hostname_filter,
-    };
+    headers: HeaderMap,
+    params: Result<Query<QueryParams>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(params) = params?;
+    // None for an Arrow stream
+    let encoding = if encoding::accepts_arrow(&headers) { None } else { Some(encoding::from_accept(&headers)?) };
+    let query = params.into_query()?;
 
-    match state.storage.query_metrics(&query) {
-        Ok(metrics) => Ok(Json(metrics)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    match state.storage.query_metrics(&query).await {
+        Ok(metrics) => Ok(match encoding {
+            Some(encoding) => Encoded(encoding, metrics).into_response(),
+            None => Columnar(metrics).into_response(),
+        }),
+        Err(e) => Err(AppError::internal("Failed to query metrics", e)),
     }
 }
 
+/// Same result set as `query_metrics`, written as newline-delimited JSON with
+/// chunked transfer encoding so clients can consume points as they arrive,
+/// mirroring gRPC server streaming
+#[utoipa::path(
+    get,
+    path = "/metrics/stream",
+    params(QueryParams),
+    responses(
+        (status = 200, description = "One JSON MetricPoint per line", body = MetricPoint, content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
+async fn stream_metrics(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    params: Result<Query<QueryParams>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(params) = params?;
+    let query = params.into_query()?;
+
+    let metrics = state.storage.query_metrics(&query).await
+        .map_err(|e| AppError::internal("Failed to query metrics", e))?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Vec<u8>, serde_json::Error>>(128);
+
+    tokio::spawn(async move {
+        for metric in metrics {
+            let line = serde_json::to_vec(&metric).map(|mut line| {
+                line.push(b'\n');
+                line
+            });
+            if tx.send(line).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let body = Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx));
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+/// Live tail as server-sent events: every point stored after the request
+/// arrives that matches the time range and hostname filter is pushed as one
+/// JSON `data` event. `limit` and `offset` are ignored.
+#[utoipa::path(
+    get,
+    path = "/metrics/subscribe",
+    params(QueryParams),
+    responses(
+        (status = 200, description = "One JSON MetricPoint per event", body = MetricPoint, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+    )
+)]
+async fn subscribe_metrics(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    params: Result<Query<QueryParams>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(params) = params?;
+    let query = MetricQuery { limit: None, offset: None, ..params.into_query()? };
+
+    let mut subscription = state.storage.subscribe();
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Event, Infallible>>(128);
+
+    tokio::spawn(async move {
+        loop {
+            let metric = tokio::select! {
+                metric = subscription.recv() => match metric {
+                    Some(metric) => metric,
+                    None => break,
+                },
+                _ = tx.closed() => break,
+            };
+            if !query.matches(&metric) {
+                continue;
+            }
+
+            let event = match Event::default().json_data(&metric) {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Failed to encode live metric: {}", e);
+                    continue;
+                }
+            };
+            if tx.send(Ok(event)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let events = tokio_stream::wrappers::ReceiverStream::new(rx);
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/statistics",
+    params(QueryParams),
+    responses(
+        (status = 200, description = "Statistics calculated successfully", body = MetricStatistics),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
 async fn get_statistics(
     axum::extract::State(state): axum::extract::State<Arc<AppState>>,
-    Query(params): Query<QueryParams>,
-) -> Result<Json<MetricStatistics>, StatusCode> {
-    let query = MetricQuery {
-        start_time: params.start_time,
-        end_time: params.end_time,
-        hostname_filter: params.hostname_filter,
-    };
+    params: Result<Query<QueryParams>, QueryRejection>,
+) -> Result<Json<MetricStatistics>, AppError> {
+    let Query(params) = params?;
+    let query = params.into_query()?;
+
+    match state.storage.calculate_statistics(&query).await {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => Err(AppError::internal("Failed to calculate statistics", e)),
+    }
+}
 
-    match state.storage.calculate_statistics(&query) {
+#[utoipa::path(
+    get,
+    path = "/rollups",
+    params(QueryParams),
+    responses(
+        (status = 200, description = "Per-minute rollups retrieved successfully", body = Vec<MetricRollup>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
+async fn query_rollups(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    params: Result<Query<QueryParams>, QueryRejection>,
+) -> Result<Json<Vec<MetricRollup>>, AppError> {
+    let Query(params) = params?;
+    let query = params.into_query()?;
+
+    match state.storage.query_rollups(&query).await {
+        Ok(rollups) => Ok(Json(rollups)),
+        Err(e) => Err(AppError::internal("Failed to query rollups", e)),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/metrics",
+    params(QueryParams),
+    responses(
+        (status = 200, description = "Matching metrics deleted", body = DeleteSummary),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
+async fn delete_metrics(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    params: Result<Query<QueryParams>, QueryRejection>,
+) -> Result<Json<DeleteSummary>, AppError> {
+    let Query(params) = params?;
+    let query = params.into_query()?;
+
+    match state.storage.delete_metrics(&query).await {
+        Ok(deleted) => Ok(Json(DeleteSummary { deleted })),
+        Err(e) => Err(AppError::internal("Failed to delete metrics", e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/storage",
+    responses(
+        (status = 200, description = "Server-side storage footprint", body = StorageStats),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
+async fn get_storage_stats(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+) -> Result<Json<StorageStats>, AppError> {
+    match state.storage.storage_stats().await {
         Ok(stats) => Ok(Json(stats)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Err(AppError::internal("Failed to read storage stats", e)),
     }
 }
@@ -0,0 +1,55 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use shared::{PayloadCipher, PAYLOAD_CIPHER};
+
+use crate::error::AppError;
+
+// Streamed as they are produced, so never buffered to be sealed
+const STREAMED: [&str; 2] = ["application/x-ndjson", "text/event-stream"];
+
+/// Open each request's sealed body and seal the response's. Every request has
+/// to name the cipher in `x-payload-cipher`, body or not, so a client that
+/// doesn't seal is turned away rather than answered in the clear. Streamed
+/// responses from `/metrics/stream` and `/metrics/subscribe` go out as they
+/// are.
+pub async fn seal_payloads(cipher: &PayloadCipher, body_limit: usize, request: Request, next: Next) -> Result<Response, AppError> {
+    let named = request.headers().get(PAYLOAD_CIPHER).and_then(|value| value.to_str().ok());
+    if named != Some(PayloadCipher::NAME) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "payload_not_sealed",
+            format!("Requests must be sealed with {}", PayloadCipher::NAME),
+        ));
+    }
+
+    let (parts, body) = request.into_parts();
+    let sealed = to_bytes(body, body_limit + PayloadCipher::OVERHEAD)
+        .await
+        .map_err(|e| AppError::new(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", e.to_string()))?;
+    let body = if sealed.is_empty() {
+        Body::empty()
+    } else {
+        let opened = cipher
+            .open(&sealed)
+            .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, "payload_not_sealed", e.to_string()))?;
+        Body::from(opened)
+    };
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    if content_type.is_some_and(|content_type| STREAMED.iter().any(|streamed| content_type.starts_with(streamed))) {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    let plain = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| AppError::internal("Failed to read the response to seal", e))?;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(PAYLOAD_CIPHER, HeaderValue::from_static(PayloadCipher::NAME));
+    Ok(Response::from_parts(parts, Body::from(cipher.seal(&plain))))
+}
@@ -0,0 +1,114 @@
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::graceful::GracefulShutdown;
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{self, pki_types::CertificateDer};
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+
+/// PEM certificate and key for HTTPS, from `PROTOBENCH_REST_TLS_CERT` and
+/// `PROTOBENCH_REST_TLS_KEY`. TLS is enabled only when both are set.
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Option<Self> {
+        let cert_path = std::env::var("PROTOBENCH_REST_TLS_CERT").ok()?;
+        let key_path = std::env::var("PROTOBENCH_REST_TLS_KEY").ok()?;
+
+        Some(Self {
+            cert_path: PathBuf::from(cert_path),
+            key_path: PathBuf::from(key_path),
+        })
+    }
+
+    /// Load the certificate and key into a rustls acceptor advertising HTTP/2
+    /// and HTTP/1.1 over ALPN. When neither file exists yet a self-signed
+    /// certificate is generated there first, so a fresh checkout can run TLS
+    /// benchmarks without any manual setup.
+    pub fn acceptor(&self) -> anyhow::Result<TlsAcceptor> {
+        if !self.cert_path.exists() && !self.key_path.exists() {
+            generate_self_signed(&self.cert_path, &self.key_path)?;
+            println!("Generated self-signed certificate at {}", self.cert_path.display());
+        }
+
+        let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&self.cert_path)?))
+            .collect::<Result<Vec<CertificateDer<'static>>, _>>()?;
+        let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&self.key_path)?))?
+            .ok_or_else(|| anyhow::anyhow!("No private key found in {}", self.key_path.display()))?;
+
+        let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Write a self-signed certificate valid for `localhost` and `127.0.0.1`,
+/// which `rest_client` can trust via `PROTOBENCH_REST_CA_CERT`
+pub fn generate_self_signed(cert_path: &Path, key_path: &Path) -> anyhow::Result<()> {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string(), "127.0.0.1".to_string()])?;
+
+    std::fs::write(cert_path, cert.pem())?;
+    std::fs::write(key_path, key_pair.serialize_pem())?;
+    Ok(())
+}
+
+/// `axum::serve` equivalent that terminates TLS on each accepted connection.
+/// Stops accepting once `shutdown` resolves and returns once open connections
+/// have finished their in-flight requests.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    acceptor: TlsAcceptor,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => accepted?.0,
+            _ = &mut shutdown => break,
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let watcher = graceful.watcher();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("TLS handshake failed: {}", e);
+                    return;
+                }
+            };
+
+            let service = hyper::service::service_fn(move |request: hyper::Request<hyper::body::Incoming>| {
+                app.clone().call(request)
+            });
+
+            let builder = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(e) = watcher.watch(connection.into_owned()).await {
+                eprintln!("TLS connection error: {}", e);
+            }
+        });
+    }
+
+    graceful.shutdown().await;
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use anyhow::Context;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Address a service binds to: `--addr <host:port>` on the command line, then
+/// the environment variable `env_var`, then `default`. Bind `0.0.0.0` to
+/// accept benchmark clients running on other hosts.
+pub fn bind_addr(env_var: &str, default: &str) -> anyhow::Result<SocketAddr> {
+    let addr = flag_arg(std::env::args().skip(1), "--addr")?
+        .or_else(|| std::env::var(env_var).ok())
+        .unwrap_or_else(|| default.to_string());
+
+    addr.parse()
+        .with_context(|| format!("Invalid bind address {:?}", addr))
+}
+
+/// `host:port` a client connects to: the environment variable `env_var`, then
+/// `configured` (its entry under `[targets]` in `protobench.toml`), then `default`
+pub fn target_addr(env_var: &str, configured: Option<&str>, default: &str) -> String {
+    std::env::var(env_var)
+        .ok()
+        .or_else(|| configured.map(str::to_string))
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Unix domain socket path from `--uds <path>` on the command line, for
+/// services that can listen on one instead of TCP
+pub fn uds_path() -> anyhow::Result<Option<PathBuf>> {
+    Ok(flag_arg(std::env::args().skip(1), "--uds")?.map(PathBuf::from))
+}
+
+/// Value given to `flag` on the command line, as `<flag> <value>` or
+/// `<flag>=<value>`
+pub fn cli_flag(flag: &str) -> anyhow::Result<Option<String>> {
+    flag_arg(std::env::args().skip(1), flag)
+}
+
+// Accepts `<flag> <value>` and `<flag>=<value>`; other arguments are ignored
+fn flag_arg(mut args: impl Iterator<Item = String>, flag: &str) -> anyhow::Result<Option<String>> {
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args
+                .next()
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("{} requires a value", flag));
+        }
+        if let Some(value) = arg.strip_prefix(flag).and_then(|rest| rest.strip_prefix('=')) {
+            return Ok(Some(value.to_string()));
+        }
+    }
+    Ok(None)
+}
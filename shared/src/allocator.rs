@@ -0,0 +1,138 @@
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+
+// jemalloc wins when both features are on, e.g. under --all-features
+#[cfg(feature = "jemalloc")]
+type Inner = tikv_jemallocator::Jemalloc;
+#[cfg(feature = "jemalloc")]
+const INNER: Inner = tikv_jemallocator::Jemalloc;
+#[cfg(feature = "jemalloc")]
+const NAME: &str = "jemalloc";
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+type Inner = mimalloc::MiMalloc;
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+const INNER: Inner = mimalloc::MiMalloc;
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+const NAME: &str = "mimalloc";
+
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+type Inner = std::alloc::System;
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+const INNER: Inner = std::alloc::System;
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+const NAME: &str = "system";
+
+/// Which allocator this build hands memory out from: `system`, or
+/// `jemalloc` or `mimalloc` when built with that feature (`jemalloc` when
+/// built with both). Allocator choice
+/// can move allocation cost and heap size more than the protocol does, so
+/// results record it.
+pub const fn allocator_name() -> &'static str {
+    NAME
+}
+
+thread_local! {
+    // Const-initialized and without a destructor, so the allocator can use it
+    // from any thread at any point, its teardown included, without allocating
+    static THREAD_ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_on_thread(bytes: usize) {
+    let _ = THREAD_ALLOCATED.try_with(|allocated| allocated.set(allocated.get().wrapping_add(bytes)));
+}
+
+/// Bytes a `CountingAllocator` has handed out to the calling thread since it
+/// started, counted as `AllocStats::bytes_allocated` is. Unlike the process
+/// totals, nothing another thread does moves it, so the difference across a
+/// piece of work is that work's own allocations. It wraps rather than
+/// overflowing; take differences with `wrapping_sub`.
+pub fn thread_allocated_bytes() -> usize {
+    THREAD_ALLOCATED.try_with(Cell::get).unwrap_or(0)
+}
+
+/// Byte totals a `CountingAllocator` has seen since the process started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// Bytes requested by allocations, and by reallocations that grew
+    pub bytes_allocated: usize,
+    /// Bytes freed by deallocations, and by reallocations that shrank
+    pub bytes_deallocated: usize,
+    /// Net bytes reallocations grew by, negative when they shrank more
+    pub bytes_reallocated: isize,
+}
+
+/// The build's allocator (see `allocator_name`) counting the bytes it hands
+/// out, for a binary's `#[global_allocator]`:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static GLOBAL: CountingAllocator = CountingAllocator::new();
+/// ```
+///
+/// Every binary in the workspace installs one, so the harness's memory
+/// figures and the services' per-request allocations are counted the same
+/// way whichever allocator is underneath.
+pub struct CountingAllocator {
+    bytes_allocated: AtomicUsize,
+    bytes_deallocated: AtomicUsize,
+    bytes_reallocated: AtomicIsize,
+    inner: Inner,
+}
+
+impl CountingAllocator {
+    pub const fn new() -> Self {
+        Self {
+            bytes_allocated: AtomicUsize::new(0),
+            bytes_deallocated: AtomicUsize::new(0),
+            bytes_reallocated: AtomicIsize::new(0),
+            inner: INNER,
+        }
+    }
+
+    /// Totals since the process started
+    pub fn stats(&self) -> AllocStats {
+        AllocStats {
+            bytes_allocated: self.bytes_allocated.load(Ordering::SeqCst),
+            bytes_deallocated: self.bytes_deallocated.load(Ordering::SeqCst),
+            bytes_reallocated: self.bytes_reallocated.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl Default for CountingAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.bytes_allocated.fetch_add(layout.size(), Ordering::SeqCst);
+        count_on_thread(layout.size());
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.bytes_deallocated.fetch_add(layout.size(), Ordering::SeqCst);
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.bytes_allocated.fetch_add(layout.size(), Ordering::SeqCst);
+        count_on_thread(layout.size());
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() {
+            self.bytes_allocated.fetch_add(new_size - layout.size(), Ordering::SeqCst);
+            count_on_thread(new_size - layout.size());
+        } else {
+            self.bytes_deallocated.fetch_add(layout.size() - new_size, Ordering::SeqCst);
+        }
+        self.bytes_reallocated.fetch_add(new_size.wrapping_sub(layout.size()) as isize, Ordering::SeqCst);
+        self.inner.realloc(ptr, layout, new_size)
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::Context;
+use arrow_array::builder::{Float32Builder, Int64Builder, MapBuilder, StringBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float32Type, Int64Type, UInt32Type, UInt64Type};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::MetricPoint;
+
+/// Media type of an Arrow IPC stream, which `GET /metrics` answers with when
+/// `Accept` asks for it
+pub const ARROW_STREAM: &str = "application/vnd.apache.arrow.stream";
+
+/// Rows per record batch, so a large result goes out as several messages
+/// rather than one that has to be built whole
+const BATCH_ROWS: usize = 64 * 1024;
+
+/// `metrics` as one Arrow column per field: `timestamp` (Int64), `hostname`
+/// (Utf8), `cpu_percent` (Float32), `memory_bytes` (UInt64), `disk_io_ops`
+/// (UInt32) and `tags` (Map of Utf8 to Utf8)
+pub fn record_batch(metrics: &[MetricPoint]) -> anyhow::Result<RecordBatch> {
+    let mut timestamps = Int64Builder::with_capacity(metrics.len());
+    let mut hostnames = StringBuilder::new();
+    let mut cpu_percents = Float32Builder::with_capacity(metrics.len());
+    let mut memory_bytes = UInt64Builder::with_capacity(metrics.len());
+    let mut disk_io_ops = UInt32Builder::with_capacity(metrics.len());
+    let mut tags = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+    for metric in metrics {
+        timestamps.append_value(metric.timestamp);
+        hostnames.append_value(&metric.hostname);
+        cpu_percents.append_value(metric.cpu_percent);
+        memory_bytes.append_value(metric.memory_bytes);
+        disk_io_ops.append_value(metric.disk_io_ops);
+        for (key, value) in &metric.tags {
+            tags.keys().append_value(key);
+            tags.values().append_value(value);
+        }
+        tags.append(true)?;
+    }
+
+    let columns: [(&str, ArrayRef); 6] = [
+        ("timestamp", Arc::new(timestamps.finish())),
+        ("hostname", Arc::new(hostnames.finish())),
+        ("cpu_percent", Arc::new(cpu_percents.finish())),
+        ("memory_bytes", Arc::new(memory_bytes.finish())),
+        ("disk_io_ops", Arc::new(disk_io_ops.finish())),
+        ("tags", Arc::new(tags.finish())),
+    ];
+    Ok(RecordBatch::try_from_iter(columns)?)
+}
+
+/// `metrics` as an Arrow IPC stream of `record_batch`es
+pub fn encode(metrics: &[MetricPoint]) -> anyhow::Result<Vec<u8>> {
+    let mut stream = Vec::new();
+    let first = record_batch(&metrics[..metrics.len().min(BATCH_ROWS)])?;
+    // Scoped so the writer's borrow of `stream` ends before it is returned
+    {
+        let mut writer = StreamWriter::try_new(&mut stream, &first.schema())?;
+        writer.write(&first)?;
+        for chunk in metrics.chunks(BATCH_ROWS).skip(1) {
+            writer.write(&record_batch(chunk)?)?;
+        }
+        writer.finish()?;
+    }
+    Ok(stream)
+}
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> anyhow::Result<&'a ArrayRef> {
+    batch.column_by_name(name).with_context(|| format!("No {} column", name))
+}
+
+/// The points in an Arrow IPC stream laid out as `record_batch` does
+pub fn decode(stream: &[u8]) -> anyhow::Result<Vec<MetricPoint>> {
+    let mut metrics = Vec::new();
+    for batch in StreamReader::try_new(stream, None)? {
+        let batch = batch?;
+        let mismatch = |name: &str| format!("{} column has the wrong type", name);
+        let timestamps = column(&batch, "timestamp")?.as_primitive_opt::<Int64Type>().with_context(|| mismatch("timestamp"))?;
+        let hostnames = column(&batch, "hostname")?.as_string_opt::<i32>().with_context(|| mismatch("hostname"))?;
+        let cpu_percents = column(&batch, "cpu_percent")?.as_primitive_opt::<Float32Type>().with_context(|| mismatch("cpu_percent"))?;
+        let memory_bytes = column(&batch, "memory_bytes")?.as_primitive_opt::<UInt64Type>().with_context(|| mismatch("memory_bytes"))?;
+        let disk_io_ops = column(&batch, "disk_io_ops")?.as_primitive_opt::<UInt32Type>().with_context(|| mismatch("disk_io_ops"))?;
+        let tags = column(&batch, "tags")?.as_map_opt().with_context(|| mismatch("tags"))?;
+        let keys = tags.keys().as_string_opt::<i32>().with_context(|| mismatch("tags"))?;
+        let values = tags.values().as_string_opt::<i32>().with_context(|| mismatch("tags"))?;
+        let offsets = tags.value_offsets();
+
+        metrics.reserve(batch.num_rows());
+        for row in 0..batch.num_rows() {
+            let entries = offsets[row] as usize..offsets[row + 1] as usize;
+            metrics.push(MetricPoint {
+                timestamp: timestamps.value(row),
+                hostname: hostnames.value(row).to_string(),
+                cpu_percent: cpu_percents.value(row),
+                memory_bytes: memory_bytes.value(row),
+                disk_io_ops: disk_io_ops.value(row),
+                tags: entries.map(|entry| (keys.value(entry).to_string(), values.value(entry).to_string())).collect::<HashMap<_, _>>(),
+            });
+        }
+    }
+    Ok(metrics)
+}
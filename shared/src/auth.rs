@@ -0,0 +1,39 @@
+/// Shared-secret authentication, from `PROTOBENCH_AUTH_TOKEN`. Services require
+/// the token on every request when it is set; clients send it.
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    pub token: String,
+}
+
+impl AuthConfig {
+    /// Returns `None` when the variable is unset or empty, which disables auth
+    pub fn from_env() -> Option<Self> {
+        let token = std::env::var("PROTOBENCH_AUTH_TOKEN").ok()?;
+        if token.is_empty() {
+            return None;
+        }
+        Some(Self { token })
+    }
+
+    /// Compare a presented token against the configured one without
+    /// short-circuiting on the first differing byte
+    pub fn verify(&self, presented: &str) -> bool {
+        let expected = self.token.as_bytes();
+        let presented = presented.as_bytes();
+        if expected.len() != presented.len() {
+            return false;
+        }
+        expected
+            .iter()
+            .zip(presented)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+
+    /// Check an HTTP `Authorization: Bearer <token>` value (gRPC metadata uses the same form)
+    pub fn verify_bearer(&self, header: &str) -> bool {
+        header
+            .strip_prefix("Bearer ")
+            .is_some_and(|token| self.verify(token))
+    }
+}
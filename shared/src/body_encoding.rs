@@ -0,0 +1,73 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Serialization format of REST bodies, selected per request by `rest-service`
+/// from `Content-Type`/`Accept` and sent by `rest_client` per
+/// `PROTOBENCH_REST_ENCODING`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BodyEncoding {
+    #[default]
+    Json,
+    MsgPack,
+    Cbor,
+}
+
+impl BodyEncoding {
+    pub const ALL: [BodyEncoding; 3] = [BodyEncoding::Json, BodyEncoding::MsgPack, BodyEncoding::Cbor];
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            BodyEncoding::Json => "application/json",
+            BodyEncoding::MsgPack => "application/msgpack",
+            BodyEncoding::Cbor => "application/cbor",
+        }
+    }
+
+    /// Match a media type (without parameters), including the common msgpack aliases
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" => Some(BodyEncoding::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => Some(BodyEncoding::MsgPack),
+            "application/cbor" => Some(BodyEncoding::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Read `PROTOBENCH_REST_ENCODING` (`json`, `msgpack` or `cbor`); unset or
+    /// unrecognised means JSON
+    pub fn from_env() -> Self {
+        match std::env::var("PROTOBENCH_REST_ENCODING").as_deref() {
+            Ok("msgpack") => BodyEncoding::MsgPack,
+            Ok("cbor") => BodyEncoding::Cbor,
+            _ => BodyEncoding::Json,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BodyEncoding::Json => "json",
+            BodyEncoding::MsgPack => "msgpack",
+            BodyEncoding::Cbor => "cbor",
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            BodyEncoding::Json => serde_json::to_vec(value)?,
+            // Named fields keep the payload self-describing like JSON and CBOR
+            BodyEncoding::MsgPack => rmp_serde::to_vec_named(value)?,
+            BodyEncoding::Cbor => {
+                let mut buffer = Vec::new();
+                ciborium::into_writer(value, &mut buffer)?;
+                buffer
+            }
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> anyhow::Result<T> {
+        Ok(match self {
+            BodyEncoding::Json => serde_json::from_slice(bytes)?,
+            BodyEncoding::MsgPack => rmp_serde::from_slice(bytes)?,
+            BodyEncoding::Cbor => ciborium::from_reader(bytes)?,
+        })
+    }
+}
@@ -0,0 +1,30 @@
+/// Stream encoding of Cap'n Proto RPC messages, used by `capnp-service` and
+/// `capnp_client` from `PROTOBENCH_CAPNP_ENCODING`. Both ends must agree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CapnpEncoding {
+    /// Plain word-aligned segments, the capnp-rpc default
+    #[default]
+    Unpacked,
+    /// Zero bytes run-length encoded, trading CPU for smaller messages
+    Packed,
+}
+
+impl CapnpEncoding {
+    pub const ALL: [CapnpEncoding; 2] = [CapnpEncoding::Unpacked, CapnpEncoding::Packed];
+
+    /// Read `PROTOBENCH_CAPNP_ENCODING` (`unpacked` or `packed`); unset or
+    /// unrecognised means unpacked
+    pub fn from_env() -> Self {
+        match std::env::var("PROTOBENCH_CAPNP_ENCODING").as_deref() {
+            Ok("packed") => CapnpEncoding::Packed,
+            _ => CapnpEncoding::Unpacked,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CapnpEncoding::Unpacked => "unpacked",
+            CapnpEncoding::Packed => "packed",
+        }
+    }
+}
@@ -0,0 +1,48 @@
+/// Which HTTP content encodings are enabled, used for both what `rest-service`
+/// will compress with and what `rest_client` advertises in `Accept-Encoding`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HttpCompression {
+    pub gzip: bool,
+    pub br: bool,
+    pub zstd: bool,
+}
+
+impl HttpCompression {
+    /// Parse a comma-separated list such as `gzip,br,zstd`. Unknown names are
+    /// ignored; an empty list or `none` disables compression.
+    pub fn parse(list: &str) -> Self {
+        let mut compression = Self::default();
+        for encoding in list.split(',').map(str::trim) {
+            match encoding {
+                "gzip" => compression.gzip = true,
+                "br" => compression.br = true,
+                "zstd" => compression.zstd = true,
+                _ => {}
+            }
+        }
+        compression
+    }
+
+    /// Read the encoding list from the environment variable `name`; unset means none
+    pub fn from_env(name: &str) -> Self {
+        std::env::var(name).map(|list| Self::parse(&list)).unwrap_or_default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.gzip || self.br || self.zstd
+    }
+
+    /// The enabled encodings as a comma-separated list, or `identity` when none are
+    pub fn label(&self) -> String {
+        let enabled: Vec<&str> = [(self.gzip, "gzip"), (self.br, "br"), (self.zstd, "zstd")]
+            .into_iter()
+            .filter_map(|(on, name)| on.then_some(name))
+            .collect();
+
+        if enabled.is_empty() {
+            "identity".to_string()
+        } else {
+            enabled.join(",")
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// Looked for in the working directory unless PROTOBENCH_CONFIG names a file
+const DEFAULT_CONFIG_PATH: &str = "protobench.toml";
+
+static FILE_CONFIG: OnceLock<FileConfig> = OnceLock::new();
+
+/// Settings from `protobench.toml`, or the file named by `PROTOBENCH_CONFIG`.
+/// Environment variables take precedence over anything set here.
+///
+/// ```toml
+/// [targets]
+/// rest = "10.0.0.5:3000"
+/// grpc = "10.0.0.5:50051"
+/// capnp = "10.0.0.5:55556"
+///
+/// [rest_pool]
+/// max_idle_per_host = 8
+/// idle_timeout_ms = 30000
+/// http2_adaptive_window = true
+/// tcp_nodelay = true
+///
+/// [resolve]
+/// "metrics.internal" = "10.0.0.5"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    #[serde(default)]
+    pub targets: Targets,
+    #[serde(default)]
+    pub rest_pool: RestPool,
+    /// Fixed addresses for target hosts; see `DnsConfig`
+    #[serde(default)]
+    pub resolve: BTreeMap<String, IpAddr>,
+}
+
+/// `host:port` each benchmark client connects to
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Targets {
+    pub rest: Option<String>,
+    pub grpc: Option<String>,
+    pub capnp: Option<String>,
+}
+
+/// Connection pool settings for the REST client; anything unset keeps
+/// reqwest's default
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RestPool {
+    /// Idle connections kept per host
+    pub max_idle_per_host: Option<usize>,
+    /// How long an idle connection is kept before it is closed
+    pub idle_timeout_ms: Option<u64>,
+    /// Size HTTP/2 flow-control windows from measured bandwidth-delay
+    pub http2_adaptive_window: Option<bool>,
+    pub tcp_nodelay: Option<bool>,
+}
+
+impl FileConfig {
+    /// Read and parse the config file. Without `PROTOBENCH_CONFIG` a missing
+    /// `protobench.toml` is an empty config; a file named explicitly must exist.
+    pub fn load() -> anyhow::Result<Self> {
+        let (path, required) = match std::env::var_os("PROTOBENCH_CONFIG") {
+            Some(path) => (PathBuf::from(path), true),
+            None => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        toml::from_str(&contents).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// The config, loaded once per process. A file that can't be loaded is
+    /// reported on stderr and ignored, leaving env vars and defaults in charge.
+    pub fn get() -> &'static Self {
+        FILE_CONFIG.get_or_init(|| {
+            Self::load().unwrap_or_else(|e| {
+                eprintln!("Ignoring config file: {:#}", e);
+                Self::default()
+            })
+        })
+    }
+}
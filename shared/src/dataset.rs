@@ -0,0 +1,110 @@
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::MetricPoint;
+
+/// One CSV row. `tags` holds `key=value` pairs separated by `;` and may be
+/// left empty or out entirely.
+#[derive(Deserialize)]
+struct CsvRow {
+    timestamp: i64,
+    hostname: String,
+    cpu_percent: f32,
+    memory_bytes: u64,
+    disk_io_ops: u32,
+    #[serde(default)]
+    tags: String,
+}
+
+impl CsvRow {
+    fn into_metric(self) -> anyhow::Result<MetricPoint> {
+        let mut tags = HashMap::new();
+        for pair in self.tags.split(';').filter(|pair| !pair.is_empty()) {
+            let Some((key, value)) = pair.split_once('=') else {
+                bail!("tag '{}' is not key=value", pair);
+            };
+            tags.insert(key.to_string(), value.to_string());
+        }
+        Ok(MetricPoint {
+            timestamp: self.timestamp,
+            hostname: self.hostname,
+            cpu_percent: self.cpu_percent,
+            memory_bytes: self.memory_bytes,
+            disk_io_ops: self.disk_io_ops,
+            tags,
+        })
+    }
+}
+
+/// Read a benchmark dataset, so runs can use production-shaped points instead
+/// of `TestDataGenerator`'s. The format follows the extension:
+///
+/// - `.csv`: a header row naming `timestamp`, `hostname`, `cpu_percent`,
+///   `memory_bytes`, `disk_io_ops` and optionally `tags` (`env=prod;region=eu`)
+/// - `.json`: an array of `MetricPoint` objects as the REST API sends them
+/// - `.jsonl` or `.ndjson`: one `MetricPoint` object per line
+///
+/// Every point must pass `validate`; errors name the offending row or line.
+/// Points come back sorted by timestamp.
+pub fn load_dataset(path: impl AsRef<Path>) -> anyhow::Result<Vec<MetricPoint>> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    let mut metrics = match extension {
+        "csv" => load_csv(path)?,
+        "json" => {
+            let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+            let metrics: Vec<MetricPoint> = serde_json::from_reader(std::io::BufReader::new(file))
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            for (index, metric) in metrics.iter().enumerate() {
+                metric.validate().with_context(|| format!("{}: point {}", path.display(), index))?;
+            }
+            metrics
+        }
+        "jsonl" | "ndjson" => load_json_lines(path)?,
+        _ => bail!("{}: unknown dataset format, expected .csv, .json, .jsonl or .ndjson", path.display()),
+    };
+    metrics.sort_by_key(|metric| metric.timestamp);
+    Ok(metrics)
+}
+
+/// `load_dataset` on the file named by `PROTOBENCH_DATASET`, or `None` when
+/// it's unset
+pub fn dataset_from_env() -> anyhow::Result<Option<Vec<MetricPoint>>> {
+    match std::env::var_os("PROTOBENCH_DATASET") {
+        Some(path) => load_dataset(path).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn load_csv(path: &Path) -> anyhow::Result<Vec<MetricPoint>> {
+    let mut reader = csv::Reader::from_path(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut metrics = Vec::new();
+    for (index, row) in reader.deserialize::<CsvRow>().enumerate() {
+        // Line 1 is the header
+        let line = index + 2;
+        let metric = row
+            .map_err(anyhow::Error::from)
+            .and_then(CsvRow::into_metric)
+            .with_context(|| format!("{}: line {}", path.display(), line))?;
+        metric.validate().with_context(|| format!("{}: line {}", path.display(), line))?;
+        metrics.push(metric);
+    }
+    Ok(metrics)
+}
+
+fn load_json_lines(path: &Path) -> anyhow::Result<Vec<MetricPoint>> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut metrics = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let metric: MetricPoint =
+            serde_json::from_str(line).with_context(|| format!("{}: line {}", path.display(), index + 1))?;
+        metric.validate().with_context(|| format!("{}: line {}", path.display(), index + 1))?;
+        metrics.push(metric);
+    }
+    Ok(metrics)
+}
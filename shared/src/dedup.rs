@@ -0,0 +1,28 @@
+/// What to do when a point arrives with the same (timestamp, hostname) as one
+/// already stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupPolicy {
+    /// Keep the stored point and drop the new one
+    KeepFirst,
+    /// Overwrite the stored point with the new one
+    KeepLast,
+}
+
+impl DedupPolicy {
+    /// Read `PROTOBENCH_DEDUP` (`keep-first` or `keep-last`). Returns `None`
+    /// when unset, which leaves deduplication off, and an error for any other
+    /// value rather than silently running without it.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// `from_env`, with the variable's value looked up through `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> anyhow::Result<Option<Self>> {
+        match var("PROTOBENCH_DEDUP").as_deref() {
+            Some("keep-first") => Ok(Some(Self::KeepFirst)),
+            Some("keep-last") => Ok(Some(Self::KeepLast)),
+            Some(other) => anyhow::bail!("Invalid PROTOBENCH_DEDUP {:?}, expected keep-first or keep-last", other),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use rand::Rng;
+use std::time::Duration;
+
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// Message every service answers an injected error with
+pub const INJECTED_FAULT: &str = "injected fault";
+
+/// What a service does to one request under `FaultInjection`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Answer with the protocol's "unavailable" error without handling it
+    Error,
+    /// Handle it, then hold the response back this long
+    Delay(Duration),
+    /// Give no response: REST closes the connection, gRPC resets the stream
+    /// and Cap'n Proto closes the connection the call came in on
+    Drop,
+}
+
+/// Faults the services inject into their own responses for resilience
+/// benchmarks, from `PROTOBENCH_FAULT_ERROR_RATE`,
+/// `PROTOBENCH_FAULT_DELAY_RATE`, `PROTOBENCH_FAULT_DELAY_MS` (default 100)
+/// and `PROTOBENCH_FAULT_DROP_RATE`. Each rate is the probability, from 0 to
+/// 1, that a request gets that fault; at most one applies to a request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultInjection {
+    pub error_rate: f64,
+    pub delay_rate: f64,
+    pub delay: Duration,
+    pub drop_rate: f64,
+}
+
+impl FaultInjection {
+    /// Returns `None` when every rate is unset or 0. Rates that don't parse
+    /// or fall outside 0 to 1 are ignored.
+    pub fn from_env() -> Option<Self> {
+        let faults = Self {
+            error_rate: env_rate("PROTOBENCH_FAULT_ERROR_RATE"),
+            delay_rate: env_rate("PROTOBENCH_FAULT_DELAY_RATE"),
+            delay: std::env::var("PROTOBENCH_FAULT_DELAY_MS")
+                .ok()
+                .and_then(|ms| ms.parse().ok())
+                .map_or(DEFAULT_DELAY, Duration::from_millis),
+            drop_rate: env_rate("PROTOBENCH_FAULT_DROP_RATE"),
+        };
+        (faults.error_rate + faults.delay_rate + faults.drop_rate > 0.0).then_some(faults)
+    }
+
+    /// The fault, if any, for the next request
+    pub fn pick(&self) -> Option<Fault> {
+        self.fault_for(rand::thread_rng().gen())
+    }
+
+    /// The fault for a uniform `roll` in [0, 1): drops take the bottom of the
+    /// range, then errors, then delays. Rates adding up to more than 1 leave
+    /// the later faults less than their share.
+    pub fn fault_for(&self, roll: f64) -> Option<Fault> {
+        let mut threshold = self.drop_rate;
+        if roll < threshold {
+            return Some(Fault::Drop);
+        }
+        threshold += self.error_rate;
+        if roll < threshold {
+            return Some(Fault::Error);
+        }
+        threshold += self.delay_rate;
+        (roll < threshold).then_some(Fault::Delay(self.delay))
+    }
+
+    pub fn label(&self) -> String {
+        format!(
+            "errors {}%, delays {}% of {:?}, drops {}%",
+            self.error_rate * 100.0,
+            self.delay_rate * 100.0,
+            self.delay,
+            self.drop_rate * 100.0
+        )
+    }
+}
+
+fn env_rate(name: &str) -> f64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|rate| rate.parse().ok())
+        .filter(|rate: &f64| (0.0..=1.0).contains(rate))
+        .unwrap_or(0.0)
+}
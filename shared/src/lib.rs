@@ -1,26 +1,127 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::future::Future;
-use std::sync::{Arc, RwLock};
+
+mod addr;
+mod allocator;
+#[cfg(feature = "arrow")]
+pub mod arrow_ipc;
+mod auth;
+mod body_encoding;
+mod capnp_encoding;
+mod compression;
+mod config;
+mod dataset;
+mod dedup;
+pub mod delta_batch;
+mod faults;
+mod live;
+mod message_limit;
+mod metric_v2;
+mod non_finite;
+mod payload_cipher;
+mod request_id;
+mod resolve;
+mod retention;
+mod rollup;
+#[cfg(feature = "grpc")]
+mod sealed_grpc;
+mod shutdown;
+mod storage;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+mod telemetry;
+mod test_data;
+mod trace_context;
+mod validation;
+mod wal;
+
+pub use addr::{bind_addr, cli_flag, target_addr, uds_path};
+pub use allocator::{allocator_name, thread_allocated_bytes, AllocStats, CountingAllocator};
+pub use auth::AuthConfig;
+pub use body_encoding::BodyEncoding;
+pub use capnp_encoding::CapnpEncoding;
+pub use compression::HttpCompression;
+pub use config::{FileConfig, RestPool, Targets};
+pub use dataset::{dataset_from_env, load_dataset};
+pub use dedup::DedupPolicy;
+pub use faults::{Fault, FaultInjection, INJECTED_FAULT};
+pub use live::{LiveFeed, Subscription};
+pub use message_limit::max_message_bytes;
+pub use metric_v2::{HistogramBucket, MetricPointV2, ProcessInfo};
+pub use payload_cipher::{PayloadCipher, SealedStream, PAYLOAD_CIPHER};
+pub use request_id::{
+    log_request, next_request_id, next_request_sequence, request_id_range, request_log_enabled, valid_request_id,
+    MAX_REQUEST_ID_LEN, REQUEST_ID,
+};
+pub use resolve::{split_host_port, DnsConfig};
+pub use retention::RetentionPolicy;
+pub use rollup::{bucket_start, MetricRollup, ROLLUP_BUCKET_SECONDS};
+#[cfg(feature = "grpc")]
+pub use sealed_grpc::SealedGrpc;
+pub use shutdown::{shutdown_grace_period, shutdown_signal};
+pub use storage::{InMemoryStorage, MetricsStorage};
+pub use telemetry::{OperationStats, RequestTimer, ServerStats, ServiceMetrics};
+pub use trace_context::{current_traceparent, init_tracing_from_env, set_remote_parent, TracingGuard, TRACEPARENT};
+pub use test_data::{
+    generate_test_data, generate_test_data_iter, generate_unusual_hostname_data, Compressibility, TestDataGenerator,
+    TestDataIter, TextMode, UNUSUAL_HOSTNAMES,
+};
+pub use validation::{
+    TimestampUnit, ValidationError, MAX_HOSTNAME_LEN, MAX_TAGS, MAX_TAG_KEY_LEN, MAX_TAG_VALUE_LEN, MAX_TIMESTAMP,
+    MIN_TIMESTAMP,
+};
+pub use wal::{FsyncPolicy, WalConfig};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MetricPoint {
+    /// Unix seconds; `validate` rejects milliseconds and finer
     pub timestamp: i64,
     pub hostname: String,
+    /// NaN and infinities are written as strings in JSON; see `non_finite`
+    #[serde(with = "non_finite")]
     pub cpu_percent: f32,
     pub memory_bytes: u64,
     pub disk_io_ops: u32,
     pub tags: HashMap<String, String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MetricQuery {
+    /// Unix seconds, inclusive, like `end_time`
     pub start_time: i64,
     pub end_time: i64,
     pub hostname_filter: Option<String>,
+    /// Maximum number of points to return from query_metrics (None = unlimited)
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// Number of matching points to skip before returning results
+    #[serde(default)]
+    pub offset: Option<u32>,
+}
+
+impl MetricQuery {
+    /// Every stored point: the whole time range, any hostname, no paging
+    pub fn all() -> Self {
+        Self {
+            start_time: i64::MIN,
+            end_time: i64::MAX,
+            hostname_filter: None,
+            limit: None,
+            offset: None,
+        }
+    }
+
+    /// Whether a point falls inside the query's time range and hostname filter
+    pub fn matches(&self, metric: &MetricPoint) -> bool {
+        metric.timestamp >= self.start_time
+            && metric.timestamp <= self.end_time
+            && self.hostname_filter.as_ref().is_none_or(|filter| &metric.hostname == filter)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MetricStatistics {
     pub count: u64,
     pub avg_cpu_percent: f32,
@@ -29,80 +130,28 @@ pub struct MetricStatistics {
     pub time_range_seconds: i64,
 }
 
+/// Server-side memory footprint of `InMemoryStorage`. Byte counts are
+/// estimates from struct sizes and heap capacities, not allocator measurements.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StorageStats {
+    pub point_count: u64,
+    pub approx_heap_bytes: u64,
+    pub rollup_bucket_count: u64,
+    pub rollup_heap_bytes: u64,
+}
+
+/// The metrics API as seen by a caller, implemented by `InMemoryStorage` and by
+/// each protocol client so the harness can drive them through `dyn MetricsService`.
+///
+/// Futures are not required to be `Send`: the Cap'n Proto client runs on a
+/// `LocalSet` because its RPC types are `!Send`.
+#[async_trait::async_trait(?Send)]
 pub trait MetricsService {
     type Error;
     
-    fn submit_metric(&self, metric: MetricPoint) -> impl Future<Output = Result<(), Self::Error>> + Send;
-    fn query_metrics(&self, query: MetricQuery) -> impl Future<Output = Result<Vec<MetricPoint>, Self::Error>> + Send;
-    fn get_statistics(&self, query: MetricQuery) -> impl Future<Output = Result<MetricStatistics, Self::Error>> + Send;
-}
-
-
-pub struct InMemoryStorage {
-    metrics: Arc<RwLock<Vec<MetricPoint>>>,
+    async fn submit_metric(&self, metric: MetricPoint) -> Result<(), Self::Error>;
+    async fn query_metrics(&self, query: MetricQuery) -> Result<Vec<MetricPoint>, Self::Error>;
+    async fn get_statistics(&self, query: MetricQuery) -> Result<MetricStatistics, Self::Error>;
+    async fn delete_metrics(&self, query: MetricQuery) -> Result<u64, Self::Error>;
 }
-
-impl Default for InMemoryStorage {
-    fn default() -> Self {
-        Self {
-            metrics: Arc::new(RwLock::new(Vec::new())),
-        }
-    }
-}
-
-impl InMemoryStorage {
-    pub fn new() -> Self {
-        Self::default()
-    }
-    
-    pub fn store_metric(&self, metric: MetricPoint) -> Result<(), anyhow::Error> {
-        let mut metrics = self.metrics.write().map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
-        metrics.push(metric);
-        Ok(())
-    }
-    
-    pub fn query_metrics(&self, query: &MetricQuery) -> Result<Vec<MetricPoint>, anyhow::Error> {
-        let metrics = self.metrics.read().map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
-        
-        let filtered: Vec<MetricPoint> = metrics
-            .iter()
-            .filter(|metric| {
-                metric.timestamp >= query.start_time && metric.timestamp <= query.end_time
-            })
-            .filter(|metric| {
-                query.hostname_filter.as_ref()
-                    .is_none_or(|filter| &metric.hostname == filter)
-            })
-            .cloned()
-            .collect();
-            
-        Ok(filtered)
-    }
-    
-    pub fn calculate_statistics(&self, query: &MetricQuery) -> Result<MetricStatistics, anyhow::Error> {
-        let metrics = self.query_metrics(query)?;
-        
-        if metrics.is_empty() {
-            return Ok(MetricStatistics {
-                count: 0,
-                avg_cpu_percent: 0.0,
-                avg_memory_bytes: 0,
-                avg_disk_io_ops: 0.0,
-                time_range_seconds: query.end_time - query.start_time,
-            });
-        }
-        
-        let count = metrics.len() as u64;
-        let avg_cpu = metrics.iter().map(|m| m.cpu_percent).sum::<f32>() / count as f32;
-        let avg_memory = metrics.iter().map(|m| m.memory_bytes).sum::<u64>() / count;
-        let avg_disk_io = metrics.iter().map(|m| m.disk_io_ops as f32).sum::<f32>() / count as f32;
-        
-        Ok(MetricStatistics {
-            count,
-            avg_cpu_percent: avg_cpu,
-            avg_memory_bytes: avg_memory,
-            avg_disk_io_ops: avg_disk_io,
-            time_range_seconds: query.end_time - query.start_time,
-        })
-    }
-}
\ No newline at end of file
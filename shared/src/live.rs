@@ -0,0 +1,76 @@
+use tokio::sync::{broadcast, watch};
+
+use crate::MetricPoint;
+
+// Points buffered per subscriber before the slowest one starts missing them
+const LIVE_FEED_CAPACITY: usize = 4096;
+
+/// Fan-out of newly stored points to live subscribers (gRPC `Subscribe`, REST
+/// `/metrics/subscribe`, Cap'n Proto `subscribe`).
+///
+/// Subscribers that fall more than `LIVE_FEED_CAPACITY` points behind skip
+/// the ones they missed rather than slowing down writers.
+pub struct LiveFeed {
+    points: broadcast::Sender<MetricPoint>,
+    closed: watch::Sender<bool>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        Self {
+            points: broadcast::channel(LIVE_FEED_CAPACITY).0,
+            closed: watch::channel(false).0,
+        }
+    }
+}
+
+impl LiveFeed {
+    pub fn subscribe(&self) -> Subscription {
+        Subscription {
+            points: self.points.subscribe(),
+            closed: self.closed.subscribe(),
+        }
+    }
+
+    /// Cloning a batch only to publish it is wasted work when nobody listens
+    pub fn has_subscribers(&self) -> bool {
+        self.points.receiver_count() > 0
+    }
+
+    pub fn publish(&self, metrics: Vec<MetricPoint>) {
+        for metric in metrics {
+            // Only fails when every subscriber has gone away in the meantime
+            let _ = self.points.send(metric);
+        }
+    }
+
+    /// End every current and future subscription. Services call this on
+    /// shutdown so open subscriptions don't hold the drain open.
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+}
+
+/// One subscriber's view of a `LiveFeed`
+pub struct Subscription {
+    points: broadcast::Receiver<MetricPoint>,
+    closed: watch::Receiver<bool>,
+}
+
+impl Subscription {
+    /// Next point stored after the subscription was created, or `None` once
+    /// the feed is closed
+    pub async fn recv(&mut self) -> Option<MetricPoint> {
+        loop {
+            tokio::select! {
+                biased;
+                _ = self.closed.wait_for(|&closed| closed) => return None,
+                point = self.points.recv() => match point {
+                    Ok(point) => return Some(point),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+/// Largest message a service accepts, in bytes, from the environment variable
+/// `name`; clients that enforce a limit of their own read the same variable.
+/// `None` when unset or not a positive number, which keeps each framework's
+/// default: 2 MiB request bodies in axum, 4 MiB decoded messages in tonic and
+/// a 64 MiB traversal limit per Cap'n Proto message.
+pub fn max_message_bytes(name: &str) -> Option<usize> {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&bytes| bytes > 0)
+}
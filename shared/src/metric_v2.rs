@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::MetricPoint;
+
+/// Richer variant of `MetricPoint` with repeated and nested fields: CPU per
+/// core, a latency histogram and the busiest processes. Defined in all three
+/// schemas (`MetricPointV2`) so the `nested_payload` benchmark group can show
+/// the serialization differences flat points hide; no service endpoint takes
+/// it yet.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MetricPointV2 {
+    pub timestamp: i64,
+    pub hostname: String,
+    pub per_core_cpu_percent: Vec<f32>,
+    pub memory_bytes: u64,
+    pub disk_io_ops: u32,
+    pub latency_histogram: Vec<HistogramBucket>,
+    pub processes: Vec<ProcessInfo>,
+    pub tags: HashMap<String, String>,
+}
+
+/// Requests that took at most `upper_bound_ms` and more than the previous
+/// bucket's bound
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HistogramBucket {
+    pub upper_bound_ms: f64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+    pub args: Vec<String>,
+}
+
+impl MetricPointV2 {
+    /// The flat point with the same values: CPU averaged over the cores, and
+    /// the histogram and process list dropped
+    pub fn flatten(&self) -> MetricPoint {
+        let cores = self.per_core_cpu_percent.len().max(1) as f32;
+        MetricPoint {
+            timestamp: self.timestamp,
+            hostname: self.hostname.clone(),
+            cpu_percent: self.per_core_cpu_percent.iter().sum::<f32>() / cores,
+            memory_bytes: self.memory_bytes,
+            disk_io_ops: self.disk_io_ops,
+            tags: self.tags.clone(),
+        }
+    }
+}
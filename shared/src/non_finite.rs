@@ -0,0 +1,70 @@
+use serde::de::{self, Visitor};
+use serde::{Deserializer, Serializer};
+use std::fmt;
+
+/// Serde `with` module for `f32` fields that may hold NaN or an infinity.
+///
+/// JSON has no literal for them and serde_json writes `null`, which then fails
+/// to parse as a number, so human-readable formats get the strings `"NaN"`,
+/// `"Infinity"` and `"-Infinity"` (protobuf's JSON mapping uses the same).
+/// Binary formats keep the native float. Reading accepts either form and
+/// takes `null` as NaN, so payloads from serde_json's default still decode
+/// and reach validation, which rejects them like every other protocol does.
+pub(crate) fn serialize<S: Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    if value.is_finite() || !serializer.is_human_readable() {
+        serializer.serialize_f32(*value)
+    } else if value.is_nan() {
+        serializer.serialize_str("NaN")
+    } else if *value > 0.0 {
+        serializer.serialize_str("Infinity")
+    } else {
+        serializer.serialize_str("-Infinity")
+    }
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    deserializer.deserialize_any(F32Visitor)
+}
+
+struct F32Visitor;
+
+impl<'de> Visitor<'de> for F32Visitor {
+    type Value = f32;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a number, \"NaN\", \"Infinity\" or \"-Infinity\"")
+    }
+
+    fn visit_f32<E: de::Error>(self, value: f32) -> Result<f32, E> {
+        Ok(value)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<f32, E> {
+        Ok(value as f32)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<f32, E> {
+        Ok(value as f32)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<f32, E> {
+        Ok(value as f32)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<f32, E> {
+        match value {
+            "NaN" => Ok(f32::NAN),
+            "Infinity" => Ok(f32::INFINITY),
+            "-Infinity" => Ok(f32::NEG_INFINITY),
+            _ => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+        }
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<f32, E> {
+        Ok(f32::NAN)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<f32, E> {
+        Ok(f32::NAN)
+    }
+}
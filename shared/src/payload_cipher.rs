@@ -0,0 +1,213 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use std::io;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{ready, Context as TaskContext, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// REST header a sealed request or response names its cipher in
+pub const PAYLOAD_CIPHER: &str = "x-payload-cipher";
+
+static CIPHER: OnceLock<Option<PayloadCipher>> = OnceLock::new();
+
+/// AES-256-GCM over serialized payloads, applied by the services and clients
+/// on top of whatever the transport does, for deployments that require
+/// application-layer encryption even over TLS. Off unless
+/// `PROTOBENCH_PAYLOAD_KEY` holds a 256-bit key as 64 hex digits, which the
+/// services and the harness must share.
+#[derive(Clone)]
+pub struct PayloadCipher {
+    cipher: Aes256Gcm,
+}
+
+impl PayloadCipher {
+    /// Sent in `PAYLOAD_CIPHER` and shown in labels
+    pub const NAME: &'static str = "aes-256-gcm";
+    const NONCE_LEN: usize = 12;
+    /// Bytes `seal` adds: the nonce and the authentication tag
+    pub const OVERHEAD: usize = Self::NONCE_LEN + 16;
+
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)) }
+    }
+
+    /// Cipher for a key given as 64 hex digits
+    pub fn from_hex(hex: &str) -> anyhow::Result<Self> {
+        let hex = hex.trim();
+        anyhow::ensure!(hex.len() == 64, "Expected 64 hex digits, got {}", hex.len());
+        let mut key = [0u8; 32];
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits)?;
+            *byte = u8::from_str_radix(digits, 16).with_context(|| format!("Invalid hex {:?}", digits))?;
+        }
+        Ok(Self::new(&key))
+    }
+
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        match std::env::var("PROTOBENCH_PAYLOAD_KEY") {
+            Ok(hex) => Self::from_hex(&hex).context("Invalid PROTOBENCH_PAYLOAD_KEY").map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// The cipher, read once per process. Panics on an invalid key rather
+    /// than sending payloads in the clear; services check `from_env` at
+    /// startup so they fail with the reason instead.
+    pub fn get() -> Option<&'static Self> {
+        CIPHER.get_or_init(|| Self::from_env().unwrap_or_else(|e| panic!("{:#}", e))).as_ref()
+    }
+
+    /// `plaintext` encrypted under a fresh random nonce, as the nonce
+    /// followed by the ciphertext and tag
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce: [u8; Self::NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .expect("AES-GCM encrypts any payload under 64 GiB");
+        let mut sealed = Vec::with_capacity(Self::NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// The plaintext `seal` was given, or an error if `sealed` was cut short,
+    /// altered or sealed under another key
+    pub fn open(&self, sealed: &[u8]) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(sealed.len() >= Self::OVERHEAD, "Sealed payload of {} bytes is too short", sealed.len());
+        let (nonce, ciphertext) = sealed.split_at(Self::NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Payload failed authentication"))
+    }
+}
+
+/// Most plaintext one `SealedStream` frame carries
+const MAX_FRAME: usize = 64 * 1024;
+
+/// A byte stream sealed frame by frame, for Cap'n Proto, whose RPC system
+/// serializes straight onto its stream. Each flush sends what was written
+/// since as one frame, a 4-byte big-endian length then `PayloadCipher::seal`'s
+/// output, splitting anything over 64 KiB. capnp-rpc flushes after every
+/// message, so messages are sealed one by one.
+pub struct SealedStream<S> {
+    inner: S,
+    cipher: PayloadCipher,
+    /// Written since the last frame was sealed
+    pending: Vec<u8>,
+    /// Sealed frame going out, of which `sent` bytes are written
+    outgoing: Vec<u8>,
+    sent: usize,
+    /// Read towards the next frame
+    incoming: Vec<u8>,
+    /// Opened frame, of which `consumed` bytes have been read
+    opened: Vec<u8>,
+    consumed: usize,
+}
+
+impl<S> SealedStream<S> {
+    pub fn new(inner: S, cipher: PayloadCipher) -> Self {
+        Self {
+            inner,
+            cipher,
+            pending: Vec::new(),
+            outgoing: Vec::new(),
+            sent: 0,
+            incoming: Vec::new(),
+            opened: Vec::new(),
+            consumed: 0,
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> SealedStream<S> {
+    /// Seal what is pending and write frames out until none are left
+    fn poll_send(&mut self, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if self.sent < self.outgoing.len() {
+                let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.outgoing[self.sent..]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                self.sent += n;
+            } else if !self.pending.is_empty() {
+                let sealed = self.cipher.seal(&self.pending);
+                self.pending.clear();
+                self.outgoing.clear();
+                self.outgoing.extend_from_slice(&(sealed.len() as u32).to_be_bytes());
+                self.outgoing.extend_from_slice(&sealed);
+                self.sent = 0;
+            } else {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for SealedStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.pending.len() >= MAX_FRAME {
+            ready!(this.poll_send(cx))?;
+        }
+        let n = buf.len().min(MAX_FRAME - this.pending.len());
+        this.pending.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for SealedStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.consumed < this.opened.len() || buf.remaining() == 0 {
+                let n = buf.remaining().min(this.opened.len() - this.consumed);
+                buf.put_slice(&this.opened[this.consumed..this.consumed + n]);
+                this.consumed += n;
+                return Poll::Ready(Ok(()));
+            }
+
+            if let Some(length) = this.incoming.get(..4) {
+                let length = u32::from_be_bytes(length.try_into().expect("four bytes")) as usize;
+                if length > MAX_FRAME + PayloadCipher::OVERHEAD {
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, format!("Sealed frame of {} bytes", length))));
+                }
+                if this.incoming.len() >= 4 + length {
+                    this.opened = this
+                        .cipher
+                        .open(&this.incoming[4..4 + length])
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                    this.consumed = 0;
+                    this.incoming.drain(..4 + length);
+                    continue;
+                }
+            }
+
+            let mut chunk = [0u8; 8192];
+            let mut read = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read))?;
+            if read.filled().is_empty() {
+                return Poll::Ready(if this.incoming.is_empty() {
+                    Ok(())
+                } else {
+                    Err(io::ErrorKind::UnexpectedEof.into())
+                });
+            }
+            this.incoming.extend_from_slice(read.filled());
+        }
+    }
+}
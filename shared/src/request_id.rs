@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Header and metadata key carrying a request's ID to the service; Cap'n
+/// Proto calls carry it in their `requestId` param
+pub const REQUEST_ID: &str = "x-request-id";
+
+/// Longest ID a service accepts; anything longer, or with spaces or control
+/// characters, is treated as missing
+pub const MAX_REQUEST_ID_LEN: usize = 64;
+
+static PREFIX: OnceLock<String> = OnceLock::new();
+static SEQUENCE: AtomicU64 = AtomicU64::new(1);
+static REQUEST_LOG: OnceLock<bool> = OnceLock::new();
+
+fn prefix() -> &'static str {
+    PREFIX.get_or_init(|| format!("{:08x}", rand::random::<u32>()))
+}
+
+/// A new ID for an outgoing request, e.g. `3f9a2c1e-42`: a prefix random to
+/// this process, so agents in a distributed run don't collide, and a
+/// sequence number counting up from 1 in the order requests go out
+pub fn next_request_id() -> String {
+    format!("{}-{}", prefix(), SEQUENCE.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Sequence number of the next ID `next_request_id` hands out. Read before
+/// and after some requests, it brackets the IDs they were sent with.
+pub fn next_request_sequence() -> u64 {
+    SEQUENCE.load(Ordering::Relaxed)
+}
+
+/// The IDs handed out from sequence `start` up to, not including, `end`:
+/// `3f9a2c1e-120..=3f9a2c1e-125`, a single ID, or `none`
+pub fn request_id_range(start: u64, end: u64) -> String {
+    match end.saturating_sub(start) {
+        0 => "none".to_string(),
+        1 => format!("{}-{}", prefix(), start),
+        _ => format!("{}-{}..={}-{}", prefix(), start, prefix(), end - 1),
+    }
+}
+
+/// `value` if it is usable as a request ID: 1 to `MAX_REQUEST_ID_LEN`
+/// printable ASCII characters, so logging it can't break the line
+pub fn valid_request_id(value: &str) -> Option<&str> {
+    let printable = value.bytes().all(|byte| byte.is_ascii_graphic());
+    (!value.is_empty() && value.len() <= MAX_REQUEST_ID_LEN && printable).then_some(value)
+}
+
+/// Whether `PROTOBENCH_REQUEST_LOG` is set to anything but `0`, read once
+/// per process
+pub fn request_log_enabled() -> bool {
+    *REQUEST_LOG.get_or_init(|| std::env::var("PROTOBENCH_REQUEST_LOG").is_ok_and(|value| value != "0"))
+}
+
+/// When the request log is enabled, write a line for one handled request to
+/// stderr, e.g. `request_id=3f9a2c1e-42 service=grpc method="SubmitMetric"
+/// status=0 duration_us=153`. Requests that came without an ID show `-`.
+pub fn log_request(service: &str, method: &str, request_id: Option<&str>, status: &str, elapsed: Duration) {
+    if !request_log_enabled() {
+        return;
+    }
+    eprintln!(
+        "request_id={} service={} method={:?} status={} duration_us={}",
+        request_id.unwrap_or("-"),
+        service,
+        method,
+        status,
+        elapsed.as_micros()
+    );
+}
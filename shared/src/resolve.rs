@@ -0,0 +1,101 @@
+use anyhow::Context;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::{Mutex, OnceLock};
+
+use crate::FileConfig;
+
+static DNS_CONFIG: OnceLock<DnsConfig> = OnceLock::new();
+static PINNED: OnceLock<Mutex<HashMap<String, Vec<SocketAddr>>>> = OnceLock::new();
+
+/// How clients turn their targets' hostnames into addresses. By default each
+/// new connection looks its host up, so REST and gRPC numbers taken against
+/// a hostname include however long the resolver happened to take.
+///
+/// `PROTOBENCH_RESOLVE` (e.g. `metrics.internal=10.0.0.5,db=10.0.0.6`), then
+/// `[resolve]` in `protobench.toml`, gives hosts fixed addresses like curl's
+/// `--resolve`, so they are never looked up. `PROTOBENCH_DNS=pinned` looks
+/// every other host up once per process and reuses the addresses.
+/// Hostnames stay in URLs either way, so TLS and `Host` are unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DnsConfig {
+    pub overrides: BTreeMap<String, IpAddr>,
+    pub pinned: bool,
+}
+
+/// Host and port of a `host:port` target; IPv6 hosts are given in brackets
+pub fn split_host_port(target: &str) -> Option<(&str, u16)> {
+    let (host, port) = target.rsplit_once(':')?;
+    let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+    Some((host, port.parse().ok()?))
+}
+
+impl DnsConfig {
+    /// Parse a comma-separated list of `host=ip` pairs
+    pub fn parse_overrides(list: &str) -> anyhow::Result<BTreeMap<String, IpAddr>> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (host, ip) = pair.split_once('=').with_context(|| format!("Expected host=ip, got {:?}", pair))?;
+                let ip = ip.trim().parse().with_context(|| format!("Invalid address for {}: {:?}", host.trim(), ip))?;
+                Ok((host.trim().to_string(), ip))
+            })
+            .collect()
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        let overrides = match std::env::var("PROTOBENCH_RESOLVE") {
+            Ok(list) => Self::parse_overrides(&list).context("Invalid PROTOBENCH_RESOLVE")?,
+            Err(_) => FileConfig::get().resolve.clone(),
+        };
+        let pinned = match std::env::var("PROTOBENCH_DNS").as_deref() {
+            Ok("pinned") => true,
+            Ok("system") | Err(_) => false,
+            Ok(other) => anyhow::bail!("Unknown PROTOBENCH_DNS {:?}; use system or pinned", other),
+        };
+        Ok(Self { overrides, pinned })
+    }
+
+    /// The config, read once per process. An invalid one is reported on
+    /// stderr and ignored, leaving lookups to the system resolver.
+    pub fn get() -> &'static Self {
+        DNS_CONFIG.get_or_init(|| {
+            Self::from_env().unwrap_or_else(|e| {
+                eprintln!("Ignoring DNS settings: {:#}", e);
+                Self::default()
+            })
+        })
+    }
+
+    /// Addresses for `target` that need no lookup: the target itself when
+    /// its host is an IP address, or its host's override
+    pub fn static_addrs(&self, target: &str) -> Option<Vec<SocketAddr>> {
+        if let Ok(addr) = target.parse::<SocketAddr>() {
+            return Some(vec![addr]);
+        }
+        let (host, port) = split_host_port(target)?;
+        let ip = self.overrides.get(host)?;
+        Some(vec![SocketAddr::new(*ip, port)])
+    }
+
+    /// Addresses to connect to for `target`, or `None` to leave the lookup to
+    /// each connection. A pinned lookup blocks the calling thread, once per
+    /// target per process.
+    pub fn resolve(&self, target: &str) -> std::io::Result<Option<Vec<SocketAddr>>> {
+        if let Some(addrs) = self.static_addrs(target) {
+            return Ok(Some(addrs));
+        }
+        if !self.pinned {
+            return Ok(None);
+        }
+        let pinned = PINNED.get_or_init(Default::default);
+        if let Some(addrs) = pinned.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).get(target) {
+            return Ok(Some(addrs.clone()));
+        }
+        // Looked up outside the lock; racing callers just look up twice
+        let addrs: Vec<SocketAddr> = target.to_socket_addrs()?.collect();
+        pinned.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(target.to_string(), addrs.clone());
+        Ok(Some(addrs))
+    }
+}
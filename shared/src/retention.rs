@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+const DEFAULT_EVICTION_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Bounds on how much data `InMemoryStorage` keeps around.
+///
+/// Long soak benchmarks submit points continuously; without a bound the
+/// services grow until the host runs out of memory and the run is lost.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// Drop points whose timestamp is older than `now - max_age`
+    pub max_age: Option<Duration>,
+    /// Keep at most this many points, evicting the oldest inserted first
+    pub max_points: Option<usize>,
+    /// How often the background task enforces the policy; must be non-zero
+    pub eviction_interval: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            max_points: None,
+            eviction_interval: DEFAULT_EVICTION_INTERVAL,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Read the policy from `PROTOBENCH_RETENTION_SECS`,
+    /// `PROTOBENCH_RETENTION_MAX_POINTS` and `PROTOBENCH_EVICTION_INTERVAL_SECS`.
+    /// Unset or unparsable variables leave the corresponding bound disabled;
+    /// an unset, unparsable or zero interval falls back to 5 seconds.
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// `from_env`, with each variable's value looked up through `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let u64_var = |name: &str| var(name)?.parse::<u64>().ok();
+        let max_age = u64_var("PROTOBENCH_RETENTION_SECS").map(Duration::from_secs);
+        let max_points = u64_var("PROTOBENCH_RETENTION_MAX_POINTS").map(|n| n as usize);
+        // tokio::time::interval panics on a zero period
+        let eviction_interval = u64_var("PROTOBENCH_EVICTION_INTERVAL_SECS")
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_EVICTION_INTERVAL);
+
+        Self {
+            max_age,
+            max_points,
+            eviction_interval,
+        }
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.max_age.is_none() && self.max_points.is_none()
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+use crate::MetricPoint;
+
+pub const ROLLUP_BUCKET_SECONDS: i64 = 60;
+
+/// Per-minute, per-hostname aggregate maintained alongside the raw points
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MetricRollup {
+    pub hostname: String,
+    pub minute_start: i64,
+    pub count: u64,
+    pub avg_cpu_percent: f32,
+    pub min_cpu_percent: f32,
+    pub max_cpu_percent: f32,
+    pub avg_memory_bytes: u64,
+    pub min_memory_bytes: u64,
+    pub max_memory_bytes: u64,
+    pub avg_disk_io_ops: f32,
+    pub min_disk_io_ops: u32,
+    pub max_disk_io_ops: u32,
+}
+
+/// Align a timestamp (epoch seconds) to the start of its rollup bucket.
+/// Saturates at `i64::MIN` so open-ended query bounds don't overflow.
+pub fn bucket_start(timestamp: i64) -> i64 {
+    timestamp.saturating_sub(timestamp.rem_euclid(ROLLUP_BUCKET_SECONDS))
+}
+
+// Running sums rather than averages so buckets can be updated in O(1) per point
+#[derive(Debug, Clone)]
+pub(crate) struct RollupAccumulator {
+    count: u64,
+    cpu_sum: f64,
+    cpu_min: f32,
+    cpu_max: f32,
+    memory_sum: u128,
+    memory_min: u64,
+    memory_max: u64,
+    disk_io_sum: u64,
+    disk_io_min: u32,
+    disk_io_max: u32,
+}
+
+impl RollupAccumulator {
+    pub(crate) fn new(metric: &MetricPoint) -> Self {
+        Self {
+            count: 1,
+            cpu_sum: metric.cpu_percent as f64,
+            cpu_min: metric.cpu_percent,
+            cpu_max: metric.cpu_percent,
+            memory_sum: metric.memory_bytes as u128,
+            memory_min: metric.memory_bytes,
+            memory_max: metric.memory_bytes,
+            disk_io_sum: metric.disk_io_ops as u64,
+            disk_io_min: metric.disk_io_ops,
+            disk_io_max: metric.disk_io_ops,
+        }
+    }
+
+    pub(crate) fn add(&mut self, metric: &MetricPoint) {
+        self.count += 1;
+        self.cpu_sum += metric.cpu_percent as f64;
+        self.cpu_min = self.cpu_min.min(metric.cpu_percent);
+        self.cpu_max = self.cpu_max.max(metric.cpu_percent);
+        self.memory_sum += metric.memory_bytes as u128;
+        self.memory_min = self.memory_min.min(metric.memory_bytes);
+        self.memory_max = self.memory_max.max(metric.memory_bytes);
+        self.disk_io_sum += metric.disk_io_ops as u64;
+        self.disk_io_min = self.disk_io_min.min(metric.disk_io_ops);
+        self.disk_io_max = self.disk_io_max.max(metric.disk_io_ops);
+    }
+
+    /// Swap `old`, one of the points already counted, for `new`. Sums stay
+    /// exact, but min and max can only widen: what `old` set them to is gone.
+    pub(crate) fn replace(&mut self, old: &MetricPoint, new: &MetricPoint) {
+        self.cpu_sum += new.cpu_percent as f64 - old.cpu_percent as f64;
+        self.cpu_min = self.cpu_min.min(new.cpu_percent);
+        self.cpu_max = self.cpu_max.max(new.cpu_percent);
+        self.memory_sum = self.memory_sum - old.memory_bytes as u128 + new.memory_bytes as u128;
+        self.memory_min = self.memory_min.min(new.memory_bytes);
+        self.memory_max = self.memory_max.max(new.memory_bytes);
+        self.disk_io_sum = self.disk_io_sum - old.disk_io_ops as u64 + new.disk_io_ops as u64;
+        self.disk_io_min = self.disk_io_min.min(new.disk_io_ops);
+        self.disk_io_max = self.disk_io_max.max(new.disk_io_ops);
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub(crate) fn to_rollup(&self, hostname: &str, minute_start: i64) -> MetricRollup {
+        MetricRollup {
+            hostname: hostname.to_string(),
+            minute_start,
+            count: self.count,
+            avg_cpu_percent: (self.cpu_sum / self.count as f64) as f32,
+            min_cpu_percent: self.cpu_min,
+            max_cpu_percent: self.cpu_max,
+            avg_memory_bytes: (self.memory_sum / self.count as u128) as u64,
+            min_memory_bytes: self.memory_min,
+            max_memory_bytes: self.memory_max,
+            avg_disk_io_ops: (self.disk_io_sum as f64 / self.count as f64) as f32,
+            min_disk_io_ops: self.disk_io_min,
+            max_disk_io_ops: self.disk_io_max,
+        }
+    }
+}
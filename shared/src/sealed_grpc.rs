@@ -0,0 +1,179 @@
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::codegen::{Body, BoxFuture, Bytes, Service, StdError};
+use tonic::server::NamedService;
+use tonic::Status;
+
+use crate::PayloadCipher;
+
+// Compression flag, then the message length as a big-endian u32
+const FRAME_HEADER_LEN: usize = 5;
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Seal,
+    Open,
+}
+
+/// Seals each gRPC message with a `PayloadCipher`, around a generated server
+/// (`SealedGrpc::server`) or a client's channel (`SealedGrpc::client`). The
+/// message is sealed inside its length-prefixed frame after tonic has encoded
+/// and compressed it, so compression still sees plaintext. Without a cipher
+/// every body passes through as it is.
+#[derive(Clone)]
+pub struct SealedGrpc<S> {
+    inner: S,
+    cipher: Option<PayloadCipher>,
+    requests: Direction,
+    responses: Direction,
+}
+
+impl<S> SealedGrpc<S> {
+    /// Opens the requests `inner` receives and seals its responses
+    pub fn server(inner: S, cipher: Option<PayloadCipher>) -> Self {
+        Self {
+            inner,
+            cipher,
+            requests: Direction::Open,
+            responses: Direction::Seal,
+        }
+    }
+
+    /// Seals the requests sent through `inner` and opens the responses
+    pub fn client(inner: S, cipher: Option<PayloadCipher>) -> Self {
+        Self {
+            inner,
+            cipher,
+            requests: Direction::Seal,
+            responses: Direction::Open,
+        }
+    }
+}
+
+impl<S: NamedService> NamedService for SealedGrpc<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SealedGrpc<S>
+where
+    S: Service<Request<BoxBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: 'static,
+    ReqBody: Body<Data = Bytes> + Unpin + Send + 'static,
+    ReqBody::Error: Into<StdError>,
+    ResBody: Body<Data = Bytes> + Unpin + Send + 'static,
+    ResBody::Error: Into<StdError>,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let request = request.map(|body| BoxBody::new(SealedBody::new(body, self.cipher.clone(), self.requests)));
+        let response = self.inner.call(request);
+        let cipher = self.cipher.clone();
+        let direction = self.responses;
+        Box::pin(async move { Ok(response.await?.map(|body| BoxBody::new(SealedBody::new(body, cipher, direction)))) })
+    }
+}
+
+/// A gRPC body with each message frame sealed or opened as it passes
+struct SealedBody<B> {
+    inner: B,
+    cipher: Option<PayloadCipher>,
+    direction: Direction,
+    // Bytes of a frame not yet complete
+    pending: Vec<u8>,
+    inner_done: bool,
+}
+
+impl<B> SealedBody<B> {
+    fn new(inner: B, cipher: Option<PayloadCipher>, direction: Direction) -> Self {
+        Self {
+            inner,
+            cipher,
+            direction,
+            pending: Vec::new(),
+            inner_done: false,
+        }
+    }
+
+    /// The next complete frame in `pending` with its message sealed or opened
+    fn next_frame(&mut self, cipher: &PayloadCipher) -> Option<Result<Bytes, Status>> {
+        let header = self.pending.get(..FRAME_HEADER_LEN)?;
+        let message_len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        if self.pending.len() < FRAME_HEADER_LEN + message_len {
+            return None;
+        }
+
+        let frame: Vec<u8> = self.pending.drain(..FRAME_HEADER_LEN + message_len).collect();
+        let message = &frame[FRAME_HEADER_LEN..];
+        let message = match self.direction {
+            Direction::Seal => cipher.seal(message),
+            Direction::Open => match cipher.open(message) {
+                Ok(message) => message,
+                Err(e) => return Some(Err(Status::invalid_argument(e.to_string()))),
+            },
+        };
+
+        let mut sealed = Vec::with_capacity(FRAME_HEADER_LEN + message.len());
+        sealed.push(frame[0]);
+        sealed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        sealed.extend_from_slice(&message);
+        Some(Ok(Bytes::from(sealed)))
+    }
+}
+
+impl<B> Body for SealedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<StdError>,
+{
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Status>>> {
+        let this = self.get_mut();
+        let Some(cipher) = this.cipher.clone() else {
+            return match ready!(Pin::new(&mut this.inner).poll_data(cx)) {
+                Some(Ok(data)) => Poll::Ready(Some(Ok(data))),
+                Some(Err(e)) => Poll::Ready(Some(Err(Status::from_error(e.into())))),
+                None => Poll::Ready(None),
+            };
+        };
+
+        loop {
+            if let Some(frame) = this.next_frame(&cipher) {
+                return Poll::Ready(Some(frame));
+            }
+            if this.inner_done {
+                if this.pending.is_empty() {
+                    return Poll::Ready(None);
+                }
+                this.pending.clear();
+                return Poll::Ready(Some(Err(Status::internal("gRPC body ended part-way through a message"))));
+            }
+            match ready!(Pin::new(&mut this.inner).poll_data(cx)) {
+                Some(Ok(data)) => this.pending.extend_from_slice(&data),
+                Some(Err(e)) => return Poll::Ready(Some(Err(Status::from_error(e.into())))),
+                None => this.inner_done = true,
+            }
+        }
+    }
+
+    fn poll_trailers(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, Status>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_trailers(cx)
+            .map_err(|e| Status::from_error(e.into()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream() && self.pending.is_empty()
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/// Resolves on SIGINT (Ctrl+C) or, on Unix, SIGTERM. Services stop accepting
+/// connections when it fires and drain the ones already open.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            eprintln!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                eprintln!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// How long to wait for open connections to finish after a shutdown signal,
+/// from `PROTOBENCH_SHUTDOWN_GRACE_SECS` (default 10). Connections still open
+/// after that are dropped.
+pub fn shutdown_grace_period() -> Duration {
+    let seconds = std::env::var("PROTOBENCH_SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10);
+    Duration::from_secs(seconds)
+}
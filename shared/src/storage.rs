@@ -0,0 +1,660 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+use crate::live::{LiveFeed, Subscription};
+use crate::rollup::RollupAccumulator;
+use crate::wal::{WalRecord, WriteAheadLog};
+use crate::{
+    bucket_start, DedupPolicy, MetricPoint, MetricQuery, MetricRollup, MetricStatistics,
+    MetricsService, RetentionPolicy, StorageStats, WalConfig, ROLLUP_BUCKET_SECONDS,
+};
+
+/// Storage backend contract used by every service. Async so handlers never
+/// park a runtime worker thread on a lock while other requests hold it.
+pub trait MetricsStorage: Send + Sync {
+    fn store_metric(&self, metric: MetricPoint) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+    fn store_metrics(&self, batch: Vec<MetricPoint>) -> impl Future<Output = Result<usize, anyhow::Error>> + Send;
+    fn query_metrics(&self, query: &MetricQuery) -> impl Future<Output = Result<Vec<MetricPoint>, anyhow::Error>> + Send;
+    fn query_rollups(&self, query: &MetricQuery) -> impl Future<Output = Result<Vec<MetricRollup>, anyhow::Error>> + Send;
+    fn calculate_statistics(&self, query: &MetricQuery) -> impl Future<Output = Result<MetricStatistics, anyhow::Error>> + Send;
+    fn delete_metrics(&self, query: &MetricQuery) -> impl Future<Output = Result<u64, anyhow::Error>> + Send;
+    fn storage_stats(&self) -> impl Future<Output = Result<StorageStats, anyhow::Error>> + Send;
+}
+
+// Rollup buckets keyed by (hostname, minute_start)
+type RollupIndex = BTreeMap<(String, i64), RollupAccumulator>;
+
+// Position in `metrics` of the point stored for each (hostname, timestamp);
+// only maintained while deduplication is enabled. Ordered so the points in a
+// rollup bucket are one range of it.
+type DedupIndex = BTreeMap<(String, i64), usize>;
+
+// Lock order is always metrics, then rollups, then the dedup index
+pub struct InMemoryStorage {
+    metrics: Arc<RwLock<Vec<MetricPoint>>>,
+    rollups: Arc<RwLock<RollupIndex>>,
+    dedup_index: Arc<RwLock<DedupIndex>>,
+    retention: RetentionPolicy,
+    dedup: Option<DedupPolicy>,
+    wal: Option<WriteAheadLog>,
+    live: LiveFeed,
+}
+
+impl Default for InMemoryStorage {
+    fn default() -> Self {
+        Self {
+            metrics: Arc::new(RwLock::new(Vec::new())),
+            rollups: Arc::new(RwLock::new(BTreeMap::new())),
+            dedup_index: Arc::new(RwLock::new(BTreeMap::new())),
+            retention: RetentionPolicy::default(),
+            dedup: None,
+            wal: None,
+            live: LiveFeed::default(),
+        }
+    }
+}
+
+impl InMemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_retention(retention: RetentionPolicy) -> Self {
+        Self {
+            retention,
+            ..Self::default()
+        }
+    }
+
+    /// Build storage the way the services run it: retention and dedup from the
+    /// environment, then either the optional snapshot or WAL replay. The WAL
+    /// alone holds everything written, so the two can't be combined.
+    pub async fn from_env() -> Result<Self, anyhow::Error> {
+        Self::from_vars(|name| std::env::var(name).ok()).await
+    }
+
+    /// `from_env`, with each variable's value looked up through `var`
+    pub async fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, anyhow::Error> {
+        let mut storage = Self::with_retention(RetentionPolicy::from_vars(&var));
+        if let Some(policy) = DedupPolicy::from_vars(&var)? {
+            storage.enable_dedup(policy)?;
+        }
+
+        let wal = WalConfig::from_vars(&var);
+        let snapshot = var("PROTOBENCH_SNAPSHOT_PATH");
+        if wal.is_some() && snapshot.is_some() {
+            anyhow::bail!(
+                "PROTOBENCH_SNAPSHOT_PATH and PROTOBENCH_WAL_PATH can't both be set: \
+                 WAL replay restores every write, so it would insert the snapshot's points twice"
+            );
+        }
+
+        if let Some(path) = snapshot {
+            let count = storage.restore(&path).await?;
+            println!("Restored {} metrics from snapshot", count);
+        }
+        if let Some(config) = wal {
+            let replayed = storage.enable_wal(&config)?;
+            println!("Replayed {} WAL records from {}", replayed, config.path.display());
+        }
+
+        Ok(storage)
+    }
+
+    /// Replay any existing log at `config.path` into empty storage, then log
+    /// every subsequent mutation there. Returns the number of records replayed.
+    ///
+    /// Writes are queued to the log in the order they are applied and
+    /// acknowledged once on disk, so a caller never sees success for a write
+    /// a crash would lose; other readers may see it slightly before that.
+    /// Eviction is not logged: once the log holds more than twice the points
+    /// storage does, eviction rewrites it with just the remaining ones.
+    pub fn enable_wal(&mut self, config: &WalConfig) -> Result<usize, anyhow::Error> {
+        // Exclusive access through &mut self, so no lock needs to be awaited
+        let metrics = Arc::get_mut(&mut self.metrics)
+            .ok_or_else(|| anyhow::anyhow!("WAL must be enabled before storage is shared"))?
+            .get_mut();
+        // Replay would add the logged points again on top of ones already here
+        if !metrics.is_empty() {
+            anyhow::bail!("WAL must be enabled on empty storage, it already holds {} points", metrics.len());
+        }
+
+        let (wal, records) = WriteAheadLog::open(config)?;
+        let replayed = records.len();
+        let rollups = Arc::get_mut(&mut self.rollups)
+            .ok_or_else(|| anyhow::anyhow!("WAL must be enabled before storage is shared"))?
+            .get_mut();
+        let dedup_index = Arc::get_mut(&mut self.dedup_index)
+            .ok_or_else(|| anyhow::anyhow!("WAL must be enabled before storage is shared"))?
+            .get_mut();
+
+        // Replay goes through the same insert path, so dedup applies exactly as it did live
+        for record in records {
+            match record {
+                WalRecord::Insert { metrics: batch } => {
+                    Self::insert_locked(self.dedup, metrics, rollups, dedup_index, batch);
+                }
+                WalRecord::Delete { query } => {
+                    Self::delete_locked(self.dedup, metrics, rollups, dedup_index, &query);
+                }
+            }
+        }
+
+        self.wal = Some(wal);
+        Ok(replayed)
+    }
+
+    /// Deduplicate inserts on (timestamp, hostname) from now on. Points already
+    /// stored are indexed but not merged. Must be called before the WAL is
+    /// enabled so replay follows the same policy.
+    pub fn enable_dedup(&mut self, policy: DedupPolicy) -> Result<(), anyhow::Error> {
+        let metrics = Arc::get_mut(&mut self.metrics)
+            .ok_or_else(|| anyhow::anyhow!("Dedup must be enabled before storage is shared"))?
+            .get_mut();
+        let dedup_index = Arc::get_mut(&mut self.dedup_index)
+            .ok_or_else(|| anyhow::anyhow!("Dedup must be enabled before storage is shared"))?
+            .get_mut();
+
+        Self::reindex(metrics, dedup_index);
+        self.dedup = Some(policy);
+        Ok(())
+    }
+
+    pub fn dedup_policy(&self) -> Option<DedupPolicy> {
+        self.dedup
+    }
+
+    pub fn retention(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+
+    /// Follow points as they are stored from now on. Every submitted point is
+    /// delivered, including ones that dedup then drops or merges; replay and
+    /// restore are not.
+    pub fn subscribe(&self) -> Subscription {
+        self.live.subscribe()
+    }
+
+    /// End all live subscriptions, see `LiveFeed::close`
+    pub fn close_subscriptions(&self) {
+        self.live.close();
+    }
+
+    /// Apply the retention policy once, returning how many points were dropped
+    pub async fn evict_expired(&self) -> Result<usize, anyhow::Error> {
+        if self.retention.is_unbounded() {
+            return Ok(0);
+        }
+
+        let mut metrics = self.metrics.write().await;
+        let before = metrics.len();
+
+        if let Some(max_age) = self.retention.max_age {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO)
+                .as_secs() as i64;
+            let cutoff = now - max_age.as_secs() as i64;
+            metrics.retain(|metric| metric.timestamp >= cutoff);
+
+            // Rollups are not capped by max_points (outliving raw points is their purpose),
+            // but buckets entirely older than the age cutoff are dropped
+            let mut rollups = self.rollups.write().await;
+            rollups.retain(|(_, minute_start), _| minute_start + ROLLUP_BUCKET_SECONDS > cutoff);
+        }
+
+        if let Some(max_points) = self.retention.max_points {
+            // Points are appended in arrival order, so the front holds the oldest inserts
+            if metrics.len() > max_points {
+                let excess = metrics.len() - max_points;
+                metrics.drain(..excess);
+            }
+        }
+
+        let evicted = before - metrics.len();
+        if evicted > 0 && self.dedup.is_some() {
+            Self::reindex(&metrics, &mut *self.dedup_index.write().await);
+        }
+
+        // Queued under the metrics lock so the rewrite lands between the same
+        // logged writes as the eviction did
+        let compaction = match &self.wal {
+            Some(wal) if evicted > 0 && wal.needs_compaction(metrics.len()) => Some(wal.compact(metrics.to_vec())?),
+            _ => None,
+        };
+        drop(metrics);
+        if let Some(compaction) = compaction {
+            compaction.wait().await?;
+        }
+
+        Ok(evicted)
+    }
+
+    /// Spawn a tokio task that enforces the retention policy every
+    /// `eviction_interval`. Returns `None` when the policy is unbounded.
+    pub fn spawn_eviction_task(self: &Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        if self.retention.is_unbounded() {
+            return None;
+        }
+
+        let storage = Arc::clone(self);
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(storage.retention.eviction_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = storage.evict_expired().await {
+                    eprintln!("Retention eviction failed: {}", e);
+                }
+            }
+        }))
+    }
+
+    /// Write every stored point to `path` as a JSON array. The points are
+    /// copied out so writes aren't held up by the file IO, which runs on the
+    /// blocking pool. The file is written and synced next to its destination,
+    /// then renamed into place, so a crash never leaves a truncated snapshot
+    /// behind.
+    pub async fn snapshot(&self, path: impl AsRef<Path>) -> Result<usize, anyhow::Error> {
+        let path = path.as_ref().to_path_buf();
+        let metrics = self.metrics.read().await.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let tmp_path = path.with_extension("tmp");
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            serde_json::to_writer(&mut writer, &metrics)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+            std::fs::rename(&tmp_path, &path)?;
+            Ok(metrics.len())
+        })
+        .await?
+    }
+
+    /// Replace the storage contents with the points in a snapshot written by
+    /// `snapshot`, rebuilding rollups from the restored points. With the WAL
+    /// enabled, the log is rewritten to hold just the restored points.
+    pub async fn restore(&self, path: impl AsRef<Path>) -> Result<usize, anyhow::Error> {
+        let reader = BufReader::new(File::open(path.as_ref())?);
+        let restored: Vec<MetricPoint> = serde_json::from_reader(reader)?;
+
+        let (count, compaction) = {
+            let mut metrics = self.metrics.write().await;
+            let mut rollups = self.rollups.write().await;
+            let mut dedup_index = self.dedup_index.write().await;
+            metrics.clear();
+            rollups.clear();
+            dedup_index.clear();
+            Self::insert_locked(self.dedup, &mut metrics, &mut rollups, &mut dedup_index, restored);
+            let compaction = self.wal.as_ref().map(|wal| wal.compact(metrics.to_vec())).transpose()?;
+            (metrics.len(), compaction)
+        };
+
+        if let Some(compaction) = compaction {
+            compaction.wait().await?;
+        }
+        Ok(count)
+    }
+
+    /// Restore from the snapshot named by `PROTOBENCH_SNAPSHOT_PATH`, if set.
+    /// Returns the number of points loaded, or `None` when no snapshot is configured.
+    pub async fn restore_from_env(&self) -> Result<Option<usize>, anyhow::Error> {
+        match std::env::var("PROTOBENCH_SNAPSHOT_PATH") {
+            Ok(path) => self.restore(&path).await.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn update_rollup(rollups: &mut RollupIndex, metric: &MetricPoint) {
+        let key = (metric.hostname.clone(), bucket_start(metric.timestamp));
+        match rollups.get_mut(&key) {
+            Some(accumulator) => accumulator.add(metric),
+            None => {
+                rollups.insert(key, RollupAccumulator::new(metric));
+            }
+        }
+    }
+
+    /// Append a batch to already-locked storage, merging duplicates when dedup is
+    /// enabled. Returns how many points were written (appended or overwritten).
+    fn insert_locked(
+        dedup: Option<DedupPolicy>,
+        metrics: &mut Vec<MetricPoint>,
+        rollups: &mut RollupIndex,
+        dedup_index: &mut DedupIndex,
+        batch: Vec<MetricPoint>,
+    ) -> usize {
+        let Some(policy) = dedup else {
+            for metric in &batch {
+                Self::update_rollup(rollups, metric);
+            }
+            let stored = batch.len();
+            metrics.reserve(stored);
+            metrics.extend(batch);
+            return stored;
+        };
+
+        let mut stale_buckets = BTreeSet::new();
+        let mut stored = 0;
+        for metric in batch {
+            let key = (metric.hostname.clone(), metric.timestamp);
+            match dedup_index.get(&key) {
+                None => {
+                    dedup_index.insert(key, metrics.len());
+                    Self::update_rollup(rollups, &metric);
+                    metrics.push(metric);
+                    stored += 1;
+                }
+                Some(_) if policy == DedupPolicy::KeepFirst => {}
+                Some(&position) => {
+                    let (hostname, timestamp) = key;
+                    let bucket = (hostname, bucket_start(timestamp));
+                    if let Some(accumulator) = rollups.get_mut(&bucket) {
+                        accumulator.replace(&metrics[position], &metric);
+                    }
+                    stale_buckets.insert(bucket);
+                    metrics[position] = metric;
+                    stored += 1;
+                }
+            }
+        }
+
+        // A bucket that outlived some of its raw points (max_points eviction
+        // keeps rollups) would shrink if rebuilt, so it keeps the adjusted
+        // accumulator; the rest are rebuilt so an overwritten min or max goes
+        stale_buckets.retain(|(hostname, minute_start)| {
+            let raw_points = Self::bucket_positions(dedup_index, hostname, *minute_start).count() as u64;
+            rollups.get(&(hostname.clone(), *minute_start)).map(RollupAccumulator::count) == Some(raw_points)
+        });
+        Self::rebuild_indexed_buckets(metrics, rollups, dedup_index, &stale_buckets);
+        stored
+    }
+
+    fn reindex(metrics: &[MetricPoint], dedup_index: &mut DedupIndex) {
+        dedup_index.clear();
+        for (position, metric) in metrics.iter().enumerate() {
+            dedup_index.insert((metric.hostname.clone(), metric.timestamp), position);
+        }
+    }
+
+    // Min/max can't be subtracted out of an accumulator, so touched buckets are
+    // rebuilt from the raw points that remain
+    fn rebuild_buckets(metrics: &[MetricPoint], rollups: &mut RollupIndex, buckets: &BTreeSet<(String, i64)>) {
+        if buckets.is_empty() {
+            return;
+        }
+        for key in buckets {
+            rollups.remove(key);
+        }
+        for metric in metrics {
+            if buckets.contains(&(metric.hostname.clone(), bucket_start(metric.timestamp))) {
+                Self::update_rollup(rollups, metric);
+            }
+        }
+    }
+
+    // Same as `rebuild_buckets`, but reads each bucket's points from its range
+    // of the dedup index instead of scanning every stored point
+    fn rebuild_indexed_buckets(
+        metrics: &[MetricPoint],
+        rollups: &mut RollupIndex,
+        dedup_index: &DedupIndex,
+        buckets: &BTreeSet<(String, i64)>,
+    ) {
+        for (hostname, minute_start) in buckets {
+            let mut accumulator: Option<RollupAccumulator> = None;
+            for position in Self::bucket_positions(dedup_index, hostname, *minute_start) {
+                let metric = &metrics[position];
+                match &mut accumulator {
+                    Some(accumulator) => accumulator.add(metric),
+                    None => accumulator = Some(RollupAccumulator::new(metric)),
+                }
+            }
+
+            let key = (hostname.clone(), *minute_start);
+            match accumulator {
+                Some(accumulator) => {
+                    rollups.insert(key, accumulator);
+                }
+                None => {
+                    rollups.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Positions of the stored points in one host's bucket, from its range of the dedup index
+    fn bucket_positions<'a>(dedup_index: &'a DedupIndex, hostname: &str, minute_start: i64) -> impl Iterator<Item = usize> + 'a {
+        let first = (hostname.to_string(), minute_start);
+        let last = (hostname.to_string(), minute_start.saturating_add(ROLLUP_BUCKET_SECONDS - 1));
+        dedup_index.range(first..=last).map(|(_, &position)| position)
+    }
+
+    fn delete_locked(
+        dedup: Option<DedupPolicy>,
+        metrics: &mut Vec<MetricPoint>,
+        rollups: &mut RollupIndex,
+        dedup_index: &mut DedupIndex,
+        query: &MetricQuery,
+    ) -> u64 {
+        let mut affected_buckets = BTreeSet::new();
+        let before = metrics.len();
+        metrics.retain(|metric| {
+            if query.matches(metric) {
+                affected_buckets.insert((metric.hostname.clone(), bucket_start(metric.timestamp)));
+                false
+            } else {
+                true
+            }
+        });
+        let deleted = (before - metrics.len()) as u64;
+
+        Self::rebuild_buckets(metrics, rollups, &affected_buckets);
+        if deleted > 0 && dedup.is_some() {
+            Self::reindex(metrics, dedup_index);
+        }
+
+        deleted
+    }
+}
+
+impl MetricsStorage for InMemoryStorage {
+    async fn store_metric(&self, metric: MetricPoint) -> Result<(), anyhow::Error> {
+        let commit = {
+            let mut metrics = self.metrics.write().await;
+            let mut rollups = self.rollups.write().await;
+            let mut dedup_index = self.dedup_index.write().await;
+            // Queued under the locks so the log order matches storage order
+            let commit = self.wal.as_ref()
+                .map(|wal| wal.append(WalRecord::Insert { metrics: vec![metric.clone()] }))
+                .transpose()?;
+            let live = self.live.has_subscribers().then(|| vec![metric.clone()]);
+            Self::insert_locked(self.dedup, &mut metrics, &mut rollups, &mut dedup_index, vec![metric]);
+            // Published under the write lock so subscribers see points in storage order
+            if let Some(live) = live {
+                self.live.publish(live);
+            }
+            commit
+        };
+
+        // The write (and fsync) happens on the WAL thread, awaited with the locks released
+        if let Some(commit) = commit {
+            commit.wait().await?;
+        }
+        Ok(())
+    }
+
+    /// Store a batch, returning how many points were written. Under keep-first
+    /// dedup, duplicates that were dropped are not counted.
+    async fn store_metrics(&self, batch: Vec<MetricPoint>) -> Result<usize, anyhow::Error> {
+        let (stored, commit) = {
+            // Single lock acquisition for the whole batch instead of one per point
+            let mut metrics = self.metrics.write().await;
+            let mut rollups = self.rollups.write().await;
+            let mut dedup_index = self.dedup_index.write().await;
+            let commit = self.wal.as_ref()
+                .map(|wal| wal.append(WalRecord::Insert { metrics: batch.clone() }))
+                .transpose()?;
+            let live = self.live.has_subscribers().then(|| batch.clone());
+            let stored = Self::insert_locked(self.dedup, &mut metrics, &mut rollups, &mut dedup_index, batch);
+            if let Some(live) = live {
+                self.live.publish(live);
+            }
+            (stored, commit)
+        };
+
+        if let Some(commit) = commit {
+            commit.wait().await?;
+        }
+        Ok(stored)
+    }
+
+    /// Per-minute rollups for every bucket overlapping the query's time range
+    async fn query_rollups(&self, query: &MetricQuery) -> Result<Vec<MetricRollup>, anyhow::Error> {
+        let rollups = self.rollups.read().await;
+        let first_bucket = bucket_start(query.start_time);
+
+        let mut result: Vec<MetricRollup> = rollups
+            .iter()
+            .filter(|((_, minute_start), _)| {
+                *minute_start >= first_bucket && *minute_start <= query.end_time
+            })
+            .filter(|((hostname, _), _)| {
+                query.hostname_filter.as_ref()
+                    .is_none_or(|filter| hostname == filter)
+            })
+            .map(|((hostname, minute_start), accumulator)| accumulator.to_rollup(hostname, *minute_start))
+            .collect();
+
+        result.sort_by(|a, b| (a.minute_start, &a.hostname).cmp(&(b.minute_start, &b.hostname)));
+        Ok(result)
+    }
+
+    async fn query_metrics(&self, query: &MetricQuery) -> Result<Vec<MetricPoint>, anyhow::Error> {
+        let metrics = self.metrics.read().await;
+
+        let filtered: Vec<MetricPoint> = metrics
+            .iter()
+            .filter(|metric| query.matches(metric))
+            .skip(query.offset.unwrap_or(0) as usize)
+            .take(query.limit.map_or(usize::MAX, |limit| limit as usize))
+            .cloned()
+            .collect();
+
+        Ok(filtered)
+    }
+
+    /// Remove every point matching the query's time range and hostname filter
+    /// (pagination is ignored), returning how many points were deleted
+    async fn delete_metrics(&self, query: &MetricQuery) -> Result<u64, anyhow::Error> {
+        let (deleted, commit) = {
+            let mut metrics = self.metrics.write().await;
+            let mut rollups = self.rollups.write().await;
+            let mut dedup_index = self.dedup_index.write().await;
+            let commit = self.wal.as_ref()
+                .map(|wal| wal.append(WalRecord::Delete { query: query.clone() }))
+                .transpose()?;
+            let deleted = Self::delete_locked(self.dedup, &mut metrics, &mut rollups, &mut dedup_index, query);
+            (deleted, commit)
+        };
+
+        if let Some(commit) = commit {
+            commit.wait().await?;
+        }
+        Ok(deleted)
+    }
+
+    async fn calculate_statistics(&self, query: &MetricQuery) -> Result<MetricStatistics, anyhow::Error> {
+        // Statistics always cover the full matching range, never a single page
+        let unpaged = MetricQuery {
+            limit: None,
+            offset: None,
+            ..query.clone()
+        };
+        let metrics = MetricsStorage::query_metrics(self, &unpaged).await?;
+
+        if metrics.is_empty() {
+            return Ok(MetricStatistics {
+                count: 0,
+                avg_cpu_percent: 0.0,
+                avg_memory_bytes: 0,
+                avg_disk_io_ops: 0.0,
+                time_range_seconds: query.end_time.saturating_sub(query.start_time),
+            });
+        }
+
+        let count = metrics.len() as u64;
+        let avg_cpu = metrics.iter().map(|m| m.cpu_percent).sum::<f32>() / count as f32;
+        // Summed as u128 so points near u64::MAX bytes can't overflow it
+        let avg_memory = (metrics.iter().map(|m| m.memory_bytes as u128).sum::<u128>() / count as u128) as u64;
+        let avg_disk_io = metrics.iter().map(|m| m.disk_io_ops as f32).sum::<f32>() / count as f32;
+
+        Ok(MetricStatistics {
+            count,
+            avg_cpu_percent: avg_cpu,
+            avg_memory_bytes: avg_memory,
+            avg_disk_io_ops: avg_disk_io,
+            time_range_seconds: query.end_time.saturating_sub(query.start_time),
+        })
+    }
+
+    async fn storage_stats(&self) -> Result<StorageStats, anyhow::Error> {
+        let metrics = self.metrics.read().await;
+        let rollups = self.rollups.read().await;
+        let dedup_index = self.dedup_index.read().await;
+
+        // Vec slots (including spare capacity) plus each point's owned strings and tag table
+        let tag_entry_size = 2 * std::mem::size_of::<String>() + 1;
+        let points_heap: usize = metrics.capacity() * std::mem::size_of::<MetricPoint>()
+            + metrics
+                .iter()
+                .map(|metric| {
+                    metric.hostname.capacity()
+                        + metric.tags.capacity() * tag_entry_size
+                        + metric.tags.iter().map(|(k, v)| k.capacity() + v.capacity()).sum::<usize>()
+                })
+                .sum::<usize>()
+            // Dedup index entries count against the raw points they index
+            + dedup_index.len() * (std::mem::size_of::<(String, i64)>() + std::mem::size_of::<usize>())
+            + dedup_index.keys().map(|(hostname, _)| hostname.capacity()).sum::<usize>();
+
+        let bucket_size = std::mem::size_of::<(String, i64)>() + std::mem::size_of::<RollupAccumulator>();
+        let rollup_heap: usize = rollups.len() * bucket_size
+            + rollups.keys().map(|(hostname, _)| hostname.capacity()).sum::<usize>();
+
+        Ok(StorageStats {
+            point_count: metrics.len() as u64,
+            approx_heap_bytes: points_heap as u64,
+            rollup_bucket_count: rollups.len() as u64,
+            rollup_heap_bytes: rollup_heap as u64,
+        })
+    }
+}
+
+// Lets the harness drive storage in-process through the same trait as the
+// protocol clients, as a no-network baseline
+#[async_trait::async_trait(?Send)]
+impl MetricsService for InMemoryStorage {
+    type Error = anyhow::Error;
+
+    async fn submit_metric(&self, metric: MetricPoint) -> Result<(), anyhow::Error> {
+        MetricsStorage::store_metric(self, metric).await
+    }
+
+    async fn query_metrics(&self, query: MetricQuery) -> Result<Vec<MetricPoint>, anyhow::Error> {
+        MetricsStorage::query_metrics(self, &query).await
+    }
+
+    async fn get_statistics(&self, query: MetricQuery) -> Result<MetricStatistics, anyhow::Error> {
+        MetricsStorage::calculate_statistics(self, &query).await
+    }
+
+    async fn delete_metrics(&self, query: MetricQuery) -> Result<u64, anyhow::Error> {
+        MetricsStorage::delete_metrics(self, &query).await
+    }
+}
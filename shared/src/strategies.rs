@@ -0,0 +1,91 @@
+//! Proptest strategies for the API types, for round-trip tests of the
+//! per-protocol converters. Enabled by the `proptest` feature.
+
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+use crate::{MetricPoint, MetricQuery};
+
+/// Floats that converters and text formats tend to get wrong
+const F32_EDGE_CASES: [f32; 8] = [0.0, -0.0, f32::MIN_POSITIVE, 1.0e-45, f32::EPSILON, f32::MAX, f32::MIN, 100.0];
+
+/// Any finite `f32`, with the edge cases above drawn often
+pub fn finite_f32() -> impl Strategy<Value = f32> {
+    prop_oneof![
+        1 => proptest::sample::select(F32_EDGE_CASES.to_vec()),
+        3 => proptest::num::f32::POSITIVE
+            | proptest::num::f32::NEGATIVE
+            | proptest::num::f32::NORMAL
+            | proptest::num::f32::SUBNORMAL
+            | proptest::num::f32::ZERO,
+    ]
+}
+
+/// `finite_f32` plus infinities and NaN
+pub fn any_f32() -> impl Strategy<Value = f32> {
+    prop_oneof![
+        4 => finite_f32(),
+        1 => proptest::num::f32::ANY,
+    ]
+}
+
+/// Any text up to 32 characters, control and multi-byte characters included
+pub fn text() -> impl Strategy<Value = String> {
+    "(?s).{0,32}"
+}
+
+pub fn tags() -> impl Strategy<Value = HashMap<String, String>> {
+    proptest::collection::hash_map(text(), text(), 0..8)
+}
+
+/// Points with a finite `cpu_percent`. Nothing is validated, so hostnames,
+/// tags and numbers go beyond what the services accept.
+pub fn metric_point() -> impl Strategy<Value = MetricPoint> {
+    metric_point_with(finite_f32())
+}
+
+/// Points with `cpu_percent` drawn from `cpu_percent`, such as `any_f32()`
+pub fn metric_point_with(cpu_percent: impl Strategy<Value = f32>) -> impl Strategy<Value = MetricPoint> {
+    (any::<i64>(), text(), cpu_percent, any::<u64>(), any::<u32>(), tags()).prop_map(
+        |(timestamp, hostname, cpu_percent, memory_bytes, disk_io_ops, tags)| MetricPoint {
+            timestamp,
+            hostname,
+            cpu_percent,
+            memory_bytes,
+            disk_io_ops,
+            tags,
+        },
+    )
+}
+
+pub fn metric_query() -> impl Strategy<Value = MetricQuery> {
+    (
+        any::<i64>(),
+        any::<i64>(),
+        proptest::option::of(text()),
+        proptest::option::of(any::<u32>()),
+        proptest::option::of(any::<u32>()),
+    )
+        .prop_map(|(start_time, end_time, hostname_filter, limit, offset)| MetricQuery {
+            start_time,
+            end_time,
+            hostname_filter,
+            limit,
+            offset,
+        })
+}
+
+/// Equal bit for bit, so `-0.0` differs from `0.0`; any NaN matches any other
+pub fn same_f32(a: f32, b: f32) -> bool {
+    (a.is_nan() && b.is_nan()) || a.to_bits() == b.to_bits()
+}
+
+/// `==` with `cpu_percent` compared by `same_f32`
+pub fn same_metric(a: &MetricPoint, b: &MetricPoint) -> bool {
+    a.timestamp == b.timestamp
+        && a.hostname == b.hostname
+        && same_f32(a.cpu_percent, b.cpu_percent)
+        && a.memory_bytes == b.memory_bytes
+        && a.disk_io_ops == b.disk_io_ops
+        && a.tags == b.tags
+}
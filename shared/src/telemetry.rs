@@ -0,0 +1,262 @@
+use anyhow::Context;
+use axum::{http::header, routing::get, Json, Router};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Server-side cost of the requests to one method
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationStats {
+    pub requests: u64,
+    /// From receiving each request to producing its response, summed
+    pub total_ns: u64,
+    /// Bytes allocated while each request's handling was polled (see
+    /// `RequestTimer::track`), summed. Counted per thread, so requests that
+    /// overlap aren't counted in each other's; what a request hands off to
+    /// other tasks isn't counted at all.
+    pub allocated_bytes: u64,
+}
+
+impl OperationStats {
+    /// What was added to these stats after `earlier` was taken
+    pub fn since(&self, earlier: &OperationStats) -> OperationStats {
+        OperationStats {
+            requests: self.requests.saturating_sub(earlier.requests),
+            total_ns: self.total_ns.saturating_sub(earlier.total_ns),
+            allocated_bytes: self.allocated_bytes.saturating_sub(earlier.allocated_bytes),
+        }
+    }
+
+    fn add(&mut self, other: &OperationStats) {
+        self.requests += other.requests;
+        self.total_ns += other.total_ns;
+        self.allocated_bytes += other.allocated_bytes;
+    }
+}
+
+/// Every method's `OperationStats` since a service started, as served at
+/// `GET /stats` beside its Prometheus metrics
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerStats {
+    pub service: String,
+    /// `allocator_name()` of the service's build
+    #[serde(default)]
+    pub allocator: String,
+    pub operations: BTreeMap<String, OperationStats>,
+}
+
+impl ServerStats {
+    /// Every method's stats added together
+    pub fn total(&self) -> OperationStats {
+        let mut total = OperationStats::default();
+        for stats in self.operations.values() {
+            total.add(stats);
+        }
+        total
+    }
+
+    /// The requests handled after `earlier` was taken, by method; methods
+    /// with none are left out
+    pub fn since(&self, earlier: &ServerStats) -> ServerStats {
+        let operations = self
+            .operations
+            .iter()
+            .map(|(method, stats)| {
+                let before = earlier.operations.get(method).copied().unwrap_or_default();
+                (method.clone(), stats.since(&before))
+            })
+            .filter(|(_, stats)| stats.requests > 0)
+            .collect();
+        ServerStats { service: self.service.clone(), allocator: self.allocator.clone(), operations }
+    }
+}
+
+/// Server-side request metrics for one service, exported in the Prometheus
+/// text format so server behavior during a run can be compared with what the
+/// benchmark client measured
+pub struct ServiceMetrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    in_flight: IntGauge,
+    service: String,
+    operations: Mutex<BTreeMap<String, OperationStats>>,
+}
+
+impl ServiceMetrics {
+    /// Metrics labelled with `service` (e.g. `rest`), registered in their own
+    /// registry. Allocations are only counted when the binary's global
+    /// allocator is a `CountingAllocator`.
+    pub fn new(service: &str) -> anyhow::Result<Self> {
+        let registry = Registry::new_custom(Some("protobench".to_string()), None)?;
+        let service_label = |opts: Opts| opts.const_label("service", service);
+
+        let requests = IntCounterVec::new(
+            service_label(Opts::new("requests_total", "Requests handled, by method and status")),
+            &["method", "status"],
+        )?;
+        // Local round trips are tens of microseconds, far below the default buckets
+        let latency = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "Time from receiving a request to producing its response")
+                .const_label("service", service)
+                .buckets(prometheus::exponential_buckets(0.000_05, 2.0, 16)?),
+            &["method"],
+        )?;
+        let in_flight = IntGauge::with_opts(service_label(Opts::new(
+            "requests_in_flight",
+            "Requests currently being handled",
+        )))?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(latency.clone()))?;
+        registry.register(Box::new(in_flight.clone()))?;
+
+        Ok(Self {
+            registry,
+            requests,
+            latency,
+            in_flight,
+            service: service.to_string(),
+            operations: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Start timing a request; drive its handling through `track` on the
+    /// returned timer and record it by calling `finish`
+    pub fn start(self: &Arc<Self>, method: impl Into<String>) -> RequestTimer {
+        self.in_flight.inc();
+        RequestTimer {
+            metrics: Arc::clone(self),
+            method: method.into(),
+            started: Instant::now(),
+            allocated: 0,
+            finished: false,
+        }
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render(&self) -> anyhow::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// Every method's requests, time and allocations so far
+    pub fn stats(&self) -> ServerStats {
+        ServerStats {
+            service: self.service.clone(),
+            allocator: crate::allocator_name().to_string(),
+            operations: self.operations.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone(),
+        }
+    }
+
+    /// When `env_var` names an address, build the metrics for `service` and
+    /// serve them at `GET /metrics` there in the background, with the
+    /// per-method `ServerStats` as JSON at `GET /stats`. Returns `None` when
+    /// unset so services skip instrumentation entirely.
+    ///
+    /// The exporter gets its own listener so scrapes never pass through the
+    /// benchmarked endpoint, its auth, or its rate limits, and never show up
+    /// in the stats they read.
+    pub fn spawn_exporter_from_env(service: &str, env_var: &str) -> anyhow::Result<Option<Arc<Self>>> {
+        let Ok(addr) = std::env::var(env_var) else {
+            return Ok(None);
+        };
+        let addr: SocketAddr = addr
+            .parse()
+            .with_context(|| format!("Invalid {} {:?}", env_var, addr))?;
+
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+
+        let metrics = Arc::new(Self::new(service)?);
+        let exported = Arc::clone(&metrics);
+        let stats = Arc::clone(&metrics);
+        let app = Router::new()
+            .route(
+                "/metrics",
+                get(move || {
+                    let exported = Arc::clone(&exported);
+                    async move {
+                        let body = exported.render().unwrap_or_else(|e| format!("# encoding failed: {}\n", e));
+                        ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body)
+                    }
+                }),
+            )
+            .route("/stats", get(move || std::future::ready(Json(stats.stats()))));
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                eprintln!("Prometheus exporter failed: {}", e);
+            }
+        });
+        println!("Prometheus metrics for {} on http://{}/metrics", service, addr);
+
+        Ok(Some(metrics))
+    }
+}
+
+/// One in-flight request. Dropping it without `finish` (e.g. the client went
+/// away mid-request) records the request as `cancelled`.
+pub struct RequestTimer {
+    metrics: Arc<ServiceMetrics>,
+    method: String,
+    started: Instant,
+    // Wraps like `thread_allocated_bytes`, whose differences it adds up
+    allocated: usize,
+    finished: bool,
+}
+
+impl RequestTimer {
+    /// Drive `future`, counting what the polling thread allocates during each
+    /// poll as this request's. The future can move between worker threads, and
+    /// other requests' allocations meanwhile are left out.
+    pub async fn track<F: Future>(&mut self, future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        std::future::poll_fn(|cx| {
+            let start = crate::thread_allocated_bytes();
+            let poll = future.as_mut().poll(cx);
+            self.allocated = self.allocated.wrapping_add(crate::thread_allocated_bytes().wrapping_sub(start));
+            poll
+        })
+        .await
+    }
+
+    pub fn finish(mut self, status: &str) {
+        self.record(status);
+    }
+
+    fn record(&mut self, status: &str) {
+        self.finished = true;
+        let elapsed = self.started.elapsed();
+        self.metrics
+            .operations
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry(self.method.clone())
+            .or_default()
+            .add(&OperationStats { requests: 1, total_ns: elapsed.as_nanos() as u64, allocated_bytes: self.allocated as u64 });
+        self.metrics
+            .latency
+            .with_label_values(&[&self.method])
+            .observe(elapsed.as_secs_f64());
+        self.metrics
+            .requests
+            .with_label_values(&[&self.method, status])
+            .inc();
+    }
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.record("cancelled");
+        }
+        self.metrics.in_flight.dec();
+    }
+}
@@ -0,0 +1,446 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{HistogramBucket, MetricPoint, MetricPointV2, ProcessInfo, MAX_HOSTNAME_LEN, MAX_TAG_VALUE_LEN, MAX_TIMESTAMP};
+
+// Hostnames, tags and their values in the default shape
+const BASE_HOSTNAMES: [&str; 10] = [
+    "web-01", "web-02", "db-primary", "db-replica", "cache-01", 
+    "api-gateway", "worker-01", "worker-02", "monitoring", "load-balancer"
+];
+const ENVIRONMENTS: [&str; 3] = ["prod", "staging", "dev"];
+const REGIONS: [&str; 4] = ["us-east", "us-west", "eu-central", "ap-southeast"];
+const SERVICES: [&str; 5] = ["frontend", "backend", "database", "cache", "queue"];
+// Repeated after a hostname or tag value to pad it to `hostname_len` or
+// `tag_value_len`
+const ASCII_PADDING: &str = ".rack-07.dc-west.protobench.internal";
+
+// Replace the hostnames and extend tag values in `TextMode::Unicode` and
+// `TextMode::Long`: 2-, 3- and 4-byte UTF-8, right-to-left text, combining
+// marks, and characters JSON has to escape
+const UNICODE_HOSTNAMES: [&str; 10] = [
+    "服务器-01", "сервер-02", "ウェブ-03", "🚀-launch-04", "nœud-émetteur-05",
+    "데이터베이스-06", "خادم-07", "κόμβος-08", "節點-🔥-09", "e\u{301}cole-10"
+];
+const UNICODE_TAG_TEXT: [&str; 8] = [
+    "生产", "😀", "données", "東京", "🌍🌎🌏", "Zürich", "\"quoted\" \\ tab\t", "👩‍💻"
+];
+const UNICODE_PADDING: &str = ".机架-07.データセンター.🌐.ñ";
+
+// Shape of `TestDataGenerator::adversarial` points
+const ADVERSARIAL_TAGS: usize = 256;
+const ADVERSARIAL_TAG_VALUE_LEN: usize = 8 * 1024;
+
+// Nested parts of `TestDataGenerator::generate_v2` points
+const V2_CORES: usize = 8;
+const V2_HISTOGRAM_BOUNDS_MS: [f64; 12] = [1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0];
+const V2_PROCESSES: [&str; 10] = [
+    "nginx", "postgres", "redis-server", "java", "python3",
+    "node", "sshd", "systemd", "containerd", "prometheus"
+];
+const V2_PROCESS_ARGS: [&str; 6] = ["--config", "/etc/protobench/app.toml", "-v", "--port=8080", "--workers=4", "--log-format=json"];
+
+/// Text content of generated hostnames and tag values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextMode {
+    /// Short ASCII names, as in the default shape
+    #[default]
+    Ascii,
+    /// Multi-byte UTF-8 hostnames (CJK, Cyrillic, emoji, ...) and tag values
+    Unicode,
+    /// `Unicode` text padded as close to `MAX_HOSTNAME_LEN` and
+    /// `MAX_TAG_VALUE_LEN` bytes as whole characters allow
+    Long,
+}
+
+/// How well generated tag values compress, for best- and worst-case
+/// compression ratios. Values keep the byte length `Natural` would give them,
+/// so only their content changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compressibility {
+    /// Values drawn from small pools, as in the default shape
+    #[default]
+    Natural,
+    /// The padding text repeated, the same on every point
+    Repetitive,
+    /// Random ASCII letters and digits, different on every point
+    Noise,
+}
+
+impl Compressibility {
+    pub fn label(self) -> &'static str {
+        match self {
+            Compressibility::Natural => "natural",
+            Compressibility::Repetitive => "repetitive",
+            Compressibility::Noise => "noise",
+        }
+    }
+}
+
+impl TextMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            TextMode::Ascii => "ascii",
+            TextMode::Unicode => "unicode",
+            TextMode::Long => "long",
+        }
+    }
+}
+
+/// Pad `text` with `padding` (repeated) up to `len` bytes, or cut it down to
+/// `len`, without splitting a character; multi-byte text can end up to three
+/// bytes short
+fn fit(text: &mut String, len: usize, padding: &str) {
+    for c in padding.chars().cycle() {
+        if text.len() + c.len_utf8() > len {
+            break;
+        }
+        text.push(c);
+    }
+    let mut end = len.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+}
+
+/// Builds deterministic test points with a configurable shape, so payload
+/// shape can be varied like any other benchmark dimension, e.g.
+/// `TestDataGenerator::new().hosts(100).tags_per_metric(16).hostname_len(64).seed(7).generate(1000)`.
+///
+/// The defaults (10 hosts, 4 tags, natural hostname lengths, seed 42) give
+/// the same points `generate_test_data` always has. Points are spread over
+/// the hour before `base_timestamp`, which defaults to now; pin it as well to
+/// get byte-identical points across runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestDataGenerator {
+    hosts: usize,
+    tags_per_metric: usize,
+    hostname_len: Option<usize>,
+    seed: u64,
+    base_timestamp: Option<i64>,
+    text: TextMode,
+    tag_value_len: Option<usize>,
+    extreme_values: bool,
+    compressibility: Compressibility,
+}
+
+impl Default for TestDataGenerator {
+    fn default() -> Self {
+        Self {
+            hosts: BASE_HOSTNAMES.len(),
+            tags_per_metric: 4,
+            hostname_len: None,
+            seed: 42,
+            base_timestamp: None,
+            text: TextMode::Ascii,
+            tag_value_len: None,
+            extreme_values: false,
+            compressibility: Compressibility::Natural,
+        }
+    }
+}
+
+impl TestDataGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of distinct hostnames points are spread over. Beyond the ten
+    /// default names, they repeat with a numeric suffix (`web-01-1`, ...).
+    pub fn hosts(mut self, hosts: usize) -> Self {
+        self.hosts = hosts.max(1);
+        self
+    }
+
+    /// Tags on every point: `env`, `region`, `service` and `version` first,
+    /// then `label-NN` tags. The services accept at most `shared::MAX_TAGS`.
+    pub fn tags_per_metric(mut self, tags: usize) -> Self {
+        self.tags_per_metric = tags;
+        self
+    }
+
+    /// Pad or cut every hostname to exactly `len` bytes (or up to three fewer
+    /// for multi-byte text); cutting below the natural length can make hosts
+    /// share a name
+    pub fn hostname_len(mut self, len: usize) -> Self {
+        self.hostname_len = Some(len.max(1));
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Unix seconds the generated hour ends at, instead of the current time
+    pub fn base_timestamp(mut self, timestamp: i64) -> Self {
+        self.base_timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn text(mut self, text: TextMode) -> Self {
+        self.text = text;
+        self
+    }
+
+    /// Pad or cut every tag value to `len` bytes, like `hostname_len`
+    pub fn tag_value_len(mut self, len: usize) -> Self {
+        self.tag_value_len = Some(len.max(1));
+        self
+    }
+
+    /// Put every numeric field at the top of its range: 100% CPU, `u64::MAX`
+    /// memory bytes, `u32::MAX` disk ops, and timestamps counting up to
+    /// `MAX_TIMESTAMP` (ignoring `base_timestamp`)
+    pub fn extreme_values(mut self, extreme: bool) -> Self {
+        self.extreme_values = extreme;
+        self
+    }
+
+    /// Usually combined with `tag_value_len` and more tags, so tag values make
+    /// up most of the payload
+    pub fn compressibility(mut self, compressibility: Compressibility) -> Self {
+        self.compressibility = compressibility;
+        self
+    }
+
+    /// Worst-case points for robustness runs and for finding message size
+    /// limits: 256 tags of 8 KiB each, hostnames at `MAX_HOSTNAME_LEN` and
+    /// extreme values, so a single point is over 2 MiB. They break the per-point
+    /// limits in `validate`, so services reject them once they have been
+    /// received and decoded, if the message size limits let them get that far.
+    pub fn adversarial(self) -> Self {
+        self.tags_per_metric(ADVERSARIAL_TAGS)
+            .tag_value_len(ADVERSARIAL_TAG_VALUE_LEN)
+            .hostname_len(MAX_HOSTNAME_LEN)
+            .extreme_values(true)
+    }
+
+    /// Short description of the shape for benchmark IDs, e.g.
+    /// `hosts=100,tags=16,hostname_len=64`
+    pub fn label(&self) -> String {
+        let hostname_len = self.hostname_len.map_or("natural".to_string(), |len| len.to_string());
+        let mut label = format!("hosts={},tags={},hostname_len={}", self.hosts, self.tags_per_metric, hostname_len);
+        if self.text != TextMode::Ascii {
+            label.push_str(&format!(",text={}", self.text.label()));
+        }
+        if let Some(len) = self.tag_value_len {
+            label.push_str(&format!(",tag_value_len={}", len));
+        }
+        if self.extreme_values {
+            label.push_str(",extreme_values");
+        }
+        if self.compressibility != Compressibility::Natural {
+            label.push_str(&format!(",compressibility={}", self.compressibility.label()));
+        }
+        label
+    }
+
+    fn hostname(&self, index: usize) -> String {
+        let (names, padding) = match self.text {
+            TextMode::Ascii => (BASE_HOSTNAMES, ASCII_PADDING),
+            TextMode::Unicode | TextMode::Long => (UNICODE_HOSTNAMES, UNICODE_PADDING),
+        };
+        let base = names[index % names.len()];
+        let mut hostname = match index / BASE_HOSTNAMES.len() {
+            0 => base.to_string(),
+            round => format!("{}-{}", base, round),
+        };
+        let len = match self.text {
+            TextMode::Long => Some(self.hostname_len.unwrap_or(MAX_HOSTNAME_LEN)),
+            _ => self.hostname_len,
+        };
+        if let Some(len) = len {
+            fit(&mut hostname, len, padding);
+        }
+        hostname
+    }
+
+    /// Tags for one point. `noise` only feeds `Compressibility::Noise`, so the
+    /// main sequence, and with it every other field, doesn't depend on it.
+    fn tags(&self, rng: &mut StdRng, noise: &mut StdRng) -> HashMap<String, String> {
+        let mut tags = HashMap::new();
+        for index in 0..self.tags_per_metric {
+            let (key, mut value) = match index {
+                0 => ("env".to_string(), ENVIRONMENTS.choose(rng).unwrap().to_string()),
+                1 => ("region".to_string(), REGIONS.choose(rng).unwrap().to_string()),
+                2 => ("service".to_string(), SERVICES.choose(rng).unwrap().to_string()),
+                3 => ("version".to_string(), format!("v{}.{}.{}", 
+                    rng.gen_range(1..3), rng.gen_range(0..10), rng.gen_range(0..5))),
+                _ => (format!("label-{:02}", index - 4), format!("value-{}", rng.gen_range(0..1000))),
+            };
+            if self.text != TextMode::Ascii {
+                value = format!("{}-{}", value, UNICODE_TAG_TEXT.choose(rng).unwrap());
+            }
+            let len = match self.text {
+                TextMode::Long => Some(self.tag_value_len.unwrap_or(MAX_TAG_VALUE_LEN)),
+                _ => self.tag_value_len,
+            };
+            let padding = if self.text == TextMode::Ascii { ASCII_PADDING } else { UNICODE_PADDING };
+            if let Some(len) = len {
+                fit(&mut value, len, padding);
+            }
+            match self.compressibility {
+                Compressibility::Natural => {}
+                Compressibility::Repetitive => {
+                    let len = value.len();
+                    value.clear();
+                    fit(&mut value, len, padding);
+                }
+                Compressibility::Noise => {
+                    value = noise.sample_iter(rand::distributions::Alphanumeric).take(value.len()).map(char::from).collect();
+                }
+            }
+            tags.insert(key, value);
+        }
+        tags
+    }
+
+    pub fn generate(&self, count: usize) -> Vec<MetricPoint> {
+        self.iter(count).collect()
+    }
+
+    /// `generate`'s points as `MetricPointV2`s: the CPU figure spread over 8
+    /// cores, a 12-bucket latency histogram and 10 processes with up to 4
+    /// arguments each. The nested parts come from their own seeded sequence.
+    pub fn generate_v2(&self, count: usize) -> Vec<MetricPointV2> {
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(1));
+        self.iter(count)
+            .map(|metric| MetricPointV2 {
+                timestamp: metric.timestamp,
+                hostname: metric.hostname,
+                per_core_cpu_percent: (0..V2_CORES)
+                    .map(|_| (metric.cpu_percent + rng.gen_range(-5.0..5.0)).clamp(0.0, 100.0))
+                    .collect(),
+                memory_bytes: metric.memory_bytes,
+                disk_io_ops: metric.disk_io_ops,
+                latency_histogram: V2_HISTOGRAM_BOUNDS_MS
+                    .iter()
+                    .map(|&upper_bound_ms| HistogramBucket { upper_bound_ms, count: rng.gen_range(0..1000) })
+                    .collect(),
+                processes: V2_PROCESSES
+                    .iter()
+                    .map(|name| {
+                        let pid = rng.gen_range(1..65_536);
+                        let cpu_percent = rng.gen_range(0.0..25.0);
+                        let memory_bytes = rng.gen_range(1_000_000..2_000_000_000);
+                        let arg_count = rng.gen_range(0..=4);
+                        ProcessInfo {
+                            pid,
+                            name: name.to_string(),
+                            cpu_percent,
+                            memory_bytes,
+                            args: V2_PROCESS_ARGS.choose_multiple(&mut rng, arg_count).map(|arg| arg.to_string()).collect(),
+                        }
+                    })
+                    .collect(),
+                tags: metric.tags,
+            })
+            .collect()
+    }
+
+    /// The same points as `generate`, produced one at a time as the iterator
+    /// is advanced, so runs over millions of points never hold them all
+    pub fn iter(&self, count: usize) -> TestDataIter {
+        let base_timestamp = self.base_timestamp.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64
+        });
+        
+        TestDataIter {
+            generator: *self,
+            rng: StdRng::seed_from_u64(self.seed), // Deterministic for consistent benchmarks
+            noise: StdRng::seed_from_u64(!self.seed),
+            hostnames: (0..self.hosts).map(|index| self.hostname(index)).collect(),
+            base_timestamp,
+            next: 0,
+            count,
+        }
+    }
+}
+
+/// Points from `TestDataGenerator::iter`
+pub struct TestDataIter {
+    generator: TestDataGenerator,
+    rng: StdRng,
+    noise: StdRng,
+    hostnames: Vec<String>,
+    base_timestamp: i64,
+    next: usize,
+    count: usize,
+}
+
+impl Iterator for TestDataIter {
+    type Item = MetricPoint;
+
+    fn next(&mut self) -> Option<MetricPoint> {
+        if self.next == self.count {
+            return None;
+        }
+        let i = self.next;
+        self.next += 1;
+        
+        let rng = &mut self.rng;
+        let tags = self.generator.tags(rng, &mut self.noise);
+        let mut metric = MetricPoint {
+            timestamp: self.base_timestamp - rng.gen_range(0..3600) + (i as i64), // Spread over last hour
+            hostname: self.hostnames.choose(rng).unwrap().clone(),
+            cpu_percent: rng.gen_range(5.0..95.0), // Realistic CPU usage
+            memory_bytes: rng.gen_range(1_000_000_000..16_000_000_000), // 1GB to 16GB
+            disk_io_ops: rng.gen_range(100..10_000), // Reasonable I/O operations
+            tags,
+        };
+        if self.generator.extreme_values {
+            metric.timestamp = MAX_TIMESTAMP - (self.count - 1 - i) as i64;
+            metric.cpu_percent = 100.0;
+            metric.memory_bytes = u64::MAX;
+            metric.disk_io_ops = u32::MAX;
+        }
+        Some(metric)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.count - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for TestDataIter {}
+
+/// `count` points in the default `TestDataGenerator` shape
+pub fn generate_test_data(count: usize) -> Vec<MetricPoint> {
+    TestDataGenerator::new().generate(count)
+}
+
+/// `generate_test_data` as an iterator that builds each point on demand
+pub fn generate_test_data_iter(count: usize) -> TestDataIter {
+    TestDataGenerator::new().iter(count)
+}
+
+/// Hostnames that only survive a query string when percent-encoded: spaces,
+/// reserved characters and non-ASCII text
+pub const UNUSUAL_HOSTNAMES: [&str; 8] = [
+    "web 01",
+    "db+primary",
+    "cache&region=eu",
+    "api/gateway?v=2",
+    "100%-busy",
+    "worker#3",
+    "nœud-émetteur",
+    "节点-01",
+];
+
+/// `generate_test_data` with hostnames drawn from `UNUSUAL_HOSTNAMES`, for
+/// checking that hostname filters round-trip through every protocol
+pub fn generate_unusual_hostname_data(count: usize) -> Vec<MetricPoint> {
+    let mut metrics = generate_test_data(count);
+    for (i, metric) in metrics.iter_mut().enumerate() {
+        metric.hostname = UNUSUAL_HOSTNAMES[i % UNUSUAL_HOSTNAMES.len()].to_string();
+    }
+    metrics
+}
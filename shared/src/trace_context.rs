@@ -0,0 +1,70 @@
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use std::collections::HashMap;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// W3C Trace Context header, metadata key and Cap'n Proto param carrying the
+/// caller's span to the service
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Keeps spans exporting; dropping it flushes the ones still queued
+pub struct TracingGuard {
+    provider: TracerProvider,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// When `PROTOBENCH_OTLP_TRACES_ENDPOINT` names an OTLP/HTTP receiver, export
+/// this process's spans to its `/v1/traces` as `service`. Returns `None` when
+/// unset, leaving no subscriber installed, so spans cost a disabled check and
+/// nothing is propagated. Call from inside a multi-threaded Tokio runtime,
+/// which the exporter's batching task runs on.
+pub fn init_tracing_from_env(service: &str) -> anyhow::Result<Option<TracingGuard>> {
+    let Ok(endpoint) = std::env::var("PROTOBENCH_OTLP_TRACES_ENDPOINT") else {
+        return Ok(None);
+    };
+    let endpoint = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+    let exporter = SpanExporter::builder().with_http().with_endpoint(&endpoint).build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service.to_string())]))
+        .build();
+
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("protobench")))
+        .try_init()?;
+    println!("Tracing {} to {}", service, endpoint);
+
+    Ok(Some(TracingGuard { provider }))
+}
+
+/// The current span as a `traceparent` value to send with a request, or
+/// `None` when it isn't traced
+pub fn current_traceparent() -> Option<String> {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&tracing::Span::current().context(), &mut carrier);
+    carrier.remove(TRACEPARENT)
+}
+
+/// Make `span` a child of the client span a request's `traceparent` names.
+/// A missing or malformed value leaves `span` the root of its own trace.
+pub fn set_remote_parent(span: &tracing::Span, traceparent: Option<&str>) {
+    let Some(traceparent) = traceparent.filter(|value| !value.is_empty()) else {
+        return;
+    };
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    span.set_parent(TraceContextPropagator::new().extract(&carrier));
+}
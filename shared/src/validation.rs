@@ -0,0 +1,167 @@
+use std::fmt;
+
+use crate::{MetricPoint, MetricQuery};
+
+// Every timestamp in the workspace, on points and in query bounds, is whole
+// seconds since the Unix epoch. Points outside MIN_TIMESTAMP..=MAX_TIMESTAMP
+// are rejected, and so are query bounds in that range scaled to a finer
+// unit: they match nothing stored, so a query in milliseconds would
+// otherwise come back empty as if the protocol had lost the points.
+
+/// Earliest accepted timestamp (2000-01-01T00:00:00Z)
+pub const MIN_TIMESTAMP: i64 = 946_684_800;
+/// Latest accepted timestamp (2100-01-01T00:00:00Z)
+pub const MAX_TIMESTAMP: i64 = 4_102_444_800;
+/// Longest hostname accepted, matching the DNS limit
+pub const MAX_HOSTNAME_LEN: usize = 253;
+pub const MAX_TAGS: usize = 32;
+pub const MAX_TAG_KEY_LEN: usize = 64;
+pub const MAX_TAG_VALUE_LEN: usize = 256;
+
+/// A unit finer than seconds that a timestamp can be mistakenly sent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampUnit {
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+impl TimestampUnit {
+    pub const ALL: [TimestampUnit; 3] = [TimestampUnit::Milliseconds, TimestampUnit::Microseconds, TimestampUnit::Nanoseconds];
+
+    pub fn per_second(self) -> i64 {
+        match self {
+            TimestampUnit::Milliseconds => 1_000,
+            TimestampUnit::Microseconds => 1_000_000,
+            TimestampUnit::Nanoseconds => 1_000_000_000,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TimestampUnit::Milliseconds => "milliseconds",
+            TimestampUnit::Microseconds => "microseconds",
+            TimestampUnit::Nanoseconds => "nanoseconds",
+        }
+    }
+
+    /// The unit that puts `timestamp` in the accepted range once converted
+    /// to seconds, if it's in seconds only by mistake. The scaled ranges
+    /// don't overlap, so at most one unit fits.
+    pub fn detect(timestamp: i64) -> Option<Self> {
+        Self::ALL.into_iter().find(|unit| {
+            let per_second = unit.per_second();
+            (MIN_TIMESTAMP * per_second..=MAX_TIMESTAMP.saturating_mul(per_second)).contains(&timestamp)
+        })
+    }
+
+    /// `timestamp` in this unit converted to seconds, rounding down
+    pub fn to_seconds(self, timestamp: i64) -> i64 {
+        timestamp.div_euclid(self.per_second())
+    }
+}
+
+/// Why a `MetricPoint` or `MetricQuery` was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    CpuPercentOutOfRange(f32),
+    EmptyHostname,
+    HostnameTooLong(usize),
+    TimestampOutOfRange(i64),
+    /// A timestamp or query bound that reads as a valid time in a finer unit
+    TimestampNotInSeconds(i64, TimestampUnit),
+    TooManyTags(usize),
+    EmptyTagKey,
+    TagKeyTooLong(String),
+    TagValueTooLong(String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CpuPercentOutOfRange(value) => {
+                write!(f, "cpu_percent must be between 0 and 100, got {}", value)
+            }
+            Self::EmptyHostname => write!(f, "hostname must not be empty"),
+            Self::HostnameTooLong(len) => {
+                write!(f, "hostname is {} bytes, limit is {}", len, MAX_HOSTNAME_LEN)
+            }
+            Self::TimestampOutOfRange(timestamp) => write!(
+                f,
+                "timestamp {} is outside the accepted range {}..={}",
+                timestamp, MIN_TIMESTAMP, MAX_TIMESTAMP
+            ),
+            Self::TimestampNotInSeconds(timestamp, unit) => write!(
+                f,
+                "timestamp {} looks like {}; timestamps are Unix seconds, e.g. {}",
+                timestamp,
+                unit.label(),
+                unit.to_seconds(*timestamp)
+            ),
+            Self::TooManyTags(count) => write!(f, "{} tags exceeds the limit of {}", count, MAX_TAGS),
+            Self::EmptyTagKey => write!(f, "tag keys must not be empty"),
+            Self::TagKeyTooLong(key) => {
+                write!(f, "tag key '{}' exceeds {} bytes", key, MAX_TAG_KEY_LEN)
+            }
+            Self::TagValueTooLong(key) => {
+                write!(f, "value of tag '{}' exceeds {} bytes", key, MAX_TAG_VALUE_LEN)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl MetricPoint {
+    /// Check the point against the limits every service enforces on ingest
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        // Written as a negated range check so NaN is rejected too
+        if !(0.0..=100.0).contains(&self.cpu_percent) {
+            return Err(ValidationError::CpuPercentOutOfRange(self.cpu_percent));
+        }
+
+        if self.hostname.is_empty() {
+            return Err(ValidationError::EmptyHostname);
+        }
+        if self.hostname.len() > MAX_HOSTNAME_LEN {
+            return Err(ValidationError::HostnameTooLong(self.hostname.len()));
+        }
+
+        if let Some(unit) = TimestampUnit::detect(self.timestamp) {
+            return Err(ValidationError::TimestampNotInSeconds(self.timestamp, unit));
+        }
+        if !(MIN_TIMESTAMP..=MAX_TIMESTAMP).contains(&self.timestamp) {
+            return Err(ValidationError::TimestampOutOfRange(self.timestamp));
+        }
+
+        if self.tags.len() > MAX_TAGS {
+            return Err(ValidationError::TooManyTags(self.tags.len()));
+        }
+        for (key, value) in &self.tags {
+            if key.is_empty() {
+                return Err(ValidationError::EmptyTagKey);
+            }
+            if key.len() > MAX_TAG_KEY_LEN {
+                return Err(ValidationError::TagKeyTooLong(key.clone()));
+            }
+            if value.len() > MAX_TAG_VALUE_LEN {
+                return Err(ValidationError::TagValueTooLong(key.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl MetricQuery {
+    /// Check the bounds are in seconds. Any other bound is accepted, from
+    /// `i64::MIN` to `i64::MAX`, and an empty range just matches nothing.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        for bound in [self.start_time, self.end_time] {
+            if let Some(unit) = TimestampUnit::detect(bound) {
+                return Err(ValidationError::TimestampNotInSeconds(bound, unit));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{MetricPoint, MetricQuery};
+
+/// When the write-ahead log forces data to stable storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Hand writes to the OS page cache only; survives a process crash but not power loss
+    Never,
+    /// `fdatasync` after every group of logged operations; the "durable writes" configuration
+    Always,
+}
+
+#[derive(Debug, Clone)]
+pub struct WalConfig {
+    pub path: PathBuf,
+    pub fsync: FsyncPolicy,
+}
+
+impl WalConfig {
+    /// Read `PROTOBENCH_WAL_PATH` and `PROTOBENCH_WAL_FSYNC` (`always` or `never`,
+    /// default `never`). Returns `None` when no WAL path is configured.
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// `from_env`, with each variable's value looked up through `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let path = var("PROTOBENCH_WAL_PATH")?;
+        let fsync = match var("PROTOBENCH_WAL_FSYNC").as_deref() {
+            Some("always") => FsyncPolicy::Always,
+            _ => FsyncPolicy::Never,
+        };
+
+        Some(Self {
+            path: PathBuf::from(path),
+            fsync,
+        })
+    }
+}
+
+// One JSON document per line, committed once its newline is written. Retention
+// eviction is not logged; the log is compacted instead (see `WriteAheadLog::compact`).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum WalRecord {
+    Insert { metrics: Vec<MetricPoint> },
+    Delete { query: MetricQuery },
+}
+
+enum WalCommand {
+    Append(WalRecord),
+    // Replace the whole log with a single insert of these points
+    Compact(Vec<MetricPoint>),
+}
+
+type Ack = oneshot::Sender<std::io::Result<()>>;
+
+/// Handle to the log's writer thread. Commands are queued without blocking,
+/// so callers can queue under the storage locks to fix the log order, then
+/// release them before waiting on the returned `WalCommit`.
+pub(crate) struct WriteAheadLog {
+    commands: mpsc::UnboundedSender<(WalCommand, Ack)>,
+    // Points inserted by the records in the log, for deciding when to compact it
+    logged_points: AtomicUsize,
+}
+
+/// Resolves once a queued command is on disk (and synced, under `FsyncPolicy::Always`)
+pub(crate) struct WalCommit(oneshot::Receiver<std::io::Result<()>>);
+
+impl WalCommit {
+    pub(crate) async fn wait(self) -> Result<(), anyhow::Error> {
+        self.0
+            .await
+            .map_err(|_| anyhow::anyhow!("WAL writer stopped"))?
+            .map_err(Into::into)
+    }
+}
+
+impl WriteAheadLog {
+    /// Open (or create) the log, returning it with every committed record
+    /// already present. A torn final line left by a crash is cut off so new
+    /// records start on a clean line; an unreadable line with committed
+    /// records after it is corruption and fails the open.
+    pub(crate) fn open(config: &WalConfig) -> Result<(Self, Vec<WalRecord>), anyhow::Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&config.path)?;
+
+        let (records, committed_len) = Self::read_records(&file)?;
+        if committed_len < file.metadata()?.len() {
+            eprintln!("Discarding torn WAL tail after byte {} of {}", committed_len, config.path.display());
+            file.set_len(committed_len)?;
+            file.sync_data()?;
+        }
+
+        let (commands, queue) = mpsc::unbounded_channel();
+        let writer = LogWriter {
+            path: config.path.clone(),
+            file: BufWriter::new(file),
+            fsync: config.fsync,
+        };
+        std::thread::Builder::new()
+            .name("protobench-wal".to_string())
+            .spawn(move || writer.run(queue))?;
+
+        let logged_points = records
+            .iter()
+            .map(|record| match record {
+                WalRecord::Insert { metrics } => metrics.len(),
+                WalRecord::Delete { .. } => 0,
+            })
+            .sum();
+        let wal = Self {
+            commands,
+            logged_points: AtomicUsize::new(logged_points),
+        };
+        Ok((wal, records))
+    }
+
+    // Returns the records and the byte length of the committed prefix they came from
+    fn read_records(file: &File) -> Result<(Vec<WalRecord>, u64), anyhow::Error> {
+        let mut reader = BufReader::new(file);
+        let mut records = Vec::new();
+        let mut committed_len = 0;
+        let mut line = Vec::new();
+        let mut line_number = 0;
+        let mut torn: Option<(usize, anyhow::Error)> = None;
+
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            line_number += 1;
+
+            let parsed = match line.strip_suffix(b"\n") {
+                Some(json) => serde_json::from_slice::<WalRecord>(json).map_err(anyhow::Error::from),
+                None => Err(anyhow::anyhow!("missing trailing newline")),
+            };
+            match parsed {
+                Ok(record) => {
+                    if let Some((bad_line, e)) = &torn {
+                        anyhow::bail!(
+                            "WAL is corrupt: line {} is unreadable ({}) but line {} after it is intact",
+                            bad_line, e, line_number
+                        );
+                    }
+                    records.push(record);
+                    committed_len += read as u64;
+                }
+                Err(e) => {
+                    torn.get_or_insert((line_number, e));
+                }
+            }
+        }
+
+        Ok((records, committed_len))
+    }
+
+    /// Queue a record behind everything queued before it
+    pub(crate) fn append(&self, record: WalRecord) -> Result<WalCommit, anyhow::Error> {
+        if let WalRecord::Insert { metrics } = &record {
+            self.logged_points.fetch_add(metrics.len(), Ordering::Relaxed);
+        }
+        self.send(WalCommand::Append(record))
+    }
+
+    /// Whether the log holds more than twice the `live_points` storage still
+    /// has, so that rewriting it with `compact` is worth the copy
+    pub(crate) fn needs_compaction(&self, live_points: usize) -> bool {
+        self.logged_points.load(Ordering::Relaxed) > 2 * live_points
+    }
+
+    /// Queue a rewrite of the log as one insert of `metrics`, which must be
+    /// the full storage contents at this point in the log order. Records
+    /// already queued are written first and then superseded; later ones
+    /// append to the rewritten log.
+    pub(crate) fn compact(&self, metrics: Vec<MetricPoint>) -> Result<WalCommit, anyhow::Error> {
+        self.logged_points.store(metrics.len(), Ordering::Relaxed);
+        self.send(WalCommand::Compact(metrics))
+    }
+
+    fn send(&self, command: WalCommand) -> Result<WalCommit, anyhow::Error> {
+        let (ack, commit) = oneshot::channel();
+        self.commands
+            .send((command, ack))
+            .map_err(|_| anyhow::anyhow!("WAL writer stopped"))?;
+        Ok(WalCommit(commit))
+    }
+}
+
+struct LogWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    fsync: FsyncPolicy,
+}
+
+impl LogWriter {
+    // Group commit: everything queued while the previous group was being
+    // written goes out with one flush and at most one fdatasync. Exits once
+    // the storage holding the sender is dropped, or after a failed write so
+    // nothing is appended behind a partial record.
+    fn run(mut self, mut queue: mpsc::UnboundedReceiver<(WalCommand, Ack)>) {
+        while let Some(first) = queue.blocking_recv() {
+            let mut group = vec![first];
+            while let Ok(next) = queue.try_recv() {
+                group.push(next);
+            }
+
+            let mut acks = Vec::with_capacity(group.len());
+            let mut result = Ok(());
+            for (command, ack) in group {
+                if result.is_ok() {
+                    result = self.apply(command);
+                }
+                acks.push(ack);
+            }
+            if result.is_ok() {
+                result = self.sync();
+            }
+
+            for ack in acks {
+                let _ = ack.send(match &result {
+                    Ok(()) => Ok(()),
+                    Err(e) => Err(std::io::Error::new(e.kind(), e.to_string())),
+                });
+            }
+            if let Err(e) = result {
+                eprintln!("WAL writer stopped: {}", e);
+                break;
+            }
+        }
+    }
+
+    fn apply(&mut self, command: WalCommand) -> std::io::Result<()> {
+        match command {
+            WalCommand::Append(record) => write_record(&mut self.file, &record),
+            WalCommand::Compact(metrics) => {
+                let file = rewrite(&self.path, &WalRecord::Insert { metrics })?;
+                self.file = BufWriter::new(file);
+                Ok(())
+            }
+        }
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        if self.fsync == FsyncPolicy::Always {
+            self.file.get_ref().sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+fn write_record(writer: &mut impl Write, record: &WalRecord) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")
+}
+
+// Written next to the log and renamed over it, so a crash mid-compaction
+// leaves the old log in place. Returns the new log opened for appending.
+fn rewrite(path: &Path, record: &WalRecord) -> std::io::Result<File> {
+    let tmp_path = path.with_extension("compact");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    write_record(&mut writer, record)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+
+    OpenOptions::new().append(true).open(path)
+}
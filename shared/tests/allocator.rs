@@ -0,0 +1,50 @@
+use shared::{allocator_name, CountingAllocator};
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator::new();
+
+#[test]
+fn allocations_are_counted() {
+    let before = GLOBAL.stats();
+    let mut buffer: Vec<u8> = Vec::with_capacity(4096);
+    let allocated = GLOBAL.stats();
+    buffer.reserve_exact(8192);
+    let grown = GLOBAL.stats();
+    drop(buffer);
+    let freed = GLOBAL.stats();
+
+    // Other tests' threads may allocate meanwhile, so only lower bounds hold.
+    // Growing counts the difference, freeing the final size.
+    assert!(allocated.bytes_allocated - before.bytes_allocated >= 4096);
+    assert!(grown.bytes_allocated - allocated.bytes_allocated >= 4096);
+    assert!(grown.bytes_reallocated - allocated.bytes_reallocated >= 4096);
+    assert!(freed.bytes_deallocated - grown.bytes_deallocated >= 8192);
+}
+
+#[test]
+fn the_allocator_is_named_after_the_build() {
+    let expected = if cfg!(feature = "jemalloc") {
+        "jemalloc"
+    } else if cfg!(feature = "mimalloc") {
+        "mimalloc"
+    } else {
+        "system"
+    };
+    assert_eq!(allocator_name(), expected);
+}
+
+#[test]
+fn each_thread_counts_only_its_own_allocations() {
+    let before = shared::thread_allocated_bytes();
+    let elsewhere = std::thread::spawn(|| {
+        let start = shared::thread_allocated_bytes();
+        std::hint::black_box(vec![0u8; 1 << 20]);
+        shared::thread_allocated_bytes() - start
+    })
+    .join()
+    .unwrap();
+    let here = shared::thread_allocated_bytes().wrapping_sub(before);
+
+    assert!(elsewhere >= 1 << 20, "{}", elsewhere);
+    assert!(here < 1 << 20, "{}", here);
+}
@@ -0,0 +1,46 @@
+use proptest::prelude::*;
+use shared::strategies::{any_f32, metric_point_with, same_metric};
+use shared::{arrow_ipc, generate_test_data};
+
+proptest! {
+    #[test]
+    fn points_round_trip_through_arrow_streams(metrics in proptest::collection::vec(metric_point_with(any_f32()), 0..16)) {
+        let decoded = arrow_ipc::decode(&arrow_ipc::encode(&metrics).unwrap()).unwrap();
+        prop_assert_eq!(decoded.len(), metrics.len());
+        prop_assert!(decoded.iter().zip(&metrics).all(|(a, b)| same_metric(a, b)), "gave {:?}", decoded);
+    }
+}
+
+#[test]
+fn a_record_batch_has_a_column_per_field() {
+    let batch = arrow_ipc::record_batch(&generate_test_data(5)).unwrap();
+
+    assert_eq!(batch.num_rows(), 5);
+    let names: Vec<_> = batch.schema().fields().iter().map(|field| field.name().clone()).collect();
+    assert_eq!(names, ["timestamp", "hostname", "cpu_percent", "memory_bytes", "disk_io_ops", "tags"]);
+}
+
+#[test]
+fn large_results_round_trip_across_record_batches() {
+    let metrics = generate_test_data(150_000);
+    let decoded = arrow_ipc::decode(&arrow_ipc::encode(&metrics).unwrap()).unwrap();
+
+    assert_eq!(decoded, metrics);
+}
+
+#[test]
+fn streams_cut_short_are_rejected() {
+    let encoded = arrow_ipc::encode(&generate_test_data(10)).unwrap();
+    // Past the 8-byte end-of-stream marker and into the record batch's body
+    assert!(arrow_ipc::decode(&encoded[..encoded.len() - 20]).is_err());
+}
+
+#[test]
+fn small_and_empty_slices_round_trip() {
+    let metrics = generate_test_data(3);
+    assert_eq!(arrow_ipc::decode(&arrow_ipc::encode(&metrics).unwrap()).unwrap(), metrics);
+
+    let empty = arrow_ipc::encode(&[]).unwrap();
+    assert!(!empty.is_empty(), "an empty result still carries the schema");
+    assert_eq!(arrow_ipc::decode(&empty).unwrap(), Vec::new());
+}
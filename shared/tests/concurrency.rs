@@ -0,0 +1,189 @@
+use shared::{InMemoryStorage, MetricPoint, MetricQuery, MetricsStorage};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+const WRITERS: usize = 8;
+const POINTS_PER_WRITER: usize = 500;
+const BASE_TIMESTAMP: i64 = 1_700_000_000;
+
+fn metric(writer: usize, seq: usize) -> MetricPoint {
+    MetricPoint {
+        timestamp: BASE_TIMESTAMP + seq as i64,
+        hostname: format!("writer-{}", writer),
+        cpu_percent: 50.0,
+        memory_bytes: 1024,
+        disk_io_ops: 10,
+        tags: HashMap::new(),
+    }
+}
+
+fn host_query(hostname: &str) -> MetricQuery {
+    MetricQuery {
+        hostname_filter: Some(hostname.to_string()),
+        ..MetricQuery::all()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_writes_are_not_lost() {
+    let storage = Arc::new(InMemoryStorage::new());
+
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                for seq in 0..POINTS_PER_WRITER {
+                    storage.store_metric(metric(writer, seq)).await.unwrap();
+                }
+            })
+        })
+        .collect();
+
+    // Readers run alongside the writers; counts they observe may only grow
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                let mut last_seen = 0;
+                for _ in 0..200 {
+                    let seen = storage.query_metrics(&MetricQuery::all()).await.unwrap().len();
+                    assert!(seen >= last_seen, "point count went backwards: {} -> {}", last_seen, seen);
+                    last_seen = seen;
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    for handle in writers.into_iter().chain(readers) {
+        handle.await.unwrap();
+    }
+
+    let stored = storage.query_metrics(&MetricQuery::all()).await.unwrap();
+    assert_eq!(stored.len(), WRITERS * POINTS_PER_WRITER);
+
+    let unique: HashSet<_> = stored.iter().map(|m| (m.hostname.clone(), m.timestamp)).collect();
+    assert_eq!(unique.len(), WRITERS * POINTS_PER_WRITER, "duplicate or missing points");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn statistics_stay_consistent_under_load() {
+    let storage = Arc::new(InMemoryStorage::new());
+
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                for seq in 0..POINTS_PER_WRITER {
+                    storage.store_metric(metric(writer, seq)).await.unwrap();
+                }
+            })
+        })
+        .collect();
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                for _ in 0..200 {
+                    let stats = storage.calculate_statistics(&MetricQuery::all()).await.unwrap();
+                    assert!(stats.count <= (WRITERS * POINTS_PER_WRITER) as u64);
+                    // Every point carries identical values, so any snapshot averages to them
+                    if stats.count > 0 {
+                        assert_eq!(stats.avg_cpu_percent, 50.0);
+                        assert_eq!(stats.avg_memory_bytes, 1024);
+                        assert_eq!(stats.avg_disk_io_ops, 10.0);
+                    }
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    for handle in writers.into_iter().chain(readers) {
+        handle.await.unwrap();
+    }
+
+    let stats = storage.calculate_statistics(&MetricQuery::all()).await.unwrap();
+    let points = storage.query_metrics(&MetricQuery::all()).await.unwrap();
+    assert_eq!(stats.count, points.len() as u64);
+    assert_eq!(stats.count, (WRITERS * POINTS_PER_WRITER) as u64);
+
+    let rollup_total: u64 = storage.query_rollups(&MetricQuery::all()).await.unwrap().iter().map(|r| r.count).sum();
+    assert_eq!(rollup_total, stats.count, "rollups disagree with raw points");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn batches_are_never_observed_partially() {
+    const BATCH_SIZE: usize = 50;
+    const BATCHES_PER_WRITER: usize = 20;
+
+    let storage = Arc::new(InMemoryStorage::new());
+
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                for batch in 0..BATCHES_PER_WRITER {
+                    let points = (0..BATCH_SIZE).map(|i| metric(writer, batch * BATCH_SIZE + i)).collect();
+                    assert_eq!(storage.store_metrics(points).await.unwrap(), BATCH_SIZE);
+                }
+            })
+        })
+        .collect();
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                for _ in 0..200 {
+                    let seen = storage.query_metrics(&MetricQuery::all()).await.unwrap().len();
+                    assert_eq!(seen % BATCH_SIZE, 0, "observed a partially applied batch ({} points)", seen);
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    for handle in writers.into_iter().chain(readers) {
+        handle.await.unwrap();
+    }
+
+    let stored = storage.query_metrics(&MetricQuery::all()).await.unwrap().len();
+    assert_eq!(stored, WRITERS * BATCHES_PER_WRITER * BATCH_SIZE);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn deletes_only_touch_their_own_hosts() {
+    let storage = Arc::new(InMemoryStorage::new());
+
+    // Even writers keep their data; odd writers repeatedly delete their own host
+    let tasks: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                let hostname = format!("writer-{}", writer);
+                for seq in 0..POINTS_PER_WRITER {
+                    storage.store_metric(metric(writer, seq)).await.unwrap();
+                    if writer % 2 == 1 && seq % 50 == 49 {
+                        storage.delete_metrics(&host_query(&hostname)).await.unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for handle in tasks {
+        handle.await.unwrap();
+    }
+
+    for writer in 0..WRITERS {
+        let hostname = format!("writer-{}", writer);
+        let count = storage.query_metrics(&host_query(&hostname)).await.unwrap().len();
+        let expected = if writer % 2 == 0 { POINTS_PER_WRITER } else { 0 };
+        assert_eq!(count, expected, "{}", hostname);
+
+        let rollup_total: u64 = storage.query_rollups(&host_query(&hostname)).await.unwrap().iter().map(|r| r.count).sum();
+        assert_eq!(rollup_total, expected as u64, "rollups for {}", hostname);
+    }
+}
@@ -0,0 +1,91 @@
+use shared::{load_dataset, MetricPoint, TestDataGenerator};
+use std::path::PathBuf;
+
+fn sample_csv() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../datasets/sample.csv")
+}
+
+/// A file under the system temp dir, removed when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, contents: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("protobench-dataset-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn sorted(mut metrics: Vec<MetricPoint>) -> Vec<MetricPoint> {
+    metrics.sort_by_key(|metric| metric.timestamp);
+    metrics
+}
+
+#[test]
+fn loads_the_sample_csv() {
+    let metrics = load_dataset(sample_csv()).unwrap();
+
+    assert_eq!(metrics.len(), 12);
+    assert!(metrics.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+    assert_eq!(metrics[0].hostname, "web-01");
+    assert_eq!(metrics[0].memory_bytes, 4_294_967_296);
+    assert_eq!(metrics[0].tags.get("service").map(String::as_str), Some("frontend"));
+    assert!(metrics.last().unwrap().tags.is_empty());
+}
+
+#[test]
+fn json_and_json_lines_match_the_points_written() {
+    let metrics = TestDataGenerator::new().tags_per_metric(6).generate(200);
+    let array = TempFile::new("points.json", &serde_json::to_string(&metrics).unwrap());
+    let lines: Vec<String> = metrics.iter().map(|metric| serde_json::to_string(metric).unwrap()).collect();
+    let json_lines = TempFile::new("points.jsonl", &(lines.join("\n") + "\n"));
+
+    assert_eq!(load_dataset(&array.0).unwrap(), sorted(metrics.clone()));
+    assert_eq!(load_dataset(&json_lines.0).unwrap(), sorted(metrics));
+}
+
+#[test]
+fn csv_without_a_tags_column_loads() {
+    let file = TempFile::new("no-tags.csv", "timestamp,hostname,cpu_percent,memory_bytes,disk_io_ops\n1700000000,web-01,10.5,1024,3\n");
+
+    let metrics = load_dataset(&file.0).unwrap();
+    assert_eq!(metrics.len(), 1);
+    assert!(metrics[0].tags.is_empty());
+}
+
+#[test]
+fn invalid_points_name_their_line() {
+    let file = TempFile::new(
+        "invalid.csv",
+        "timestamp,hostname,cpu_percent,memory_bytes,disk_io_ops,tags\n1700000000,web-01,10.5,1024,3,\n1700000001,web-01,150.0,1024,3,\n",
+    );
+
+    let error = format!("{:#}", load_dataset(&file.0).unwrap_err());
+    assert!(error.contains("line 3"), "{}", error);
+    assert!(error.contains("cpu_percent"), "{}", error);
+}
+
+#[test]
+fn malformed_tags_are_rejected() {
+    let file = TempFile::new(
+        "bad-tags.csv",
+        "timestamp,hostname,cpu_percent,memory_bytes,disk_io_ops,tags\n1700000000,web-01,10.5,1024,3,env=prod;region\n",
+    );
+
+    let error = format!("{:#}", load_dataset(&file.0).unwrap_err());
+    assert!(error.contains("line 2") && error.contains("'region'"), "{}", error);
+}
+
+#[test]
+fn unknown_extensions_are_rejected() {
+    let file = TempFile::new("points.xml", "<points/>");
+
+    let error = load_dataset(&file.0).unwrap_err().to_string();
+    assert!(error.contains("unknown dataset format"), "{}", error);
+}
@@ -0,0 +1,115 @@
+use shared::{DedupPolicy, FsyncPolicy, InMemoryStorage, MetricPoint, MetricQuery, MetricsStorage, RetentionPolicy, WalConfig};
+use std::collections::HashMap;
+
+const BASE_TIMESTAMP: i64 = 1_700_000_040;
+
+fn metric(offset: i64, cpu_percent: f32) -> MetricPoint {
+    MetricPoint {
+        timestamp: BASE_TIMESTAMP + offset,
+        hostname: "web-01".to_string(),
+        cpu_percent,
+        memory_bytes: 1024,
+        disk_io_ops: 10,
+        tags: HashMap::new(),
+    }
+}
+
+fn deduplicating(policy: DedupPolicy) -> InMemoryStorage {
+    let mut storage = InMemoryStorage::new();
+    storage.enable_dedup(policy).unwrap();
+    storage
+}
+
+#[tokio::test]
+async fn keep_first_drops_duplicates_without_counting_them() {
+    let storage = deduplicating(DedupPolicy::KeepFirst);
+    assert_eq!(storage.store_metrics(vec![metric(0, 10.0), metric(1, 20.0), metric(0, 90.0)]).await.unwrap(), 2);
+    assert_eq!(storage.store_metrics(vec![metric(1, 80.0), metric(2, 30.0)]).await.unwrap(), 1);
+    storage.store_metric(metric(2, 70.0)).await.unwrap();
+
+    assert_eq!(
+        storage.query_metrics(&MetricQuery::all()).await.unwrap(),
+        vec![metric(0, 10.0), metric(1, 20.0), metric(2, 30.0)]
+    );
+    let rollups = storage.query_rollups(&MetricQuery::all()).await.unwrap();
+    assert_eq!(rollups.len(), 1);
+    assert_eq!(rollups[0].count, 3);
+    assert_eq!(rollups[0].max_cpu_percent, 30.0);
+}
+
+#[tokio::test]
+async fn keep_last_overwrites_in_place_and_rebuilds_the_rollup() {
+    let storage = deduplicating(DedupPolicy::KeepLast);
+    storage.store_metrics(vec![metric(0, 10.0), metric(1, 90.0), metric(2, 30.0)]).await.unwrap();
+    // Overwriting the bucket's maximum has to bring the max down, which a running accumulator can't do
+    assert_eq!(storage.store_metrics(vec![metric(1, 20.0), metric(65, 40.0)]).await.unwrap(), 2);
+
+    assert_eq!(
+        storage.query_metrics(&MetricQuery::all()).await.unwrap(),
+        vec![metric(0, 10.0), metric(1, 20.0), metric(2, 30.0), metric(65, 40.0)]
+    );
+    let rollups = storage.query_rollups(&MetricQuery::all()).await.unwrap();
+    assert_eq!(rollups.len(), 2);
+    assert_eq!(rollups[0].count, 3);
+    assert_eq!(rollups[0].min_cpu_percent, 10.0);
+    assert_eq!(rollups[0].max_cpu_percent, 30.0);
+    assert_eq!(rollups[0].avg_cpu_percent, 20.0);
+    assert_eq!(rollups[1].count, 1);
+}
+
+#[tokio::test]
+async fn keep_last_keeps_a_bucket_that_outlived_its_raw_points() {
+    let mut storage = InMemoryStorage::with_retention(RetentionPolicy {
+        max_points: Some(2),
+        ..RetentionPolicy::default()
+    });
+    storage.enable_dedup(DedupPolicy::KeepLast).unwrap();
+    storage.store_metrics(vec![metric(0, 10.0), metric(1, 90.0), metric(2, 30.0)]).await.unwrap();
+    assert_eq!(storage.evict_expired().await.unwrap(), 1);
+    storage.store_metric(metric(1, 20.0)).await.unwrap();
+
+    assert_eq!(storage.query_metrics(&MetricQuery::all()).await.unwrap(), vec![metric(1, 20.0), metric(2, 30.0)]);
+    // Still counts the evicted point; the overwritten maximum can't be taken out
+    let rollups = storage.query_rollups(&MetricQuery::all()).await.unwrap();
+    assert_eq!(rollups.len(), 1);
+    assert_eq!(rollups[0].count, 3);
+    assert_eq!(rollups[0].avg_cpu_percent, 20.0);
+    assert_eq!(rollups[0].min_cpu_percent, 10.0);
+    assert_eq!(rollups[0].max_cpu_percent, 90.0);
+}
+
+#[tokio::test]
+async fn wal_replay_deduplicates_the_same_way() {
+    let path = std::env::temp_dir().join(format!("protobench-dedup-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let config = WalConfig {
+        path: path.clone(),
+        fsync: FsyncPolicy::Never,
+    };
+
+    let live = {
+        let mut storage = deduplicating(DedupPolicy::KeepLast);
+        storage.enable_wal(&config).unwrap();
+        storage.store_metrics(vec![metric(0, 10.0), metric(1, 90.0)]).await.unwrap();
+        storage.store_metric(metric(1, 20.0)).await.unwrap();
+        storage.store_metrics(vec![metric(0, 50.0), metric(2, 30.0)]).await.unwrap();
+        (storage.query_metrics(&MetricQuery::all()).await.unwrap(), storage.query_rollups(&MetricQuery::all()).await.unwrap())
+    };
+
+    let mut replayed = deduplicating(DedupPolicy::KeepLast);
+    assert_eq!(replayed.enable_wal(&config).unwrap(), 3);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(live.0, vec![metric(0, 50.0), metric(1, 20.0), metric(2, 30.0)]);
+    assert_eq!(replayed.query_metrics(&MetricQuery::all()).await.unwrap(), live.0);
+    assert_eq!(replayed.query_rollups(&MetricQuery::all()).await.unwrap(), live.1);
+}
+
+#[test]
+fn an_unrecognised_policy_is_an_error() {
+    let policy = |value: Option<&str>| DedupPolicy::from_vars(|name| value.filter(|_| name == "PROTOBENCH_DEDUP").map(str::to_string));
+
+    assert!(policy(Some("keep_last")).is_err());
+    assert_eq!(policy(Some("keep-last")).unwrap(), Some(DedupPolicy::KeepLast));
+    assert_eq!(policy(None).unwrap(), None);
+}
@@ -0,0 +1,41 @@
+use proptest::prelude::*;
+use shared::strategies::{any_f32, metric_point_with, same_metric};
+use shared::{delta_batch, generate_test_data, BodyEncoding};
+
+proptest! {
+    #[test]
+    fn batches_round_trip_through_the_delta_format(metrics in proptest::collection::vec(metric_point_with(any_f32()), 0..16)) {
+        let decoded = delta_batch::decode(&delta_batch::encode(&metrics)).unwrap();
+        prop_assert_eq!(decoded.len(), metrics.len());
+        prop_assert!(decoded.iter().zip(&metrics).all(|(a, b)| same_metric(a, b)), "gave {:?}", decoded);
+    }
+
+    #[test]
+    fn truncated_batches_are_rejected(metrics in proptest::collection::vec(metric_point_with(any_f32()), 1..8), cut in 1usize..64) {
+        let encoded = delta_batch::encode(&metrics);
+        let cut = cut.min(encoded.len());
+        prop_assert!(delta_batch::decode(&encoded[..encoded.len() - cut]).is_err());
+    }
+}
+
+#[test]
+fn repeated_hosts_and_tags_are_stored_once() {
+    let metrics = generate_test_data(1000);
+    let encoded = delta_batch::encode(&metrics);
+
+    let json = BodyEncoding::Json.encode(&metrics).unwrap();
+    assert!(encoded.len() * 4 < json.len(), "{} bytes against {} as JSON", encoded.len(), json.len());
+    assert!(delta_batch::decode(&encoded).unwrap().iter().zip(&metrics).all(|(a, b)| same_metric(a, b)));
+}
+
+#[test]
+fn trailing_bytes_and_other_versions_are_rejected() {
+    let mut encoded = delta_batch::encode(&generate_test_data(3));
+    encoded.push(0);
+    assert!(delta_batch::decode(&encoded).is_err());
+
+    encoded.pop();
+    encoded[0] = 2;
+    assert!(delta_batch::decode(&encoded).is_err());
+    assert!(delta_batch::decode(&[]).is_err());
+}
@@ -0,0 +1,57 @@
+use shared::{Fault, FaultInjection};
+use std::time::Duration;
+
+fn faults(error_rate: f64, delay_rate: f64, drop_rate: f64) -> FaultInjection {
+    FaultInjection { error_rate, delay_rate, delay: Duration::from_millis(50), drop_rate }
+}
+
+#[test]
+fn rolls_split_into_drops_then_errors_then_delays() {
+    let faults = faults(0.2, 0.3, 0.1);
+
+    assert_eq!(faults.fault_for(0.0), Some(Fault::Drop));
+    assert_eq!(faults.fault_for(0.15), Some(Fault::Error));
+    assert_eq!(faults.fault_for(0.45), Some(Fault::Delay(Duration::from_millis(50))));
+    assert_eq!(faults.fault_for(0.65), None);
+    assert_eq!(faults.fault_for(0.99), None);
+}
+
+#[test]
+fn certain_and_impossible_faults() {
+    for _ in 0..100 {
+        assert_eq!(faults(1.0, 0.0, 0.0).pick(), Some(Fault::Error));
+        assert_eq!(faults(0.0, 0.0, 0.0).pick(), None);
+    }
+}
+
+// Only this test touches the variables, so it can't race another
+#[test]
+fn configured_from_the_environment() {
+    let variables = [
+        "PROTOBENCH_FAULT_ERROR_RATE",
+        "PROTOBENCH_FAULT_DELAY_RATE",
+        "PROTOBENCH_FAULT_DELAY_MS",
+        "PROTOBENCH_FAULT_DROP_RATE",
+    ];
+    for variable in variables {
+        std::env::remove_var(variable);
+    }
+    assert_eq!(FaultInjection::from_env(), None);
+
+    // Out of range and unparsable rates count as unset
+    std::env::set_var("PROTOBENCH_FAULT_ERROR_RATE", "1.5");
+    std::env::set_var("PROTOBENCH_FAULT_DROP_RATE", "often");
+    assert_eq!(FaultInjection::from_env(), None);
+
+    std::env::set_var("PROTOBENCH_FAULT_DELAY_RATE", "0.25");
+    let configured = FaultInjection::from_env().unwrap();
+    assert_eq!(configured, FaultInjection { error_rate: 0.0, delay_rate: 0.25, delay: Duration::from_millis(100), drop_rate: 0.0 });
+
+    std::env::set_var("PROTOBENCH_FAULT_DELAY_MS", "5");
+    assert_eq!(FaultInjection::from_env().unwrap().delay, Duration::from_millis(5));
+    assert_eq!(FaultInjection::from_env().unwrap().label(), "errors 0%, delays 25% of 5ms, drops 0%");
+
+    for variable in variables {
+        std::env::remove_var(variable);
+    }
+}
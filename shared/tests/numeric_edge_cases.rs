@@ -0,0 +1,115 @@
+use shared::{BodyEncoding, MetricPoint, MetricQuery, ValidationError, MIN_TIMESTAMP};
+use std::collections::HashMap;
+
+fn metric(cpu_percent: f32) -> MetricPoint {
+    MetricPoint {
+        timestamp: 1_700_000_000,
+        hostname: "server-001".to_string(),
+        cpu_percent,
+        memory_bytes: 8_589_934_592,
+        disk_io_ops: 150,
+        tags: HashMap::new(),
+    }
+}
+
+fn json(metric: &MetricPoint) -> serde_json::Value {
+    serde_json::from_slice(&BodyEncoding::Json.encode(metric).unwrap()).unwrap()
+}
+
+#[test]
+fn non_finite_cpu_is_a_string_in_json() {
+    assert_eq!(json(&metric(f32::NAN))["cpu_percent"], "NaN");
+    assert_eq!(json(&metric(f32::INFINITY))["cpu_percent"], "Infinity");
+    assert_eq!(json(&metric(f32::NEG_INFINITY))["cpu_percent"], "-Infinity");
+    assert_eq!(json(&metric(42.5))["cpu_percent"], 42.5);
+}
+
+#[test]
+fn non_finite_cpu_round_trips_through_every_body_encoding() {
+    for cpu_percent in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+        for encoding in BodyEncoding::ALL {
+            let decoded: MetricPoint = encoding.decode(&encoding.encode(&metric(cpu_percent)).unwrap()).unwrap();
+            assert_eq!(decoded.cpu_percent.to_bits(), cpu_percent.to_bits(), "{}", encoding.label());
+        }
+    }
+}
+
+#[test]
+fn binary_encodings_keep_the_native_float() {
+    for encoding in [BodyEncoding::MsgPack, BodyEncoding::Cbor] {
+        let bytes = encoding.encode(&metric(f32::NAN)).unwrap();
+        assert!(!bytes.windows(3).any(|window| window == b"NaN"), "{}", encoding.label());
+    }
+}
+
+#[test]
+fn null_cpu_reads_as_nan_and_fails_validation() {
+    // What serde_json writes for NaN by default
+    let body = r#"{"timestamp":1700000000,"hostname":"server-001","cpu_percent":null,"memory_bytes":1,"disk_io_ops":1,"tags":{}}"#;
+    let decoded: MetricPoint = BodyEncoding::Json.decode(body.as_bytes()).unwrap();
+
+    assert!(decoded.cpu_percent.is_nan());
+    assert!(matches!(decoded.validate(), Err(ValidationError::CpuPercentOutOfRange(_))));
+}
+
+#[test]
+fn integer_and_unknown_cpu_values() {
+    let body = |cpu: &str| {
+        format!(r#"{{"timestamp":1700000000,"hostname":"h","cpu_percent":{},"memory_bytes":1,"disk_io_ops":1,"tags":{{}}}}"#, cpu)
+    };
+
+    let decoded: MetricPoint = BodyEncoding::Json.decode(body("50").as_bytes()).unwrap();
+    assert_eq!(decoded.cpu_percent, 50.0);
+    let error = BodyEncoding::Json.decode::<MetricPoint>(body("\"nan\"").as_bytes()).unwrap_err();
+    assert!(error.to_string().contains("\"NaN\""), "{}", error);
+}
+
+#[test]
+fn non_finite_cpu_fails_validation() {
+    for cpu_percent in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+        assert!(matches!(metric(cpu_percent).validate(), Err(ValidationError::CpuPercentOutOfRange(_))));
+    }
+    // -0.0 compares equal to 0.0
+    assert_eq!(metric(-0.0).validate(), Ok(()));
+}
+
+#[test]
+fn u64_max_memory_is_exact_in_every_body_encoding() {
+    let point = MetricPoint { memory_bytes: u64::MAX, ..metric(42.5) };
+
+    assert_eq!(json(&point)["memory_bytes"].as_u64(), Some(u64::MAX));
+    assert!(String::from_utf8(BodyEncoding::Json.encode(&point).unwrap()).unwrap().contains("18446744073709551615"));
+    for encoding in BodyEncoding::ALL {
+        let decoded: MetricPoint = encoding.decode(&encoding.encode(&point).unwrap()).unwrap();
+        assert_eq!(decoded.memory_bytes, u64::MAX, "{}", encoding.label());
+    }
+    assert_eq!(point.validate(), Ok(()));
+}
+
+#[test]
+fn negative_timestamps_round_trip_and_fail_validation() {
+    for timestamp in [-1, i64::MIN, MIN_TIMESTAMP - 1] {
+        let point = MetricPoint { timestamp, ..metric(42.5) };
+        for encoding in BodyEncoding::ALL {
+            let decoded: MetricPoint = encoding.decode(&encoding.encode(&point).unwrap()).unwrap();
+            assert_eq!(decoded, point, "{}", encoding.label());
+        }
+        assert_eq!(point.validate(), Err(ValidationError::TimestampOutOfRange(timestamp)));
+    }
+}
+
+#[test]
+fn queries_keep_negative_and_extreme_bounds() {
+    let query = MetricQuery {
+        start_time: i64::MIN,
+        end_time: -1,
+        hostname_filter: None,
+        limit: Some(u32::MAX),
+        offset: Some(0),
+    };
+
+    for encoding in BodyEncoding::ALL {
+        let decoded: MetricQuery = encoding.decode(&encoding.encode(&query).unwrap()).unwrap();
+        assert_eq!(decoded, query, "{}", encoding.label());
+    }
+}
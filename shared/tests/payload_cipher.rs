@@ -0,0 +1,141 @@
+use shared::{PayloadCipher, SealedGrpc, SealedStream};
+use std::convert::Infallible;
+use std::future::{poll_fn, Ready};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{Request, Response};
+use tonic::codegen::{Body, Service};
+use tonic::{Code, Status};
+
+const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+#[test]
+fn sealed_payloads_open_only_unaltered_and_under_the_same_key() {
+    let cipher = PayloadCipher::from_hex(KEY).unwrap();
+    let sealed = cipher.seal(b"cpu_percent=42");
+
+    assert_eq!(sealed.len(), 14 + PayloadCipher::OVERHEAD);
+    assert_eq!(cipher.open(&sealed).unwrap(), b"cpu_percent=42");
+    // A fresh nonce every time
+    assert_ne!(cipher.seal(b"cpu_percent=42"), sealed);
+
+    let mut altered = sealed.clone();
+    *altered.last_mut().unwrap() ^= 1;
+    assert!(cipher.open(&altered).is_err());
+    assert!(cipher.open(&sealed[..PayloadCipher::OVERHEAD - 1]).is_err());
+    let other = PayloadCipher::new(&[7; 32]);
+    assert!(other.open(&sealed).is_err());
+}
+
+#[test]
+fn keys_are_64_hex_digits() {
+    assert!(PayloadCipher::from_hex(&KEY[..62]).is_err());
+    assert!(PayloadCipher::from_hex(&KEY.replace('0', "g")).is_err());
+    assert!(PayloadCipher::from_hex(&format!(" {}\n", KEY.to_uppercase())).is_ok());
+}
+
+#[tokio::test]
+async fn sealed_streams_carry_writes_across_frames() {
+    let cipher = PayloadCipher::from_hex(KEY).unwrap();
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = SealedStream::new(client, cipher.clone());
+    let mut server = SealedStream::new(server, cipher);
+    // Bigger than one frame, so it is split
+    let message: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+
+    let sent = message.clone();
+    let writer = tokio::spawn(async move {
+        client.write_all(b"hello").await.unwrap();
+        client.flush().await.unwrap();
+        client.write_all(&sent).await.unwrap();
+        client.shutdown().await.unwrap();
+    });
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).await.unwrap();
+    writer.await.unwrap();
+
+    assert_eq!(&received[..5], b"hello");
+    assert_eq!(&received[5..], &message[..]);
+}
+
+#[tokio::test]
+async fn a_stream_sealed_under_another_key_fails_to_read() {
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = SealedStream::new(client, PayloadCipher::new(&[1; 32]));
+    let mut server = SealedStream::new(server, PayloadCipher::new(&[2; 32]));
+
+    client.write_all(b"hello").await.unwrap();
+    client.flush().await.unwrap();
+    let mut buffer = [0u8; 5];
+    let error = server.read_exact(&mut buffer).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+/// A gRPC service answering each request with its own body
+#[derive(Clone)]
+struct Echo;
+
+impl Service<Request<BoxBody>> for Echo {
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
+        std::future::ready(Ok(Response::new(request.into_body())))
+    }
+}
+
+/// `message` in a gRPC frame: compression flag, big-endian length, message
+fn frame(compressed: bool, message: &[u8]) -> Vec<u8> {
+    let mut frame = vec![compressed as u8];
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+async fn call<S>(service: &mut S, body: Vec<u8>) -> Result<Vec<u8>, Status>
+where
+    S: Service<Request<tonic::transport::Body>, Response = Response<BoxBody>, Error = Infallible>,
+{
+    let mut body = service.call(Request::new(body.into())).await.unwrap().into_body();
+    let mut bytes = Vec::new();
+    while let Some(data) = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await {
+        bytes.extend_from_slice(&data?);
+    }
+    Ok(bytes)
+}
+
+#[tokio::test]
+async fn sealed_grpc_messages_round_trip_between_client_and_server() {
+    let cipher = PayloadCipher::from_hex(KEY).unwrap();
+    let messages = [frame(false, b"cpu_percent=42"), frame(true, b""), frame(false, &[7; 70_000])].concat();
+
+    let mut sealed = SealedGrpc::client(SealedGrpc::server(Echo, Some(cipher.clone())), Some(cipher));
+    assert_eq!(call(&mut sealed, messages.clone()).await.unwrap(), messages);
+    // Without a cipher bodies pass through as they are
+    let mut plain = SealedGrpc::client(SealedGrpc::server(Echo, None), None);
+    assert_eq!(call(&mut plain, messages.clone()).await.unwrap(), messages);
+}
+
+#[tokio::test]
+async fn a_sealed_grpc_server_answers_with_sealed_frames() {
+    let cipher = PayloadCipher::from_hex(KEY).unwrap();
+    let mut server = SealedGrpc::server(Echo, Some(cipher.clone()));
+
+    let response = call(&mut server, frame(true, &cipher.seal(b"cpu_percent=42"))).await.unwrap();
+    let message_len = u32::from_be_bytes(response[1..5].try_into().unwrap()) as usize;
+    assert_eq!(response[0], 1, "the compression flag is kept");
+    assert_eq!(message_len, 14 + PayloadCipher::OVERHEAD);
+    assert_eq!(cipher.open(&response[5..]).unwrap(), b"cpu_percent=42");
+
+    let error = call(&mut server, frame(false, b"cpu_percent=42")).await.unwrap_err();
+    assert_eq!(error.code(), Code::InvalidArgument);
+    let error = call(&mut server, frame(false, b"cpu_percent=42")[..8].to_vec()).await.unwrap_err();
+    assert_eq!(error.code(), Code::Internal);
+}
@@ -0,0 +1,34 @@
+use shared::{next_request_id, next_request_sequence, request_id_range, valid_request_id, MAX_REQUEST_ID_LEN};
+
+#[test]
+fn ids_share_a_prefix_and_count_up() {
+    let start = next_request_sequence();
+    let first = next_request_id();
+    let second = next_request_id();
+
+    let (prefix, sequence) = first.split_once('-').unwrap();
+    assert_eq!(prefix.len(), 8, "{}", first);
+    assert!(second.starts_with(&format!("{}-", prefix)), "{} {}", first, second);
+    // Other tests in this binary may take IDs in between
+    assert!(sequence.parse::<u64>().unwrap() >= start);
+    assert!(second.split_once('-').unwrap().1.parse::<u64>().unwrap() > sequence.parse::<u64>().unwrap());
+    assert!(next_request_sequence() > start + 1);
+}
+
+#[test]
+fn ranges_name_the_ids_handed_out() {
+    let prefix = next_request_id().split_once('-').unwrap().0.to_string();
+
+    assert_eq!(request_id_range(7, 7), "none");
+    assert_eq!(request_id_range(7, 8), format!("{}-7", prefix));
+    assert_eq!(request_id_range(7, 10), format!("{}-7..={}-9", prefix, prefix));
+}
+
+#[test]
+fn only_short_printable_ids_are_accepted() {
+    assert_eq!(valid_request_id("3f9a2c1e-42"), Some("3f9a2c1e-42"));
+    assert_eq!(valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN)).map(str::len), Some(MAX_REQUEST_ID_LEN));
+    for invalid in ["", "two words", "line\nbreak", &"a".repeat(MAX_REQUEST_ID_LEN + 1)] {
+        assert_eq!(valid_request_id(invalid), None, "{:?}", invalid);
+    }
+}
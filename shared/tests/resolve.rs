@@ -0,0 +1,46 @@
+use shared::{split_host_port, DnsConfig};
+use std::net::SocketAddr;
+
+#[test]
+fn targets_split_into_host_and_port() {
+    assert_eq!(split_host_port("metrics.internal:3000"), Some(("metrics.internal", 3000)));
+    assert_eq!(split_host_port("[::1]:50051"), Some(("::1", 50051)));
+    assert_eq!(split_host_port("metrics.internal"), None);
+    assert_eq!(split_host_port("metrics.internal:http"), None);
+}
+
+#[test]
+fn overrides_parse_from_a_list() {
+    let overrides = DnsConfig::parse_overrides("metrics.internal=10.0.0.5, db = ::1,").unwrap();
+
+    assert_eq!(overrides.len(), 2);
+    assert_eq!(overrides["metrics.internal"].to_string(), "10.0.0.5");
+    assert_eq!(overrides["db"].to_string(), "::1");
+    assert!(DnsConfig::parse_overrides("metrics.internal").is_err());
+    assert!(DnsConfig::parse_overrides("metrics.internal=not-an-ip").is_err());
+}
+
+#[test]
+fn overridden_and_literal_hosts_need_no_lookup() {
+    let config = DnsConfig {
+        overrides: DnsConfig::parse_overrides("metrics.internal=10.0.0.5").unwrap(),
+        pinned: false,
+    };
+    let addr = |addr: &str| addr.parse::<SocketAddr>().unwrap();
+
+    assert_eq!(config.static_addrs("metrics.internal:3000"), Some(vec![addr("10.0.0.5:3000")]));
+    assert_eq!(config.static_addrs("127.0.0.1:50051"), Some(vec![addr("127.0.0.1:50051")]));
+    assert_eq!(config.static_addrs("other.internal:3000"), None);
+    // Without pinning, other hosts are left to each connection
+    assert_eq!(config.resolve("other.internal:3000").unwrap(), None);
+}
+
+#[test]
+fn pinned_hosts_are_looked_up_once_and_reused() {
+    let config = DnsConfig { pinned: true, ..DnsConfig::default() };
+
+    let first = config.resolve("localhost:3000").unwrap().unwrap();
+    assert!(!first.is_empty());
+    assert!(first.iter().all(|addr| addr.ip().is_loopback() && addr.port() == 3000), "{:?}", first);
+    assert_eq!(config.resolve("localhost:3000").unwrap().unwrap(), first);
+}
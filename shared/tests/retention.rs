@@ -0,0 +1,82 @@
+use shared::{InMemoryStorage, MetricPoint, MetricQuery, MetricsStorage, RetentionPolicy};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+fn metric(timestamp: i64) -> MetricPoint {
+    MetricPoint {
+        timestamp,
+        hostname: "web-01".to_string(),
+        cpu_percent: 50.0,
+        memory_bytes: 1024,
+        disk_io_ops: 10,
+        tags: HashMap::new(),
+    }
+}
+
+#[tokio::test]
+async fn points_older_than_max_age_are_evicted_with_their_rollups() {
+    let storage = InMemoryStorage::with_retention(RetentionPolicy {
+        max_age: Some(Duration::from_secs(3600)),
+        ..RetentionPolicy::default()
+    });
+    let now = now();
+    let recent = vec![metric(now - 60), metric(now)];
+    storage.store_metrics(vec![metric(now - 7200), metric(now - 3700)]).await.unwrap();
+    storage.store_metrics(recent.clone()).await.unwrap();
+
+    assert_eq!(storage.evict_expired().await.unwrap(), 2);
+    assert_eq!(storage.query_metrics(&MetricQuery::all()).await.unwrap(), recent);
+    let rollups = storage.query_rollups(&MetricQuery::all()).await.unwrap();
+    assert!(rollups.iter().all(|rollup| rollup.minute_start + 60 > now - 3600), "{:?}", rollups);
+    assert_eq!(rollups.iter().map(|rollup| rollup.count).sum::<u64>(), 2);
+
+    assert_eq!(storage.evict_expired().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn max_points_evicts_the_oldest_inserts_first() {
+    let storage = InMemoryStorage::with_retention(RetentionPolicy {
+        max_points: Some(3),
+        ..RetentionPolicy::default()
+    });
+    // Insert order, not timestamp order, decides what goes
+    let points: Vec<MetricPoint> = [50, 10, 40, 20, 30].into_iter().map(metric).collect();
+    storage.store_metrics(points.clone()).await.unwrap();
+
+    assert_eq!(storage.evict_expired().await.unwrap(), 2);
+    assert_eq!(storage.query_metrics(&MetricQuery::all()).await.unwrap(), points[2..]);
+    assert_eq!(storage.evict_expired().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn the_eviction_task_enforces_the_policy_on_its_interval() {
+    let storage = Arc::new(InMemoryStorage::with_retention(RetentionPolicy {
+        max_points: Some(1),
+        eviction_interval: Duration::from_millis(10),
+        ..RetentionPolicy::default()
+    }));
+    storage.store_metrics(vec![metric(1), metric(2)]).await.unwrap();
+    let task = storage.spawn_eviction_task().unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(storage.query_metrics(&MetricQuery::all()).await.unwrap(), vec![metric(2)]);
+    task.abort();
+
+    // Nothing to enforce without a bound
+    assert!(Arc::new(InMemoryStorage::new()).spawn_eviction_task().is_none());
+}
+
+#[test]
+fn a_zero_eviction_interval_falls_back_to_the_default() {
+    let interval = |secs: &str| {
+        RetentionPolicy::from_vars(|name| (name == "PROTOBENCH_EVICTION_INTERVAL_SECS").then(|| secs.to_string())).eviction_interval
+    };
+
+    assert_eq!(interval("0"), RetentionPolicy::default().eviction_interval);
+    assert_eq!(interval("2"), Duration::from_secs(2));
+}
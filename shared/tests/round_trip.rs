@@ -0,0 +1,51 @@
+use proptest::prelude::*;
+use shared::strategies::{any_f32, metric_point, metric_point_with, metric_query, same_metric};
+use shared::{BodyEncoding, MetricPoint, MetricQuery};
+use std::collections::HashMap;
+
+proptest! {
+    #[test]
+    fn points_round_trip_through_every_body_encoding(metric in metric_point()) {
+        for encoding in BodyEncoding::ALL {
+            let decoded: MetricPoint = encoding.decode(&encoding.encode(&metric).unwrap()).unwrap();
+            prop_assert!(same_metric(&decoded, &metric), "{} gave {:?}", encoding.label(), decoded);
+        }
+    }
+
+    #[test]
+    fn batches_round_trip_through_every_body_encoding(metrics in proptest::collection::vec(metric_point(), 0..16)) {
+        for encoding in BodyEncoding::ALL {
+            let decoded: Vec<MetricPoint> = encoding.decode(&encoding.encode(&metrics).unwrap()).unwrap();
+            prop_assert_eq!(decoded.len(), metrics.len());
+            prop_assert!(decoded.iter().zip(&metrics).all(|(a, b)| same_metric(a, b)), "{} gave {:?}", encoding.label(), decoded);
+        }
+    }
+
+    #[test]
+    fn non_finite_floats_round_trip_through_every_body_encoding(metric in metric_point_with(any_f32())) {
+        for encoding in BodyEncoding::ALL {
+            let decoded: MetricPoint = encoding.decode(&encoding.encode(&metric).unwrap()).unwrap();
+            prop_assert!(same_metric(&decoded, &metric), "{} gave {:?}", encoding.label(), decoded);
+        }
+    }
+
+    #[test]
+    fn queries_round_trip_through_every_body_encoding(query in metric_query()) {
+        for encoding in BodyEncoding::ALL {
+            let decoded: MetricQuery = encoding.decode(&encoding.encode(&query).unwrap()).unwrap();
+            prop_assert_eq!(&decoded, &query, "{}", encoding.label());
+        }
+    }
+
+    #[test]
+    fn tag_insertion_order_does_not_matter(metric in metric_point()) {
+        // Rebuild the map in reverse so it iterates, and encodes, in another order
+        let mut pairs: Vec<(String, String)> = metric.tags.clone().into_iter().collect();
+        pairs.reverse();
+        let reordered = MetricPoint { tags: pairs.into_iter().collect::<HashMap<_, _>>(), ..metric.clone() };
+        for encoding in BodyEncoding::ALL {
+            let decoded: MetricPoint = encoding.decode(&encoding.encode(&reordered).unwrap()).unwrap();
+            prop_assert!(same_metric(&decoded, &metric), "{} gave {:?}", encoding.label(), decoded);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use shared::{InMemoryStorage, MetricPoint, MetricQuery, MetricsStorage, TestDataGenerator};
+use std::path::PathBuf;
+
+/// A snapshot path under the system temp dir, removed when dropped
+struct TempSnapshot(PathBuf);
+
+impl TempSnapshot {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("protobench-snapshot-{}-{}.json", std::process::id(), name)))
+    }
+}
+
+impl Drop for TempSnapshot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn a_snapshot_restores_points_and_rollups_into_fresh_storage() {
+    let snapshot = TempSnapshot::new("round-trip");
+    let points: Vec<MetricPoint> = TestDataGenerator::new().generate(500);
+    let original = InMemoryStorage::new();
+    original.store_metrics(points.clone()).await.unwrap();
+    assert_eq!(original.snapshot(&snapshot.0).await.unwrap(), points.len());
+
+    let restored = InMemoryStorage::new();
+    restored.store_metric(points[0].clone()).await.unwrap();
+    // Restore replaces what was there rather than adding to it
+    assert_eq!(restored.restore(&snapshot.0).await.unwrap(), points.len());
+
+    assert_eq!(restored.query_metrics(&MetricQuery::all()).await.unwrap(), points);
+    assert_eq!(
+        restored.query_rollups(&MetricQuery::all()).await.unwrap(),
+        original.query_rollups(&MetricQuery::all()).await.unwrap()
+    );
+    assert_eq!(
+        restored.storage_stats().await.unwrap().rollup_bucket_count,
+        original.storage_stats().await.unwrap().rollup_bucket_count
+    );
+}
+
+#[tokio::test]
+async fn a_missing_or_corrupt_snapshot_is_an_error() {
+    let storage = InMemoryStorage::new();
+    storage.store_metrics(TestDataGenerator::new().generate(3)).await.unwrap();
+
+    let missing = TempSnapshot::new("missing");
+    assert!(storage.restore(&missing.0).await.is_err());
+
+    let corrupt = TempSnapshot::new("corrupt");
+    std::fs::write(&corrupt.0, r#"[{"timestamp": 1700000000, "hostname": "web-0"#).unwrap();
+    assert!(storage.restore(&corrupt.0).await.is_err());
+
+    // A failed restore leaves storage as it was
+    assert_eq!(storage.query_metrics(&MetricQuery::all()).await.unwrap().len(), 3);
+}
@@ -0,0 +1,82 @@
+use shared::{CountingAllocator, OperationStats, ServerStats, ServiceMetrics};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator::new();
+
+#[tokio::test]
+async fn requests_are_recorded_per_method() {
+    let metrics = Arc::new(ServiceMetrics::new("test").unwrap());
+
+    let mut timer = metrics.start("SubmitBatch");
+    let batch = timer.track(async { vec![0u8; 100_000] }).await;
+    timer.finish("0");
+    metrics.start("SubmitBatch").finish("0");
+    // Dropped unfinished, as when the client goes away mid-request
+    drop(metrics.start("QueryMetrics"));
+
+    let stats = metrics.stats();
+    assert_eq!(stats.service, "test");
+    assert_eq!(stats.operations["SubmitBatch"].requests, 2);
+    assert!(stats.operations["SubmitBatch"].allocated_bytes >= batch.len() as u64);
+    assert_eq!(stats.operations["QueryMetrics"].requests, 1);
+    assert_eq!(stats.total().requests, 3);
+}
+
+#[test]
+fn allocations_on_other_threads_are_not_counted() {
+    let metrics = Arc::new(ServiceMetrics::new("test").unwrap());
+    let (started, running) = std::sync::mpsc::channel();
+    let (stop, stopped) = std::sync::mpsc::channel::<()>();
+    let other = std::thread::spawn(move || {
+        started.send(()).unwrap();
+        while stopped.try_recv().is_err() {
+            std::hint::black_box(vec![0u8; 1_000_000]);
+        }
+    });
+    running.recv().unwrap();
+
+    let mut timer = metrics.start("SubmitBatch");
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(timer.track(async { std::thread::sleep(std::time::Duration::from_millis(20)) }));
+    timer.finish("0");
+    stop.send(()).unwrap();
+    other.join().unwrap();
+
+    assert!(metrics.stats().operations["SubmitBatch"].allocated_bytes < 1_000_000);
+}
+
+#[test]
+fn differences_leave_out_idle_methods() {
+    let stats = |submits: u64, queries: u64| ServerStats {
+        service: "grpc".to_string(),
+        allocator: "system".to_string(),
+        operations: [
+            ("SubmitBatch".to_string(), OperationStats { requests: submits, total_ns: submits * 100, allocated_bytes: submits * 10 }),
+            ("QueryMetrics".to_string(), OperationStats { requests: queries, total_ns: queries * 300, allocated_bytes: queries * 30 }),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let since = stats(5, 2).since(&stats(3, 2));
+    assert_eq!(since.operations.len(), 1);
+    assert_eq!(since.total(), OperationStats { requests: 2, total_ns: 200, allocated_bytes: 20 });
+
+    // A method the earlier stats hadn't seen counts from zero
+    let mut earlier = stats(3, 2);
+    earlier.operations.remove("QueryMetrics");
+    assert_eq!(stats(3, 2).since(&earlier).total().requests, 2);
+}
+
+#[test]
+fn stats_round_trip_through_json() {
+    let metrics = Arc::new(ServiceMetrics::new("capnp").unwrap());
+    metrics.start("queryMetrics").finish("ok");
+    let stats = metrics.stats();
+
+    let json = serde_json::to_string(&stats).unwrap();
+    assert_eq!(serde_json::from_str::<ServerStats>(&json).unwrap(), stats);
+}
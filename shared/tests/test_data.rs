@@ -0,0 +1,272 @@
+use shared::{
+    generate_test_data, generate_test_data_iter, BodyEncoding, Compressibility, InMemoryStorage, MetricPoint, MetricPointV2,
+    MetricQuery, MetricsStorage, TestDataGenerator, TextMode, ValidationError, MAX_HOSTNAME_LEN, MAX_TAGS, MAX_TAG_VALUE_LEN,
+    MAX_TIMESTAMP,
+};
+use std::collections::{HashMap, HashSet};
+
+const BASE_TIMESTAMP: i64 = 1_700_000_000;
+
+fn point(timestamp: i64, hostname: &str, cpu_percent: f32, memory_bytes: u64, disk_io_ops: u32, tags: [(&str, &str); 4]) -> MetricPoint {
+    MetricPoint {
+        timestamp,
+        hostname: hostname.to_string(),
+        cpu_percent,
+        memory_bytes,
+        disk_io_ops,
+        tags: tags.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect::<HashMap<_, _>>(),
+    }
+}
+
+#[test]
+fn default_shape_is_pinned() {
+    let expected = vec![
+        point(1_699_999_877, "worker-01", 42.34611, 12_061_366_415, 1939,
+            [("env", "prod"), ("region", "eu-central"), ("service", "backend"), ("version", "v2.6.2")]),
+        point(1_699_997_445, "web-02", 77.19483, 13_275_420_634, 2265,
+            [("env", "dev"), ("region", "us-east"), ("service", "queue"), ("version", "v2.5.4")]),
+        point(1_699_996_958, "api-gateway", 42.27969, 5_529_621_888, 7964,
+            [("env", "prod"), ("region", "eu-central"), ("service", "database"), ("version", "v1.6.3")]),
+    ];
+
+    assert_eq!(TestDataGenerator::new().base_timestamp(BASE_TIMESTAMP).generate(3), expected);
+}
+
+#[test]
+fn generate_test_data_uses_the_default_shape() {
+    let from_function = generate_test_data(50);
+    let from_builder = TestDataGenerator::new().generate(50);
+
+    // Both read the clock, so only compare timestamps relative to the first point
+    let without_clock = |metrics: Vec<MetricPoint>| -> Vec<MetricPoint> {
+        let first = metrics[0].timestamp;
+        metrics.into_iter().map(|metric| MetricPoint { timestamp: metric.timestamp - first, ..metric }).collect()
+    };
+    assert_eq!(without_clock(from_function), without_clock(from_builder));
+}
+
+#[test]
+fn same_seed_gives_same_points() {
+    let generator = TestDataGenerator::new().seed(7).base_timestamp(BASE_TIMESTAMP);
+
+    assert_eq!(generator.generate(200), generator.generate(200));
+    assert_ne!(generator.generate(200), generator.seed(8).generate(200));
+}
+
+#[test]
+fn shape_options_are_applied() {
+    let metrics = TestDataGenerator::new()
+        .hosts(100)
+        .tags_per_metric(16)
+        .hostname_len(64)
+        .base_timestamp(BASE_TIMESTAMP)
+        .generate(5000);
+
+    let hostnames: HashSet<&str> = metrics.iter().map(|metric| metric.hostname.as_str()).collect();
+    assert_eq!(hostnames.len(), 100);
+    assert!(hostnames.iter().all(|hostname| hostname.len() == 64));
+    assert!(metrics.iter().all(|metric| metric.tags.len() == 16));
+    assert!(metrics.iter().all(|metric| metric.validate().is_ok()));
+}
+
+#[test]
+fn points_span_the_hour_before_the_base_timestamp() {
+    let metrics = TestDataGenerator::new().base_timestamp(BASE_TIMESTAMP).generate(100);
+
+    assert!(metrics.iter().all(|metric| metric.timestamp > BASE_TIMESTAMP - 3600));
+    assert!(metrics.iter().all(|metric| metric.timestamp < BASE_TIMESTAMP + 100));
+}
+
+#[test]
+fn short_hostname_len_truncates() {
+    let metrics = TestDataGenerator::new().hostname_len(3).generate(100);
+
+    assert!(metrics.iter().all(|metric| metric.hostname.len() == 3));
+}
+
+#[test]
+fn tags_stay_within_the_service_limit() {
+    let metrics = TestDataGenerator::new().tags_per_metric(MAX_TAGS).generate(10);
+
+    assert!(metrics.iter().all(|metric| metric.tags.len() == MAX_TAGS));
+    assert!(metrics.iter().all(|metric| metric.validate().is_ok()));
+}
+
+#[test]
+fn label_describes_the_shape() {
+    assert_eq!(TestDataGenerator::new().label(), "hosts=10,tags=4,hostname_len=natural");
+    assert_eq!(
+        TestDataGenerator::new().hosts(100).tags_per_metric(16).hostname_len(64).label(),
+        "hosts=100,tags=16,hostname_len=64"
+    );
+    assert_eq!(TestDataGenerator::new().text(TextMode::Long).label(), "hosts=10,tags=4,hostname_len=natural,text=long");
+    assert_eq!(
+        TestDataGenerator::new().adversarial().label(),
+        "hosts=10,tags=256,hostname_len=253,tag_value_len=8192,extreme_values"
+    );
+    assert_eq!(
+        TestDataGenerator::new().compressibility(Compressibility::Noise).label(),
+        "hosts=10,tags=4,hostname_len=natural,compressibility=noise"
+    );
+}
+
+#[test]
+fn unicode_text_is_multi_byte() {
+    let metrics = TestDataGenerator::new().text(TextMode::Unicode).generate(200);
+
+    let hostnames: HashSet<&str> = metrics.iter().map(|metric| metric.hostname.as_str()).collect();
+    assert_eq!(hostnames.len(), 10);
+    assert!(hostnames.iter().any(|hostname| hostname.chars().any(|c| c.len_utf8() == 4)));
+    let values: Vec<&String> = metrics.iter().flat_map(|metric| metric.tags.values()).collect();
+    assert!(values.iter().any(|value| value.chars().any(|c| c.len_utf8() == 4)));
+    // Needs escaping in JSON
+    assert!(values.iter().any(|value| value.contains('"') && value.contains('\t')));
+    assert!(metrics.iter().all(|metric| metric.validate().is_ok()));
+}
+
+#[test]
+fn long_text_fills_the_limits() {
+    let metrics = TestDataGenerator::new().text(TextMode::Long).tags_per_metric(8).generate(200);
+
+    for metric in &metrics {
+        assert!((MAX_HOSTNAME_LEN - 3..=MAX_HOSTNAME_LEN).contains(&metric.hostname.len()), "{:?}", metric.hostname);
+        for value in metric.tags.values() {
+            assert!((MAX_TAG_VALUE_LEN - 3..=MAX_TAG_VALUE_LEN).contains(&value.len()), "{:?}", value);
+        }
+        assert_eq!(metric.validate(), Ok(()));
+    }
+}
+
+#[test]
+fn multi_byte_hostnames_are_cut_on_character_boundaries() {
+    for len in 1..40 {
+        let metrics = TestDataGenerator::new().text(TextMode::Unicode).hostname_len(len).generate(20);
+        // Slicing a `String` mid-character would have panicked already
+        assert!(metrics.iter().all(|metric| metric.hostname.len() <= len && metric.hostname.len() + 3 >= len));
+    }
+}
+
+#[test]
+fn unicode_text_round_trips_through_every_body_encoding() {
+    for text in [TextMode::Unicode, TextMode::Long] {
+        let metrics = TestDataGenerator::new().text(text).tags_per_metric(8).generate(100);
+        for encoding in BodyEncoding::ALL {
+            let bytes = encoding.encode(&metrics).unwrap();
+            let decoded: Vec<MetricPoint> = encoding.decode(&bytes).unwrap();
+            assert_eq!(decoded, metrics, "{} with {:?}", encoding.label(), text);
+        }
+    }
+}
+
+#[test]
+fn adversarial_points_are_worst_case() {
+    let metrics = TestDataGenerator::new().adversarial().generate(3);
+
+    for (i, metric) in metrics.iter().enumerate() {
+        assert_eq!(metric.timestamp, MAX_TIMESTAMP - 2 + i as i64);
+        assert_eq!(metric.hostname.len(), MAX_HOSTNAME_LEN);
+        assert_eq!((metric.cpu_percent, metric.memory_bytes, metric.disk_io_ops), (100.0, u64::MAX, u32::MAX));
+        assert_eq!(metric.tags.len(), 256);
+        assert!(metric.tags.values().all(|value| value.len() == 8 * 1024));
+        assert_eq!(metric.validate(), Err(ValidationError::TooManyTags(256)));
+    }
+    let encoded = BodyEncoding::Json.encode(&metrics[0]).unwrap();
+    assert!(encoded.len() > 2 * 1024 * 1024);
+}
+
+#[tokio::test]
+async fn extreme_values_do_not_overflow_statistics() {
+    let storage = InMemoryStorage::new();
+    let metrics = TestDataGenerator::new().extreme_values(true).generate(100);
+    storage.store_metrics(metrics).await.unwrap();
+
+    let everything = MetricQuery::all();
+    let statistics = storage.calculate_statistics(&everything).await.unwrap();
+    assert_eq!(statistics.count, 100);
+    assert_eq!(statistics.avg_memory_bytes, u64::MAX);
+    assert_eq!(statistics.avg_cpu_percent, 100.0);
+}
+
+#[test]
+fn iterator_matches_generate() {
+    let generator = TestDataGenerator::new().hosts(50).tags_per_metric(8).base_timestamp(BASE_TIMESTAMP);
+    let points = generator.iter(500);
+
+    assert_eq!(points.len(), 500);
+    assert_eq!(points.collect::<Vec<_>>(), generator.generate(500));
+    assert_eq!(
+        TestDataGenerator::new().adversarial().iter(4).collect::<Vec<_>>(),
+        TestDataGenerator::new().adversarial().generate(4)
+    );
+}
+
+#[test]
+fn iterator_generates_on_demand() {
+    // Materializing this many points would exhaust memory
+    let mut points = generate_test_data_iter(usize::MAX / 2);
+
+    assert_eq!(points.len(), usize::MAX / 2);
+    assert_eq!(points.by_ref().take(1000).count(), 1000);
+    assert_eq!(points.len(), usize::MAX / 2 - 1000);
+}
+
+#[test]
+fn compressibility_only_changes_tag_values() {
+    let natural = TestDataGenerator::new().tags_per_metric(16).base_timestamp(BASE_TIMESTAMP).generate(200);
+
+    for compressibility in [Compressibility::Repetitive, Compressibility::Noise] {
+        let metrics = TestDataGenerator::new()
+            .tags_per_metric(16)
+            .base_timestamp(BASE_TIMESTAMP)
+            .compressibility(compressibility)
+            .generate(200);
+        for (metric, natural) in metrics.iter().zip(&natural) {
+            assert_eq!(MetricPoint { tags: natural.tags.clone(), ..metric.clone() }, *natural);
+            for (key, value) in &metric.tags {
+                assert_eq!(value.len(), natural.tags[key].len(), "{:?} {}", compressibility, key);
+            }
+        }
+    }
+}
+
+#[test]
+fn repetitive_values_repeat_and_noise_values_do_not() {
+    let shape = TestDataGenerator::new().tags_per_metric(8).tag_value_len(64);
+    let repetitive = shape.compressibility(Compressibility::Repetitive).generate(100);
+    let noise = shape.compressibility(Compressibility::Noise).generate(100);
+
+    let distinct = |metrics: &[MetricPoint]| metrics.iter().flat_map(|metric| metric.tags.values()).collect::<HashSet<_>>().len();
+    assert_eq!(distinct(&repetitive), 1);
+    assert_eq!(distinct(&noise), 800);
+    assert!(noise.iter().flat_map(|metric| metric.tags.values()).all(|value| value.chars().all(|c| c.is_ascii_alphanumeric())));
+    assert!(repetitive.iter().chain(&noise).all(|metric| metric.validate().is_ok()));
+}
+
+#[test]
+fn v2_points_nest_the_flat_ones() {
+    let generator = TestDataGenerator::new().base_timestamp(BASE_TIMESTAMP);
+    let nested = generator.generate_v2(100);
+
+    for (metric, flat) in nested.iter().zip(generator.generate(100)) {
+        let flattened = metric.flatten();
+        assert_eq!(MetricPoint { cpu_percent: flat.cpu_percent, ..flattened.clone() }, flat);
+        assert!((flattened.cpu_percent - flat.cpu_percent).abs() <= 5.0);
+        assert_eq!(metric.per_core_cpu_percent.len(), 8);
+        assert!(metric.per_core_cpu_percent.iter().all(|cpu| (0.0..=100.0).contains(cpu)));
+        assert_eq!(metric.latency_histogram.len(), 12);
+        assert!(metric.latency_histogram.windows(2).all(|pair| pair[0].upper_bound_ms < pair[1].upper_bound_ms));
+        assert_eq!(metric.processes.len(), 10);
+        assert!(metric.processes.iter().all(|process| process.args.len() <= 4));
+    }
+    assert_eq!(generator.generate_v2(100), nested);
+}
+
+#[test]
+fn v2_points_round_trip_through_every_body_encoding() {
+    let metrics = TestDataGenerator::new().generate_v2(50);
+
+    for encoding in BodyEncoding::ALL {
+        let decoded: Vec<MetricPointV2> = encoding.decode(&encoding.encode(&metrics).unwrap()).unwrap();
+        assert_eq!(decoded, metrics, "{}", encoding.label());
+    }
+}
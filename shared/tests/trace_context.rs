@@ -0,0 +1,63 @@
+use opentelemetry::trace::TracerProvider as _;
+use shared::{current_traceparent, set_remote_parent};
+use tracing_subscriber::layer::SubscriberExt;
+
+// A subscriber that records spans the way `init_tracing_from_env` sets up,
+// minus the exporter
+fn traced() -> tracing::subscriber::DefaultGuard {
+    let provider = opentelemetry_sdk::trace::TracerProvider::builder().build();
+    let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    tracing::subscriber::set_default(subscriber)
+}
+
+// version-trace_id-span_id-flags
+fn fields(traceparent: &str) -> Vec<&str> {
+    traceparent.split('-').collect()
+}
+
+#[test]
+fn untraced_calls_send_nothing() {
+    assert_eq!(current_traceparent(), None);
+    let _span = tracing::info_span!("client").entered();
+    assert_eq!(current_traceparent(), None);
+}
+
+#[test]
+fn a_traced_span_has_a_traceparent() {
+    let _subscriber = traced();
+    let _span = tracing::info_span!("client").entered();
+
+    let traceparent = current_traceparent().unwrap();
+    let fields = fields(&traceparent);
+    assert_eq!(fields.len(), 4, "{}", traceparent);
+    assert_eq!(fields[0], "00");
+    assert_eq!(fields[1].len(), 32);
+    assert_eq!(fields[2].len(), 16);
+}
+
+#[test]
+fn server_spans_continue_the_client_trace() {
+    let _subscriber = traced();
+    let client = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    let server = tracing::info_span!("server");
+    set_remote_parent(&server, Some(client));
+    let _entered = server.enter();
+
+    let traceparent = current_traceparent().unwrap();
+    assert_eq!(fields(&traceparent)[1], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_ne!(fields(&traceparent)[2], "00f067aa0ba902b7");
+}
+
+#[test]
+fn a_missing_or_malformed_parent_starts_a_new_trace() {
+    let _subscriber = traced();
+    for traceparent in [None, Some(""), Some("not-a-traceparent")] {
+        let server = tracing::info_span!("server");
+        set_remote_parent(&server, traceparent);
+        let _entered = server.enter();
+
+        let own = current_traceparent().unwrap();
+        assert_ne!(fields(&own)[1], "00000000000000000000000000000000", "{:?}", traceparent);
+    }
+}
@@ -0,0 +1,132 @@
+use shared::{
+    MetricPoint, MetricQuery, TimestampUnit, ValidationError, MAX_HOSTNAME_LEN, MAX_TAGS, MAX_TAG_KEY_LEN, MAX_TAG_VALUE_LEN,
+    MAX_TIMESTAMP, MIN_TIMESTAMP,
+};
+use std::collections::HashMap;
+
+fn valid_metric() -> MetricPoint {
+    let mut tags = HashMap::new();
+    tags.insert("env".to_string(), "prod".to_string());
+
+    MetricPoint {
+        timestamp: 1_700_000_000,
+        hostname: "server-001".to_string(),
+        cpu_percent: 42.5,
+        memory_bytes: 8_589_934_592,
+        disk_io_ops: 150,
+        tags,
+    }
+}
+
+#[test]
+fn accepts_valid_metric() {
+    assert_eq!(valid_metric().validate(), Ok(()));
+}
+
+#[test]
+fn accepts_boundary_values() {
+    let mut metric = valid_metric();
+    metric.cpu_percent = 0.0;
+    metric.timestamp = MIN_TIMESTAMP;
+    assert_eq!(metric.validate(), Ok(()));
+
+    metric.cpu_percent = 100.0;
+    metric.timestamp = MAX_TIMESTAMP;
+    metric.hostname = "h".repeat(MAX_HOSTNAME_LEN);
+    assert_eq!(metric.validate(), Ok(()));
+}
+
+#[test]
+fn rejects_cpu_out_of_range() {
+    for cpu_percent in [-0.1, 100.1] {
+        let metric = MetricPoint { cpu_percent, ..valid_metric() };
+        assert_eq!(metric.validate(), Err(ValidationError::CpuPercentOutOfRange(cpu_percent)));
+    }
+
+    let metric = MetricPoint { cpu_percent: f32::NAN, ..valid_metric() };
+    assert!(matches!(metric.validate(), Err(ValidationError::CpuPercentOutOfRange(_))));
+}
+
+#[test]
+fn rejects_bad_hostnames() {
+    let metric = MetricPoint { hostname: String::new(), ..valid_metric() };
+    assert_eq!(metric.validate(), Err(ValidationError::EmptyHostname));
+
+    let metric = MetricPoint { hostname: "h".repeat(MAX_HOSTNAME_LEN + 1), ..valid_metric() };
+    assert_eq!(metric.validate(), Err(ValidationError::HostnameTooLong(MAX_HOSTNAME_LEN + 1)));
+}
+
+#[test]
+fn rejects_timestamps_outside_range() {
+    for timestamp in [0, MIN_TIMESTAMP - 1, MAX_TIMESTAMP + 1] {
+        let metric = MetricPoint { timestamp, ..valid_metric() };
+        assert_eq!(metric.validate(), Err(ValidationError::TimestampOutOfRange(timestamp)));
+    }
+}
+
+#[test]
+fn rejects_timestamps_in_finer_units() {
+    let seconds = valid_metric().timestamp;
+    for unit in TimestampUnit::ALL {
+        for timestamp in [seconds * unit.per_second(), seconds * unit.per_second() + unit.per_second() - 1] {
+            let metric = MetricPoint { timestamp, ..valid_metric() };
+            assert_eq!(metric.validate(), Err(ValidationError::TimestampNotInSeconds(timestamp, unit)));
+            assert_eq!(unit.to_seconds(timestamp), seconds);
+        }
+        assert_eq!(TimestampUnit::detect(MIN_TIMESTAMP * unit.per_second()), Some(unit));
+        assert_eq!(TimestampUnit::detect(MAX_TIMESTAMP * unit.per_second()), Some(unit));
+        assert_eq!(TimestampUnit::detect(MAX_TIMESTAMP * unit.per_second() + 1), None);
+    }
+    assert_eq!(TimestampUnit::detect(seconds), None);
+
+    let metric = MetricPoint { timestamp: 1_700_000_000_123, ..valid_metric() };
+    let message = metric.validate().unwrap_err().to_string();
+    assert!(message.contains("milliseconds") && message.contains("1700000000"), "{}", message);
+}
+
+#[test]
+fn queries_need_bounds_in_seconds() {
+    let query = |start_time, end_time| MetricQuery { start_time, end_time, hostname_filter: None, limit: None, offset: None };
+
+    for (start_time, end_time) in [(1_700_000_000, 1_700_003_600), (i64::MIN, i64::MAX), (0, 0), (10, 5)] {
+        assert_eq!(query(start_time, end_time).validate(), Ok(()));
+    }
+    assert_eq!(
+        query(1_700_000_000, 1_700_003_600_000).validate(),
+        Err(ValidationError::TimestampNotInSeconds(1_700_003_600_000, TimestampUnit::Milliseconds))
+    );
+    assert_eq!(
+        query(1_700_000_000_000_000, i64::MAX).validate(),
+        Err(ValidationError::TimestampNotInSeconds(1_700_000_000_000_000, TimestampUnit::Microseconds))
+    );
+}
+
+#[test]
+fn rejects_too_many_tags() {
+    let tags = (0..=MAX_TAGS).map(|i| (format!("key{}", i), "value".to_string())).collect();
+    let metric = MetricPoint { tags, ..valid_metric() };
+    assert_eq!(metric.validate(), Err(ValidationError::TooManyTags(MAX_TAGS + 1)));
+}
+
+#[test]
+fn rejects_bad_tags() {
+    let mut metric = valid_metric();
+    metric.tags.insert(String::new(), "value".to_string());
+    assert_eq!(metric.validate(), Err(ValidationError::EmptyTagKey));
+
+    let long_key = "k".repeat(MAX_TAG_KEY_LEN + 1);
+    let mut metric = valid_metric();
+    metric.tags.insert(long_key.clone(), "value".to_string());
+    assert_eq!(metric.validate(), Err(ValidationError::TagKeyTooLong(long_key)));
+
+    let mut metric = valid_metric();
+    metric.tags.insert("owner".to_string(), "v".repeat(MAX_TAG_VALUE_LEN + 1));
+    assert_eq!(metric.validate(), Err(ValidationError::TagValueTooLong("owner".to_string())));
+}
+
+#[test]
+fn error_messages_name_the_field() {
+    let metric = MetricPoint { cpu_percent: 150.0, ..valid_metric() };
+    let message = metric.validate().unwrap_err().to_string();
+    assert!(message.contains("cpu_percent"), "{}", message);
+}
@@ -0,0 +1,190 @@
+//! Write-ahead log replay through `InMemoryStorage::enable_wal`, one log file
+//! per test under the system temp dir.
+
+use shared::{FsyncPolicy, InMemoryStorage, MetricPoint, MetricQuery, MetricsStorage, RetentionPolicy, WalConfig};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+const BASE_TIMESTAMP: i64 = 1_700_000_000;
+
+/// A WAL path under the system temp dir, removed (with any compaction leftover) when dropped
+struct TempLog(PathBuf);
+
+impl TempLog {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("protobench-wal-{}-{}.log", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        Self(path)
+    }
+
+    fn config(&self) -> WalConfig {
+        WalConfig {
+            path: self.0.clone(),
+            fsync: FsyncPolicy::Always,
+        }
+    }
+
+    fn append_raw(&self, bytes: &[u8]) {
+        let mut file = std::fs::OpenOptions::new().append(true).open(&self.0).unwrap();
+        file.write_all(bytes).unwrap();
+    }
+}
+
+impl Drop for TempLog {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+        let _ = std::fs::remove_file(self.0.with_extension("compact"));
+    }
+}
+
+fn metric(hostname: &str, offset: i64) -> MetricPoint {
+    MetricPoint {
+        timestamp: BASE_TIMESTAMP + offset,
+        hostname: hostname.to_string(),
+        cpu_percent: offset as f32,
+        memory_bytes: 1024,
+        disk_io_ops: 10,
+        tags: HashMap::new(),
+    }
+}
+
+fn host_query(hostname: &str) -> MetricQuery {
+    MetricQuery {
+        hostname_filter: Some(hostname.to_string()),
+        ..MetricQuery::all()
+    }
+}
+
+fn reopen(log: &TempLog) -> (InMemoryStorage, usize) {
+    let mut storage = InMemoryStorage::new();
+    let replayed = storage.enable_wal(&log.config()).unwrap();
+    (storage, replayed)
+}
+
+#[tokio::test]
+async fn writes_are_replayed_after_a_restart() {
+    let log = TempLog::new("restart");
+    let points: Vec<MetricPoint> = (0..5).map(|i| metric("web-01", i)).collect();
+    {
+        let (storage, replayed) = reopen(&log);
+        assert_eq!(replayed, 0);
+        storage.store_metrics(points[..4].to_vec()).await.unwrap();
+        storage.store_metric(points[4].clone()).await.unwrap();
+    }
+
+    let (storage, replayed) = reopen(&log);
+    assert_eq!(replayed, 2);
+    assert_eq!(storage.query_metrics(&MetricQuery::all()).await.unwrap(), points);
+    let rollups = storage.query_rollups(&MetricQuery::all()).await.unwrap();
+    assert_eq!(rollups.iter().map(|rollup| rollup.count).sum::<u64>(), 5);
+}
+
+#[tokio::test]
+async fn deletes_are_replayed_after_a_restart() {
+    let log = TempLog::new("delete");
+    {
+        let (storage, _) = reopen(&log);
+        storage.store_metrics(vec![metric("web-01", 0), metric("web-02", 1), metric("web-01", 2)]).await.unwrap();
+        assert_eq!(storage.delete_metrics(&host_query("web-01")).await.unwrap(), 2);
+        storage.store_metric(metric("web-01", 3)).await.unwrap();
+    }
+
+    let (storage, replayed) = reopen(&log);
+    assert_eq!(replayed, 3);
+    assert_eq!(
+        storage.query_metrics(&MetricQuery::all()).await.unwrap(),
+        vec![metric("web-02", 1), metric("web-01", 3)]
+    );
+    let rollups = storage.query_rollups(&host_query("web-01")).await.unwrap();
+    assert_eq!(rollups.len(), 1);
+    assert_eq!(rollups[0].count, 1);
+}
+
+#[tokio::test]
+async fn a_torn_last_line_is_cut_off_so_later_writes_survive() {
+    let log = TempLog::new("torn");
+    {
+        let (storage, _) = reopen(&log);
+        storage.store_metric(metric("web-01", 0)).await.unwrap();
+    }
+    // A crash part-way through writing the next record
+    log.append_raw(br#"{"op":"insert","metrics":[{"timest"#);
+
+    {
+        let (storage, replayed) = reopen(&log);
+        assert_eq!(replayed, 1);
+        storage.store_metric(metric("web-01", 1)).await.unwrap();
+    }
+
+    let (storage, replayed) = reopen(&log);
+    assert_eq!(replayed, 2);
+    assert_eq!(
+        storage.query_metrics(&MetricQuery::all()).await.unwrap(),
+        vec![metric("web-01", 0), metric("web-01", 1)]
+    );
+}
+
+#[tokio::test]
+async fn an_unreadable_line_followed_by_intact_ones_fails_the_open() {
+    let log = TempLog::new("corrupt");
+    {
+        let (storage, _) = reopen(&log);
+        storage.store_metric(metric("web-01", 0)).await.unwrap();
+    }
+    // An unreadable line with an intact record behind it isn't a torn write
+    let contents = std::fs::read(&log.0).unwrap();
+    log.append_raw(b"not json\n");
+    log.append_raw(&contents);
+
+    let mut storage = InMemoryStorage::new();
+    let error = storage.enable_wal(&log.config()).unwrap_err();
+    assert!(error.to_string().contains("line 2"), "{}", error);
+}
+
+#[tokio::test]
+async fn eviction_compacts_the_log_to_the_remaining_points() {
+    let log = TempLog::new("compact");
+    let retention = RetentionPolicy {
+        max_points: Some(2),
+        ..RetentionPolicy::default()
+    };
+    {
+        let mut storage = InMemoryStorage::with_retention(retention);
+        storage.enable_wal(&log.config()).unwrap();
+        for i in 0..6 {
+            storage.store_metric(metric("web-01", i)).await.unwrap();
+        }
+        assert_eq!(storage.evict_expired().await.unwrap(), 4);
+        storage.store_metric(metric("web-01", 6)).await.unwrap();
+    }
+
+    // One rewritten insert of the two survivors, then the write after it
+    let (storage, replayed) = reopen(&log);
+    assert_eq!(replayed, 2);
+    assert_eq!(
+        storage.query_metrics(&MetricQuery::all()).await.unwrap(),
+        vec![metric("web-01", 4), metric("web-01", 5), metric("web-01", 6)]
+    );
+}
+
+#[tokio::test]
+async fn wal_is_enabled_only_on_empty_storage() {
+    let log = TempLog::new("nonempty");
+    let mut storage = InMemoryStorage::new();
+    storage.store_metric(metric("web-01", 0)).await.unwrap();
+    assert!(storage.enable_wal(&log.config()).is_err());
+}
+
+#[tokio::test]
+async fn snapshot_and_wal_paths_cannot_be_combined() {
+    let log = TempLog::new("with-snapshot");
+    let vars = HashMap::from([
+        ("PROTOBENCH_WAL_PATH", log.0.display().to_string()),
+        ("PROTOBENCH_SNAPSHOT_PATH", log.0.with_extension("json").display().to_string()),
+    ]);
+    let result = InMemoryStorage::from_vars(|name| vars.get(name).cloned()).await;
+
+    let error = result.err().expect("from_vars should refuse both paths");
+    assert!(error.to_string().contains("PROTOBENCH_SNAPSHOT_PATH"), "{}", error);
+}